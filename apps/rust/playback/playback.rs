@@ -31,6 +31,13 @@ pub fn main() {
     )
     .expect("sdk_audio_reset");
 
+    // correct_dc_offsets & scale_waveform handle 16-bit stereo samples.
+    let format = sdk_audio_get_format(AudioDirection::Record).expect("sdk_audio_get_format");
+    if format.channels != 2 || format.bits_per_sample != 16 {
+        info!("Unsupported audio format {:?}", format);
+        return;
+    }
+
     const SAMPLE_COUNT: usize = 5 * 16000;
     static mut SAMPLES: [u32; SAMPLE_COUNT] = [0u32; SAMPLE_COUNT];
 
@@ -72,15 +79,24 @@ fn record(samples: &mut [u32]) -> usize {
     let mut samples_captured: usize = 0;
     while samples_captured < samples.len() {
         let count = core::cmp::min(samples.len() - samples_captured, MAX_SAMPLES_PER_READ);
-        let data_count =
-            sdk_audio_record_collect(&mut samples[samples_captured..samples_captured + count])
-                .expect("sdk_audio_record_collect");
+        let (data_count, session_ended, dropped) = sdk_audio_record_collect_status(
+            &mut samples[samples_captured..samples_captured + count],
+            /*wait_if_empty=*/ true,
+        )
+        .expect("sdk_audio_record_collect");
+        if dropped > 0 {
+            info!("Recording overwrote {dropped} samples");
+        }
         // XXX need a proper check for "no signal" and over more data
         if let Some(index) = first_zero(&samples[samples_captured..samples_captured + data_count]) {
             samples_captured += index;
             break;
         }
         samples_captured += data_count;
+        if session_ended {
+            // The record buffer filled and has been drained.
+            break;
+        }
     }
 
     sdk_audio_record_stop().expect("sdk_audio_record_stop");
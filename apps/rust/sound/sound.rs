@@ -6,6 +6,9 @@
 #![no_std]
 #![no_main]
 
+#[cfg(feature = "collect_benchmark")]
+extern crate alloc;
+
 use core::mem::size_of;
 use libcantrip::sdk_init;
 use log::{error, info, trace};
@@ -65,12 +68,96 @@ fn sdk_audio_record(data: &mut [u32]) -> Result<usize, SDKError> {
     Ok(data.len())
 }
 
+// Collects timed by collect_benchmark.
+#[cfg(feature = "collect_benchmark")]
+const BENCHMARK_COLLECTS: usize = 1000;
+// RPC round trips timed by rpc_benchmark.
+#[cfg(feature = "rpc_benchmark")]
+const BENCHMARK_RPCS: usize = 1000;
+// Timer used to measure the benchmarks; far longer than they run.
+#[cfg(any(feature = "collect_benchmark", feature = "rpc_benchmark"))]
+const BENCHMARK_TIMER_MS: u32 = 60_000;
+
+// Runs BENCHMARK_COLLECTS collects into |data|, passing each to |copy|,
+// and logs the elapsed time. Elapsed time is read from a oneshot timer
+// armed for far longer than the benchmark runs.
+#[cfg(feature = "collect_benchmark")]
+fn time_collects(name: &str, data: &mut [u32], copy: impl Fn(&mut [u32])) {
+    sdk_timer_oneshot(/*timer=*/ 1, BENCHMARK_TIMER_MS).expect("sdk_timer_oneshot");
+    let mut samples = 0;
+    for _ in 0..BENCHMARK_COLLECTS {
+        let count = sdk_audio_record_collect_non_blocking(data)
+            .expect("sdk_audio_record_collect_non_blocking");
+        copy(&mut data[..count]);
+        samples += count;
+    }
+    let elapsed_ms = BENCHMARK_TIMER_MS - sdk_timer_remaining(/*timer=*/ 1).unwrap_or(0);
+    let _ = sdk_timer_cancel(/*timer=*/ 1);
+    info!(
+        "{name} benchmark: {samples} samples in {elapsed_ms}ms, {}us/collect",
+        elapsed_ms as usize * 1000 / BENCHMARK_COLLECTS
+    );
+}
+
+// Measures the cost of sdk_audio_record_collect while capturing at
+// RECORD_FREQ_HZ. The record buffer is circular so collects always find
+// data; each takes up to a reply frame's worth of samples without
+// waiting. For comparison the collects are re-run with the copy the
+// client made before AudioRecordCollectResponse::copy_to: the samples
+// were first copied to a heap Vec.
+#[cfg(feature = "collect_benchmark")]
+fn collect_benchmark() {
+    let mut data = [0u32; AUDIO_RECORD_COLLECT_MAX_SAMPLES];
+    sdk_audio_record_start(
+        /*rate=*/ RECORD_FREQ_HZ,
+        /*buffer_size=*/ AUDIO_RECORD_BUFFER_MAX,
+        /*stop_on_full=*/ false,
+    )
+    .expect("sdk_audio_record_start");
+    time_collects("collect", &mut data, |_| {});
+    time_collects("collect+to_vec", &mut data, |samples| {
+        let copy = core::hint::black_box(samples.to_vec());
+        samples.copy_from_slice(&copy);
+    });
+    sdk_audio_record_stop().expect("sdk_audio_record_stop");
+}
+
+// Measures the cost of a no-op SDKRuntime round trip (sdk_ping) once
+// the RPC path is warmed by sdk_prewarm. NB: a cold first RPC cannot be
+// timed this way; reading the timer is itself an RPC and has only ms
+// resolution. Build the SDKRuntime with its rpc_benchmark feature to
+// have it log the server time of the first real RPC (here arming the
+// timer) and of the pings that follow.
+#[cfg(feature = "rpc_benchmark")]
+fn rpc_benchmark() {
+    sdk_timer_oneshot(/*timer=*/ 1, BENCHMARK_TIMER_MS).expect("sdk_timer_oneshot");
+    for _ in 0..BENCHMARK_RPCS {
+        sdk_ping().expect("sdk_ping");
+    }
+    let elapsed_ms = BENCHMARK_TIMER_MS - sdk_timer_remaining(/*timer=*/ 1).unwrap_or(0);
+    let _ = sdk_timer_cancel(/*timer=*/ 1);
+    info!(
+        "rpc benchmark: {BENCHMARK_RPCS} round trips in {elapsed_ms}ms, {}us/rpc",
+        elapsed_ms as usize * 1000 / BENCHMARK_RPCS
+    );
+}
+
 #[no_mangle]
 pub fn main() {
     static mut HEAP: [u8; 4096] = [0; 4096];
     sdk_init(unsafe { &mut HEAP });
+    // Audio is latency-sensitive; don't pay setup costs on the first record.
+    // NB: the demo still works without it, the first RPC is just slower
+    if let Err(e) = sdk_prewarm() {
+        error!("sdk_prewarm failed: {:?}", e);
+    }
     set_max_level(LevelFilter::Info);
 
+    #[cfg(feature = "rpc_benchmark")]
+    rpc_benchmark();
+    #[cfg(feature = "collect_benchmark")]
+    collect_benchmark();
+
     let model_name = "soundstream_encoder_non_streaming.kelvin";
 
     info!("Soundstream demo using {model_name}.");
@@ -82,6 +169,9 @@ pub fn main() {
     trace!("{model_name} loaded: {:x?}", &model_input);
     // XXX verify model_input.input_ptr & model_input.input_size_bytes
 
+    // Output is kept until the next run completes; remember the last
+    // generation seen so a stale result is not mistaken for a new one.
+    let mut last_generation = sdk_model_output(model_id).map_or(0, |output| output.generation);
     let mut model_running = false;
 
     loop {
@@ -120,7 +210,9 @@ pub fn main() {
         if model_running {
             // Fetch output and send through uart.
             match sdk_model_output(model_id) {
+                Ok(output) if output.generation == last_generation => sleep(1000),
                 Ok(output) => {
+                    last_generation = output.generation;
                     if output.return_code == 0 {
                         // Send encoder output to the UART base64-encoded.
                         use base64ct::{Base64, Encoding};
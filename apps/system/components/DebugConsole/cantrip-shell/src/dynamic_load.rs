@@ -115,11 +115,11 @@ fn install_command(
     // The frames are in SELF_CNODE; wrap them in a dynamically allocated
     // CNode (as expected by cantrip_pgk_mgmt_install).
     // TODO(sleffler): useful idiom, add to MemoryManager
-    let cnode_depth = pkg_contents.get().count_log2();
-    let cnode = cantrip_cnode_alloc(cnode_depth).map_err(|_| CommandError::Memory)?;
+    let frame_count = pkg_contents.get().count();
+    let cnode = cantrip_cnode_alloc_for(frame_count).map_err(|_| CommandError::Memory)?;
     pkg_contents
         .get_mut()
-        .move_objects_from_toplevel(cnode.objs[0].cptr, cnode_depth as u8)
+        .move_objects_from_toplevel(cnode.objs[0].cptr, cnode_depth_for(frame_count) as u8)
         .map_err(|_| CommandError::Memory)?;
     match pkg_contents.install() {
         Ok(id) => {
@@ -18,6 +18,7 @@ extern crate alloc;
 use alloc::vec::Vec;
 use core::fmt;
 use core::fmt::Write;
+use core::str;
 use hashbrown::HashMap;
 
 use cantrip_line_reader::LineReader;
@@ -33,9 +34,11 @@ use cantrip_proc_interface::cantrip_proc_ctrl_stop;
 use cantrip_proc_interface::BundleState;
 use cantrip_security_interface::cantrip_security_delete_key;
 use cantrip_security_interface::cantrip_security_get_packages;
+use cantrip_security_interface::cantrip_security_list_keys;
 use cantrip_security_interface::cantrip_security_load_application;
 use cantrip_security_interface::cantrip_security_read_key;
 use cantrip_security_interface::cantrip_security_write_key;
+use cantrip_security_interface::KeyValueData;
 
 use sel4_sys::seL4_CPtr;
 
@@ -127,14 +130,19 @@ fn get_cmds() -> HashMap<&'static str, CmdFn> {
         ("builtins", packages_command as CmdFn), // NB: for backwards compat
         ("bundles", bundles_command as CmdFn),
         ("capscan", capscan_command as CmdFn),
+        ("diagnostics", diagnostics_command as CmdFn),
         ("kvdelete", kvdelete_command as CmdFn),
+        ("kvlist", kvlist_command as CmdFn),
         ("kvread", kvread_command as CmdFn),
         ("kvwrite", kvwrite_command as CmdFn),
         ("loglevel", loglevel_command as CmdFn),
         ("mdebug", mdebug_command as CmdFn),
+        ("mpolicy", mpolicy_command as CmdFn),
+        ("mreset", mreset_command as CmdFn),
         ("mstats", mstats_command as CmdFn),
         ("packages", packages_command as CmdFn),
         ("ps", ps_command as CmdFn),
+        ("sdkstate", sdkstate_command as CmdFn),
         #[cfg(feature = "timer_support")]
         ("sleep", sleep_command as CmdFn),
         ("source", source_command as CmdFn),
@@ -264,7 +272,7 @@ fn source_command(
         filename => (false, filename),
     };
     let mut container_slot = CSpaceSlot::new();
-    match cantrip_security_load_application(filename, &container_slot) {
+    match cantrip_security_load_application(filename, None, &container_slot) {
         Ok(frames) => {
             container_slot.release(); // NB: take ownership
             let mut script_input = io::BufReader::new(cantrip_io_objdesc::Rx::new(&frames));
@@ -360,6 +368,99 @@ fn bundles_command(
     Ok(())
 }
 
+/// Implements a "diagnostics" command that dumps the diagnostics report
+/// of each system service (or only the named one) to the console.
+fn diagnostics_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let target = args.next();
+    let wants = |name: &str| target.is_none() || target == Some(name);
+    if wants("memory") {
+        match cantrip_memory_diagnostics() {
+            Ok(report) => writeln!(output, "{}", report)?,
+            Err(status) => writeln!(output, "memory diagnostics failed: {:?}", status)?,
+        }
+    }
+    #[cfg(feature = "ml_support")]
+    if wants("mlcoord") {
+        match cantrip_mlcoord_diagnostics() {
+            Ok(report) => writeln!(output, "{}", report)?,
+            Err(status) => writeln!(output, "mlcoord diagnostics failed: {:?}", status)?,
+        }
+    }
+    if wants("sdk") {
+        match cantrip_sdk_manager::cantrip_sdk_manager_diagnostics() {
+            Ok(report) => writeln!(output, "{}", report)?,
+            Err(status) => writeln!(output, "sdk diagnostics failed: {:?}", status)?,
+        }
+    }
+    #[cfg(feature = "timer_support")]
+    if wants("timer") {
+        match cantrip_timer_interface::cantrip_timer_diagnostics() {
+            Ok(report) => writeln!(output, "{}", report)?,
+            Err(status) => writeln!(output, "timer diagnostics failed: {:?}", status)?,
+        }
+    }
+    Ok(())
+}
+
+/// Implements an "sdkstate" command that dumps a snapshot of the
+/// SDKRuntime's per-app state to the console. The snapshot is fetched
+/// a page of apps at a time.
+fn sdkstate_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let mut first = 0;
+    loop {
+        let page = match cantrip_sdk_manager::cantrip_sdk_manager_snapshot(first) {
+            Ok(page) => page,
+            Err(status) => {
+                writeln!(output, "sdkstate failed: {:?}", status)?;
+                break;
+            }
+        };
+        let snapshot = page.snapshot;
+        if first == 0 {
+            writeln!(
+                output,
+                "ids {:#010x} pending {:#010x} released {:#010x} faults {}",
+                snapshot.ids, snapshot.pending_mask, snapshot.released_mask, snapshot.faults
+            )?;
+        }
+        for app in snapshot.apps {
+            writeln!(
+                output,
+                "{} (badge {:#x}): models {:?} audio {:?} faults {} ({:?}) log_seq {}",
+                app.app_id,
+                app.badge,
+                app.models,
+                app.audio,
+                app.faults,
+                app.fault_policy,
+                app.log_seq
+            )?;
+            for timer in app.timers {
+                writeln!(
+                    output,
+                    "  timer {} -> {}{}",
+                    timer.app_timer_id,
+                    timer.sdk_timer_id,
+                    if timer.periodic { " periodic" } else { "" }
+                )?;
+            }
+        }
+        match page.next {
+            Some(next) => first = next,
+            None => break,
+        }
+    }
+    Ok(())
+}
+
 /// Implements a "capscan" command that dumps seL4 capabilities to the console.
 #[allow(unused_variables)]
 fn capscan_command(
@@ -493,6 +594,34 @@ fn kvdelete_command(
     Ok(())
 }
 
+fn kvlist_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
+    match cantrip_security_list_keys(bundle_id) {
+        Ok(keys) => {
+            for key in keys {
+                writeln!(output, "{}", key)?;
+            }
+        }
+        Err(status) => {
+            writeln!(output, "List keys failed: {:?}", status)?;
+        }
+    }
+    Ok(())
+}
+
+// Returns |keyval| w/o the zero padding added by the store.
+fn kv_trim(keyval: &KeyValueData) -> &[u8] {
+    let len = keyval
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(0, |pos| pos + 1);
+    &keyval[..len]
+}
+
 fn kvread_command(
     args: &mut dyn Iterator<Item = &str>,
     _input: &mut dyn io::BufRead,
@@ -501,9 +630,10 @@ fn kvread_command(
     let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
     let key = args.next().ok_or(CommandError::BadArgs)?;
     match cantrip_security_read_key(bundle_id, key) {
-        Ok(keyval) => {
-            writeln!(output, "Read key \"{}\" = {:?}.", key, keyval)?;
-        }
+        Ok(keyval) => match str::from_utf8(kv_trim(&keyval)) {
+            Ok(value) => writeln!(output, "Read key \"{}\" = {:?}.", key, value)?,
+            Err(_) => writeln!(output, "Read key \"{}\" = {:?}.", key, kv_trim(&keyval))?,
+        },
         Err(status) => {
             writeln!(output, "Read key \"{}\" failed: {:?}", key, status)?;
         }
@@ -541,6 +671,45 @@ fn mdebug_command(
     Ok(())
 }
 
+fn mreset_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    match cantrip_memory_reset_failure_counters() {
+        Ok(_) => {
+            writeln!(output, "Memory failure counters reset.")?;
+        }
+        Err(status) => {
+            writeln!(output, "reset failed: {:?}", status)?;
+        }
+    }
+    Ok(())
+}
+
+/// Implements an "mpolicy" command that selects how the MemoryManager
+/// picks slabs for allocations: "first" (first-fit) or "best" (best-fit).
+fn mpolicy_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let policy = match args.next().ok_or(CommandError::BadArgs)? {
+        "first" => AllocPolicy::FirstFit,
+        "best" => AllocPolicy::BestFit,
+        _ => return Err(CommandError::BadArgs),
+    };
+    match cantrip_memory_set_alloc_policy(policy) {
+        Ok(_) => {
+            writeln!(output, "Allocation policy {:?}", policy)?;
+        }
+        Err(status) => {
+            writeln!(output, "mpolicy failed: {:?}", status)?;
+        }
+    }
+    Ok(())
+}
+
 fn mstats(output: &mut dyn io::Write, stats: &MemoryManagerStats) -> Result<(), CommandError> {
     writeln!(
         output,
@@ -552,6 +721,11 @@ fn mstats(output: &mut dyn io::Write, stats: &MemoryManagerStats) -> Result<(),
         "{} objs in-use, {} objs requested",
         stats.allocated_objs, stats.total_requested_objs
     )?;
+    writeln!(
+        output,
+        "{} slab too small, {} out of memory failures",
+        stats.untyped_slab_too_small, stats.out_of_memory
+    )?;
     Ok(())
 }
 
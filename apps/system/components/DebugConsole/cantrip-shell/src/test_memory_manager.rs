@@ -23,8 +23,10 @@ use alloc::vec;
 use core::fmt::Write;
 
 use cantrip_io as io;
+use cantrip_memory_interface::stress::Stress;
 use cantrip_memory_interface::*;
 use cantrip_os_common::sel4_sys;
+use cantrip_os_common::slot_allocator::CANTRIP_CSPACE_SLOTS;
 
 use sel4_sys::seL4_CPtr;
 use sel4_sys::seL4_MinSchedContextBits;
@@ -34,12 +36,48 @@ use sel4_sys::seL4_WordBits;
 
 pub fn add_cmds(cmds: &mut HashMap<&str, CmdFn>) {
     cmds.extend([
+        ("test_mdiagnostics", mdiagnostics_command as CmdFn),
+        ("test_mheartbeat", mheartbeat_command as CmdFn),
         ("test_malloc", malloc_command as CmdFn),
         ("test_mfree", mfree_command as CmdFn),
         ("test_obj_alloc", obj_alloc_command as CmdFn),
+        ("test_cspace_reserve", cspace_reserve_command as CmdFn),
+        ("test_mslab_free", mslab_free_command as CmdFn),
+        ("mstress", mstress_command as CmdFn),
     ]);
 }
 
+/// Implements a command that checks the MemoryManager diagnostics report
+/// is populated and consistent with the stats.
+fn mdiagnostics_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let stats = cantrip_memory_stats().expect("stats");
+    let report = cantrip_memory_diagnostics().expect("diagnostics");
+    assert!(!report.is_empty());
+    assert_eq!(report.get("allocated_bytes"), Some(stats.allocated_bytes as u64));
+    assert_eq!(report.get("free_bytes"), Some(stats.free_bytes as u64));
+    assert!(report.get("slabs").is_some());
+    writeln!(output, "{}", report)?;
+    Ok(writeln!(output, "All tests passed!")?)
+}
+
+/// Implements a command that checks the MemoryManager heartbeat count
+/// advances across calls.
+fn mheartbeat_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let first = cantrip_memory_heartbeat().expect("heartbeat");
+    let second = cantrip_memory_heartbeat().expect("heartbeat");
+    assert_ne!(first, second);
+    writeln!(output, "heartbeat {} -> {}", first, second)?;
+    Ok(writeln!(output, "All tests passed!")?)
+}
+
 fn malloc_command(
     args: &mut dyn Iterator<Item = &str>,
     _input: &mut dyn io::BufRead,
@@ -156,10 +194,12 @@ fn obj_alloc_command(
             ObjDesc::new(seL4_SmallPageObject, 10, 6), // 10 4K pages
         ],
     );
-    match cantrip_object_alloc(&objs) {
+    // NB: tagged so a leak shows up in the MemoryManager debug dump.
+    const OWNER: &str = "shell:test_obj_alloc";
+    match cantrip_object_alloc_tagged(&objs, OWNER) {
         Ok(_) => {
             writeln!(output, "Batch alloc ok: {:?}", objs)?;
-            if let Err(e) = cantrip_object_free(&objs) {
+            if let Err(e) = cantrip_object_free_tagged(&objs, OWNER) {
                 writeln!(output, "Batch free err: {:?}", e)?;
             }
         }
@@ -197,3 +237,199 @@ fn obj_alloc_command(
 
     Ok(writeln!(output, "All tests passed!")?)
 }
+
+/// Implements a command that reserves top-level CNode slots for a batch
+/// of allocations and checks the batch does not run out midway.
+fn cspace_reserve_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let free_slots = unsafe { CANTRIP_CSPACE_SLOTS.free_slots() };
+
+    // Reserving more slots than are available fails w/o side effects.
+    assert_eq!(
+        cantrip_cspace_reserve(free_slots + 1).err(),
+        Some(MemoryManagerError::CapAllocFailed)
+    );
+    assert_eq!(unsafe { CANTRIP_CSPACE_SLOTS.free_slots() }, free_slots);
+
+    const BATCH: usize = 4;
+    let mut reservation = cantrip_cspace_reserve(2 * BATCH).expect("reserve");
+    assert_eq!(unsafe { CANTRIP_CSPACE_SLOTS.free_slots() }, free_slots - 2 * BATCH);
+
+    // Each step draws from the reservation.
+    let mut bundles = vec![];
+    for _ in 0..BATCH {
+        match cantrip_object_alloc_in_reserved(
+            vec![
+                ObjDesc::new(seL4_EndpointObject, 1, 0),
+                ObjDesc::new(seL4_SmallPageObject, 1, 1),
+            ],
+            &mut reservation,
+        ) {
+            Ok(objs) => bundles.push(objs),
+            Err(e) => {
+                writeln!(output, "cantrip_object_alloc_in_reserved failed: {:?}", e)?;
+                break;
+            }
+        }
+    }
+    assert_eq!(bundles.len(), BATCH);
+    assert_eq!(reservation.remaining(), 0);
+    // The reservation is exhausted; the next step fails cleanly.
+    assert_eq!(
+        cantrip_object_alloc_in_reserved(
+            vec![ObjDesc::new(seL4_EndpointObject, 1, 0)],
+            &mut reservation
+        )
+        .err(),
+        Some(MemoryManagerError::ObjCapInvalid)
+    );
+    drop(reservation);
+
+    for objs in &bundles {
+        if let Err(e) = cantrip_object_free_toplevel(objs) {
+            writeln!(output, "free {:?} failed: {:?}", objs, e)?;
+        }
+    }
+    assert_eq!(unsafe { CANTRIP_CSPACE_SLOTS.free_slots() }, free_slots);
+
+    Ok(writeln!(output, "All tests passed!")?)
+}
+
+/// Implements a command that checks freed memory is returned to the
+/// slabs it came from. Untypeds are allocated until they spill across
+/// several slabs, then freed in reverse order; the stats must return to
+/// where they started and the same allocations must succeed again.
+fn mslab_free_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    // NB: sizes vary so the objects land in slabs of different sizes
+    const SIZES: [usize; 8] = [16, 12, 14, 16, 13, 15, 16, 12];
+
+    let before_stats = cantrip_memory_stats().expect("before stats");
+    mstats(output, &before_stats)?;
+
+    for pass in 0..2 {
+        let mut allocated = vec![];
+        for size_bits in SIZES {
+            match cantrip_untyped_alloc(size_bits) {
+                Ok(objs) => allocated.push(objs),
+                Err(e) => {
+                    writeln!(output, "pass {}: alloc {} failed: {:?}", pass, size_bits, e)?;
+                    break;
+                }
+            }
+        }
+        // NB: the second pass reuses the space credited back by the first
+        assert_eq!(allocated.len(), SIZES.len());
+        let stats = cantrip_memory_stats().expect("stats");
+        assert!(stats.allocated_bytes > before_stats.allocated_bytes);
+
+        for objs in allocated.iter().rev() {
+            if let Err(e) = cantrip_object_free_toplevel(objs) {
+                writeln!(output, "free {:?} failed: {:?}", objs, e)?;
+            }
+        }
+        let after_stats = cantrip_memory_stats().expect("after stats");
+        mstats(output, &after_stats)?;
+        assert_eq!(before_stats.allocated_bytes, after_stats.allocated_bytes);
+        assert_eq!(before_stats.allocated_objs, after_stats.allocated_objs);
+        assert_eq!(before_stats.free_bytes, after_stats.free_bytes);
+    }
+
+    Ok(writeln!(output, "All tests passed!")?)
+}
+
+// Writes the per-slab usage in |stats|, one line per slab.
+fn mslabs(output: &mut dyn io::Write, stats: &MemoryManagerStats) -> Result<(), CommandError> {
+    writeln!(
+        output,
+        "slab bits {:>10} {:>10} {:>6} {:>10}",
+        "size", "in-use", "objs", "largest"
+    )?;
+    for (index, slab) in stats.slabs.iter().enumerate() {
+        writeln!(
+            output,
+            "{:4} {:4} {:10} {:10} {:6} {:10}",
+            index,
+            slab.size_bits,
+            slab.free_bytes,
+            slab.allocated_bytes,
+            slab.allocated_objects,
+            slab.largest_contiguous_free,
+        )?;
+    }
+    Ok(())
+}
+
+/// Implements a command that runs CYCLES rounds of allocating a random
+/// mix of objects and freeing a random subset to fragment the untyped
+/// slabs. SEED makes a run reproducible. Fragmentation is reported each
+/// cycle and per-slab after the last; at the end everything is freed
+/// and the stats are checked against those taken before the run.
+fn mstress_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let seed = args.next().ok_or(CommandError::BadArgs)?.parse::<u64>()?;
+    let cycles = args.next().ok_or(CommandError::BadArgs)?.parse::<usize>()?;
+    const ALLOCS_PER_CYCLE: usize = 16;
+
+    let before_stats = cantrip_memory_stats().expect("before stats");
+    mstats(output, &before_stats)?;
+
+    let mut stress = Stress::new(seed);
+    let mut too_small = before_stats.untyped_slab_too_small;
+    let mut out_of_memory = before_stats.out_of_memory;
+    for cycle in 0..cycles {
+        let report = stress.cycle(
+            ALLOCS_PER_CYCLE,
+            |od| cantrip_object_alloc_in_toplevel(vec![od]).ok(),
+            |objs| {
+                if let Err(e) = cantrip_object_free_toplevel(&objs) {
+                    let _ = writeln!(output, "free {:?} failed: {:?}", objs, e);
+                }
+            },
+        );
+        let stats = cantrip_memory_stats().expect("stats");
+        // NB: slab too small counts requests that fit the free space but
+        //   not any one slab, i.e. are blocked by fragmentation.
+        writeln!(
+            output,
+            "cycle {}: {} allocs, {} failed, {} frees, {} live",
+            cycle, report.allocs, report.failures, report.frees, report.live,
+        )?;
+        writeln!(
+            output,
+            "  {} bytes in-use, {} bytes free, +{} slab too small, +{} out of memory",
+            stats.allocated_bytes,
+            stats.free_bytes,
+            stats.untyped_slab_too_small - too_small,
+            stats.out_of_memory - out_of_memory,
+        )?;
+        too_small = stats.untyped_slab_too_small;
+        out_of_memory = stats.out_of_memory;
+        if cycle + 1 == cycles {
+            mslabs(output, &stats)?;
+        }
+    }
+    let freed = stress.drain(|objs| {
+        if let Err(e) = cantrip_object_free_toplevel(&objs) {
+            let _ = writeln!(output, "free {:?} failed: {:?}", objs, e);
+        }
+    });
+    writeln!(output, "Freed {} remaining allocations", freed)?;
+
+    let after_stats = cantrip_memory_stats().expect("after stats");
+    mstats(output, &after_stats)?;
+    assert_eq!(before_stats.allocated_bytes, after_stats.allocated_bytes);
+    assert_eq!(before_stats.allocated_objs, after_stats.allocated_objs);
+    assert_eq!(before_stats.free_bytes, after_stats.free_bytes);
+
+    Ok(writeln!(output, "All tests passed!")?)
+}
@@ -25,8 +25,13 @@ use cantrip_ml_interface::*;
 pub fn add_cmds(cmds: &mut HashMap<&str, CmdFn>) {
     cmds.extend([
         ("test_mlcancel", mlcancel_command as CmdFn),
+        ("test_mldiagnostics", mldiagnostics_command as CmdFn),
         ("test_mlexecute", mlexecute_command as CmdFn),
+        ("test_mlheartbeat", mlheartbeat_command as CmdFn),
+        ("test_mlmemory", mlmemory_command as CmdFn),
+        ("test_mlpause", mlpause_command as CmdFn),
         ("test_mlperiodic", mlperiodic_command as CmdFn),
+        ("test_mlresume", mlresume_command as CmdFn),
     ]);
 }
 
@@ -47,6 +52,78 @@ fn mlcancel_command(
     Ok(())
 }
 
+/// Implements a command that checks the MlCoordinator diagnostics report
+/// is populated.
+fn mldiagnostics_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let report = cantrip_mlcoord_diagnostics().expect("diagnostics");
+    assert!(!report.is_empty());
+    assert!(report.get("models").is_some());
+    assert!(report.get("queued").is_some());
+    writeln!(output, "{}", report)?;
+    Ok(writeln!(output, "All tests passed!")?)
+}
+
+/// Implements a command that checks the MlCoordinator heartbeat count
+/// advances across calls.
+fn mlheartbeat_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let first = cantrip_mlcoord_heartbeat().expect("heartbeat");
+    let second = cantrip_mlcoord_heartbeat().expect("heartbeat");
+    assert_ne!(first, second);
+    writeln!(output, "heartbeat {} -> {}", first, second)?;
+    Ok(writeln!(output, "All tests passed!")?)
+}
+
+/// Implements a command that checks the TCM used by the MlCoordinator
+/// grows by a model's footprint when the model is loaded.
+fn mlmemory_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
+    let model_id = args.next().ok_or(CommandError::BadArgs)?;
+
+    let is_model = |m: &MlModelMemory| m.bundle_id == bundle_id && m.model_id == model_id;
+    let before = cantrip_mlcoord_memory_stats().expect("memory_stats");
+    assert_eq!(before.used_bytes + before.free_bytes, before.total_bytes);
+    assert!(
+        !before.models.iter().any(is_model),
+        "{}:{} already loaded",
+        bundle_id,
+        model_id
+    );
+
+    cantrip_mlcoord_oneshot(bundle_id, model_id, None).expect("oneshot");
+    let after = cantrip_mlcoord_memory_stats().expect("memory_stats");
+    let model = after
+        .models
+        .into_iter()
+        .find(is_model)
+        .expect("model not loaded");
+    // NB: used may grow by more than the model if its temporary data
+    //     are larger than those of the models already loaded.
+    assert!(after.used_bytes >= before.used_bytes + model.size_bytes);
+    assert_eq!(after.used_bytes + after.free_bytes, after.total_bytes);
+    writeln!(
+        output,
+        "used {} -> {} of {} ({} enabled), model {}",
+        before.used_bytes,
+        after.used_bytes,
+        after.total_bytes,
+        after.enabled_bytes,
+        model.size_bytes
+    )?;
+    Ok(writeln!(output, "All tests passed!")?)
+}
+
 /// Implements a command that runs a oneshot ML execution.
 fn mlexecute_command(
     args: &mut dyn Iterator<Item = &str>,
@@ -56,13 +133,47 @@ fn mlexecute_command(
     let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
     let model_id = args.next().ok_or(CommandError::BadArgs)?;
 
-    if let Err(e) = cantrip_mlcoord_oneshot(bundle_id, model_id) {
+    if let Err(e) = cantrip_mlcoord_oneshot(bundle_id, model_id, None) {
         writeln!(output, "Execute {:?} {:?} err: {:?}", bundle_id, model_id, e)?;
     }
 
     Ok(())
 }
 
+/// Implements a command that pauses a periodic ML execution.
+fn mlpause_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
+    let model_id = args.next().ok_or(CommandError::BadArgs)?;
+
+    if let Err(e) = cantrip_mlcoord_pause(bundle_id, model_id) {
+        writeln!(output, "Pause {:?} {:?} err: {:?}", bundle_id, model_id, e)?;
+    } else {
+        writeln!(output, "Paused {:?} {:?}", bundle_id, model_id)?;
+    }
+    Ok(())
+}
+
+/// Implements a command that resumes a paused periodic ML execution.
+fn mlresume_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
+    let model_id = args.next().ok_or(CommandError::BadArgs)?;
+
+    if let Err(e) = cantrip_mlcoord_resume(bundle_id, model_id) {
+        writeln!(output, "Resume {:?} {:?} err: {:?}", bundle_id, model_id, e)?;
+    } else {
+        writeln!(output, "Resumed {:?} {:?}", bundle_id, model_id)?;
+    }
+    Ok(())
+}
+
 /// Implements a command that runs a periodic ML execution.
 fn mlperiodic_command(
     args: &mut dyn Iterator<Item = &str>,
@@ -74,7 +185,7 @@ fn mlperiodic_command(
     let rate_str = args.next().ok_or(CommandError::BadArgs)?;
     let rate_in_ms = rate_str.parse::<u32>()?;
 
-    if let Err(e) = cantrip_mlcoord_periodic(bundle_id, model_id, rate_in_ms) {
+    if let Err(e) = cantrip_mlcoord_periodic(bundle_id, model_id, rate_in_ms, None) {
         writeln!(output, "Periodic {:?} {:?} err: {:?}", bundle_id, model_id, e)?;
     }
 
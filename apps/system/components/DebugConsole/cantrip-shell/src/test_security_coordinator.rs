@@ -15,9 +15,12 @@
 //! SecurityCoordinator shell test commands
 
 extern crate alloc;
+use crate::kv_trim;
 use crate::CmdFn;
 use crate::CommandError;
 use crate::HashMap;
+use alloc::string::ToString;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::Write;
 use core::mem::size_of;
@@ -35,9 +38,30 @@ pub fn add_cmds(cmds: &mut HashMap<&str, CmdFn>) {
         ("load_application", load_application_command as CmdFn),
         ("load_model", load_model_command as CmdFn),
         ("test_mailbox", test_mailbox_command as CmdFn),
+        ("kvtest", kvtest_command as CmdFn),
     ]);
 }
 
+// Bundle used by the kvtest command. Key-value ops require a loaded
+// bundle; the name is given w/o suffix to exercise find_key promotion.
+const KV_TEST_BUNDLE: &str = "keyval";
+
+// Loads KV_TEST_BUNDLE if it is not already loaded. Note loading
+// resets the bundle's key-value store so this is done only once.
+fn kv_test_bundle() -> Result<&'static str, SecurityRequestError> {
+    match cantrip_security_list_keys(KV_TEST_BUNDLE) {
+        Err(SecurityRequestError::BundleNotFound) => {
+            let mut container_slot = CSpaceSlot::new();
+            let frames = cantrip_security_load_application(KV_TEST_BUNDLE, None, &container_slot)?;
+            container_slot.release(); // NB: take ownership
+            let _ = cantrip_object_free_in_cnode(&frames);
+            Ok(KV_TEST_BUNDLE)
+        }
+        Err(e) => Err(e),
+        Ok(_) => Ok(KV_TEST_BUNDLE),
+    }
+}
+
 /// Implements an "scecho" command that sends arguments to the Security Core's echo service.
 fn scecho_command(
     args: &mut dyn Iterator<Item = &str>,
@@ -85,7 +109,7 @@ fn load_application_command(
 ) -> Result<(), CommandError> {
     let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
     let mut container_slot = CSpaceSlot::new();
-    match cantrip_security_load_application(bundle_id, &container_slot) {
+    match cantrip_security_load_application(bundle_id, None, &container_slot) {
         Ok(frames) => {
             container_slot.release(); // NB: take ownership
             writeln!(output, "{:?}", frames)?;
@@ -136,3 +160,67 @@ fn test_mailbox_command(
     }
     Ok(())
 }
+
+/// Implements a "kvtest" command that runs the key-value store through
+/// set/get/delete round trips and checks the key listing.
+fn kvtest_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let bundle_id = kv_test_bundle().expect("load test bundle");
+    // Start from an empty store.
+    for key in cantrip_security_list_keys(bundle_id).expect("list") {
+        cantrip_security_delete_key(bundle_id, &key).expect("delete");
+    }
+
+    // The fully-qualified bundle name must reach the same store.
+    let app_bundle_id = bundle_id.to_string() + ".app";
+    assert_eq!(cantrip_security_write_key(bundle_id, "foo", b"bar"), Ok(()));
+    assert_eq!(
+        cantrip_security_read_key(&app_bundle_id, "foo")
+            .as_ref()
+            .map(kv_trim),
+        Ok(&b"bar"[..])
+    );
+    assert_eq!(cantrip_security_write_key(&app_bundle_id, "baz", b"qux"), Ok(()));
+    assert_eq!(
+        cantrip_security_list_keys(bundle_id),
+        Ok(vec!["baz".to_string(), "foo".to_string()])
+    );
+
+    // Overwrite replaces the whole value.
+    assert_eq!(cantrip_security_write_key(bundle_id, "foo", b"b"), Ok(()));
+    assert_eq!(
+        cantrip_security_read_key(bundle_id, "foo")
+            .as_ref()
+            .map(kv_trim),
+        Ok(&b"b"[..])
+    );
+
+    // Oversized values are rejected and leave the store untouched.
+    assert_eq!(
+        cantrip_security_write_key(bundle_id, "big", &[1u8; KEY_VALUE_DATA_SIZE + 1]),
+        Err(SecurityRequestError::ValueInvalid)
+    );
+    assert_eq!(
+        cantrip_security_read_key(bundle_id, "big"),
+        Err(SecurityRequestError::KeyNotFound)
+    );
+
+    assert_eq!(cantrip_security_delete_key(bundle_id, "foo"), Ok(()));
+    assert_eq!(
+        cantrip_security_read_key(bundle_id, "foo"),
+        Err(SecurityRequestError::KeyNotFound)
+    );
+    assert_eq!(cantrip_security_delete_key(bundle_id, "baz"), Ok(()));
+    assert_eq!(cantrip_security_list_keys(bundle_id), Ok(vec![]));
+
+    // Unknown bundles are reported as such.
+    assert_eq!(
+        cantrip_security_list_keys("nosuchbundle"),
+        Err(SecurityRequestError::BundleNotFound)
+    );
+
+    Ok(writeln!(output, "All tests passed!")?)
+}
@@ -22,6 +22,8 @@ use core::fmt::Write;
 use cantrip_io as io;
 
 use cantrip_timer_interface::cantrip_timer_completed_timers;
+use cantrip_timer_interface::cantrip_timer_diagnostics;
+use cantrip_timer_interface::cantrip_timer_heartbeat;
 use cantrip_timer_interface::cantrip_timer_oneshot;
 use cantrip_timer_interface::cantrip_timer_wait;
 
@@ -30,6 +32,8 @@ pub fn add_cmds(cmds: &mut HashMap<&str, CmdFn>) {
         ("test_timer_async", timer_async_command as CmdFn),
         ("test_timer_blocking", timer_blocking_command as CmdFn),
         ("test_timer_completed", timer_completed_command as CmdFn),
+        ("test_timer_diagnostics", timer_diagnostics_command as CmdFn),
+        ("test_timer_heartbeat", timer_heartbeat_command as CmdFn),
     ]);
 }
 
@@ -90,3 +94,32 @@ fn timer_completed_command(
         cantrip_timer_completed_timers().unwrap(),
     )?)
 }
+
+/// Implements a command that checks the TimerService diagnostics report
+/// is populated.
+fn timer_diagnostics_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let report = cantrip_timer_diagnostics().expect("diagnostics");
+    assert!(!report.is_empty());
+    assert!(report.get("now").is_some());
+    assert!(report.get("pending").is_some());
+    writeln!(output, "{}", report)?;
+    Ok(writeln!(output, "All tests passed!")?)
+}
+
+/// Implements a command that checks the TimerService heartbeat count
+/// advances across calls.
+fn timer_heartbeat_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+) -> Result<(), CommandError> {
+    let first = cantrip_timer_heartbeat().expect("heartbeat");
+    let second = cantrip_timer_heartbeat().expect("heartbeat");
+    assert_ne!(first, second);
+    writeln!(output, "heartbeat {} -> {}", first, second)?;
+    Ok(writeln!(output, "All tests passed!")?)
+}
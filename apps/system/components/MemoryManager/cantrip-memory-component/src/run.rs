@@ -22,6 +22,9 @@
 //error[E0658]: dereferencing raw mutable pointers in statics is unstable
 #![feature(const_mut_refs)]
 
+use cantrip_memory_interface::AllocPolicy;
+use cantrip_memory_interface::DiagnosticsResponse;
+use cantrip_memory_interface::HeartbeatResponse;
 use cantrip_memory_interface::MemoryLifetime;
 use cantrip_memory_interface::MemoryManagerError;
 use cantrip_memory_interface::MemoryManagerInterface;
@@ -31,6 +34,8 @@ use cantrip_memory_interface::StatsResponse;
 use cantrip_memory_interface::MEMORY_REQUEST_DATA_SIZE;
 use cantrip_memory_manager::CantripMemoryManager;
 use cantrip_os_common::camkes;
+use cantrip_os_common::diagnostics::Diagnostics;
+use cantrip_os_common::diagnostics::Heartbeat;
 use cantrip_os_common::logger;
 use cantrip_os_common::sel4_sys;
 use core::ops::Range;
@@ -48,7 +53,7 @@ mod generated {
 }
 use generated::*;
 
-fn cantrip_memory() -> impl MemoryManagerInterface {
+fn cantrip_memory() -> impl MemoryManagerInterface + Diagnostics {
     static CANTRIP_MEMORY: CantripMemoryManager = CantripMemoryManager::empty();
     let mut manager = CANTRIP_MEMORY.get();
     if manager.is_empty() {
@@ -103,6 +108,12 @@ impl CamkesThreadInterface for MemoryManagerControlThread {
 
 type MemoryManagerResult = Result<Option<seL4_CPtr>, MemoryManagerError>;
 
+// Connection badges of the clients allowed to change the global allocation
+// policy (debug_console & process_manager).
+// NB: badges are assigned in the order clients are listed in the multi_memory
+//   connection, starting at 1; system.camkes must keep these two first.
+const ALLOC_POLICY_CLIENTS: [usize; 2] = [1, 2];
+
 struct MemoryInterfaceThread;
 impl CamkesThreadInterface for MemoryInterfaceThread {
     fn run() {
@@ -116,7 +127,7 @@ impl CamkesThreadInterface for MemoryInterfaceThread {
 }
 impl MemoryInterfaceThread {
     fn dispatch(
-        _client_badge: usize,
+        client_badge: usize,
         request_buffer: &[u8],
         reply_buffer: &mut [u8],
     ) -> MemoryManagerResult {
@@ -125,20 +136,34 @@ impl MemoryInterfaceThread {
             Ok(request) => request,
             Err(_) => return Err(MemoryManagerError::DeserializeFailed),
         };
+        let owner = request.owner();
         match request {
             MemoryManagerRequest::Alloc {
                 mut bundle,
                 lifetime,
-            } => Self::alloc_request(bundle.to_mut(), lifetime),
-            MemoryManagerRequest::Free(mut bundle) => Self::free_request(bundle.to_mut()),
+                ..
+            } => Self::alloc_request(bundle.to_mut(), lifetime, owner),
+            MemoryManagerRequest::Free { mut bundle, .. } => {
+                Self::free_request(bundle.to_mut(), owner)
+            }
             MemoryManagerRequest::Stats => Self::stats_request(reply_buffer),
 
             MemoryManagerRequest::Debug => Self::debug_request(),
             MemoryManagerRequest::Capscan => Self::capscan_request(),
+            MemoryManagerRequest::Diagnostics => Self::diagnostics_request(reply_buffer),
+            MemoryManagerRequest::Heartbeat => Self::heartbeat_request(reply_buffer),
+            MemoryManagerRequest::ResetFailureCounters => Self::reset_failure_counters_request(),
+            MemoryManagerRequest::SetAllocPolicy(policy) => {
+                Self::set_alloc_policy_request(client_badge, policy)
+            }
         }
     }
 
-    fn alloc_request(bundle: &mut ObjDescBundle, lifetime: MemoryLifetime) -> MemoryManagerResult {
+    fn alloc_request(
+        bundle: &mut ObjDescBundle,
+        lifetime: MemoryLifetime,
+        owner: Option<&str>,
+    ) -> MemoryManagerResult {
         // NB: make sure noone clobbers the setup done in memory__init;
         // and clear any capability the path points to when dropped, for next request
         let recv_path = CAMKES.get_owned_current_recv_path();
@@ -147,10 +172,12 @@ impl MemoryInterfaceThread {
 
         bundle.cnode = recv_path.1;
         // NB: bundle.depth should reflect the received cnode
-        cantrip_memory().alloc(bundle, lifetime).map(|_| None)
+        cantrip_memory()
+            .alloc(bundle, lifetime, owner)
+            .map(|_| None)
     }
 
-    fn free_request(bundle: &mut ObjDescBundle) -> MemoryManagerResult {
+    fn free_request(bundle: &mut ObjDescBundle, owner: Option<&str>) -> MemoryManagerResult {
         // NB: make sure noone clobbers the setup done in pre_init;
         // and clear any capability the path points to when dropped, for next request
         let recv_path = CAMKES.get_owned_current_recv_path();
@@ -159,7 +186,7 @@ impl MemoryInterfaceThread {
 
         bundle.cnode = recv_path.1;
         // NB: bundle.depth should reflect the received cnode
-        cantrip_memory().free(bundle).map(|_| None)
+        cantrip_memory().free(bundle, owner).map(|_| None)
     }
 
     fn stats_request(reply_buffer: &mut [u8]) -> MemoryManagerResult {
@@ -181,6 +208,26 @@ impl MemoryInterfaceThread {
         cantrip_memory().debug().map(|_| None)
     }
 
+    fn reset_failure_counters_request() -> MemoryManagerResult {
+        let recv_path = CAMKES.get_current_recv_path();
+        CAMKES.assert_recv_path();
+        Camkes::debug_assert_slot_empty("reset_failure_counters_request", &recv_path);
+
+        cantrip_memory().reset_failure_counters().map(|_| None)
+    }
+
+    fn set_alloc_policy_request(client_badge: usize, policy: AllocPolicy) -> MemoryManagerResult {
+        let recv_path = CAMKES.get_current_recv_path();
+        CAMKES.assert_recv_path();
+        Camkes::debug_assert_slot_empty("set_alloc_policy_request", &recv_path);
+
+        if !ALLOC_POLICY_CLIENTS.contains(&client_badge) {
+            info!("Reject alloc policy change from badge {}", client_badge);
+            return Err(MemoryManagerError::PermissionDenied);
+        }
+        cantrip_memory().set_alloc_policy(policy).map(|_| None)
+    }
+
     fn capscan_request() -> MemoryManagerResult {
         let recv_path = CAMKES.get_current_recv_path();
         CAMKES.assert_recv_path();
@@ -189,4 +236,27 @@ impl MemoryInterfaceThread {
         let _ = Camkes::capscan();
         Ok(None)
     }
+
+    fn diagnostics_request(reply_buffer: &mut [u8]) -> MemoryManagerResult {
+        let recv_path = CAMKES.get_current_recv_path();
+        CAMKES.assert_recv_path();
+        Camkes::debug_assert_slot_empty("diagnostics_request", &recv_path);
+
+        let report = cantrip_memory().diagnostics();
+        let _ = postcard::to_slice(&DiagnosticsResponse { report }, reply_buffer)
+            .or(Err(MemoryManagerError::SerializeFailed))?;
+        Ok(None)
+    }
+
+    fn heartbeat_request(reply_buffer: &mut [u8]) -> MemoryManagerResult {
+        let recv_path = CAMKES.get_current_recv_path();
+        CAMKES.assert_recv_path();
+        Camkes::debug_assert_slot_empty("heartbeat_request", &recv_path);
+
+        static HEARTBEAT: Heartbeat = Heartbeat::new();
+        let count = HEARTBEAT.beat();
+        let _ = postcard::to_slice(&HeartbeatResponse { count }, reply_buffer)
+            .or(Err(MemoryManagerError::SerializeFailed))?;
+        Ok(None)
+    }
 }
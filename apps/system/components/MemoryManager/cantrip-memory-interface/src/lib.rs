@@ -21,6 +21,7 @@ use alloc::borrow::Cow;
 use alloc::vec;
 use alloc::vec::Vec;
 use cantrip_os_common::camkes;
+use cantrip_os_common::diagnostics::DiagnosticsReport;
 use cantrip_os_common::sel4_sys;
 use cantrip_os_common::slot_allocator;
 use core::fmt;
@@ -40,10 +41,16 @@ use sel4_sys::seL4_PageBits;
 use sel4_sys::seL4_PageTableObject;
 use sel4_sys::seL4_Result;
 use sel4_sys::seL4_SmallPageObject;
+use sel4_sys::seL4_Word;
 use sel4_sys::seL4_WordBits;
 
+use slot_allocator::SlotReservation;
 use slot_allocator::CANTRIP_CSPACE_SLOTS;
 
+mod mapped;
+pub use mapped::{cantrip_frame_alloc_mapped, FrameMapper, MappedFrames, Sel4FrameMapper};
+pub mod stress;
+
 extern "Rust" {
     // Each CAmkES-generated CNode has a writable self-reference to itself in
     // the slot SELF_CNODE to enable dynamic management of capabilities.
@@ -65,7 +72,6 @@ extern "Rust" {
 // allocated objects; e.g. map page frames into a VSpace, bind endpoints
 // to irq's, configure TCB slots, etc.
 //
-// TODO(sleffler): support setting fixed physical address for drivers
 // TODO(sleffler): maybe allocate associated resources like endpoint #'s?
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct ObjDesc {
@@ -89,16 +95,41 @@ pub struct ObjDesc {
     // the vm attributes of page frames (e.g. mark not executable as
     // appropriate).
     pub cptr: seL4_CPtr,
+
+    // Physical address the objects must occupy. Only honored for
+    // MemoryLifetime::Device requests (e.g. a driver's register frames).
+    paddr: Option<seL4_Word>,
 }
 impl ObjDesc {
     pub fn new(type_: seL4_ObjectType, count: usize, cptr: seL4_CPtr) -> Self {
-        ObjDesc { type_, count, cptr }
+        ObjDesc {
+            type_,
+            count,
+            cptr,
+            paddr: None,
+        }
+    }
+
+    // Returns the ObjDesc with the objects placed starting at |paddr|.
+    pub fn with_paddr(self, paddr: seL4_Word) -> Self {
+        ObjDesc {
+            paddr: Some(paddr),
+            ..self
+        }
     }
 
-    // Returns a new ObjDesc with count of 1 and the cptr offset by |index|.
+    pub fn paddr(&self) -> Option<seL4_Word> { self.paddr }
+
+    // Returns a new ObjDesc with count of 1 and the cptr (and paddr, if
+    // any) offset by |index|.
     pub fn new_at(&self, index: usize) -> ObjDesc {
         assert!(index < self.retype_count());
-        ObjDesc::new(self.type_, 1, self.cptr + index)
+        ObjDesc {
+            paddr: self
+                .paddr
+                .and_then(|paddr| Some(paddr + index * (1 << self.size_bits()?))),
+            ..ObjDesc::new(self.type_, 1, self.cptr + index)
+        }
     }
 
     // Parameters for seL4_Untyped_Retype call.
@@ -142,8 +173,12 @@ impl ObjDesc {
 
     // Checks if two descriptors can be combined. This is used to optimize
     // dynamically constructed ObjDescBundle's (e.g. rz::Upload)
+    // NB: objects placed at a physical address are never combined.
     pub fn can_combine(&self, other: &ObjDesc) -> bool {
-        self.type_ == other.type_ && self.cptr + self.count == other.cptr
+        self.type_ == other.type_
+            && self.cptr + self.count == other.cptr
+            && self.paddr.is_none()
+            && other.paddr.is_none()
     }
 }
 
@@ -159,10 +194,41 @@ pub struct ObjDescBundle {
 }
 impl ObjDescBundle {
     pub fn new(cnode: seL4_CPtr, depth: u8, objs: Vec<ObjDesc>) -> Self {
-        // TODO(sleffler): assert the largest cptr fits in the container
+        // NB: validate_depth checks the largest cptr fits in the container
         ObjDescBundle { cnode, depth, objs }
     }
 
+    // Checks |depth| is usable for addressing |cnode|: it must be in
+    // [1, seL4_WordBits] and, for anything but the top-level CNode
+    // (addressed with seL4_WordBits), every object must fit in the
+    // 2^|depth| slots of the container. A mismatch otherwise shows up
+    // as an obscure kernel error from retype or delete.
+    pub fn validate_depth(&self) -> Result<(), MemoryManagerError> {
+        let depth = self.depth as usize;
+        if depth == 0 || depth > seL4_WordBits {
+            return Err(MemoryManagerError::DepthInvalid);
+        }
+        if depth < seL4_WordBits {
+            let slots = 1usize << depth;
+            for od in &self.objs {
+                match od.cptr.checked_add(od.retype_count()) {
+                    Some(end) if end <= slots => {}
+                    _ => return Err(MemoryManagerError::DepthInvalid),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Like validate_depth but for a container whose size is known:
+    // |cnode| has 2^|size_bits| slots and no guard so |depth| must match.
+    pub fn validate_depth_for(&self, size_bits: usize) -> Result<(), MemoryManagerError> {
+        if self.depth as usize != size_bits {
+            return Err(MemoryManagerError::DepthInvalid);
+        }
+        self.validate_depth()
+    }
+
     // Returns whether there are any object descriptors.
     pub fn is_empty(&self) -> bool { self.objs.len() == 0 }
 
@@ -205,13 +271,26 @@ impl ObjDescBundle {
     // CNode and mutate the Object Descriptor with the new cptr's.
     // NB: there is no attempt to preserve the order of cptr's (and
     // in practice they are linearized).
-    // TODO(sleffler) make generic (requires supplying slot allocator)?
     pub fn move_objects_to_toplevel(&mut self) -> seL4_Result {
+        self.move_objects_to_toplevel_with(|count| unsafe { CANTRIP_CSPACE_SLOTS.alloc(count) })
+    }
+
+    // Like move_objects_to_toplevel but the top-level slots are taken
+    // from |reservation|.
+    pub fn move_objects_to_reserved(&mut self, reservation: &mut CSpaceReservation) -> seL4_Result {
+        self.move_objects_to_toplevel_with(|count| reservation.alloc(count))
+    }
+
+    fn move_objects_to_toplevel_with(
+        &mut self,
+        mut alloc_slots: impl FnMut(usize) -> Option<usize>,
+    ) -> seL4_Result {
         let dest_cnode = unsafe { SELF_CNODE };
         let dest_depth = seL4_WordBits as u8;
         for od in &mut self.objs {
-            let dest_slot = unsafe { CANTRIP_CSPACE_SLOTS.alloc(od.retype_count()) }
-                .ok_or(seL4_Error::seL4_NotEnoughMemory)?; // XXX seL4_Result not a good fit
+            // XXX seL4_Result not a good fit
+            let dest_slot =
+                alloc_slots(od.retype_count()).ok_or(seL4_Error::seL4_NotEnoughMemory)?;
             for offset in 0..od.retype_count() {
                 unsafe {
                     // TODO(sleffler): cleanup on error?
@@ -301,6 +380,10 @@ pub enum MemoryManagerError {
     // Generic errors.
     AllocFailed,
     FreeFailed,
+    MapFailed,
+    DepthInvalid,           // Bundle depth does not match its CNode
+    DeviceRangeUnavailable, // No device memory covers the physical range
+    PermissionDenied,       // Caller may not make the request
 }
 impl From<MemoryManagerError> for Result<(), MemoryManagerError> {
     fn from(err: MemoryManagerError) -> Result<(), MemoryManagerError> {
@@ -312,6 +395,21 @@ impl From<MemoryManagerError> for Result<(), MemoryManagerError> {
     }
 }
 
+// Usage of one untyped slab; used to study fragmentation.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SlabStats {
+    pub size_bits: usize,         // Log2 size of the slab
+    pub free_bytes: usize,        // Space available in the slab when empty
+    pub allocated_bytes: usize,   // Space held by objects
+    pub allocated_objects: usize, // Objects held
+    // Largest allocation possible. Space is only reclaimed when a slab
+    // empties so this is what remains past the high-watermark.
+    pub largest_contiguous_free: usize,
+}
+
+// Slabs reported in MemoryManagerStats; this bounds the reply size.
+pub const MAX_SLAB_STATS: usize = 32;
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct MemoryManagerStats {
     // Current space committed to allocations.
@@ -337,6 +435,9 @@ pub struct MemoryManagerStats {
 
     // Alloc requests failed due to lack of untyped memory.
     pub out_of_memory: usize,
+
+    // Per-slab usage of the first MAX_SLAB_STATS slabs.
+    pub slabs: Vec<SlabStats>,
 }
 
 // Hint that indicates the expected lifetime of the allocated memory
@@ -349,19 +450,37 @@ pub enum MemoryLifetime {
     Short,  // e.g. loading a model
     Medium, // e.g. for application/process construction
     Static, // Never free'd
+    Device, // Device memory at a physical address (see ObjDesc::paddr)
+}
+
+// How the MemoryManager chooses the slab each object is allocated from.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AllocPolicy {
+    FirstFit, // First slab with room, starting from the last one used
+    #[default]
+    BestFit, // Slab left with the least space after the allocation
 }
 
 // Objects are potentially batched with caps to allocated objects returned
-// in the container slots specified by the |bundle] objects.
+// in the container slots specified by the |bundle] objects. An |owner| tag
+// names the subsystem responsible for the objects so leaks can be
+// attributed; objects allocated with a tag must be freed with the same tag.
 pub trait MemoryManagerInterface {
     fn alloc(
         &mut self,
         bundle: &ObjDescBundle,
         lifetime: MemoryLifetime,
+        owner: Option<&str>,
+    ) -> Result<(), MemoryManagerError>;
+    fn free(
+        &mut self,
+        bundle: &ObjDescBundle,
+        owner: Option<&str>,
     ) -> Result<(), MemoryManagerError>;
-    fn free(&mut self, bundle: &ObjDescBundle) -> Result<(), MemoryManagerError>;
     fn stats(&self) -> Result<MemoryManagerStats, MemoryManagerError>;
     fn debug(&self) -> Result<(), MemoryManagerError>;
+    fn set_alloc_policy(&mut self, policy: AllocPolicy) -> Result<(), MemoryManagerError>;
+    fn reset_failure_counters(&mut self) -> Result<(), MemoryManagerError>;
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -369,27 +488,81 @@ pub struct StatsResponse {
     pub value: MemoryManagerStats,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiagnosticsResponse {
+    pub report: DiagnosticsReport,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeartbeatResponse {
+    pub count: u64,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum MemoryManagerRequest<'a> {
     Alloc {
         bundle: Cow<'a, ObjDescBundle>,
         lifetime: MemoryLifetime,
+        #[cfg(feature = "CONFIG_ALLOC_OWNERS")]
+        owner: Option<&'a str>,
+    },
+    Free {
+        bundle: Cow<'a, ObjDescBundle>,
+        #[cfg(feature = "CONFIG_ALLOC_OWNERS")]
+        owner: Option<&'a str>,
     },
-    Free(Cow<'a, ObjDescBundle>),
     Stats, // -> MemoryResponseData
     Debug,
     Capscan,
+    Diagnostics, // -> DiagnosticsResponse
+    Heartbeat,   // -> HeartbeatResponse
+    ResetFailureCounters,
+    SetAllocPolicy(AllocPolicy),
 }
 
 impl<'a> MemoryManagerRequest<'a> {
+    // Returns an Alloc request; |owner| is dropped unless owner tags
+    // are configured.
+    fn alloc(bundle: &'a ObjDescBundle, lifetime: MemoryLifetime, owner: Option<&'a str>) -> Self {
+        #[cfg(not(feature = "CONFIG_ALLOC_OWNERS"))]
+        let _ = owner;
+        Self::Alloc {
+            bundle: Cow::Borrowed(bundle),
+            lifetime,
+            #[cfg(feature = "CONFIG_ALLOC_OWNERS")]
+            owner,
+        }
+    }
+    // Returns a Free request; see alloc.
+    fn free(bundle: &'a ObjDescBundle, owner: Option<&'a str>) -> Self {
+        #[cfg(not(feature = "CONFIG_ALLOC_OWNERS"))]
+        let _ = owner;
+        Self::Free {
+            bundle: Cow::Borrowed(bundle),
+            #[cfg(feature = "CONFIG_ALLOC_OWNERS")]
+            owner,
+        }
+    }
+
+    // Returns the owner tag of an Alloc or Free request.
+    pub fn owner(&self) -> Option<&'a str> {
+        #[cfg(feature = "CONFIG_ALLOC_OWNERS")]
+        if let Self::Alloc { owner, .. } | Self::Free { owner, .. } = self {
+            return *owner;
+        }
+        None
+    }
+
     fn get_container_cap(&self) -> Option<seL4_CPtr> {
         match self {
-            Self::Alloc {
-                bundle,
-                lifetime: _,
-            }
-            | Self::Free(bundle) => Some(bundle.cnode),
-            Self::Stats | Self::Debug | Self::Capscan => None,
+            Self::Alloc { bundle, .. } | Self::Free { bundle, .. } => Some(bundle.cnode),
+            Self::Stats
+            | Self::Debug
+            | Self::Capscan
+            | Self::Diagnostics
+            | Self::Heartbeat
+            | Self::ResetFailureCounters
+            | Self::SetAllocPolicy(_) => None,
         }
     }
 }
@@ -426,10 +599,24 @@ fn cantrip_memory_request<T: DeserializeOwned>(
 #[inline]
 pub fn cantrip_object_alloc(request: &ObjDescBundle) -> Result<(), MemoryManagerError> {
     trace!("cantrip_object_alloc {}", request);
-    cantrip_memory_request(&MemoryManagerRequest::Alloc {
-        bundle: Cow::Borrowed(request),
-        lifetime: MemoryLifetime::Medium,
-    })
+    cantrip_memory_request(&MemoryManagerRequest::alloc(request, MemoryLifetime::Medium, None))
+}
+
+// Like cantrip_object_alloc but the objects are tagged with |owner| (e.g.
+// "SecurityCoordinator:deep_copy") for leak attribution. The objects must
+// be released with cantrip_object_free_tagged and the same |owner|. The
+// tag is recorded only with CONFIG_ALLOC_OWNERS.
+#[inline]
+pub fn cantrip_object_alloc_tagged(
+    request: &ObjDescBundle,
+    owner: &'static str,
+) -> Result<(), MemoryManagerError> {
+    trace!("cantrip_object_alloc_tagged {} {}", request, owner);
+    cantrip_memory_request(&MemoryManagerRequest::alloc(
+        request,
+        MemoryLifetime::Medium,
+        Some(owner),
+    ))
 }
 
 // Allocates the objects specified in |request| with a specified |lifetime|.
@@ -441,10 +628,7 @@ pub fn cantrip_object_alloc_with_lifetime(
     lifetime: MemoryLifetime,
 ) -> Result<(), MemoryManagerError> {
     trace!("cantrip_object_alloc_with_lifetime {} {:?}", request, lifetime);
-    cantrip_memory_request(&MemoryManagerRequest::Alloc {
-        bundle: Cow::Borrowed(request),
-        lifetime,
-    })
+    cantrip_memory_request(&MemoryManagerRequest::alloc(request, lifetime, None))
 }
 
 // Allocates the objects specified in |objs|. The capabilities are moved
@@ -452,14 +636,66 @@ pub fn cantrip_object_alloc_with_lifetime(
 #[inline]
 pub fn cantrip_object_alloc_in_toplevel(
     objs: Vec<ObjDesc>,
+) -> Result<ObjDescBundle, MemoryManagerError> {
+    object_alloc_in_toplevel(objs, None)
+}
+
+// Like cantrip_object_alloc_in_toplevel but the objects are tagged with
+// |owner|; see cantrip_object_alloc_tagged.
+#[inline]
+pub fn cantrip_object_alloc_in_toplevel_tagged(
+    objs: Vec<ObjDesc>,
+    owner: &'static str,
+) -> Result<ObjDescBundle, MemoryManagerError> {
+    object_alloc_in_toplevel(objs, Some(owner))
+}
+
+fn object_alloc_in_toplevel(
+    objs: Vec<ObjDesc>,
+    owner: Option<&'static str>,
 ) -> Result<ObjDescBundle, MemoryManagerError> {
     // Request the objects using the dedicated MemoryManager container.
     let mut request =
         ObjDescBundle::new(unsafe { MEMORY_RECV_CNODE }, unsafe { MEMORY_RECV_CNODE_DEPTH }, objs);
-    cantrip_object_alloc(&request)?;
+    cantrip_memory_request(&MemoryManagerRequest::alloc(&request, MemoryLifetime::Medium, owner))?;
     match request.move_objects_to_toplevel() {
         Err(_) => {
-            cantrip_object_free(&request).expect("cantrip_object_alloc_in_toplevel");
+            cantrip_memory_request(&MemoryManagerRequest::free(&request, owner))
+                .expect("cantrip_object_alloc_in_toplevel");
+            Err(MemoryManagerError::ObjCapInvalid) // TODO(sleffler): e.into
+        }
+        Ok(_) => Ok(request),
+    }
+}
+
+// Top-level CNode slots set aside with cantrip_cspace_reserve.
+pub type CSpaceReservation = SlotReservation<'static>;
+
+// Reserves |count| slots in SELF_CNODE for a multi-step build so that
+// running out of slots is detected before any objects are allocated.
+// Objects are placed in the reserved slots with
+// cantrip_object_alloc_in_reserved; any slots not used are returned
+// when the reservation is dropped.
+#[inline]
+pub fn cantrip_cspace_reserve(count: usize) -> Result<CSpaceReservation, MemoryManagerError> {
+    trace!("cantrip_cspace_reserve {}", count);
+    unsafe { CANTRIP_CSPACE_SLOTS.reserve(count) }.ok_or(MemoryManagerError::CapAllocFailed)
+}
+
+// Like cantrip_object_alloc_in_toplevel but the capabilities are moved
+// to slots taken from |reservation|.
+#[inline]
+pub fn cantrip_object_alloc_in_reserved(
+    objs: Vec<ObjDesc>,
+    reservation: &mut CSpaceReservation,
+) -> Result<ObjDescBundle, MemoryManagerError> {
+    // Request the objects using the dedicated MemoryManager container.
+    let mut request =
+        ObjDescBundle::new(unsafe { MEMORY_RECV_CNODE }, unsafe { MEMORY_RECV_CNODE_DEPTH }, objs);
+    cantrip_object_alloc(&request)?;
+    match request.move_objects_to_reserved(reservation) {
+        Err(_) => {
+            cantrip_object_free(&request).expect("cantrip_object_alloc_in_reserved");
             Err(MemoryManagerError::ObjCapInvalid) // TODO(sleffler): e.into
         }
         Ok(_) => Ok(request),
@@ -591,6 +827,25 @@ pub fn cantrip_cnode_alloc(size_bits: usize) -> Result<ObjDescBundle, MemoryMana
     Ok(objs)
 }
 
+// Returns the log2 #slots of the smallest CNode that holds |count| caps.
+// The slot count is rounded up to a power of two so, for example, 4 caps
+// fit in a depth 2 CNode but 5 need depth 3 (8 slots). NB: we return
+// values > 0 since the kernel rejects a CapTable object with size_bits=0.
+pub fn cnode_depth_for(count: usize) -> usize {
+    let depth = match count.checked_next_power_of_two() {
+        Some(slots) => slots.trailing_zeros() as usize,
+        None => usize::BITS as usize, // NB: too large to ever allocate
+    };
+    depth.max(1)
+}
+
+#[inline]
+// Allocates a CNode with the fewest slots that hold |frame_count| caps;
+// the CNode depth is cnode_depth_for(|frame_count|).
+pub fn cantrip_cnode_alloc_for(frame_count: usize) -> Result<ObjDescBundle, MemoryManagerError> {
+    cantrip_cnode_alloc(cnode_depth_for(frame_count))
+}
+
 #[cfg(feature = "CONFIG_KERNEL_MCS")]
 #[inline]
 pub fn cantrip_sched_context_alloc(size_bits: usize) -> Result<ObjDescBundle, MemoryManagerError> {
@@ -644,6 +899,33 @@ pub fn cantrip_frame_alloc(space_bytes: usize) -> Result<ObjDescBundle, MemoryMa
     Ok(objs)
 }
 
+// Allocates frames covering the |size| bytes of device memory starting
+// at |paddr| (e.g. a driver's registers). The capabilities are moved to
+// SELF_CNODE. Fails with DeviceRangeUnavailable if the range is not
+// covered by a single device untyped.
+#[inline]
+pub fn cantrip_device_frame_alloc(
+    paddr: seL4_Word,
+    size: usize,
+) -> Result<ObjDescBundle, MemoryManagerError> {
+    fn howmany(value: usize, unit: usize) -> usize { (value + (unit - 1)) / unit }
+    let mut objs = ObjDescBundle::new(
+        unsafe { MEMORY_RECV_CNODE },
+        unsafe { MEMORY_RECV_CNODE_DEPTH },
+        // NB: always allocate 4K pages
+        vec![ObjDesc::new(
+            seL4_SmallPageObject,
+            howmany(size, 1 << seL4_PageBits),
+            /*cptr=*/ 0,
+        )
+        .with_paddr(paddr)],
+    );
+    cantrip_object_alloc_with_lifetime(&objs, MemoryLifetime::Device)?;
+    objs.move_objects_to_toplevel()
+        .or(Err(MemoryManagerError::ObjCapInvalid))?;
+    Ok(objs)
+}
+
 // Like cantrip_frame_alloc but also create a CNode to hold the frames.
 #[inline]
 pub fn cantrip_frame_alloc_in_cnode(
@@ -686,7 +968,17 @@ pub fn cantrip_page_table_alloc() -> Result<ObjDescBundle, MemoryManagerError> {
 #[inline]
 pub fn cantrip_object_free(request: &ObjDescBundle) -> Result<(), MemoryManagerError> {
     trace!("cantrip_object_free {}", request);
-    cantrip_memory_request(&MemoryManagerRequest::Free(Cow::Borrowed(request)))
+    cantrip_memory_request(&MemoryManagerRequest::free(request, None))
+}
+
+// Frees objects allocated with cantrip_object_alloc_tagged.
+#[inline]
+pub fn cantrip_object_free_tagged(
+    request: &ObjDescBundle,
+    owner: &'static str,
+) -> Result<(), MemoryManagerError> {
+    trace!("cantrip_object_free_tagged {} {}", request, owner);
+    cantrip_memory_request(&MemoryManagerRequest::free(request, Some(owner)))
 }
 
 // Free |request| and then the container that holds them. The container
@@ -694,6 +986,29 @@ pub fn cantrip_object_free(request: &ObjDescBundle) -> Result<(), MemoryManagerE
 // cantrip_object_alloc_in_cnode).
 #[inline]
 pub fn cantrip_object_free_in_cnode(request: &ObjDescBundle) -> Result<(), MemoryManagerError> {
+    cantrip_object_free(request)?;
+    // No way to recover if this fails..
+    cantrip_cnode_free(request)
+}
+
+// Like cantrip_object_free_in_cnode for objects allocated with |owner|;
+// the container is untagged.
+#[inline]
+pub fn cantrip_object_free_in_cnode_tagged(
+    request: &ObjDescBundle,
+    owner: &'static str,
+) -> Result<(), MemoryManagerError> {
+    cantrip_object_free_tagged(request, owner)?;
+    // No way to recover if this fails..
+    cantrip_cnode_free(request)
+}
+
+// Free only the container that holds |request|; any caps left in it
+// are deleted with the container but the objects are not freed (e.g.
+// |request| holds dups of objects owned by another component). The
+// container is expected to be in the top-level CNode.
+#[inline]
+pub fn cantrip_cnode_free(request: &ObjDescBundle) -> Result<(), MemoryManagerError> {
     let cnode_obj = ObjDescBundle::new(
         unsafe { SELF_CNODE },
         seL4_WordBits as u8,
@@ -703,8 +1018,6 @@ pub fn cantrip_object_free_in_cnode(request: &ObjDescBundle) -> Result<(), Memor
             /*cptr=*/ request.cnode,
         )],
     );
-    cantrip_object_free(request)?;
-    // No way to recover if this fails..
     cantrip_object_free_toplevel(&cnode_obj)
 }
 
@@ -731,7 +1044,147 @@ pub fn cantrip_memory_debug() -> Result<(), MemoryManagerError> {
     cantrip_memory_request(&MemoryManagerRequest::Debug)
 }
 
+#[inline]
+pub fn cantrip_memory_reset_failure_counters() -> Result<(), MemoryManagerError> {
+    cantrip_memory_request(&MemoryManagerRequest::ResetFailureCounters)
+}
+
+// NB: only the DebugConsole & ProcessManager may change the policy; other
+//   callers get PermissionDenied.
+#[inline]
+pub fn cantrip_memory_set_alloc_policy(policy: AllocPolicy) -> Result<(), MemoryManagerError> {
+    cantrip_memory_request(&MemoryManagerRequest::SetAllocPolicy(policy))
+}
+
 #[inline]
 pub fn cantrip_memory_capscan() -> Result<(), MemoryManagerError> {
     cantrip_memory_request(&MemoryManagerRequest::Capscan)
 }
+
+#[inline]
+pub fn cantrip_memory_diagnostics() -> Result<DiagnosticsReport, MemoryManagerError> {
+    cantrip_memory_request(&MemoryManagerRequest::Diagnostics)
+        .map(|reply: DiagnosticsResponse| reply.report)
+}
+
+#[inline]
+pub fn cantrip_memory_heartbeat() -> Result<u64, MemoryManagerError> {
+    cantrip_memory_request(&MemoryManagerRequest::Heartbeat)
+        .map(|reply: HeartbeatResponse| reply.count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cnode_depth_fits() {
+        for (count, depth) in [
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (3, 2),
+            (4, 2),
+            (5, 3),
+            (64, 6),
+            (65, 7),
+            (1 << 12, 12),
+            ((1 << 12) + 1, 13),
+        ] {
+            assert_eq!(cnode_depth_for(count), depth, "count {}", count);
+        }
+    }
+
+    #[test]
+    fn stats_fit_reply() {
+        let slab = SlabStats {
+            size_bits: 31,
+            free_bytes: 1 << 31,
+            allocated_bytes: 1 << 31,
+            allocated_objects: 1 << 19,
+            largest_contiguous_free: 1 << 31,
+        };
+        let response = StatsResponse {
+            value: MemoryManagerStats {
+                allocated_bytes: usize::MAX,
+                free_bytes: usize::MAX,
+                total_requested_bytes: usize::MAX,
+                overhead_bytes: usize::MAX,
+                allocated_objs: usize::MAX,
+                total_requested_objs: usize::MAX,
+                untyped_slab_too_small: usize::MAX,
+                out_of_memory: usize::MAX,
+                slabs: vec![slab; MAX_SLAB_STATS],
+            },
+        };
+        // NB: the reply gets what remains of the 4KiB rpc buffer.
+        let mut reply = [0u8; 4096 - MEMORY_REQUEST_DATA_SIZE];
+        assert!(postcard::to_slice(&response, &mut reply).is_ok());
+    }
+
+    fn bundle(depth: u8, objs: Vec<ObjDesc>) -> ObjDescBundle { ObjDescBundle::new(1, depth, objs) }
+
+    #[test]
+    fn bundle_depth_valid() {
+        let frames = |count, cptr| ObjDesc::new(seL4_SmallPageObject, count, cptr);
+        assert_eq!(bundle(1, vec![frames(2, 0)]).validate_depth(), Ok(()));
+        assert_eq!(bundle(3, vec![frames(4, 0), frames(4, 4)]).validate_depth(), Ok(()));
+        // Top-level path addressing does not bound the cptr's.
+        assert_eq!(
+            bundle(seL4_WordBits as u8, vec![frames(1, usize::MAX - 1)]).validate_depth(),
+            Ok(())
+        );
+        assert_eq!(bundle(8, vec![]).validate_depth_for(8), Ok(()));
+        // A CNode's size is log2 slots, not a count of objects.
+        let cnode = ObjDesc::new(seL4_CapTableObject, 10, 0);
+        assert_eq!(bundle(1, vec![cnode]).validate_depth(), Ok(()));
+    }
+
+    #[test]
+    fn bundle_depth_invalid() {
+        let frames = |count, cptr| ObjDesc::new(seL4_SmallPageObject, count, cptr);
+        assert_eq!(
+            bundle(0, vec![frames(1, 0)]).validate_depth(),
+            Err(MemoryManagerError::DepthInvalid)
+        );
+        assert_eq!(
+            bundle(seL4_WordBits as u8 + 1, vec![frames(1, 0)]).validate_depth(),
+            Err(MemoryManagerError::DepthInvalid)
+        );
+        // Last object overruns the 8 slots of a depth 3 CNode.
+        assert_eq!(
+            bundle(3, vec![frames(4, 0), frames(4, 5)]).validate_depth(),
+            Err(MemoryManagerError::DepthInvalid)
+        );
+        assert_eq!(
+            bundle(3, vec![frames(1, usize::MAX)]).validate_depth(),
+            Err(MemoryManagerError::DepthInvalid)
+        );
+        // Depth disagrees with the CNode's actual size.
+        assert_eq!(
+            bundle(4, vec![frames(1, 0)]).validate_depth_for(5),
+            Err(MemoryManagerError::DepthInvalid)
+        );
+    }
+
+    #[test]
+    fn obj_desc_paddr() {
+        let frames = ObjDesc::new(seL4_SmallPageObject, 4, 8).with_paddr(0x4000_0000);
+        let frame = frames.new_at(2);
+        assert_eq!((frame.cptr, frame.paddr()), (10, Some(0x4000_2000)));
+        assert_eq!(ObjDesc::new(seL4_SmallPageObject, 4, 8).new_at(2).paddr(), None);
+        // Placed objects are not merged with their neighbours.
+        let next = ObjDesc::new(seL4_SmallPageObject, 1, 12);
+        assert!(!frames.can_combine(&next));
+        assert!(ObjDesc::new(seL4_SmallPageObject, 4, 8).can_combine(&next));
+    }
+
+    #[test]
+    fn cnode_depth_smallest() {
+        for count in 2..=1024usize {
+            let depth = cnode_depth_for(count);
+            assert!(count <= 1 << depth, "count {} depth {}", count, depth);
+            assert!(count > 1 << (depth - 1), "count {} depth {}", count, depth);
+        }
+    }
+}
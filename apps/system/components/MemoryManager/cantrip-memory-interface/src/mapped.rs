@@ -0,0 +1,266 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Page frames allocated and mapped for immediate use.
+//!
+//! The frames are mapped contiguously into a window of the caller's
+//! VSpace reserved for the purpose (typically a CAmkES copyregion).
+//! The MappedFrames guard unmaps and frees them when dropped.
+
+use crate::cantrip_frame_alloc;
+use crate::cantrip_object_free_toplevel;
+use crate::MemoryManagerError;
+use crate::ObjDescBundle;
+use cantrip_os_common::sel4_sys;
+use core::marker::PhantomData;
+use log::error;
+
+use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_CapRights;
+use sel4_sys::seL4_Default_VMAttributes;
+use sel4_sys::seL4_PageBits;
+use sel4_sys::seL4_Page_Map;
+use sel4_sys::seL4_Page_Unmap;
+use sel4_sys::seL4_Word;
+
+const PAGE_SIZE: usize = 1 << seL4_PageBits;
+
+extern "Rust" {
+    static SELF_VSPACE_ROOT: seL4_CPtr;
+}
+
+/// Operations on frames used by MappedFrames; split out for testing.
+pub trait FrameMapper {
+    /// Maps |frame| r/w at |vaddr| in our VSpace.
+    fn map(&mut self, frame: seL4_CPtr, vaddr: usize) -> Result<(), MemoryManagerError>;
+    /// Unmaps a frame previously mapped with |map|.
+    fn unmap(&mut self, frame: seL4_CPtr);
+    /// Returns |frames| to the MemoryManager.
+    fn free(&mut self, frames: &ObjDescBundle);
+}
+
+/// FrameMapper that uses seL4 & the MemoryManager.
+pub struct Sel4FrameMapper;
+impl FrameMapper for Sel4FrameMapper {
+    fn map(&mut self, frame: seL4_CPtr, vaddr: usize) -> Result<(), MemoryManagerError> {
+        unsafe {
+            seL4_Page_Map(
+                frame,
+                SELF_VSPACE_ROOT,
+                vaddr as seL4_Word,
+                // seL4_ReadWrite
+                seL4_CapRights::new(
+                    /*grant_reply=*/ 0, /*grant=*/ 0, /*read=*/ 1, /*write=*/ 1,
+                ),
+                seL4_Default_VMAttributes,
+            )
+        }
+        .or(Err(MemoryManagerError::MapFailed))
+    }
+    fn unmap(&mut self, frame: seL4_CPtr) {
+        if let Err(e) = unsafe { seL4_Page_Unmap(frame) } {
+            error!("unmap {} failed: {:?}", frame, e);
+        }
+    }
+    fn free(&mut self, frames: &ObjDescBundle) {
+        if let Err(e) = cantrip_object_free_toplevel(frames) {
+            error!("free {} failed: {:?}", frames, e);
+        }
+    }
+}
+
+/// Frames mapped into a VSpace window; see cantrip_frame_alloc_mapped.
+///
+/// The lifetime 'a ties the guard to the window the frames are mapped in.
+pub struct MappedFrames<'a, M: FrameMapper = Sel4FrameMapper> {
+    frames: ObjDescBundle,
+    vaddr: *mut u8,
+    size: usize,   // Bytes requested
+    mapped: usize, // # frames mapped
+    mapper: M,
+    _window_lifetime: PhantomData<&'a mut [u8]>,
+}
+impl<'a, M: FrameMapper> MappedFrames<'a, M> {
+    /// Maps |frames| into |window| and takes ownership of them. |size|
+    /// is the number of usable bytes. On failure |frames| are freed.
+    pub fn new(
+        frames: ObjDescBundle,
+        size: usize,
+        window: &'a mut [u8],
+        mapper: M,
+    ) -> Result<Self, MemoryManagerError> {
+        let mut mapped = MappedFrames {
+            frames,
+            vaddr: window.as_mut_ptr(),
+            size,
+            mapped: 0,
+            mapper,
+            _window_lifetime: PhantomData,
+        };
+        if (mapped.vaddr as usize) & (PAGE_SIZE - 1) != 0
+            || mapped.frames.count() * PAGE_SIZE > window.len()
+            || size > mapped.frames.count() * PAGE_SIZE
+        {
+            return Err(MemoryManagerError::ObjCountInvalid);
+        }
+        let vaddr = mapped.vaddr as usize;
+        for (index, frame) in mapped.frames.cptr_iter().enumerate() {
+            // NB: Drop unmaps what was mapped before any failure.
+            mapped.mapper.map(frame, vaddr + index * PAGE_SIZE)?;
+            mapped.mapped += 1;
+        }
+        Ok(mapped)
+    }
+
+    /// Returns the frame objects.
+    pub fn frames(&self) -> &ObjDescBundle { &self.frames }
+
+    /// Returns a pointer to the start of the mapped memory.
+    pub fn as_mut_ptr(&mut self) -> *mut u8 { self.vaddr }
+
+    /// Returns the mapped memory.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.vaddr, self.size) }
+    }
+}
+impl<'a, M: FrameMapper> Drop for MappedFrames<'a, M> {
+    fn drop(&mut self) {
+        for frame in self.frames.cptr_iter().take(self.mapped) {
+            self.mapper.unmap(frame);
+        }
+        self.mapper.free(&self.frames);
+    }
+}
+
+/// Allocates frames for |space_bytes| and maps them at the start of
+/// |window|. The frames are unmapped and freed when the returned guard
+/// is dropped.
+pub fn cantrip_frame_alloc_mapped(
+    space_bytes: usize,
+    window: &mut [u8],
+) -> Result<MappedFrames<'_>, MemoryManagerError> {
+    if space_bytes > window.len() {
+        return Err(MemoryManagerError::ObjCountInvalid);
+    }
+    let frames = cantrip_frame_alloc(space_bytes)?;
+    MappedFrames::new(frames, space_bytes, window, Sel4FrameMapper)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObjDesc;
+    use alloc::rc::Rc;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+    use sel4_sys::seL4_SmallPageObject;
+
+    #[derive(Default)]
+    struct Log {
+        mapped: Vec<(seL4_CPtr, usize)>,
+        unmapped: Vec<seL4_CPtr>,
+        freed: Vec<ObjDescBundle>,
+    }
+
+    // Window memory is ordinary host memory so mapping is a no-op.
+    struct MockMapper {
+        log: Rc<RefCell<Log>>,
+        fail_at: Option<seL4_CPtr>,
+    }
+    impl FrameMapper for MockMapper {
+        fn map(&mut self, frame: seL4_CPtr, vaddr: usize) -> Result<(), MemoryManagerError> {
+            if self.fail_at == Some(frame) {
+                return Err(MemoryManagerError::MapFailed);
+            }
+            self.log.borrow_mut().mapped.push((frame, vaddr));
+            Ok(())
+        }
+        fn unmap(&mut self, frame: seL4_CPtr) { self.log.borrow_mut().unmapped.push(frame); }
+        fn free(&mut self, frames: &ObjDescBundle) {
+            self.log.borrow_mut().freed.push(frames.clone());
+        }
+    }
+
+    #[repr(align(4096))]
+    struct Window([u8; 2 * PAGE_SIZE]);
+
+    fn frames(count: usize) -> ObjDescBundle {
+        ObjDescBundle::new(
+            /*cnode=*/ 1,
+            /*depth=*/ 32,
+            vec![ObjDesc::new(seL4_SmallPageObject, count, /*cptr=*/ 10)],
+        )
+    }
+
+    #[test]
+    fn write_and_drop() {
+        let log = Rc::new(RefCell::new(Log::default()));
+        let mut window = Window([0; 2 * PAGE_SIZE]);
+        let base = window.0.as_ptr() as usize;
+        {
+            let mapper = MockMapper {
+                log: log.clone(),
+                fail_at: None,
+            };
+            let mut mapped =
+                MappedFrames::new(frames(2), PAGE_SIZE + 10, &mut window.0, mapper).unwrap();
+            assert_eq!(log.borrow().mapped, [(10, base), (11, base + PAGE_SIZE)]);
+            assert_eq!(mapped.as_mut_slice().len(), PAGE_SIZE + 10);
+            unsafe { mapped.as_mut_ptr().add(PAGE_SIZE).write(0xa5) };
+            mapped.as_mut_slice()[0] = 0x5a;
+            assert!(log.borrow().unmapped.is_empty());
+            assert!(log.borrow().freed.is_empty());
+        }
+        assert_eq!(window.0[0], 0x5a);
+        assert_eq!(window.0[PAGE_SIZE], 0xa5);
+        // Dropping the guard unmaps & frees every frame.
+        assert_eq!(log.borrow().unmapped, [10, 11]);
+        assert_eq!(log.borrow().freed.len(), 1);
+        assert_eq!(log.borrow().freed[0].count(), 2);
+    }
+
+    #[test]
+    fn map_failure() {
+        let log = Rc::new(RefCell::new(Log::default()));
+        let mut window = Window([0; 2 * PAGE_SIZE]);
+        let mapper = MockMapper {
+            log: log.clone(),
+            fail_at: Some(11),
+        };
+        assert!(matches!(
+            MappedFrames::new(frames(2), 2 * PAGE_SIZE, &mut window.0, mapper),
+            Err(MemoryManagerError::MapFailed)
+        ));
+        // The frame mapped before the failure is unmapped; all are freed.
+        assert_eq!(log.borrow().unmapped, [10]);
+        assert_eq!(log.borrow().freed.len(), 1);
+    }
+
+    #[test]
+    fn window_too_small() {
+        let log = Rc::new(RefCell::new(Log::default()));
+        let mut window = Window([0; 2 * PAGE_SIZE]);
+        let mapper = MockMapper {
+            log: log.clone(),
+            fail_at: None,
+        };
+        assert!(matches!(
+            MappedFrames::new(frames(3), PAGE_SIZE, &mut window.0, mapper),
+            Err(MemoryManagerError::ObjCountInvalid)
+        ));
+        assert!(log.borrow().mapped.is_empty());
+        assert_eq!(log.borrow().freed.len(), 1);
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Synthetic allocator workload for studying fragmentation.
+//!
+//! Each cycle allocates a random mix of objects and then frees a random
+//! subset of everything live, leaving holes for the next cycle to fill.
+//! The workload is reproducible from its seed. Allocation and free are
+//! supplied by the caller so the same workload can drive the
+//! MemoryManager (e.g. the shell's mstress command) or a model of it.
+
+use crate::ObjDesc;
+use alloc::vec::Vec;
+use cantrip_os_common::sel4_sys;
+
+use sel4_sys::seL4_ObjectType;
+use sel4_sys::seL4_ObjectType::*;
+use sel4_sys::seL4_SmallPageObject;
+
+// Objects drawn by each allocation: (type, count). Frames dominate as
+// they do for applications and come in a range of sizes.
+const MIX: [(seL4_ObjectType, usize); 8] = [
+    (seL4_SmallPageObject, 1),
+    (seL4_SmallPageObject, 1),
+    (seL4_SmallPageObject, 2),
+    (seL4_SmallPageObject, 4),
+    (seL4_SmallPageObject, 8),
+    (seL4_EndpointObject, 1),
+    (seL4_NotificationObject, 1),
+    (seL4_TCBObject, 1),
+];
+
+/// Outcome of one stress cycle.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct StressCycle {
+    pub allocs: usize,   // Allocations that succeeded
+    pub failures: usize, // Allocations that failed
+    pub frees: usize,    // Allocations freed
+    pub live: usize,     // Allocations still held
+}
+
+/// Stress workload holding the allocations (|H|) still live.
+pub struct Stress<H> {
+    state: u64, // xorshift64 state; never zero
+    live: Vec<H>,
+}
+impl<H> Stress<H> {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: if seed == 0 { 1 } else { seed },
+            live: Vec::new(),
+        }
+    }
+
+    // Returns a pseudo-random value in [0, n).
+    fn below(&mut self, n: usize) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state % n as u64) as usize
+    }
+
+    pub fn live(&self) -> usize { self.live.len() }
+
+    /// Runs one cycle: makes |allocs| requests for objects drawn from
+    /// the mix with |alloc| then frees a random subset (about half) of
+    /// the live allocations with |free|. |alloc| returns None when the
+    /// request fails.
+    pub fn cycle(
+        &mut self,
+        allocs: usize,
+        mut alloc: impl FnMut(ObjDesc) -> Option<H>,
+        mut free: impl FnMut(H),
+    ) -> StressCycle {
+        let mut cycle = StressCycle::default();
+        for _ in 0..allocs {
+            let (type_, count) = MIX[self.below(MIX.len())];
+            match alloc(ObjDesc::new(type_, count, 0)) {
+                Some(handle) => {
+                    self.live.push(handle);
+                    cycle.allocs += 1;
+                }
+                None => cycle.failures += 1,
+            }
+        }
+        let mut index = 0;
+        while index < self.live.len() {
+            if self.below(2) == 0 {
+                free(self.live.swap_remove(index));
+                cycle.frees += 1;
+            } else {
+                index += 1;
+            }
+        }
+        cycle.live = self.live.len();
+        cycle
+    }
+
+    /// Frees every live allocation. Returns the number freed.
+    pub fn drain(&mut self, free: impl FnMut(H)) -> usize {
+        let count = self.live.len();
+        self.live.drain(..).for_each(free);
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tracks what is allocated like the MemoryManager stats; allocations
+    // beyond |capacity| bytes fail.
+    struct Model {
+        capacity: usize,
+        allocated_bytes: usize,
+        allocated_objs: usize,
+    }
+    impl Model {
+        fn new(capacity: usize) -> Self {
+            Self {
+                capacity,
+                allocated_bytes: 0,
+                allocated_objs: 0,
+            }
+        }
+        fn alloc(&mut self, od: ObjDesc) -> Option<ObjDesc> {
+            let size_bytes = od.size_bytes().unwrap();
+            if self.allocated_bytes + size_bytes > self.capacity {
+                return None;
+            }
+            self.allocated_bytes += size_bytes;
+            self.allocated_objs += od.retype_count();
+            Some(od)
+        }
+        fn free(&mut self, od: ObjDesc) {
+            self.allocated_bytes -= od.size_bytes().unwrap();
+            self.allocated_objs -= od.retype_count();
+        }
+        fn stats(&self) -> (usize, usize) { (self.allocated_bytes, self.allocated_objs) }
+    }
+
+    // Runs |cycles| cycles followed by a drain.
+    fn run(seed: u64, cycles: usize, model: &mut Model) -> Vec<StressCycle> {
+        let model = core::cell::RefCell::new(model);
+        let mut stress = Stress::new(seed);
+        let reports = (0..cycles)
+            .map(|_| {
+                stress.cycle(
+                    16,
+                    |od| model.borrow_mut().alloc(od),
+                    |od| model.borrow_mut().free(od),
+                )
+            })
+            .collect();
+        stress.drain(|od| model.borrow_mut().free(od));
+        assert_eq!(stress.live(), 0);
+        reports
+    }
+
+    #[test]
+    fn no_leak() {
+        let mut model = Model::new(usize::MAX);
+        let baseline = model.stats();
+        let reports = run(1, 8, &mut model);
+        assert!(reports.iter().all(|r| r.allocs == 16 && r.failures == 0));
+        // Holes are left each cycle so allocations accumulate.
+        assert!(reports.iter().all(|r| r.frees > 0 && r.live > 0));
+        let allocs: usize = reports.iter().map(|r| r.allocs).sum();
+        let frees: usize = reports.iter().map(|r| r.frees).sum();
+        assert_eq!(allocs - frees, reports.last().unwrap().live);
+        assert_eq!(model.stats(), baseline);
+    }
+
+    #[test]
+    fn no_leak_on_failure() {
+        // Room for a handful of objects so some requests fail.
+        let mut model = Model::new(8 * 4096);
+        let reports = run(7, 8, &mut model);
+        assert!(reports.iter().any(|r| r.failures > 0));
+        assert_eq!(model.stats(), (0, 0));
+    }
+
+    #[test]
+    fn reproducible() {
+        let mut model = Model::new(usize::MAX);
+        let first = run(42, 4, &mut model);
+        assert_eq!(run(42, 4, &mut model), first);
+        assert_ne!(run(43, 4, &mut model), first);
+    }
+}
@@ -16,11 +16,16 @@
 
 #![cfg_attr(not(test), no_std)]
 
+extern crate alloc;
+use alloc::vec::Vec;
+
+use cantrip_memory_interface::AllocPolicy;
 use cantrip_memory_interface::MemoryLifetime;
 use cantrip_memory_interface::MemoryManagerError;
 use cantrip_memory_interface::MemoryManagerInterface;
 use cantrip_memory_interface::MemoryManagerStats;
 use cantrip_memory_interface::ObjDescBundle;
+use cantrip_os_common::diagnostics::{Diagnostics, DiagnosticsReport};
 use cantrip_os_common::sel4_sys;
 use core::ops::Range;
 use sel4_sys::seL4_CPtr;
@@ -64,20 +69,43 @@ impl Guard<'_> {
         assert!(self.manager.is_none());
         *self.manager = Some(MemoryManager::new(ut_slots, untypeds));
     }
+
+    // Splits the untyped at |src_cptr|; see MemoryManager::carve.
+    pub fn carve(
+        &self,
+        src_cptr: seL4_CPtr,
+        sizes: &[usize],
+    ) -> Result<Vec<seL4_CPtr>, MemoryManagerError> {
+        self.manager.as_ref().unwrap().carve(src_cptr, sizes)
+    }
 }
 impl MemoryManagerInterface for Guard<'_> {
     fn alloc(
         &mut self,
         objs: &ObjDescBundle,
         lifetime: MemoryLifetime,
+        owner: Option<&str>,
     ) -> Result<(), MemoryManagerError> {
-        self.manager.as_mut().unwrap().alloc(objs, lifetime)
+        self.manager.as_mut().unwrap().alloc(objs, lifetime, owner)
     }
-    fn free(&mut self, objs: &ObjDescBundle) -> Result<(), MemoryManagerError> {
-        self.manager.as_mut().unwrap().free(objs)
+    fn free(
+        &mut self,
+        objs: &ObjDescBundle,
+        owner: Option<&str>,
+    ) -> Result<(), MemoryManagerError> {
+        self.manager.as_mut().unwrap().free(objs, owner)
     }
     fn stats(&self) -> Result<MemoryManagerStats, MemoryManagerError> {
         self.manager.as_ref().unwrap().stats()
     }
     fn debug(&self) -> Result<(), MemoryManagerError> { self.manager.as_ref().unwrap().debug() }
+    fn set_alloc_policy(&mut self, policy: AllocPolicy) -> Result<(), MemoryManagerError> {
+        self.manager.as_mut().unwrap().set_alloc_policy(policy)
+    }
+    fn reset_failure_counters(&mut self) -> Result<(), MemoryManagerError> {
+        self.manager.as_mut().unwrap().reset_failure_counters()
+    }
+}
+impl Diagnostics for Guard<'_> {
+    fn diagnostics(&self) -> DiagnosticsReport { self.manager.as_ref().unwrap().diagnostics() }
 }
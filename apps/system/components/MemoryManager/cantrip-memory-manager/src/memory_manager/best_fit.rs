@@ -0,0 +1,179 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Best-fit slab selection.
+//!
+//! The slab chosen for an object is the one with the fewest bytes left
+//! over after the object is placed. Slabs left with the same number of
+//! bytes are broken by the smaller cptr: the slabs are re-sorted by
+//! free_bytes as allocations proceed but cptrs are assigned at boot so
+//! placement is reproducible.
+//!
+//! Best-fit must look at every slab. First-fit stops at the first slab
+//! with room and is used instead when there are many slabs.
+
+use super::UntypedSlab;
+use cantrip_memory_interface::ObjDesc;
+
+// Returns the bytes of |slab| in use after placing |od| at the slab's
+// free index (aligned to the size of a single object) or None if |od|
+// has no size.
+pub fn new_slab_size(slab: &UntypedSlab, od: &ObjDesc) -> Option<usize> {
+    fn align_up_bytes(base_value: usize, alignment_bytes: usize) -> usize {
+        (base_value + alignment_bytes - 1) & !(alignment_bytes - 1)
+    }
+    // NB: the size of a single object is needed to get the alignment
+    //   right. Variable-size objects (untypeds, CNodes, sched contexts)
+    //   are sized by the request and retyped one at a time; fixed-size
+    //   objects are retyped retype_count at a time. This matches what
+    //   the kernel does with retype_size_bits & retype_count.
+    let object_bytes = 1 << od.size_bits()?;
+    Some(align_up_bytes(slab.watermark, object_bytes) + object_bytes * od.retype_count())
+}
+
+// Returns the bytes left in |slab| after placing |od| or None if |od|
+// does not fit.
+pub fn slab_bytes_after_alloc(slab: &UntypedSlab, od: &ObjDesc) -> Option<usize> {
+    slab.free_bytes.checked_sub(new_slab_size(slab, od)?)
+}
+
+// Returns true if |od| does not fit in |slab| only because the free
+// index must first be aligned up to the size of a single object.
+pub fn alignment_induced_miss(slab: &UntypedSlab, od: &ObjDesc) -> bool {
+    match od.size_bytes() {
+        Some(size_bytes) => {
+            slab_bytes_after_alloc(slab, od).is_none()
+                && slab.watermark + size_bytes <= slab.free_bytes
+        }
+        None => false,
+    }
+}
+
+/// Returns the index of the slab in |slabs| that best fits |od| and the
+/// bytes left in that slab after the allocation.
+pub fn find_best_fit(slabs: &[UntypedSlab], od: &ObjDesc) -> Option<(usize, usize)> {
+    slabs
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, slab)| slab_bytes_after_alloc(slab, od).map(|after| (idx, after)))
+        .min_by_key(|&(idx, after)| (after, slabs[idx].cptr))
+}
+
+/// Returns the index of the first slab in |slabs| that fits |od| and the
+/// bytes left in that slab after the allocation.
+pub fn find_first_fit(slabs: &[UntypedSlab], od: &ObjDesc) -> Option<(usize, usize)> {
+    slabs
+        .iter()
+        .enumerate()
+        .find_map(|(idx, slab)| slab_bytes_after_alloc(slab, od).map(|after| (idx, after)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cantrip_os_common::sel4_sys::seL4_CPtr;
+    use cantrip_os_common::sel4_sys::seL4_UntypedDesc;
+    use cantrip_os_common::sel4_sys::seL4_UntypedObject;
+
+    // Returns a 4KiB slab at |cptr| with |watermark| bytes in use.
+    fn slab(cptr: seL4_CPtr, watermark: usize) -> UntypedSlab {
+        // NB: seL4_UntypedDesc has a private alignment field.
+        let mut ut: seL4_UntypedDesc = unsafe { core::mem::zeroed() };
+        ut.sizeBits = 12;
+        let mut slab = UntypedSlab::new(&ut, 1 << 12, cptr);
+        slab.watermark = watermark;
+        slab
+    }
+
+    // 1KiB object.
+    fn object() -> ObjDesc { ObjDesc::new(seL4_UntypedObject, 10, 0) }
+
+    #[test]
+    fn best_fit() {
+        let slabs = [slab(1, 0), slab(2, 0x800), slab(3, 0x400)];
+        assert_eq!(find_best_fit(&slabs, &object()), Some((1, 0x400)));
+        // An exact fit leaves nothing over.
+        let slabs = [slab(1, 0), slab(2, 0xc00)];
+        assert_eq!(find_best_fit(&slabs, &object()), Some((1, 0)));
+    }
+
+    #[test]
+    fn best_fit_aligns() {
+        // The free index is aligned up to the object size first.
+        let slabs = [slab(1, 0x100)];
+        assert_eq!(find_best_fit(&slabs, &object()), Some((0, 0x800)));
+        let slabs = [slab(1, 0xc01)];
+        assert_eq!(find_best_fit(&slabs, &object()), None);
+    }
+
+    #[test]
+    fn alignment_miss() {
+        // NB: a miss needs free_bytes that is not a multiple of the object
+        //   size; otherwise anything that fits unaligned also fits aligned.
+        let mut short = slab(1, 0x901);
+        short.free_bytes = 0xe00;
+        // 0x901 + 1KiB fits in 0xe00 but aligned up to 0xc00 it does not.
+        assert_eq!(new_slab_size(&short, &object()), Some(0x1000));
+        assert!(alignment_induced_miss(&short, &object()));
+        // Too full regardless of alignment.
+        short.watermark = 0xa01;
+        assert!(!alignment_induced_miss(&short, &object()));
+        assert!(!alignment_induced_miss(&slab(1, 0xc01), &object()));
+        // Fits.
+        short.watermark = 0x401;
+        assert!(!alignment_induced_miss(&short, &object()));
+        assert!(!alignment_induced_miss(&slab(1, 0x100), &object()));
+    }
+
+    #[test]
+    fn zero_count() {
+        use cantrip_os_common::sel4_sys::seL4_SmallPageObject;
+        // No objects take no space beyond aligning the free index.
+        let pages = ObjDesc::new(seL4_SmallPageObject, 0, 0);
+        assert_eq!(new_slab_size(&slab(1, 0), &pages), Some(0));
+        assert_eq!(new_slab_size(&slab(1, 0x100), &pages), Some(0x1000));
+    }
+
+    #[test]
+    fn best_fit_none() {
+        let slabs = [slab(1, 0x1000), slab(2, 0xf00)];
+        assert_eq!(find_best_fit(&slabs, &object()), None);
+        assert_eq!(find_best_fit(&[], &object()), None);
+    }
+
+    #[test]
+    fn best_fit_tie_break() {
+        // Slabs 7 & 3 leave the same bytes over; the smaller cptr wins
+        // regardless of the order of the slabs.
+        let slabs = [slab(7, 0x400), slab(5, 0), slab(3, 0x400)];
+        assert_eq!(find_best_fit(&slabs, &object()), Some((2, 0x800)));
+        let slabs = [slab(3, 0x400), slab(5, 0), slab(7, 0x400)];
+        assert_eq!(find_best_fit(&slabs, &object()), Some((0, 0x800)));
+        for _ in 0..10 {
+            assert_eq!(find_best_fit(&slabs, &object()), Some((0, 0x800)));
+        }
+    }
+
+    #[test]
+    fn first_fit() {
+        // The first slab with room wins even if another fits better.
+        let slabs = [slab(1, 0xc01), slab(2, 0), slab(3, 0xc00)];
+        assert_eq!(find_first_fit(&slabs, &object()), Some((1, 0xc00)));
+        assert_eq!(find_best_fit(&slabs, &object()), Some((2, 0)));
+
+        let slabs = [slab(1, 0x1000), slab(2, 0xf00)];
+        assert_eq!(find_first_fit(&slabs, &object()), None);
+        assert_eq!(find_first_fit(&[], &object()), None);
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Layout of untyped objects carved from an untyped slab.
+//!
+//! Each Untyped_Retype places the new object at the slab's free index
+//! rounded up to the object size and then advances the free index past
+//! the object. Replaying that here lets MemoryManager::carve check a
+//! request fits before touching the slab and lets device allocations
+//! pad a slab out to a requested physical address.
+
+extern crate alloc;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Returns the byte range within a slab of 2^|slab_bits| bytes that each
+/// untyped in |sizes| (log2 bytes) occupies when retyped in order starting
+/// at |free_index|. Returns None if they do not all fit.
+pub fn carve_layout(
+    slab_bits: usize,
+    free_index: usize,
+    sizes: &[usize],
+) -> Option<Vec<Range<usize>>> {
+    let slab_bytes = 1usize.checked_shl(slab_bits as u32)?;
+    let mut next = free_index;
+    let mut layout = Vec::with_capacity(sizes.len());
+    for &bits in sizes {
+        let size = 1usize.checked_shl(bits as u32)?;
+        let start = next.checked_add(size - 1)? & !(size - 1);
+        let end = start.checked_add(size)?;
+        if end > slab_bytes {
+            return None;
+        }
+        layout.push(start..end);
+        next = end;
+    }
+    Some(layout)
+}
+
+/// Returns the sizes (log2 bytes) of the untypeds that, retyped in order
+/// starting at |free_index|, advance the free index to exactly |offset|
+/// so the next object lands there. Each is the largest naturally aligned
+/// block that fits. Returns None if |offset| is behind |free_index| or
+/// cannot be reached with blocks of at least 2^|min_bits| bytes.
+pub fn pad_layout(free_index: usize, offset: usize, min_bits: usize) -> Option<Vec<usize>> {
+    if offset < free_index {
+        return None;
+    }
+    let mut next = free_index;
+    let mut sizes = Vec::new();
+    while next < offset {
+        let fit_bits = (usize::BITS - 1 - (offset - next).leading_zeros()) as usize;
+        let bits = if next == 0 {
+            fit_bits
+        } else {
+            fit_bits.min(next.trailing_zeros() as usize)
+        };
+        if bits < min_bits {
+            return None;
+        }
+        sizes.push(bits);
+        next += 1 << bits;
+    }
+    Some(sizes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the layout as (start, end) pairs for comparison.
+    fn layout(slab_bits: usize, free_index: usize, sizes: &[usize]) -> Option<Vec<(usize, usize)>> {
+        carve_layout(slab_bits, free_index, sizes)
+            .map(|layout| layout.iter().map(|r| (r.start, r.end)).collect())
+    }
+
+    #[test]
+    fn carve_sizes() {
+        let sizes = [12, 14, 12, 13];
+        let ranges = carve_layout(16, 0, &sizes).unwrap();
+        assert_eq!(ranges.len(), sizes.len());
+        for (range, bits) in ranges.iter().zip(sizes) {
+            assert_eq!(range.len(), 1 << bits);
+            assert_eq!(range.start % (1 << bits), 0);
+        }
+        // The 16K untyped is aligned up past the first 4K.
+        assert_eq!(
+            layout(16, 0, &sizes).unwrap(),
+            [
+                (0, 0x1000),
+                (0x4000, 0x8000),
+                (0x8000, 0x9000),
+                (0xa000, 0xc000)
+            ]
+        );
+    }
+
+    #[test]
+    fn carve_whole_slab() {
+        assert_eq!(layout(16, 0, &[16]), Some(vec![(0, 0x10000)]));
+        assert_eq!(layout(16, 0, &[15, 15]), Some(vec![(0, 0x8000), (0x8000, 0x10000)]));
+        assert_eq!(layout(16, 0, &[]), Some(vec![]));
+    }
+
+    #[test]
+    fn carve_from_used_slab() {
+        // Space already retyped from the slab is skipped.
+        assert_eq!(layout(16, 0x100, &[12]), Some(vec![(0x1000, 0x2000)]));
+        assert_eq!(layout(16, 0x100, &[15, 15]), None);
+    }
+
+    #[test]
+    fn carve_too_large() {
+        assert_eq!(layout(16, 0, &[17]), None);
+        assert_eq!(layout(16, 0, &[15, 15, 4]), None);
+        // Alignment padding counts against the slab.
+        assert_eq!(layout(16, 0, &[4, 15, 15]), None);
+        assert_eq!(layout(16, 0, &[usize::BITS as usize]), None);
+    }
+
+    #[test]
+    fn pad_to_offset() {
+        assert_eq!(pad_layout(0, 0x5000, 4), Some(vec![14, 12]));
+        assert_eq!(pad_layout(0x1000, 0x1000, 4), Some(vec![]));
+        assert_eq!(pad_layout(0x1000, 0x8000, 4), Some(vec![12, 13, 14]));
+        // Each block is placed where the previous one ended.
+        let sizes = pad_layout(0x3000, 0x1_1000, 4).unwrap();
+        assert_eq!(
+            layout(20, 0x3000, &sizes).unwrap().last().map(|r| r.1),
+            Some(0x1_1000)
+        );
+    }
+
+    #[test]
+    fn pad_unreachable() {
+        // Space already retyped cannot be reached.
+        assert_eq!(pad_layout(0x2000, 0x1000, 4), None);
+        // Nor can an offset finer than the smallest untyped.
+        assert_eq!(pad_layout(0x8, 0x1000, 4), None);
+        assert_eq!(pad_layout(0, 0x1008, 4), None);
+    }
+}
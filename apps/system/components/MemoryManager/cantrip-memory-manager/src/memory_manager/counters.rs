@@ -0,0 +1,94 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Allocation failure counters.
+//!
+//! The counters accumulate from boot but may be reset so a workload
+//! can measure the failures of a single run. Each slab also keeps
+//! SlabCounters so the failures can be attributed; a retype the kernel
+//! refuses for lack of space counts as one slab_too_small failure and
+//! one rejection of the slab.
+
+pub struct FailureCounters {
+    // Retype requests failed due to insufficient available memory.
+    slab_too_small: usize,
+
+    // Slabs passed over without a retype because the bookkeeping shows
+    // the object cannot fit.
+    slab_skipped: usize,
+
+    // Alloc requests failed due to lack of untyped memory (NB: may be
+    // due to fragmentation of untyped slabs).
+    out_of_memory: usize,
+}
+impl FailureCounters {
+    pub const fn new() -> Self {
+        Self {
+            slab_too_small: 0,
+            slab_skipped: 0,
+            out_of_memory: 0,
+        }
+    }
+
+    /// Records a retype that failed because a slab was too small.
+    pub fn slab_too_small_failure(&mut self) { self.slab_too_small += 1; }
+
+    /// Records a slab skipped without a retype because it is too small.
+    pub fn slab_skipped_failure(&mut self) { self.slab_skipped += 1; }
+
+    /// Records an alloc request that failed for lack of untyped memory.
+    pub fn out_of_memory_failure(&mut self) { self.out_of_memory += 1; }
+
+    pub fn slab_too_small(&self) -> usize { self.slab_too_small }
+    pub fn slab_skipped(&self) -> usize { self.slab_skipped }
+    pub fn out_of_memory(&self) -> usize { self.out_of_memory }
+
+    /// Zeroes all counters.
+    pub fn reset(&mut self) { *self = Self::new(); }
+}
+
+/// Per-slab allocation counters.
+#[derive(Debug, Default)]
+pub struct SlabCounters {
+    attempts: usize,   // Retypes tried on the slab
+    successes: usize,  // Retypes that succeeded
+    rejections: usize, // Retypes the kernel refused for lack of space
+}
+impl SlabCounters {
+    pub const fn new() -> Self {
+        Self {
+            attempts: 0,
+            successes: 0,
+            rejections: 0,
+        }
+    }
+
+    /// Records a retype of the slab; |ok| is true if it succeeded.
+    pub fn retype(&mut self, ok: bool) {
+        self.attempts += 1;
+        if ok {
+            self.successes += 1;
+        }
+    }
+
+    /// Records the kernel refusing a retype for lack of space.
+    pub fn rejection(&mut self) { self.rejections += 1; }
+
+    pub fn attempts(&self) -> usize { self.attempts }
+    pub fn successes(&self) -> usize { self.successes }
+    pub fn rejections(&self) -> usize { self.rejections }
+
+    /// Zeroes all counters.
+    pub fn reset(&mut self) { *self = Self::new(); }
+}
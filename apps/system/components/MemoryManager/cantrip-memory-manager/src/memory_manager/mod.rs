@@ -15,13 +15,21 @@
 //! Cantrip OS global memory management support
 
 extern crate alloc;
+use alloc::collections::BTreeMap;
+#[cfg(any(test, feature = "CONFIG_ALLOC_OWNERS"))]
+use alloc::string::String;
+use alloc::vec::Vec;
+use cantrip_memory_interface::AllocPolicy;
 use cantrip_memory_interface::MemoryLifetime;
 use cantrip_memory_interface::MemoryManagerError;
 use cantrip_memory_interface::MemoryManagerInterface;
 use cantrip_memory_interface::MemoryManagerStats;
 use cantrip_memory_interface::ObjDesc;
 use cantrip_memory_interface::ObjDescBundle;
+use cantrip_memory_interface::SlabStats;
+use cantrip_memory_interface::MAX_SLAB_STATS;
 use cantrip_os_common::camkes::{seL4_CPath, Camkes};
+use cantrip_os_common::diagnostics::{Diagnostics, DiagnosticsReport};
 use cantrip_os_common::sel4_sys;
 use cantrip_os_common::slot_allocator;
 use core::ops::Range;
@@ -32,6 +40,8 @@ use sel4_sys::seL4_CNode_Delete;
 use sel4_sys::seL4_CNode_Revoke;
 use sel4_sys::seL4_CPtr;
 use sel4_sys::seL4_Error;
+use sel4_sys::seL4_MaxUntypedBits;
+use sel4_sys::seL4_MinUntypedBits;
 use sel4_sys::seL4_Result;
 use sel4_sys::seL4_UntypedDesc;
 use sel4_sys::seL4_UntypedObject;
@@ -41,6 +51,19 @@ use sel4_sys::seL4_Word;
 
 use slot_allocator::CANTRIP_CSPACE_SLOTS;
 
+mod best_fit;
+use best_fit::{find_best_fit, find_first_fit, slab_bytes_after_alloc};
+mod carve;
+use carve::{carve_layout, pad_layout};
+mod counters;
+use counters::{FailureCounters, SlabCounters};
+#[cfg(any(test, feature = "CONFIG_ALLOC_OWNERS"))]
+mod owners;
+#[cfg(any(test, feature = "CONFIG_ALLOC_OWNERS"))]
+use owners::{tally, OwnerCounts};
+mod tainted;
+use tainted::TaintedSlabs;
+
 extern "Rust" {
     static SELF_CNODE: seL4_CPtr;
 }
@@ -60,9 +83,33 @@ fn untyped_describe(cptr: seL4_CPtr) -> seL4_Untyped_Describe {
 // one for anonymous memory and one for device-backed memory. The memory
 // manager is expected to be setup as a static global so these data
 // structures will land in .bss and only overflow to the heap if
-// initialized with more than this count.
-const UNTYPED_SLAB_CAPACITY: usize = 64; // # slabs kept inline
-const STATIC_UNTYPED_SLAB_CAPACITY: usize = 4; // # slabs kept inline
+// initialized with more than this count. Each inline entry costs
+// .bss whether used or not while spilling puts every slab on the heap
+// (and the inline space is then wasted) so the capacity should match
+// the platform's untyped count. The defaults may be changed with the
+// CONFIG_SMALL_UNTYPED_SLABS & CONFIG_LARGE_UNTYPED_SLABS features or
+// by instantiating MemoryManager with explicit capacities.
+#[cfg(all(feature = "CONFIG_SMALL_UNTYPED_SLABS", feature = "CONFIG_LARGE_UNTYPED_SLABS"))]
+compile_error!(
+    "features \"CONFIG_SMALL_UNTYPED_SLABS\" and \"CONFIG_LARGE_UNTYPED_SLABS\" \
+     are mutually exclusive"
+);
+#[cfg(feature = "CONFIG_SMALL_UNTYPED_SLABS")]
+pub const UNTYPED_SLAB_CAPACITY: usize = 16; // # slabs kept inline
+#[cfg(feature = "CONFIG_LARGE_UNTYPED_SLABS")]
+pub const UNTYPED_SLAB_CAPACITY: usize = 256; // # slabs kept inline
+#[cfg(not(any(
+    feature = "CONFIG_SMALL_UNTYPED_SLABS",
+    feature = "CONFIG_LARGE_UNTYPED_SLABS"
+)))]
+pub const UNTYPED_SLAB_CAPACITY: usize = 64; // # slabs kept inline
+pub const STATIC_UNTYPED_SLAB_CAPACITY: usize = 4; // # slabs kept inline
+
+// Best-fit scans every slab on each allocation. Beyond this many slabs
+// (e.g. after fragmented slabs are split at boot) allocations fall back
+// to first-fit which stops at the first slab with room. May be changed
+// with MemoryManager::set_best_fit_max_slabs.
+pub const BEST_FIT_MAX_SLABS: usize = 32;
 
 // The MemoryManager supports allocating & freeing seL4 objects that are
 // instantiated from UntypedMemory "slabs". Allocation causes untyped memory
@@ -70,40 +117,87 @@ const STATIC_UNTYPED_SLAB_CAPACITY: usize = 4; // # slabs kept inline
 // and updates the bookkeeping. Note that a free only releases the specified
 // cap; if there are dups or derived objects the memory will not be returned
 // to the untyped slab from which it was allocated and the bookkeeping done
-// here will be out of sync with the kernel.
+// here will be out of sync with the kernel. When the kernel reports the
+// last reference to an object is deleted the slab it came from is credited;
+// once a slab holds no objects its watermark is reset so the space is
+// reused.
 // TODO(sleffler): support device-backed memory objects
 #[derive(Debug)]
 struct UntypedSlab {
-    pub _size_bits: usize,      // NB: only used to sort
-    pub free_bytes: usize,      // Available space in slab
-    pub allocated_bytes: usize,  // How many bytes of memory are currently allocated
-    pub allocated_objects: usize,   // Number of objects in the slab currently
-    pub _base_paddr: seL4_Word, // Physical address of slab start
-    pub _last_paddr: seL4_Word, // Physical address of slab end
-    pub cptr: seL4_CPtr,        // seL4 untyped object
+    pub size_bits: usize,         // Log2 size of the slab
+    pub free_bytes: usize,        // Available space in slab
+    pub watermark: usize,         // Retype offset of the next object
+    pub allocated_bytes: usize,   // Bytes of objects currently allocated
+    pub allocated_objects: usize, // Objects currently allocated
+    pub base_paddr: seL4_Word,    // Physical address of slab start
+    pub last_paddr: seL4_Word,    // Physical address of slab end
+    pub cptr: seL4_CPtr,          // seL4 untyped object
+    pub counters: SlabCounters,   // Allocation attempts on this slab
+    #[cfg(any(test, feature = "CONFIG_ALLOC_OWNERS"))]
+    pub owners: OwnerCounts, // Objects allocated by owner tag
 }
 impl UntypedSlab {
     fn new(ut: &seL4_UntypedDesc, free_bytes: usize, cptr: seL4_CPtr) -> Self {
         UntypedSlab {
-            _size_bits: ut.size_bits(),
+            size_bits: ut.size_bits(),
             free_bytes,
+            watermark: 0,
             allocated_bytes: 0,
             allocated_objects: 0,
-            _base_paddr: ut.paddr,
-            _last_paddr: ut.paddr + l2tob(ut.size_bits()),
+            base_paddr: ut.paddr,
+            last_paddr: ut.paddr + l2tob(ut.size_bits()),
             cptr,
+            counters: SlabCounters::new(),
+            #[cfg(any(test, feature = "CONFIG_ALLOC_OWNERS"))]
+            owners: OwnerCounts::new(),
+        }
+    }
+
+    // Returns a slab of 2^|size_bits| bytes split from |ut| at |paddr|.
+    fn new_split(
+        ut: &seL4_UntypedDesc,
+        size_bits: usize,
+        paddr: seL4_Word,
+        cptr: seL4_CPtr,
+    ) -> Self {
+        UntypedSlab {
+            size_bits,
+            base_paddr: paddr,
+            last_paddr: paddr + l2tob(size_bits),
+            ..Self::new(ut, l2tob(size_bits), cptr)
+        }
+    }
+
+    fn stats(&self) -> SlabStats {
+        SlabStats {
+            size_bits: self.size_bits,
+            free_bytes: self.free_bytes,
+            allocated_bytes: self.allocated_bytes,
+            allocated_objects: self.allocated_objects,
+            largest_contiguous_free: self.free_bytes.saturating_sub(self.watermark),
         }
     }
 }
-pub struct MemoryManager {
-    untypeds: SmallVec<[UntypedSlab; UNTYPED_SLAB_CAPACITY]>,
-    static_untypeds: SmallVec<[UntypedSlab; STATIC_UNTYPED_SLAB_CAPACITY]>,
-    _device_untypeds: SmallVec<[UntypedSlab; UNTYPED_SLAB_CAPACITY]>,
+// |SLABS| and |STATIC_SLABS| are the number of slabs kept inline for
+// normal (and device) and static allocations respectively.
+pub struct MemoryManager<
+    const SLABS: usize = UNTYPED_SLAB_CAPACITY,
+    const STATIC_SLABS: usize = STATIC_UNTYPED_SLAB_CAPACITY,
+> {
+    untypeds: SmallVec<[UntypedSlab; SLABS]>,
+    static_untypeds: SmallVec<[UntypedSlab; STATIC_SLABS]>,
+    device_untypeds: SmallVec<[UntypedSlab; SLABS]>,
     cur_untyped: usize,
     cur_static_untyped: usize,
     _cur_device_untyped: usize,
-    //_map_cptr_untypeds: HashMap<seL4_CPtr, usize>, // Map from CNode cptr index to untypeds SmallVec index
-    //_map_cptr_untypeds: SmallVec<[usize; UNTYPED_SLAB_CAPACITY]>, // Map from CNode cptr index to untypeds SmallVec index
+    // Slabs used by the rootserver; revoked on demand.
+    tainted: TaintedSlabs<(seL4_UntypedDesc, seL4_CPtr)>,
+    // Map from slab cptr to index in |untypeds|; rebuilt by index_slabs
+    // whenever |untypeds| is reordered.
+    slab_index: BTreeMap<seL4_CPtr, usize>,
+    // Untypeds retyped from device slabs to reach a requested paddr as
+    // (slab, padding) pairs; deleted when the slab's objects are freed.
+    device_padding: Vec<(seL4_CPtr, seL4_CPtr)>,
 
     total_bytes: usize,     // Total available space
     allocated_bytes: usize, // Amount of space currently allocated
@@ -113,12 +207,14 @@ pub struct MemoryManager {
     allocated_objs: usize, // # seL4 objects currently allocated
     requested_objs: usize, // # seL4 objects allocated over all time
 
-    // Retype requests failed due to insufficient available memory.
-    untyped_slab_too_small: usize,
+    // Allocation failures; resettable to bracket a workload.
+    failures: FailureCounters,
+
+    // How slabs are chosen for allocations.
+    alloc_policy: AllocPolicy,
 
-    // Alloc requests failed due to lack of untyped memory (NB: may be
-    // due to fragmentation of untyped slabs).
-    out_of_memory: usize,
+    // Slab count above which first-fit is used instead of best-fit.
+    best_fit_max_slabs: usize,
 }
 
 fn _howmany(value: usize, unit: usize) -> usize { value + (unit - 1) / unit }
@@ -127,21 +223,62 @@ fn _round_up(value: usize, align: usize) -> usize { _howmany(value, align) * ali
 // Log2 bits to bytes.
 fn l2tob(size_bits: usize) -> usize { 1 << size_bits }
 
-impl MemoryManager {
+// Returns the size of the slab left at the end when splitting a
+// partially used untyped of |slab_bits| with |remaining_bytes| free:
+// 1/2 the untyped or, when less than that remains, the largest power
+// of 2 that fits (it ends on the untyped's end so is naturally aligned).
+fn split_size_bits(slab_bits: usize, remaining_bytes: usize) -> usize {
+    let fits_bits = (usize::BITS - 1 - remaining_bytes.leading_zeros()) as usize;
+    fits_bits.min(slab_bits - 1)
+}
+
+impl<const SLABS: usize, const STATIC_SLABS: usize> MemoryManager<SLABS, STATIC_SLABS> {
     // Creates a new MemoryManager instance. The allocator is seeded
     // from the untyped memory descriptors.
     pub fn new(slots: Range<seL4_CPtr>, untypeds: &[seL4_UntypedDesc]) -> Self {
         assert!(!untypeds.is_empty());
         assert_eq!(slots.end - slots.start, untypeds.len());
-        let mut m = MemoryManager {
+        let mut m = Self::empty();
+        for (ut_index, ut) in untypeds.iter().enumerate() {
+            m.add_untyped(ut, slots.start + ut_index);
+        }
+        // NB: one slab is taken below to seed the static pool; make sure
+        //   another remains for normal allocations.
+        while m.untypeds.len() < 2 && m.reclaim_tainted() {}
+        // Sort non-device slabs by descending amount of free space.
+        m.untypeds
+            .sort_unstable_by(|a, b| b.free_bytes.cmp(&a.free_bytes));
+        m.static_untypeds
+            .sort_unstable_by(|a, b| b.free_bytes.cmp(&a.free_bytes));
+        if m.static_untypeds.is_empty() {
+            // Seed the pool for static object requests with the smallest
+            // "normal" slab.
+            m.static_untypeds.push(m.untypeds.pop().unwrap());
+        }
+        m.index_slabs();
+        if m.spilled() {
+            warn!(
+                "{} slabs, {} device slabs exceed inline capacity {}; using heap",
+                m.untypeds.len(),
+                m.device_untypeds.len(),
+                SLABS
+            );
+        }
+        m
+    }
+
+    // Returns an instance with no slabs.
+    fn empty() -> Self {
+        MemoryManager {
             untypeds: SmallVec::new(),
             static_untypeds: SmallVec::new(),
-            _device_untypeds: SmallVec::new(),
+            device_untypeds: SmallVec::new(),
             cur_untyped: 0,
             cur_static_untyped: 0,
             _cur_device_untyped: 0,
-            //_map_cptr_untypeds: HashMap::<seL4_CPtr, usize>::new(), // TODO: @Willmish possibly initialise with capacity, min length of bootinfo untypeds
-            //_map_cptr_untypeds: SmallVec::new(),
+            tainted: TaintedSlabs::new(),
+            slab_index: BTreeMap::new(),
+            device_padding: Vec::new(),
 
             total_bytes: 0,
             allocated_bytes: 0,
@@ -151,109 +288,225 @@ impl MemoryManager {
             allocated_objs: 0,
             requested_objs: 0,
 
-            untyped_slab_too_small: 0,
-            out_of_memory: 0,
-        };
-        for (ut_index, ut) in untypeds.iter().enumerate() {
-            let ut_cptr = slots.start + ut_index;
-            #[cfg(feature = "CONFIG_NOISY_UNTYPEDS")]
-            log::info!("slot {} {:?}", ut_cptr, ut);
-            let slab_size = l2tob(ut.size_bits());
-            if ut.is_device() {
-                m._device_untypeds
-                    .push(UntypedSlab::new(ut, slab_size, ut_cptr));
+            failures: FailureCounters::new(),
+
+            alloc_policy: AllocPolicy::default(),
+            best_fit_max_slabs: BEST_FIT_MAX_SLABS,
+        }
+    }
+
+    // Adds the untyped memory |ut| at |ut_cptr| to the pool it belongs in.
+    fn add_untyped(&mut self, ut: &seL4_UntypedDesc, ut_cptr: seL4_CPtr) {
+        #[cfg(feature = "CONFIG_NOISY_UNTYPEDS")]
+        log::info!("slot {} {:?}", ut_cptr, ut);
+        let slab_size = l2tob(ut.size_bits());
+        if ut.is_device() {
+            self.device_untypeds
+                .push(UntypedSlab::new(ut, slab_size, ut_cptr));
+        } else if ut.is_tainted() {
+            // Slabs marked "tainted" were used by the rootserver
+            // which has terminated. The resources are reclaimed
+            // with a revoke when an allocation needs the space.
+            self.tainted.push((*ut, ut_cptr));
+        } else {
+            self.add_slab(ut, ut_cptr);
+        }
+    }
+
+    // Returns true if any slab pool overflowed its inline capacity.
+    fn spilled(&self) -> bool {
+        self.untypeds.spilled() || self.static_untypeds.spilled() || self.device_untypeds.spilled()
+    }
+
+    // Adds the untyped memory in |ut| at |ut_cptr| to the pool of slabs
+    // used for normal allocations.
+    fn add_slab(&mut self, ut: &seL4_UntypedDesc, ut_cptr: seL4_CPtr) {
+        let slab_size = l2tob(ut.size_bits());
+        // NB: must get the current state of the slab as the value
+        //   supplied by the rootserver (in |untypeds|) will reflect
+        //   resources available before any revoke.
+        let info = untyped_describe(ut_cptr);
+        assert_eq!(info.sizeBits, ut.size_bits());
+
+        // We only have the remainder available for allocations.
+        // Beware that slabs with existing allocations (for the
+        // services constructed by the rootserver) are not generally
+        // useful because we cannot recycle memory once retype'd;
+        // those we carefully split to reclaim avaiilable space.
+        if info.remainingBytes > 0 {
+            if info.remainingBytes == slab_size {
+                self.untypeds
+                    .push(UntypedSlab::new(ut, info.remainingBytes, ut_cptr));
             } else {
-                if ut.is_tainted() {
-                    // Slabs marked "tainted" were used by the rootserver
-                    // which has terminated. Reclaim the resources with a
-                    // revoke.
-                    revoke_cap(slots.start + ut_index).expect("revoke untyped");
-                }
-                // NB: must get the current state of the slab as the value
-                //   supplied by the rootserver (in |untypeds|) will reflect
-                //   resources available before the above revoke.
-                let info = untyped_describe(ut_cptr);
-                assert_eq!(info.sizeBits, ut.size_bits());
-
-                // We only have the remainder available for allocations.
-                // Beware that slabs with existing allocations (for the
-                // services constructed by the rootserver) are not generally
-                // useful because we cannot recycle memory once retype'd;
-                // those we carefully split to reclaim avaiilable space.
-                if info.remainingBytes > 0 {
-                    if info.remainingBytes == slab_size {
-                        m.untypeds
-                            .push(UntypedSlab::new(ut, info.remainingBytes, ut_cptr));
-                    } else {
-                        // Split the unallocated space into smaller slabs that
-                        // are entirely unused. This is a bit tricky as the
-                        // kernel allocator does implicit alignment to the slab
-                        // size. We compensate for this by logically splitting
-                        // the slab in 1/2 and then searching for the best slab
-                        // in the smaller region. The goal here is to reclaim
-                        // as much space as possible using the minimum number
-                        // of slabs (to reduce overhead searching slabs when
-                        // doing allocations).
-                        // TODO(sleffler): move this to the rootserver
-                        let size_bits = info.sizeBits - 1; // 1/2 the slab size
-                                                           // Allocate alignment slabs.
-                        while let Some(align_bits) = Self::find_best_slab(ut_cptr, size_bits) {
-                            match Self::new_untyped(ut_cptr, align_bits) {
-                                Ok(free_untyped) => {
-                                    m.untypeds.push(UntypedSlab::new(
-                                        // TODO: originally was ut, @Willmish need to update to reflect the size of the new Untyped!!
-                                        // See: output of mdebug
-                                        ut, /*XXX*/
-                                        l2tob(align_bits),
-                                        free_untyped,
-                                    ));
-                                }
-                                Err(e) => {
-                                    error!("Retype align {align_bits}: {e:?}")
-                                }
-                            }
+                // Split the unallocated space into smaller slabs that
+                // are entirely unused. This is a bit tricky as the
+                // kernel allocator does implicit alignment to the slab
+                // size. We compensate for this by logically splitting
+                // the slab in 1/2 and then searching for the best slab
+                // in the smaller region. The goal here is to reclaim
+                // as much space as possible using the minimum number
+                // of slabs (to reduce overhead searching slabs when
+                // doing allocations).
+                // If the slab is dirty beyond its halfway point the
+                // "1/2" slab is the largest that fits in what remains.
+                // TODO(sleffler): move this to the rootserver
+                let size_bits = split_size_bits(info.sizeBits, info.remainingBytes);
+                // Allocate alignment slabs.
+                while let Some(align_bits) = Self::find_best_slab(ut_cptr, size_bits) {
+                    let paddr = Self::next_split_paddr(ut, ut_cptr, align_bits);
+                    match Self::new_untyped(ut_cptr, align_bits) {
+                        Ok(free_untyped) => {
+                            self.untypeds.push(UntypedSlab::new_split(
+                                ut,
+                                align_bits,
+                                paddr,
+                                free_untyped,
+                            ));
                         }
-                        // And finally allocate the 1/2-size slab.
-                        match Self::new_untyped(ut_cptr, size_bits) {
-                            Ok(free_untyped) => {
-                                m.untypeds.push(UntypedSlab::new(
-                                    ut, /*XXX*/
-                                    l2tob(size_bits),
-                                    free_untyped,
-                                ));
-                            }
-                            Err(e) => {
-                                error!("Retype size {size_bits}: {e:?}")
-                            }
+                        Err(e) => {
+                            // NB: the slab is unchanged so retrying would spin
+                            error!("Retype align {align_bits}: {e:?}");
+                            break;
                         }
                     }
-                    // XXX assumes all space in the slab is reclaimed
-                    m.total_bytes += info.remainingBytes;
-                } else {
-                    trace!("Discard slot {ut_cptr}, size {}, no usable space", ut.size_bits());
                 }
+                // And finally allocate the 1/2-size slab.
+                let paddr = Self::next_split_paddr(ut, ut_cptr, size_bits);
+                match Self::new_untyped(ut_cptr, size_bits) {
+                    Ok(free_untyped) => {
+                        self.untypeds.push(UntypedSlab::new_split(
+                            ut,
+                            size_bits,
+                            paddr,
+                            free_untyped,
+                        ));
+                    }
+                    Err(e) => {
+                        error!("Retype size {size_bits}: {e:?}")
+                    }
+                }
+            }
+            // XXX assumes all space in the slab is reclaimed
+            self.total_bytes += info.remainingBytes;
+        } else {
+            trace!("Discard slot {ut_cptr}, size {}, no usable space", ut.size_bits());
+        }
 
-                // Use overhead to track memory allocated out of our control.
-                m.overhead_bytes += slab_size - info.remainingBytes;
+        // Use overhead to track memory allocated out of our control.
+        self.overhead_bytes += slab_size - info.remainingBytes;
+    }
+
+    // Revokes a tainted slab and adds its space to the pool. Returns
+    // false if no tainted slab could be reclaimed.
+    fn reclaim_tainted(&mut self) -> bool {
+        let reclaimed = self.tainted.reclaim(|(ut, ut_cptr)| {
+            revoke_cap(*ut_cptr).map_err(|e| {
+                error!("Revoke tainted slot {ut_cptr}, size {}: {e:?}", ut.size_bits());
+            })
+        });
+        match reclaimed {
+            Some((ut, ut_cptr)) => {
+                trace!("Reclaimed tainted slot {ut_cptr}, size {}", ut.size_bits());
+                self.add_slab(&ut, ut_cptr);
+                self.untypeds
+                    .sort_unstable_by(|a, b| b.free_bytes.cmp(&a.free_bytes));
+                self.index_slabs();
+                true
             }
+            None => false,
         }
-        // Sort non-device slabs by descending amount of free space.
-        m.untypeds
-            .sort_unstable_by(|a, b| b.free_bytes.cmp(&a.free_bytes));
-        m.static_untypeds
-            .sort_unstable_by(|a, b| b.free_bytes.cmp(&a.free_bytes));
-        if m.static_untypeds.is_empty() {
-            // Seed the pool for static object requests with the smallest
-            // "normal" slab.
-            m.static_untypeds.push(m.untypeds.pop().unwrap());
+    }
+
+    // Returns the physical ranges covered by runs of 2 or more empty
+    // slabs that are physically contiguous (e.g. the pieces of a split
+    // untyped). These are reported but not merged: the kernel reclaims
+    // an untyped's space only once it has no children and the parent of
+    // split slabs still holds the objects the rootserver created (a
+    // revoke would destroy them).
+    // TODO(sleffler): coalescing is still open. The split pieces are
+    //   already the largest naturally aligned untypeds the free space
+    //   holds so merging them cannot yield a bigger single object; a
+    //   multi-object request (e.g. 128 4K frames) could instead be
+    //   spread across a run. Until then large requests that span the
+    //   pieces fail and the runs are only logged.
+    fn coalescible(&self) -> Vec<Range<seL4_Word>> {
+        let mut empty: Vec<Range<seL4_Word>> = self
+            .untypeds
+            .iter()
+            .filter(|ut| ut.allocated_objects == 0 && ut.watermark == 0)
+            .map(|ut| ut.base_paddr..ut.last_paddr)
+            .collect();
+        empty.sort_unstable_by_key(|r| r.start);
+        let mut runs = Vec::new();
+        let mut iter = empty.into_iter();
+        if let Some(mut run) = iter.next() {
+            let mut count = 1;
+            for r in iter {
+                if r.start == run.end {
+                    run.end = r.end;
+                    count += 1;
+                } else {
+                    if count > 1 {
+                        runs.push(run);
+                    }
+                    run = r;
+                    count = 1;
+                }
+            }
+            if count > 1 {
+                runs.push(run);
+            }
         }
-        // Fill in the hashmap, mapping from CNode (cptr) index to an index into m.untypeds
-        //for i in 0..m.untypeds.len() {
-        //    // TODO: @Willmish ensure whether it is actually safe to do this: cptr should be unique, but maybe corner case?
-        //    m._map_cptr_untypeds.insert(m.untypeds[i].cptr, i);
-        //}
-        //info!("Map cptr untypeds: {:?}", m._map_cptr_untypeds);
-        m
+        runs
+    }
+
+    // Returns true if some slab has room for all of |bundle|. This is
+    // conservative as objects in a bundle may be placed in different slabs.
+    fn has_space_for(&self, bundle: &ObjDescBundle) -> bool {
+        let size_bytes = bundle.size_bytes();
+        self.untypeds
+            .iter()
+            .any(|ut| ut.free_bytes.saturating_sub(ut.watermark) >= size_bytes)
+    }
+
+    // Splits the untyped at |src_cptr| into new untypeds with the sizes
+    // (log2 bytes) in |sizes|, retyped in order. Returns the new untypeds'
+    // cptrs in the same order. Fails without modifying |src_cptr| if they
+    // do not all fit in the space remaining. |src_cptr| must not be one of
+    // the slabs used for allocations as its bookkeeping is not updated.
+    pub fn carve(
+        &self,
+        src_cptr: seL4_CPtr,
+        sizes: &[usize],
+    ) -> Result<Vec<seL4_CPtr>, MemoryManagerError> {
+        if sizes
+            .iter()
+            .any(|&bits| !(seL4_MinUntypedBits..=seL4_MaxUntypedBits).contains(&bits))
+        {
+            return Err(MemoryManagerError::ObjTypeInvalid);
+        }
+        let info = untyped_describe(src_cptr);
+        let free_index = l2tob(info.sizeBits) - info.remainingBytes;
+        if carve_layout(info.sizeBits, free_index, sizes).is_none() {
+            debug!("Carve {sizes:?} from slot {src_cptr}: does not fit");
+            return Err(MemoryManagerError::AllocFailed);
+        }
+        let mut carved = Vec::with_capacity(sizes.len());
+        for &bits in sizes {
+            match Self::new_untyped(src_cptr, bits) {
+                Ok(free_untyped) => carved.push(free_untyped),
+                Err(e) => {
+                    // Should not happen; the layout was checked above.
+                    error!("Carve {bits} from slot {src_cptr}: {e:?}");
+                    for cptr in carved {
+                        let _ = delete_path(&Camkes::top_level_path(cptr));
+                        unsafe { CANTRIP_CSPACE_SLOTS.free(cptr, 1) };
+                    }
+                    return Err(MemoryManagerError::AllocFailed);
+                }
+            }
+        }
+        Ok(carved)
     }
 
     // Total available space.
@@ -272,8 +525,14 @@ impl MemoryManager {
     // Total objects allocated over time
     pub fn total_requested_objs(&self) -> usize { self.requested_objs }
 
-    pub fn untyped_slab_too_small(&self) -> usize { self.untyped_slab_too_small }
-    pub fn out_of_memory(&self) -> usize { self.out_of_memory }
+    pub fn untyped_slab_too_small(&self) -> usize { self.failures.slab_too_small() }
+    pub fn untyped_slab_skipped(&self) -> usize { self.failures.slab_skipped() }
+
+    // Sets the slab count above which allocations use first-fit.
+    pub fn set_best_fit_max_slabs(&mut self, max_slabs: usize) {
+        self.best_fit_max_slabs = max_slabs;
+    }
+    pub fn out_of_memory(&self) -> usize { self.failures.out_of_memory() }
 
     // Align |base_value| according to |alignment|. This mimics the
     // alignUp logic the kernel uses for an Untyped_Retype operation.
@@ -283,18 +542,41 @@ impl MemoryManager {
         (base_value + (bit(alignment) - 1)) & !mask(alignment)
     }
 
+    // Returns the physical address of the next untyped of 2^|size_bits|
+    // bytes retyped from |ut| at |ut_cptr|.
+    fn next_split_paddr(ut: &seL4_UntypedDesc, ut_cptr: seL4_CPtr, size_bits: usize) -> seL4_Word {
+        let info = untyped_describe(ut_cptr);
+        Self::split_paddr(ut, info.remainingBytes, size_bits)
+    }
+
+    // Returns the physical address where the kernel places an untyped of
+    // 2^|size_bits| bytes retyped from |ut| with |remaining_bytes| free.
+    fn split_paddr(ut: &seL4_UntypedDesc, remaining_bytes: usize, size_bits: usize) -> seL4_Word {
+        let free_index = l2tob(ut.size_bits()) - remaining_bytes;
+        ut.paddr + Self::align_up(free_index, size_bits)
+    }
+
     // Finds the largest slab with minimum mis-alignment (if any).
     fn find_best_slab(ut_cptr: seL4_CPtr, size_bits: usize) -> Option<usize> {
         // NB: must use the current state to track each slab split
         let info = untyped_describe(ut_cptr);
-        let alignment = info.remainingBytes - l2tob(size_bits);
+        Self::best_slab_bits(info.sizeBits, info.remainingBytes, size_bits)
+    }
+
+    // Returns the size of the next alignment slab to carve from an
+    // untyped of |slab_bits| with |remaining_bytes| free such that a
+    // slab of |size_bits| is left at the end. None when the space before
+    // that slab is used up or there is no room for it at all.
+    fn best_slab_bits(slab_bits: usize, remaining_bytes: usize, size_bits: usize) -> Option<usize> {
+        // NB: a slab dirty past l2tob(size_bits) would otherwise wrap here
+        let alignment = remaining_bytes.checked_sub(l2tob(size_bits))?;
         let mut min_mis_alignment = alignment;
         let mut best_bits = None;
         // XXX could go down to 4 (seL4_MinUntypedBits).
         for bits in (8..size_bits).rev() {
             let slab_size = l2tob(bits);
             if slab_size <= alignment {
-                let free_index = l2tob(info.sizeBits) - info.remainingBytes;
+                let free_index = l2tob(slab_bits) - remaining_bytes;
                 let aligned_free_index = Self::align_up(free_index, bits);
                 let mis_alignment = aligned_free_index - free_index;
                 if mis_alignment == 0 {
@@ -346,7 +628,13 @@ impl MemoryManager {
         }
     }
 
-    fn delete_caps(&mut self, root: seL4_CPtr, depth: u8, od: &ObjDesc) -> seL4_Result {
+    fn delete_caps(
+        &mut self,
+        root: seL4_CPtr,
+        depth: u8,
+        od: &ObjDesc,
+        owner: Option<&str>,
+    ) -> seL4_Result {
         for offset in 0..od.retype_count() {
             let path = (root, od.cptr + offset, depth as usize);
             let result: seL4_CNode_Delete = delete_path(&path);
@@ -356,25 +644,124 @@ impl MemoryManager {
 
             // Only update bookkeeping if this was the last reference and untypedSlabIndex is valid
             if result.isLastReference != 0 && result.untypedSlabIndex != 0 {
-                // TODO: @Willmish - to be replaced by a hashmap or a SmallVec with cptr -> self.untypeds mapping!
-                for i in 0..self.untypeds.len() {
-                    if self.untypeds[i].cptr == result.untypedSlabIndex {
-                        // NOTE: decrementing by one at each CNode_Delete call, since objects could potentially span multiple CNodes.
-                        // This should never overflow, if object count is tracked properly
-                        self.untypeds[i].allocated_objects -= 1;
-                        // check if object count dropped to 0
-                        if self.untypeds[i].allocated_objects == 0 {
-                            // reset the slab!
-                            self.untypeds[i].allocated_bytes = 0;
-                        }
-                        break;
-                    }
-                }
+                let size_bytes = od.new_at(offset).size_bytes().unwrap();
+                self.release_from_slab(result.untypedSlabIndex, size_bytes, owner);
             }
         }
         Ok(())
     }
 
+    // Rebuilds the map from slab cptr to index in |untypeds|.
+    fn index_slabs(&mut self) {
+        self.slab_index = self
+            .untypeds
+            .iter()
+            .enumerate()
+            .map(|(ut_index, ut)| (ut.cptr, ut_index))
+            .collect();
+    }
+
+    // Returns the usage of the first MAX_SLAB_STATS slabs.
+    fn slab_stats(&self) -> Vec<SlabStats> {
+        self.untypeds
+            .iter()
+            .take(MAX_SLAB_STATS)
+            .map(UntypedSlab::stats)
+            .collect()
+    }
+
+    // Returns the bytes & objects allocated summed over all slabs.
+    fn slab_totals(&self) -> (usize, usize) {
+        self.untypeds.iter().fold((0, 0), |(bytes, objs), ut| {
+            (bytes + ut.allocated_bytes, objs + ut.allocated_objects)
+        })
+    }
+
+    // Updates the bookkeeping of the slab at |slab_cptr| for an object
+    // of |size_bytes| and |owner| that was deleted. When the last object
+    // is released the slab's watermark is reset; the kernel does the same
+    // on the next retype.
+    fn release_from_slab(&mut self, slab_cptr: seL4_CPtr, size_bytes: usize, owner: Option<&str>) {
+        let ut_index = match self.slab_index.get(&slab_cptr) {
+            Some(&ut_index) => ut_index,
+            None => {
+                self.release_from_device_slab(slab_cptr, size_bytes);
+                return;
+            }
+        };
+        let ut = &mut self.untypeds[ut_index];
+        // NB: called for each cap deleted; objects are released one at a time
+        if ut.allocated_objects == 0 || ut.allocated_bytes < size_bytes {
+            warn!(
+                "Underflow on free of {size_bytes} bytes from slab {slab_cptr}: {} bytes, {} objects",
+                ut.allocated_bytes, ut.allocated_objects
+            );
+            ut.allocated_objects = ut.allocated_objects.saturating_sub(1);
+            ut.allocated_bytes = ut.allocated_bytes.saturating_sub(size_bytes);
+        } else {
+            ut.allocated_objects -= 1;
+            ut.allocated_bytes -= size_bytes;
+        }
+        #[cfg(any(test, feature = "CONFIG_ALLOC_OWNERS"))]
+        if let Some(owner) = owner {
+            if !ut.owners.remove(owner, 1) {
+                debug!("Free by {owner} of object it does not own in slab {slab_cptr}");
+            }
+        }
+        if ut.allocated_objects == 0 {
+            ut.watermark = 0;
+            ut.allocated_bytes = 0;
+            #[cfg(any(test, feature = "CONFIG_ALLOC_OWNERS"))]
+            ut.owners.clear();
+        }
+        #[cfg(not(any(test, feature = "CONFIG_ALLOC_OWNERS")))]
+        let _ = owner;
+    }
+
+    // Like release_from_slab but for a device slab. When the last object
+    // is released the padding is deleted so the space can be reused.
+    fn release_from_device_slab(&mut self, slab_cptr: seL4_CPtr, size_bytes: usize) {
+        let ut = match self
+            .device_untypeds
+            .iter_mut()
+            .find(|ut| ut.cptr == slab_cptr)
+        {
+            Some(ut) => ut,
+            None => {
+                // NB: static slabs are not tracked
+                trace!("Free from untracked slab {slab_cptr}");
+                return;
+            }
+        };
+        ut.allocated_objects = ut.allocated_objects.saturating_sub(1);
+        ut.allocated_bytes = ut.allocated_bytes.saturating_sub(size_bytes);
+        if ut.allocated_objects == 0 {
+            ut.allocated_bytes = 0;
+            self.device_padding.retain(|&(slab, pad_cptr)| {
+                if slab != slab_cptr {
+                    return true;
+                }
+                let _ = delete_path(&Camkes::top_level_path(pad_cptr));
+                unsafe { CANTRIP_CSPACE_SLOTS.free(pad_cptr, 1) };
+                false
+            });
+        }
+    }
+
+    // Records |count| objects of |owner| allocated from slab |ut_index|.
+    fn record_owner(&mut self, ut_index: usize, owner: Option<&str>, count: usize) {
+        #[cfg(any(test, feature = "CONFIG_ALLOC_OWNERS"))]
+        if let Some(owner) = owner {
+            self.untypeds[ut_index].owners.add(owner, count);
+        }
+        #[cfg(not(any(test, feature = "CONFIG_ALLOC_OWNERS")))]
+        let _ = (ut_index, owner, count);
+    }
+
+    // Returns the objects held by each owner tag, sorted by owner.
+    #[cfg(any(test, feature = "CONFIG_ALLOC_OWNERS"))]
+    pub fn owners(&self) -> Vec<(String, usize)> { tally(self.untypeds.iter().map(|ut| &ut.owners)) }
+
     fn alloc_static(&mut self, bundle: &ObjDescBundle) -> Result<(), MemoryManagerError> {
         let first_ut = self.cur_static_untyped;
         let mut ut_index = first_ut;
@@ -403,85 +790,234 @@ impl MemoryManager {
         Ok(())
     }
 
-    fn alloc_best_fit(
-        &mut self,
-        bundle: &ObjDescBundle,
-        lifetime: MemoryLifetime,
-    ) -> Result<(), MemoryManagerError> {
-        //let first_ut = self.cur_untyped;
-        //let mut ut_index = first_ut;
-
-        let mut allocated_bytes: usize = 0;
-        let mut allocated_objs: usize = 0;
+    // Returns the index of the device slab holding all of |range|.
+    fn find_device_slab(&self, range: &Range<seL4_Word>) -> Option<usize> {
+        self.device_untypeds
+            .iter()
+            .position(|ut| ut.base_paddr <= range.start && range.end <= ut.last_paddr)
+    }
 
-        //fn align_up(base_value: seL4_Word, alignment: seL4_Word) -> seL4_Word {
-        //    fn bit(x: seL4_Word) -> seL4_Word { 1 << x }
-        //    fn mask(x: seL4_Word) -> seL4_Word { bit(x) - 1 }
-        //    (base_value + (bit(alignment) - 1)) & !mask(alignment)
-        //}
-        fn get_single_object_size(od: &ObjDesc) -> Option<usize> {
-            // Gets the size for a single object of a particular type.
-            // This is necessary to get the per-object alignment right
-            od.size_bytes().map(|x| x / od.retype_count())
+    // Returns the free index of device slab |ut_index|. The kernel resets
+    // an untyped's free index on the next retype once it has no children
+    // so Untyped_Describe is stale after we empty a slab, unless objects
+    // we did not create remain; those are noted on first use in free_bytes.
+    fn device_free_index(&mut self, ut_index: usize) -> usize {
+        let slab_cptr = self.device_untypeds[ut_index].cptr;
+        let padded = self
+            .device_padding
+            .iter()
+            .any(|&(slab, _)| slab == slab_cptr);
+        let ut = &mut self.device_untypeds[ut_index];
+        let slab_size = l2tob(ut.size_bits);
+        if ut.counters.attempts() == 0 {
+            // No children of ours yet so the kernel's view is current.
+            ut.free_bytes = untyped_describe(slab_cptr).remainingBytes;
         }
-        fn align_up_bytes(base_value: seL4_Word, alignment_bytes: seL4_Word) -> seL4_Word {
-            // Get the object alignemnt based on its size in bytes
-            (base_value + alignment_bytes - 1) & !(alignment_bytes - 1)
+        if ut.allocated_objects == 0 && !padded && ut.free_bytes == slab_size {
+            0
+        } else {
+            slab_size - untyped_describe(slab_cptr).remainingBytes
         }
+    }
 
+    // Allocates the objects in |bundle| from device memory at the paddr
+    // each specifies. The kernel places an object at the slab's free index
+    // (aligned to the object size) so untypeds are first retyped to pad
+    // the free index out to the paddr; these are held until the slab's
+    // objects are all freed.
+    fn alloc_device(&mut self, bundle: &ObjDescBundle) -> Result<(), MemoryManagerError> {
         for od in &bundle.objs {
-            // Find slab which best fits the object - naively iterate through all
-            // First slab is best slab at the start
-            let mut best_slab_idx: usize = 0;
-            // compute first slabs left over bytes
-            let mut free_index = self.untypeds[best_slab_idx].allocated_bytes;
-            let mut new_slab_size: usize = align_up_bytes(free_index, get_single_object_size(od).unwrap()) + od.size_bytes().unwrap();
-            //let mut new_slab_size: usize = Self::align_up(free_index, od.size_bits().unwrap()) + od.size_bytes().unwrap();
-            let mut slab_bytes_after_alloc: usize = self.untypeds[best_slab_idx].free_bytes - new_slab_size;
-            // best_slabs left over bytes
-            let mut best_slab_bytes_after_alloc: usize = slab_bytes_after_alloc;
-            // Identify slab which will have the smallest number of bytes left over after alloc
-            for slab_idx in 1..self.untypeds.len() {
-                free_index = self.untypeds[slab_idx].allocated_bytes;
-                //new_slab_size = Self::align_up(free_index, od.size_bits().unwrap()) + od.size_bytes().unwrap();
-                new_slab_size = align_up_bytes(free_index, get_single_object_size(od).unwrap()) + od.size_bytes().unwrap();
-                // Only consider if enough space
-                if new_slab_size > self.untypeds[slab_idx].free_bytes {
-                    continue;
+            let (paddr, size_bytes) = match (od.paddr(), od.size_bytes()) {
+                (Some(paddr), Some(size_bytes)) => (paddr, size_bytes),
+                _ => {
+                    error!("Device alloc without paddr: {:?}", od);
+                    return Err(MemoryManagerError::ObjTypeInvalid);
+                }
+            };
+            let ut_index = match self.find_device_slab(&(paddr..paddr + size_bytes)) {
+                Some(ut_index) => ut_index,
+                None => {
+                    debug!("No device memory at {:#x}..{:#x}", paddr, paddr + size_bytes);
+                    return Err(MemoryManagerError::DeviceRangeUnavailable);
                 }
-                slab_bytes_after_alloc = self.untypeds[slab_idx].free_bytes - new_slab_size; 
-                if slab_bytes_after_alloc < best_slab_bytes_after_alloc {
-                    best_slab_idx = slab_idx;
-                    best_slab_bytes_after_alloc = slab_bytes_after_alloc;
+            };
+            let slab_cptr = self.device_untypeds[ut_index].cptr;
+            let offset = paddr - self.device_untypeds[ut_index].base_paddr;
+            let aligned = offset & (l2tob(od.size_bits().unwrap()) - 1) == 0;
+            let free_index = self.device_free_index(ut_index);
+            let pad = match pad_layout(free_index, offset, seL4_MinUntypedBits) {
+                Some(pad) if aligned => pad,
+                _ => {
+                    debug!("Device {paddr:#x} unreachable in slab {slab_cptr} at {free_index:#x}");
+                    return Err(MemoryManagerError::AllocFailed);
                 }
-                // If perfect fit, break
-                if slab_bytes_after_alloc == 0 {
-                    break;
+            };
+            for bits in pad {
+                let result = Self::new_untyped(slab_cptr, bits);
+                self.device_untypeds[ut_index]
+                    .counters
+                    .retype(result.is_ok());
+                match result {
+                    Ok(pad_cptr) => self.device_padding.push((slab_cptr, pad_cptr)),
+                    Err(e) => {
+                        error!("Device pad {bits} in slab {slab_cptr}: {e:?}");
+                        return Err(MemoryManagerError::AllocFailed);
+                    }
                 }
             }
+            let result = Self::retype_untyped(slab_cptr, bundle.cnode, od);
+            let ut = &mut self.device_untypeds[ut_index];
+            ut.counters.retype(result.is_ok());
+            if let Err(e) = result {
+                error!("Device alloc {od:?} in slab {slab_cptr}: {e:?}");
+                return Err(MemoryManagerError::AllocFailed);
+            }
+            ut.allocated_objects += od.retype_count();
+            ut.allocated_bytes += size_bytes;
+        }
+        Ok(())
+    }
 
-            if let Err(e) = Self::retype_untyped(self.untypeds[best_slab_idx].cptr, bundle.cnode, od) {
-                if e != seL4_Error::seL4_NoError {
-                    if e != seL4_Error::seL4_NotEnoughMemory {
-                        // Should not happen.
-                        // TODO(sleffler): reclaim allocations
-                        error!("Allocation request failed (retype returned {:?})", e);
-                        return Err(MemoryManagerError::UnknownError);
+    // Retypes |od| from slab |ut_index| into |root| with |retype| and
+    // counts the attempt against the slab.
+    fn retype_slab(
+        &mut self,
+        ut_index: usize,
+        root: seL4_CPtr,
+        od: &ObjDesc,
+        retype: impl Fn(seL4_CPtr, seL4_CPtr, &ObjDesc) -> seL4_Result,
+    ) -> seL4_Result {
+        let result = retype(self.untypeds[ut_index].cptr, root, od);
+        self.untypeds[ut_index].counters.retype(result.is_ok());
+        result
+    }
+
+    // Records that the kernel refused to retype an object from slab
+    // |ut_index| for lack of space.
+    fn slab_too_small(&mut self, ut_index: usize) {
+        self.untypeds[ut_index].counters.rejection();
+        self.failures.slab_too_small_failure();
+    }
+
+    // Logs why no slab could hold |od|: for each slab the space it has,
+    // the size it would grow to with |od| placed, and whether aligning
+    // the free index is what made |od| not fit. Quiet unless
+    // CONFIG_NOISY_UNTYPEDS is enabled.
+    #[allow(unused_variables)]
+    fn debug_alloc_failure(&self, od: &ObjDesc) {
+        #[cfg(feature = "CONFIG_NOISY_UNTYPEDS")]
+        {
+            log::info!("No slab fits {:?} ({:?} bytes)", od, od.size_bytes());
+            for (ut_index, ut) in self.untypeds.iter().enumerate() {
+                let aligned = best_fit::alignment_induced_miss(ut, od);
+                log::info!(
+                    "[{:2}, cptr {}] free_bytes {} allocated_bytes {} new_slab_size {:?}{}",
+                    ut_index,
+                    ut.cptr,
+                    ut.free_bytes,
+                    ut.allocated_bytes,
+                    best_fit::new_slab_size(ut, od),
+                    if aligned { " (alignment)" } else { "" },
+                );
+            }
+            // NB: see coalescible for why these are not merged
+            let bytes = od.size_bytes().unwrap_or(0);
+            for run in self.coalescible() {
+                if run.end - run.start >= bytes {
+                    log::info!("Contiguous free slabs {:#x}..{:#x} would fit", run.start, run.end);
+                }
+            }
+        }
+    }
+
+    // Records |od| was placed in slab |ut_index| for |owner|. Both
+    // allocation policies use this so the slab bookkeeping is the same
+    // however the slab was chosen.
+    fn record_alloc(&mut self, ut_index: usize, od: &ObjDesc, owner: Option<&str>) {
+        let ut = &mut self.untypeds[ut_index];
+        // NB: first-fit goes by the kernel which may place an object our
+        //   bookkeeping says does not fit; treat the slab as full.
+        let bytes_after_alloc = slab_bytes_after_alloc(ut, od).unwrap_or(0);
+        ut.allocated_objects += od.retype_count();
+        ut.allocated_bytes += od.size_bytes().unwrap();
+        ut.watermark = ut.free_bytes - bytes_after_alloc;
+        self.record_owner(ut_index, owner, od.retype_count());
+    }
+
+    // Allocates |bundle| according to the AllocPolicy with |retype|
+    // doing the work.
+    fn alloc_with(
+        &mut self,
+        bundle: &ObjDescBundle,
+        owner: Option<&str>,
+        retype: impl Fn(seL4_CPtr, seL4_CPtr, &ObjDesc) -> seL4_Result,
+    ) -> Result<(), MemoryManagerError> {
+        match self.alloc_policy {
+            AllocPolicy::FirstFit => self.alloc_first_fit_with(bundle, owner, retype),
+            AllocPolicy::BestFit => self.alloc_best_fit_with(bundle, owner, retype),
+        }
+    }
+
+    // First-fit allocation of |bundle| with |retype| doing the work.
+    // Slabs are tried in order starting from the last one used until
+    // the kernel accepts the object. Slabs the bookkeeping shows are too
+    // small are skipped without a retype; the kernel has the final say
+    // so if no slab takes the object the skipped slab with the most
+    // space left is retyped once before the request fails.
+    fn alloc_first_fit_with(
+        &mut self,
+        bundle: &ObjDescBundle,
+        owner: Option<&str>,
+        retype: impl Fn(seL4_CPtr, seL4_CPtr, &ObjDesc) -> seL4_Result,
+    ) -> Result<(), MemoryManagerError> {
+        // TODO(sleffler): split by device vs no-device (or allow mixing)
+        let first_ut = self.cur_untyped;
+        let mut ut_index = first_ut;
+
+        let mut allocated_bytes: usize = 0;
+        let mut allocated_objs: usize = 0;
+
+        for od in &bundle.objs {
+            // NB: we don't check slots are available (the kernel will tell us).
+            // The skipped slab with the most space left, if any.
+            let mut roomiest_skipped: Option<usize> = None;
+            loop {
+                if slab_bytes_after_alloc(&self.untypeds[ut_index], od).is_some() {
+                    if self.first_fit_retype(ut_index, bundle.cnode, od, &retype)? {
+                        break;
+                    }
+                } else {
+                    self.failures.slab_skipped_failure();
+                    let space = |ut: &UntypedSlab| ut.free_bytes - ut.watermark;
+                    if roomiest_skipped.map_or(true, |idx| {
+                        space(&self.untypeds[ut_index]) > space(&self.untypeds[idx])
+                    }) {
+                        roomiest_skipped = Some(ut_index);
+                    }
+                }
+                // This untyped does not have enough available space, try
+                // the next slab until we exhaust all slabs.
+                ut_index = (ut_index + 1) % self.untypeds.len();
+                trace!("Advance to untyped slab {}", ut_index);
+                if ut_index == first_ut {
+                    // Last resort: the bookkeeping may be pessimistic.
+                    if let Some(idx) = roomiest_skipped {
+                        if self.first_fit_retype(idx, bundle.cnode, od, &retype)? {
+                            ut_index = idx;
+                            break;
+                        }
                     }
                     // TODO(sleffler): reclaim allocations
-                    self.out_of_memory += 1;
+                    self.failures.out_of_memory_failure();
                     debug!("Allocation request failed (out of space)");
                     return Err(MemoryManagerError::AllocFailed);
                 }
             }
-            trace!("Allocated object in best slab: {:?}, wasting {:?} bytes of memory", best_slab_idx, best_slab_bytes_after_alloc);
             allocated_objs += od.retype_count();
             allocated_bytes += od.size_bytes().unwrap();
-            // Update bookkeeping info for the modified slab
-            self.untypeds[best_slab_idx].allocated_objects += od.retype_count();
-            self.untypeds[best_slab_idx].allocated_bytes = self.untypeds[best_slab_idx].free_bytes - best_slab_bytes_after_alloc;
+            self.record_alloc(ut_index, od, owner);
         }
-        //self.cur_untyped = best;
+        self.cur_untyped = ut_index;
 
         self.allocated_bytes += allocated_bytes;
         self.allocated_objs += allocated_objs;
@@ -490,75 +1026,105 @@ impl MemoryManager {
         self.requested_objs += allocated_objs;
         self.requested_bytes += allocated_bytes;
 
-
         Ok(())
     }
-}
 
-impl MemoryManagerInterface for MemoryManager {
-    fn alloc(
+    // Retypes |od| from slab |ut_index| for first-fit. Returns whether
+    // the kernel accepted the object; a refusal for lack of space is
+    // counted against the slab.
+    fn first_fit_retype(
         &mut self,
-        bundle: &ObjDescBundle,
-        lifetime: MemoryLifetime,
-    ) -> Result<(), MemoryManagerError> {
-        trace!("alloc {:?} {:?}", bundle, lifetime);
-
-        if lifetime == MemoryLifetime::Static {
-            // Static allocations are handle separately.
-            return self.alloc_static(bundle);
+        ut_index: usize,
+        root: seL4_CPtr,
+        od: &ObjDesc,
+        retype: impl Fn(seL4_CPtr, seL4_CPtr, &ObjDesc) -> seL4_Result,
+    ) -> Result<bool, MemoryManagerError> {
+        // NB: we don't allocate ASIDPool objects but if we did it
+        //   would fail because it needs to map to an UntypedObject
+        match self.retype_slab(ut_index, root, od, retype) {
+            Ok(_) => Ok(true),
+            Err(seL4_Error::seL4_NotEnoughMemory) => {
+                self.slab_too_small(ut_index);
+                Ok(false)
+            }
+            Err(e) => {
+                // Should not happen.
+                // TODO(sleffler): reclaim allocations
+                error!("Allocation request failed (retype returned {:?})", e);
+                Err(MemoryManagerError::UnknownError)
+            }
         }
-        let USE_BEST_FIT = true;
-        if USE_BEST_FIT {
-            return self.alloc_best_fit(bundle, lifetime)
+    }
+
+    // Returns the slab to place |od| in and the bytes left in that slab
+    // after the allocation, together with the number of slabs examined.
+    // Slabs are searched best-fit unless there are too many.
+    fn find_fit(&self, od: &ObjDesc) -> (Option<(usize, usize)>, usize) {
+        if self.untypeds.len() > self.best_fit_max_slabs {
+            let fit = find_first_fit(&self.untypeds, od);
+            (fit, fit.map_or(self.untypeds.len(), |(idx, _)| idx + 1))
+        } else {
+            (find_best_fit(&self.untypeds, od), self.untypeds.len())
         }
-        // TODO(sleffler): split by device vs no-device (or allow mixing)
-        let first_ut = self.cur_untyped;
-        let mut ut_index = first_ut;
+    }
+
+    // Best-fit (or first-fit, see find_fit) allocation of |bundle| with
+    // |retype| doing the work.
+    fn alloc_best_fit_with(
+        &mut self,
+        bundle: &ObjDescBundle,
+        owner: Option<&str>,
+        retype: impl Fn(seL4_CPtr, seL4_CPtr, &ObjDesc) -> seL4_Result,
+    ) -> Result<(), MemoryManagerError> {
+        //let first_ut = self.cur_untyped;
+        //let mut ut_index = first_ut;
 
         let mut allocated_bytes: usize = 0;
         let mut allocated_objs: usize = 0;
 
         for od in &bundle.objs {
-            // NB: we don't check slots are available (the kernel will tell us).
-            // TODO(sleffler): maybe check size_bytes() against untyped slab?
-            //    (we depend on the kernel for now)
-            while let Err(e) =
-                // NB: we don't allocate ASIDPool objects but if we did it
-                //   would fail because it needs to map to an UntypedObject
-                Self::retype_untyped(self.untypeds[ut_index].cptr, bundle.cnode, od)
-            {
-                if e != seL4_Error::seL4_NotEnoughMemory {
-                    // Should not happen.
-                    // TODO(sleffler): reclaim allocations
-                    error!("Allocation request failed (retype returned {:?})", e);
-                    return Err(MemoryManagerError::UnknownError);
+            let (fit, scanned) = self.find_fit(od);
+            // NB: slabs passed over by the scan are counted as skipped;
+            //   only the kernel refusing a retype counts as too small.
+            for ut_index in 0..scanned {
+                if slab_bytes_after_alloc(&self.untypeds[ut_index], od).is_none() {
+                    self.failures.slab_skipped_failure();
                 }
-                // This untyped does not have enough available space, try
-                // the next slab until we exhaust all slabs. This is the best
-                // we can do without per-slab bookkeeping.
-                self.untyped_slab_too_small += 1;
-                ut_index = (ut_index + 1) % self.untypeds.len();
-                trace!("Advance to untyped slab {}", ut_index);
-                // XXX { self.cur_untyped = ut_index; let _ = self.debug(); }
-                if ut_index == first_ut {
+            }
+            let (best_slab_idx, best_slab_bytes_after_alloc) =
+                match fit {
+                    Some(best) => best,
+                    None => {
+                        // TODO(sleffler): reclaim allocations
+                        self.failures.out_of_memory_failure();
+                        debug!("Allocation request failed (no slab fits {:?})", od);
+                        self.debug_alloc_failure(od);
+                        return Err(MemoryManagerError::AllocFailed);
+                    }
+                };
+
+            if let Err(e) = self.retype_slab(best_slab_idx, bundle.cnode, od, &retype) {
+                if e != seL4_Error::seL4_NoError {
+                    if e != seL4_Error::seL4_NotEnoughMemory {
+                        // Should not happen.
+                        // TODO(sleffler): reclaim allocations
+                        error!("Allocation request failed (retype returned {:?})", e);
+                        return Err(MemoryManagerError::UnknownError);
+                    }
                     // TODO(sleffler): reclaim allocations
-                    self.out_of_memory += 1;
+                    self.slab_too_small(best_slab_idx);
+                    self.failures.out_of_memory_failure();
                     debug!("Allocation request failed (out of space)");
+                    self.debug_alloc_failure(od);
                     return Err(MemoryManagerError::AllocFailed);
                 }
             }
+            trace!("Allocated object in best slab: {:?}, wasting {:?} bytes of memory", best_slab_idx, best_slab_bytes_after_alloc);
             allocated_objs += od.retype_count();
             allocated_bytes += od.size_bytes().unwrap();
-            // Update bookkeeping info for the modified slab
-            let free_index = self.untypeds[ut_index].allocated_bytes;//l2tob(info.sizeBits) - info.remainingBytes;
-            // TODO: @Willmish verify bit value correct:
-            let aligned_free_index = Self::align_up(free_index, od.retype_size_bits().unwrap());
-            self.untypeds[ut_index].allocated_objects += od.retype_count();
-            self.untypeds[ut_index].allocated_bytes = aligned_free_index + od.size_bytes().unwrap();
-            // OLD method of purely accumulating allocated bytes (does not account for alignment - maybe worth tracking for fragmentation stats?)
-            //self.untypeds[ut_index].allocated_bytes += od.size_bytes().unwrap();
+            self.record_alloc(best_slab_idx, od, owner);
         }
-        self.cur_untyped = ut_index;
+        //self.cur_untyped = best;
 
         self.allocated_bytes += allocated_bytes;
         self.allocated_objs += allocated_objs;
@@ -570,25 +1136,58 @@ impl MemoryManagerInterface for MemoryManager {
 
         Ok(())
     }
-    fn free(&mut self, bundle: &ObjDescBundle) -> Result<(), MemoryManagerError> {
-        trace!("free {:?}", bundle);
+}
 
+impl<const SLABS: usize, const STATIC_SLABS: usize> MemoryManagerInterface
+    for MemoryManager<SLABS, STATIC_SLABS>
+{
+    fn alloc(
+        &mut self,
+        bundle: &ObjDescBundle,
+        lifetime: MemoryLifetime,
+        owner: Option<&str>,
+    ) -> Result<(), MemoryManagerError> {
+        trace!("alloc {:?} {:?} {:?}", bundle, lifetime, owner);
+        if let Err(e) = bundle.validate_depth() {
+            error!("alloc: depth {} invalid for {:?}", bundle.depth, bundle);
+            return Err(e);
+        }
+
+        if lifetime == MemoryLifetime::Static {
+            // Static allocations are handle separately.
+            return self.alloc_static(bundle);
+        }
+        if lifetime == MemoryLifetime::Device {
+            return self.alloc_device(bundle);
+        }
+        // Reclaim tainted slabs only when the space is needed.
+        while !self.has_space_for(bundle) && self.reclaim_tainted() {}
+        self.alloc_with(bundle, owner, Self::retype_untyped)
+    }
+    fn free(
+        &mut self,
+        bundle: &ObjDescBundle,
+        owner: Option<&str>,
+    ) -> Result<(), MemoryManagerError> {
+        trace!("free {:?} {:?}", bundle, owner);
+        if let Err(e) = bundle.validate_depth() {
+            error!("free: depth {} invalid for {:?}", bundle.depth, bundle);
+            return Err(e);
+        }
+
+        if bundle.objs.iter().any(|od| od.size_bytes().is_none()) {
+            return Err(MemoryManagerError::ObjTypeInvalid);
+        }
         for od in &bundle.objs {
             // TODO(sleffler): support leaving objects so client can do bulk
             //   reclaim on exit (maybe require cptr != 0)
-            if self.delete_caps(bundle.cnode, bundle.depth, od).is_ok() {
-                // NB: atm we do not do per-untyped bookkeeping so just track
-                //   global stats.
-                // TODO(sleffler): temp workaround for bad bookkeeping / client mis-handling
-                let size_bytes = od.size_bytes().ok_or(MemoryManagerError::ObjTypeInvalid)?;
-                if size_bytes <= self.allocated_bytes {
-                    self.allocated_bytes -= size_bytes;
-                    self.allocated_objs -= od.retype_count();
-                } else {
-                    debug!("Underflow on free of {:?}", od);
-                }
-            }
+            let _ = self.delete_caps(bundle.cnode, bundle.depth, od, owner);
         }
+        // The slabs credited by delete_caps are authoritative; objects
+        // with remaining references (e.g. dups) stay allocated.
+        let (allocated_bytes, allocated_objs) = self.slab_totals();
+        self.allocated_bytes = allocated_bytes;
+        self.allocated_objs = allocated_objs;
         Ok(())
     }
     fn stats(&self) -> Result<MemoryManagerStats, MemoryManagerError> {
@@ -603,6 +1202,8 @@ impl MemoryManagerInterface for MemoryManager {
 
             untyped_slab_too_small: self.untyped_slab_too_small(),
             out_of_memory: self.out_of_memory(),
+
+            slabs: self.slab_stats(),
         })
     }
     fn debug(&self) -> Result<(), MemoryManagerError> {
@@ -612,14 +1213,18 @@ impl MemoryManagerInterface for MemoryManager {
             let info = untyped_describe(ut.cptr);
             let size = l2tob(info.sizeBits);
             info!(target: if ut.cptr == cur_cptr { "*" } else { " " },
-                "[{:2}, bits {:2}] watermark {:8} available {}, max_size_bytes {}, allocated_bytes: {}, allocated_objects: {}",
+                "[{:2}, bits {:2}] watermark {:8} available {}, max_size_bytes {}, allocated_bytes: {}, allocated_objects: {}, largest_free {}, attempts {} successes {} rejections {}",
                 ut.cptr,
                 info.sizeBits,
                 size - info.remainingBytes,
                 info.remainingBytes,
                 ut.free_bytes,
                 ut.allocated_bytes,
-                ut.allocated_objects
+                ut.allocated_objects,
+                ut.stats().largest_contiguous_free,
+                ut.counters.attempts(),
+                ut.counters.successes(),
+                ut.counters.rejections()
             );
         }
         if !self.static_untypeds.is_empty() {
@@ -636,7 +1241,716 @@ impl MemoryManagerInterface for MemoryManager {
                 );
             }
         }
-        info!("Allocation failed on a slab: {} times. Out of memory thrown {} times.", self.untyped_slab_too_small, self.out_of_memory);
+        for run in self.coalescible() {
+            info!(
+                "Contiguous free slabs {:#x}..{:#x} ({} bytes) cannot be coalesced",
+                run.start,
+                run.end,
+                run.end - run.start
+            );
+        }
+        info!(
+            "Failures: slab_too_small {} slab_skipped {} out_of_memory {}",
+            self.untyped_slab_too_small(),
+            self.untyped_slab_skipped(),
+            self.out_of_memory()
+        );
+        #[cfg(any(test, feature = "CONFIG_ALLOC_OWNERS"))]
+        for (owner, objs) in self.owners() {
+            info!("Owner {owner}: {objs} objects");
+        }
+        Ok(())
+    }
+    fn set_alloc_policy(&mut self, policy: AllocPolicy) -> Result<(), MemoryManagerError> {
+        info!("Allocation policy {:?}", policy);
+        self.alloc_policy = policy;
+        Ok(())
+    }
+    fn reset_failure_counters(&mut self) -> Result<(), MemoryManagerError> {
+        self.failures.reset();
+        for ut in &mut self.untypeds {
+            ut.counters.reset();
+        }
         Ok(())
     }
 }
+
+impl<const SLABS: usize, const STATIC_SLABS: usize> Diagnostics
+    for MemoryManager<SLABS, STATIC_SLABS>
+{
+    fn diagnostics(&self) -> DiagnosticsReport {
+        let mut report = DiagnosticsReport::new("memory");
+        report
+            .add("allocated_bytes", self.allocated_space() as u64)
+            .add("free_bytes", self.free_space() as u64)
+            .add("requested_bytes", self.total_requested_space() as u64)
+            .add("overhead_bytes", self.overhead_space() as u64)
+            .add("allocated_objs", self.allocated_objs() as u64)
+            .add("requested_objs", self.total_requested_objs() as u64)
+            .add("slabs", self.untypeds.len() as u64)
+            .add("static_slabs", self.static_untypeds.len() as u64)
+            .add("slabs_spilled", self.spilled() as u64)
+            .add("tainted_slabs", self.tainted.pending() as u64)
+            .add("unusable_slabs", self.tainted.unusable() as u64)
+            .add("slab_too_small", self.untyped_slab_too_small() as u64)
+            .add("slab_skipped", self.untyped_slab_skipped() as u64)
+            .add("out_of_memory", self.out_of_memory() as u64);
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns a device untyped; these are added without any syscalls.
+    fn device_untyped() -> seL4_UntypedDesc {
+        // NB: seL4_UntypedDesc has a private alignment field.
+        let mut ut: seL4_UntypedDesc = unsafe { core::mem::zeroed() };
+        ut.sizeBits = 12;
+        ut.isDevice = 1;
+        ut
+    }
+
+    fn add_device_untypeds<const SLABS: usize>(m: &mut MemoryManager<SLABS, 1>, count: usize) {
+        for cptr in 0..count {
+            m.add_untyped(&device_untyped(), cptr);
+        }
+    }
+
+    #[test]
+    fn slabs_spill() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        add_device_untypeds(&mut m, 6);
+        assert!(m.spilled());
+        // Every slab is kept, in order, after moving to the heap.
+        assert_eq!(m.device_untypeds.len(), 6);
+        assert!(m.device_untypeds.iter().map(|ut| ut.cptr).eq(0..6));
+        assert!(m
+            .device_untypeds
+            .iter()
+            .all(|ut| ut.free_bytes == l2tob(12)));
+    }
+
+    #[test]
+    fn slabs_inline() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        add_device_untypeds(&mut m, 3);
+        assert!(!m.spilled());
+        assert!(m.device_untypeds.iter().map(|ut| ut.cptr).eq(0..3));
+
+        // Filling exactly to capacity does not spill.
+        let mut m = MemoryManager::<4, 1>::empty();
+        add_device_untypeds(&mut m, 4);
+        assert!(!m.spilled());
+    }
+
+    #[test]
+    fn device_slab_lookup() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        for (cptr, paddr) in [(1, 0x1000_0000), (2, 0x1000_1000)] {
+            let mut ut = device_untyped();
+            ut.paddr = paddr;
+            m.add_untyped(&ut, cptr);
+        }
+        assert_eq!(m.find_device_slab(&(0x1000_0000..0x1000_1000)), Some(0));
+        assert_eq!(m.find_device_slab(&(0x1000_1800..0x1000_2000)), Some(1));
+        // A range spanning slabs cannot be retyped from either.
+        assert_eq!(m.find_device_slab(&(0x1000_0000..0x1000_2000)), None);
+        assert_eq!(m.find_device_slab(&(0x2000_0000..0x2000_1000)), None);
+    }
+
+    // Returns a 4KiB slab at |cptr| with |allocated_bytes| in use.
+    fn slab(cptr: seL4_CPtr, allocated_bytes: usize) -> UntypedSlab {
+        let mut ut: seL4_UntypedDesc = unsafe { core::mem::zeroed() };
+        ut.sizeBits = 12;
+        let mut slab = UntypedSlab::new(&ut, l2tob(12), cptr);
+        slab.watermark = allocated_bytes;
+        slab.allocated_bytes = allocated_bytes;
+        slab
+    }
+
+    // Returns a bundle holding a single untyped of 2^|size_bits| bytes.
+    fn untyped(size_bits: usize) -> ObjDescBundle {
+        ObjDescBundle::new(0, 0, vec![ObjDesc::new(seL4_UntypedObject, size_bits, 0)])
+    }
+
+    #[test]
+    fn slab_counters() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        m.untypeds.push(slab(1, 0));
+        m.untypeds.push(slab(2, 0xc00));
+        m.untypeds.push(slab(3, 0));
+        // The kernel refuses every retype from slab 3.
+        let retype = |cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result {
+            if cptr == 3 {
+                Err(seL4_Error::seL4_NotEnoughMemory)
+            } else {
+                Ok(())
+            }
+        };
+
+        // 1KiB fills slab 2; then 2KiB skips the full slab 2 and goes to
+        // slab 1 (tied with slab 3).
+        assert!(m.alloc_best_fit_with(&untyped(10), None, retype).is_ok());
+        assert!(m.alloc_best_fit_with(&untyped(11), None, retype).is_ok());
+        // 4KiB fits only slab 3 which the kernel refuses.
+        assert_eq!(
+            m.alloc_best_fit_with(&untyped(12), None, retype),
+            Err(MemoryManagerError::AllocFailed)
+        );
+        // 8KiB fits nowhere.
+        assert_eq!(
+            m.alloc_best_fit_with(&untyped(13), None, retype),
+            Err(MemoryManagerError::AllocFailed)
+        );
+
+        let counts = |cptr: seL4_CPtr| {
+            let ut = m.untypeds.iter().find(|ut| ut.cptr == cptr).unwrap();
+            (ut.counters.attempts(), ut.counters.successes(), ut.counters.rejections())
+        };
+        // Only the kernel refusing slab 3 is a rejection; slabs the scan
+        // found too small are counted once each as skipped.
+        assert_eq!(counts(1), (1, 1, 0));
+        assert_eq!(counts(2), (1, 1, 0));
+        assert_eq!(counts(3), (1, 0, 1));
+        assert_eq!(m.untyped_slab_skipped(), 1 + 2 + 3);
+
+        // The per-slab counters account for the global totals.
+        let successes: usize = m.untypeds.iter().map(|ut| ut.counters.successes()).sum();
+        assert_eq!(rejections(&m), m.untyped_slab_too_small());
+        assert_eq!(rejections(&m), 1);
+        assert_eq!(successes, m.total_requested_objs());
+        assert_eq!(m.out_of_memory(), 2);
+
+        assert!(m.reset_failure_counters().is_ok());
+        assert!(m.untypeds.iter().all(|ut| ut.counters.attempts() == 0));
+        assert_eq!(m.untyped_slab_too_small(), 0);
+        assert_eq!(m.untyped_slab_skipped(), 0);
+    }
+
+    #[test]
+    fn reset_failure_counters() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        m.untypeds.push(slab(1, 0));
+        m.untypeds.push(slab(2, 0));
+        let retype = |_cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result {
+            Err(seL4_Error::seL4_NotEnoughMemory)
+        };
+        // The kernel refuses every retype so each alloc fails.
+        for _ in 0..2 {
+            assert_eq!(
+                m.alloc_with(&untyped(10), None, retype),
+                Err(MemoryManagerError::AllocFailed)
+            );
+        }
+        // 8KiB fits no slab.
+        assert_eq!(
+            m.alloc_with(&untyped(13), None, retype),
+            Err(MemoryManagerError::AllocFailed)
+        );
+        assert_eq!(m.untyped_slab_too_small(), 2);
+        assert_eq!(m.untyped_slab_skipped(), 2);
+        assert_eq!(m.out_of_memory(), 3);
+        let stats = m.stats().unwrap();
+        assert_eq!((stats.untyped_slab_too_small, stats.out_of_memory), (2, 3));
+
+        assert!(m.reset_failure_counters().is_ok());
+        assert_eq!(m.untyped_slab_too_small(), 0);
+        assert_eq!(m.untyped_slab_skipped(), 0);
+        assert_eq!(m.out_of_memory(), 0);
+        let stats = m.stats().unwrap();
+        assert_eq!((stats.untyped_slab_too_small, stats.out_of_memory), (0, 0));
+
+        // Counting resumes from zero for the next run.
+        assert!(m.alloc_with(&untyped(10), None, retype).is_err());
+        assert_eq!(m.out_of_memory(), 1);
+    }
+
+    // Fills 8 slabs (sorted by descending free space as at boot) with
+    // 1KiB objects, using best-fit for at most |max_slabs| slabs. Returns
+    // the manager and the cptr of the slab each object went to.
+    fn fill_slabs(max_slabs: usize) -> (MemoryManager<8, 1>, Vec<seL4_CPtr>) {
+        let mut m = MemoryManager::<8, 1>::empty();
+        m.set_best_fit_max_slabs(max_slabs);
+        for (cptr, allocated_bytes) in [0, 0, 0x400, 0x400, 0x800, 0x800, 0xc00, 0xc00]
+            .into_iter()
+            .enumerate()
+        {
+            m.untypeds.push(slab(cptr + 1, allocated_bytes));
+        }
+        let last = core::cell::Cell::new(0);
+        let retype = |cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result {
+            last.set(cptr);
+            Ok(())
+        };
+        let mut placed = Vec::new();
+        while m.alloc_best_fit_with(&untyped(10), None, retype).is_ok() {
+            placed.push(last.get());
+        }
+        (m, placed)
+    }
+
+    fn rejections<const SLABS: usize>(m: &MemoryManager<SLABS, 1>) -> usize {
+        m.untypeds.iter().map(|ut| ut.counters.rejections()).sum()
+    }
+
+    #[test]
+    fn first_fit_fallback() {
+        // Within the threshold slabs are filled best-fit: the fullest first.
+        let (best, mut best_placed) = fill_slabs(8);
+        assert_eq!(&best_placed[..4], [7, 8, 5, 5]);
+        // Beyond it they are filled first-fit: in order.
+        let (first, mut first_placed) = fill_slabs(7);
+        assert_eq!(&first_placed[..5], [1, 1, 1, 1, 2]);
+
+        // Both place the same objects and fill every slab exactly.
+        for m in [&best, &first] {
+            assert_eq!(m.total_requested_objs(), 20);
+            assert_eq!(m.out_of_memory(), 1);
+            assert!(m.untypeds.iter().all(|ut| ut.allocated_bytes == ut.free_bytes));
+        }
+        best_placed.sort();
+        first_placed.sort();
+        assert_eq!(best_placed, first_placed);
+
+        // First-fit examines fewer slabs: it skips only the full slabs
+        // ahead of the one used while best-fit skips every full slab. The
+        // kernel refused nothing.
+        assert_eq!(best.untyped_slab_skipped(), 98);
+        assert_eq!(first.untyped_slab_skipped(), 58);
+        assert_eq!(rejections(&best) + rejections(&first), 0);
+    }
+    #[test]
+    fn owner_leak() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        m.untypeds.push(slab(1, 0));
+        m.untypeds.push(slab(2, 0));
+        m.index_slabs();
+        let last = core::cell::Cell::new(0);
+        let retype = |cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result {
+            last.set(cptr);
+            Ok(())
+        };
+        let mut allocated = Vec::new();
+        for owner in ["a", "b", "c", "a"] {
+            assert!(m
+                .alloc_best_fit_with(&untyped(10), Some(owner), retype)
+                .is_ok());
+            allocated.push((owner, last.get()));
+        }
+        assert!(m.alloc_best_fit_with(&untyped(10), None, retype).is_ok());
+        assert_eq!(
+            m.owners(),
+            [
+                ("a".to_string(), 2),
+                ("b".to_string(), 1),
+                ("c".to_string(), 1)
+            ]
+        );
+
+        // Free everything but "b"'s object (NB: the kernel reports the
+        // slab each deleted object came from).
+        for &(owner, slab_cptr) in allocated.iter().filter(|(owner, _)| *owner != "b") {
+            m.release_from_slab(slab_cptr, l2tob(10), Some(owner));
+        }
+        // The leak is attributed to "b"; untagged objects are not counted.
+        assert_eq!(m.owners(), [("b".to_string(), 1)]);
+    }
+
+    #[test]
+    fn free_credits_slab() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        for cptr in 1..=3 {
+            m.untypeds.push(slab(cptr, 0));
+        }
+        m.index_slabs();
+        let last = core::cell::Cell::new(0);
+        let retype = |cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result {
+            last.set(cptr);
+            Ok(())
+        };
+        // Fill every slab with 1KiB objects.
+        let mut allocated = Vec::new();
+        while m.alloc_best_fit_with(&untyped(10), None, retype).is_ok() {
+            allocated.push(last.get());
+        }
+        assert_eq!(allocated.len(), 12);
+        assert_eq!(m.slab_totals(), (3 * l2tob(12), 12));
+
+        // Releasing part of a slab does not make room in it.
+        let (first, rest) = allocated.split_first().unwrap();
+        m.release_from_slab(*first, l2tob(10), None);
+        assert_eq!(m.slab_totals(), (3 * l2tob(12) - l2tob(10), 11));
+        assert!(m.alloc_best_fit_with(&untyped(10), None, retype).is_err());
+
+        // Releasing the rest (in reverse) resets each slab as it empties.
+        for &slab_cptr in rest.iter().rev() {
+            m.release_from_slab(slab_cptr, l2tob(10), None);
+        }
+        assert_eq!(m.slab_totals(), (0, 0));
+        assert!(m.untypeds.iter().all(|ut| ut.watermark == 0));
+
+        // Objects from untracked (e.g. static) slabs are ignored.
+        m.release_from_slab(99, l2tob(10), None);
+        assert_eq!(m.slab_totals(), (0, 0));
+
+        // All the space is available again.
+        assert!(m.alloc_best_fit_with(&untyped(12), None, retype).is_ok());
+        assert!(m.alloc_best_fit_with(&untyped(12), None, retype).is_ok());
+        assert!(m.alloc_best_fit_with(&untyped(12), None, retype).is_ok());
+    }
+
+    #[test]
+    fn alloc_policy() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        m.untypeds.push(slab(1, 0));
+        m.untypeds.push(slab(2, 0xc00));
+        let last = core::cell::Cell::new(0);
+        let retype = |cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result {
+            last.set(cptr);
+            Ok(())
+        };
+        // Best-fit (the default) fills the fuller slab; first-fit takes
+        // the first with room.
+        assert!(m.alloc_with(&untyped(10), None, retype).is_ok());
+        assert_eq!(last.get(), 2);
+        assert!(m.set_alloc_policy(AllocPolicy::FirstFit).is_ok());
+        assert!(m.alloc_with(&untyped(10), None, retype).is_ok());
+        assert_eq!(last.get(), 1);
+    }
+
+    #[test]
+    fn first_fit_skips_full_slabs() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        m.untypeds.push(slab(1, 0x1000));
+        m.untypeds.push(slab(2, 0xf00));
+        m.untypeds.push(slab(3, 0));
+        let retype = |_cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result { Ok(()) };
+        // Slabs 1 & 2 are too small so only slab 3 is retyped.
+        assert!(m.alloc_first_fit_with(&untyped(10), None, retype).is_ok());
+        assert_eq!(m.untypeds[0].counters.attempts(), 0);
+        assert_eq!(m.untypeds[1].counters.attempts(), 0);
+        assert_eq!(m.untypeds[2].counters.attempts(), 1);
+        assert_eq!(m.untyped_slab_skipped(), 2);
+        assert_eq!(m.untyped_slab_too_small(), 0);
+    }
+
+    #[test]
+    fn first_fit_kernel_decides() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        m.untypeds.push(slab(1, 0));
+        m.untypeds.push(slab(2, 0x1000));
+        // The kernel refuses slab 1 but takes slab 2 even though the
+        // bookkeeping shows it full.
+        let retype = |cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result {
+            if cptr == 1 {
+                Err(seL4_Error::seL4_NotEnoughMemory)
+            } else {
+                Ok(())
+            }
+        };
+        assert!(m.alloc_first_fit_with(&untyped(10), None, retype).is_ok());
+        assert_eq!(m.untypeds[0].counters.attempts(), 1);
+        assert_eq!(m.untypeds[1].counters.successes(), 1);
+        assert_eq!(m.untyped_slab_too_small(), 1);
+        assert_eq!(m.untyped_slab_skipped(), 1);
+
+        // Once every slab is refused the request fails.
+        let retype = |_cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result {
+            Err(seL4_Error::seL4_NotEnoughMemory)
+        };
+        assert_eq!(
+            m.alloc_first_fit_with(&untyped(10), None, retype),
+            Err(MemoryManagerError::AllocFailed)
+        );
+        assert_eq!(m.out_of_memory(), 1);
+    }
+
+    #[test]
+    fn first_fit_last_resort() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        m.untypeds.push(slab(1, 0x1000));
+        m.untypeds.push(slab(2, 0xf00));
+        m.untypeds.push(slab(3, 0x1000));
+        let retype = |_cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result {
+            Err(seL4_Error::seL4_NotEnoughMemory)
+        };
+        // Every slab is skipped; only the one with the most space left
+        // is retyped before the request fails.
+        assert_eq!(
+            m.alloc_first_fit_with(&untyped(10), None, retype),
+            Err(MemoryManagerError::AllocFailed)
+        );
+        let attempts: Vec<usize> = m.untypeds.iter().map(|ut| ut.counters.attempts()).collect();
+        assert_eq!(attempts, [0, 1, 0]);
+        assert_eq!(m.untyped_slab_skipped(), 3);
+        assert_eq!(m.untyped_slab_too_small(), 1);
+        assert_eq!(m.out_of_memory(), 1);
+    }
+
+    #[test]
+    fn alloc_policy_bookkeeping() {
+        use sel4_sys::seL4_ObjectType::seL4_CapTableObject;
+        // A 1KiB untyped then a 2KiB CNode which the kernel aligns to its
+        // size, leaving a 1KiB hole.
+        let slot_bits = seL4_CapTableObject.size_bits().unwrap();
+        let cnode = ObjDesc::new(seL4_CapTableObject, 11 - slot_bits, 0);
+        let retype = |_cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result { Ok(()) };
+        for policy in [AllocPolicy::FirstFit, AllocPolicy::BestFit] {
+            let mut m = MemoryManager::<4, 1>::empty();
+            m.untypeds.push(slab(1, 0));
+            assert!(m.set_alloc_policy(policy).is_ok());
+            assert!(m.alloc_with(&untyped(10), None, retype).is_ok());
+            assert!(m
+                .alloc_with(&ObjDescBundle::new(0, 0, vec![cnode]), None, retype)
+                .is_ok());
+            let ut = &m.untypeds[0];
+            assert_eq!(ut.watermark, l2tob(12), "{:?}", policy);
+            assert_eq!(ut.allocated_bytes, l2tob(10) + l2tob(11), "{:?}", policy);
+            assert_eq!(ut.allocated_objects, 2, "{:?}", policy);
+        }
+    }
+
+    // Allocates |objs| best-fit from an empty 64KiB slab, checks the
+    // bookkeeping, and frees them again. Returns the slab's watermark
+    // after the allocation.
+    fn alloc_free_bundle(objs: Vec<ObjDesc>) -> usize {
+        let mut m = MemoryManager::<4, 1>::empty();
+        let ut: seL4_UntypedDesc = unsafe { core::mem::zeroed() };
+        m.untypeds.push(UntypedSlab::new_split(&ut, 16, 0, 1));
+        m.index_slabs();
+        let retype = |_cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result { Ok(()) };
+        let bundle = ObjDescBundle::new(0, 0, objs.clone());
+        assert!(m.alloc_best_fit_with(&bundle, None, retype).is_ok());
+
+        let size_bytes: usize = objs.iter().map(|od| od.size_bytes().unwrap()).sum();
+        let count: usize = objs.iter().map(|od| od.retype_count()).sum();
+        let ut = &m.untypeds[0];
+        assert_eq!(ut.allocated_objects, count);
+        assert_eq!(ut.allocated_bytes, size_bytes);
+        assert!(ut.allocated_bytes <= ut.watermark && ut.watermark <= ut.free_bytes);
+        let watermark = ut.watermark;
+
+        // Freeing each object returns the slab to empty without underflow.
+        for od in &objs {
+            for _ in 0..od.retype_count() {
+                m.release_from_slab(1, l2tob(od.size_bits().unwrap()), None);
+            }
+        }
+        let ut = &m.untypeds[0];
+        assert_eq!((ut.allocated_objects, ut.allocated_bytes, ut.watermark), (0, 0, 0));
+        watermark
+    }
+
+    #[test]
+    fn best_fit_mixed_bundle() {
+        use sel4_sys::seL4_ObjectType::seL4_TCBObject;
+        use sel4_sys::seL4_SmallPageObject;
+        // The pages are aligned up to the page after the tcb.
+        let objs = vec![
+            ObjDesc::new(seL4_TCBObject, 1, 0),
+            ObjDesc::new(seL4_SmallPageObject, 2, 1),
+        ];
+        assert_eq!(alloc_free_bundle(objs), 3 * l2tob(12));
+    }
+
+    #[cfg(feature = "CONFIG_KERNEL_MCS")]
+    #[test]
+    fn best_fit_mixed_bundle_sched_context() {
+        use sel4_sys::seL4_MinSchedContextBits;
+        use sel4_sys::seL4_ObjectType::{seL4_SchedContextObject, seL4_TCBObject};
+        use sel4_sys::seL4_SmallPageObject;
+        // The sched context packs after the tcb; the pages are aligned up
+        // to the next page.
+        let objs = vec![
+            ObjDesc::new(seL4_TCBObject, 1, 0),
+            ObjDesc::new(seL4_SchedContextObject, seL4_MinSchedContextBits, 1),
+            ObjDesc::new(seL4_SmallPageObject, 2, 2),
+        ];
+        assert_eq!(alloc_free_bundle(objs), 3 * l2tob(12));
+    }
+
+    #[test]
+    fn slab_stats() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        let ut: seL4_UntypedDesc = unsafe { core::mem::zeroed() };
+        m.untypeds.push(UntypedSlab::new_split(&ut, 12, 0, 1));
+        m.untypeds
+            .push(UntypedSlab::new_split(&ut, 13, l2tob(13), 2));
+        m.index_slabs();
+        let retype = |cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result {
+            assert_eq!(cptr, 1);
+            Ok(())
+        };
+        // Two 1KiB objects go to the 4KiB slab (the best fit).
+        assert!(m.alloc_best_fit_with(&untyped(10), None, retype).is_ok());
+        assert!(m.alloc_best_fit_with(&untyped(10), None, retype).is_ok());
+        // Freeing the first leaves a hole that is not reused until the
+        // slab empties.
+        m.release_from_slab(1, l2tob(10), None);
+
+        let slabs = m.stats().unwrap().slabs;
+        assert_eq!(
+            slabs,
+            [
+                SlabStats {
+                    size_bits: 12,
+                    free_bytes: l2tob(12),
+                    allocated_bytes: l2tob(10),
+                    allocated_objects: 1,
+                    largest_contiguous_free: l2tob(11),
+                },
+                SlabStats {
+                    size_bits: 13,
+                    free_bytes: l2tob(13),
+                    allocated_bytes: 0,
+                    allocated_objects: 0,
+                    largest_contiguous_free: l2tob(13),
+                },
+            ]
+        );
+    }
+
+    // Models add_slab splitting an untyped of 2^|slab_bits| bytes with
+    // |remaining| bytes free using the kernel's retype alignment; returns
+    // the log2 size of each slab carved, the final (largest) slab last.
+    fn split(slab_bits: usize, mut remaining: usize) -> Vec<usize> {
+        type M = MemoryManager<4, 1>;
+        // Returns the space remaining after retyping a slab of |bits|.
+        let retype = |remaining: usize, bits: usize| {
+            let free_index = l2tob(slab_bits) - remaining;
+            let end = M::align_up(free_index, bits) + l2tob(bits);
+            assert!(end <= l2tob(slab_bits), "retype {bits} overflows slab");
+            l2tob(slab_bits) - end
+        };
+        let size_bits = split_size_bits(slab_bits, remaining);
+        let mut slabs = Vec::new();
+        while let Some(bits) = M::best_slab_bits(slab_bits, remaining, size_bits) {
+            remaining = retype(remaining, bits);
+            slabs.push(bits);
+        }
+        assert_eq!(retype(remaining, size_bits), 0);
+        slabs.push(size_bits);
+        slabs
+    }
+
+    #[test]
+    fn split_half_used() {
+        // Less than 1/2 remains: there is no room for a 1/2-size slab.
+        let remaining = l2tob(20) * 4 / 10;
+        assert_eq!(MemoryManager::<4, 1>::best_slab_bits(20, remaining, 19), None);
+
+        // Instead the largest slab that fits is left at the end.
+        assert_eq!(split_size_bits(20, remaining), 18);
+        assert_eq!(MemoryManager::<4, 1>::best_slab_bits(20, remaining, 18), Some(9));
+        let slabs = split(20, remaining);
+        assert_eq!(slabs.last(), Some(&18));
+        let carved: usize = slabs.iter().map(|&bits| l2tob(bits)).sum();
+        assert!(carved <= remaining && carved > remaining - l2tob(10));
+    }
+
+    #[test]
+    fn split_mostly_free() {
+        // More than 1/2 remains: the 1/2-size slab is used as before.
+        let remaining = l2tob(20) - 3 * l2tob(12);
+        assert_eq!(split_size_bits(20, remaining), 19);
+        let slabs = split(20, remaining);
+        assert_eq!(slabs.last(), Some(&19));
+        let carved: usize = slabs.iter().map(|&bits| l2tob(bits)).sum();
+        assert_eq!(carved, remaining);
+    }
+
+    #[test]
+    fn coalescible() {
+        type M = MemoryManager<4, 1>;
+        // A 1MiB untyped at 16MiB with its first page used by the
+        // rootserver, split as add_slab does.
+        let mut ut: seL4_UntypedDesc = unsafe { core::mem::zeroed() };
+        ut.sizeBits = 20;
+        ut.paddr = 0x100_0000;
+        let remaining = l2tob(20) - l2tob(12);
+        let mut m = M::empty();
+        // Carves a slab of |bits|; returns the space remaining after it.
+        let carve = |m: &mut M, remaining: usize, bits: usize| {
+            let paddr = M::split_paddr(&ut, remaining, bits);
+            let cptr = m.untypeds.len() + 1;
+            m.untypeds
+                .push(UntypedSlab::new_split(&ut, bits, paddr, cptr));
+            ut.paddr + l2tob(20) - (paddr + l2tob(bits))
+        };
+        let size_bits = split_size_bits(20, remaining);
+        let mut left = remaining;
+        while let Some(bits) = M::best_slab_bits(20, left, size_bits) {
+            left = carve(&mut m, left, bits);
+        }
+        assert_eq!(carve(&mut m, left, size_bits), 0);
+        m.index_slabs();
+
+        // The 1/2-size slab is the largest so a 512KiB + 4KiB request
+        // does not fit though the split pieces are contiguous.
+        let mut big = untyped(19);
+        big.objs.push(ObjDesc::new(seL4_UntypedObject, 12, 0));
+        assert!(m.has_space_for(&untyped(19)));
+        assert!(!m.has_space_for(&big));
+        let runs = m.coalescible();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0], ut.paddr + l2tob(12)..ut.paddr + l2tob(20));
+
+        // An allocation splits the run.
+        let retype = |_cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result { Ok(()) };
+        assert!(m.alloc_best_fit_with(&untyped(12), None, retype).is_ok());
+        let runs = m.coalescible();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0], ut.paddr + l2tob(13)..ut.paddr + l2tob(20));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut m = MemoryManager::<4, 1>::empty();
+        m.untypeds.push(slab(1, 0));
+        m.untypeds.push(slab(2, 0));
+        m.total_bytes = 2 * l2tob(12);
+        m.index_slabs();
+        let report = m.diagnostics();
+        assert_eq!(report.component, "memory");
+        assert_eq!(report.get("slabs"), Some(2));
+        assert_eq!(report.get("free_bytes"), Some(l2tob(13) as u64));
+        assert_eq!(report.get("allocated_objs"), Some(0));
+
+        let retype = |_cptr: seL4_CPtr, _root: seL4_CPtr, _od: &ObjDesc| -> seL4_Result { Ok(()) };
+        assert!(m.alloc_best_fit_with(&untyped(10), None, retype).is_ok());
+        assert!(m.alloc_best_fit_with(&untyped(11), None, retype).is_ok());
+        let report = m.diagnostics();
+        assert_eq!(report.get("allocated_bytes"), Some(3 * l2tob(10) as u64));
+        assert_eq!(report.get("free_bytes"), Some((l2tob(13) - 3 * l2tob(10)) as u64));
+        assert_eq!(report.get("allocated_objs"), Some(2));
+        assert_eq!(report.get("requested_objs"), Some(2));
+        assert_eq!(report.get("static_slabs"), Some(0));
+        // Every counter is reported, in a fixed order.
+        let names: Vec<&str> = report
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "allocated_bytes",
+                "free_bytes",
+                "requested_bytes",
+                "overhead_bytes",
+                "allocated_objs",
+                "requested_objs",
+                "slabs",
+                "static_slabs",
+                "slabs_spilled",
+                "tainted_slabs",
+                "unusable_slabs",
+                "slab_too_small",
+                "slab_skipped",
+                "out_of_memory",
+            ]
+        );
+    }
+}
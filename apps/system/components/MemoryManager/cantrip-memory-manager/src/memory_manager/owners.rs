@@ -0,0 +1,121 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Owner tags of allocated objects.
+//!
+//! Each slab counts the objects allocated from it by owner tag so that
+//! objects never freed can be attributed to the subsystem that leaked
+//! them. Untagged objects are not counted. There are few owners per
+//! slab so a list is used rather than a map.
+
+extern crate alloc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+#[derive(Debug, Default)]
+pub struct OwnerCounts(Vec<(String, usize)>);
+impl OwnerCounts {
+    pub const fn new() -> Self { Self(Vec::new()) }
+
+    /// Records |count| objects allocated by |owner|.
+    pub fn add(&mut self, owner: &str, count: usize) {
+        match self.0.iter_mut().find(|(tag, _)| tag == owner) {
+            Some((_, objs)) => *objs += count,
+            None => self.0.push((owner.to_string(), count)),
+        }
+    }
+
+    /// Records |count| objects freed by |owner|. Returns false if |owner|
+    /// does not hold that many objects.
+    pub fn remove(&mut self, owner: &str, count: usize) -> bool {
+        let index = match self.0.iter().position(|(tag, _)| tag == owner) {
+            Some(index) => index,
+            None => return false,
+        };
+        let objs = &mut self.0[index].1;
+        if *objs < count {
+            return false;
+        }
+        *objs -= count;
+        if *objs == 0 {
+            self.0.swap_remove(index);
+        }
+        true
+    }
+
+    /// Forgets all owners (e.g. when the slab is reset).
+    pub fn clear(&mut self) { self.0.clear(); }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.0.iter().map(|(tag, objs)| (tag.as_str(), *objs))
+    }
+}
+
+/// Returns the objects held by each owner summed over |counts|, sorted
+/// by owner.
+pub fn tally<'a>(counts: impl Iterator<Item = &'a OwnerCounts>) -> Vec<(String, usize)> {
+    let mut total = OwnerCounts::new();
+    for (owner, objs) in counts.flat_map(|c| c.iter()) {
+        total.add(owner, objs);
+    }
+    let mut total = total.0;
+    total.sort_unstable();
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owners(counts: &OwnerCounts) -> Vec<(&str, usize)> {
+        let mut owners: Vec<_> = counts.iter().collect();
+        owners.sort_unstable();
+        owners
+    }
+
+    #[test]
+    fn add_remove() {
+        let mut counts = OwnerCounts::new();
+        counts.add("a", 2);
+        counts.add("b", 1);
+        counts.add("a", 1);
+        assert_eq!(owners(&counts), [("a", 3), ("b", 1)]);
+
+        assert!(counts.remove("a", 2));
+        assert!(counts.remove("b", 1));
+        assert_eq!(owners(&counts), [("a", 1)]);
+
+        // Unknown owners and over-frees are rejected.
+        assert!(!counts.remove("b", 1));
+        assert!(!counts.remove("a", 2));
+        assert!(counts.remove("a", 1));
+        assert!(counts.is_empty());
+    }
+
+    #[test]
+    fn tally_slabs() {
+        let mut slab1 = OwnerCounts::new();
+        slab1.add("b", 1);
+        slab1.add("a", 2);
+        let mut slab2 = OwnerCounts::new();
+        slab2.add("a", 1);
+        assert_eq!(
+            tally([&slab1, &slab2].into_iter()),
+            [("a".to_string(), 3), ("b".to_string(), 1)]
+        );
+        assert!(tally([].into_iter()).is_empty());
+    }
+}
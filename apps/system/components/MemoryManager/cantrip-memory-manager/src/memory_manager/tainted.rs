@@ -0,0 +1,103 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deferred reclamation of "tainted" untyped slabs.
+//!
+//! Slabs marked tainted were used by the rootserver and must be revoked
+//! before their space can be reused. Rather than revoke every slab at
+//! boot the MemoryManager records them here and reclaims one at a time
+//! when an allocation needs the space. A slab whose revoke fails is
+//! dropped and counted as unusable.
+
+use smallvec::SmallVec;
+
+const TAINTED_SLAB_CAPACITY: usize = 8; // # slabs kept inline
+
+pub struct TaintedSlabs<T> {
+    pending: SmallVec<[T; TAINTED_SLAB_CAPACITY]>,
+    unusable: usize, // Slabs whose revoke failed
+}
+impl<T> TaintedSlabs<T> {
+    pub fn new() -> Self {
+        Self {
+            pending: SmallVec::new(),
+            unusable: 0,
+        }
+    }
+
+    /// Records |slab| for reclamation on demand.
+    pub fn push(&mut self, slab: T) { self.pending.push(slab); }
+
+    /// Returns the number of slabs waiting to be reclaimed.
+    pub fn pending(&self) -> usize { self.pending.len() }
+
+    /// Returns the number of slabs discarded because their revoke failed.
+    pub fn unusable(&self) -> usize { self.unusable }
+
+    /// Revokes pending slabs with |revoke| until one succeeds and returns
+    /// that slab. Returns None when no slab could be reclaimed.
+    pub fn reclaim<E>(&mut self, mut revoke: impl FnMut(&T) -> Result<(), E>) -> Option<T> {
+        while let Some(slab) = self.pending.pop() {
+            if revoke(&slab).is_ok() {
+                return Some(slab);
+            }
+            self.unusable += 1;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lazy_revoke() {
+        let mut tainted = TaintedSlabs::new();
+        tainted.push(10usize);
+        assert_eq!(tainted.pending(), 1);
+
+        // Nothing is revoked until a slab is reclaimed.
+        let mut revoked = Vec::new();
+        let slab = tainted.reclaim(|&cptr| -> Result<(), ()> {
+            revoked.push(cptr);
+            Ok(())
+        });
+        assert_eq!(slab, Some(10));
+        assert_eq!(revoked, [10]);
+        assert_eq!(tainted.pending(), 0);
+        assert_eq!(tainted.unusable(), 0);
+
+        // Once drained there is nothing more to reclaim.
+        assert_eq!(tainted.reclaim(|_| -> Result<(), ()> { panic!("revoke") }), None);
+    }
+
+    #[test]
+    fn revoke_failure() {
+        let mut tainted = TaintedSlabs::new();
+        tainted.push(10usize);
+        tainted.push(11usize);
+        tainted.push(12usize);
+
+        // A failed revoke marks the slab unusable and moves on.
+        let slab = tainted.reclaim(|&cptr| if cptr == 12 { Err(()) } else { Ok(()) });
+        assert_eq!(slab, Some(11));
+        assert_eq!(tainted.unusable(), 1);
+        assert_eq!(tainted.pending(), 1);
+
+        assert_eq!(tainted.reclaim(|_| Err(())), None);
+        assert_eq!(tainted.unusable(), 2);
+        assert_eq!(tainted.pending(), 0);
+    }
+}
@@ -18,19 +18,31 @@
 
 extern crate alloc;
 use alloc::string::ToString;
+use alloc::vec::Vec;
 use cantrip_ml_coordinator::MLCoordinator;
 use cantrip_ml_coordinator::ModelIdx;
 use cantrip_ml_interface::CompleteJobsResponse;
+use cantrip_ml_interface::DiagnosticsResponse;
 use cantrip_ml_interface::GetInputParamsResponse;
+use cantrip_ml_interface::GetInputResponse;
+use cantrip_ml_interface::GetOutputRangeResponse;
 use cantrip_ml_interface::GetOutputResponse;
+use cantrip_ml_interface::HeartbeatResponse;
+use cantrip_ml_interface::MemoryStatsResponse;
 use cantrip_ml_interface::MlCoordError;
 use cantrip_ml_interface::MlCoordRequest;
+use cantrip_ml_interface::MlJobId;
+use cantrip_ml_interface::MlJobMask;
+use cantrip_ml_interface::MAX_OUTPUT_SIZE;
 use cantrip_ml_interface::MLCOORD_REQUEST_DATA_SIZE;
 use cantrip_ml_shared::ImageId;
 use cantrip_os_common::camkes;
+use cantrip_os_common::diagnostics::Diagnostics;
+use cantrip_os_common::diagnostics::Heartbeat;
 use cantrip_os_common::logger;
 use cantrip_os_common::sel4_sys;
 use cantrip_timer_interface::*;
+use core::cmp;
 use log::error;
 use spin::Mutex;
 
@@ -45,13 +57,23 @@ use generated::*;
 
 static ML_COORD: Mutex<MLCoordinator> = Mutex::new(MLCoordinator::new());
 
+// Max varint size of a u32; replies that return data (e.g. input or
+// output ranges) carry its length ahead of the data.
+const LEN_PREFIX: usize = 5;
+
 struct MlCoordinatorControlThread;
 impl CamkesThreadInterface for MlCoordinatorControlThread {
     fn pre_init() {
         // XXX how to handle "maybe" inclusion
         static_logger!(logger);
 
-        static mut HEAP_MEMORY: [u8; 4 * 1024] = [0; 4 * 1024];
+        // NB: room for one model's kept and pinned output plus a range
+        //   read (see OutputBuffer) on top of the MlCoordinator's needs.
+        //   Kept output and staged input for more models compete for what
+        //   is left; allocations that fail are reported as OutOfMemory
+        //   (NoMemory for input) rather than sizing for MAX_MODELS.
+        const HEAP_SIZE: usize = 4 * 1024 + 3 * MAX_OUTPUT_SIZE;
+        static mut HEAP_MEMORY: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
         unsafe {
             CAMKES.pre_init(&mut HEAP_MEMORY);
         }
@@ -149,28 +171,85 @@ impl MlcoordInterfaceThread {
             MlCoordRequest::GetInputParams {
                 bundle_id,
                 model_id,
-            } => Self::get_input_params_request(client_badge, bundle_id, model_id, reply_buffer),
+                job_id,
+            } => Self::get_input_params_request(
+                client_badge,
+                bundle_id,
+                model_id,
+                job_id,
+                reply_buffer,
+            ),
             MlCoordRequest::SetInput {
                 bundle_id,
                 model_id,
                 input_data_offset,
                 input_data,
-            } => Self::set_input_request(bundle_id, model_id, input_data_offset, input_data),
+                crc,
+            } => Self::set_input_request(bundle_id, model_id, input_data_offset, input_data, crc),
             MlCoordRequest::Oneshot {
                 bundle_id,
                 model_id,
-            } => Self::oneshot_request(client_badge, bundle_id, model_id),
+                job_id,
+            } => Self::oneshot_request(client_badge, bundle_id, model_id, job_id),
             MlCoordRequest::Periodic {
                 bundle_id,
                 model_id,
                 rate_in_ms,
-            } => Self::periodic_request(client_badge, bundle_id, model_id, rate_in_ms),
+                job_id,
+            } => Self::periodic_request(client_badge, bundle_id, model_id, rate_in_ms, job_id),
             MlCoordRequest::Cancel {
                 bundle_id,
                 model_id,
             } => Self::cancel_request(bundle_id, model_id),
+            MlCoordRequest::Pause {
+                bundle_id,
+                model_id,
+            } => Self::pause_request(bundle_id, model_id),
+            MlCoordRequest::Resume {
+                bundle_id,
+                model_id,
+            } => Self::resume_request(bundle_id, model_id),
+            MlCoordRequest::TransferModel {
+                from_bundle,
+                to_bundle,
+                model_id,
+                job_id,
+            } => {
+                Self::transfer_model_request(client_badge, from_bundle, to_bundle, model_id, job_id)
+            }
             MlCoordRequest::DebugState => Self::debug_state_request(),
             MlCoordRequest::Capscan => Self::capscan_request(),
+            MlCoordRequest::Diagnostics => Self::diagnostics_request(reply_buffer),
+            MlCoordRequest::Heartbeat => Self::heartbeat_request(reply_buffer),
+            MlCoordRequest::MemoryStats => Self::memory_stats_request(reply_buffer),
+            MlCoordRequest::GetInput {
+                bundle_id,
+                model_id,
+                input_data_offset,
+                len,
+            } => Self::get_input_request(bundle_id, model_id, input_data_offset, len, reply_buffer),
+            MlCoordRequest::VerifyInput {
+                bundle_id,
+                model_id,
+                crc,
+            } => Self::verify_input_request(bundle_id, model_id, crc),
+            MlCoordRequest::GetOutputRange {
+                bundle_id,
+                model_id,
+                offset,
+                len,
+                clear,
+            } => Self::get_output_range_request(
+                bundle_id,
+                model_id,
+                offset,
+                len,
+                clear,
+                reply_buffer,
+            ),
+            MlCoordRequest::CompletedJobsMasked { interested } => {
+                Self::completed_jobs_masked_request(interested, reply_buffer)
+            }
         }
     }
 
@@ -181,6 +260,16 @@ impl MlcoordInterfaceThread {
         Ok(())
     }
 
+    fn completed_jobs_masked_request(
+        interested: MlJobMask,
+        reply_buffer: &mut [u8],
+    ) -> MlCoordResult {
+        let job_mask = ML_COORD.lock().completed_jobs_masked(interested);
+        let _ = postcard::to_slice(&CompleteJobsResponse { job_mask }, reply_buffer)
+            .or(Err(MlCoordError::SerializeError))?;
+        Ok(())
+    }
+
     fn get_output_request(
         bundle_id: &str,
         model_id: &str,
@@ -200,13 +289,16 @@ impl MlcoordInterfaceThread {
         client_badge: usize,
         bundle_id: &str,
         model_id: &str,
+        job_id: Option<MlJobId>,
         reply_buffer: &mut [u8],
     ) -> MlCoordResult {
         let image_id = ImageId {
             bundle_id: bundle_id.to_string(),
             model_id: model_id.to_string(),
         };
-        let input = ML_COORD.lock().get_input_params(client_badge, image_id)?;
+        let input = ML_COORD
+            .lock()
+            .get_input_params(client_badge, image_id, job_id)?;
         let _ = postcard::to_slice(&GetInputParamsResponse { input }, reply_buffer)
             .or(Err(MlCoordError::SerializeError))?;
         Ok(())
@@ -217,6 +309,7 @@ impl MlcoordInterfaceThread {
         model_id: &str,
         input_data_offset: u32,
         input_data: &[u8],
+        crc: Option<u32>,
     ) -> MlCoordResult {
         let image_id = ImageId {
             bundle_id: bundle_id.to_string(),
@@ -224,15 +317,88 @@ impl MlcoordInterfaceThread {
         };
         ML_COORD
             .lock()
-            .set_input(&image_id, input_data_offset, input_data)
+            .set_input(&image_id, input_data_offset, input_data, crc)
+    }
+
+    fn verify_input_request(bundle_id: &str, model_id: &str, crc: u32) -> MlCoordResult {
+        let image_id = ImageId {
+            bundle_id: bundle_id.to_string(),
+            model_id: model_id.to_string(),
+        };
+        ML_COORD.lock().verify_input(&image_id, crc)
     }
 
-    fn oneshot_request(client_badge: usize, bundle_id: &str, model_id: &str) -> MlCoordResult {
+    fn get_input_request(
+        bundle_id: &str,
+        model_id: &str,
+        input_data_offset: u32,
+        len: u32,
+        reply_buffer: &mut [u8],
+    ) -> MlCoordResult {
+        // NB: bound the copy by what can be returned
+        let len = len as usize;
+        if len > reply_buffer.len().saturating_sub(LEN_PREFIX) {
+            return Err(MlCoordError::InvalidInputRange);
+        }
         let image_id = ImageId {
             bundle_id: bundle_id.to_string(),
             model_id: model_id.to_string(),
         };
-        ML_COORD.lock().oneshot(client_badge, image_id)
+        let mut input_data = Vec::new();
+        input_data
+            .try_reserve_exact(len)
+            .or(Err(MlCoordError::OutOfMemory))?;
+        input_data.resize(len, 0);
+        ML_COORD
+            .lock()
+            .get_input(&image_id, input_data_offset, &mut input_data)?;
+        let _ = postcard::to_slice(&GetInputResponse { input_data }, reply_buffer)
+            .or(Err(MlCoordError::SerializeError))?;
+        Ok(())
+    }
+
+    fn get_output_range_request(
+        bundle_id: &str,
+        model_id: &str,
+        offset: u32,
+        len: u32,
+        clear: bool,
+        reply_buffer: &mut [u8],
+    ) -> MlCoordResult {
+        // NB: bound the copy by what is kept and what can be returned
+        let len = len as usize;
+        if len > cmp::min(MAX_OUTPUT_SIZE, reply_buffer.len().saturating_sub(LEN_PREFIX)) {
+            return Err(MlCoordError::InvalidInputRange);
+        }
+        let image_id = ImageId {
+            bundle_id: bundle_id.to_string(),
+            model_id: model_id.to_string(),
+        };
+        let mut output_data = Vec::new();
+        output_data
+            .try_reserve_exact(len)
+            .or(Err(MlCoordError::OutOfMemory))?;
+        output_data.resize(len, 0);
+        let count = ML_COORD
+            .lock()
+            .get_output_range(&image_id, offset, &mut output_data, clear)?;
+        output_data.truncate(count);
+        let _ = postcard::to_slice(&GetOutputRangeResponse { output_data }, reply_buffer)
+            .or(Err(MlCoordError::SerializeError))?;
+        Ok(())
+    }
+
+    fn oneshot_request(
+        client_badge: usize,
+        bundle_id: &str,
+        model_id: &str,
+        job_id: Option<MlJobId>,
+    ) -> MlCoordResult {
+        let image_id = ImageId {
+            bundle_id: bundle_id.to_string(),
+            model_id: model_id.to_string(),
+        };
+        ML_COORD.lock().oneshot(client_badge, image_id, job_id)
     }
 
     fn periodic_request(
@@ -240,12 +406,15 @@ impl MlcoordInterfaceThread {
         bundle_id: &str,
         model_id: &str,
         rate_in_ms: u32,
+        job_id: Option<MlJobId>,
     ) -> MlCoordResult {
         let image_id = ImageId {
             bundle_id: bundle_id.to_string(),
             model_id: model_id.to_string(),
         };
-        ML_COORD.lock().periodic(client_badge, image_id, rate_in_ms)
+        ML_COORD
+            .lock()
+            .periodic(client_badge, image_id, rate_in_ms, job_id)
     }
 
     fn cancel_request(bundle_id: &str, model_id: &str) -> MlCoordResult {
@@ -256,6 +425,38 @@ impl MlcoordInterfaceThread {
         ML_COORD.lock().cancel(&image_id)
     }
 
+    fn pause_request(bundle_id: &str, model_id: &str) -> MlCoordResult {
+        let image_id = ImageId {
+            bundle_id: bundle_id.to_string(),
+            model_id: model_id.to_string(),
+        };
+        ML_COORD.lock().pause(&image_id)
+    }
+
+    fn resume_request(bundle_id: &str, model_id: &str) -> MlCoordResult {
+        let image_id = ImageId {
+            bundle_id: bundle_id.to_string(),
+            model_id: model_id.to_string(),
+        };
+        ML_COORD.lock().resume(&image_id)
+    }
+
+    fn transfer_model_request(
+        client_badge: usize,
+        from_bundle: &str,
+        to_bundle: &str,
+        model_id: &str,
+        job_id: Option<MlJobId>,
+    ) -> MlCoordResult {
+        let image_id = ImageId {
+            bundle_id: from_bundle.to_string(),
+            model_id: model_id.to_string(),
+        };
+        ML_COORD
+            .lock()
+            .transfer(client_badge, &image_id, to_bundle, job_id)
+    }
+
     fn debug_state_request() -> MlCoordResult {
         ML_COORD.lock().debug_state();
         Ok(())
@@ -265,4 +466,28 @@ impl MlcoordInterfaceThread {
         let _ = Camkes::capscan();
         Ok(())
     }
+
+    fn diagnostics_request(reply_buffer: &mut [u8]) -> MlCoordResult {
+        let report = ML_COORD.lock().diagnostics();
+        let _ = postcard::to_slice(&DiagnosticsResponse { report }, reply_buffer)
+            .or(Err(MlCoordError::SerializeError))?;
+        Ok(())
+    }
+
+    fn memory_stats_request(reply_buffer: &mut [u8]) -> MlCoordResult {
+        let stats = ML_COORD.lock().memory_stats();
+        let _ = postcard::to_slice(&MemoryStatsResponse { stats }, reply_buffer)
+            .or(Err(MlCoordError::SerializeError))?;
+        Ok(())
+    }
+
+    fn heartbeat_request(reply_buffer: &mut [u8]) -> MlCoordResult {
+        // NB: does not take ML_COORD so a wedged model load or run does
+        //   not stall the count of the interface thread
+        static HEARTBEAT: Heartbeat = Heartbeat::new();
+        let count = HEARTBEAT.beat();
+        let _ = postcard::to_slice(&HeartbeatResponse { count }, reply_buffer)
+            .or(Err(MlCoordError::SerializeError))?;
+        Ok(())
+    }
 }
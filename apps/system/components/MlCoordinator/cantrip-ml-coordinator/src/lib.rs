@@ -12,11 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 // ML Coordinator Design Doc: go/shodan-ml-doc
 
-use static_assertions::assert_cfg;
+use static_assertions::{assert_cfg, const_assert};
 assert_cfg!(
     any(feature = "springbok_support", feature = "kelvin_support"),
     "No vector core configured"
@@ -27,20 +27,36 @@ assert_cfg!(
 );
 
 extern crate alloc;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use cantrip_memory_interface::cantrip_object_free_in_cnode;
+use cantrip_memory_interface::cantrip_cnode_free;
 use cantrip_ml_interface::MlCoordError;
 use cantrip_ml_interface::MlInput;
+use cantrip_ml_interface::MlJobId;
+use cantrip_ml_interface::MlJobMask;
+use cantrip_ml_interface::MlMemoryStats;
+use cantrip_ml_interface::MlModelMemory;
 use cantrip_ml_interface::MlOutput;
 use cantrip_ml_interface::MAX_OUTPUT_DATA;
+use cantrip_ml_interface::MAX_OUTPUT_SIZE;
 use cantrip_ml_shared::*;
+use cantrip_ml_support::completed_jobs::{CompletedJobs, DEFAULT_JOB_ID_BASE, MAX_JOB_ID};
 use cantrip_ml_support::image_manager::ImageManager;
+use cantrip_ml_support::input_buffer::{input_checksum, InputBuffer, InputError};
+use cantrip_ml_support::output_buffer::OutputBuffer;
+use cantrip_ml_support::output_stamp::{OutputGenerations, OutputStamp};
 use cantrip_os_common::cspace_slot::CSpaceSlot;
+use cantrip_os_common::diagnostics::{Diagnostics, DiagnosticsReport};
 use cantrip_os_common::sel4_sys::seL4_Word;
 use cantrip_proc_interface::BundleImage;
 use cantrip_security_interface::*;
 use cantrip_timer_interface::*;
+use core::cmp;
 use log::{error, info, trace, warn};
+#[cfg(test)]
+use tests::{
+    cantrip_security_size_buffer, cantrip_timer_cancel, cantrip_timer_now, cantrip_timer_periodic,
+};
 
 #[cfg(feature = "kelvin_support")]
 use kelvin_vec_core as MlCore;
@@ -48,6 +64,13 @@ use kelvin_vec_core as MlCore;
 use springbok_vec_core as MlCore;
 
 use MlCore::MAX_MODELS;
+// Each slot has a default job id in the completed job mask.
+const_assert!(DEFAULT_JOB_ID_BASE as usize + MAX_MODELS <= MlJobMask::BITS as usize);
+
+// Returns the job id for a model in slot |index| loaded with |job_id|.
+fn job_id_or_default(job_id: Option<MlJobId>, index: usize) -> MlJobId {
+    job_id.unwrap_or(DEFAULT_JOB_ID_BASE + index as MlJobId)
+}
 
 /// Loadable model.
 #[derive(Debug)]
@@ -55,21 +78,69 @@ struct LoadableModel {
     id: ImageId,
     rate_in_ms: Option<u32>,
     client_id: seL4_Word,
+    job_id: MlJobId, // Bit set in the completed job mask.
     jobnum: usize,
+    paused: bool,                        // Periodic execution paused.
     output_header: Option<OutputHeader>, // Output header from last run.
     output_data: [u8; MAX_OUTPUT_DATA],  // Data returned from last run.
+    output_stamp: OutputStamp,           // Freshness of output_data.
+    output: OutputBuffer,                // All output for range reads.
+    input: InputBuffer,                  // Input staged for the next run.
+    loaded_from: Option<String>,         // Bundle holding the image if transferred.
 }
 impl LoadableModel {
-    pub fn new(id: ImageId, rate_in_ms: Option<u32>, client_id: seL4_Word) -> Self {
+    pub fn new(
+        id: ImageId,
+        rate_in_ms: Option<u32>,
+        client_id: seL4_Word,
+        job_id: MlJobId,
+    ) -> Self {
         Self {
             id,
             rate_in_ms,
             client_id,
+            job_id,
             jobnum: 0,
+            paused: false,
             output_header: None,
             output_data: [0; MAX_OUTPUT_DATA],
+            output_stamp: OutputStamp::default(),
+            output: OutputBuffer::new(),
+            input: InputBuffer::new(),
+            loaded_from: None,
         }
     }
+
+    // Returns the bundle the model's image is loaded from.
+    fn image_bundle_id(&self) -> &str {
+        self.loaded_from.as_deref().unwrap_or(&self.id.bundle_id)
+    }
+}
+
+// Checks a client-supplied job id fits in the completed job mask.
+fn check_job_id(job_id: Option<MlJobId>) -> Result<(), MlCoordError> {
+    match job_id {
+        Some(job_id) if job_id > MAX_JOB_ID => Err(MlCoordError::InvalidJobId),
+        _ => Ok(()),
+    }
+}
+
+fn input_error(e: InputError) -> MlCoordError {
+    match e {
+        InputError::OutOfRange => MlCoordError::InvalidInputRange,
+        InputError::NoMemory => MlCoordError::OutOfMemory,
+    }
+}
+
+// Returns the TimerService clock in microseconds; 0 if it cannot be read.
+fn timer_now_us() -> u64 {
+    cantrip_timer_now().map_or_else(
+        |e| {
+            warn!("cantrip_timer_now failed: {:?}", e);
+            0
+        },
+        |now| now.as_micros() as u64,
+    )
 }
 
 /// Statistics on non-happy-path events.
@@ -83,21 +154,25 @@ struct Statistics {
 pub struct MLCoordinator {
     /// The currently running model, if any.
     running_model: Option<ImageId>,
+    /// TimerService clock when the running model started; 0 if unknown.
+    /// NB: includes any time the core is frozen by a pause.
+    run_started_us: u64,
     /// A list of all models that have been requested for oneshot or periodic
     /// execution.
     models: [Option<LoadableModel>; MAX_MODELS],
     /// A queue of models that are ready for immediate execution on the vector
     /// core, once the currently running model has finished.
     execution_queue: Vec<ModelIdx>,
-    /// Bitmask of completed model runs.
-    // XXX needs to be per-client
-    completed_job_mask: usize,
+    /// Completed model runs by job id.
+    completed_jobs: CompletedJobs,
     /// The image manager is responsible for tracking, loading, and unloading
     /// images.
     image_manager: ImageManager,
     /// Value associated with each model run.
     /// Returned by get_output to distinguish returned data.
     jobnum: usize,
+    /// Stamps output of completed runs so clients can detect new results.
+    generations: OutputGenerations,
     statistics: Statistics,
 }
 
@@ -110,11 +185,13 @@ impl MLCoordinator {
         const INIT_NONE: Option<LoadableModel> = None;
         MLCoordinator {
             running_model: None,
+            run_started_us: 0,
             models: [INIT_NONE; MAX_MODELS],
             execution_queue: Vec::new(),
-            completed_job_mask: 0,
+            completed_jobs: CompletedJobs::new(),
             image_manager: ImageManager::new(),
             jobnum: 0,
+            generations: OutputGenerations::new(),
             statistics: Statistics {
                 load_failures: 0,
                 already_queued: 0,
@@ -140,16 +217,24 @@ impl MLCoordinator {
         let next_idx = self.execution_queue.remove(0);
         let model = self.models[next_idx].as_mut().expect("Model get fail");
 
+        // Refuse to run with staged input that no longer matches the
+        // checksums supplied by the client (e.g. a partial write).
+        if !model.input.verify() {
+            warn!("{}: staged input fails checksum, run skipped", &model.id);
+            return Err(MlCoordError::InputChecksumMismatch);
+        }
+
         if !self.image_manager.is_loaded(&model.id) {
             // Loads |model_id| associated with |bundle_id| from the
             // SecurityCoordinator. The data are returned as unmapped
             // page frames in a CNode container left in |container_slot|.
             // To load the model into the vector core the pages must be
             // mapped into the MlCoordinator's VSpace before being copied
-            // to the TCM.
+            // to the TCM. The frames are only read so they are shared
+            // with other loads of the model rather than copied.
             let mut container_slot = CSpaceSlot::new();
-            match cantrip_security_load_model(
-                &model.id.bundle_id,
+            match cantrip_security_load_shared_model(
+                model.image_bundle_id(),
                 &model.id.model_id,
                 &container_slot,
             ) {
@@ -172,7 +257,7 @@ impl MLCoordinator {
                     info!("Load {} successful.", &model.id);
 
                     drop(image); // NB: before releasing objects
-                    let _ = cantrip_object_free_in_cnode(&model_frames);
+                    let _ = cantrip_cnode_free(&model_frames);
 
                     // Inform the image manager the image has been written.
                     self.image_manager
@@ -193,17 +278,26 @@ impl MLCoordinator {
         self.image_manager.clear_temp_data();
 
         #[cfg(feature = "springbok_support")]
-        self.image_manager.set_wmmu(&model.id);
+        if !self.image_manager.set_wmmu(&model.id) {
+            // NB: set_wmmu logs the reason
+            return Err(MlCoordError::LoadModelFailed);
+        }
 
         // Clear output state.
         // TODO(sleffler): defer to give client more time to retrieve? (esp for periodic)
         model.output_header = None;
 
+        // Swap in any input staged since the last run.
+        for (offset, data) in model.input.swap() {
+            MlCore::set_input_data(offset, data)?;
+        }
+
         // Assign run a new jobnum.
         model.jobnum = self.jobnum;
         self.jobnum = self.jobnum.wrapping_add(1);
 
         self.running_model = Some(model.id.clone());
+        self.run_started_us = timer_now_us();
         MlCore::run(); // Start core at default PC.
 
         Ok(())
@@ -245,9 +339,13 @@ impl MLCoordinator {
         let idx = self.get_model_index(image_id)?;
 
         // Save output header and any indirect data.
+        self.stamp_output(idx, header);
         let model = self.models[idx].as_mut().unwrap();
-        model.output_header = Some(header);
         model.output_data.fill(0);
+        // NB: release the previous output before allocating for this run
+        model.output.set(Vec::new());
+        let mut output = Vec::new();
+        let mut lost = false;
         if header.output_length != 0 {
             trace!("{:#x?}", &header);
             if let Some(output_ptr) = header.output_ptr {
@@ -257,11 +355,35 @@ impl MLCoordinator {
                     &mut model.output_data,
                 );
                 trace!("{:#x?}", &model.output_data);
+
+                let output_length = header.output_length as usize;
+                if output_length > MAX_OUTPUT_SIZE {
+                    warn!(
+                        "{}: output truncated from {} to {} bytes",
+                        &model.id, output_length, MAX_OUTPUT_SIZE
+                    );
+                }
+                // NB: the heap has room for MAX_OUTPUT_SIZE but output held
+                //   by other models may use it; what does not fit is
+                //   dropped and range reads report OutOfMemory.
+                let output_length = cmp::min(output_length, MAX_OUTPUT_SIZE);
+                if output.try_reserve_exact(output_length).is_ok() {
+                    output.resize(output_length, 0);
+                    MlCore::tcm_read(output_ptr as usize, output.len(), &mut output);
+                } else {
+                    warn!("{}: no memory for {} bytes of output", &model.id, output_length);
+                    lost = true;
+                }
             }
         }
+        if lost {
+            model.output.set_lost();
+        } else {
+            model.output.set(output);
+        }
 
         // Mark the job completed and notify the client.
-        self.completed_job_mask |= 1 << idx;
+        self.completed_jobs.complete(model.job_id);
         unsafe {
             extern "Rust" {
                 fn mlcoord_emit(badge: seL4_Word);
@@ -271,13 +393,26 @@ impl MLCoordinator {
         Some(())
     }
 
+    // Records |header| for the run of the model at |idx| that just
+    // completed and stamps it so clients can tell the output is new.
+    fn stamp_output(&mut self, idx: ModelIdx, header: OutputHeader) {
+        let now_us = timer_now_us();
+        let model = self.models[idx].as_mut().unwrap();
+        model.output_header = Some(header);
+        model.output_stamp = self.generations.stamp(self.run_started_us, now_us);
+    }
+
     // Sets up a loadable model for |id|, returning the index of that model.
+    // Completions are reported with |job_id| or, if None, the default
+    // job id for the index (see job_id_or_default).
     fn ready_model(
         &mut self,
         client_id: usize,
         id: ImageId,
         rate_in_ms: Option<u32>,
+        job_id: Option<MlJobId>,
     ) -> Result<ModelIdx, MlCoordError> {
+        check_job_id(job_id)?;
         // Return NoModelSlotsLeft if all slots are full.
         let index = self
             .models
@@ -288,11 +423,39 @@ impl MLCoordinator {
         // NB: use size_buffer to check the model exists.
         let _ = cantrip_security_size_buffer(&id.model_id).or(Err(MlCoordError::InvalidImage))?;
 
-        self.models[index] = Some(LoadableModel::new(id, rate_in_ms, client_id));
+        let job_id = job_id_or_default(job_id, index);
+        self.models[index] = Some(LoadableModel::new(id, rate_in_ms, client_id, job_id));
 
         Ok(index)
     }
 
+    // Returns the index of the loaded model |id| after switching it to
+    // |job_id| (if given); otherwise sets up a model with ready_model.
+    fn find_or_ready_model(
+        &mut self,
+        client_id: usize,
+        id: ImageId,
+        rate_in_ms: Option<u32>,
+        job_id: Option<MlJobId>,
+    ) -> Result<ModelIdx, MlCoordError> {
+        match self.get_model_index(&id) {
+            Some(idx) => {
+                check_job_id(job_id)?;
+                let model = self.models[idx].as_mut().unwrap();
+                if let Some(job_id) = job_id {
+                    model.job_id = job_id;
+                }
+                if rate_in_ms.is_some() {
+                    // Force the timer duration in case the image was loaded as a oneshot
+                    // XXX if was periodic is there a timer running that needs to be canceled?
+                    model.rate_in_ms = rate_in_ms;
+                }
+                Ok(idx)
+            }
+            None => self.ready_model(client_id, id, rate_in_ms, job_id),
+        }
+    }
+
     // Returns the index for model |id|, if it exists.
     fn get_model_index(&self, id: &ImageId) -> Option<ModelIdx> {
         self.models.iter().position(|opti| {
@@ -305,12 +468,14 @@ impl MLCoordinator {
     }
 
     /// Starts a one-time model execution, to happen immediately.
-    pub fn oneshot(&mut self, client_id: usize, id: ImageId) -> Result<(), MlCoordError> {
+    pub fn oneshot(
+        &mut self,
+        client_id: usize,
+        id: ImageId,
+        job_id: Option<MlJobId>,
+    ) -> Result<(), MlCoordError> {
         // Check if we've loaded this model already.
-        let idx = match self.get_model_index(&id) {
-            Some(idx) => idx,
-            None => self.ready_model(client_id, id, None)?,
-        };
+        let idx = self.find_or_ready_model(client_id, id, None, job_id)?;
 
         self.execution_queue.push(idx);
         self.schedule_next_model()?;
@@ -325,18 +490,11 @@ impl MLCoordinator {
         client_id: usize,
         id: ImageId,
         rate_in_ms: u32,
+        job_id: Option<MlJobId>,
     ) -> Result<(), MlCoordError> {
         // XXX mucks with model state before we are assured of succcess
         // Check if we've loaded this model already.
-        let idx = match self.get_model_index(&id) {
-            Some(idx) => {
-                // Force the timer duration in case the image was loaded as a oneshot
-                // XXX if was periodic is there a timer running that needs to be canceled?
-                self.models[idx].as_mut().unwrap().rate_in_ms = Some(rate_in_ms);
-                idx
-            }
-            None => self.ready_model(client_id, id, Some(rate_in_ms))?,
-        };
+        let idx = self.find_or_ready_model(client_id, id, Some(rate_in_ms), job_id)?;
 
         match cantrip_timer_periodic(idx as TimerId, rate_in_ms) {
             Ok(_) => {
@@ -380,9 +538,120 @@ impl MLCoordinator {
         }
 
         self.image_manager.unload_image(id);
-        self.completed_job_mask |= 1 << model_idx;
+        let model = self.models[model_idx].as_ref().unwrap();
+        self.completed_jobs.complete(model.job_id);
 
+        let paused = model.paused;
         self.models[model_idx] = None;
+
+        if paused && self.is_running(id) {
+            // The core was frozen by pause; reset it and move on.
+            MlCore::reset();
+            MlCore::freeze(false);
+            self.running_model = None;
+            self.schedule_next_model()?;
+        }
+        Ok(())
+    }
+
+    /// Moves the loaded model |id| to |to_bundle| without reloading it.
+    /// The model keeps its TCM allocation, timer, staged input and last
+    /// output; afterwards only |to_bundle| can run or query it and
+    /// completions are reported with |job_id| (or, if None, the model's
+    /// default job id) as for a newly readied model. Only the client that readied
+    /// the model (|client_id|) may transfer it; to other clients it
+    /// appears not to exist.
+    pub fn transfer(
+        &mut self,
+        client_id: usize,
+        id: &ImageId,
+        to_bundle: &str,
+        job_id: Option<MlJobId>,
+    ) -> Result<(), MlCoordError> {
+        check_job_id(job_id)?;
+        let model_idx = self
+            .get_model_index(id)
+            .filter(|idx| self.models[*idx].as_ref().unwrap().client_id == client_id)
+            .ok_or(MlCoordError::NoSuchModel)?;
+        let to = ImageId {
+            bundle_id: to_bundle.to_string(),
+            model_id: id.model_id.clone(),
+        };
+        if self.get_model_index(&to).is_some() {
+            return Err(MlCoordError::ModelAlreadyLoaded);
+        }
+
+        // NB: the image may have been evicted in which case it is
+        //   reloaded from the original bundle on the next run.
+        if self.image_manager.is_loaded(id) {
+            // An image left loaded for |to| (e.g. by a canceled model)
+            // is stale; drop it so the transferred image takes its place.
+            self.image_manager.unload_image(&to);
+            if !self.image_manager.rename_image(id, to.clone()) {
+                error!("Transfer {} to {}: rename failed", id, to_bundle);
+                return Err(MlCoordError::LoadModelFailed);
+            }
+        }
+        if self.is_running(id) {
+            self.running_model = Some(to.clone());
+        }
+        info!("Transfer {} to {}", id, to_bundle);
+        let model = self.models[model_idx].as_mut().unwrap();
+        let source = model.image_bundle_id().to_string();
+        model.loaded_from = (source != to_bundle).then_some(source);
+        model.id = to;
+        // Completions are now reported with the new owner's job id.
+        model.job_id = job_id_or_default(job_id, model_idx);
+        Ok(())
+    }
+
+    // Returns true if |id| is the model currently running on the core.
+    fn is_running(&self, id: &ImageId) -> bool { self.running_model.as_ref() == Some(id) }
+
+    /// Pauses periodic execution of a model. The model is left loaded
+    /// with any input data intact so a resume does not require a reload.
+    /// If the model is running the core is frozen until resumed (or the
+    /// model is canceled); no other model runs while the core is frozen.
+    pub fn pause(&mut self, id: &ImageId) -> Result<(), MlCoordError> {
+        let model_idx = self.get_model_index(id).ok_or(MlCoordError::NoSuchModel)?;
+        let model = self.models[model_idx].as_mut().unwrap();
+        if model.rate_in_ms.is_none() {
+            return Err(MlCoordError::InvalidModelState);
+        }
+        if model.paused {
+            return Ok(());
+        }
+        model.paused = true;
+
+        if let Err(e) = cantrip_timer_cancel(model_idx as TimerId) {
+            warn!("Cancel timer {} failed: {:?}", model_idx, e);
+        }
+        self.execution_queue.retain(|idx| *idx != model_idx);
+        if self.is_running(id) {
+            MlCore::freeze(true);
+        }
+        Ok(())
+    }
+
+    /// Resumes periodic execution of a model paused with |pause|.
+    pub fn resume(&mut self, id: &ImageId) -> Result<(), MlCoordError> {
+        let model_idx = self.get_model_index(id).ok_or(MlCoordError::NoSuchModel)?;
+        let model = self.models[model_idx].as_ref().unwrap();
+        if !model.paused {
+            return Ok(());
+        }
+        let rate_in_ms = model.rate_in_ms.ok_or(MlCoordError::InvalidModelState)?;
+        if let Err(e) = cantrip_timer_periodic(model_idx as TimerId, rate_in_ms) {
+            error!(
+                "cantrip_timer_periodic({}, {}) returns {:?}",
+                model_idx, rate_in_ms, e
+            );
+            return Err(MlCoordError::InvalidTimer);
+        }
+        if self.is_running(id) {
+            MlCore::freeze(false);
+        }
+        self.models[model_idx].as_mut().unwrap().paused = false;
         Ok(())
     }
 
@@ -396,6 +665,10 @@ impl MLCoordinator {
             }
         };
         if let Some(model) = &self.models[model_idx] {
+            if model.paused {
+                // Timer fired before the pause took effect; ignore.
+                return Ok(());
+            }
             // Model is still loaded; schedule it for execution.
             if is_running_model(model) {
                 warn!("Model {} is running slower than periodic timer.", &model.id);
@@ -415,22 +688,23 @@ impl MLCoordinator {
         Ok(())
     }
 
-    pub fn completed_jobs(&mut self) -> u32 {
-        // XXX restrict mask to client jobs
-        let mask = self.completed_job_mask;
-        self.completed_job_mask = 0;
-        mask as u32
+    /// Returns all completed jobs and resets them; clients that share
+    /// the MlCoordinator use completed_jobs_masked.
+    pub fn completed_jobs(&mut self) -> MlJobMask { self.completed_jobs.take() }
+
+    /// Returns the completed jobs in |interested|; only those are reset.
+    pub fn completed_jobs_masked(&mut self, interested: MlJobMask) -> MlJobMask {
+        self.completed_jobs.take_masked(interested)
     }
 
     pub fn get_input_params(
         &mut self,
         client_id: usize,
         id: ImageId,
+        job_id: Option<MlJobId>,
     ) -> Result<MlInput, MlCoordError> {
         // Load model as needed.
-        if self.get_model_index(&id).is_none() {
-            self.ready_model(client_id, id, None)?;
-        }
+        self.find_or_ready_model(client_id, id, None, job_id)?;
         let (input_ptr, input_size_bytes) = MlCore::get_input_params()?;
         Ok(MlInput {
             input_ptr,
@@ -438,15 +712,71 @@ impl MLCoordinator {
         })
     }
 
+    /// Stages input data for the next run of model |id|. Input may be
+    /// written while a run of the model is in flight; it is written to
+    /// the model's input region when the next run starts. If |crc| is
+    /// given the data are staged only if they match it and the next run
+    /// starts only if the staged bytes still match.
     pub fn set_input(
         &mut self,
         id: &ImageId,
         input_data_offset: u32,
         input_data: &[u8],
+        crc: Option<u32>,
     ) -> Result<(), MlCoordError> {
-        // NB: model must be loaded so the header is in TCM
-        let _ = self.get_model_index(id).ok_or(MlCoordError::NoSuchModel)?;
-        MlCore::set_input_data(input_data_offset as usize, input_data)
+        let idx = self.get_model_index(id).ok_or(MlCoordError::NoSuchModel)?;
+        if crc.is_some() && crc != Some(input_checksum(input_data)) {
+            warn!("{}: input at {} fails checksum", id, input_data_offset);
+            return Err(MlCoordError::InputChecksumMismatch);
+        }
+        let (_, input_size_bytes) = MlCore::get_input_params()?;
+        let model = self.models[idx].as_mut().unwrap();
+        let offset = input_data_offset as usize;
+        model
+            .input
+            .write(input_size_bytes as usize, offset, input_data)
+            .map_err(input_error)?;
+        if let Some(crc) = crc {
+            model
+                .input
+                .expect(offset..offset + input_data.len(), crc)
+                .map_err(input_error)?;
+        }
+        Ok(())
+    }
+
+    /// Reads back input for model |id| at |input_data_offset| into |out|;
+    /// this includes input staged for the next run.
+    pub fn get_input(
+        &self,
+        id: &ImageId,
+        input_data_offset: u32,
+        out: &mut [u8],
+    ) -> Result<(), MlCoordError> {
+        let idx = self.get_model_index(id).ok_or(MlCoordError::NoSuchModel)?;
+        let (_, input_size_bytes) = MlCore::get_input_params()?;
+        let model = self.models[idx].as_ref().unwrap();
+        if !model
+            .input
+            .read(input_size_bytes as usize, input_data_offset as usize, out)
+        {
+            return Err(MlCoordError::InvalidInputRange);
+        }
+        Ok(())
+    }
+
+    /// Checks |crc| against the input staged for model |id|; see
+    /// InputBuffer::checksum for what is covered. On success the next
+    /// run starts only if the staged input still matches |crc|.
+    pub fn verify_input(&mut self, id: &ImageId, crc: u32) -> Result<(), MlCoordError> {
+        let idx = self.get_model_index(id).ok_or(MlCoordError::NoSuchModel)?;
+        let input = &mut self.models[idx].as_mut().unwrap().input;
+        let staged = input.checksum();
+        if staged != crc {
+            warn!("{}: input checksum {:#x} expected {:#x}", id, staged, crc);
+            return Err(MlCoordError::InputChecksumMismatch);
+        }
+        input.expect(0..input.extent(), crc).map_err(input_error)
     }
 
     pub fn get_output(&mut self, id: &ImageId) -> Result<MlOutput, MlCoordError> {
@@ -455,12 +785,57 @@ impl MLCoordinator {
         let header = model.output_header.ok_or(MlCoordError::NoOutputHeader)?;
         Ok(MlOutput {
             jobnum: model.jobnum,
+            generation: model.output_stamp.generation,
+            produced_at_us: model.output_stamp.produced_at_us,
+            elapsed_us: model.output_stamp.elapsed_us,
             return_code: header.return_code,
             epc: header.epc,
             data: model.output_data,
         })
     }
 
+    /// Copies output of model |id| at |offset| into |out| and returns
+    /// the number of bytes copied; see OutputBuffer for how output is
+    /// kept across reads and released by |clear|. Output that was dropped
+    /// for lack of memory is reported as OutOfMemory.
+    pub fn get_output_range(
+        &mut self,
+        id: &ImageId,
+        offset: u32,
+        out: &mut [u8],
+        clear: bool,
+    ) -> Result<usize, MlCoordError> {
+        let idx = self.get_model_index(id).ok_or(MlCoordError::NoSuchModel)?;
+        let model = self.models[idx].as_mut().unwrap();
+        if model.output.is_empty() && model.output_header.is_none() {
+            return Err(MlCoordError::NoOutputHeader);
+        }
+        model
+            .output
+            .read(offset as usize, out, clear)
+            .ok_or(MlCoordError::OutOfMemory)
+    }
+
+    /// Returns TCM usage and the footprint of each loaded model.
+    pub fn memory_stats(&self) -> MlMemoryStats {
+        let usage = self.image_manager.tcm_usage();
+        MlMemoryStats {
+            total_bytes: usage.total,
+            enabled_bytes: MlCore::tcm_enabled_size(),
+            used_bytes: usage.used,
+            free_bytes: usage.free,
+            models: self
+                .image_manager
+                .image_footprints()
+                .map(|(id, size_bytes)| MlModelMemory {
+                    bundle_id: id.bundle_id.clone(),
+                    model_id: id.model_id.clone(),
+                    size_bytes,
+                })
+                .collect(),
+        }
+    }
+
     // This interrupt shouldn't occur,
     // so panic if it does.
     pub fn handle_host_req_interrupt(&self) {
@@ -513,3 +888,271 @@ impl MLCoordinator {
         self.image_manager.debug_state();
     }
 }
+
+impl Diagnostics for MLCoordinator {
+    fn diagnostics(&self) -> DiagnosticsReport {
+        let mut report = DiagnosticsReport::new("mlcoord");
+        report
+            .add("running", self.running_model.is_some() as u64)
+            .add("models", self.models.iter().flatten().count() as u64)
+            .add("queued", self.execution_queue.len() as u64)
+            .add("completed_mask", self.completed_jobs.pending())
+            .add("jobnum", self.jobnum as u64)
+            .add("load_failures", self.statistics.load_failures as u64)
+            .add("already_queued", self.statistics.already_queued as u64)
+            .add("already_running", self.statistics.already_running as u64);
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+
+    // Timers are owned by the TimerService; tests track which are armed.
+    std::thread_local! {
+        static ARMED: RefCell<BTreeMap<TimerId, TimerDuration>> = RefCell::new(BTreeMap::new());
+    }
+    fn armed(timer_id: TimerId) -> Option<TimerDuration> {
+        ARMED.with(|armed| armed.borrow().get(&timer_id).copied())
+    }
+    pub fn cantrip_timer_periodic(
+        timer_id: TimerId,
+        duration_in_ms: TimerDuration,
+    ) -> Result<(), TimerServiceError> {
+        ARMED.with(|armed| armed.borrow_mut().insert(timer_id, duration_in_ms));
+        Ok(())
+    }
+    pub fn cantrip_timer_cancel(timer_id: TimerId) -> Result<(), TimerServiceError> {
+        ARMED
+            .with(|armed| armed.borrow_mut().remove(&timer_id))
+            .map(|_| ())
+            .ok_or(TimerServiceError::NoSuchTimer)
+    }
+
+    // The clock is set by tests; None if it cannot be read.
+    std::thread_local! {
+        static NOW_US: RefCell<Option<u64>> = RefCell::new(None);
+    }
+    fn set_now(now_us: Option<u64>) { NOW_US.with(|now| *now.borrow_mut() = now_us); }
+    pub fn cantrip_timer_now() -> Result<core::time::Duration, TimerServiceError> {
+        NOW_US
+            .with(|now| *now.borrow())
+            .map(core::time::Duration::from_micros)
+            .ok_or(TimerServiceError::UnknownError)
+    }
+
+    // Every model exists as far as the SecurityCoordinator is concerned.
+    pub fn cantrip_security_size_buffer(_bundle_id: &str) -> Result<usize, SecurityRequestError> {
+        Ok(0)
+    }
+
+    fn image_id(bundle_id: &str, model_id: &str) -> ImageId {
+        ImageId {
+            bundle_id: bundle_id.to_string(),
+            model_id: model_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn transfer_changes_owner() {
+        let mut coordinator = MLCoordinator::new();
+        let a = image_id("A", "model");
+        let b = image_id("B", "model");
+        // NB: ready_model checks the image exists with the SecurityCoordinator
+        coordinator.models[2] = Some(LoadableModel::new(a.clone(), None, 1, 5));
+        // NB: with another model on the core runs are only queued.
+        coordinator.running_model = Some(image_id("C", "busy"));
+
+        // Another client cannot take the model.
+        assert_eq!(
+            coordinator.transfer(2, &a, "B", Some(7)),
+            Err(MlCoordError::NoSuchModel)
+        );
+
+        // B has the model and its completions are reported with B's job id.
+        assert_eq!(coordinator.transfer(1, &a, "B", Some(7)), Ok(()));
+        assert_eq!(coordinator.get_model_index(&a), None);
+        assert_eq!(coordinator.get_model_index(&b), Some(2));
+        let model = coordinator.models[2].as_ref().unwrap();
+        assert_eq!((model.client_id, model.job_id), (1, 7));
+        assert_eq!(model.image_bundle_id(), "A");
+
+        // B runs the transferred model without reloading it.
+        assert_eq!(coordinator.oneshot(1, b.clone(), Some(7)), Ok(()));
+        assert_eq!(coordinator.execution_queue, [2]);
+
+        // A can no longer use it: a run by A readies a model of its own
+        // and the transferred one is left untouched.
+        assert_eq!(coordinator.transfer(1, &a, "C", None), Err(MlCoordError::NoSuchModel));
+        assert_eq!(coordinator.pause(&a), Err(MlCoordError::NoSuchModel));
+        assert_eq!(coordinator.oneshot(1, a.clone(), None), Ok(()));
+        assert_eq!(coordinator.get_model_index(&a), Some(0));
+        assert_eq!(coordinator.execution_queue, [2, 0]);
+        assert_eq!(coordinator.models[2].as_ref().unwrap().id, b);
+
+        assert_eq!(
+            coordinator.transfer(1, &b, "D", Some(MAX_JOB_ID + 1)),
+            Err(MlCoordError::InvalidJobId)
+        );
+        // Handing it on without a job id reports by model index.
+        assert_eq!(coordinator.transfer(1, &b, "D", None), Ok(()));
+        let model = coordinator.models[2].as_ref().unwrap();
+        assert_eq!(model.job_id, DEFAULT_JOB_ID_BASE + 2);
+        assert_eq!(model.image_bundle_id(), "A");
+    }
+
+    #[test]
+    fn pause_resume_periodic() {
+        let mut coordinator = MLCoordinator::new();
+        let a = image_id("A", "periodic");
+        let b = image_id("B", "oneshot");
+        coordinator.models[0] = Some(LoadableModel::new(a.clone(), Some(100), 1, 1));
+        coordinator.models[1] = Some(LoadableModel::new(b.clone(), None, 2, 2));
+        assert_eq!(cantrip_timer_periodic(0, 100), Ok(()));
+        // NB: with |b| on the core expired timers only queue their model.
+        coordinator.running_model = Some(b.clone());
+
+        assert_eq!(coordinator.timer_completed(0), Ok(()));
+        assert_eq!(coordinator.execution_queue, [0]);
+
+        // Pausing drops the queued run and stops the timer.
+        assert_eq!(coordinator.pause(&a), Ok(()));
+        assert!(coordinator.models[0].as_ref().unwrap().paused);
+        assert!(coordinator.execution_queue.is_empty());
+        assert_eq!(armed(0), None);
+        assert_eq!(coordinator.pause(&a), Ok(()));
+
+        // A timer that fired before the pause took effect is ignored.
+        assert_eq!(coordinator.timer_completed(0), Ok(()));
+        assert!(coordinator.execution_queue.is_empty());
+
+        // Resuming re-arms the timer at the original rate; the model
+        // is still in its slot so runs continue without a reload.
+        assert_eq!(coordinator.resume(&a), Ok(()));
+        assert!(!coordinator.models[0].as_ref().unwrap().paused);
+        assert_eq!(armed(0), Some(100));
+        assert_eq!(coordinator.timer_completed(0), Ok(()));
+        assert_eq!(coordinator.execution_queue, [0]);
+        assert_eq!(coordinator.get_model_index(&a), Some(0));
+        assert_eq!(coordinator.resume(&a), Ok(()));
+    }
+
+    #[test]
+    fn pause_resume_errors() {
+        let mut coordinator = MLCoordinator::new();
+        let a = image_id("A", "oneshot");
+        coordinator.models[0] = Some(LoadableModel::new(a.clone(), None, 1, 1));
+
+        // Only periodic models can be paused.
+        assert_eq!(coordinator.pause(&a), Err(MlCoordError::InvalidModelState));
+        assert!(!coordinator.models[0].as_ref().unwrap().paused);
+        assert_eq!(coordinator.resume(&a), Ok(()));
+        assert_eq!(armed(0), None);
+
+        let missing = image_id("A", "missing");
+        assert_eq!(coordinator.pause(&missing), Err(MlCoordError::NoSuchModel));
+        assert_eq!(coordinator.resume(&missing), Err(MlCoordError::NoSuchModel));
+    }
+
+    #[test]
+    fn input_checked_at_run_start() {
+        let mut coordinator = MLCoordinator::new();
+        let a = image_id("A", "model");
+        coordinator.models[0] = Some(LoadableModel::new(a.clone(), None, 1, 1));
+        let input = &mut coordinator.models[0].as_mut().unwrap().input;
+        assert_eq!(input.write(16, 0, &[1, 2, 3]), Ok(()));
+        assert_eq!(input.expect(0..3, input_checksum(&[1, 2, 3])), Ok(()));
+        // A later write clobbers the checked input.
+        assert_eq!(input.write(16, 1, &[9]), Ok(()));
+
+        assert_eq!(
+            coordinator.oneshot(1, a.clone(), None),
+            Err(MlCoordError::InputChecksumMismatch)
+        );
+        assert_eq!(coordinator.running_model, None);
+        assert!(coordinator.models[0].as_ref().unwrap().input.is_pending());
+
+        // A failed verify leaves the check in place; a good one
+        // replaces it.
+        assert_eq!(
+            coordinator.verify_input(&a, input_checksum(&[1, 2, 3])),
+            Err(MlCoordError::InputChecksumMismatch)
+        );
+        assert!(!coordinator.models[0].as_ref().unwrap().input.verify());
+        assert_eq!(coordinator.verify_input(&a, input_checksum(&[1, 9, 3])), Ok(()));
+        assert!(coordinator.models[0].as_ref().unwrap().input.verify());
+    }
+
+    #[test]
+    fn output_stamped_on_completion() {
+        let mut coordinator = MLCoordinator::new();
+        let a = image_id("A", "model");
+        let b = image_id("B", "model");
+        coordinator.models[0] = Some(LoadableModel::new(a.clone(), None, 1, 1));
+        coordinator.models[1] = Some(LoadableModel::new(b.clone(), None, 2, 2));
+        let header = |return_code| OutputHeader {
+            return_code,
+            output_ptr: None,
+            output_length: 0,
+            epc: None,
+        };
+        assert_eq!(coordinator.get_output(&a).err(), Some(MlCoordError::NoOutputHeader));
+
+        // Run |a| twice; the second result is newer than the first.
+        coordinator.run_started_us = 400;
+        set_now(Some(1_000));
+        coordinator.stamp_output(0, header(0));
+        let first = coordinator.get_output(&a).unwrap();
+        assert_eq!(first.generation, 1);
+        assert_eq!(first.produced_at_us, 1_000);
+        assert_eq!(first.elapsed_us, 600);
+
+        coordinator.run_started_us = 2_000;
+        set_now(Some(2_500));
+        coordinator.stamp_output(0, header(3));
+        let second = coordinator.get_output(&a).unwrap();
+        assert!(second.generation > first.generation);
+        assert_eq!(second.produced_at_us, 2_500);
+        assert_eq!(second.elapsed_us, 500);
+        assert_eq!(second.return_code, 3);
+
+        // A run of another model advances the generation but leaves the
+        // output of |a| as it was; without a clock only the time is lost.
+        set_now(None);
+        coordinator.stamp_output(1, header(0));
+        let other = coordinator.get_output(&b).unwrap();
+        assert!(other.generation > second.generation);
+        assert_eq!((other.produced_at_us, other.elapsed_us), (0, 0));
+        assert_eq!(coordinator.get_output(&a).unwrap().generation, second.generation);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut coordinator = MLCoordinator::new();
+        let report = coordinator.diagnostics();
+        assert_eq!(report.component, "mlcoord");
+        assert_eq!(report.get("running"), Some(0));
+        assert_eq!(report.get("models"), Some(0));
+        assert_eq!(report.get("queued"), Some(0));
+
+        let a = image_id("A", "periodic");
+        let b = image_id("B", "oneshot");
+        coordinator.models[0] = Some(LoadableModel::new(a, Some(100), 1, 1));
+        coordinator.models[1] = Some(LoadableModel::new(b.clone(), None, 2, 2));
+        // NB: with |b| on the core expired timers only queue their model.
+        coordinator.running_model = Some(b);
+        assert_eq!(coordinator.timer_completed(0), Ok(()));
+        assert_eq!(coordinator.timer_completed(0), Ok(()));
+        assert_eq!(coordinator.timer_completed(1), Ok(()));
+        let report = coordinator.diagnostics();
+        assert_eq!(report.get("running"), Some(1));
+        assert_eq!(report.get("models"), Some(2));
+        assert_eq!(report.get("queued"), Some(2));
+        assert_eq!(report.get("already_queued"), Some(1));
+        assert_eq!(report.get("already_running"), Some(1));
+        assert_eq!(report.get("load_failures"), Some(0));
+    }
+}
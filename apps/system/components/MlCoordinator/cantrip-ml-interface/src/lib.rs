@@ -13,7 +13,11 @@
 // limitations under the License.
 
 #![no_std]
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
 use cantrip_os_common::camkes;
+use cantrip_os_common::diagnostics::DiagnosticsReport;
 use cantrip_os_common::sel4_sys;
 use log::trace;
 use num_enum::{FromPrimitive, IntoPrimitive};
@@ -27,16 +31,22 @@ use sel4_sys::seL4_NBWait;
 use sel4_sys::seL4_Wait;
 
 pub type MlJobId = u32;
-pub type MlJobMask = u32;
+pub type MlJobMask = u64;
 
 use serde_big_array::big_array;
 big_array! { BigArray; }
 
 pub const MAX_OUTPUT_DATA: usize = 128;
+// Output kept for cantrip_mlcoord_get_output_range; any more is dropped.
+// NB: output is copied to the MlCoordinator heap; see HEAP_SIZE there.
+pub const MAX_OUTPUT_SIZE: usize = 2 * 1024;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MlOutput {
-    pub jobnum: usize, // unique value per model run
+    pub jobnum: usize,       // unique value per model run
+    pub generation: u64,     // advances with each completed run
+    pub produced_at_us: u64, // TimerService clock when the run completed
+    pub elapsed_us: u32,     // Run time; 0 if it could not be measured
     pub return_code: u32,
     pub epc: Option<u32>, // NB: Springbok only
     #[serde(with = "BigArray")]
@@ -57,6 +67,29 @@ pub struct MlInput {
     pub input_size_bytes: u32,
 }
 
+/// TCM used by a loaded model. This covers the sections private to the
+/// model (text, constant data, model output & static data); temporary
+/// data are shared by all loaded models.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MlModelMemory {
+    pub bundle_id: String,
+    pub model_id: String,
+    pub size_bytes: usize,
+}
+
+/// TCM usage. |used_bytes| includes the sensor frames and the temporary
+/// data shared by loaded models so |used_bytes| + |free_bytes| is
+/// |total_bytes|. |enabled_bytes| is the TCM in memory banks that are
+/// currently powered on.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MlMemoryStats {
+    pub total_bytes: usize,
+    pub enabled_bytes: usize,
+    pub used_bytes: usize,
+    pub free_bytes: usize,
+    pub models: Vec<MlModelMemory>,
+}
+
 /// Errors that can occur when interacting with the MlCoordinator.
 #[repr(usize)]
 #[derive(Debug, Default, Eq, PartialEq, FromPrimitive, IntoPrimitive)]
@@ -73,6 +106,12 @@ pub enum MlCoordError {
     #[default]
     UnknownError,
     InvalidInputRange,
+    InvalidModelState,
+    ModelAlreadyLoaded,
+    InputChecksumMismatch,
+    InvalidJobId,
+    OutOfMemory,
+    InputNotSupported, // Vector core has no model input region
 }
 impl From<MlCoordError> for Result<(), MlCoordError> {
     fn from(err: MlCoordError) -> Result<(), MlCoordError> {
@@ -90,20 +129,44 @@ pub enum MlCoordRequest<'a> {
     // Outstanding completed jobs are reset to 0 during this call.
     CompletedJobs, // -> MlJobMask
 
+    // |job_id| names the model in the completed job mask; if None the
+    // MlCoordinator picks one (see cantrip_mlcoord_oneshot).
     Oneshot {
         bundle_id: &'a str,
         model_id: &'a str,
+        job_id: Option<MlJobId>,
     },
     Periodic {
         bundle_id: &'a str,
         model_id: &'a str,
         rate_in_ms: u32,
+        job_id: Option<MlJobId>,
     },
     Cancel {
         bundle_id: &'a str,
         model_id: &'a str,
     },
 
+    // Stops scheduling a periodic model but keeps it loaded (along with
+    // any input data) so it can be quickly resumed.
+    Pause {
+        bundle_id: &'a str,
+        model_id: &'a str,
+    },
+    // Re-arms a paused periodic model.
+    Resume {
+        bundle_id: &'a str,
+        model_id: &'a str,
+    },
+
+    // Hands a loaded model to another bundle without reloading it.
+    TransferModel {
+        from_bundle: &'a str,
+        to_bundle: &'a str,
+        model_id: &'a str,
+        job_id: Option<MlJobId>,
+    },
+
     // Returns the relevant OutputHeader & and any indirect data.
     GetOutput {
         // -> MlOutput
@@ -116,18 +179,59 @@ pub enum MlCoordRequest<'a> {
         // -> MlInput
         bundle_id: &'a str,
         model_id: &'a str,
+        job_id: Option<MlJobId>,
     },
 
-    // Sets/writes input data.
+    // Sets/writes input data. The optional |crc| is the CRC-32 (IEEE)
+    // of |input_data|; if it does not match nothing is written. It is
+    // checked again against the staged data when the next run starts.
     SetInput {
         bundle_id: &'a str,
         model_id: &'a str,
         input_data_offset: u32,
         input_data: &'a [u8],
+        crc: Option<u32>,
     },
 
     DebugState,
     Capscan,
+    Diagnostics, // -> DiagnosticsResponse
+    Heartbeat,   // -> HeartbeatResponse
+    MemoryStats, // -> MemoryStatsResponse
+
+    // Reads back input data.
+    GetInput {
+        // -> GetInputResponse
+        bundle_id: &'a str,
+        model_id: &'a str,
+        input_data_offset: u32,
+        len: u32,
+    },
+
+    // Checks |crc| against the input staged for the next run; on success
+    // it is checked again when the run starts.
+    VerifyInput {
+        bundle_id: &'a str,
+        model_id: &'a str,
+        crc: u32,
+    },
+
+    // Reads back output data past what GetOutput returns.
+    GetOutputRange {
+        // -> GetOutputRangeResponse
+        bundle_id: &'a str,
+        model_id: &'a str,
+        offset: u32,
+        len: u32,
+        clear: bool,
+    },
+
+    // Like CompletedJobs but returns and resets only the jobs in
+    // |interested|; other completed jobs remain pending.
+    CompletedJobsMasked {
+        // -> MlJobMask
+        interested: MlJobMask,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -145,6 +249,31 @@ pub struct GetInputParamsResponse {
     pub input: MlInput,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetInputResponse {
+    pub input_data: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetOutputRangeResponse {
+    pub output_data: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiagnosticsResponse {
+    pub report: DiagnosticsReport,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeartbeatResponse {
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemoryStatsResponse {
+    pub stats: MlMemoryStats,
+}
+
 // NB: selected s.t. MlOutput (MAX_OUTPUT_DATA) + MlInput (MAX_INPUT_DATA) work
 pub const MLCOORD_REQUEST_DATA_SIZE: usize = rpc_shared::RPC_BUFFER_SIZE_BYTES / 2;
 
@@ -166,24 +295,38 @@ fn cantrip_mlcoord_request<T: DeserializeOwned>(
     }
 }
 
+/// Runs the specified model once. Completion of the run sets bit
+/// |job_id| in the mask returned by cantrip_mlcoord_completed_jobs; if
+/// |job_id| is None the bit is chosen by the MlCoordinator above any
+/// job id a client may choose ([0..=31]) so they cannot collide. The job id
+/// is kept while the model stays loaded; a later request with a
+/// different job id replaces it.
 #[inline]
-pub fn cantrip_mlcoord_oneshot(bundle_id: &str, model_id: &str) -> Result<(), MlCoordError> {
+pub fn cantrip_mlcoord_oneshot(
+    bundle_id: &str,
+    model_id: &str,
+    job_id: Option<MlJobId>,
+) -> Result<(), MlCoordError> {
     cantrip_mlcoord_request(&MlCoordRequest::Oneshot {
         bundle_id,
         model_id,
+        job_id,
     })
 }
 
+/// Like cantrip_mlcoord_oneshot but the model is run every |rate_in_ms|.
 #[inline]
 pub fn cantrip_mlcoord_periodic(
     bundle_id: &str,
     model_id: &str,
     rate_in_ms: u32,
+    job_id: Option<MlJobId>,
 ) -> Result<(), MlCoordError> {
     cantrip_mlcoord_request(&MlCoordRequest::Periodic {
         bundle_id,
         model_id,
         rate_in_ms,
+        job_id,
     })
 }
 
@@ -195,6 +338,47 @@ pub fn cantrip_mlcoord_cancel(bundle_id: &str, model_id: &str) -> Result<(), MlC
     })
 }
 
+/// Pauses periodic execution of a model. The model remains loaded and
+/// any input data is retained; use cantrip_mlcoord_resume to restart it.
+#[inline]
+pub fn cantrip_mlcoord_pause(bundle_id: &str, model_id: &str) -> Result<(), MlCoordError> {
+    cantrip_mlcoord_request(&MlCoordRequest::Pause {
+        bundle_id,
+        model_id,
+    })
+}
+
+/// Resumes periodic execution of a model paused with cantrip_mlcoord_pause.
+#[inline]
+pub fn cantrip_mlcoord_resume(bundle_id: &str, model_id: &str) -> Result<(), MlCoordError> {
+    cantrip_mlcoord_request(&MlCoordRequest::Resume {
+        bundle_id,
+        model_id,
+    })
+}
+
+/// Moves the loaded model |model_id| from |from_bundle| to |to_bundle|
+/// (e.g. from a pipeline producer to its consumer). The model's TCM
+/// allocation, staged input and last output move with it so nothing is
+/// reloaded. Afterwards the model is known only by |to_bundle|; it is an
+/// error if |to_bundle| already has |model_id| loaded. Only the client
+/// that readied the model may transfer it. Completions are reported to
+/// the caller with |job_id| as for cantrip_mlcoord_oneshot.
+#[inline]
+pub fn cantrip_mlcoord_transfer_model(
+    from_bundle: &str,
+    to_bundle: &str,
+    model_id: &str,
+    job_id: Option<MlJobId>,
+) -> Result<(), MlCoordError> {
+    cantrip_mlcoord_request(&MlCoordRequest::TransferModel {
+        from_bundle,
+        to_bundle,
+        model_id,
+        job_id,
+    })
+}
+
 /// Returns a bitmask of job id's registered with cantrip_mlcoord_oneshot
 /// and cantrip_mlcoord_periodic that have expired.
 #[inline]
@@ -203,6 +387,17 @@ pub fn cantrip_mlcoord_completed_jobs() -> Result<MlJobMask, MlCoordError> {
         .map(|reply: CompleteJobsResponse| reply.job_mask)
 }
 
+/// Returns the jobs in |interested| that have completed; unlike
+/// cantrip_mlcoord_completed_jobs, completed jobs outside |interested|
+/// are left pending so another caller may collect them.
+#[inline]
+pub fn cantrip_mlcoord_completed_jobs_masked(
+    interested: MlJobMask,
+) -> Result<MlJobMask, MlCoordError> {
+    cantrip_mlcoord_request(&MlCoordRequest::CompletedJobsMasked { interested })
+        .map(|reply: CompleteJobsResponse| reply.job_mask)
+}
+
 /// Returns the OutputHeader & indirect data for the specified job.
 #[inline]
 pub fn cantrip_mlcoord_get_output(
@@ -216,15 +411,18 @@ pub fn cantrip_mlcoord_get_output(
     .map(|reply: GetOutputResponse| reply.output)
 }
 
-/// Returns the input parameters for the specified job.
+/// Returns the input parameters for the specified job. The model is
+/// loaded if need be with |job_id| as for cantrip_mlcoord_oneshot.
 #[inline]
 pub fn cantrip_mlcoord_get_input_params(
     bundle_id: &str,
     model_id: &str,
+    job_id: Option<MlJobId>,
 ) -> Result<MlInput, MlCoordError> {
     cantrip_mlcoord_request(&MlCoordRequest::GetInputParams {
         bundle_id,
         model_id,
+        job_id,
     })
     .map(|reply: GetInputParamsResponse| reply.input)
 }
@@ -234,23 +432,98 @@ pub fn cantrip_mlcoord_get_input_params(
 /// cantrip_mlcoord_get_input_params. It is an error to write data that
 /// do not fit entirely in the input data area. Input data are initially
 /// written as part of loading a model and can be written many times
-/// before a job is run (e.g. to write data piecemeal). Writing input
-/// data while a job is running is undefined.
+/// before a job is run (e.g. to write data piecemeal). Input data are
+/// staged and handed to the model when the next job starts so the input
+/// for the next job may be written while a job is running. If |crc| is
+/// given it must be the CRC-32 (IEEE) of |input_data| otherwise nothing
+/// is written and InputChecksumMismatch is returned; the next job is not
+/// started if the staged data no longer match |crc| (e.g. they were
+/// partly overwritten without a new checksum).
 #[inline]
 pub fn cantrip_mlcoord_set_input(
     bundle_id: &str,
     model_id: &str,
     input_data_offset: u32,
     input_data: &[u8],
+    crc: Option<u32>,
 ) -> Result<(), MlCoordError> {
     cantrip_mlcoord_request(&MlCoordRequest::SetInput {
         bundle_id,
         model_id,
         input_data_offset,
         input_data,
+        crc,
+    })
+}
+
+/// Reads back |len| bytes of the input data area for the specified job
+/// starting at |input_data_offset|. The data returned include any input
+/// staged for the next job (see cantrip_mlcoord_set_input); bytes never
+/// written read as 0. This is meant for checking input is formatted
+/// correctly.
+#[inline]
+pub fn cantrip_mlcoord_get_input(
+    bundle_id: &str,
+    model_id: &str,
+    input_data_offset: u32,
+    len: u32,
+) -> Result<Vec<u8>, MlCoordError> {
+    cantrip_mlcoord_request(&MlCoordRequest::GetInput {
+        bundle_id,
+        model_id,
+        input_data_offset,
+        len,
+    })
+    .map(|reply: GetInputResponse| reply.input_data)
+}
+
+/// Checks the input staged for the specified job against |crc|, the
+/// CRC-32 (IEEE) of the input data from the start of the input data
+/// area to the end of the furthest byte written (unwritten gaps count
+/// as 0). Returns InputChecksumMismatch if they disagree, e.g. because
+/// a writer died part way through staging the input. On success the
+/// check is repeated when the next job starts and the job is not run
+/// (InputChecksumMismatch) if the staged input changed in between.
+#[inline]
+pub fn cantrip_mlcoord_verify_input(
+    bundle_id: &str,
+    model_id: &str,
+    crc: u32,
+) -> Result<(), MlCoordError> {
+    cantrip_mlcoord_request(&MlCoordRequest::VerifyInput {
+        bundle_id,
+        model_id,
+        crc,
     })
 }
 
+/// Reads up to |len| bytes of the output of the specified job starting
+/// at |offset|; this is for output too large for the data returned by
+/// cantrip_mlcoord_get_output. The first read pins the output of the
+/// last completed run so later reads see the same output even if more
+/// runs complete. Pass |clear| on the final read to release it. Fewer
+/// than |len| bytes (possibly none) are returned at the end of the
+/// output. Output past MAX_OUTPUT_SIZE is not kept. Output the
+/// MlCoordinator had no memory for is reported as OutOfMemory. At most
+/// MAX_OUTPUT_SIZE bytes may be read at once.
+#[inline]
+pub fn cantrip_mlcoord_get_output_range(
+    bundle_id: &str,
+    model_id: &str,
+    offset: u32,
+    len: u32,
+    clear: bool,
+) -> Result<Vec<u8>, MlCoordError> {
+    cantrip_mlcoord_request(&MlCoordRequest::GetOutputRange {
+        bundle_id,
+        model_id,
+        offset,
+        len,
+        clear,
+    })
+    .map(|reply: GetOutputRangeResponse| reply.output_data)
+}
+
 /// Waits for the next pending job for the client. If a job completes
 /// the associated job id is returned.
 #[inline]
@@ -277,6 +550,32 @@ pub fn cantrip_mlcoord_poll() -> Result<MlJobMask, MlCoordError> {
     cantrip_mlcoord_completed_jobs()
 }
 
+/// Like cantrip_mlcoord_wait but only jobs in |interested| are returned
+/// and reset; see cantrip_mlcoord_completed_jobs_masked.
+#[inline]
+pub fn cantrip_mlcoord_wait_masked(interested: MlJobMask) -> Result<MlJobMask, MlCoordError> {
+    unsafe {
+        extern "Rust" {
+            static MLCOORD_INTERFACE_NOTIFICATION: seL4_CPtr;
+        }
+        seL4_Wait(MLCOORD_INTERFACE_NOTIFICATION, core::ptr::null_mut());
+    }
+    cantrip_mlcoord_completed_jobs_masked(interested)
+}
+
+/// Like cantrip_mlcoord_poll but only jobs in |interested| are returned
+/// and reset; see cantrip_mlcoord_completed_jobs_masked.
+#[inline]
+pub fn cantrip_mlcoord_poll_masked(interested: MlJobMask) -> Result<MlJobMask, MlCoordError> {
+    unsafe {
+        extern "Rust" {
+            static MLCOORD_INTERFACE_NOTIFICATION: seL4_CPtr;
+        }
+        seL4_NBWait(MLCOORD_INTERFACE_NOTIFICATION, core::ptr::null_mut());
+    }
+    cantrip_mlcoord_completed_jobs_masked(interested)
+}
+
 #[inline]
 pub fn cantrip_mlcoord_debug_state() {
     let _ = cantrip_mlcoord_request::<()>(&MlCoordRequest::DebugState);
@@ -286,3 +585,22 @@ pub fn cantrip_mlcoord_debug_state() {
 pub fn cantrip_mlcoord_capscan() -> Result<(), MlCoordError> {
     cantrip_mlcoord_request(&MlCoordRequest::Capscan)
 }
+
+#[inline]
+pub fn cantrip_mlcoord_diagnostics() -> Result<DiagnosticsReport, MlCoordError> {
+    cantrip_mlcoord_request(&MlCoordRequest::Diagnostics)
+        .map(|reply: DiagnosticsResponse| reply.report)
+}
+
+#[inline]
+pub fn cantrip_mlcoord_heartbeat() -> Result<u64, MlCoordError> {
+    cantrip_mlcoord_request(&MlCoordRequest::Heartbeat).map(|reply: HeartbeatResponse| reply.count)
+}
+
+/// Returns TCM usage and the footprint of each loaded model; e.g. to
+/// check whether another model will fit without evicting one.
+#[inline]
+pub fn cantrip_mlcoord_memory_stats() -> Result<MlMemoryStats, MlCoordError> {
+    cantrip_mlcoord_request(&MlCoordRequest::MemoryStats)
+        .map(|reply: MemoryStatsResponse| reply.stats)
+}
@@ -93,6 +93,61 @@ bitflags::bitflags! {
     }
 }
 
+/// Reasons a WMMU window permission is rejected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PermissionError {
+    NoAccess,       // No access granted
+    WriteOnly,      // Write granted without read
+    WritableText,   // Instruction window writable but not executable
+    ExecutableData, // Data window executable
+}
+
+impl Permission {
+    /// Checks the permission makes sense for |window|. Only the text
+    /// window holds instructions; every other window is data.
+    pub fn check(&self, window: WindowId) -> Result<(), PermissionError> {
+        if self.is_empty() {
+            return Err(PermissionError::NoAccess);
+        }
+        if self.contains(Permission::WRITE) && !self.contains(Permission::READ) {
+            return Err(PermissionError::WriteOnly);
+        }
+        match window {
+            WindowId::Text => {
+                if self.contains(Permission::WRITE) && !self.contains(Permission::EXECUTE) {
+                    return Err(PermissionError::WritableText);
+                }
+            }
+            _ => {
+                if self.contains(Permission::EXECUTE) {
+                    return Err(PermissionError::ExecutableData);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds a validated WMMU window permission, e.g.
+/// PermissionBuilder::new().read().write().build(WindowId::StaticData).
+#[derive(Clone, Copy, Debug)]
+pub struct PermissionBuilder(Permission);
+impl PermissionBuilder {
+    pub fn new() -> Self { Self(Permission::empty()) }
+
+    pub fn read(self) -> Self { Self(self.0 | Permission::READ) }
+    pub fn write(self) -> Self { Self(self.0 | Permission::WRITE) }
+    pub fn execute(self) -> Self { Self(self.0 | Permission::EXECUTE) }
+
+    /// Returns the permission if it is legal for |window|.
+    pub fn build(self, window: WindowId) -> Result<Permission, PermissionError> {
+        self.0.check(window).map(|_| self.0)
+    }
+}
+impl Default for PermissionBuilder {
+    fn default() -> Self { Self::new() }
+}
+
 /// After execution our ML executable populates the top of .model_output with
 /// the return code, the address of the fault if the RC is non-zero, and the
 /// length of the output that follows.
@@ -111,3 +166,55 @@ pub fn round_up(a: usize, b: usize) -> usize {
         usize::checked_add(a, b).unwrap() - (a % b)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA_WINDOWS: [WindowId; 5] = [
+        WindowId::ConstData,
+        WindowId::ModelOutput,
+        WindowId::StaticData,
+        WindowId::ModelInput,
+        WindowId::TempData,
+    ];
+
+    #[test]
+    fn valid_permissions() {
+        let b = PermissionBuilder::new();
+        assert_eq!(b.read().execute().build(WindowId::Text), Ok(Permission::READ_EXECUTE));
+        assert_eq!(
+            b.read().write().execute().build(WindowId::Text),
+            Ok(Permission::READ_WRITE | Permission::EXECUTE)
+        );
+        for window in DATA_WINDOWS {
+            assert_eq!(b.read().build(window), Ok(Permission::READ));
+            assert_eq!(b.write().read().build(window), Ok(Permission::READ_WRITE));
+        }
+    }
+
+    #[test]
+    fn invalid_permissions() {
+        let b = PermissionBuilder::new();
+        assert_eq!(b.build(WindowId::Text), Err(PermissionError::NoAccess));
+        assert_eq!(b.write().build(WindowId::StaticData), Err(PermissionError::WriteOnly));
+        assert_eq!(
+            b.read().write().build(WindowId::Text),
+            Err(PermissionError::WritableText)
+        );
+        for window in DATA_WINDOWS {
+            assert_eq!(b.build(window), Err(PermissionError::NoAccess));
+            assert_eq!(b.read().execute().build(window), Err(PermissionError::ExecutableData));
+        }
+    }
+
+    #[test]
+    fn image_manager_permissions() {
+        // Permissions used to map each section of an image are legal.
+        assert_eq!(Permission::READ_EXECUTE.check(WindowId::Text), Ok(()));
+        assert_eq!(Permission::READ.check(WindowId::ConstData), Ok(()));
+        assert_eq!(Permission::READ_WRITE.check(WindowId::ModelOutput), Ok(()));
+        assert_eq!(Permission::READ_WRITE.check(WindowId::StaticData), Ok(()));
+        assert_eq!(Permission::READ_WRITE.check(WindowId::TempData), Ok(()));
+    }
+}
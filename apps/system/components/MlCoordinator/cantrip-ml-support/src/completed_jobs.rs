@@ -0,0 +1,98 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Completed job bookkeeping.
+//!
+//! Each loaded model has a job id that names it in the mask of completed
+//! jobs. A client may choose the job id when it loads a model (e.g. the
+//! SDKRuntime uses the model id it hands the application); otherwise the
+//! model's slot in the MlCoordinator, offset by DEFAULT_JOB_ID_BASE, is
+//! used. The two ranges are disjoint so a client that lets the slot name
+//! its job (e.g. the shell) cannot collect another client's completions.
+//! A completed run sets the bit for the model's job id until a client
+//! collects it.
+
+/// Job id's chosen by a client are in [0..=MAX_JOB_ID].
+pub const MAX_JOB_ID: u32 = u32::BITS - 1;
+/// Models loaded without a job id use this plus their slot.
+pub const DEFAULT_JOB_ID_BASE: u32 = MAX_JOB_ID + 1;
+
+/// Mask of completed jobs not yet collected.
+#[derive(Debug, Default)]
+pub struct CompletedJobs {
+    mask: u64,
+}
+impl CompletedJobs {
+    pub const fn new() -> Self { CompletedJobs { mask: 0 } }
+
+    /// Records a completed run of job |job_id|.
+    pub fn complete(&mut self, job_id: u32) {
+        assert!(job_id < u64::BITS);
+        self.mask |= 1 << job_id;
+    }
+
+    /// Returns the completed jobs without collecting them.
+    pub fn pending(&self) -> u64 { self.mask }
+
+    /// Returns and collects all completed jobs.
+    pub fn take(&mut self) -> u64 { core::mem::take(&mut self.mask) }
+
+    /// Returns and collects the completed jobs in |interested|; others
+    /// are left for another client.
+    pub fn take_masked(&mut self, interested: u64) -> u64 {
+        let mask = self.mask & interested;
+        self.mask &= !mask;
+        mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_collects() {
+        let mut jobs = CompletedJobs::new();
+        assert_eq!(jobs.take(), 0);
+        jobs.complete(3);
+        jobs.complete(MAX_JOB_ID);
+        jobs.complete(3); // NB: runs are not counted
+        assert_eq!(jobs.pending(), (1 << 3) | (1 << MAX_JOB_ID));
+        assert_eq!(jobs.take(), (1 << 3) | (1 << MAX_JOB_ID));
+        assert_eq!(jobs.take(), 0);
+    }
+
+    #[test]
+    fn take_masked_leaves_others() {
+        let mut jobs = CompletedJobs::new();
+        jobs.complete(1);
+        jobs.complete(2);
+        jobs.complete(5);
+        assert_eq!(jobs.take_masked((1 << 2) | (1 << 4)), 1 << 2);
+        assert_eq!(jobs.take_masked(1 << 4), 0);
+        assert_eq!(jobs.pending(), (1 << 1) | (1 << 5));
+        assert_eq!(jobs.take(), (1 << 1) | (1 << 5));
+    }
+
+    #[test]
+    fn default_jobs_are_separate() {
+        let mut jobs = CompletedJobs::new();
+        // Slot 3's default job does not complete client job 3.
+        jobs.complete(DEFAULT_JOB_ID_BASE + 3);
+        assert_eq!(jobs.take_masked(u32::MAX as u64), 0);
+        jobs.complete(3);
+        assert_eq!(jobs.take_masked(1 << 3), 1 << 3);
+        assert_eq!(jobs.take(), 1 << (DEFAULT_JOB_ID_BASE + 3));
+    }
+}
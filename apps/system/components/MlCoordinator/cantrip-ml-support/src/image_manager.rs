@@ -47,7 +47,7 @@ extern crate alloc;
 use alloc::vec::Vec;
 use cantrip_ml_shared::*;
 use core::cmp;
-use log::{info, trace};
+use log::{error, info, trace};
 
 #[cfg(test)]
 use fake_vec_core as MlCore;
@@ -90,6 +90,15 @@ struct Image {
 
 pub type ImageIdx = usize;
 
+/// TCM usage in bytes. |used| includes the sensor frames and the shared
+/// temporary data.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct TcmUsage {
+    pub total: usize,
+    pub used: usize,
+    pub free: usize,
+}
+
 // ImageManager tracks three pointers into TCM:
 //                   +---------------+
 //                   |               |
@@ -160,6 +169,25 @@ impl ImageManager {
 
     fn tcm_free_space(&self) -> usize { TCM_SIZE - self.tcm_top_size() - self.tcm_bottom_size() }
 
+    /// Returns the current TCM usage.
+    pub fn tcm_usage(&self) -> TcmUsage {
+        let free = self.tcm_free_space();
+        TcmUsage {
+            total: TCM_SIZE,
+            used: TCM_SIZE - free,
+            free,
+        }
+    }
+
+    /// Returns the loaded images and the TCM bytes private to each.
+    pub fn image_footprints(&self) -> impl Iterator<Item = (&ImageId, usize)> {
+        self.image_queue.iter().filter_map(|idx| {
+            self.images[*idx]
+                .as_ref()
+                .map(|image| (&image.id, image.sizes.data_top_size()))
+        })
+    }
+
     // Returns the size of the largest temporary data block of loaded images.
     fn required_temporary_data(&self) -> usize {
         self.images
@@ -245,12 +273,14 @@ impl ImageManager {
         self.tcm_bottom = TCM_PADDR + TCM_SIZE - temp_data_size;
 
         #[cfg(feature = "springbok_support")]
-        MlCore::set_wmmu_window(
+        if let Err(e) = MlCore::set_wmmu_window(
             WindowId::TempData,
             self.tcm_bottom,
             temp_data_size,
             Permission::READ_WRITE,
-        );
+        ) {
+            error!("Set temporary data window failed: {:?}", e);
+        }
     }
 
     // Returns the index for image |id| if it exists.
@@ -309,55 +339,54 @@ impl ImageManager {
         false
     }
 
+    /// Reassigns the loaded image |from| to |to| in place; nothing is
+    /// moved or reloaded. Returns true if |from| was loaded and |to|
+    /// was not.
+    pub fn rename_image(&mut self, from: &ImageId, to: ImageId) -> bool {
+        if self.get_image_index(&to).is_some() {
+            return false;
+        }
+        if let Some(idx) = self.get_image_index(from) {
+            trace!("Rename image {} -> {}", from, &to);
+            self.images[idx].as_mut().unwrap().id = to;
+            return true;
+        }
+
+        false
+    }
+
     /// Sets the WMMU to match the loaded image |id|. Returns true if that
     /// image exists and the WMMU was set.
     #[cfg(feature = "springbok_support")]
     pub fn set_wmmu(&self, id: &ImageId) -> bool {
-        if let Some(idx) = self.get_image_index(id) {
-            let image = &self.images[idx].as_ref().unwrap();
-
-            let mut top = image.data_top_addr;
-
-            MlCore::set_wmmu_window(
-                WindowId::Text,
-                top,
-                image.sizes.text,
-                Permission::READ_EXECUTE,
-            );
-            top += image.sizes.text;
-
-            MlCore::set_wmmu_window(
-                WindowId::ConstData,
-                top,
-                image.sizes.constant_data,
-                Permission::READ,
-            );
-            top += image.sizes.constant_data;
-
-            MlCore::set_wmmu_window(
+        let image = match self.get_image_index(id) {
+            Some(idx) => self.images[idx].as_ref().unwrap(),
+            None => return false,
+        };
+        let windows = [
+            (WindowId::Text, image.sizes.text, Permission::READ_EXECUTE),
+            (WindowId::ConstData, image.sizes.constant_data, Permission::READ),
+            (
                 WindowId::ModelOutput,
-                top,
                 image.sizes.model_output,
                 Permission::READ_WRITE,
-            );
-            top += image.sizes.model_output;
-
-            MlCore::set_wmmu_window(
-                WindowId::StaticData,
-                top,
-                image.sizes.static_data,
-                Permission::READ_WRITE,
-            );
-
-            // TODO(jesionowski): Set model_input window when sensor manager
-            // is integrated.
-
-            // NB: TEMP_DATA_WINDOW is set in set_tcm_bottom.
-
-            return true;
+            ),
+            (WindowId::StaticData, image.sizes.static_data, Permission::READ_WRITE),
+        ];
+        let mut top = image.data_top_addr;
+        for (window_id, size, permission) in windows {
+            if let Err(e) = MlCore::set_wmmu_window(window_id, top, size, permission) {
+                error!("{}: set window {:?} failed: {:?}", id, window_id, e);
+                return false;
+            }
+            top += size;
         }
 
-        false
+        // TODO(jesionowski): Set model_input window when sensor manager
+        // is integrated.
+
+        // NB: TEMP_DATA_WINDOW is set in set_tcm_bottom.
+        true
     }
 
     /// Zeroes out the temporary data section.
@@ -468,6 +497,36 @@ mod test {
         assert!(!image_manager.unload_image(&default_id()));
     }
 
+    // Load a model for one bundle and rename it to another. The image
+    // stays where it is and only the new id finds it.
+    #[test]
+    fn rename() {
+        let mut image_manager = ImageManager::new();
+        let sizes = constant_image_size(0x1000);
+        let from = default_id();
+        let to = ImageId {
+            bundle_id: "C".to_string(),
+            model_id: from.model_id.clone(),
+        };
+        load_image(&mut image_manager, from.clone(), sizes);
+        let usage = image_manager.tcm_usage();
+
+        assert!(image_manager.rename_image(&from, to.clone()));
+        assert!(image_manager.is_loaded(&to));
+        assert!(!image_manager.is_loaded(&from));
+        assert_eq!(image_manager.tcm_usage(), usage);
+        assert_eq!(
+            image_manager.image_footprints().collect::<Vec<_>>(),
+            [(&to, sizes.data_top_size())]
+        );
+
+        // Nothing to rename and the target must not already be loaded.
+        assert!(!image_manager.rename_image(&from, to.clone()));
+        load_image(&mut image_manager, from.clone(), sizes);
+        assert!(!image_manager.rename_image(&from, to.clone()));
+        assert!(image_manager.is_loaded(&from));
+    }
+
     // Image that fills half the TCM. Zero temporary data in order to only
     // test tcm_top accounting.
     fn half_image() -> ImageSizes {
@@ -494,6 +553,32 @@ mod test {
         }
     }
 
+    // Load a model and check the TCM usage grows by the model's size.
+    #[test]
+    fn tcm_usage() {
+        let mut image_manager = ImageManager::new();
+        image_manager.allocate_sensor_input(0x1000);
+        let before = image_manager.tcm_usage();
+        assert_eq_hex!(before.used, 0x1000);
+        assert_eq_hex!(before.used + before.free, TCM_SIZE);
+
+        let sizes = constant_image_size(0x1000);
+        load_image(&mut image_manager, default_id(), sizes);
+        let after = image_manager.tcm_usage();
+        assert_eq_hex!(after.used, before.used + sizes.data_top_size() + sizes.temporary_data);
+        assert_eq_hex!(after.free, before.free - (after.used - before.used));
+        assert_eq!(
+            image_manager.image_footprints().collect::<Vec<_>>(),
+            [(&default_id(), sizes.data_top_size())]
+        );
+
+        // Unloading returns the space. NB: the temporary data window is
+        // never shrunk to zero so it remains in use.
+        assert!(image_manager.unload_image(&default_id()));
+        assert_eq!(image_manager.image_footprints().count(), 0);
+        assert_eq_hex!(image_manager.tcm_usage().used, before.used + sizes.temporary_data);
+    }
+
     // Load two models that fit into the TCM and a third that forces an unload
     // of the second model. Then, load a 4th that unloads the others.
     #[test]
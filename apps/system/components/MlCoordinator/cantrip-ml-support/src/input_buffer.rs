@@ -0,0 +1,328 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Double-buffered model input.
+//!
+//! A model reads its input from a fixed region in TCM (the active buffer)
+//! that must not change while a run is in flight. Input written by a
+//! client is collected in a staging buffer instead so the next input can
+//! be prepared while the current run executes. The staged bytes are
+//! swapped into the active buffer when the next run starts.
+//!
+//! Clients may check what is staged with a CRC-32 (IEEE) of the input
+//! written so far; see InputBuffer::checksum. Checksums registered with
+//! InputBuffer::expect are re-checked against the staged bytes when the
+//! next run starts (see InputBuffer::verify) so input changed after it
+//! was checked (e.g. by a writer that died mid-staging) is not used.
+
+extern crate alloc;
+use alloc::vec::Vec;
+use core::ops::Range;
+use crc::crc32;
+
+/// Returns the CRC-32 (IEEE) of |data| as used to check input.
+pub fn input_checksum(data: &[u8]) -> u32 { crc32::checksum_ieee(data) }
+
+/// Errors from staging input.
+#[derive(Debug, Eq, PartialEq)]
+pub enum InputError {
+    OutOfRange, // Data do not fit in the input region
+    NoMemory,   // Staging buffer could not be allocated
+}
+
+#[derive(Debug, Default)]
+pub struct InputBuffer {
+    staged: Vec<u8>,
+    // Disjoint, non-adjacent ranges written since the last swap.
+    dirty: Vec<Range<usize>>,
+    // End of the furthest byte ever written.
+    extent: usize,
+    // Checksums the staged bytes must match when the next run starts.
+    checks: Vec<(Range<usize>, u32)>,
+}
+impl InputBuffer {
+    pub const fn new() -> Self {
+        InputBuffer {
+            staged: Vec::new(),
+            dirty: Vec::new(),
+            extent: 0,
+            checks: Vec::new(),
+        }
+    }
+
+    /// Stages |data| at |offset| for the next run. |capacity| is the size
+    /// of the model's input region. Nothing is staged on error.
+    pub fn write(&mut self, capacity: usize, offset: usize, data: &[u8]) -> Result<(), InputError> {
+        let end = match offset.checked_add(data.len()) {
+            Some(end) if end <= capacity => end,
+            _ => return Err(InputError::OutOfRange),
+        };
+        if data.is_empty() {
+            return Ok(());
+        }
+        if self.staged.len() < capacity {
+            self.staged
+                .try_reserve_exact(capacity - self.staged.len())
+                .or(Err(InputError::NoMemory))?;
+            self.staged.resize(capacity, 0);
+        }
+        // NB: reserve for the merged range up front so nothing fails
+        //   after the data are copied
+        self.dirty.try_reserve(1).or(Err(InputError::NoMemory))?;
+        self.staged[offset..end].copy_from_slice(data);
+        self.extent = self.extent.max(end);
+
+        // Merge with any ranges the write overlaps or abuts; piecemeal
+        // writes of a whole input collapse to a single range.
+        let mut written = offset..end;
+        self.dirty.retain(|r| {
+            if r.start <= written.end && written.start <= r.end {
+                written = written.start.min(r.start)..written.end.max(r.end);
+                false
+            } else {
+                true
+            }
+        });
+        self.dirty.push(written);
+        Ok(())
+    }
+
+    /// Reads back input at |offset| into |out|. This is what the model
+    /// sees once any staged input is swapped in; bytes never written
+    /// read as 0. Returns false if the range is outside |capacity|.
+    pub fn read(&self, capacity: usize, offset: usize, out: &mut [u8]) -> bool {
+        let end = match offset.checked_add(out.len()) {
+            Some(end) if end <= capacity => end,
+            _ => return false,
+        };
+        let written = end.min(self.staged.len()).saturating_sub(offset);
+        out[..written].copy_from_slice(&self.staged[offset..offset + written]);
+        out[written..].fill(0);
+        true
+    }
+
+    /// Returns the checksum (see input_checksum) of the input from the
+    /// start of the region to the end of the furthest byte written; gaps
+    /// never written count as 0. This is what a client that wrote its
+    /// input from offset 0 (in one piece or many) computes over its data.
+    pub fn checksum(&self) -> u32 { input_checksum(&self.staged[..self.extent]) }
+
+    /// Requires the staged bytes in |range| to match |crc| (see
+    /// input_checksum) when the next run starts. Checks of ranges that
+    /// |range| covers are replaced; so input rewritten after it was
+    /// checked must be checked again.
+    pub fn expect(&mut self, range: Range<usize>, crc: u32) -> Result<(), InputError> {
+        if range.end > self.staged.len() {
+            return Err(InputError::OutOfRange);
+        }
+        self.checks
+            .retain(|(r, _)| !(range.start <= r.start && r.end <= range.end));
+        self.checks.try_reserve(1).or(Err(InputError::NoMemory))?;
+        self.checks.push((range, crc));
+        Ok(())
+    }
+
+    /// Returns true if the staged input matches every checksum
+    /// registered with expect since the last swap.
+    pub fn verify(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|(r, crc)| input_checksum(&self.staged[r.clone()]) == *crc)
+    }
+
+    /// Returns the end of the furthest byte written; this is the extent
+    /// covered by checksum.
+    pub fn extent(&self) -> usize { self.extent }
+
+    /// Returns true if input is staged for the next run.
+    pub fn is_pending(&self) -> bool { !self.dirty.is_empty() }
+
+    /// Hands over staged input at the start of a run. Returns the offset
+    /// in the active buffer and the bytes to write there for each region
+    /// written since the last swap. Bytes not written keep their value
+    /// from the previous run.
+    pub fn swap(&mut self) -> impl Iterator<Item = (usize, &[u8])> + '_ {
+        self.checks.clear();
+        let dirty = core::mem::take(&mut self.dirty);
+        let staged = &self.staged;
+        dirty.into_iter().map(move |r| (r.start, &staged[r]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CAPACITY: usize = 16;
+
+    // Simulates starting a run: swaps staged input into |active|.
+    fn start_run(input: &mut InputBuffer, active: &mut [u8; CAPACITY]) {
+        for (offset, data) in input.swap() {
+            active[offset..offset + data.len()].copy_from_slice(data);
+        }
+    }
+
+    #[test]
+    fn write_out_of_range() {
+        let mut input = InputBuffer::new();
+        assert_eq!(input.write(CAPACITY, CAPACITY, &[1]), Err(InputError::OutOfRange));
+        assert_eq!(input.write(CAPACITY, 8, &[1; 9]), Err(InputError::OutOfRange));
+        assert_eq!(input.write(CAPACITY, usize::MAX, &[1]), Err(InputError::OutOfRange));
+        assert_eq!(input.write(0, 0, &[1]), Err(InputError::OutOfRange));
+        assert!(!input.is_pending());
+        // Empty writes that fit are accepted but stage nothing.
+        assert_eq!(input.write(CAPACITY, CAPACITY, &[]), Ok(()));
+        assert!(!input.is_pending());
+    }
+
+    #[test]
+    fn write_no_memory() {
+        let mut input = InputBuffer::new();
+        // A staging buffer that cannot be allocated is an error, not an abort.
+        let huge = isize::MAX as usize + 1;
+        assert_eq!(input.write(huge, 0, &[1]), Err(InputError::NoMemory));
+        assert!(!input.is_pending());
+        assert_eq!(input.write(CAPACITY, 0, &[1]), Ok(()));
+        assert!(input.is_pending());
+    }
+
+    #[test]
+    fn next_run_uses_staged_input() {
+        let mut input = InputBuffer::new();
+        let mut active = [0u8; CAPACITY];
+
+        // Buffer A is staged and picked up by the first run.
+        assert_eq!(input.write(CAPACITY, 0, &[0xa; CAPACITY]), Ok(()));
+        start_run(&mut input, &mut active);
+        assert_eq!(active, [0xa; CAPACITY]);
+        assert!(!input.is_pending());
+
+        // Buffer B is written while the run using A is in flight; the
+        // active buffer is untouched.
+        assert_eq!(input.write(CAPACITY, 0, &[0xb; CAPACITY]), Ok(()));
+        assert!(input.is_pending());
+        assert_eq!(active, [0xa; CAPACITY]);
+
+        // The next run uses B.
+        start_run(&mut input, &mut active);
+        assert_eq!(active, [0xb; CAPACITY]);
+
+        // With nothing staged a run reuses the previous input.
+        start_run(&mut input, &mut active);
+        assert_eq!(active, [0xb; CAPACITY]);
+    }
+
+    #[test]
+    fn read_back() {
+        let mut input = InputBuffer::new();
+        let mut out = [0xffu8; 4];
+        // Nothing written yet.
+        assert!(input.read(CAPACITY, 0, &mut out));
+        assert_eq!(out, [0; 4]);
+
+        assert_eq!(input.write(CAPACITY, 4, &[1, 2, 3, 4]), Ok(()));
+        assert!(input.read(CAPACITY, 4, &mut out));
+        assert_eq!(out, [1, 2, 3, 4]);
+        // Reads spanning the start & end of the written data.
+        assert!(input.read(CAPACITY, 2, &mut out));
+        assert_eq!(out, [0, 0, 1, 2]);
+        assert!(input.read(CAPACITY, 6, &mut out));
+        assert_eq!(out, [3, 4, 0, 0]);
+
+        // Staged input reads the same after it is swapped in.
+        let mut active = [0u8; CAPACITY];
+        start_run(&mut input, &mut active);
+        let mut all = [0u8; CAPACITY];
+        assert!(input.read(CAPACITY, 0, &mut all));
+        assert_eq!(all, active);
+
+        assert!(!input.read(CAPACITY, CAPACITY - 3, &mut out));
+        assert!(!input.read(CAPACITY, usize::MAX, &mut out));
+        assert!(input.read(CAPACITY, CAPACITY, &mut []));
+    }
+
+    #[test]
+    fn piecemeal_writes() {
+        let mut input = InputBuffer::new();
+        // Active buffer holds the input written when the model was loaded.
+        let mut active = [0xff; CAPACITY];
+
+        assert_eq!(input.write(CAPACITY, 2, &[1, 2]), Ok(()));
+        assert_eq!(input.write(CAPACITY, 8, &[3]), Ok(()));
+        assert_eq!(input.write(CAPACITY, 3, &[4]), Ok(()));
+        assert_eq!(input.write(CAPACITY, 4, &[5]), Ok(()));
+        start_run(&mut input, &mut active);
+        assert_eq!(active[..10], [0xff, 0xff, 1, 4, 5, 0xff, 0xff, 0xff, 3, 0xff]);
+
+        // Unwritten bytes keep their value from the previous run.
+        assert_eq!(input.write(CAPACITY, 9, &[6]), Ok(()));
+        start_run(&mut input, &mut active);
+        assert_eq!(active[..10], [0xff, 0xff, 1, 4, 5, 0xff, 0xff, 0xff, 3, 6]);
+    }
+    #[test]
+    fn checksum() {
+        let mut input = InputBuffer::new();
+        assert_eq!(input.checksum(), input_checksum(&[]));
+        assert_eq!(input_checksum(b"123456789"), 0xcbf43926);
+
+        // Piecemeal writes checksum the same as one write of the whole.
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        assert_eq!(input.write(CAPACITY, 4, &data[4..]), Ok(()));
+        assert_eq!(input.write(CAPACITY, 0, &data[..4]), Ok(()));
+        assert_eq!(input.checksum(), input_checksum(&data));
+
+        // A swap does not change what is covered.
+        let mut active = [0u8; CAPACITY];
+        start_run(&mut input, &mut active);
+        assert_eq!(input.checksum(), input_checksum(&data));
+
+        // A partial overwrite is caught.
+        assert_eq!(input.write(CAPACITY, 2, &[0xff]), Ok(()));
+        assert_ne!(input.checksum(), input_checksum(&data));
+
+        // Unwritten gaps count as 0.
+        let mut input = InputBuffer::new();
+        assert_eq!(input.write(CAPACITY, 2, &[1]), Ok(()));
+        assert_eq!(input.checksum(), input_checksum(&[0, 0, 1]));
+    }
+
+    #[test]
+    fn verify_at_run_start() {
+        let mut input = InputBuffer::new();
+        let mut active = [0u8; CAPACITY];
+        assert!(input.verify());
+
+        // A checked piece and a check of the whole input.
+        assert_eq!(input.write(CAPACITY, 4, &[1, 2, 3, 4]), Ok(()));
+        assert_eq!(input.expect(4..8, input_checksum(&[1, 2, 3, 4])), Ok(()));
+        assert_eq!(input.write(CAPACITY, 0, &[5, 6, 7, 8]), Ok(()));
+        assert_eq!(input.expect(0..8, input.checksum()), Ok(()));
+        assert!(input.verify());
+
+        // Overwriting checked input is caught until it is checked again.
+        assert_eq!(input.write(CAPACITY, 6, &[0xff]), Ok(()));
+        assert!(!input.verify());
+        assert_eq!(input.expect(4..8, input_checksum(&[1, 2, 0xff, 4])), Ok(()));
+        assert!(!input.verify()); // NB: the whole-input check still applies
+        assert_eq!(input.expect(0..8, input.checksum()), Ok(()));
+        assert!(input.verify());
+
+        // Checks apply to the next run only.
+        start_run(&mut input, &mut active);
+        assert_eq!(input.write(CAPACITY, 6, &[0]), Ok(()));
+        assert!(input.verify());
+
+        assert_eq!(input.expect(0..CAPACITY + 1, 0), Err(InputError::OutOfRange));
+    }
+}
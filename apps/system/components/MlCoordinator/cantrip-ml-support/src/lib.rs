@@ -14,4 +14,8 @@
 
 #![no_std]
 
+pub mod completed_jobs;
 pub mod image_manager;
+pub mod input_buffer;
+pub mod output_buffer;
+pub mod output_stamp;
@@ -0,0 +1,153 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Model output kept for range reads.
+//!
+//! Output larger than what fits in MlOutput is read a window at a time.
+//! A client paging through the output of one run must not see the next
+//! run's output part way through so the first range read pins the
+//! output of the last completed run. Later reads come from the pinned
+//! copy until the client clears it; runs that complete meanwhile replace
+//! only the unpinned copy. Once cleared a run's output is gone; the next
+//! read sees the output of a later run, if any.
+//!
+//! Output that could not be kept (e.g. there was no memory for it) is
+//! recorded as lost so a read that would pin it fails rather than
+//! returning nothing.
+
+extern crate alloc;
+use alloc::vec::Vec;
+use core::cmp;
+
+#[derive(Debug, Default)]
+pub struct OutputBuffer {
+    latest: Vec<u8>,         // Output of the last completed run
+    pinned: Option<Vec<u8>>, // Output being read by the client
+    lost: bool,              // Output of the last completed run was dropped
+}
+impl OutputBuffer {
+    pub const fn new() -> Self {
+        OutputBuffer {
+            latest: Vec::new(),
+            pinned: None,
+            lost: false,
+        }
+    }
+
+    /// Replaces the output of the last completed run with |data|.
+    pub fn set(&mut self, data: Vec<u8>) {
+        self.latest = data;
+        self.lost = false;
+    }
+
+    /// Records that the output of the last completed run was dropped.
+    pub fn set_lost(&mut self) {
+        self.latest = Vec::new();
+        self.lost = true;
+    }
+
+    /// Copies output at |offset| into |out|, pinning the last completed
+    /// run's output if it is not already. Returns the number of bytes
+    /// copied; this is short (possibly 0) at the end of the output. With
+    /// |clear| the pinned output is released after the copy. Returns None
+    /// if the output to pin was lost (see set_lost).
+    pub fn read(&mut self, offset: usize, out: &mut [u8], clear: bool) -> Option<usize> {
+        if self.pinned.is_none() && self.lost {
+            return None;
+        }
+        let latest = &mut self.latest;
+        let pinned = self.pinned.get_or_insert_with(|| core::mem::take(latest));
+        let data = pinned.get(offset..).unwrap_or(&[]);
+        let count = cmp::min(out.len(), data.len());
+        out[..count].copy_from_slice(&data[..count]);
+        if clear {
+            self.pinned = None;
+        }
+        Some(count)
+    }
+
+    /// Returns true if there is no output to read.
+    pub fn is_empty(&self) -> bool {
+        match &self.pinned {
+            Some(pinned) => pinned.is_empty(),
+            None => self.latest.is_empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn output(len: u8) -> Vec<u8> { (0..len).collect() }
+
+    #[test]
+    fn read_in_pieces() {
+        let mut buf = OutputBuffer::new();
+        assert!(buf.is_empty());
+        buf.set(output(200));
+        assert!(!buf.is_empty());
+
+        let mut out = [0u8; 128];
+        assert_eq!(buf.read(0, &mut out, false), Some(128));
+        assert_eq!(out[..], output(128)[..]);
+        assert_eq!(buf.read(128, &mut out, false), Some(72));
+        assert_eq!(out[..72], output(200)[128..]);
+        // Past the end nothing is copied.
+        assert_eq!(buf.read(200, &mut out, false), Some(0));
+        assert_eq!(buf.read(usize::MAX, &mut out, false), Some(0));
+    }
+
+    #[test]
+    fn pinned_until_cleared() {
+        let mut buf = OutputBuffer::new();
+        buf.set(output(10));
+        let mut out = [0u8; 4];
+        assert_eq!(buf.read(0, &mut out, false), Some(4));
+
+        // A run completing mid-read does not change what is read.
+        buf.set(Vec::from([0xff; 10]));
+        assert_eq!(buf.read(4, &mut out, true), Some(4));
+        assert_eq!(out, [4, 5, 6, 7]);
+
+        // Once cleared the next read sees the newer output.
+        assert_eq!(buf.read(0, &mut out, true), Some(4));
+        assert_eq!(out, [0xff; 4]);
+
+        // Nothing is left once that is cleared too.
+        assert!(buf.is_empty());
+        assert_eq!(buf.read(0, &mut out, false), Some(0));
+    }
+
+    #[test]
+    fn lost_output() {
+        let mut buf = OutputBuffer::new();
+        buf.set(output(10));
+        buf.set_lost();
+        let mut out = [0u8; 4];
+        assert_eq!(buf.read(0, &mut out, false), None);
+
+        // Output being read is unaffected by a later run's loss.
+        buf.set(output(10));
+        assert_eq!(buf.read(0, &mut out, false), Some(4));
+        buf.set_lost();
+        assert_eq!(buf.read(4, &mut out, true), Some(4));
+        assert_eq!(out, [4, 5, 6, 7]);
+        assert_eq!(buf.read(0, &mut out, false), None);
+
+        // The next run's output clears the loss.
+        buf.set(output(10));
+        assert_eq!(buf.read(0, &mut out, true), Some(4));
+    }
+}
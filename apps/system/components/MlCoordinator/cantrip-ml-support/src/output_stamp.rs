@@ -0,0 +1,98 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Freshness stamps for model output.
+//!
+//! Output from a model's last run is kept until the next run completes
+//! so a client polling for results cannot tell a new result from one it
+//! has already seen by looking at the data. Each completed run is
+//! stamped with a generation number and the time it finished. The
+//! generation is shared by all models and never reused (even across
+//! reloads) so a client need only remember the last generation it saw.
+//! The stamp also records how long the run took.
+
+/// Stamp attached to the output of a completed run.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct OutputStamp {
+    pub generation: u64,     // 0 until a run completes
+    pub produced_at_us: u64, // TimerService clock; 0 if unavailable
+    pub elapsed_us: u32,     // Run time; 0 if unavailable
+}
+
+/// Source of OutputStamps.
+#[derive(Debug, Default)]
+pub struct OutputGenerations {
+    last: u64,
+}
+impl OutputGenerations {
+    pub const fn new() -> Self { OutputGenerations { last: 0 } }
+
+    /// Returns the stamp for a run that started at |started_us| and
+    /// completed at |now_us|. Either time is 0 if the clock could not
+    /// be read in which case the run time is reported as 0.
+    pub fn stamp(&mut self, started_us: u64, now_us: u64) -> OutputStamp {
+        self.last += 1;
+        let elapsed_us = if started_us == 0 || now_us == 0 {
+            0
+        } else {
+            u32::try_from(now_us.saturating_sub(started_us)).unwrap_or(u32::MAX)
+        };
+        OutputStamp {
+            generation: self.last,
+            produced_at_us: now_us,
+            elapsed_us,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generation_advances() {
+        let mut generations = OutputGenerations::new();
+        let mut output = OutputStamp::default();
+        assert_eq!(output.generation, 0);
+
+        // Run a model twice; each completion is newer than the last.
+        output = generations.stamp(400, 1_000);
+        let first = output;
+        assert_eq!(first.produced_at_us, 1_000);
+        output = generations.stamp(2_000, 2_500);
+        assert!(output.generation > first.generation);
+        assert_eq!(output.produced_at_us, 2_500);
+    }
+
+    #[test]
+    fn generation_shared() {
+        // Runs of different models are ordered by completion.
+        let mut generations = OutputGenerations::new();
+        let a = generations.stamp(5, 10);
+        let b = generations.stamp(5, 10);
+        let a2 = generations.stamp(15, 20);
+        assert!(a.generation < b.generation);
+        assert!(b.generation < a2.generation);
+    }
+    #[test]
+    fn elapsed() {
+        let mut generations = OutputGenerations::new();
+        assert_eq!(generations.stamp(1_000, 3_500).elapsed_us, 2_500);
+        // Without a clock reading the run time is unknown.
+        assert_eq!(generations.stamp(0, 3_500).elapsed_us, 0);
+        assert_eq!(generations.stamp(1_000, 0).elapsed_us, 0);
+        // Runs too long to report saturate.
+        assert_eq!(generations.stamp(1, 1 << 40).elapsed_us, u32::MAX);
+    }
+}
@@ -29,16 +29,31 @@ pub const TCM_SIZE: usize = 0x1000000;
 
 pub fn enable_interrupts(_enable: bool) {}
 
+pub fn tcm_enabled_size() -> usize { TCM_SIZE }
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VectorCoreError {
+    InvalidWindow,
+    InvalidOffset,
+    InvalidLength,
+    InvalidPermission,
+}
+
 pub fn set_wmmu_window(
-    _window_id: WindowId,
+    window_id: WindowId,
     _start_address: usize,
     _length: usize,
-    _permission: Permission,
-) {
+    permission: Permission,
+) -> Result<(), VectorCoreError> {
+    permission
+        .check(window_id)
+        .or(Err(VectorCoreError::InvalidPermission))
 }
 
 pub fn run() {}
 
+pub fn freeze(_freeze: bool) {}
+
 pub fn write_image_part(
     _image: &mut Box<dyn Read>,
     _start_address: usize,
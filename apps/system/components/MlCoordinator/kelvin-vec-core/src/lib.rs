@@ -22,7 +22,6 @@ mod ml_top;
 
 use cantrip_io::Read;
 use cantrip_ml_interface::MlCoordError;
-use cantrip_ml_interface::MAX_OUTPUT_DATA;
 use cantrip_ml_shared::*;
 use cantrip_proc_interface::BundleImage;
 use core::cmp;
@@ -58,6 +57,15 @@ pub fn debug_state() {
     info!(target: "KELVIN", "TCM {} @ {:#X}", TCM_SIZE, TCM_PADDR);
 }
 
+// Number of TCM (DMEM) banks; see MemoryBankCtrl.
+const TCM_BANKS: usize = 16;
+
+/// Returns the bytes of TCM in memory banks that are enabled.
+pub fn tcm_enabled_size() -> usize {
+    let enabled = ml_top::get_memory_bank_ctrl().d_mem_enable().count_ones() as usize;
+    TCM_SIZE / TCM_BANKS * enabled
+}
+
 pub fn enable_interrupts(enable: bool) {
     trace!("ENABLE {}", enable);
     let intr_enable = ml_top::IntrEnable::new()
@@ -78,6 +86,12 @@ pub fn run() {
     ml_top::set_ctrl(ctrl);
 }
 
+/// Freeze (or unfreeze) the core, preserving the rest of the ctrl state.
+pub fn freeze(freeze: bool) {
+    trace!("FREEZE {}", freeze);
+    ml_top::set_ctrl(ml_top::get_ctrl().with_freeze(freeze));
+}
+
 /*
 * From the kelvin linker script.
 *
@@ -204,7 +218,7 @@ pub fn tcm_move(src: usize, dest: usize, byte_length: usize) {
 /// If |src| is out of range the copy is not done.
 /// if |src_len| extends past the end of TCM or |dest| is
 /// too small the copy is truncated to fit.
-pub fn tcm_read(src: usize, src_len: usize, dest: &mut [u8; MAX_OUTPUT_DATA]) {
+pub fn tcm_read(src: usize, src_len: usize, dest: &mut [u8]) {
     trace!("READ {} bytes from {:#x}", src_len, src);
 
     if !(TCM_PADDR <= src && src < TCM_PADDR + TCM_SIZE) {
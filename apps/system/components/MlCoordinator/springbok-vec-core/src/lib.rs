@@ -23,7 +23,6 @@ mod vc_top;
 extern crate alloc;
 use cantrip_io::Read;
 use cantrip_ml_interface::MlCoordError;
-use cantrip_ml_interface::MAX_OUTPUT_DATA;
 use cantrip_ml_shared::*;
 use cantrip_proc_interface::BundleImage;
 use core::cmp;
@@ -67,6 +66,15 @@ pub fn debug_state() {
     info!(target: "SPRINGBOK", "TCM {} @ {:#X}", TCM_SIZE, TCM_PADDR);
 }
 
+// Number of TCM (DMEM) banks; see MemoryBankCtrl.
+const TCM_BANKS: usize = 8;
+
+/// Returns the bytes of TCM in memory banks that are enabled.
+pub fn tcm_enabled_size() -> usize {
+    let enabled = vc_top::get_memory_bank_ctrl().d_mem_enable().count_ones() as usize;
+    TCM_SIZE / TCM_BANKS * enabled
+}
+
 pub fn enable_interrupts(enable: bool) {
     vc_top::set_intr_enable(
         vc_top::IntrEnable::new()
@@ -77,12 +85,21 @@ pub fn enable_interrupts(enable: bool) {
     );
 }
 
+/// Errors from programming the vector core.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VectorCoreError {
+    InvalidWindow,     // WMMU window index out of range
+    InvalidOffset,     // Window starts outside the TCM
+    InvalidLength,     // Window is empty or extends past the TCM
+    InvalidPermission, // Permission is illegal for the window
+}
+
 pub fn set_wmmu_window(
     window_id: WindowId,
     start_address: usize,
     length: usize,
     permission: Permission,
-) {
+) -> Result<(), VectorCoreError> {
     trace!(
         "Set window {:?} to addr {:x} len {:x}",
         window_id,
@@ -90,13 +107,25 @@ pub fn set_wmmu_window(
         length
     );
 
-    vc_top::set_mmu_window_offset(window_id as usize, start_address);
+    // NB: windows are given (and programmed) by TCM address; check the
+    //   whole window is in the TCM before programming any registers
+    if !(TCM_PADDR..TCM_PADDR + TCM_SIZE).contains(&start_address) {
+        return Err(VectorCoreError::InvalidOffset);
+    }
+    match (start_address - TCM_PADDR).checked_add(length) {
+        Some(end) if length > 0 && end <= TCM_SIZE => {}
+        _ => return Err(VectorCoreError::InvalidLength),
+    }
+    if let Err(e) = permission.check(window_id) {
+        error!("Window {:?} permission {:?}: {:?}", window_id, permission, e);
+        return Err(VectorCoreError::InvalidPermission);
+    }
+    vc_top::set_mmu_window_offset(window_id as usize, start_address)?;
     // The length of the window is not the size of the window, but rather
     // the last address of the window. This saves us a bit in hardware:
     // 0x400000 is 23 bits vs. 0x3FFFFF 22 bits.
-    assert!(length > 0);
-    vc_top::set_mmu_window_length(window_id as usize, length - 1);
-    vc_top::set_mmu_window_permission(window_id as usize, permission);
+    vc_top::set_mmu_window_length(window_id as usize, length - 1)?;
+    vc_top::set_mmu_window_permission(window_id as usize, permission)
 }
 
 /// Start the core at the default PC.
@@ -109,6 +138,9 @@ pub fn run() {
     );
 }
 
+/// Freeze (or unfreeze) the core, preserving the rest of the ctrl state.
+pub fn freeze(freeze: bool) { vc_top::set_ctrl(vc_top::get_ctrl().with_freeze(freeze)); }
+
 /// Scan the image for loadable sections and verify the image has a valid
 /// format and fits into the TCM.
 pub fn preprocess_image(id: &ImageId, image: &mut BundleImage) -> Option<(ImageSizes, ImageSizes)> {
@@ -244,7 +276,7 @@ pub fn tcm_move(src: usize, dest: usize, byte_length: usize) {
 /// If |src| is out of range the copy is not done.
 /// if |src_len| extends past the end of TCM or |dest| is
 /// too small the copy is truncated to fit.
-pub fn tcm_read(src: usize, src_len: usize, dest: &mut [u8; MAX_OUTPUT_DATA]) {
+pub fn tcm_read(src: usize, src_len: usize, dest: &mut [u8]) {
     trace!("Reading {:#x} bytes from {:#x}", src_len, src);
 
     if !(TCM_PADDR <= src && src < TCM_PADDR + TCM_SIZE) {
@@ -340,10 +372,12 @@ pub fn get_output_header(data_top_addr: usize, sizes: &ImageSizes) -> OutputHead
 }
 
 /// Returns the loaded model's input parameters.
+// NB: Springbok models have no input region (the WMMU window for model
+//   input is never set up) so input cannot be set or read back.
 pub fn get_input_params() -> Result<(u32, u32), MlCoordError> {
-    Ok((0, 0)) // TODO(sleffler): worth fixing?
+    Err(MlCoordError::InputNotSupported)
 }
 
 pub fn set_input_data(_input_data_offset: usize, _input_data: &[u8]) -> Result<(), MlCoordError> {
-    Err(MlCoordError::NoOutputHeader) // TODO(sleffler): add new code?
+    Err(MlCoordError::InputNotSupported)
 }
@@ -15,6 +15,9 @@
 // Setters and getters for the Springbok Vector Core CSRs.
 
 use crate::Permission;
+use crate::VectorCoreError;
+use crate::TCM_PADDR;
+use crate::TCM_SIZE;
 use modular_bitfield::prelude::*;
 use reg_constants::vc_top::*;
 
@@ -267,41 +270,58 @@ const BYTES_PER_WINDOW: usize = 0x10;
 
 const MAX_WINDOW: usize = 0x40;
 
-unsafe fn window_ptr_mut(window: usize) -> *mut u8 {
+unsafe fn window_ptr_mut(window: usize) -> Result<*mut u8, VectorCoreError> {
     extern "Rust" {
         fn get_csr_mut() -> &'static mut [u8];
     }
-    assert!(window < MAX_WINDOW, "Window out of range of WMMU");
-    get_csr_mut()
+    if window >= MAX_WINDOW {
+        return Err(VectorCoreError::InvalidWindow);
+    }
+    Ok(get_csr_mut()
         .as_mut_ptr()
-        .add(WMMU_OFFSET + (window * BYTES_PER_WINDOW))
+        .add(WMMU_OFFSET + (window * BYTES_PER_WINDOW)))
 }
 
-pub fn set_mmu_window_offset(window: usize, offset: usize) {
+// Sets the TCM address where |window| starts.
+pub fn set_mmu_window_offset(window: usize, offset: usize) -> Result<(), VectorCoreError> {
+    if !(TCM_PADDR..TCM_PADDR + TCM_SIZE).contains(&offset) {
+        return Err(VectorCoreError::InvalidOffset);
+    }
     unsafe {
-        window_ptr_mut(window)
+        window_ptr_mut(window)?
             .add(OFFSET_ADDR)
             .cast::<usize>()
             .write_volatile(offset);
     }
+    Ok(())
 }
 
-pub fn set_mmu_window_length(window: usize, length: usize) {
+// Sets the length of |window|. NB: |length| is the last byte offset in
+// the window, not the size.
+pub fn set_mmu_window_length(window: usize, length: usize) -> Result<(), VectorCoreError> {
+    if length >= TCM_SIZE {
+        return Err(VectorCoreError::InvalidLength);
+    }
     unsafe {
-        window_ptr_mut(window)
+        window_ptr_mut(window)?
             .add(LENGTH_ADDR)
             .cast::<usize>()
             .write_volatile(length);
     }
+    Ok(())
 }
 
-pub fn set_mmu_window_permission(window: usize, permission: Permission) {
+pub fn set_mmu_window_permission(
+    window: usize,
+    permission: Permission,
+) -> Result<(), VectorCoreError> {
     unsafe {
-        window_ptr_mut(window)
+        window_ptr_mut(window)?
             .add(PERMISSIONS_ADDR)
             .cast::<usize>()
             .write_volatile(permission.bits() as usize);
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -314,6 +334,13 @@ mod vc_tests {
     fn bit_mask(width: u32) -> u32 { bit(width) - 1 }
     fn field(v: u32, mask: u32, shift: usize) -> u32 { (v & mask) << shift }
 
+    // Backs the CSR region so the WMMU setters can be exercised.
+    #[repr(align(8))]
+    struct Csr([u8; WMMU_OFFSET + MAX_WINDOW * BYTES_PER_WINDOW]);
+    static mut CSR: Csr = Csr([0; WMMU_OFFSET + MAX_WINDOW * BYTES_PER_WINDOW]);
+    #[no_mangle]
+    fn get_csr_mut() -> &'static mut [u8] { unsafe { &mut *core::ptr::addr_of_mut!(CSR.0) } }
+
     #[test]
     fn intr_state() {
         assert_eq!(
@@ -517,4 +544,58 @@ mod vc_tests {
             bit(VC_TOP_INIT_STATUS_INIT_DONE_BIT)
         );
     }
+
+    #[test]
+    fn mmu_window_out_of_range() {
+        assert_eq!(
+            set_mmu_window_offset(MAX_WINDOW, 0),
+            Err(VectorCoreError::InvalidWindow)
+        );
+        assert_eq!(
+            set_mmu_window_length(MAX_WINDOW, 0),
+            Err(VectorCoreError::InvalidWindow)
+        );
+        assert_eq!(
+            set_mmu_window_permission(MAX_WINDOW, Permission::READ),
+            Err(VectorCoreError::InvalidWindow)
+        );
+        assert_eq!(
+            set_mmu_window_offset(usize::MAX, TCM_PADDR),
+            Err(VectorCoreError::InvalidWindow)
+        );
+        assert_eq!(set_mmu_window_offset(MAX_WINDOW - 1, TCM_PADDR), Ok(()));
+    }
+
+    #[test]
+    fn mmu_window_length_out_of_range() {
+        assert_eq!(
+            set_mmu_window_length(0, TCM_SIZE),
+            Err(VectorCoreError::InvalidLength)
+        );
+        assert_eq!(
+            set_mmu_window_offset(0, TCM_PADDR + TCM_SIZE),
+            Err(VectorCoreError::InvalidOffset)
+        );
+        assert_eq!(
+            set_mmu_window_offset(0, TCM_PADDR - 1),
+            Err(VectorCoreError::InvalidOffset)
+        );
+        assert_eq!(set_mmu_window_length(0, TCM_SIZE - 1), Ok(()));
+        assert_eq!(set_mmu_window_offset(0, TCM_PADDR + TCM_SIZE - 1), Ok(()));
+    }
+
+    #[test]
+    fn mmu_window_offset_is_address() {
+        // The window start is programmed with the TCM address.
+        let window = 2;
+        assert_eq!(set_mmu_window_offset(window, TCM_PADDR + 0x100), Ok(()));
+        let offset = unsafe {
+            core::ptr::addr_of!(CSR.0)
+                .cast::<u8>()
+                .add(WMMU_OFFSET + window * BYTES_PER_WINDOW + OFFSET_ADDR)
+                .cast::<usize>()
+                .read_volatile()
+        };
+        assert_eq!(offset, TCM_PADDR + 0x100);
+    }
 }
@@ -98,7 +98,8 @@ impl PkgMgmtInterfaceThread {
             PackageManagementRequest::InstallApp {
                 app_id,
                 pkg_contents,
-            } => Self::install_app_request(app_id, pkg_contents.into_owned()),
+                signature,
+            } => Self::install_app_request(app_id, pkg_contents.into_owned(), signature),
             PackageManagementRequest::Uninstall(bundle_id) => Self::uninstall_request(bundle_id),
         }
     }
@@ -115,7 +116,11 @@ impl PkgMgmtInterfaceThread {
             .or(Err(ProcessManagerError::SerializeError))?;
         Ok(None)
     }
-    fn install_app_request(app_id: &str, mut pkg_contents: ObjDescBundle) -> PkgMgmtResult {
+    fn install_app_request(
+        app_id: &str,
+        mut pkg_contents: ObjDescBundle,
+        signature: Option<&[u8]>,
+    ) -> PkgMgmtResult {
         // NB: make sure noone clobbers the setup done in init(),
         // and clear any capability the path points to when dropped
         let recv_path = CAMKES.get_owned_current_recv_path();
@@ -124,7 +129,7 @@ impl PkgMgmtInterfaceThread {
         pkg_contents.cnode = recv_path.1;
 
         cantrip_proc()
-            .install_app(app_id, &pkg_contents)
+            .install_app(app_id, &pkg_contents, signature)
             .map(|_| None)
     }
     fn uninstall_request(bundle_id: &str) -> PkgMgmtResult {
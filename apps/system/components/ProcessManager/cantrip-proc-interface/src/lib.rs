@@ -146,6 +146,7 @@ pub trait ProcessManagerInterface {
         &mut self,
         app_id: &str,
         pkg_contents: &ObjDescBundle,
+        signature: Option<&[u8]>,
     ) -> Result<(), ProcessManagerError>;
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), ProcessManagerError>;
     fn start(&mut self, bundle: &Bundle) -> Result<Self::BundleImpl, ProcessManagerError>;
@@ -159,6 +160,7 @@ pub trait PackageManagementInterface {
         &mut self,
         app_id: &str,
         pkg_contents: &ObjDescBundle,
+        signature: Option<&[u8]>,
     ) -> Result<(), ProcessManagerError>;
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), ProcessManagerError>;
 }
@@ -178,6 +180,7 @@ pub enum PackageManagementRequest<'a> {
         // Install application
         app_id: &'a str,
         pkg_contents: Cow<'a, ObjDescBundle>,
+        signature: Option<&'a [u8]>, // Detached package signature
     },
     Uninstall(&'a str), // Uninstall package
 }
@@ -188,6 +191,7 @@ impl<'a> PackageManagementRequest<'a> {
             | PackageManagementRequest::InstallApp {
                 app_id: _,
                 pkg_contents,
+                signature: _,
             } => Some(pkg_contents.cnode),
             PackageManagementRequest::Uninstall(_) => None,
         }
@@ -234,10 +238,13 @@ pub fn cantrip_pkg_mgmt_install(
         .map(|reply: InstallResponse| reply.bundle_id)
 }
 
+/// Installs application |app_id| from |pkg_contents| with an optional
+/// detached |signature|; see cantrip_security_install_application.
 #[inline]
 pub fn cantrip_pkg_mgmt_install_app(
     app_id: &str,
     pkg_contents: &ObjDescBundle,
+    signature: Option<&[u8]>,
 ) -> Result<(), ProcessManagerError> {
     Camkes::debug_assert_slot_cnode(
         "cantrip_pkg_mgmt_install_app",
@@ -246,6 +253,7 @@ pub fn cantrip_pkg_mgmt_install_app(
     cantrip_pkg_mgmt_request(&PackageManagementRequest::InstallApp {
         app_id,
         pkg_contents: Cow::Borrowed(pkg_contents),
+        signature,
     })
 }
 
@@ -28,10 +28,13 @@ use cantrip_proc_interface::PackageManagementInterface;
 use cantrip_proc_interface::ProcessControlInterface;
 use cantrip_proc_interface::ProcessManagerError;
 use cantrip_proc_interface::ProcessManagerInterface;
+use cantrip_sdk_manager::ResourceLimits;
+use cantrip_security_interface::cantrip_security_get_manifest;
 use cantrip_security_interface::cantrip_security_install;
 use cantrip_security_interface::cantrip_security_install_application;
 use cantrip_security_interface::cantrip_security_load_application;
 use cantrip_security_interface::cantrip_security_uninstall;
+use cantrip_security_interface::Manifest;
 use log::trace;
 use spin::Mutex;
 use spin::MutexGuard;
@@ -88,11 +91,12 @@ impl PackageManagementInterface for Guard<'_> {
         &mut self,
         app_id: &str,
         pkg_contents: &ObjDescBundle,
+        signature: Option<&[u8]>,
     ) -> Result<(), ProcessManagerError> {
         self.manager
             .as_mut()
             .unwrap()
-            .install_app(app_id, pkg_contents)
+            .install_app(app_id, pkg_contents, signature)
     }
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), ProcessManagerError> {
         self.manager.as_mut().unwrap().uninstall(bundle_id)
@@ -136,13 +140,14 @@ impl ProcessManagerInterface for CantripManagerInterface {
         &mut self,
         app_id: &str,
         pkg_contents: &ObjDescBundle,
+        signature: Option<&[u8]>,
     ) -> Result<(), ProcessManagerError> {
         trace!(
             "ProcessManagerInterface::install_app {} pkg_contents {}",
             app_id,
             pkg_contents
         );
-        Ok(cantrip_security_install_application(app_id, pkg_contents)?)
+        Ok(cantrip_security_install_application(app_id, pkg_contents, signature)?)
     }
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), ProcessManagerError> {
         trace!("ProcessManagerInterface::uninstall bundle_id {}", bundle_id);
@@ -179,8 +184,10 @@ impl ProcessManagerInterface for CantripManagerInterface {
 
         // TODO(sleffler): awkward container_slot ownership
         let mut container_slot = CSpaceSlot::new();
-        let bundle_frames = cantrip_security_load_application(&bundle.app_id, &container_slot)?;
-        let mut sel4_bundle = seL4BundleImpl::new(bundle, &bundle_frames)?;
+        let bundle_frames =
+            cantrip_security_load_application(&bundle.app_id, None, &container_slot)?;
+        let limits = sdk_limits(&bundle.app_id);
+        let mut sel4_bundle = seL4BundleImpl::new(bundle, &bundle_frames, &limits)?;
         // sel4_bundle owns container_slot now; release our ref so it's not
         // reclaimed when container_slot goes out of scope.
         container_slot.release();
@@ -207,3 +214,25 @@ impl ProcessManagerInterface for CantripManagerInterface {
         bundle_impl.capscan()
     }
 }
+
+// Returns the SDKRuntime limits for |app_id| from its manifest. An app
+// without a manifest, or a limit its manifest does not set, is unlimited.
+fn sdk_limits(app_id: &str) -> ResourceLimits {
+    let unlimited = ResourceLimits::UNLIMITED;
+    match cantrip_security_get_manifest(app_id)
+        .ok()
+        .as_deref()
+        .and_then(Manifest::parse)
+    {
+        Some(Manifest { limits, .. }) => ResourceLimits {
+            max_timers: limits.max_timers.unwrap_or(unlimited.max_timers),
+            max_models: limits.max_models.unwrap_or(unlimited.max_models),
+            max_audio_bytes: limits.max_audio_bytes.unwrap_or(unlimited.max_audio_bytes),
+            log_bytes_per_sec: limits
+                .log_bytes_per_sec
+                .unwrap_or(unlimited.log_bytes_per_sec),
+            max_request_ms: limits.max_request_ms.unwrap_or(unlimited.max_request_ms),
+        },
+        None => unlimited,
+    }
+}
@@ -100,12 +100,13 @@ impl<P: ProcessManagerInterface> PackageManagementInterface for ProcessManager<P
         &mut self,
         app_id: &str,
         pkg_contents: &ObjDescBundle,
+        signature: Option<&[u8]>,
     ) -> Result<(), ProcessManagerError> {
         trace!("install_app {} pkg_contents {}", app_id, pkg_contents);
 
         // NB: defer to StorageManager for handling an install of a previously
         // installed app
-        self.manager.install_app(app_id, pkg_contents)?;
+        self.manager.install_app(app_id, pkg_contents, signature)?;
 
         let bundle = Bundle::new(app_id);
         assert!(self
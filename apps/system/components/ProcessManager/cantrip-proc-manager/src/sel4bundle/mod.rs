@@ -20,10 +20,12 @@
 
 extern crate alloc;
 use alloc::string::String;
-use cantrip_memory_interface::cantrip_cnode_alloc;
-use cantrip_memory_interface::cantrip_object_alloc_in_toplevel;
-use cantrip_memory_interface::cantrip_object_free;
+use cantrip_memory_interface::cantrip_cnode_alloc_for;
+use cantrip_memory_interface::cantrip_object_alloc_in_toplevel_tagged;
 use cantrip_memory_interface::cantrip_object_free_in_cnode;
+use cantrip_memory_interface::cantrip_object_free_in_cnode_tagged;
+use cantrip_memory_interface::cantrip_object_free_tagged;
+use cantrip_memory_interface::cnode_depth_for;
 use cantrip_memory_interface::ObjDesc;
 use cantrip_memory_interface::ObjDescBundle;
 use cantrip_os_common::copyregion::CopyRegion;
@@ -36,6 +38,7 @@ use cantrip_proc_interface::BundleImplInterface;
 use cantrip_proc_interface::ProcessManagerError;
 use cantrip_sdk_manager::cantrip_sdk_manager_get_endpoint;
 use cantrip_sdk_manager::cantrip_sdk_manager_release_endpoint;
+use cantrip_sdk_manager::ResourceLimits;
 use core::cmp;
 use core::mem::size_of;
 #[cfg(feature = "CONFIG_CHECK_BUNDLE_IMAGE")]
@@ -181,6 +184,10 @@ const INDEX_FRAME: usize = INDEX_STACK + 1;
 const INDEX_LAST_COMMON: usize = INDEX_FRAME;
 // arch-specific descriptors start at INDEX_LAST_COMMON + 1
 
+// Owner tag for dynamic_objs so MemoryManager leak reports can
+// attribute them to ProcessManager.
+const DYNAMIC_OBJS_OWNER: &str = "ProcessManager:dynamic_objs";
+
 pub struct seL4BundleImpl {
     // Application binary pages ordered by virtual address.
     bundle_frames: ObjDescBundle,
@@ -210,6 +217,7 @@ pub struct seL4BundleImpl {
 
     sdk_ep_slot: seL4_CPtr,
     sdk_ep: CSpaceSlot,
+    sdk_limits: ResourceLimits, // Applied to SDKRuntime requests
 
     sdk_frame_addr: seL4_Word, // Address of SDK frame in app's VSpace
     stack_base: seL4_Word,     // Base address of stack in app's VSpace
@@ -228,6 +236,7 @@ impl seL4BundleImpl {
     pub fn new(
         bundle: &Bundle,
         bundle_frames: &ObjDescBundle,
+        sdk_limits: &ResourceLimits,
     ) -> Result<Self, ProcessManagerError> {
         trace!("seL4BundleImpl::new {:?} bundle_frames {}", bundle, bundle_frames);
 
@@ -309,16 +318,18 @@ impl seL4BundleImpl {
         // so the (careful) layout in |desc| is lost. This means one should
         // not assume SLOT_* are meaningful; use INDEX_* to fetch a cptr
         // from dynamic_objs.
-        let dynamic_objs = cantrip_object_alloc_in_toplevel(desc.into_vec())
-            .or(Err(ProcessManagerError::StartFailed))?;
-
-        // Allocate the top-level CNode that will hold |dynamic_objs|.
-        let cspace_root_depth = dynamic_objs.count_log2();
-        let cspace_root = match cantrip_cnode_alloc(cspace_root_depth) {
+        let dynamic_objs =
+            cantrip_object_alloc_in_toplevel_tagged(desc.into_vec(), DYNAMIC_OBJS_OWNER)
+                .or(Err(ProcessManagerError::StartFailed))?;
+
+        // Allocate the top-level CNode that will hold |dynamic_objs|
+        // plus the SDK endpoint.
+        let cspace_root_depth = cnode_depth_for(sdk_ep_slot + 1);
+        let cspace_root = match cantrip_cnode_alloc_for(sdk_ep_slot + 1) {
             Err(e) => {
                 error!("seL4BundleImpl::new: cnode alloc failed: {:?}", e);
                 info!("seL4BundleImpl::new: dynamic objects: {:?}", &dynamic_objs);
-                if let Err(e) = cantrip_object_free(&dynamic_objs) {
+                if let Err(e) = cantrip_object_free_tagged(&dynamic_objs, DYNAMIC_OBJS_OWNER) {
                     error!("seL4BundleImpl::new: freeing dynamic_objs returned {:?}", e);
                 }
                 return Err(ProcessManagerError::StartFailed);
@@ -344,6 +355,7 @@ impl seL4BundleImpl {
             tcb_sp: 0,
             sdk_ep_slot,
             sdk_ep: CSpaceSlot::new(),
+            sdk_limits: *sdk_limits,
             sdk_frame_addr: 0,
             stack_base: 0,
             first_vaddr,
@@ -585,7 +597,7 @@ impl seL4BundleImpl {
         // move it in init_cspace later. We have to do this in the toplevel
         // cspace because the fault handler is copied implicitly from this
         // thread's root CSpace into the new thread's TCB when MCS is enabled.
-        cantrip_sdk_manager_get_endpoint(&self.tcb_name, &self.sdk_ep)
+        cantrip_sdk_manager_get_endpoint(&self.tcb_name, &self.sdk_limits, &self.sdk_ep)
             .or(Err(seL4_Error::seL4_NoError))?; // XXX error
 
         // XXX MCS v non-MCS
@@ -740,7 +752,8 @@ impl BundleImplInterface for seL4BundleImpl {
         //    the next retype operation. This will not be necessary
         //    when we remove the TCB reference in the CNode.
         self.cap_tcb = CSpaceSlot::new(); // NB: force drop
-        cantrip_object_free_in_cnode(&self.dynamic_objs).map_err(handle_error)?;
+        cantrip_object_free_in_cnode_tagged(&self.dynamic_objs, DYNAMIC_OBJS_OWNER)
+            .map_err(handle_error)?;
         // XXX delete any other local caps
         Ok(())
     }
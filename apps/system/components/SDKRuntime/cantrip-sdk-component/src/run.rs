@@ -34,30 +34,43 @@ use cantrip_memory_interface::ObjDesc;
 use cantrip_os_common::camkes;
 use cantrip_os_common::copyregion::CopyRegion;
 use cantrip_os_common::cspace_slot::CSpaceSlot;
+use cantrip_os_common::diagnostics::Diagnostics;
 use cantrip_os_common::logger;
 use cantrip_os_common::sel4_sys;
+use cantrip_proc_interface::cantrip_proc_ctrl_start;
+use cantrip_proc_interface::cantrip_proc_ctrl_stop;
+use cantrip_sdk_manager::DiagnosticsResponse;
+use cantrip_sdk_manager::ResourceLimits;
 use cantrip_sdk_manager::SDKManagerError;
 use cantrip_sdk_manager::SDKManagerInterface;
 use cantrip_sdk_manager::SDKManagerRequest;
+use cantrip_sdk_manager::SnapshotResponse;
 use cantrip_sdk_manager::SDK_MANAGER_REQUEST_DATA_SIZE;
 use cantrip_sdk_runtime::CantripSDKRuntime;
+use cantrip_sdk_runtime::FaultAction;
+use cantrip_sdk_runtime::FaultInfo;
+use cantrip_sdk_runtime::Guard;
 use log::{error, info};
 use zerovec::ZeroVec;
 
 use camkes::*;
 use logger::*;
 
+use sdk_interface::check_request_frame;
 use sdk_interface::SDKAppId;
 use sdk_interface::SDKError;
+use sdk_interface::SDKReply;
 use sdk_interface::SDKRuntimeError;
 use sdk_interface::SDKRuntimeInterface;
 use sdk_interface::SDKRuntimeRequest;
+use sdk_interface::AUDIO_RECORD_COLLECT_MAX_SAMPLES;
 use sdk_interface::SDKRUNTIME_REQUEST_DATA_SIZE;
 
 use sel4_sys::seL4_CPtr;
 use sel4_sys::seL4_CapRights;
 use sel4_sys::seL4_EndpointObject;
 use sel4_sys::seL4_FaultTag;
+use sel4_sys::seL4_GetMR;
 use sel4_sys::seL4_MessageInfo;
 use sel4_sys::seL4_Recv;
 use sel4_sys::seL4_ReplyObject;
@@ -70,7 +83,7 @@ mod generated {
 }
 use generated::*;
 
-fn cantrip_sdk() -> impl SDKManagerInterface + SDKRuntimeInterface {
+fn cantrip_sdk() -> Guard<'static> {
     static CANTRIP_SDK: CantripSDKRuntime = CantripSDKRuntime::empty();
     let mut runtime = CANTRIP_SDK.get();
     if runtime.is_empty() {
@@ -114,7 +127,14 @@ impl CamkesThreadInterface for SdkRuntimeControlThread {
         // XXX how to handle "maybe" inclusion
         static_logger!(logger);
 
-        static mut HEAP_MEMORY: [u8; 8 * 1024] = [0; 8 * 1024];
+        // NB: record buffers larger than the i2s driver's default are
+        //   allocated from the heap (up to 2 * AUDIO_RECORD_BUFFER_MAX
+        //   samples), hence the extra 32KiB when audio is supported.
+        #[cfg(feature = "i2s-driver")]
+        const HEAP_SIZE: usize = 40 * 1024;
+        #[cfg(not(feature = "i2s-driver"))]
+        const HEAP_SIZE: usize = 8 * 1024;
+        static mut HEAP_MEMORY: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
         unsafe {
             CAMKES.pre_init(&mut HEAP_MEMORY);
         }
@@ -180,11 +200,22 @@ impl CamkesThreadInterface for SdkRuntimeControlThread {
                 if label < (SDKRuntimeRequest::Ping as usize) {
                     match seL4_FaultTag::try_from(label) {
                         Ok(fault_tag) => {
+                            // NB: decode before anything else uses the IPC buffer.
+                            let fault = FaultInfo::decode(&fault_tag, |mr| seL4_GetMR(mr));
+                            info!("Fault from {}: {}", app_id, fault);
+
+                            // NB: the guard must be dropped before acting
+                            //   as the ProcessManager calls back to release
+                            //   the app's endpoint.
+                            let action = cantrip_sdk().handle_fault(app_id, &fault_tag);
+
                             #[cfg(feature = "CONFIG_DEBUG_BUILD")]
                             print_fault_debug(app_id, fault_tag);
 
-                            #[cfg(not(feature = "CONFIG_DEBUG_BUILD"))]
-                            info!("Fault tag {} from {}", fault_tag as usize, app_id);
+                            match action {
+                                Ok(action) => apply_fault_action(action),
+                                Err(e) => error!("Fault from {}: {:?}", app_id, e),
+                            }
                         }
                         Err(_) => error!("Bad fault tag {} on msg from {}", label, app_id),
                     }
@@ -205,7 +236,15 @@ impl CamkesThreadInterface for SdkRuntimeControlThread {
                     continue;
                 }
 
-                Camkes::debug_assert_slot_frame("run", recv_path);
+                #[cfg(feature = "rpc_benchmark")]
+                let rpc_start = cantrip_sdk_runtime::rpc_clock();
+
+                // A client whose frame cap is invalid (e.g. revoked) sends
+                // no cap and the receive slot is left empty.
+                let frame = check_request_frame(&info);
+                if frame.is_ok() {
+                    Camkes::debug_assert_slot_frame("run", recv_path);
+                }
                 // seL4_Recv & seL4_ReplyRecv return any badge but do not reset
                 // the ipcbuffer state. If the ipcbuffer is turned around for a
                 // send operation the received badge may be interpreted as an
@@ -213,8 +252,12 @@ impl CamkesThreadInterface for SdkRuntimeControlThread {
                 // (so it happens for both calls) with clear_request_cap().
                 // XXX not true with rust templates
                 Camkes::clear_request_cap();
-                // Map the frame with RPC parameters and process the request.
-                if copy_region.map(recv_path.1).is_ok() {
+                if let Err(e) = frame {
+                    // TODO(b/254286176): possible ddos
+                    error!("No RPC parameters frame; badge {}", client_badge);
+                    response = Err(e);
+                } else if copy_region.map(recv_path.1).is_ok() {
+                    // Map the frame with RPC parameters and process the request.
                     // The request token is passed in the MessageInfo label field.
                     // Any request-specific parameters are serialized in the first
                     // half of the page, with the second half reserved for reply data.
@@ -229,9 +272,13 @@ impl CamkesThreadInterface for SdkRuntimeControlThread {
                         .split_at_mut(SDKRUNTIME_REQUEST_DATA_SIZE);
                     let request_slice = &*request_slice; // NB: immutable alias
 
+                    // NB: the guard must be dropped before dispatching.
+                    let faulted = cantrip_sdk().check_faulted(app_id);
+
                     // TODO(sleffler): decode from shared page instead of label
                     response = match SDKRuntimeRequest::try_from(label) {
-                        Ok(tag) => Self::request(tag, app_id, request_slice, reply_slice),
+                        Ok(tag) => faulted
+                            .and_then(|_| Self::request(tag, app_id, request_slice, reply_slice)),
                         Err(_) => {
                             // TODO(b/254286176): possible ddos
                             error!("Unknown RPC request {}", label);
@@ -247,6 +294,12 @@ impl CamkesThreadInterface for SdkRuntimeControlThread {
                 Camkes::delete_path(recv_path).expect("delete");
                 Camkes::debug_assert_slot_empty("run", recv_path);
 
+                #[cfg(feature = "rpc_benchmark")]
+                if let Some(start) = rpc_start {
+                    let ping = label == SDKRuntimeRequest::Ping as usize;
+                    cantrip_sdk().record_rpc(app_id, ping, start);
+                }
+
                 info = seL4_ReplyRecv(
                     /*src=*/ SDKRUNTIME_ENDPOINT,
                     /*msgInfo=*/
@@ -266,22 +319,21 @@ impl CamkesThreadInterface for SdkRuntimeControlThread {
 
 #[cfg(feature = "CONFIG_DEBUG_BUILD")]
 fn print_fault_debug(app_id: SDKAppId, fault_type: seL4_FaultTag) {
-    use sel4_sys::seL4_GetMR;
     match fault_type {
         seL4_FaultTag::seL4_Fault_NullFault => {
-            let _ = cantrip_sdk().log(app_id, "normal exit or termination");
+            let _ = cantrip_sdk().log(app_id, "normal exit or termination", false);
         }
         seL4_FaultTag::seL4_Fault_CapFault => {
-            let _ = cantrip_sdk().log(app_id, "invalid capability");
+            let _ = cantrip_sdk().log(app_id, "invalid capability", false);
         }
         seL4_FaultTag::seL4_Fault_UnknownSyscall => {
-            let _ = cantrip_sdk().log(app_id, "unknown syscall");
+            let _ = cantrip_sdk().log(app_id, "unknown syscall", false);
         }
         seL4_FaultTag::seL4_Fault_UserException => {
-            let _ = cantrip_sdk().log(app_id, "user exception");
+            let _ = cantrip_sdk().log(app_id, "user exception", false);
         }
         seL4_FaultTag::seL4_Fault_VMFault => {
-            let _ = cantrip_sdk().log(app_id, "virtual-memory fault:");
+            let _ = cantrip_sdk().log(app_id, "virtual-memory fault:", false);
             info!(target: "", "IP       {:#010x}", unsafe { seL4_GetMR(0) });
             info!(target: "", "Addr     {:#010x}", unsafe { seL4_GetMR(1) });
             info!(target: "", "Prefetch {:#x}", unsafe { seL4_GetMR(2) });
@@ -291,14 +343,39 @@ fn print_fault_debug(app_id: SDKAppId, fault_type: seL4_FaultTag) {
 
         #[cfg(feature = "CONFIG_KERNEL_MCS")]
         seL4_FaultTag::seL4_Fault_Timeout => {
-            let _ = cantrip_sdk().log(app_id, "application timed out");
+            let _ = cantrip_sdk().log(app_id, "application timed out", false);
+        }
+    }
+}
+
+// Carries out the fault policy of an app; see SDKRuntime::handle_fault.
+fn apply_fault_action(action: FaultAction) {
+    match action {
+        FaultAction::Kill(bundle_id) => {
+            info!("Stopping {} after fault", bundle_id);
+            if let Err(e) = cantrip_proc_ctrl_stop(&bundle_id) {
+                error!("Stop {} failed: {:?}", bundle_id, e);
+            }
+        }
+        FaultAction::Restart(bundle_id) => {
+            info!("Restarting {} after fault", bundle_id);
+            if let Err(e) = cantrip_proc_ctrl_stop(&bundle_id) {
+                error!("Stop {} failed: {:?}", bundle_id, e);
+            } else if let Err(e) = cantrip_proc_ctrl_start(&bundle_id) {
+                error!("Start {} failed: {:?}", bundle_id, e);
+            }
         }
+        FaultAction::Suspend => {}
     }
 }
 
-fn serialize_failure(e: postcard::Error) -> SDKError {
-    error!("serialize failed: {:?}", e);
-    SDKError::SerializeFailed
+// Serializes |reply| to |reply_slice|; see sdk_interface::serialize_reply.
+fn serialize_reply<T: SDKReply>(reply: &T, reply_slice: &mut [u8]) -> Result<(), SDKError> {
+    let available = reply_slice.len();
+    sdk_interface::serialize_reply(reply, reply_slice).map_err(|e| {
+        error!("serialize reply ({} bytes available) failed: {:?}", available, e);
+        e
+    })
 }
 fn deserialize_failure(e: postcard::Error) -> SDKError {
     error!("deserialize failed: {:?}", e);
@@ -384,6 +461,72 @@ impl SdkRuntimeControlThread {
             SDKRuntimeRequest::AudioPlayStop => {
                 Self::audio_play_stop_request(app_id, request_slice, reply_slice)
             }
+            SDKRuntimeRequest::SyncKeys => {
+                Self::sync_keys_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::AudioGetState => {
+                Self::audio_get_state_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::SetFaultPolicy => {
+                Self::set_fault_policy_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::ModelUploadBegin => {
+                Self::model_upload_begin_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::ModelUploadChunk => {
+                Self::model_upload_chunk_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::ModelUploadCommit => {
+                Self::model_upload_commit_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::ReadKeys => {
+                Self::read_keys_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::ListKeys => {
+                Self::list_keys_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::GetModelInput => {
+                Self::model_get_input_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::AudioPlayWriteNonBlocking => {
+                Self::audio_play_write_nb_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::AudioGetFormat => {
+                Self::audio_get_format_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::ModelGetState => {
+                Self::model_get_state_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::WaitForTimersTimeout => {
+                Self::timer_wait_timeout_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::TimerRemaining => {
+                Self::timer_remaining_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::TimerGetResolution => {
+                Self::timer_resolution_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::AudioSelfTest => {
+                Self::audio_selftest_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::VerifyModelInput => {
+                Self::model_verify_input_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::GetModelOutputRange => {
+                Self::model_output_range_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::GetResourceInfo => {
+                Self::resource_info_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::Shutdown => {
+                Self::shutdown_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::AudioPlayFlush => {
+                Self::audio_play_flush_request(app_id, request_slice, reply_slice)
+            }
+            SDKRuntimeRequest::TransferModel => {
+                Self::model_transfer_request(app_id, request_slice, reply_slice)
+            }
         }
     }
 
@@ -402,8 +545,9 @@ impl SdkRuntimeControlThread {
     ) -> Result<(), SDKError> {
         let request = postcard::from_bytes::<sdk_interface::LogRequest>(request_slice)
             .map_err(deserialize_failure)?;
-        let msg = core::str::from_utf8(request.msg).or(Err(SDKError::InvalidString))?;
-        cantrip_sdk().log(app_id, msg)
+        // NB: the msg is untrusted; sanitize rather than reject
+        let (msg, truncated) = request.msg_lossy();
+        cantrip_sdk().log(app_id, &msg, truncated)
     }
 
     fn read_key_request(
@@ -414,8 +558,27 @@ impl SdkRuntimeControlThread {
         let request = postcard::from_bytes::<sdk_interface::ReadKeyRequest>(request_slice)
             .map_err(deserialize_failure)?;
         let value = cantrip_sdk().read_key(app_id, request.key)?;
-        let _ = postcard::to_slice(&sdk_interface::ReadKeyResponse { value: &value }, reply_slice)
-            .map_err(serialize_failure)?;
+        serialize_reply(&sdk_interface::ReadKeyResponse { value: &value }, reply_slice)?;
+        Ok(())
+    }
+
+    fn read_keys_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::ReadKeysRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        let values = cantrip_sdk().read_keys(app_id, &request.keys)?;
+        serialize_reply(
+            &sdk_interface::ReadKeysResponse {
+                values: values
+                    .iter()
+                    .map(|value| value.as_ref().map(|v| &v[..]).map_err(|e| *e))
+                    .collect(),
+            },
+            reply_slice,
+        )?;
         Ok(())
     }
 
@@ -442,6 +605,33 @@ impl SdkRuntimeControlThread {
         cantrip_sdk().delete_key(app_id, request.key)
     }
 
+    fn sync_keys_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        _reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let _ = postcard::from_bytes::<sdk_interface::SyncKeysRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        cantrip_sdk().sync_keys(app_id)
+    }
+
+    fn list_keys_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let _ = postcard::from_bytes::<sdk_interface::ListKeysRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        let keys = cantrip_sdk().list_keys(app_id)?;
+        serialize_reply(
+            &sdk_interface::ListKeysResponse {
+                keys: keys.iter().map(String::as_str).collect(),
+            },
+            reply_slice,
+        )?;
+        Ok(())
+    }
+
     fn timer_oneshot_request(
         app_id: SDKAppId,
         request_slice: &[u8],
@@ -478,8 +668,19 @@ impl SdkRuntimeControlThread {
         reply_slice: &mut [u8],
     ) -> Result<(), SDKError> {
         let mask = cantrip_sdk().timer_wait(app_id)?;
-        let _ = postcard::to_slice(&sdk_interface::TimerWaitResponse { mask }, reply_slice)
-            .map_err(serialize_failure)?;
+        serialize_reply(&sdk_interface::TimerWaitResponse { mask }, reply_slice)?;
+        Ok(())
+    }
+
+    fn timer_wait_timeout_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::TimerWaitTimeoutRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        let mask = cantrip_sdk().timer_wait_timeout(app_id, request.timeout_ms)?;
+        serialize_reply(&sdk_interface::TimerWaitResponse { mask }, reply_slice)?;
         Ok(())
     }
 
@@ -489,8 +690,39 @@ impl SdkRuntimeControlThread {
         reply_slice: &mut [u8],
     ) -> Result<(), SDKError> {
         let mask = cantrip_sdk().timer_poll(app_id)?;
-        let _ = postcard::to_slice(&sdk_interface::TimerWaitResponse { mask }, reply_slice)
-            .map_err(serialize_failure)?;
+        serialize_reply(&sdk_interface::TimerWaitResponse { mask }, reply_slice)?;
+        Ok(())
+    }
+
+    fn timer_remaining_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::TimerRemainingRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        let remaining_ms = cantrip_sdk().timer_remaining(app_id, request.id)?;
+        serialize_reply(&sdk_interface::TimerRemainingResponse { remaining_ms }, reply_slice)?;
+        Ok(())
+    }
+
+    fn timer_resolution_request(
+        app_id: SDKAppId,
+        _request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let resolution_hz = cantrip_sdk().timer_resolution(app_id)?;
+        serialize_reply(&sdk_interface::TimerResolutionResponse { resolution_hz }, reply_slice)?;
+        Ok(())
+    }
+
+    fn resource_info_request(
+        app_id: SDKAppId,
+        _request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let info = cantrip_sdk().resource_info(app_id)?;
+        serialize_reply(&sdk_interface::ResourceInfoResponse { info }, reply_slice)?;
         Ok(())
     }
 
@@ -502,8 +734,7 @@ impl SdkRuntimeControlThread {
         let request = postcard::from_bytes::<sdk_interface::ModelOneshotRequest>(request_slice)
             .map_err(deserialize_failure)?;
         let id = cantrip_sdk().model_oneshot(app_id, request.model_id)?;
-        let _ = postcard::to_slice(&sdk_interface::ModelStartResponse { id }, reply_slice)
-            .map_err(serialize_failure)?;
+        serialize_reply(&sdk_interface::ModelStartResponse { id }, reply_slice)?;
         Ok(())
     }
 
@@ -515,8 +746,7 @@ impl SdkRuntimeControlThread {
         let request = postcard::from_bytes::<sdk_interface::ModelPeriodicRequest>(request_slice)
             .map_err(deserialize_failure)?;
         let id = cantrip_sdk().model_periodic(app_id, request.model_id, request.duration_ms)?;
-        let _ = postcard::to_slice(&sdk_interface::ModelStartResponse { id }, reply_slice)
-            .map_err(serialize_failure)?;
+        serialize_reply(&sdk_interface::ModelStartResponse { id }, reply_slice)?;
         Ok(())
     }
 
@@ -530,14 +760,23 @@ impl SdkRuntimeControlThread {
         cantrip_sdk().model_cancel(app_id, request.id)
     }
 
+    fn model_transfer_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        _reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::ModelTransferRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        cantrip_sdk().model_transfer(app_id, request.id, request.to_app)
+    }
+
     fn model_wait_request(
         app_id: SDKAppId,
         _request_slice: &[u8],
         reply_slice: &mut [u8],
     ) -> Result<(), SDKError> {
         let mask = cantrip_sdk().model_wait(app_id)?;
-        let _ = postcard::to_slice(&sdk_interface::ModelWaitResponse { mask }, reply_slice)
-            .map_err(serialize_failure)?;
+        serialize_reply(&sdk_interface::ModelWaitResponse { mask }, reply_slice)?;
         Ok(())
     }
 
@@ -547,8 +786,7 @@ impl SdkRuntimeControlThread {
         reply_slice: &mut [u8],
     ) -> Result<(), SDKError> {
         let mask = cantrip_sdk().model_poll(app_id)?;
-        let _ = postcard::to_slice(&sdk_interface::ModelWaitResponse { mask }, reply_slice)
-            .map_err(serialize_failure)?;
+        serialize_reply(&sdk_interface::ModelWaitResponse { mask }, reply_slice)?;
         Ok(())
     }
 
@@ -560,18 +798,20 @@ impl SdkRuntimeControlThread {
         let request = postcard::from_bytes::<sdk_interface::ModelOutputRequest>(request_slice)
             .map_err(deserialize_failure)?;
         let mloutput = cantrip_sdk().model_output(app_id, request.id)?;
-        let _ = postcard::to_slice(
+        serialize_reply(
             &sdk_interface::ModelOutputResponse {
                 output: sdk_interface::ModelOutput {
                     jobnum: mloutput.jobnum,
+                    generation: mloutput.generation,
+                    produced_at_us: mloutput.produced_at_us,
+                    elapsed_us: mloutput.elapsed_us,
                     return_code: mloutput.return_code,
                     epc: mloutput.epc,
                     data: mloutput.data,
                 },
             },
             reply_slice,
-        )
-        .map_err(serialize_failure)?;
+        )?;
         Ok(())
     }
 
@@ -584,11 +824,10 @@ impl SdkRuntimeControlThread {
             postcard::from_bytes::<sdk_interface::ModelGetInputParamsRequest>(request_slice)
                 .map_err(deserialize_failure)?;
         let (id, input_params) = cantrip_sdk().model_get_input_params(app_id, request.model_id)?;
-        let _ = postcard::to_slice(
+        serialize_reply(
             &sdk_interface::ModelGetInputParamsResponse { id, input_params },
             reply_slice,
-        )
-        .map_err(serialize_failure)?;
+        )?;
         Ok(())
     }
 
@@ -608,6 +847,68 @@ impl SdkRuntimeControlThread {
         )
     }
 
+    fn model_verify_input_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        _reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::ModelVerifyInputRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        cantrip_sdk().model_verify_input(app_id, request.id, request.crc)
+    }
+
+    fn model_get_input_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::ModelGetInputRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        let input_data = cantrip_sdk().model_get_input(
+            app_id,
+            request.id,
+            request.input_data_offset,
+            request.len,
+        )?;
+        serialize_reply(
+            &sdk_interface::ModelGetInputResponse {
+                input_data: &input_data,
+            },
+            reply_slice,
+        )?;
+        Ok(())
+    }
+
+    fn model_output_range_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::ModelOutputRangeRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        let data = cantrip_sdk().model_output_range(
+            app_id,
+            request.id,
+            request.offset,
+            request.len,
+            request.clear,
+        )?;
+        serialize_reply(&sdk_interface::ModelOutputRangeResponse { data: &data }, reply_slice)?;
+        Ok(())
+    }
+
+    fn model_get_state_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::ModelGetStateRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        let state = cantrip_sdk().model_get_state(app_id, request.id)?;
+        serialize_reply(&sdk_interface::ModelGetStateResponse { state }, reply_slice)?;
+        Ok(())
+    }
+
     fn audio_reset_request(
         app_id: SDKAppId,
         request_slice: &[u8],
@@ -636,6 +937,7 @@ impl SdkRuntimeControlThread {
             request.rate,
             request.buffer_size,
             request.stop_on_full,
+            request.drop_log_ms,
         )
     }
 
@@ -647,15 +949,42 @@ impl SdkRuntimeControlThread {
         let request =
             postcard::from_bytes::<sdk_interface::AudioRecordCollectRequest>(request_slice)
                 .map_err(deserialize_failure)?;
-        let mut sdk = cantrip_sdk();
-        let data = sdk.audio_record_collect(app_id, request.max_samples, request.wait_if_empty)?;
-        let _ = postcard::to_slice(
+        let max_samples = core::cmp::min(request.max_samples, AUDIO_RECORD_COLLECT_MAX_SAMPLES);
+        // NB: when possible serialize the samples straight from the record
+        //   buffer into the reply frame; otherwise fall back to copying.
+        if cantrip_sdk().audio_record_collect_in_place(
+            app_id,
+            max_samples,
+            request.wait_if_empty,
+            &mut |data, session_ended, dropped| {
+                serialize_reply(
+                    &sdk_interface::AudioRecordCollectResponse {
+                        data: ZeroVec::from_slice_or_alloc(data),
+                        session_ended,
+                        dropped: dropped.try_into().unwrap_or(u32::MAX),
+                    },
+                    reply_slice,
+                )
+            },
+        )? {
+            return Ok(());
+        }
+        // NB: collect directly into a stack buffer sized to fit the reply
+        //   frame; this avoids any heap allocation in the record loop.
+        let mut data = [0u32; AUDIO_RECORD_COLLECT_MAX_SAMPLES];
+        let (count, session_ended, dropped) = cantrip_sdk().audio_record_collect(
+            app_id,
+            &mut data[..max_samples],
+            request.wait_if_empty,
+        )?;
+        serialize_reply(
             &sdk_interface::AudioRecordCollectResponse {
-                data: ZeroVec::from_slice_or_alloc(data),
+                data: ZeroVec::from_slice_or_alloc(&data[..count]),
+                session_ended,
+                dropped: dropped.try_into().unwrap_or(u32::MAX),
             },
             reply_slice,
-        )
-        .map_err(serialize_failure)?;
+        )?;
         Ok(())
     }
 
@@ -674,7 +1003,12 @@ impl SdkRuntimeControlThread {
     ) -> Result<(), SDKError> {
         let request = postcard::from_bytes::<sdk_interface::AudioPlayStartRequest>(request_slice)
             .map_err(deserialize_failure)?;
-        cantrip_sdk().audio_play_start(app_id, request.rate, request.buffer_size)
+        cantrip_sdk().audio_play_start(
+            app_id,
+            request.rate,
+            request.buffer_size,
+            request.wakeup_threshold,
+        )
     }
 
     fn audio_play_write_request(
@@ -687,6 +1021,19 @@ impl SdkRuntimeControlThread {
         cantrip_sdk().audio_play_write(app_id, request.data.to_vec().as_slice())
     }
 
+    fn audio_play_write_nb_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::AudioPlayWriteRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        let accepted =
+            cantrip_sdk().audio_play_write_nb(app_id, request.data.to_vec().as_slice())?;
+        serialize_reply(&sdk_interface::AudioPlayWriteResponse { accepted }, reply_slice)?;
+        Ok(())
+    }
+
     fn audio_play_stop_request(
         app_id: SDKAppId,
         _request_slice: &[u8],
@@ -694,6 +1041,105 @@ impl SdkRuntimeControlThread {
     ) -> Result<(), SDKError> {
         cantrip_sdk().audio_play_stop(app_id)
     }
+
+    fn audio_play_flush_request(
+        app_id: SDKAppId,
+        _request_slice: &[u8],
+        _reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        cantrip_sdk().audio_play_flush(app_id)
+    }
+
+    fn audio_get_state_request(
+        app_id: SDKAppId,
+        _request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let state = cantrip_sdk().audio_get_state(app_id)?;
+        serialize_reply(&sdk_interface::AudioGetStateResponse { state }, reply_slice)?;
+        Ok(())
+    }
+
+    fn audio_get_format_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::AudioGetFormatRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        let format = cantrip_sdk().audio_get_format(app_id, request.direction)?;
+        serialize_reply(&sdk_interface::AudioGetFormatResponse { format }, reply_slice)?;
+        Ok(())
+    }
+
+    fn audio_selftest_request(
+        app_id: SDKAppId,
+        _request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let passed = cantrip_sdk().audio_selftest(app_id)?;
+        serialize_reply(&sdk_interface::AudioSelfTestResponse { passed }, reply_slice)?;
+        Ok(())
+    }
+
+    fn set_fault_policy_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        _reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::SetFaultPolicyRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        cantrip_sdk().set_fault_policy(app_id, request.policy)
+    }
+
+    fn shutdown_request(
+        app_id: SDKAppId,
+        _request_slice: &[u8],
+        _reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        cantrip_sdk().shutdown(app_id)
+    }
+
+    fn model_upload_begin_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::ModelUploadBeginRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        let received = cantrip_sdk().model_upload_begin(app_id, request.model_id, request.size)?;
+        serialize_reply(&sdk_interface::ModelUploadResponse { received }, reply_slice)?;
+        Ok(())
+    }
+
+    fn model_upload_chunk_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request = postcard::from_bytes::<sdk_interface::ModelUploadChunkRequest>(request_slice)
+            .map_err(deserialize_failure)?;
+        // NB: referencing data in the ipc buffer is safe
+        let received = cantrip_sdk().model_upload_chunk(
+            app_id,
+            request.model_id,
+            request.offset,
+            request.data,
+        )?;
+        serialize_reply(&sdk_interface::ModelUploadResponse { received }, reply_slice)?;
+        Ok(())
+    }
+
+    fn model_upload_commit_request(
+        app_id: SDKAppId,
+        request_slice: &[u8],
+        _reply_slice: &mut [u8],
+    ) -> Result<(), SDKError> {
+        let request =
+            postcard::from_bytes::<sdk_interface::ModelUploadCommitRequest>(request_slice)
+                .map_err(deserialize_failure)?;
+        cantrip_sdk().model_upload_commit(app_id, request.model_id, request.signature)
+    }
 }
 
 type SDKManagerResult = Result<(usize, Option<seL4_CPtr>), SDKManagerError>;
@@ -713,22 +1159,26 @@ impl SdkManagerInterfaceThread {
     fn dispatch(
         _client_badge: usize,
         request_buffer: &[u8],
-        _reply_buffer: &mut [u8],
+        reply_buffer: &mut [u8],
     ) -> SDKManagerResult {
         let request = match postcard::from_bytes::<SDKManagerRequest>(request_buffer) {
             Ok(request) => request,
             Err(_) => return Err(SDKManagerError::DeserializeFailed),
         };
         match request {
-            SDKManagerRequest::GetEndpoint(app_id) => Self::get_endpoint_request(app_id),
+            SDKManagerRequest::GetEndpoint(app_id, limits) => {
+                Self::get_endpoint_request(app_id, &limits)
+            }
             SDKManagerRequest::ReleaseEndpoint(app_id) => Self::release_endpoint_request(app_id),
             SDKManagerRequest::Capscan => Self::capscan_request(),
+            SDKManagerRequest::Diagnostics => Self::diagnostics_request(reply_buffer),
+            SDKManagerRequest::Snapshot(first) => Self::snapshot_request(first, reply_buffer),
         }
     }
-    fn get_endpoint_request(app_id: &str) -> SDKManagerResult {
+    fn get_endpoint_request(app_id: &str, limits: &ResourceLimits) -> SDKManagerResult {
         // TODO(283265795): copy app_id from the IPCBuffer
         cantrip_sdk()
-            .get_endpoint(&String::from(app_id))
+            .get_endpoint(&String::from(app_id), limits)
             .map(|cap| (0, Some(cap)))
     }
     fn release_endpoint_request(app_id: &str) -> SDKManagerResult {
@@ -741,6 +1191,17 @@ impl SdkManagerInterfaceThread {
         let _ = Camkes::capscan();
         Ok((0, None))
     }
+    fn diagnostics_request(reply_buffer: &mut [u8]) -> SDKManagerResult {
+        let report = cantrip_sdk().diagnostics();
+        let reply_slice = postcard::to_slice(&DiagnosticsResponse { report }, reply_buffer)
+            .or(Err(SDKManagerError::SerializeFailed))?;
+        Ok((reply_slice.len(), None))
+    }
+    fn snapshot_request(first: usize, reply_buffer: &mut [u8]) -> SDKManagerResult {
+        let snapshot = cantrip_sdk().snapshot();
+        let reply_len = SnapshotResponse::serialize_page(snapshot, first, reply_buffer)?;
+        Ok((reply_len, None))
+    }
 }
 
 // Glue in i2s driver (for now).
@@ -16,10 +16,19 @@
 
 #![cfg_attr(not(test), no_std)]
 
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
 use cantrip_os_common::camkes;
 use cantrip_os_common::cspace_slot::CSpaceSlot;
+use cantrip_os_common::diagnostics::DiagnosticsReport;
 use cantrip_os_common::sel4_sys;
 use num_enum::{FromPrimitive, IntoPrimitive};
+use sdk_interface::AudioState;
+use sdk_interface::FaultPolicy;
+use sdk_interface::ModelId;
+use sdk_interface::SDKAppId;
+use sdk_interface::TimerId;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
@@ -38,6 +47,8 @@ pub enum SDKManagerError {
     ReleaseEndpointFailed,
     #[default]
     UnknownError,
+    AlreadyRegistered,
+    ReplyTooLarge,
 }
 impl From<SDKManagerError> for Result<(), SDKManagerError> {
     fn from(err: SDKManagerError) -> Result<(), SDKManagerError> {
@@ -49,13 +60,42 @@ impl From<SDKManagerError> for Result<(), SDKManagerError> {
     }
 }
 
+/// Per-application resource limits applied when an application's
+/// endpoint is created. Requests that would exceed a limit fail with
+/// SDKError::QuotaExceeded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    pub max_timers: usize,        // Concurrently active timers
+    pub max_models: usize,        // Concurrently loaded models
+    pub max_audio_bytes: usize,   // Total record+play buffer space
+    pub log_bytes_per_sec: usize, // Sustained log output
+    pub max_request_ms: usize,    // Server-side time spent on one request
+}
+impl ResourceLimits {
+    pub const UNLIMITED: Self = Self {
+        max_timers: usize::MAX,
+        max_models: usize::MAX,
+        max_audio_bytes: usize::MAX,
+        log_bytes_per_sec: usize::MAX,
+        max_request_ms: usize::MAX,
+    };
+}
+impl Default for ResourceLimits {
+    fn default() -> Self { Self::UNLIMITED }
+}
+
 /// Rust manager interface for the SDKRuntime.
 pub trait SDKManagerInterface {
     /// Returns a badged endpoint capability setup for making
     /// SDKRuntime requests. The endpoint is meant to be returned
     /// to the caller attached to the IPC buffer. SDKRuntime requests
     /// are rejected unless they arrive through a properly-badged endpoint.
-    fn get_endpoint(&mut self, app_id: &str) -> Result<seL4_CPtr, SDKManagerError>;
+    /// Requests made through the endpoint are constrained by |limits|.
+    fn get_endpoint(
+        &mut self,
+        app_id: &str,
+        limits: &ResourceLimits,
+    ) -> Result<seL4_CPtr, SDKManagerError>;
 
     /// Remove an application badge setup with get_endpoint.
     fn release_endpoint(&mut self, app_id: &str) -> Result<(), SDKManagerError>;
@@ -63,9 +103,102 @@ pub trait SDKManagerInterface {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum SDKManagerRequest<'a> {
-    GetEndpoint(&'a str), // -> cap_endpoint
+    GetEndpoint(&'a str, ResourceLimits), // -> cap_endpoint
     ReleaseEndpoint(&'a str),
     Capscan,
+    Diagnostics,     // -> DiagnosticsResponse
+    Snapshot(usize), // -> SnapshotResponse for apps starting at this index
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiagnosticsResponse {
+    pub report: DiagnosticsReport,
+}
+
+/// An active app timer and the runtime timer id it is mapped to.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TimerSnapshot {
+    pub app_timer_id: TimerId,
+    pub sdk_timer_id: TimerId,
+    pub periodic: bool,
+}
+
+/// An app's model state; the model is identified by name.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ModelSnapshot {
+    None,
+    Idle(String),
+    Oneshot(String),
+    Periodic(String),
+}
+
+/// The runtime state of one app.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AppSnapshot {
+    pub badge: SDKAppId,
+    pub app_id: String,
+    pub timers: Vec<TimerSnapshot>, // Active timers ordered by app timer id
+    pub models: Vec<(ModelId, ModelSnapshot)>, // Loaded models & their runtime id's
+    pub audio: AudioState,
+    pub fault_policy: FaultPolicy,
+    pub log_seq: u32,
+    pub faults: u32,
+}
+
+/// A consistent copy of the SDKRuntime state for debugging (and,
+/// eventually, migrating apps). Apps are ordered by badge.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeSnapshot {
+    pub apps: Vec<AppSnapshot>,
+    pub ids: u32,          // Runtime timer+model id's in use
+    pub pending_mask: u32, // Undelivered events
+    pub released_mask: u32,
+    pub faults: u32,
+}
+
+/// One page of a RuntimeSnapshot; |snapshot.apps| holds as many apps
+/// as fit in the reply starting with the requested index. Each page is
+/// consistent but apps may come and go between pages.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotResponse {
+    pub snapshot: RuntimeSnapshot,
+    pub next: Option<usize>, // Index of the first app not returned
+}
+impl SnapshotResponse {
+    /// Serializes the page of |snapshot| starting with app |first| into
+    /// |buf|, dropping apps from the end until it fits. Returns the
+    /// number of bytes written or ReplyTooLarge if app |first| does not
+    /// fit on its own.
+    pub fn serialize_page(
+        mut snapshot: RuntimeSnapshot,
+        first: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SDKManagerError> {
+        let total = snapshot.apps.len();
+        snapshot.apps.drain(..first.min(total));
+        let mut reply = SnapshotResponse {
+            snapshot,
+            next: None,
+        };
+        loop {
+            let count = reply.snapshot.apps.len();
+            reply.next = if first + count < total {
+                Some(first + count)
+            } else {
+                None
+            };
+            match postcard::to_slice(&reply, buf) {
+                Ok(reply_slice) => return Ok(reply_slice.len()),
+                Err(postcard::Error::SerializeBufferFull) if count > 1 => {
+                    reply.snapshot.apps.pop();
+                }
+                Err(postcard::Error::SerializeBufferFull) => {
+                    return Err(SDKManagerError::ReplyTooLarge)
+                }
+                Err(_) => return Err(SDKManagerError::SerializeFailed),
+            }
+        }
+    }
 }
 
 pub const SDK_MANAGER_REQUEST_DATA_SIZE: usize = 128;
@@ -92,6 +225,7 @@ fn cantrip_sdk_manager_request<D: DeserializeOwned>(
 #[inline]
 pub fn cantrip_sdk_manager_get_endpoint(
     app_id: &str,
+    limits: &ResourceLimits,
     container_slot: &CSpaceSlot,
 ) -> Result<(), SDKManagerError> {
     let _cleanup = container_slot.push_recv_path();
@@ -103,7 +237,7 @@ pub fn cantrip_sdk_manager_get_endpoint(
         sel4_sys::cap_identify(container_slot.slot)
     );
 
-    cantrip_sdk_manager_request(&SDKManagerRequest::GetEndpoint(app_id))
+    cantrip_sdk_manager_request(&SDKManagerRequest::GetEndpoint(app_id, *limits))
 }
 
 #[inline]
@@ -115,3 +249,97 @@ pub fn cantrip_sdk_manager_release_endpoint(app_id: &str) -> Result<(), SDKManag
 pub fn cantrip_sdk_manager_capscan() -> Result<(), SDKManagerError> {
     cantrip_sdk_manager_request(&SDKManagerRequest::Capscan)
 }
+
+#[inline]
+pub fn cantrip_sdk_manager_diagnostics() -> Result<DiagnosticsReport, SDKManagerError> {
+    cantrip_sdk_manager_request(&SDKManagerRequest::Diagnostics)
+        .map(|reply: DiagnosticsResponse| reply.report)
+}
+
+/// Returns the page of the runtime snapshot starting with app |first|;
+/// pass the reply's |next| to get the following page.
+#[inline]
+pub fn cantrip_sdk_manager_snapshot(first: usize) -> Result<SnapshotResponse, SDKManagerError> {
+    cantrip_sdk_manager_request(&SDKManagerRequest::Snapshot(first))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdk_interface::AudioPlayState;
+    use sdk_interface::AudioRecordState;
+
+    fn app(badge: SDKAppId, timers: usize) -> AppSnapshot {
+        AppSnapshot {
+            badge,
+            app_id: String::from("hello"),
+            timers: (0..timers as TimerId)
+                .map(|id| TimerSnapshot {
+                    app_timer_id: id,
+                    sdk_timer_id: id,
+                    periodic: false,
+                })
+                .collect(),
+            models: Vec::new(),
+            audio: AudioState {
+                record: AudioRecordState::Idle,
+                play: AudioPlayState::Idle,
+            },
+            fault_policy: FaultPolicy::default(),
+            log_seq: 0,
+            faults: 0,
+        }
+    }
+
+    fn runtime(apps: Vec<AppSnapshot>) -> RuntimeSnapshot {
+        RuntimeSnapshot {
+            apps,
+            ids: 0,
+            pending_mask: 0,
+            released_mask: 0,
+            faults: 0,
+        }
+    }
+
+    #[test]
+    fn snapshot_pages() {
+        let apps: Vec<AppSnapshot> = (0..10).map(|badge| app(badge, 2)).collect();
+        let mut buf = [0u8; 256];
+
+        // Page through the apps; each reply fits and none are lost.
+        let mut first = 0;
+        let mut pages = 0;
+        let mut got = Vec::new();
+        loop {
+            let len =
+                SnapshotResponse::serialize_page(runtime(apps.clone()), first, &mut buf).unwrap();
+            let page: SnapshotResponse = postcard::from_bytes(&buf[..len]).unwrap();
+            assert!(!page.snapshot.apps.is_empty());
+            got.extend(page.snapshot.apps);
+            pages += 1;
+            match page.next {
+                Some(next) => first = next,
+                None => break,
+            }
+        }
+        assert!(pages > 1);
+        assert_eq!(got, apps);
+
+        // Past the end only the runtime state is returned.
+        let len = SnapshotResponse::serialize_page(runtime(apps), 20, &mut buf).unwrap();
+        let page: SnapshotResponse = postcard::from_bytes(&buf[..len]).unwrap();
+        assert!(page.snapshot.apps.is_empty());
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn snapshot_too_large() {
+        let mut buf = [0u8; 256];
+        let apps = vec![app(1, 1), app(2, 32)];
+        assert!(SnapshotResponse::serialize_page(runtime(apps.clone()), 0, &mut buf).is_ok());
+        assert_eq!(
+            SnapshotResponse::serialize_page(runtime(apps), 1, &mut buf),
+            Err(SDKManagerError::ReplyTooLarge)
+        );
+    }
+}
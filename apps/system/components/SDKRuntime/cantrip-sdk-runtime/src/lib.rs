@@ -15,16 +15,29 @@
 #![cfg_attr(not(test), no_std)]
 #![feature(build_hasher_simple_hash_one)]
 
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
 use cantrip_os_common::camkes::seL4_CPath;
+use cantrip_os_common::diagnostics::{Diagnostics, DiagnosticsReport};
 use cantrip_os_common::sel4_sys;
+use cantrip_sdk_manager::ResourceLimits;
+use cantrip_sdk_manager::RuntimeSnapshot;
 use cantrip_sdk_manager::SDKManagerError;
 use cantrip_sdk_manager::SDKManagerInterface;
 use sdk_interface::error::SDKError;
+use sdk_interface::AudioDirection;
+use sdk_interface::AudioFormat;
+use sdk_interface::AudioRecordCollectFn;
+use sdk_interface::AudioState;
+use sdk_interface::FaultPolicy;
 use sdk_interface::KeyValueData;
 use sdk_interface::ModelId;
 use sdk_interface::ModelInput;
 use sdk_interface::ModelMask;
 use sdk_interface::ModelOutput;
+use sdk_interface::ModelState;
+use sdk_interface::ResourceInfo;
 use sdk_interface::SDKAppId;
 use sdk_interface::SDKRuntimeInterface;
 use sdk_interface::TimerDuration;
@@ -34,8 +47,13 @@ use spin::Mutex;
 use spin::MutexGuard;
 
 use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_FaultTag;
 
 mod runtime;
+#[cfg(feature = "rpc_benchmark")]
+pub use runtime::rpc_clock;
+pub use runtime::FaultAction;
+pub use runtime::FaultInfo;
 use runtime::SDKRuntime;
 
 /// Wrapper around SDKRuntime implementation. Because we have two CAmkES
@@ -70,34 +88,77 @@ impl Guard<'_> {
     }
     // Returns the bundle capacity.
     pub fn capacity(&self) -> usize { self.runtime.as_ref().unwrap().capacity() }
+    // Returns a copy of the runtime state; the lock is held so requests
+    // are quiesced while it is taken.
+    pub fn snapshot(&self) -> RuntimeSnapshot { self.runtime.as_ref().unwrap().snapshot() }
+    // Records an app fault and returns the action to take.
+    pub fn handle_fault(
+        &mut self,
+        app_id: SDKAppId,
+        fault: &seL4_FaultTag,
+    ) -> Result<FaultAction, SDKError> {
+        self.runtime.as_mut().unwrap().handle_fault(app_id, fault)
+    }
+    // Returns Faulted if the app was left suspended by a fault.
+    pub fn check_faulted(&self, app_id: SDKAppId) -> Result<(), SDKError> {
+        self.runtime.as_ref().unwrap().check_faulted(app_id)
+    }
+    // Records the server time of a request that started at |start|.
+    #[cfg(feature = "rpc_benchmark")]
+    pub fn record_rpc(&mut self, app_id: SDKAppId, ping: bool, start: core::time::Duration) {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .record_rpc(app_id, ping, start)
+    }
 }
 // These just lock accesses and handle the necessary indirection.
 impl SDKManagerInterface for Guard<'_> {
-    fn get_endpoint(&mut self, app_id: &str) -> Result<seL4_CPtr, SDKManagerError> {
-        self.runtime.as_mut().unwrap().get_endpoint(app_id)
+    fn get_endpoint(
+        &mut self,
+        app_id: &str,
+        limits: &ResourceLimits,
+    ) -> Result<seL4_CPtr, SDKManagerError> {
+        self.runtime.as_mut().unwrap().get_endpoint(app_id, limits)
     }
     fn release_endpoint(&mut self, app_id: &str) -> Result<(), SDKManagerError> {
         self.runtime.as_mut().unwrap().release_endpoint(app_id)
     }
 }
+impl Diagnostics for Guard<'_> {
+    fn diagnostics(&self) -> DiagnosticsReport { self.runtime.as_ref().unwrap().diagnostics() }
+}
 impl SDKRuntimeInterface for Guard<'_> {
     fn ping(&self, app_id: SDKAppId) -> Result<(), SDKError> {
         self.runtime.as_ref().unwrap().ping(app_id)
     }
-    fn log(&self, app_id: SDKAppId, msg: &str) -> Result<(), SDKError> {
-        self.runtime.as_ref().unwrap().log(app_id, msg)
+    fn log(&mut self, app_id: SDKAppId, msg: &str, truncated: bool) -> Result<(), SDKError> {
+        self.runtime.as_mut().unwrap().log(app_id, msg, truncated)
     }
 
     // Key-value store interfaces.
     fn read_key(&self, app_id: SDKAppId, key: &str) -> Result<KeyValueData, SDKError> {
         self.runtime.as_ref().unwrap().read_key(app_id, key)
     }
+    fn read_keys(
+        &self,
+        app_id: SDKAppId,
+        keys: &[&str],
+    ) -> Result<Vec<Result<KeyValueData, SDKError>>, SDKError> {
+        self.runtime.as_ref().unwrap().read_keys(app_id, keys)
+    }
     fn write_key(&self, app_id: SDKAppId, key: &str, value: &KeyValueData) -> Result<(), SDKError> {
         self.runtime.as_ref().unwrap().write_key(app_id, key, value)
     }
     fn delete_key(&self, app_id: SDKAppId, key: &str) -> Result<(), SDKError> {
         self.runtime.as_ref().unwrap().delete_key(app_id, key)
     }
+    fn sync_keys(&self, app_id: SDKAppId) -> Result<(), SDKError> {
+        self.runtime.as_ref().unwrap().sync_keys(app_id)
+    }
+    fn list_keys(&self, app_id: SDKAppId) -> Result<Vec<String>, SDKError> {
+        self.runtime.as_ref().unwrap().list_keys(app_id)
+    }
 
     // Timer interfaces.
     fn timer_oneshot(
@@ -128,9 +189,28 @@ impl SDKRuntimeInterface for Guard<'_> {
     fn timer_wait(&mut self, app_id: SDKAppId) -> Result<TimerMask, SDKError> {
         self.runtime.as_mut().unwrap().timer_wait(app_id)
     }
+    fn timer_wait_timeout(
+        &mut self,
+        app_id: SDKAppId,
+        timeout_ms: TimerDuration,
+    ) -> Result<TimerMask, SDKError> {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .timer_wait_timeout(app_id, timeout_ms)
+    }
     fn timer_poll(&mut self, app_id: SDKAppId) -> Result<TimerMask, SDKError> {
         self.runtime.as_mut().unwrap().timer_poll(app_id)
     }
+    fn timer_remaining(&self, app_id: SDKAppId, id: TimerId) -> Result<TimerDuration, SDKError> {
+        self.runtime.as_ref().unwrap().timer_remaining(app_id, id)
+    }
+    fn timer_resolution(&self, app_id: SDKAppId) -> Result<u32, SDKError> {
+        self.runtime.as_ref().unwrap().timer_resolution(app_id)
+    }
+    fn resource_info(&self, app_id: SDKAppId) -> Result<ResourceInfo, SDKError> {
+        self.runtime.as_ref().unwrap().resource_info(app_id)
+    }
 
     // Model interfaces.
     fn model_oneshot(&mut self, app_id: SDKAppId, model_id: &str) -> Result<ModelId, SDKError> {
@@ -153,6 +233,17 @@ impl SDKRuntimeInterface for Guard<'_> {
     fn model_cancel(&mut self, app_id: SDKAppId, id: ModelId) -> Result<(), SDKError> {
         self.runtime.as_mut().unwrap().model_cancel(app_id, id)
     }
+    fn model_transfer(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        to_app: &str,
+    ) -> Result<(), SDKError> {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .model_transfer(app_id, id, to_app)
+    }
     fn model_wait(&mut self, app_id: SDKAppId) -> Result<ModelMask, SDKError> {
         self.runtime.as_mut().unwrap().model_wait(app_id)
     }
@@ -184,6 +275,45 @@ impl SDKRuntimeInterface for Guard<'_> {
             .unwrap()
             .model_set_input(app_id, id, input_data_offset, input_data)
     }
+    fn model_get_input(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        input_data_offset: u32,
+        len: u32,
+    ) -> Result<Vec<u8>, SDKError> {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .model_get_input(app_id, id, input_data_offset, len)
+    }
+    fn model_verify_input(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        crc: u32,
+    ) -> Result<(), SDKError> {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .model_verify_input(app_id, id, crc)
+    }
+    fn model_output_range(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        offset: u32,
+        len: u32,
+        clear: bool,
+    ) -> Result<Vec<u8>, SDKError> {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .model_output_range(app_id, id, offset, len, clear)
+    }
+    fn model_get_state(&self, app_id: SDKAppId, id: ModelId) -> Result<ModelState, SDKError> {
+        self.runtime.as_ref().unwrap().model_get_state(app_id, id)
+    }
 
     fn audio_reset(
         &mut self,
@@ -204,22 +334,38 @@ impl SDKRuntimeInterface for Guard<'_> {
         rate: usize,
         buffer_size: usize,
         stop_on_full: bool,
+        drop_log_ms: Option<u32>,
     ) -> Result<(), SDKError> {
+        self.runtime.as_mut().unwrap().audio_record_start(
+            app_id,
+            rate,
+            buffer_size,
+            stop_on_full,
+            drop_log_ms,
+        )
+    }
+    fn audio_record_collect(
+        &mut self,
+        app_id: SDKAppId,
+        data: &mut [u32],
+        wait_if_empty: bool,
+    ) -> Result<(usize, bool, usize), SDKError> {
         self.runtime
             .as_mut()
             .unwrap()
-            .audio_record_start(app_id, rate, buffer_size, stop_on_full)
+            .audio_record_collect(app_id, data, wait_if_empty)
     }
-    fn audio_record_collect(
+    fn audio_record_collect_in_place(
         &mut self,
         app_id: SDKAppId,
         max_samples: usize,
         wait_if_empty: bool,
-    ) -> Result<&[u32], SDKError> {
+        f: &mut AudioRecordCollectFn<'_>,
+    ) -> Result<bool, SDKError> {
         self.runtime
             .as_mut()
             .unwrap()
-            .audio_record_collect(app_id, max_samples, wait_if_empty)
+            .audio_record_collect_in_place(app_id, max_samples, wait_if_empty, f)
     }
     fn audio_record_stop(&mut self, app_id: SDKAppId) -> Result<(), SDKError> {
         self.runtime.as_mut().unwrap().audio_record_stop(app_id)
@@ -230,11 +376,12 @@ impl SDKRuntimeInterface for Guard<'_> {
         app_id: SDKAppId,
         rate: usize,
         buffer_size: usize,
+        wakeup_threshold: Option<usize>,
     ) -> Result<(), SDKError> {
         self.runtime
             .as_mut()
             .unwrap()
-            .audio_play_start(app_id, rate, buffer_size)
+            .audio_play_start(app_id, rate, buffer_size, wakeup_threshold)
     }
     fn audio_play_write(&mut self, app_id: SDKAppId, data: &[u32]) -> Result<(), SDKError> {
         self.runtime
@@ -242,7 +389,79 @@ impl SDKRuntimeInterface for Guard<'_> {
             .unwrap()
             .audio_play_write(app_id, data)
     }
+    fn audio_play_write_nb(&mut self, app_id: SDKAppId, data: &[u32]) -> Result<usize, SDKError> {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .audio_play_write_nb(app_id, data)
+    }
     fn audio_play_stop(&mut self, app_id: SDKAppId) -> Result<(), SDKError> {
         self.runtime.as_mut().unwrap().audio_play_stop(app_id)
     }
+    fn audio_play_flush(&mut self, app_id: SDKAppId) -> Result<(), SDKError> {
+        self.runtime.as_mut().unwrap().audio_play_flush(app_id)
+    }
+    fn audio_get_state(&self, app_id: SDKAppId) -> Result<AudioState, SDKError> {
+        self.runtime.as_ref().unwrap().audio_get_state(app_id)
+    }
+    fn audio_get_format(
+        &self,
+        app_id: SDKAppId,
+        direction: AudioDirection,
+    ) -> Result<AudioFormat, SDKError> {
+        self.runtime
+            .as_ref()
+            .unwrap()
+            .audio_get_format(app_id, direction)
+    }
+    fn audio_selftest(&mut self, app_id: SDKAppId) -> Result<bool, SDKError> {
+        self.runtime.as_mut().unwrap().audio_selftest(app_id)
+    }
+
+    fn set_fault_policy(&mut self, app_id: SDKAppId, policy: FaultPolicy) -> Result<(), SDKError> {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .set_fault_policy(app_id, policy)
+    }
+    fn shutdown(&mut self, app_id: SDKAppId) -> Result<(), SDKError> {
+        self.runtime.as_mut().unwrap().shutdown(app_id)
+    }
+
+    fn model_upload_begin(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        size: usize,
+    ) -> Result<usize, SDKError> {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .model_upload_begin(app_id, model_id, size)
+    }
+
+    fn model_upload_chunk(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<usize, SDKError> {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .model_upload_chunk(app_id, model_id, offset, data)
+    }
+
+    fn model_upload_commit(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        signature: Option<&[u8]>,
+    ) -> Result<(), SDKError> {
+        self.runtime
+            .as_mut()
+            .unwrap()
+            .model_upload_commit(app_id, model_id, signature)
+    }
 }
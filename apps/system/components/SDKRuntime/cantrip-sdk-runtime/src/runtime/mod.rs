@@ -18,60 +18,105 @@
 use cfg_if::cfg_if;
 
 extern crate alloc;
-use alloc::boxed::Box;
 use alloc::string::String;
+use alloc::vec::Vec;
 use bitvec::prelude::*;
 use cantrip_os_common::camkes::seL4_CPath;
 use cantrip_os_common::cspace_slot::CSpaceSlot;
+use cantrip_os_common::diagnostics::{Diagnostics, DiagnosticsReport};
 use cantrip_os_common::sel4_sys;
+use cantrip_sdk_manager::AppSnapshot;
+use cantrip_sdk_manager::ModelSnapshot;
+use cantrip_sdk_manager::ResourceLimits;
+use cantrip_sdk_manager::RuntimeSnapshot;
 use cantrip_sdk_manager::SDKManagerError;
 use cantrip_sdk_manager::SDKManagerInterface;
+use cantrip_sdk_manager::TimerSnapshot;
 use cantrip_security_interface::cantrip_security_delete_key;
+use cantrip_security_interface::cantrip_security_list_keys;
+use cantrip_security_interface::cantrip_security_model_upload_abort;
+use cantrip_security_interface::cantrip_security_model_upload_begin;
+use cantrip_security_interface::cantrip_security_model_upload_chunk;
+use cantrip_security_interface::cantrip_security_model_upload_commit;
 use cantrip_security_interface::cantrip_security_read_key;
+use cantrip_security_interface::cantrip_security_sync_keys;
 use cantrip_security_interface::cantrip_security_write_key;
+use cantrip_security_interface::SecurityRequestError;
+use core::cmp;
+use core::fmt;
 use core::hash::BuildHasher;
-use core::mem::size_of;
+use core::time::Duration;
 use hashbrown::HashMap;
 cfg_if! {
     if #[cfg(feature = "ml_support")] {
         use cantrip_ml_interface::cantrip_mlcoord_cancel;
+        use cantrip_ml_interface::cantrip_mlcoord_completed_jobs_masked;
         use cantrip_ml_interface::cantrip_mlcoord_oneshot;
         use cantrip_ml_interface::cantrip_mlcoord_periodic;
-        use cantrip_ml_interface::cantrip_mlcoord_poll;
-        use cantrip_ml_interface::cantrip_mlcoord_wait;
+        use cantrip_ml_interface::cantrip_mlcoord_poll_masked;
+        use cantrip_ml_interface::cantrip_mlcoord_wait_masked;
         use cantrip_ml_interface::cantrip_mlcoord_get_output;
+        use cantrip_ml_interface::cantrip_mlcoord_get_output_range;
+        use cantrip_ml_interface::cantrip_mlcoord_get_input;
         use cantrip_ml_interface::cantrip_mlcoord_get_input_params;
         use cantrip_ml_interface::cantrip_mlcoord_set_input;
+        use cantrip_ml_interface::cantrip_mlcoord_transfer_model;
+        use cantrip_ml_interface::cantrip_mlcoord_verify_input;
         use cantrip_ml_interface::MlCoordError;
+        use cantrip_ml_interface::MlJobMask;
     }
 }
 cfg_if! {
     if #[cfg(feature = "timer_support")] {
         use cantrip_timer_interface::cantrip_timer_cancel;
+        use cantrip_timer_interface::cantrip_timer_now;
         use cantrip_timer_interface::cantrip_timer_oneshot;
         use cantrip_timer_interface::cantrip_timer_periodic;
         use cantrip_timer_interface::cantrip_timer_poll;
+        use cantrip_timer_interface::cantrip_timer_remaining;
+        use cantrip_timer_interface::cantrip_timer_resolution;
         use cantrip_timer_interface::cantrip_timer_wait;
         use cantrip_timer_interface::TimerServiceError;
     }
 }
-use log::{info, trace};
+use log::{info, trace, warn};
 use sdk_interface::error::SDKError;
+#[cfg(feature = "audio_support")]
+use sdk_interface::AudioConfig;
+use sdk_interface::AudioDirection;
+use sdk_interface::AudioFormat;
+use sdk_interface::AudioPlayState;
+use sdk_interface::AudioRecordCollectFn;
+use sdk_interface::AudioRecordState;
+use sdk_interface::AudioState;
+use sdk_interface::FaultPolicy;
 use sdk_interface::KeyValueData;
 use sdk_interface::ModelId;
 use sdk_interface::ModelInput;
 use sdk_interface::ModelMask;
 use sdk_interface::ModelOutput;
+use sdk_interface::ResourceInfo;
 use sdk_interface::SDKAppId;
 use sdk_interface::SDKRuntimeInterface;
 use sdk_interface::TimerDuration;
 use sdk_interface::TimerId;
 use sdk_interface::TimerMask;
+use sdk_interface::MAX_LOG_MSG_LEN;
+use sdk_interface::MAX_READ_KEYS;
+use sdk_interface::MODEL_GET_INPUT_MAX;
+use sdk_interface::MODEL_OUTPUT_RANGE_MAX;
 use smallstr::SmallString;
 use smallvec::SmallVec;
 
 use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_CapFault_Addr;
+use sel4_sys::seL4_CapFault_IP;
 use sel4_sys::seL4_CapRights;
+use sel4_sys::seL4_FaultTag;
+use sel4_sys::seL4_Word;
+
+mod quota;
+use quota::AppQuota;
 
 // App capacity before spillover to the heap; should be the max concurrent
 // started apps. Set very small because we expect, at least initially, that
@@ -85,18 +130,16 @@ const DEFAULT_BUNDLE_ID_CAPACITY: usize = 64;
 type SmallId = SmallString<[u8; DEFAULT_BUNDLE_ID_CAPACITY]>;
 
 // Each application gets timer & model id's in the range [0..31].
-// There is one active model at any time and it is assigned the
-// last valid id. Timer id's are mapped between application-assigned
-// values and the global SDKRuntime id space. It is possible to exhaust
-// the SDKRuntime id space (since it is only 32).
-const MODEL_ID: ModelId = 31;
-// Max TimerId an application can use.
-const MAX_TIMER_ID: TimerId = (MODEL_ID - 1) as TimerId;
-
-// Size of audio recording buffer (in u32's). This holds samples to be
-// returned to a user via an |audio_record_collect| call. The buffer is
-// allocated on the heap while actively recording.
-const AUDIO_RECORD_CAPACITY: usize = 4096 / size_of::<u32>(); // 4KB XXX maybe match i2s::buffer::BUFFER_CAPACITY
+// Timer id's are mapped between application-assigned values and the
+// global SDKRuntime id space. Model id's are allocated from the same
+// global space and handed to the application as-is. It is possible to
+// exhaust the SDKRuntime id space (since it is only 32).
+
+// Max models an application may have loaded at once.
+const MAX_APP_MODELS: usize = 4;
+// Max TimerId an application can use; an application's timers and
+// models together always fit in the id space.
+const MAX_TIMER_ID: TimerId = (u32::BITS as usize - MAX_APP_MODELS - 1) as TimerId;
 
 #[allow(dead_code)]
 #[derive(PartialEq)]
@@ -137,74 +180,159 @@ impl ModelState {
     }
     pub fn is_idle(&self) -> bool { matches!(self, ModelState::Idle(_)) }
 }
-
-#[allow(dead_code)]
-#[derive(PartialEq)]
-enum AudioRecordState {
-    Idle,
-    Recording(Box<[u32; AUDIO_RECORD_CAPACITY]>),
-}
-#[allow(dead_code)]
-impl AudioRecordState {
-    pub fn is_idle(&self) -> bool { matches!(self, AudioRecordState::Idle) }
-    pub fn is_recording(&self) -> bool { matches!(self, AudioRecordState::Recording(_)) }
-    pub fn get_data(&self, max_samples: usize) -> &[u32] {
-        match self {
-            AudioRecordState::Recording(data) => {
-                &data[..core::cmp::min(max_samples, AUDIO_RECORD_CAPACITY)]
-            }
-            _ => unimplemented!(),
+const NO_MODEL: (ModelId, ModelState) = (0, ModelState::None); // NB: for initializing models
+
+// NB: apps see the state but not the model name
+impl From<&ModelState> for sdk_interface::ModelState {
+    fn from(state: &ModelState) -> Self {
+        match state {
+            ModelState::None => sdk_interface::ModelState::None,
+            ModelState::Idle(_) => sdk_interface::ModelState::Idle,
+            ModelState::Oneshot(_) => sdk_interface::ModelState::Oneshot,
+            ModelState::Periodic(_) => sdk_interface::ModelState::Periodic,
         }
     }
-    pub fn get_data_mut(&mut self, max_samples: usize) -> &mut [u32] {
-        match self {
-            AudioRecordState::Recording(data) => {
-                &mut data[..core::cmp::min(max_samples, AUDIO_RECORD_CAPACITY)]
-            }
-            _ => unimplemented!(),
+}
+impl From<&ModelState> for ModelSnapshot {
+    fn from(state: &ModelState) -> Self {
+        match state {
+            ModelState::None => ModelSnapshot::None,
+            ModelState::Idle(name) => ModelSnapshot::Idle(name.clone()),
+            ModelState::Oneshot(name) => ModelSnapshot::Oneshot(name.clone()),
+            ModelState::Periodic(name) => ModelSnapshot::Periodic(name.clone()),
         }
     }
 }
 
-#[allow(dead_code)]
-#[derive(PartialEq)]
-enum AudioPlayState {
-    Idle,
-    Playing,
+// Warm requests between logs of an app's average request time.
+#[cfg(feature = "rpc_benchmark")]
+const RPC_TIMES_LOG_INTERVAL: u32 = 1000;
+
+// Server time spent on an app's requests, from receipt through mapping
+// the params frame to the reply. The first request other than a Ping is
+// kept apart from the rest so the cost of an app's first real RPC can
+// be compared with and without sdk_prewarm (which sends a Ping).
+#[cfg(feature = "rpc_benchmark")]
+#[derive(Debug, Default, Eq, PartialEq)]
+struct RpcTimes {
+    prewarmed: bool,       // A Ping preceded the first real request
+    first_us: Option<u64>, // First real request
+    warm_us: u64,          // Total for later requests
+    warm_count: u32,       // # later requests
 }
-#[allow(dead_code)]
-impl AudioPlayState {
-    pub fn is_idle(&self) -> bool { matches!(self, AudioPlayState::Idle) }
-    pub fn is_playing(&self) -> bool { matches!(self, AudioPlayState::Playing) }
+#[cfg(feature = "rpc_benchmark")]
+impl RpcTimes {
+    // Records a request that took |elapsed_us|; |ping| is set for Ping
+    // requests. Returns true if this was the first real request.
+    fn record(&mut self, ping: bool, elapsed_us: u64) -> bool {
+        if self.first_us.is_none() {
+            if ping {
+                self.prewarmed = true;
+                return false;
+            }
+            self.first_us = Some(elapsed_us);
+            return true;
+        }
+        self.warm_us = self.warm_us.saturating_add(elapsed_us);
+        self.warm_count = self.warm_count.saturating_add(1);
+        false
+    }
+
+    // Returns the average time of the requests after the first.
+    fn warm_avg_us(&self) -> Option<u64> {
+        (self.warm_count != 0).then(|| self.warm_us / self.warm_count as u64)
+    }
 }
 
 // Per-app runtime state (mostly)  for tracking asynchronous activities:
-// running models and timers. Only one running model is supported. Up to
-// MAX_TIMER_ID timers may active but timers are shared betweenn applications
-// so fewer may be available at any one time. Each application has it's own
-// timer id space that is mapped into the id space of the runtime. The one
-// model is always associated with id |MODEL_ID| which is MAX_TIMER_ID+1.
+// running models and timers. Up to MAX_APP_MODELS models may be loaded
+// and up to MAX_TIMER_ID timers may active but id's are shared betweenn
+// applications so fewer may be available at any one time. Each application
+// has it's own timer id space that is mapped into the id space of the
+// runtime. Each loaded model holds a runtime id for as long as it stays
+// loaded. Resource usage is constrained by the limits given to get_endpoint.
 struct SDKRuntimeState {
     app_id: SmallId,
-    model_state: ModelState,
+    quota: AppQuota,
+    // Loaded models & their runtime id's; ModelState::None marks a free slot
+    models: [(ModelId, ModelState); MAX_APP_MODELS],
     audio_record_state: AudioRecordState,
     audio_play_state: AudioPlayState,
     timer_state: [TimerState; MAX_TIMER_ID as usize + 1],
     // Bitmask of runtime timer id's; use native bit order because the
     // underlying u32 is used directly in timer_wait & timer_poll.
     sdk_timer_mask: BitArray<[u32; 1], Lsb0>,
+    log_seq: u32,        // Sequence # stamped on the next log message
+    log_dropped: u32,    // # log messages dropped by the rate limit
+    log_truncated: bool, // A log message has been truncated
+    fault_policy: FaultPolicy,
+    faults: u32,     // # faults handled
+    suspended: bool, // Faulted and left suspended
+    #[cfg(feature = "rpc_benchmark")]
+    rpc_times: RpcTimes,
 }
 impl SDKRuntimeState {
     // Allocates a runtime state instance for application |app_id|.
-    pub fn new(app_id: &str) -> Self {
+    pub fn new(app_id: &str, limits: &ResourceLimits) -> Self {
         Self {
             app_id: SmallId::from_str(app_id),
-            model_state: ModelState::None,
+            quota: AppQuota::new(limits),
+            models: [NO_MODEL; MAX_APP_MODELS],
             audio_record_state: AudioRecordState::Idle,
             audio_play_state: AudioPlayState::Idle,
             timer_state: [NO_TIMER; MAX_TIMER_ID as usize + 1],
             sdk_timer_mask: BitArray::ZERO,
+            log_seq: 0,
+            log_dropped: 0,
+            log_truncated: false,
+            fault_policy: FaultPolicy::default(),
+            faults: 0,
+            suspended: false,
+            #[cfg(feature = "rpc_benchmark")]
+            rpc_times: RpcTimes::default(),
+        }
+    }
+
+    // Forgets the app's timers, models & audio activity; the caller is
+    // responsible for releasing what they hold.
+    pub fn clear_resources(&mut self) {
+        self.models = [NO_MODEL; MAX_APP_MODELS];
+        self.timer_state = [NO_TIMER; MAX_TIMER_ID as usize + 1];
+        self.sdk_timer_mask = BitArray::ZERO;
+        self.audio_record_state = AudioRecordState::Idle;
+        self.audio_play_state = AudioPlayState::Idle;
+        self.quota.set_record_bytes(0);
+        self.quota.set_play_bytes(0);
+    }
+
+    // Returns the sequence # for the app's next log message. Every
+    // request is numbered, including those rejected by the log quota,
+    // so a consumer can spot missing messages by gaps in the sequence.
+    pub fn next_log_seq(&mut self) -> u32 {
+        let seq = self.log_seq;
+        self.log_seq = self.log_seq.wrapping_add(1);
+        seq
+    }
+
+    // Charges |len| bytes of log output against the app's log rate.
+    // Messages that exceed the rate are counted as dropped. The clock is
+    // read through |now| only when the app's ResourceLimits set a rate;
+    // without a clock there is no rate to enforce.
+    pub fn log_quota(
+        &mut self,
+        now: impl FnOnce() -> Option<Duration>,
+        len: usize,
+    ) -> Result<(), SDKError> {
+        if !self.quota.log_limited() {
+            return Ok(());
+        }
+        if let Some(now) = now() {
+            if let Err(e) = self.quota.log(now, len) {
+                self.log_dropped = self.log_dropped.saturating_add(1);
+                return Err(e);
+            }
         }
+        Ok(())
     }
 
     #[cfg(feature = "timer_support")]
@@ -238,23 +366,242 @@ impl SDKRuntimeState {
         self.timer_state.iter().filter_map(|s| s.get_id())
     }
 
+    // Returns an iterator that enumerates loaded models as (id, name).
+    pub fn model_iter(&self) -> impl Iterator<Item = (ModelId, &str)> + '_ {
+        self.models
+            .iter()
+            .filter_map(|(id, state)| state.get_name().map(|name| (*id, name)))
+    }
+
+    // Returns the name of the loaded model with runtime id |id|.
+    pub fn get_model_name(&self, id: ModelId) -> Result<&str, SDKError> {
+        self.model_iter()
+            .find(|(model_id, _)| *model_id == id)
+            .map(|(_, name)| name)
+            .ok_or(SDKError::NoSuchModel)
+    }
+
+    // Returns the state of the model with runtime id |id|; None if no
+    // model is loaded with |id|.
+    pub fn get_model_state(&self, id: ModelId) -> sdk_interface::ModelState {
+        self.models
+            .iter()
+            .find(|(model_id, state)| *model_id == id && *state != ModelState::None)
+            .map_or(sdk_interface::ModelState::None, |(_, state)| state.into())
+    }
+
+    // Returns the most timers the app may have active at once.
+    pub fn timer_limit(&self) -> usize { cmp::min(self.quota.max_timers(), self.timer_state.len()) }
+
+    // Returns the number of timers the app may still start, ignoring
+    // the runtime id's they need.
+    pub fn timers_left(&self) -> usize {
+        let active = self
+            .timer_state
+            .iter()
+            .filter(|state| **state != TimerState::None)
+            .count();
+        self.timer_limit().saturating_sub(active)
+    }
+
+    // Returns the number of models the app may still load, ignoring
+    // the runtime id's they need.
+    pub fn models_left(&self) -> usize {
+        let loaded = self
+            .models
+            .iter()
+            .filter(|(_, state)| *state != ModelState::None)
+            .count();
+        cmp::min(self.quota.max_models(), self.models.len()).saturating_sub(loaded)
+    }
+
+    // Returns the mask of runtime id's of the loaded models.
+    pub fn model_mask(&self) -> ModelMask {
+        self.model_iter().fold(0, |mask, (id, _)| mask | (1 << id))
+    }
+
+    #[cfg(feature = "ml_support")]
+    // Returns the slot holding model |model_id| if it is loaded.
+    pub fn find_model(&self, model_id: &str) -> Option<usize> {
+        self.models
+            .iter()
+            .position(|(_, state)| state.get_name() == Some(model_id))
+    }
+
+    #[cfg(feature = "ml_support")]
+    // Returns a slot to hold a newly loaded model.
+    pub fn free_model_slot(&self) -> Option<usize> {
+        self.models
+            .iter()
+            .position(|(_, state)| *state == ModelState::None)
+    }
+
+    #[cfg(feature = "ml_support")]
+    // Clears the slot for the model with runtime id |id|.
+    pub fn unload_model(&mut self, id: ModelId) {
+        if let Some(slot) = self
+            .models
+            .iter_mut()
+            .find(|(model_id, state)| *model_id == id && *state != ModelState::None)
+        {
+            *slot = NO_MODEL;
+        }
+    }
+
+    // Returns a copy of the app's state; |badge| is the app's key in
+    // the runtime's table.
+    pub fn snapshot(&self, badge: SDKAppId) -> AppSnapshot {
+        let timers = self
+            .timer_state
+            .iter()
+            .enumerate()
+            .filter_map(|(app_timer_id, state)| {
+                let (sdk_timer_id, periodic) = match state {
+                    TimerState::None => return None,
+                    TimerState::Oneshot(id) => (*id, false),
+                    TimerState::Periodic(id) => (*id, true),
+                };
+                Some(TimerSnapshot {
+                    app_timer_id: app_timer_id as TimerId,
+                    sdk_timer_id,
+                    periodic,
+                })
+            })
+            .collect();
+        AppSnapshot {
+            badge,
+            app_id: String::from(self.app_id.as_str()),
+            timers,
+            models: self
+                .models
+                .iter()
+                .filter(|(_, state)| *state != ModelState::None)
+                .map(|(id, state)| (*id, ModelSnapshot::from(state)))
+                .collect(),
+            audio: AudioState {
+                record: self.audio_record_state,
+                play: self.audio_play_state,
+            },
+            fault_policy: self.fault_policy,
+            log_seq: self.log_seq,
+            faults: self.faults,
+        }
+    }
+
     #[cfg(feature = "ml_support")]
-    // Processes a mask of completed ML jobs. This is simple atm because
-    // at most one model may be loaded at a time and we fix the model id
-    // (and ignore multiple apps running simultaneously)..
+    // Processes a mask of completed ML jobs; job mask bits are taken to
+    // be runtime model id's (and we ignore multiple apps running
+    // simultaneously). Returns the completed jobs that belong to the app.
     pub fn process_completed_jobs(&mut self, mask: ModelMask) -> ModelMask {
-        if (mask & (1 << MODEL_ID)) != 0 {
-            if let ModelState::Oneshot(name) = &self.model_state {
-                // XXX is this safe or do we need to go to None;
-                // the latter would require doing a get_input_params
-                // before every or using a model name instead of id
-                self.model_state = ModelState::Idle(name.clone());
+        for (id, state) in self.models.iter_mut() {
+            if (mask & (1 << *id)) != 0 {
+                if let ModelState::Oneshot(name) = state {
+                    // XXX is this safe or do we need to go to None;
+                    // the latter would require doing a get_input_params
+                    // before every or using a model name instead of id
+                    *state = ModelState::Idle(core::mem::take(name));
+                }
+            }
+        }
+        mask & self.model_mask()
+    }
+}
+
+/// What the SDKRuntime component should do about an app fault; the
+/// bundle to stop (and maybe start) is included as the app state is
+/// released by the stop.
+#[derive(Debug, Eq, PartialEq)]
+pub enum FaultAction {
+    Kill(String),
+    Restart(String),
+    Suspend, // Leave the faulting thread suspended
+}
+
+// Fault message registers not named by sel4_sys; these are the same
+// for every architecture.
+const VMFAULT_IP: usize = 0;
+const VMFAULT_ADDR: usize = 1;
+const USER_EXCEPTION_FAULT_IP: usize = 0;
+
+/// An app fault decoded from the fault message the kernel sends the
+/// SDKRuntime (as the app's fault handler).
+#[derive(Debug, Eq, PartialEq)]
+pub struct FaultInfo {
+    pub name: &'static str,      // Fault type
+    pub ip: Option<seL4_Word>,   // Faulting instruction
+    pub addr: Option<seL4_Word>, // Faulting address (CapFault: the CPtr)
+}
+impl FaultInfo {
+    /// Decodes a |fault| message; |mr| returns the message registers.
+    /// The UnknownSyscall layout is architecture-specific so only the
+    /// fault type is kept.
+    pub fn decode(fault: &seL4_FaultTag, mr: impl Fn(usize) -> seL4_Word) -> Self {
+        let (name, ip, addr) = match fault {
+            seL4_FaultTag::seL4_Fault_NullFault => ("NullFault", None, None),
+            seL4_FaultTag::seL4_Fault_CapFault => {
+                ("CapFault", Some(mr(seL4_CapFault_IP)), Some(mr(seL4_CapFault_Addr)))
+            }
+            seL4_FaultTag::seL4_Fault_UnknownSyscall => ("UnknownSyscall", None, None),
+            seL4_FaultTag::seL4_Fault_UserException => {
+                ("UserException", Some(mr(USER_EXCEPTION_FAULT_IP)), None)
             }
+            seL4_FaultTag::seL4_Fault_VMFault => {
+                ("VMFault", Some(mr(VMFAULT_IP)), Some(mr(VMFAULT_ADDR)))
+            }
+            #[cfg(feature = "CONFIG_KERNEL_MCS")]
+            seL4_FaultTag::seL4_Fault_Timeout => ("Timeout", None, None),
+        };
+        Self { name, ip, addr }
+    }
+}
+impl fmt::Display for FaultInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(ip) = self.ip {
+            write!(f, " ip {:#x}", ip)?;
+        }
+        if let Some(addr) = self.addr {
+            write!(f, " addr {:#x}", addr)?;
         }
-        mask
+        Ok(())
     }
 }
 
+// Calls to other services used to release an app's resources; see
+// SDKRuntime::release_resources. Errors are ignored since the app is
+// giving up whatever it holds. Tests substitute calls that need no seL4.
+#[derive(Clone, Copy)]
+struct ReleaseRpcs {
+    upload_abort: fn(&str),
+    #[cfg(feature = "ml_support")]
+    cancel_model: fn(&str, &str, ModelId),
+    #[cfg(feature = "timer_support")]
+    cancel_timer: fn(TimerId),
+    #[cfg(feature = "audio_support")]
+    audio_reset: fn(),
+}
+impl ReleaseRpcs {
+    const SERVICES: Self = Self {
+        upload_abort: |app_id| {
+            let _ = cantrip_security_model_upload_abort(app_id);
+        },
+        #[cfg(feature = "ml_support")]
+        cancel_model: |app_id, model_id, id| {
+            let _ = ml_cancel(app_id, model_id, id);
+        },
+        #[cfg(feature = "timer_support")]
+        cancel_timer: |timer_id| {
+            let _ = cantrip_timer_cancel(timer_id);
+        },
+        #[cfg(feature = "audio_support")]
+        audio_reset: || {
+            let _ = i2s_driver::audio_reset(
+                /*rxrst=*/ true, /*txrst=*/ true, /*rxilvl=*/ 1, /*txilvl=*/ 1,
+            );
+        },
+    };
+}
+
 /// Kata OS SDK support for third-party applications,
 ///
 /// This is the server-side implementation. There is (currently) one thread
@@ -277,6 +624,11 @@ pub struct SDKRuntime {
     apps: HashMap<SDKAppId, SDKRuntimeState>,
     ids: BitArray<[u32; 1], Lsb0>, // Pool of global timer+model id's
     pending_mask: u32,             // Bitmask of undelivered events
+    // Id's released since events were last collected from the TimerService;
+    // a completion for them may still be queued there.
+    released_mask: u32,
+    faults: u32, // # app faults handled
+    release_rpcs: ReleaseRpcs,
 }
 impl SDKRuntime {
     pub fn new(endpoint: &seL4_CPath) -> Self {
@@ -285,6 +637,9 @@ impl SDKRuntime {
             apps: HashMap::with_capacity(DEFAULT_APP_CAPACITY),
             ids: BitArray::ZERO,
             pending_mask: 0,
+            released_mask: 0,
+            faults: 0,
+            release_rpcs: ReleaseRpcs::SERVICES,
         }
     }
 
@@ -305,6 +660,66 @@ impl SDKRuntime {
 
     pub fn capacity(&self) -> usize { self.apps.capacity() }
 
+    // Returns a copy of the runtime state. The caller holds the runtime
+    // lock (see Guard) so no request can run while the copy is made and
+    // the result is consistent across apps.
+    pub fn snapshot(&self) -> RuntimeSnapshot {
+        let mut apps: Vec<AppSnapshot> = self
+            .apps
+            .iter()
+            .map(|(badge, app)| app.snapshot(*badge))
+            .collect();
+        apps.sort_unstable_by_key(|app| app.badge);
+        RuntimeSnapshot {
+            apps,
+            ids: self.ids.into_inner()[0],
+            pending_mask: self.pending_mask,
+            released_mask: self.released_mask,
+            faults: self.faults,
+        }
+    }
+
+    // Checks |app_id| may be given an endpoint and returns the badge to
+    // use. An app that is already registered is only permitted with the
+    // "reuse_endpoint" feature.
+    fn check_endpoint(&self, app_id: &str) -> Result<SDKAppId, SDKManagerError> {
+        let id = SmallId::from_str(app_id);
+        let badge = self.calculate_badge(&id);
+        match self.apps.get(&badge) {
+            None => Ok(badge),
+            Some(app) if app.app_id != id => {
+                // NB: badge collision between different apps
+                trace!("badge {} for {} already assigned to {}", badge, app_id, app.app_id);
+                Err(SDKManagerError::GetEndpointFailed)
+            }
+            Some(_) if cfg!(feature = "reuse_endpoint") => Ok(badge),
+            Some(_) => Err(SDKManagerError::AlreadyRegistered),
+        }
+    }
+
+    // Creates the runtime state for |app_id| using |badge|. An app that
+    // is already registered (see check_endpoint) keeps its state but is
+    // no longer suspended; the endpoint is for a restarted instance.
+    fn register(&mut self, badge: SDKAppId, app_id: &str, limits: &ResourceLimits) {
+        match self.apps.get_mut(&badge) {
+            Some(app) => app.suspended = false,
+            None => {
+                self.apps
+                    .insert(badge, SDKRuntimeState::new(app_id, limits));
+            }
+        }
+    }
+
+    // Returns the badge of the registered app |app_id|.
+    fn find_app(&self, app_id: &str) -> Result<SDKAppId, SDKError> {
+        let id = SmallId::from_str(app_id);
+        let badge = self.calculate_badge(&id);
+        match self.apps.get(&badge) {
+            Some(app) if app.app_id == id => Ok(badge),
+            _ => Err(SDKError::InvalidBadge),
+        }
+    }
+
     // Wrappers that check for a valid client badge.
     fn get_app(&self, app_id: SDKAppId) -> Result<&SDKRuntimeState, SDKError> {
         self.apps.get(&app_id).ok_or(SDKError::InvalidBadge)
@@ -313,12 +728,134 @@ impl SDKRuntime {
         self.apps.get_mut(&app_id).ok_or(SDKError::InvalidBadge)
     }
 
-    #[cfg(feature = "timer_support")]
-    // Allocates a timer id in the runtime time space.
+    // Records a fault by |app_id| and returns the action to take
+    // according to the app's fault policy. A NullFault is not an error
+    // (e.g. the app exited) so it never causes a restart.
+    pub fn handle_fault(
+        &mut self,
+        app_id: SDKAppId,
+        fault: &seL4_FaultTag,
+    ) -> Result<FaultAction, SDKError> {
+        self.faults += 1;
+        let app = self.get_mut_app(app_id)?;
+        app.faults += 1;
+        let bundle_id = String::from(app.app_id.as_str());
+        Ok(match app.fault_policy {
+            FaultPolicy::Suspend => {
+                app.suspended = true;
+                FaultAction::Suspend
+            }
+            FaultPolicy::LogAndRestart if !matches!(fault, seL4_FaultTag::seL4_Fault_NullFault) => {
+                FaultAction::Restart(bundle_id)
+            }
+            _ => FaultAction::Kill(bundle_id),
+        })
+    }
+
+    // Returns Faulted if |app_id| was left suspended by a fault. Its
+    // requests (e.g. from another thread) are refused until the app is
+    // stopped and the runtime state released.
+    pub fn check_faulted(&self, app_id: SDKAppId) -> Result<(), SDKError> {
+        match self.apps.get(&app_id) {
+            Some(app) if app.suspended => Err(SDKError::Faulted),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "rpc_benchmark")]
+    // Records the server time of a request from |app_id| that started
+    // at |start| (read with rpc_clock); |ping| is set for Ping requests.
+    // The app's first real request is logged as is the average of the
+    // later ones every RPC_TIMES_LOG_INTERVAL requests.
+    pub fn record_rpc(&mut self, app_id: SDKAppId, ping: bool, start: Duration) {
+        let elapsed_us = match now() {
+            Some(end) => end.saturating_sub(start).as_micros() as u64,
+            None => return,
+        };
+        let app = match self.get_mut_app(app_id) {
+            Ok(app) => app,
+            Err(_) => return,
+        };
+        let times = &mut app.rpc_times;
+        if times.record(ping, elapsed_us) {
+            info!(
+                "{}: first RPC {}us{}",
+                app.app_id,
+                elapsed_us,
+                if times.prewarmed { " (prewarmed)" } else { "" }
+            );
+        } else if times.warm_count % RPC_TIMES_LOG_INTERVAL == 0 {
+            if let Some(avg_us) = times.warm_avg_us() {
+                info!("{}: {} RPCs, {}us/rpc", app.app_id, times.warm_count, avg_us);
+            }
+        }
+    }
+
+    // Returns the number of id's alloc_id can hand out.
+    fn free_ids(&self) -> usize {
+        (self.ids.into_inner()[0] | self.released_mask).count_zeros() as usize
+    }
+
+    // Releases the timers, models, audio & any model upload held by |app|.
+    // The app is left holding nothing so this may be repeated (e.g. by a
+    // shutdown request and then by release_endpoint).
+    fn release_resources(&mut self, app: &mut SDKRuntimeState) {
+        let rpcs = self.release_rpcs;
+        // Discard any partially uploaded models.
+        (rpcs.upload_abort)(&app.app_id);
+        // Cleanup app timer & model state.
+        #[cfg(feature = "ml_support")]
+        for (id, name) in app.model_iter() {
+            (rpcs.cancel_model)(&app.app_id, name, id);
+            self.release_model_id(id);
+        }
+        #[cfg(feature = "timer_support")]
+        for timer_id in app.timer_id_iter() {
+            (rpcs.cancel_timer)(timer_id);
+            self.release_id(timer_id);
+        }
+        // NB: the audio hardware is shared; only stop what this app started
+        #[cfg(feature = "audio_support")]
+        if !app.audio_record_state.is_idle() || !app.audio_play_state.is_idle() {
+            (rpcs.audio_reset)();
+        }
+        app.clear_resources();
+    }
+
+    #[cfg(any(feature = "timer_support", feature = "ml_support"))]
+    // Allocates a timer or model id in the runtime id space. Recently
+    // released timer id's are skipped so a stale completion cannot be
+    // delivered for the new timer.
     fn alloc_id(&mut self) -> Option<TimerId> {
-        let bits = self.ids.as_mut_bitslice();
-        let id = bits.first_zero()?;
-        unsafe { bits.set_unchecked(id, true) };
+        self.alloc_id_with(|| {
+            #[cfg(feature = "timer_support")]
+            let mask = cantrip_timer_poll().ok();
+            #[cfg(not(feature = "timer_support"))]
+            let mask = None;
+            mask
+        })
+    }
+
+    #[cfg(any(feature = "timer_support", feature = "ml_support"))]
+    // Does the work of alloc_id. If only released timer id's are left
+    // |poll| is used to collect the TimerService's completions so they
+    // may be reused; otherwise an app that never waits on its timers
+    // could exhaust the pool.
+    fn alloc_id_with(&mut self, poll: impl FnOnce() -> Option<TimerMask>) -> Option<TimerId> {
+        #[cfg(feature = "timer_support")]
+        if (self.ids.into_inner()[0] | self.released_mask) == !0 && self.released_mask != 0 {
+            if let Some(mask) = poll() {
+                self.post_timer_events(mask);
+            }
+        }
+        #[cfg(not(feature = "timer_support"))]
+        let _ = poll;
+        let busy = self.ids.into_inner()[0] | self.released_mask;
+        let id = (!busy).trailing_zeros() as usize;
+        if id >= u32::BITS as usize {
+            return None;
+        }
+        self.ids.set(id, true);
         Some(id as TimerId)
     }
 
@@ -327,6 +864,140 @@ impl SDKRuntime {
     fn release_id(&mut self, id: TimerId) {
         self.ids.set(id as usize, false);
         self.pending_mask &= !(1 << id); // Discard any pending notification
+        self.released_mask |= 1 << id;
+    }
+
+    #[cfg(feature = "ml_support")]
+    // Releases a runtime model id previously allocated with alloc_id.
+    // No completion can be queued in the TimerService for a model id
+    // so it may be reused immediately once the MlCoordinator holds none
+    // (see cancel_job).
+    fn release_model_id(&mut self, id: ModelId) {
+        self.ids.set(id as usize, false);
+        self.pending_mask &= !(1 << id);
+    }
+
+    #[cfg(feature = "ml_support")]
+    // Starts model |model_id| for |app_id| with |start| and records it as
+    // |state|. A model the app already has loaded keeps its slot & id;
+    // otherwise a slot is claimed, subject to the app's quota, and a
+    // runtime id allocated. The id is released if |start| fails. |start|
+    // is passed the app's bundle id and the runtime id; the latter must
+    // be given to the MlCoordinator as the job id so completions can be
+    // matched by process_completed_jobs.
+    fn start_model<T>(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        state: fn(String) -> ModelState,
+        start: impl FnOnce(&str, ModelId) -> Result<T, SDKError>,
+    ) -> Result<(ModelId, T), SDKError> {
+        // NB: cannot hold mutable ref over alloc_id call
+        let app = self.get_app(app_id)?;
+        let (slot, loaded) = match app.find_model(model_id) {
+            Some(slot) => (slot, Some(app.models[slot].0)),
+            None => {
+                app.quota.check_models(app.model_iter().count())?;
+                (app.free_model_slot().ok_or(SDKError::OutOfResources)?, None)
+            }
+        };
+        let id = match loaded {
+            Some(id) => id,
+            None => self.alloc_id().ok_or(SDKError::OutOfResources)?,
+        };
+        let app = unsafe { self.get_mut_app(app_id).unwrap_unchecked() };
+        match start(&app.app_id, id) {
+            Ok(result) => {
+                app.models[slot] = (id, state(model_id.into()));
+                Ok((id, result))
+            }
+            Err(e) => {
+                if loaded.is_none() {
+                    self.release_model_id(id);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    #[cfg(feature = "ml_support")]
+    // Starts a model as start_model does within |app_id|'s request
+    // deadline, read through |now|. Loading a model (e.g. copying its
+    // image) happens in other components and cannot be interrupted so
+    // the deadline is checked when |start| returns; if it has passed, a
+    // model loaded by this request is cancelled with |cancel|, which is
+    // passed the bundle id, model name & runtime id, and unloaded, its
+    // id released, and Timeout returned. A model that was already loaded
+    // is left as it was.
+    fn start_model_timed<T>(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        state: fn(String) -> ModelState,
+        now: impl Fn() -> Option<Duration>,
+        start: impl FnOnce(&str, ModelId) -> Result<T, SDKError>,
+        cancel: impl FnOnce(&str, &str, ModelId) -> Result<(), SDKError>,
+    ) -> Result<(ModelId, T), SDKError> {
+        let app = self.get_app(app_id)?;
+        let deadline = app.quota.deadline(&now);
+        let loaded = app.find_model(model_id).is_some();
+        let (id, result) = self.start_model(app_id, model_id, state, start)?;
+        if loaded || !deadline.expired(&now) {
+            return Ok((id, result));
+        }
+        let app = unsafe { self.get_mut_app(app_id).unwrap_unchecked() };
+        // NB: the model is unloaded even if it cannot be cancelled
+        //   (e.g. a oneshot that already completed)
+        let _ = cancel(&app.app_id, model_id, id);
+        app.unload_model(id);
+        self.release_model_id(id);
+        Err(SDKError::Timeout)
+    }
+
+    #[cfg(feature = "ml_support")]
+    // Moves model |id| loaded by |app_id| to the app with badge |to_id|
+    // using |transfer|, which is passed the bundle id's of both apps and
+    // the model name. The model keeps its runtime id (and so its job id
+    // with the MlCoordinator) but occupies a slot of |to_id|, subject to
+    // its quota; |app_id| can no longer use it.
+    fn move_model(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        to_id: SDKAppId,
+        transfer: impl FnOnce(&str, &str, &str) -> Result<(), SDKError>,
+    ) -> Result<(), SDKError> {
+        let from = self.get_app(app_id)?;
+        let name = from.get_model_name(id)?;
+        let to = self.get_app(to_id)?;
+        if to.find_model(name).is_some() {
+            return Err(SDKError::ModelAlreadyExists);
+        }
+        to.quota.check_models(to.model_iter().count())?;
+        let slot = to.free_model_slot().ok_or(SDKError::OutOfResources)?;
+        transfer(&from.app_id, &to.app_id, name)?;
+
+        let from = unsafe { self.get_mut_app(app_id).unwrap_unchecked() };
+        let from_slot = from
+            .models
+            .iter()
+            .position(|(model_id, state)| *model_id == id && *state != ModelState::None)
+            .unwrap();
+        let model = core::mem::replace(&mut from.models[from_slot], NO_MODEL);
+        let to = unsafe { self.get_mut_app(to_id).unwrap_unchecked() };
+        to.models[slot] = model;
+        Ok(())
+    }
+
+    #[cfg(feature = "timer_support")]
+    // Records timer completions collected from the TimerService (by a
+    // wait, a poll or alloc_id). Events for id's that are not allocated
+    // (e.g. a timer cancelled after it fired) are dropped. Any completion
+    // for a released id was returned by this collection so those id's
+    // may be reused.
+    fn post_timer_events(&mut self, mask: TimerMask) {
+        self.pending_mask |= mask & self.ids.into_inner()[0];
+        self.released_mask = 0;
     }
 
     #[cfg(feature = "timer_support")]
@@ -374,13 +1045,55 @@ impl SDKRuntime {
         }
         Ok(app_mask)
     }
+
+    #[cfg(feature = "timer_support")]
+    // Blocks until one of |app_id|'s timers completes and returns the mask
+    // of app timer id's. Runtime id's in |guard_mask| (not owned by the
+    // app) also end the wait, in which case an empty mask is returned;
+    // their pending events are left for the caller to release.
+    fn wait_for_timers(
+        &mut self,
+        app_id: SDKAppId,
+        guard_mask: TimerMask,
+    ) -> Result<TimerMask, SDKError> {
+        loop {
+            let mut ret_mask = self.get_app(app_id)?.sdk_timer_mask.into_inner()[0];
+            if ret_mask == 0 {
+                // No pending timers for app.
+                return Ok(0);
+            }
+            // Check for pending events.
+            if (self.pending_mask & (ret_mask | guard_mask)) == 0 {
+                // XXX this is blocking
+                let mask = cantrip_timer_wait().map_err(map_timer_err)?;
+                self.post_timer_events(mask);
+            }
+            // Calculate app's events & subtract those from the pending set.
+            ret_mask &= self.pending_mask;
+            self.pending_mask &= !ret_mask;
+            if ret_mask != 0 {
+                // NB:: converts runtime id mask to app id mask for return.
+                return self.process_completed_timers(app_id, ret_mask);
+            }
+            if (self.pending_mask & guard_mask) != 0 {
+                return Ok(0);
+            }
+        }
+    }
 }
 impl SDKManagerInterface for SDKRuntime {
     /// Returns an seL4 Endpoint capability for |app_id| to make SDKRuntime
     /// requests. All requests will fail without first calling
-    /// cantrip_sdk_manager_get_endpoint().
-    fn get_endpoint(&mut self, app_id: &str) -> Result<seL4_CPtr, SDKManagerError> {
-        let badge = self.calculate_badge(&SmallId::from_str(app_id));
+    /// cantrip_sdk_manager_get_endpoint(). Requests that would exceed
+    /// |limits| fail with SDKError::QuotaExceeded. Calling this again
+    /// before release_endpoint fails with SDKManagerError::AlreadyRegistered
+    /// unless built with the "reuse_endpoint" feature.
+    fn get_endpoint(
+        &mut self,
+        app_id: &str,
+        limits: &ResourceLimits,
+    ) -> Result<seL4_CPtr, SDKManagerError> {
+        let badge = self.check_endpoint(app_id)?;
 
         // Mint a badged endpoint for the client to talk to us.
         let mut slot = CSpaceSlot::new();
@@ -397,38 +1110,17 @@ impl SDKManagerInterface for SDKRuntime {
         )
         .or(Err(SDKManagerError::GetEndpointFailed))?;
 
-        // Create the entry & return the endpoint capability.
-        assert!(self
-            .apps
-            .insert(badge, SDKRuntimeState::new(app_id))
-            .is_none());
+        // Create (or revive) the entry & return the endpoint capability.
+        self.register(badge, app_id, limits);
         Ok(slot.release())
     }
 
     /// Releases |app_id| state. No future requests may be made without
     /// first calling cantrip_sdk_manager_get_endpoint().
-    #[allow(unused_variables)]
     fn release_endpoint(&mut self, app_id: &str) -> Result<(), SDKManagerError> {
         let badge = self.calculate_badge(&SmallId::from_str(app_id));
-        if let Some(app) = self.apps.remove(&badge) {
-            // Cleanup app timer & model state.
-            #[cfg(feature = "ml_support")]
-            if let Some(name) = app.model_state.get_name() {
-                let _ = cantrip_mlcoord_cancel(app_id, name);
-                self.pending_mask &= !(1 << MODEL_ID);
-            }
-            #[cfg(feature = "timer_support")]
-            for timer_id in app.timer_id_iter() {
-                let _ = cantrip_timer_cancel(timer_id);
-                self.release_id(timer_id);
-            }
-            #[cfg(feature = "audio_support")]
-            {
-                let _ = i2s_driver::audio_reset(
-                    /*rxrst=*/ true, /*txrst=*/ true, /*rxilvl=*/ 1,
-                    /*txilvl=*/ 1,
-                );
-            }
+        if let Some(mut app) = self.apps.remove(&badge) {
+            self.release_resources(&mut app);
         } else {
             // NB: assumed to be compiled out in release build (no DDOS).
             trace!("release of nonexistent endpoint {}", app_id);
@@ -436,6 +1128,18 @@ impl SDKManagerInterface for SDKRuntime {
         Ok(())
     }
 }
+impl Diagnostics for SDKRuntime {
+    fn diagnostics(&self) -> DiagnosticsReport {
+        let mut report = DiagnosticsReport::new("sdk");
+        report
+            .add("apps", self.apps.len() as u64)
+            .add("capacity", self.capacity() as u64)
+            .add("ids_in_use", self.ids.count_ones() as u64)
+            .add("pending_mask", self.pending_mask as u64)
+            .add("faults", self.faults as u64);
+        report
+    }
+}
 impl SDKRuntimeInterface for SDKRuntime {
     /// Pings the SDK runtime, going from client to server and back via CAmkES IPC.
     fn ping(&self, app_id: SDKAppId) -> Result<(), SDKError> {
@@ -450,10 +1154,17 @@ impl SDKRuntimeInterface for SDKRuntime {
     }
 
     /// Logs |msg| through the system logger.
-    fn log(&self, app_id: SDKAppId, msg: &str) -> Result<(), SDKError> {
-        let app = self.get_app(app_id)?;
-        // NB: app can use this to overflow the heap
-        info!(target: &alloc::format!("[{}]", app.app_id), "{}", msg);
+    fn log(&mut self, app_id: SDKAppId, msg: &str, truncated: bool) -> Result<(), SDKError> {
+        let app = self.get_mut_app(app_id)?;
+        let seq = app.next_log_seq();
+        // NB: |msg| is bounded (see LogRequest::msg_lossy) so an app
+        //   cannot use it to overflow the heap
+        if truncated && !app.log_truncated {
+            warn!("{}: log messages truncated to {} bytes", app.app_id, MAX_LOG_MSG_LEN);
+            app.log_truncated = true;
+        }
+        app.log_quota(now, msg.len())?;
+        info!(target: &alloc::format!("[{}]", app.app_id), "#{} {}", seq, msg);
         Ok(())
     }
 
@@ -463,6 +1174,24 @@ impl SDKRuntimeInterface for SDKRuntime {
         cantrip_security_read_key(&app.app_id, key).or(Err(SDKError::ReadKeyFailed))
     }
 
+    /// Returns the value or error for each of |keys| in the app's private
+    /// key-value store. Asking for more keys than fit in the reply fails
+    /// with SerializeFailed rather than returning only some of them.
+    fn read_keys(
+        &self,
+        app_id: SDKAppId,
+        keys: &[&str],
+    ) -> Result<Vec<Result<KeyValueData, SDKError>>, SDKError> {
+        let app = self.get_app(app_id)?;
+        if keys.len() > MAX_READ_KEYS {
+            return Err(SDKError::SerializeFailed);
+        }
+        Ok(keys
+            .iter()
+            .map(|key| cantrip_security_read_key(&app.app_id, key).or(Err(SDKError::ReadKeyFailed)))
+            .collect())
+    }
+
     /// Writes |value| for the specified |key| in the app's private key-value store.
     fn write_key(&self, app_id: SDKAppId, key: &str, value: &KeyValueData) -> Result<(), SDKError> {
         let app = self.get_app(app_id)?;
@@ -477,6 +1206,21 @@ impl SDKRuntimeInterface for SDKRuntime {
         Ok(())
     }
 
+    /// Persists the app's private key-value store to backing storage.
+    fn sync_keys(&self, app_id: SDKAppId) -> Result<(), SDKError> {
+        let app = self.get_app(app_id)?;
+        cantrip_security_sync_keys(&app.app_id).map_err(|e| match e {
+            SecurityRequestError::NoPlatformSupport => SDKError::NoPlatformSupport,
+            _ => SDKError::WriteKeyFailed,
+        })
+    }
+
+    /// Returns the names of the keys in the app's private key-value store.
+    fn list_keys(&self, app_id: SDKAppId) -> Result<Vec<String>, SDKError> {
+        let app = self.get_app(app_id)?;
+        cantrip_security_list_keys(&app.app_id).or(Err(SDKError::ReadKeyFailed))
+    }
+
     #[allow(unused_variables)]
     fn timer_oneshot(
         &mut self,
@@ -486,12 +1230,15 @@ impl SDKRuntimeInterface for SDKRuntime {
     ) -> Result<(), SDKError> {
         trace!("timer_oneshot id {} duration {}", id, duration_ms);
         // NB: cannot hold mutable ref over alloc_id call
-        let _ = self.get_app(app_id)?;
+        let app = self.get_app(app_id)?;
         if id > MAX_TIMER_ID {
             return Err(SDKError::NoSuchTimer);
         }
         #[cfg(feature = "timer_support")]
         {
+            if app.get_mapping(id).is_none() {
+                app.quota.check_timers(app.timer_id_iter().count())?;
+            }
             let timer_id = self.alloc_id().ok_or(SDKError::OutOfResources)?;
             if let Err(e) = cantrip_timer_oneshot(timer_id, duration_ms) {
                 self.release_id(timer_id);
@@ -515,12 +1262,15 @@ impl SDKRuntimeInterface for SDKRuntime {
     ) -> Result<(), SDKError> {
         trace!("timer_periodic id {} duration {}", id, duration_ms);
         // NB: cannot hold mutable ref over alloc_id call
-        let _ = self.get_app(app_id)?;
+        let app = self.get_app(app_id)?;
         if id > MAX_TIMER_ID {
             return Err(SDKError::NoSuchTimer);
         }
         #[cfg(feature = "timer_support")]
         {
+            if app.get_mapping(id).is_none() {
+                app.quota.check_timers(app.timer_id_iter().count())?;
+            }
             let timer_id = self.alloc_id().ok_or(SDKError::OutOfResources)?;
             if let Err(e) = cantrip_timer_periodic(timer_id, duration_ms) {
                 self.release_id(timer_id);
@@ -562,28 +1312,39 @@ impl SDKRuntimeInterface for SDKRuntime {
         trace!("timer_wait");
         #[cfg(feature = "timer_support")]
         {
-            let mut ret_mask;
-            loop {
-                ret_mask = self.get_app(app_id)?.sdk_timer_mask.into_inner()[0];
-                if ret_mask == 0 {
-                    // No pending timers for app.
-                    break;
-                }
-                // Check for pending events.
-                if (self.pending_mask & ret_mask) == 0 {
-                    // XXX this is blocking
-                    self.pending_mask |= cantrip_timer_wait().map_err(map_timer_err)?;
-                }
-                // Calculate app's events & subtract those from the pending set.
-                ret_mask &= self.pending_mask;
-                self.pending_mask &= !ret_mask;
-                if ret_mask != 0 {
-                    // NB:: converts runtime id mask to app id mask for return.
-                    ret_mask = self.process_completed_timers(app_id, ret_mask)?;
-                    break;
-                }
+            self.wait_for_timers(app_id, 0)
+        }
+
+        #[cfg(not(feature = "timer_support"))]
+        Err(SDKError::NoPlatformSupport)
+    }
+
+    #[allow(unused_variables)]
+    fn timer_wait_timeout(
+        &mut self,
+        app_id: SDKAppId,
+        timeout_ms: TimerDuration,
+    ) -> Result<TimerMask, SDKError> {
+        trace!("timer_wait_timeout {}", timeout_ms);
+        let app = self.get_app(app_id)?;
+        #[cfg(feature = "timer_support")]
+        {
+            if app.sdk_timer_mask.not_any() {
+                // No pending timers for app.
+                return Ok(0);
             }
-            Ok(ret_mask)
+            // Arm a guard timer in the runtime id space; it does not count
+            // against the app's quota. The id is released on every return
+            // so a late completion is discarded.
+            let guard_id = self.alloc_id().ok_or(SDKError::OutOfResources)?;
+            let result = cantrip_timer_oneshot(guard_id, timeout_ms)
+                .map_err(map_timer_err)
+                .and_then(|_| self.wait_for_timers(app_id, 1 << guard_id));
+            if (self.pending_mask & (1 << guard_id)) == 0 {
+                let _ = cantrip_timer_cancel(guard_id);
+            }
+            self.release_id(guard_id);
+            result
         }
 
         #[cfg(not(feature = "timer_support"))]
@@ -598,7 +1359,8 @@ impl SDKRuntimeInterface for SDKRuntime {
         {
             if ret_mask != 0 {
                 if (self.pending_mask & ret_mask) == 0 {
-                    self.pending_mask |= cantrip_timer_poll().map_err(map_timer_err)?;
+                    let mask = cantrip_timer_poll().map_err(map_timer_err)?;
+                    self.post_timer_events(mask);
                 }
                 ret_mask &= self.pending_mask;
                 self.pending_mask &= !ret_mask;
@@ -615,55 +1377,123 @@ impl SDKRuntimeInterface for SDKRuntime {
     }
 
     #[allow(unused_variables)]
-    fn model_oneshot(&mut self, app_id: SDKAppId, model_id: &str) -> Result<ModelId, SDKError> {
-        trace!("model_oneshot {}", model_id);
-        let app = self.get_mut_app(app_id)?;
-        #[cfg(feature = "ml_support")]
+    fn timer_remaining(&self, app_id: SDKAppId, id: TimerId) -> Result<TimerDuration, SDKError> {
+        trace!("timer_remaining id {}", id);
+        let app = self.get_app(app_id)?;
+        if id > MAX_TIMER_ID {
+            return Err(SDKError::NoSuchTimer);
+        }
+        #[cfg(feature = "timer_support")]
         {
-            cantrip_mlcoord_oneshot(&app.app_id, model_id).map_err(map_ml_err)?;
-            app.model_state = ModelState::Oneshot(model_id.into());
-            Ok(MODEL_ID)
+            let timer_id = app.get_mapping(id).ok_or(SDKError::NoSuchTimer)?;
+            let remaining = cantrip_timer_remaining(timer_id).map_err(map_timer_err)?;
+            // NB: saturate rather than wrap for very long durations
+            let remaining_ms = remaining.as_millis();
+            Ok(remaining_ms.try_into().unwrap_or(TimerDuration::MAX))
         }
 
-        #[cfg(not(feature = "ml_support"))]
+        #[cfg(not(feature = "timer_support"))]
         Err(SDKError::NoPlatformSupport)
     }
 
-    #[allow(unused_variables)]
-    fn model_periodic(
-        &mut self,
-        app_id: SDKAppId,
-        model_id: &str,
-        duration_ms: TimerDuration,
+    fn timer_resolution(&self, app_id: SDKAppId) -> Result<u32, SDKError> {
+        trace!("timer_resolution");
+        let _ = self.get_app(app_id)?;
+        #[cfg(feature = "timer_support")]
+        {
+            cantrip_timer_resolution().map_err(map_timer_err)
+        }
+
+        #[cfg(not(feature = "timer_support"))]
+        Err(SDKError::NoPlatformSupport)
+    }
+
+    fn resource_info(&self, app_id: SDKAppId) -> Result<ResourceInfo, SDKError> {
+        trace!("resource_info");
+        let app = self.get_app(app_id)?;
+        // NB: timers & models draw from the same id's so starting one
+        //   may reduce what is available for the other
+        let free_ids = self.free_ids();
+        Ok(ResourceInfo {
+            timers_available: cmp::min(app.timers_left(), free_ids) as u8,
+            timers_max: app.timer_limit() as u8,
+            model_available: cmp::min(app.models_left(), free_ids) as u8,
+            log_dropped: app.log_dropped,
+        })
+    }
+
+    #[allow(unused_variables)]
+    fn model_oneshot(&mut self, app_id: SDKAppId, model_id: &str) -> Result<ModelId, SDKError> {
+        trace!("model_oneshot {}", model_id);
+        #[cfg(feature = "ml_support")]
+        {
+            self.start_model_timed(
+                app_id,
+                model_id,
+                ModelState::Oneshot,
+                now,
+                |app_id, id| {
+                    cantrip_mlcoord_oneshot(app_id, model_id, Some(id)).map_err(map_ml_err)
+                },
+                ml_cancel,
+            )
+            .map(|(id, _)| id)
+        }
+
+        #[cfg(not(feature = "ml_support"))]
+        {
+            let _ = self.get_app(app_id)?;
+            Err(SDKError::NoPlatformSupport)
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn model_periodic(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        duration_ms: TimerDuration,
     ) -> Result<ModelId, SDKError> {
         trace!("model_periodic {} duration {}", model_id, duration_ms);
-        let app = self.get_mut_app(app_id)?;
         #[cfg(feature = "ml_support")]
         {
-            cantrip_mlcoord_periodic(&app.app_id, model_id, duration_ms).map_err(map_ml_err)?;
-            app.model_state = ModelState::Periodic(model_id.into());
-            Ok(MODEL_ID)
+            self.start_model_timed(
+                app_id,
+                model_id,
+                ModelState::Periodic,
+                now,
+                |app_id, id| {
+                    cantrip_mlcoord_periodic(app_id, model_id, duration_ms, Some(id))
+                        .map_err(map_ml_err)
+                },
+                ml_cancel,
+            )
+            .map(|(id, _)| id)
         }
 
         #[cfg(not(feature = "ml_support"))]
-        Err(SDKError::NoPlatformSupport)
+        {
+            let _ = self.get_app(app_id)?;
+            Err(SDKError::NoPlatformSupport)
+        }
     }
 
+    #[allow(unused_variables)]
     fn model_cancel(&mut self, app_id: SDKAppId, id: ModelId) -> Result<(), SDKError> {
         trace!("model_cancel {}", id);
         let app = self.get_mut_app(app_id)?;
-        if id != MODEL_ID {
-            return Err(SDKError::NoSuchModel);
-        }
-        if app.model_state == ModelState::None {
-            return Ok(()); // XXX error?
-        }
+        let name = app.get_model_name(id)?;
         #[cfg(feature = "ml_support")]
         {
-            cantrip_mlcoord_cancel(&app.app_id, app.model_state.get_name().unwrap())
-                .map_err(map_ml_err)?;
+            cancel_job(
+                id,
+                || cantrip_mlcoord_cancel(&app.app_id, name),
+                cantrip_mlcoord_completed_jobs_masked,
+            )
+            .map_err(map_ml_err)?;
             // XXX Idle?
-            app.model_state = ModelState::None;
+            app.unload_model(id);
+            self.release_model_id(id);
             Ok(())
         }
 
@@ -671,18 +1501,43 @@ impl SDKRuntimeInterface for SDKRuntime {
         Err(SDKError::NoPlatformSupport)
     }
 
+    #[allow(unused_variables)]
+    fn model_transfer(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        to_app: &str,
+    ) -> Result<(), SDKError> {
+        trace!("model_transfer {} to {}", id, to_app);
+        let to_id = self.find_app(to_app)?;
+        #[cfg(feature = "ml_support")]
+        {
+            self.move_model(app_id, id, to_id, |from, to, name| {
+                cantrip_mlcoord_transfer_model(from, to, name, Some(id)).map_err(map_ml_err)
+            })
+        }
+
+        #[cfg(not(feature = "ml_support"))]
+        Err(SDKError::NoPlatformSupport)
+    }
+
     fn model_wait(&mut self, app_id: SDKAppId) -> Result<ModelMask, SDKError> {
         trace!("model_wait");
         let app = self.get_mut_app(app_id)?;
-        if app.model_state == ModelState::None {
+        if app.model_mask() == 0 {
             return Ok(0); // Nothing running
         }
         #[cfg(feature = "ml_support")]
         {
             // XXX blocking
-            cantrip_mlcoord_wait()
-                .map_err(map_ml_err)
-                .map(|mask| app.process_completed_jobs(mask))
+            // NB: collect only this app's jobs so others remain pending
+            let interested = app.model_mask();
+            wait_for_jobs(
+                || cantrip_mlcoord_poll_masked(interested.into()).map(|mask| mask as ModelMask),
+                || cantrip_mlcoord_wait_masked(interested.into()).map(|mask| mask as ModelMask),
+            )
+            .map_err(map_ml_err)
+            .map(|mask| app.process_completed_jobs(mask))
         }
 
         #[cfg(not(feature = "ml_support"))]
@@ -692,35 +1547,34 @@ impl SDKRuntimeInterface for SDKRuntime {
     fn model_poll(&mut self, app_id: SDKAppId) -> Result<ModelMask, SDKError> {
         trace!("model_poll");
         let app = self.get_mut_app(app_id)?;
-        if app.model_state == ModelState::None {
+        if app.model_mask() == 0 {
             return Ok(0); // Nothing running
         }
         #[cfg(feature = "ml_support")]
         {
-            cantrip_mlcoord_poll()
+            cantrip_mlcoord_poll_masked(app.model_mask().into())
                 .map_err(map_ml_err)
-                .map(|mask| app.process_completed_jobs(mask))
+                .map(|mask| app.process_completed_jobs(mask as ModelMask))
         }
 
         #[cfg(not(feature = "ml_support"))]
         Err(SDKError::NoPlatformSupport)
     }
 
+    #[allow(unused_variables)]
     fn model_output(&mut self, app_id: SDKAppId, id: ModelId) -> Result<ModelOutput, SDKError> {
         trace!("model_output {}", id);
         let app = self.get_mut_app(app_id)?;
-        if id != MODEL_ID {
-            return Err(SDKError::NoSuchModel);
-        }
-        if app.model_state == ModelState::None {
-            return Err(SDKError::NoSuchModel);
-        }
+        let name = app.get_model_name(id)?;
         #[cfg(feature = "ml_support")]
         {
-            cantrip_mlcoord_get_output(&app.app_id, app.model_state.get_name().unwrap())
+            cantrip_mlcoord_get_output(&app.app_id, name)
                 .map_err(map_ml_err)
                 .map(|output| ModelOutput {
                     jobnum: output.jobnum,
+                    generation: output.generation,
+                    produced_at_us: output.produced_at_us,
+                    elapsed_us: output.elapsed_us,
                     return_code: output.return_code,
                     epc: output.epc,
                     data: output.data,
@@ -738,14 +1592,20 @@ impl SDKRuntimeInterface for SDKRuntime {
         model_id: &str,
     ) -> Result<(ModelId, ModelInput), SDKError> {
         trace!("model_get_input_params {}", model_id);
-        let app = self.get_mut_app(app_id)?;
         #[cfg(feature = "ml_support")]
         {
-            let mlinput =
-                cantrip_mlcoord_get_input_params(&app.app_id, model_id).map_err(map_ml_err)?;
-            app.model_state = ModelState::Idle(model_id.into());
+            let (id, mlinput) = self.start_model_timed(
+                app_id,
+                model_id,
+                ModelState::Idle,
+                now,
+                |app_id, id| {
+                    cantrip_mlcoord_get_input_params(app_id, model_id, Some(id)).map_err(map_ml_err)
+                },
+                ml_cancel,
+            )?;
             Ok((
-                MODEL_ID,
+                id,
                 ModelInput {
                     input_ptr: mlinput.input_ptr,
                     input_size_bytes: mlinput.input_size_bytes,
@@ -754,7 +1614,10 @@ impl SDKRuntimeInterface for SDKRuntime {
         }
 
         #[cfg(not(feature = "ml_support"))]
-        Err(SDKError::NoPlatformSupport)
+        {
+            let _ = self.get_app(app_id)?;
+            Err(SDKError::NoPlatformSupport)
+        }
     }
 
     #[allow(unused_variables)]
@@ -767,28 +1630,99 @@ impl SDKRuntimeInterface for SDKRuntime {
     ) -> Result<(), SDKError> {
         trace!("model_set_input {id} {input_data_offset} {:x?}", input_data);
         let app = self.get_mut_app(app_id)?;
-        if id != MODEL_ID {
-            return Err(SDKError::NoSuchModel);
+        // Require model to be loaded; the MlCoordinator stages the input
+        // for the next run so the model may be running.
+        let name = app.get_model_name(id)?;
+        #[cfg(feature = "ml_support")]
+        {
+            cantrip_mlcoord_set_input(&app.app_id, name, input_data_offset, input_data, None)
+                .map_err(map_ml_err)
+        }
+
+        #[cfg(not(feature = "ml_support"))]
+        Err(SDKError::NoPlatformSupport)
+    }
+
+    #[allow(unused_variables)]
+    fn model_get_input(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        input_data_offset: u32,
+        len: u32,
+    ) -> Result<Vec<u8>, SDKError> {
+        trace!("model_get_input {id} {input_data_offset} {len}");
+        let app = self.get_mut_app(app_id)?;
+        let name = app.get_model_name(id)?;
+        if len as usize > MODEL_GET_INPUT_MAX {
+            return Err(SDKError::InvalidInputRange);
         }
-        // Require model to be loaded+stopped; this may be too conservative.
-        if !app.model_state.is_idle() {
-            return Err(SDKError::NoSuchModel); // XXX need is running error
+        #[cfg(feature = "ml_support")]
+        {
+            cantrip_mlcoord_get_input(&app.app_id, name, input_data_offset, len).map_err(map_ml_err)
         }
+
+        #[cfg(not(feature = "ml_support"))]
+        Err(SDKError::NoPlatformSupport)
+    }
+
+    #[allow(unused_variables)]
+    fn model_verify_input(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        crc: u32,
+    ) -> Result<(), SDKError> {
+        trace!("model_verify_input {id} {crc:#x}");
+        let app = self.get_mut_app(app_id)?;
+        let name = app.get_model_name(id)?;
         #[cfg(feature = "ml_support")]
         {
-            cantrip_mlcoord_set_input(
-                &app.app_id,
-                app.model_state.get_name().unwrap(),
-                input_data_offset,
-                input_data,
-            )
-            .map_err(map_ml_err)
+            cantrip_mlcoord_verify_input(&app.app_id, name, crc).map_err(map_ml_err)
+        }
+
+        #[cfg(not(feature = "ml_support"))]
+        Err(SDKError::NoPlatformSupport)
+    }
+
+    #[allow(unused_variables)]
+    fn model_output_range(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        offset: u32,
+        len: u32,
+        clear: bool,
+    ) -> Result<Vec<u8>, SDKError> {
+        trace!("model_output_range {id} {offset} {len} {clear}");
+        let app = self.get_mut_app(app_id)?;
+        let name = app.get_model_name(id)?;
+        if len as usize > MODEL_OUTPUT_RANGE_MAX {
+            return Err(SDKError::InvalidInputRange);
+        }
+        #[cfg(feature = "ml_support")]
+        {
+            cantrip_mlcoord_get_output_range(&app.app_id, name, offset, len, clear)
+                .map_err(map_ml_err)
         }
 
         #[cfg(not(feature = "ml_support"))]
         Err(SDKError::NoPlatformSupport)
     }
 
+    fn model_get_state(
+        &self,
+        app_id: SDKAppId,
+        id: ModelId,
+    ) -> Result<sdk_interface::ModelState, SDKError> {
+        trace!("model_get_state {id}");
+        let app = self.get_app(app_id)?;
+        if id >= u32::BITS {
+            return Err(SDKError::NoSuchModel);
+        }
+        Ok(app.get_model_state(id))
+    }
+
     #[allow(unused_variables)]
     fn audio_reset(
         &mut self,
@@ -805,9 +1739,11 @@ impl SDKRuntimeInterface for SDKRuntime {
                 i2s_driver::audio_reset(rxrst, txrst, rxilvl, txilvl)?;
                 if rxrst {
                     app.audio_record_state = AudioRecordState::Idle;
+                    app.quota.set_record_bytes(0);
                 }
                 if txrst {
                     app.audio_play_state = AudioPlayState::Idle;
+                    app.quota.set_play_bytes(0);
                 }
                 Ok(())
             } else {
@@ -822,15 +1758,22 @@ impl SDKRuntimeInterface for SDKRuntime {
         rate: usize,
         buffer_size: usize,
         stop_on_full: bool,
+        drop_log_ms: Option<u32>,
     ) -> Result<(), SDKError> {
-        trace!("audio_record_start {rate} {buffer_size} {stop_on_full}");
+        trace!("audio_record_start {rate} {buffer_size} {stop_on_full} {drop_log_ms:?}");
         let app = self.get_mut_app(app_id)?;
         cfg_if! {
             if #[cfg(feature = "audio_support")] {
+                let bytes = audio_buffer_bytes(buffer_size);
+                app.quota.check_record_bytes(bytes)?;
+                let interval = drop_log_ms.map_or(i2s_driver::RX_DROP_LOG_INTERVAL_DEFAULT, |ms| {
+                    Duration::from_millis(ms as u64)
+                });
+                i2s_driver::audio_set_drop_log(interval, now);
                 i2s_driver::audio_record_start(rate, buffer_size, stop_on_full)?;
-                // XXX buffer_size
-                // XXX new_uninit
-                app.audio_record_state = AudioRecordState::Recording(Box::new([0u32; AUDIO_RECORD_CAPACITY]));
+                app.audio_record_state =
+                    AudioRecordState::Recording(AudioConfig { rate, buffer_size });
+                app.quota.set_record_bytes(bytes);
                 Ok(())
             } else {
                 Err(SDKError::NoPlatformSupport)
@@ -839,22 +1782,43 @@ impl SDKRuntimeInterface for SDKRuntime {
     }
     #[allow(unused_variables)]
     fn audio_record_collect(
+        &mut self,
+        app_id: SDKAppId,
+        data: &mut [u32],
+        wait_if_empty: bool,
+    ) -> Result<(usize, bool, usize), SDKError> {
+        trace!("audio_record_collect {}", data.len());
+        let app = self.get_mut_app(app_id)?;
+        if !app.audio_record_state.is_recording() {
+            return Err(SDKError::InvalidAudioState);
+        }
+        cfg_if! {
+            if #[cfg(feature = "audio_support")] {
+                i2s_driver::audio_record_collect(data, wait_if_empty, request_expired(&app.quota))
+            } else {
+                Err(SDKError::NoPlatformSupport)
+            }
+        }
+    }
+    #[allow(unused_variables)]
+    fn audio_record_collect_in_place(
         &mut self,
         app_id: SDKAppId,
         max_samples: usize,
         wait_if_empty: bool,
-    ) -> Result<&[u32], SDKError> {
-        trace!("audio_record_collect {max_samples}");
+        f: &mut AudioRecordCollectFn<'_>,
+    ) -> Result<bool, SDKError> {
+        trace!("audio_record_collect_in_place {}", max_samples);
         let app = self.get_mut_app(app_id)?;
         if !app.audio_record_state.is_recording() {
             return Err(SDKError::InvalidAudioState);
         }
         cfg_if! {
             if #[cfg(feature = "audio_support")] {
-                let data = app.audio_record_state.get_data_mut(max_samples);
-                // XXX pin?
-                let count = i2s_driver::audio_record_collect(data, wait_if_empty)?;
-                Ok(&data[..count])
+                match i2s_driver::audio_record_collect_in_place(max_samples, wait_if_empty, f) {
+                    Some(result) => result.map(|_| true),
+                    None => Ok(false),
+                }
             } else {
                 Err(SDKError::NoPlatformSupport)
             }
@@ -868,6 +1832,7 @@ impl SDKRuntimeInterface for SDKRuntime {
             if #[cfg(feature = "audio_support")] {
                 i2s_driver::audio_record_stop()?;
                 app.audio_record_state = AudioRecordState::Idle;
+                app.quota.set_record_bytes(0);
                 Ok(())
             } else {
                 Err(SDKError::NoPlatformSupport)
@@ -881,13 +1846,17 @@ impl SDKRuntimeInterface for SDKRuntime {
         app_id: SDKAppId,
         rate: usize,
         buffer_size: usize,
+        wakeup_threshold: Option<usize>,
     ) -> Result<(), SDKError> {
-        trace!("audio_play_start {rate} {buffer_size}");
+        trace!("audio_play_start {rate} {buffer_size} {wakeup_threshold:?}");
         let app = self.get_mut_app(app_id)?;
         cfg_if! {
             if #[cfg(feature = "audio_support")] {
-                i2s_driver::audio_play_start(rate, buffer_size)?;
-                app.audio_play_state = AudioPlayState::Playing;
+                let bytes = audio_buffer_bytes(buffer_size);
+                app.quota.check_play_bytes(bytes)?;
+                i2s_driver::audio_play_start(rate, buffer_size, wakeup_threshold)?;
+                app.audio_play_state = AudioPlayState::Playing(AudioConfig { rate, buffer_size });
+                app.quota.set_play_bytes(bytes);
                 Ok(())
             } else {
                 Err(SDKError::NoPlatformSupport)
@@ -904,7 +1873,22 @@ impl SDKRuntimeInterface for SDKRuntime {
         cfg_if! {
             if #[cfg(feature = "audio_support")] {
                 // XXX async + double-buffer?
-                i2s_driver::audio_play_write(data)
+                i2s_driver::audio_play_write(data, request_expired(&app.quota))
+            } else {
+                Err(SDKError::NoPlatformSupport)
+            }
+        }
+    }
+    #[allow(unused_variables)]
+    fn audio_play_write_nb(&mut self, app_id: SDKAppId, data: &[u32]) -> Result<usize, SDKError> {
+        trace!("audio_play_write_nb {}", data.len());
+        let app = self.get_mut_app(app_id)?;
+        if !app.audio_play_state.is_playing() {
+            return Err(SDKError::InvalidAudioState);
+        }
+        cfg_if! {
+            if #[cfg(feature = "audio_support")] {
+                Ok(i2s_driver::audio_play_write_nb(data))
             } else {
                 Err(SDKError::NoPlatformSupport)
             }
@@ -916,14 +1900,160 @@ impl SDKRuntimeInterface for SDKRuntime {
         let app = self.get_mut_app(app_id)?;
         cfg_if! {
             if #[cfg(feature = "audio_support")] {
-                i2s_driver::audio_play_stop()?;
+                let result = i2s_driver::audio_play_stop(request_expired(&app.quota));
+                // NB: playback is stopped even if draining timed out
                 app.audio_play_state = AudioPlayState::Idle;
+                app.quota.set_play_bytes(0);
+                result
+            } else {
+                Err(SDKError::NoPlatformSupport)
+            }
+        }
+    }
+    fn audio_play_flush(&mut self, app_id: SDKAppId) -> Result<(), SDKError> {
+        trace!("audio_play_flush");
+        let app = self.get_app(app_id)?;
+        if !app.audio_play_state.is_playing() {
+            return Err(SDKError::InvalidAudioState);
+        }
+        cfg_if! {
+            if #[cfg(feature = "audio_support")] {
+                i2s_driver::audio_play_flush();
                 Ok(())
             } else {
                 Err(SDKError::NoPlatformSupport)
             }
         }
     }
+    fn audio_get_state(&self, app_id: SDKAppId) -> Result<AudioState, SDKError> {
+        trace!("audio_get_state");
+        let app = self.get_app(app_id)?;
+        Ok(AudioState {
+            record: app.audio_record_state,
+            play: app.audio_play_state,
+        })
+    }
+    #[allow(unused_variables)]
+    fn audio_get_format(
+        &self,
+        app_id: SDKAppId,
+        direction: AudioDirection,
+    ) -> Result<AudioFormat, SDKError> {
+        trace!("audio_get_format {:?}", direction);
+        let _app = self.get_app(app_id)?;
+        cfg_if! {
+            if #[cfg(feature = "audio_support")] {
+                Ok(i2s_driver::audio_get_format(direction))
+            } else {
+                Err(SDKError::NoPlatformSupport)
+            }
+        }
+    }
+    fn audio_selftest(&mut self, app_id: SDKAppId) -> Result<bool, SDKError> {
+        trace!("audio_selftest");
+        let _app = self.get_app(app_id)?;
+        cfg_if! {
+            if #[cfg(feature = "audio_support")] {
+                // NB: a failed test is a result not an error
+                match i2s_driver::audio_selftest() {
+                    Ok(()) => Ok(true),
+                    Err(SDKError::SelfTestFailed) => Ok(false),
+                    Err(e) => Err(e),
+                }
+            } else {
+                Err(SDKError::NoPlatformSupport)
+            }
+        }
+    }
+
+    /// Sets the action taken when the app faults.
+    fn set_fault_policy(&mut self, app_id: SDKAppId, policy: FaultPolicy) -> Result<(), SDKError> {
+        trace!("set_fault_policy {:?}", policy);
+        self.get_mut_app(app_id)?.fault_policy = policy;
+        Ok(())
+    }
+
+    /// Releases everything the app holds but keeps it registered so it
+    /// can still make requests; see release_resources.
+    fn shutdown(&mut self, app_id: SDKAppId) -> Result<(), SDKError> {
+        trace!("shutdown");
+        // NB: take the app out of the table so its resources can be
+        //   released back to the runtime
+        let mut app = self.apps.remove(&app_id).ok_or(SDKError::InvalidBadge)?;
+        self.release_resources(&mut app);
+        self.apps.insert(app_id, app);
+        Ok(())
+    }
+
+    /// Starts or resumes an upload of |model_id|; returns the bytes received.
+    fn model_upload_begin(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        size: usize,
+    ) -> Result<usize, SDKError> {
+        trace!("model_upload_begin {} size {}", model_id, size);
+        let app = self.get_app(app_id)?;
+        cantrip_security_model_upload_begin(&app.app_id, model_id, size).map_err(map_upload_err)
+    }
+
+    /// Writes |data| at |offset| of |model_id|; returns the bytes received.
+    fn model_upload_chunk(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<usize, SDKError> {
+        trace!("model_upload_chunk {} offset {} len {}", model_id, offset, data.len());
+        let app = self.get_app(app_id)?;
+        cantrip_security_model_upload_chunk(&app.app_id, model_id, offset, data)
+            .map_err(map_upload_err)
+    }
+
+    /// Installs the uploaded |model_id|.
+    fn model_upload_commit(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        signature: Option<&[u8]>,
+    ) -> Result<(), SDKError> {
+        trace!("model_upload_commit {}", model_id);
+        let app = self.get_app(app_id)?;
+        cantrip_security_model_upload_commit(&app.app_id, model_id, signature)
+            .map_err(map_upload_err)
+    }
+}
+
+// Returns the clock used to time requests; see SDKRuntime::record_rpc.
+#[cfg(feature = "rpc_benchmark")]
+pub fn rpc_clock() -> Option<Duration> { now() }
+
+// Returns the current time for rate limiting, if a clock is available.
+fn now() -> Option<Duration> {
+    cfg_if! {
+        if #[cfg(feature = "timer_support")] {
+            cantrip_timer_now().ok()
+        } else {
+            None
+        }
+    }
+}
+
+// Returns a check of whether a blocking audio request that starts now is
+// past the deadline set by |quota|, or None if requests have no deadline.
+#[cfg(feature = "audio_support")]
+fn request_expired(quota: &AppQuota) -> Option<impl Fn() -> bool> {
+    let deadline = quota.deadline(now);
+    quota
+        .request_limited()
+        .then(|| move || deadline.expired(now))
+}
+
+// Returns the size in bytes of an audio buffer of |buffer_size| samples.
+#[cfg(feature = "audio_support")]
+fn audio_buffer_bytes(buffer_size: usize) -> usize {
+    buffer_size.saturating_mul(core::mem::size_of::<u32>())
 }
 
 #[cfg(feature = "timer_support")]
@@ -938,10 +2068,55 @@ fn map_timer_err(err: TimerServiceError) -> SDKError {
     }
 }
 
+// Collects completed jobs with |poll|, blocking with |wait| until there
+// are some. The MlCoordinator signals all apps through one notification
+// so a wakeup may be for another app's job, and the signal for this
+// app's job may already have been consumed by another app collecting
+// its own: check what is pending before blocking and block again if
+// nothing collected belongs to the caller.
+#[cfg(feature = "ml_support")]
+fn wait_for_jobs<E>(
+    poll: impl FnOnce() -> Result<ModelMask, E>,
+    mut wait: impl FnMut() -> Result<ModelMask, E>,
+) -> Result<ModelMask, E> {
+    let mut mask = poll()?;
+    while mask == 0 {
+        mask = wait()?;
+    }
+    Ok(mask)
+}
+
+// Cancels model |id| with |cancel| and then discards its completion
+// with |collect|. The MlCoordinator marks a cancelled model's job
+// complete; left pending, the bit would be reported to whichever model
+// is next given |id|.
+#[cfg(feature = "ml_support")]
+fn cancel_job<E>(
+    id: ModelId,
+    cancel: impl FnOnce() -> Result<(), E>,
+    collect: impl FnOnce(MlJobMask) -> Result<MlJobMask, E>,
+) -> Result<(), E> {
+    cancel()?;
+    collect(1 << id).map(|_| ())
+}
+
+// Cancels model |model_id| of |app_id| with runtime id |id| and collects
+// its job; see cancel_job.
+#[cfg(feature = "ml_support")]
+fn ml_cancel(app_id: &str, model_id: &str, id: ModelId) -> Result<(), SDKError> {
+    cancel_job(
+        id,
+        || cantrip_mlcoord_cancel(app_id, model_id),
+        cantrip_mlcoord_completed_jobs_masked,
+    )
+    .map_err(map_ml_err)
+}
+
 #[cfg(feature = "ml_support")]
 fn map_ml_err(err: MlCoordError) -> SDKError {
     match err {
-        MlCoordError::NoSuchModel | MlCoordError::InvalidImage => SDKError::NoSuchModel,
+        MlCoordError::NoSuchModel => SDKError::NoSuchModel,
+        MlCoordError::InvalidImage => SDKError::InvalidModelImage,
         MlCoordError::InvalidTimer => SDKError::InvalidTimer,
         MlCoordError::LoadModelFailed => SDKError::LoadModelFailed,
         MlCoordError::NoModelSlotsLeft => SDKError::OutOfResources,
@@ -951,5 +2126,1027 @@ fn map_ml_err(err: MlCoordError) -> SDKError {
         MlCoordError::UnknownError => unreachable!(),
         MlCoordError::Success => unreachable!(),
         MlCoordError::InvalidInputRange => SDKError::InvalidInputRange,
+        MlCoordError::InvalidModelState => SDKError::InvalidModelState,
+        MlCoordError::ModelAlreadyLoaded => SDKError::ModelAlreadyExists,
+        MlCoordError::InputChecksumMismatch => SDKError::InputChecksumMismatch,
+        // NB: runtime model id's always fit the job mask
+        MlCoordError::InvalidJobId => SDKError::OutOfResources,
+        MlCoordError::OutOfMemory => SDKError::OutOfResources,
+        MlCoordError::InputNotSupported => SDKError::NoPlatformSupport,
+    }
+}
+
+fn map_upload_err(err: SecurityRequestError) -> SDKError {
+    match err {
+        SecurityRequestError::DeleteFirst => SDKError::ModelAlreadyExists,
+        SecurityRequestError::UploadNotFound => SDKError::NoSuchModel,
+        SecurityRequestError::UploadOffsetInvalid => SDKError::InvalidUploadOffset,
+        SecurityRequestError::UploadIncomplete => SDKError::UploadIncomplete,
+        SecurityRequestError::TooManyBundles | SecurityRequestError::UploadQuotaExceeded => {
+            SDKError::QuotaExceeded
+        }
+        SecurityRequestError::BundleDataInvalid | SecurityRequestError::SignatureInvalid => {
+            SDKError::InvalidModelImage
+        }
+        _ => SDKError::LoadModelFailed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Does the registration part of get_endpoint; minting the endpoint
+    // capability requires seL4.
+    fn get_endpoint_limited(
+        sdk: &mut SDKRuntime,
+        app_id: &str,
+        limits: &ResourceLimits,
+    ) -> Result<SDKAppId, SDKManagerError> {
+        let badge = sdk.check_endpoint(app_id)?;
+        sdk.register(badge, app_id, limits);
+        Ok(badge)
+    }
+    fn get_endpoint(sdk: &mut SDKRuntime, app_id: &str) -> Result<SDKAppId, SDKManagerError> {
+        get_endpoint_limited(sdk, app_id, &ResourceLimits::default())
+    }
+
+    #[test]
+    fn get_endpoint_twice() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let badge = get_endpoint(&mut sdk, "hello").unwrap();
+        if cfg!(feature = "reuse_endpoint") {
+            assert_eq!(get_endpoint(&mut sdk, "hello"), Ok(badge));
+        } else {
+            assert_eq!(
+                get_endpoint(&mut sdk, "hello"),
+                Err(SDKManagerError::AlreadyRegistered)
+            );
+        }
+        assert_eq!(sdk.apps.len(), 1);
+
+        // Other apps are unaffected.
+        assert!(get_endpoint(&mut sdk, "world").is_ok());
+        assert_eq!(sdk.apps.len(), 2);
+
+        // Once released the app may register again.
+        assert!(sdk.apps.remove(&badge).is_some());
+        assert_eq!(get_endpoint(&mut sdk, "hello"), Ok(badge));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let report = sdk.diagnostics();
+        assert_eq!(report.component, "sdk");
+        assert_eq!(report.get("apps"), Some(0));
+        assert_eq!(report.get("ids_in_use"), Some(0));
+
+        assert!(get_endpoint(&mut sdk, "hello").is_ok());
+        assert!(get_endpoint(&mut sdk, "world").is_ok());
+        let report = sdk.diagnostics();
+        assert_eq!(report.get("apps"), Some(2));
+        assert_eq!(report.get("capacity"), Some(sdk.capacity() as u64));
+        assert_eq!(report.get("pending_mask"), Some(0));
+        assert_eq!(report.get("faults"), Some(0));
+        assert_eq!(report.len(), 5);
+    }
+
+    #[test]
+    fn log_seq_per_app() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let world = get_endpoint(&mut sdk, "world").unwrap();
+        let mut next = |app_id| sdk.get_mut_app(app_id).unwrap().next_log_seq();
+        assert_eq!(next(hello), 0);
+        assert_eq!(next(hello), 1);
+        // Each app has its own sequence.
+        assert_eq!(next(world), 0);
+        assert_eq!(next(hello), 2);
+        assert_eq!(next(world), 1);
+    }
+
+    #[test]
+    fn log_seq_wraps() {
+        let mut state = SDKRuntimeState::new("hello", &ResourceLimits::default());
+        state.log_seq = u32::MAX;
+        assert_eq!(state.next_log_seq(), u32::MAX);
+        assert_eq!(state.next_log_seq(), 0);
+    }
+
+    #[test]
+    fn log_truncate() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        assert_eq!(sdk.log(hello, "hello", false), Ok(()));
+        assert!(!sdk.get_app(hello).unwrap().log_truncated);
+
+        // The first truncated message is noted.
+        let data = [b'x'; MAX_LOG_MSG_LEN + 1];
+        let (msg, truncated) = sdk_interface::LogRequest { msg: &data }.msg_lossy();
+        assert_eq!(sdk.log(hello, &msg, truncated), Ok(()));
+        assert!(sdk.get_app(hello).unwrap().log_truncated);
+    }
+
+    #[test]
+    fn get_endpoint_limits() {
+        let limits = ResourceLimits {
+            max_timers: 1,
+            max_models: 1,
+            max_audio_bytes: 1024,
+            log_bytes_per_sec: 10,
+            max_request_ms: 5,
+        };
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint_limited(&mut sdk, "hello", &limits).unwrap();
+        let world = get_endpoint(&mut sdk, "world").unwrap();
+
+        // The limits apply to the app registered with them and no other.
+        let info = sdk.resource_info(hello).unwrap();
+        assert_eq!((info.timers_max, info.model_available), (1, 1));
+        let info = sdk.resource_info(world).unwrap();
+        assert_eq!(info.timers_max, MAX_TIMER_ID as u8 + 1);
+        assert_eq!(info.model_available, MAX_APP_MODELS as u8);
+
+        let t0 = || Some(Duration::from_secs(1));
+        let app = sdk.get_mut_app(hello).unwrap();
+        assert_eq!(app.quota.check_timers(1), Err(SDKError::QuotaExceeded));
+        assert_eq!(app.quota.check_models(1), Err(SDKError::QuotaExceeded));
+        assert_eq!(app.quota.check_record_bytes(1025), Err(SDKError::QuotaExceeded));
+        assert!(app.quota.request_limited());
+        assert_eq!(app.log_quota(t0, 10), Ok(()));
+        assert_eq!(app.log_quota(t0, 1), Err(SDKError::QuotaExceeded));
+        assert_eq!(sdk.resource_info(hello).unwrap().log_dropped, 1);
+
+        let app = sdk.get_mut_app(world).unwrap();
+        assert_eq!(app.quota.check_timers(1), Ok(()));
+        assert!(!app.quota.request_limited());
+        assert_eq!(app.log_quota(t0, 4096), Ok(()));
+        assert_eq!(sdk.resource_info(world).unwrap().log_dropped, 0);
+    }
+
+    #[test]
+    fn log_dropped() {
+        let limits = ResourceLimits {
+            log_bytes_per_sec: 10,
+            ..ResourceLimits::default()
+        };
+        let mut state = SDKRuntimeState::new("hello", &limits);
+        let t0 = || Some(Duration::from_secs(1));
+        assert_eq!(state.log_quota(t0, 10), Ok(()));
+        assert_eq!(state.log_quota(t0, 1), Err(SDKError::QuotaExceeded));
+        assert_eq!(state.log_quota(t0, 1), Err(SDKError::QuotaExceeded));
+        assert_eq!(state.log_dropped, 2);
+        // Nothing is enforced without a clock.
+        assert_eq!(state.log_quota(|| None, 100), Ok(()));
+        assert_eq!(state.log_dropped, 2);
+    }
+
+    #[test]
+    fn log_unlimited_skips_clock() {
+        let mut state = SDKRuntimeState::new("hello", &ResourceLimits::default());
+        let no_clock = || -> Option<Duration> { panic!("clock read without a log rate") };
+        assert_eq!(state.log_quota(no_clock, 4096), Ok(()));
+        assert_eq!(state.log_dropped, 0);
+    }
+
+    #[test]
+    fn audio_get_state() {
+        use sdk_interface::AudioConfig;
+
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let world = get_endpoint(&mut sdk, "world").unwrap();
+        let idle = AudioState {
+            record: AudioRecordState::Idle,
+            play: AudioPlayState::Idle,
+        };
+        assert_eq!(sdk.audio_get_state(hello), Ok(idle));
+
+        // Start recording as audio_record_start does once the driver
+        // has accepted the request (the driver needs hardware).
+        let config = AudioConfig {
+            rate: 1_000_000,
+            buffer_size: 160,
+        };
+        sdk.get_mut_app(hello).unwrap().audio_record_state = AudioRecordState::Recording(config);
+        let state = sdk.audio_get_state(hello).unwrap();
+        assert!(state.record.is_recording());
+        assert_eq!(state.record, AudioRecordState::Recording(config));
+        assert!(state.play.is_idle());
+
+        // State is per-app.
+        assert_eq!(sdk.audio_get_state(world), Ok(idle));
+    }
+
+    #[cfg(feature = "timer_support")]
+    #[test]
+    fn cancel_racing_completion() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let id = sdk.alloc_id().unwrap();
+        let other = sdk.alloc_id().unwrap();
+
+        // A completion collected before the cancel is discarded.
+        sdk.post_timer_events(1 << id);
+        assert_eq!(sdk.pending_mask, 1 << id);
+        sdk.release_id(id);
+        assert_eq!(sdk.pending_mask, 0);
+        sdk.post_timer_events(0);
+
+        // The timer fires and is cancelled before its completion is
+        // collected; the id is not handed out again in the meantime.
+        assert_eq!(sdk.alloc_id(), Some(id));
+        sdk.release_id(id);
+        let next = sdk.alloc_id().unwrap();
+        assert_ne!(next, id);
+
+        // The late completion is dropped; others are kept.
+        sdk.post_timer_events((1 << id) | (1 << other));
+        assert_eq!(sdk.pending_mask, 1 << other);
+
+        // Once collected the id may be reused.
+        assert_eq!(sdk.alloc_id(), Some(id));
+    }
+
+    #[cfg(feature = "timer_support")]
+    #[test]
+    fn released_ids_reclaimed() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let no_poll = || -> Option<TimerMask> { panic!("unexpected poll") };
+
+        // Cancelled timers whose completions are never waited for.
+        for id in 0..u32::BITS as TimerId {
+            assert_eq!(sdk.alloc_id_with(no_poll), Some(id));
+            sdk.release_id(id);
+        }
+        assert_eq!(sdk.free_ids(), 0);
+
+        // The pool is reclaimed by collecting from the TimerService;
+        // the late completion for id 3 is dropped.
+        assert_eq!(sdk.alloc_id_with(|| Some(1 << 3)), Some(0));
+        assert_eq!(sdk.pending_mask, 0);
+        assert_eq!(sdk.released_mask, 0);
+        assert_eq!(sdk.free_ids(), u32::BITS as usize - 1);
+
+        // A failed poll leaves the id's quarantined.
+        sdk.ids = BitArray::new([!0b10]);
+        sdk.released_mask = 0b10;
+        assert_eq!(sdk.alloc_id_with(|| None), None);
+        assert_eq!(sdk.released_mask, 0b10);
+    }
+
+    #[cfg(feature = "rpc_benchmark")]
+    #[test]
+    fn rpc_times() {
+        // Without sdk_prewarm the first request is timed on its own.
+        let mut cold = RpcTimes::default();
+        assert!(cold.record(/*ping=*/ false, 50));
+        assert!(!cold.record(false, 10));
+        assert!(!cold.prewarmed);
+        assert_eq!(cold.first_us, Some(50));
+        assert_eq!(cold.warm_avg_us(), Some(10));
+
+        // The Ping sent by sdk_prewarm is not the first real request.
+        let mut warm = RpcTimes::default();
+        assert_eq!(warm.warm_avg_us(), None);
+        assert!(!warm.record(/*ping=*/ true, 50));
+        assert!(warm.record(false, 12));
+        assert!(!warm.record(true, 10));
+        assert!(!warm.record(false, 14));
+        assert!(warm.prewarmed);
+        assert_eq!(warm.first_us, Some(12));
+        assert_eq!(warm.warm_count, 2);
+        assert_eq!(warm.warm_avg_us(), Some(12));
+    }
+
+    #[cfg(feature = "timer_support")]
+    #[test]
+    fn wait_for_timers_guard() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let timer_id = sdk.alloc_id().unwrap();
+        let guard = sdk.alloc_id().unwrap();
+        sdk.get_mut_app(hello)
+            .unwrap()
+            .set_state(3, TimerState::Periodic(timer_id));
+
+        // App timers that complete are returned ahead of the guard.
+        sdk.post_timer_events((1 << timer_id) | (1 << guard));
+        assert_eq!(sdk.wait_for_timers(hello, 1 << guard), Ok(1 << 3));
+
+        // Otherwise the guard ends the wait with an empty mask.
+        assert_eq!(sdk.wait_for_timers(hello, 1 << guard), Ok(0));
+        sdk.release_id(guard);
+        assert_eq!(sdk.pending_mask, 0);
+    }
+
+    #[cfg(feature = "ml_support")]
+    #[test]
+    fn ml_model_errors() {
+        // A missing model and a corrupt one need different fixes.
+        assert_eq!(map_ml_err(MlCoordError::NoSuchModel), SDKError::NoSuchModel);
+        assert_eq!(map_ml_err(MlCoordError::InvalidImage), SDKError::InvalidModelImage);
+        assert_eq!(map_ml_err(MlCoordError::LoadModelFailed), SDKError::LoadModelFailed);
+        // As does input corrupted while it was staged.
+        assert_eq!(
+            map_ml_err(MlCoordError::InputChecksumMismatch),
+            SDKError::InputChecksumMismatch
+        );
+        // A model in the wrong state (e.g. pausing a one-shot) is passed
+        // through rather than crashing the runtime.
+        assert_eq!(
+            map_ml_err(MlCoordError::InvalidModelState),
+            SDKError::InvalidModelState
+        );
+    }
+
+    #[cfg(feature = "ml_support")]
+    #[test]
+    fn model_completion() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let world = get_endpoint(&mut sdk, "world").unwrap();
+        // Hold some id's so runtime id's differ from MlCoordinator slots.
+        for _ in 0..3 {
+            assert!(sdk.alloc_id().is_some());
+        }
+        // The job id handed to the MlCoordinator is the runtime id.
+        let job = |_: &str, id: ModelId| Ok(id);
+        let (vad, vad_job) = sdk
+            .start_model(hello, "vad", ModelState::Oneshot, job)
+            .unwrap();
+        let (kws, kws_job) = sdk
+            .start_model(world, "kws", ModelState::Oneshot, job)
+            .unwrap();
+        assert_eq!((vad_job, kws_job), (vad, kws));
+
+        // The MlCoordinator sets the job bit when vad's run completes;
+        // only hello collects it and its model returns to Idle.
+        let mask = 1 << vad_job;
+        let world_app = sdk.get_mut_app(world).unwrap();
+        assert_eq!(world_app.process_completed_jobs(mask), 0);
+        assert_eq!(world_app.get_model_state(kws), sdk_interface::ModelState::Oneshot);
+        let hello_app = sdk.get_mut_app(hello).unwrap();
+        assert_eq!(hello_app.process_completed_jobs(mask), 1 << vad);
+        assert_eq!(hello_app.get_model_state(vad), sdk_interface::ModelState::Idle);
+    }
+
+    #[cfg(feature = "ml_support")]
+    #[test]
+    fn model_cancel_collects_job() {
+        use core::cell::Cell;
+
+        // Mimics the MlCoordinator's completed job mask; cancelling a
+        // model marks its job complete.
+        let completed: &Cell<MlJobMask> = &Cell::new(1 << 9);
+        let cancel = |id: ModelId| {
+            move || -> Result<(), ()> {
+                completed.set(completed.get() | (1 << id));
+                Ok(())
+            }
+        };
+        let collect = |interested: MlJobMask| {
+            let mask = completed.get() & interested;
+            completed.set(completed.get() & !mask);
+            Ok(mask)
+        };
+
+        // The cancelled model's bit is gone so a model that reuses the id
+        // does not see a completion; others are left pending.
+        assert_eq!(cancel_job(3, cancel(3), collect), Ok(()));
+        assert_eq!(completed.get(), 1 << 9);
+        assert_eq!(cancel_job(3, || Err(()), collect), Err(()));
+        assert_eq!(completed.get(), 1 << 9);
+    }
+
+    #[cfg(feature = "ml_support")]
+    #[test]
+    fn model_start_deadline() {
+        use core::cell::Cell;
+        use core::cell::RefCell;
+
+        let limits = ResourceLimits {
+            max_request_ms: 5,
+            ..ResourceLimits::default()
+        };
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint_limited(&mut sdk, "hello", &limits).unwrap();
+        let free_ids = sdk.free_ids();
+
+        // A load that takes |ms| of wall-clock time.
+        let clock = Cell::new(Duration::from_secs(1));
+        let now = || Some(clock.get());
+        let load = |ms| {
+            let clock = &clock;
+            move |_: &str, _: ModelId| -> Result<(), SDKError> {
+                clock.set(clock.get() + Duration::from_millis(ms));
+                Ok(())
+            }
+        };
+        let cancelled = RefCell::new(Vec::new());
+        let cancel = |app_id: &str, model_id: &str, id: ModelId| {
+            cancelled
+                .borrow_mut()
+                .push((app_id.to_string(), model_id.to_string(), id));
+            Ok(())
+        };
+
+        // A load that overruns the deadline is abandoned and undone.
+        assert_eq!(
+            sdk.start_model_timed(hello, "kws", ModelState::Oneshot, now, load(5), cancel),
+            Err(SDKError::Timeout)
+        );
+        let id = cancelled.borrow()[0].2;
+        assert_eq!(cancelled.take(), [("hello".to_string(), "kws".to_string(), id)]);
+        assert_eq!(sdk.get_app(hello).unwrap().model_mask(), 0);
+        assert_eq!(sdk.free_ids(), free_ids);
+
+        // One within the deadline is kept; running the loaded model again
+        // does not unload it however long that takes.
+        let (id, _) = sdk
+            .start_model_timed(hello, "kws", ModelState::Oneshot, now, load(4), cancel)
+            .unwrap();
+        assert_eq!(
+            sdk.start_model_timed(hello, "kws", ModelState::Oneshot, now, load(50), cancel),
+            Ok((id, ()))
+        );
+        assert!(cancelled.borrow().is_empty());
+        assert_eq!(sdk.get_app(hello).unwrap().model_mask(), 1 << id);
+
+        // Without a limit the clock is not read.
+        let world = get_endpoint(&mut sdk, "world").unwrap();
+        let no_clock = || -> Option<Duration> { unreachable!() };
+        assert!(sdk
+            .start_model_timed(world, "kws", ModelState::Idle, no_clock, load(50), cancel)
+            .is_ok());
+    }
+
+    #[cfg(feature = "ml_support")]
+    #[test]
+    fn model_wait_shared_notification() {
+        use core::cell::RefCell;
+
+        // Mimics the MlCoordinator: completed jobs and the notification
+        // it signals, which is shared by all apps. |runs| are the jobs
+        // that complete, in order, each time a waiter blocks.
+        struct FakeMlCoordinator {
+            completed: u32,
+            signaled: bool,
+            runs: Vec<u32>,
+        }
+        impl FakeMlCoordinator {
+            fn complete(&mut self, jobs: u32) {
+                self.completed |= jobs;
+                self.signaled = true;
+            }
+            fn take_masked(&mut self, interested: u32) -> Result<u32, ()> {
+                self.signaled = false;
+                let mask = self.completed & interested;
+                self.completed &= !mask;
+                Ok(mask)
+            }
+        }
+        let ml = RefCell::new(FakeMlCoordinator {
+            completed: 0,
+            signaled: false,
+            runs: Vec::new(),
+        });
+        let wait = |interested| {
+            wait_for_jobs(
+                || ml.borrow_mut().take_masked(interested),
+                || {
+                    let mut ml = ml.borrow_mut();
+                    if !ml.signaled {
+                        let jobs = ml.runs.remove(0); // NB: panics if blocked forever
+                        ml.complete(jobs);
+                    }
+                    ml.take_masked(interested)
+                },
+            )
+        };
+        let (hello, world) = (1 << 1, 1 << 4);
+
+        // Both apps' jobs complete with one signal; the first app to wait
+        // consumes it but the other still collects its job.
+        ml.borrow_mut().complete(hello | world);
+        assert_eq!(wait(world), Ok(world));
+        assert_eq!(wait(hello), Ok(hello));
+
+        // A wakeup for another app's job does not return an empty mask;
+        // the waiter blocks again and the other job stays pending.
+        ml.borrow_mut().runs = Vec::from([world, hello]);
+        assert_eq!(wait(hello), Ok(hello));
+        assert_eq!(wait(world), Ok(world));
+        assert!(ml.borrow().runs.is_empty());
+    }
+
+    #[cfg(feature = "ml_support")]
+    #[test]
+    fn model_slots() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let ok = |_: &str, _: ModelId| Ok(());
+
+        // Each model gets its own runtime id; a reload keeps it.
+        let (vad, _) = sdk
+            .start_model(hello, "vad", ModelState::Oneshot, ok)
+            .unwrap();
+        let (kws, _) = sdk
+            .start_model(hello, "kws", ModelState::Oneshot, ok)
+            .unwrap();
+        assert_ne!(vad, kws);
+        assert_eq!(sdk.start_model(hello, "vad", ModelState::Periodic, ok), Ok((vad, ())));
+        let app = sdk.get_app(hello).unwrap();
+        assert_eq!(app.get_model_name(kws), Ok("kws"));
+        assert_eq!(app.model_mask(), (1 << vad) | (1 << kws));
+        assert_eq!(sdk.ids.count_ones(), 2);
+
+        // A failed load does not hold a slot or id.
+        assert_eq!(
+            sdk.start_model(hello, "bad", ModelState::Idle, |_, _| -> Result<(), SDKError> {
+                Err(SDKError::LoadModelFailed)
+            }),
+            Err(SDKError::LoadModelFailed)
+        );
+        assert_eq!(sdk.ids.count_ones(), 2);
+
+        // Completions are applied to the matching slot and limited to
+        // the app's models.
+        let app = sdk.get_mut_app(hello).unwrap();
+        assert_eq!(app.process_completed_jobs((1 << kws) | (1 << 31)), 1 << kws);
+        assert!(app
+            .models
+            .iter()
+            .any(|(id, state)| *id == kws && state.is_idle()));
+        assert!(app
+            .models
+            .iter()
+            .any(|(id, state)| *id == vad && *state == ModelState::Periodic(String::from("vad"))));
+
+        // Unloading frees the slot; unknown id's are rejected.
+        app.unload_model(kws);
+        assert_eq!(app.get_model_name(kws), Err(SDKError::NoSuchModel));
+        assert_eq!(app.model_mask(), 1 << vad);
+        sdk.release_model_id(kws);
+
+        // Slots are limited.
+        for name in ["a", "b", "c"] {
+            assert!(sdk.start_model(hello, name, ModelState::Idle, ok).is_ok());
+        }
+        assert_eq!(
+            sdk.start_model(hello, "d", ModelState::Idle, ok),
+            Err(SDKError::OutOfResources)
+        );
+        assert_eq!(sdk.ids.count_ones(), MAX_APP_MODELS);
+    }
+
+    #[cfg(feature = "ml_support")]
+    #[test]
+    fn model_transfer() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let world = get_endpoint(&mut sdk, "world").unwrap();
+        let ok = |_: &str, _: ModelId| Ok(());
+        let moved = |_: &str, _: &str, _: &str| Ok(());
+        let (vad, _) = sdk
+            .start_model(hello, "vad", ModelState::Oneshot, ok)
+            .unwrap();
+
+        // Only the app that loaded the model may hand it off.
+        assert_eq!(sdk.move_model(world, vad, hello, moved), Err(SDKError::NoSuchModel));
+        assert_eq!(sdk.find_app("nobody"), Err(SDKError::InvalidBadge));
+        assert_eq!(sdk.find_app("world"), Ok(world));
+
+        // The MlCoordinator is asked to move the model between bundles.
+        let mut args = None;
+        assert_eq!(
+            sdk.move_model(hello, vad, world, |from, to, name| {
+                args = Some((from.to_string(), to.to_string(), name.to_string()));
+                Ok(())
+            }),
+            Ok(())
+        );
+        assert_eq!(
+            args,
+            Some((String::from("hello"), String::from("world"), String::from("vad")))
+        );
+
+        // The new owner has the model with the same id; the old owner
+        // can no longer use it and the id is not released.
+        let world_app = sdk.get_app(world).unwrap();
+        assert_eq!(world_app.get_model_name(vad), Ok("vad"));
+        assert_eq!(world_app.model_mask(), 1 << vad);
+        let hello_app = sdk.get_mut_app(hello).unwrap();
+        assert_eq!(hello_app.get_model_name(vad), Err(SDKError::NoSuchModel));
+        assert_eq!(hello_app.process_completed_jobs(1 << vad), 0);
+        assert_eq!(sdk.model_cancel(hello, vad), Err(SDKError::NoSuchModel));
+        assert_eq!(sdk.ids.count_ones(), 1);
+        let world_app = sdk.get_mut_app(world).unwrap();
+        assert_eq!(world_app.process_completed_jobs(1 << vad), 1 << vad);
+
+        // A model the target already has is not replaced and a failed
+        // transfer leaves the model where it was.
+        let (kws, _) = sdk
+            .start_model(hello, "kws", ModelState::Oneshot, ok)
+            .unwrap();
+        assert!(sdk.start_model(world, "kws", ModelState::Idle, ok).is_ok());
+        assert_eq!(
+            sdk.move_model(hello, kws, world, moved),
+            Err(SDKError::ModelAlreadyExists)
+        );
+        let (aec, _) = sdk
+            .start_model(hello, "aec", ModelState::Oneshot, ok)
+            .unwrap();
+        assert_eq!(
+            sdk.move_model(hello, aec, world, |_, _, _| Err(SDKError::NoSuchModel)),
+            Err(SDKError::NoSuchModel)
+        );
+        assert_eq!(sdk.get_app(hello).unwrap().get_model_name(aec), Ok("aec"));
+
+        // The target's model quota applies.
+        for name in ["a", "b"] {
+            assert!(sdk.start_model(world, name, ModelState::Idle, ok).is_ok());
+        }
+        assert_eq!(
+            sdk.move_model(hello, aec, world, moved),
+            Err(SDKError::OutOfResources)
+        );
+    }
+
+    #[test]
+    fn resource_info() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let timers_max = MAX_TIMER_ID as u8 + 1;
+        assert_eq!(
+            sdk.resource_info(hello),
+            Ok(ResourceInfo {
+                timers_available: timers_max,
+                timers_max,
+                model_available: MAX_APP_MODELS as u8,
+                log_dropped: 0,
+            })
+        );
+
+        // The app's timers & models count against its own limits.
+        let app = sdk.get_mut_app(hello).unwrap();
+        app.timer_state[0] = TimerState::Oneshot(0);
+        app.models[0] = (1, ModelState::Idle(String::from("kws")));
+        sdk.ids = BitArray::new([0b11]);
+        let info = sdk.resource_info(hello).unwrap();
+        assert_eq!(info.timers_available, timers_max - 1);
+        assert_eq!(info.model_available, MAX_APP_MODELS as u8 - 1);
+
+        // Id's held by other apps (or just released) are shared.
+        sdk.ids = BitArray::new([!0b1100]);
+        sdk.released_mask = 0b100;
+        let info = sdk.resource_info(hello).unwrap();
+        assert_eq!(info.timers_available, 1);
+        assert_eq!(info.timers_max, timers_max);
+        assert_eq!(info.model_available, 1);
+    }
+
+    #[test]
+    fn clear_resources() {
+        use sdk_interface::AudioConfig;
+
+        let limits = ResourceLimits {
+            max_audio_bytes: 1024,
+            ..ResourceLimits::default()
+        };
+        let mut state = SDKRuntimeState::new("hello", &limits);
+        state.models[0] = (1, ModelState::Idle(String::from("kws")));
+        state.timer_state[2] = TimerState::Oneshot(3);
+        state.sdk_timer_mask.set(3, true);
+        state.audio_play_state = AudioPlayState::Playing(AudioConfig {
+            rate: 1_000_000,
+            buffer_size: 160,
+        });
+        state.quota.set_play_bytes(1024);
+        assert_eq!(state.quota.check_record_bytes(1), Err(SDKError::QuotaExceeded));
+
+        // Nothing is held afterwards so a repeat (e.g. a shutdown
+        // followed by release_endpoint) has nothing to release.
+        for _ in 0..2 {
+            state.clear_resources();
+            assert_eq!(state.model_mask(), 0);
+            assert!(state.timer_state.iter().all(|s| *s == TimerState::None));
+            assert_eq!(state.sdk_timer_mask.count_ones(), 0);
+            assert!(state.audio_play_state.is_idle());
+            assert_eq!(state.quota.check_record_bytes(1024), Ok(()));
+        }
+    }
+
+    #[cfg(all(feature = "timer_support", feature = "ml_support"))]
+    #[test]
+    fn shutdown_then_release_endpoint() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+        static AUDIO_RESETS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        sdk.release_rpcs = ReleaseRpcs {
+            upload_abort: |_| {},
+            cancel_model: |_, _, _| {},
+            cancel_timer: |_| {},
+            #[cfg(feature = "audio_support")]
+            audio_reset: || {
+                AUDIO_RESETS.fetch_add(1, Ordering::Relaxed);
+            },
+        };
+        let no_poll = || -> Option<TimerMask> { panic!("unexpected poll") };
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let free_ids = sdk.free_ids();
+
+        // A timer, a model & a recording.
+        let timer_id = sdk.alloc_id_with(no_poll).unwrap();
+        sdk.get_mut_app(hello)
+            .unwrap()
+            .set_state(1, TimerState::Oneshot(timer_id));
+        let (model_id, _) = sdk
+            .start_model(hello, "kws", ModelState::Oneshot, |_, _| Ok(()))
+            .unwrap();
+        assert_ne!(model_id, timer_id);
+        sdk.get_mut_app(hello).unwrap().audio_record_state =
+            AudioRecordState::Recording(sdk_interface::AudioConfig {
+                rate: 16_000,
+                buffer_size: 160,
+            });
+        assert_eq!(sdk.free_ids(), free_ids - 2);
+
+        // Shutdown releases everything; the timer id is reusable once
+        // completions have been collected from the TimerService.
+        assert_eq!(sdk.shutdown(hello), Ok(()));
+        assert_eq!(sdk.free_ids(), free_ids - 1);
+        sdk.post_timer_events(0);
+        assert_eq!(sdk.free_ids(), free_ids);
+        #[cfg(feature = "audio_support")]
+        assert_eq!(AUDIO_RESETS.load(Ordering::Relaxed), 1);
+
+        // The id's handed out again are not released a second time and
+        // the audio hardware, now idle for this app, is left alone.
+        let world = get_endpoint(&mut sdk, "world").unwrap();
+        let reused = sdk.alloc_id_with(no_poll).unwrap();
+        assert!(reused == timer_id || reused == model_id);
+        sdk.get_mut_app(world)
+            .unwrap()
+            .set_state(1, TimerState::Oneshot(reused));
+        assert_eq!(sdk.release_endpoint("hello"), Ok(()));
+        assert!(sdk.get_app(hello).is_err());
+        assert_eq!(sdk.free_ids(), free_ids - 1);
+        assert_eq!(sdk.released_mask, 0);
+        #[cfg(feature = "audio_support")]
+        assert_eq!(AUDIO_RESETS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn model_get_state() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        sdk.get_mut_app(hello).unwrap().models[1] = (5, ModelState::Oneshot(String::from("kws")));
+
+        use sdk_interface::ModelState as State;
+        assert_eq!(sdk.model_get_state(hello, 5), Ok(State::Oneshot));
+        assert_eq!(sdk.model_get_state(hello, 0), Ok(State::None));
+        assert_eq!(sdk.model_get_state(hello, 32), Err(SDKError::NoSuchModel));
+
+        let app = sdk.get_mut_app(hello).unwrap();
+        assert_eq!(app.process_completed_jobs(1 << 5), 1 << 5);
+        assert_eq!(sdk.model_get_state(hello, 5), Ok(State::Idle));
+    }
+
+    #[test]
+    fn model_upload_errors() {
+        // Errors an app can recover from are distinguished.
+        assert_eq!(
+            map_upload_err(SecurityRequestError::UploadOffsetInvalid),
+            SDKError::InvalidUploadOffset
+        );
+        assert_eq!(
+            map_upload_err(SecurityRequestError::UploadIncomplete),
+            SDKError::UploadIncomplete
+        );
+        assert_eq!(
+            map_upload_err(SecurityRequestError::DeleteFirst),
+            SDKError::ModelAlreadyExists
+        );
+        assert_eq!(
+            map_upload_err(SecurityRequestError::TooManyBundles),
+            SDKError::QuotaExceeded
+        );
+        assert_eq!(
+            map_upload_err(SecurityRequestError::UploadQuotaExceeded),
+            SDKError::QuotaExceeded
+        );
+        assert_eq!(
+            map_upload_err(SecurityRequestError::SignatureInvalid),
+            SDKError::InvalidModelImage
+        );
+        assert_eq!(
+            map_upload_err(SecurityRequestError::InstallModelFailed),
+            SDKError::LoadModelFailed
+        );
+    }
+
+    #[test]
+    fn fault_policy() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let vm_fault = &seL4_FaultTag::seL4_Fault_VMFault;
+
+        // By default the app is left suspended.
+        assert_eq!(FaultPolicy::default(), FaultPolicy::Suspend);
+        assert_eq!(sdk.handle_fault(hello, vm_fault), Ok(FaultAction::Suspend));
+
+        assert_eq!(sdk.set_fault_policy(hello, FaultPolicy::LogAndKill), Ok(()));
+        assert_eq!(
+            sdk.handle_fault(hello, vm_fault),
+            Ok(FaultAction::Kill(String::from("hello")))
+        );
+
+        assert_eq!(sdk.set_fault_policy(hello, FaultPolicy::LogAndRestart), Ok(()));
+        assert_eq!(
+            sdk.handle_fault(hello, vm_fault),
+            Ok(FaultAction::Restart(String::from("hello")))
+        );
+        // An exit is not restarted.
+        assert_eq!(
+            sdk.handle_fault(hello, &seL4_FaultTag::seL4_Fault_NullFault),
+            Ok(FaultAction::Kill(String::from("hello")))
+        );
+
+        assert_eq!(sdk.set_fault_policy(hello, FaultPolicy::Suspend), Ok(()));
+        assert_eq!(sdk.handle_fault(hello, vm_fault), Ok(FaultAction::Suspend));
+
+        assert_eq!(sdk.get_app(hello).unwrap().faults, 5);
+        assert_eq!(sdk.faults, 5);
+    }
+
+    #[test]
+    fn fault_suspends_requests() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let world = get_endpoint(&mut sdk, "world").unwrap();
+        let vm_fault = &seL4_FaultTag::seL4_Fault_VMFault;
+
+        // Faults that stop the app do not mark it suspended.
+        assert_eq!(sdk.set_fault_policy(hello, FaultPolicy::LogAndKill), Ok(()));
+        assert!(sdk.handle_fault(hello, vm_fault).is_ok());
+        assert_eq!(sdk.check_faulted(hello), Ok(()));
+
+        // The default policy does.
+        assert_eq!(sdk.set_fault_policy(hello, FaultPolicy::default()), Ok(()));
+        assert_eq!(sdk.handle_fault(hello, vm_fault), Ok(FaultAction::Suspend));
+        assert_eq!(sdk.check_faulted(hello), Err(SDKError::Faulted));
+        // Other apps are unaffected.
+        assert_eq!(sdk.check_faulted(world), Ok(()));
+
+        // A restarted app that re-registers is no longer suspended.
+        if cfg!(feature = "reuse_endpoint") {
+            assert_eq!(get_endpoint(&mut sdk, "hello"), Ok(hello));
+        } else {
+            // NB: what get_endpoint does once check_endpoint allows it
+            sdk.register(hello, "hello", &ResourceLimits::default());
+        }
+        assert_eq!(sdk.check_faulted(hello), Ok(()));
+        assert_eq!(sdk.get_app(hello).unwrap().faults, 2);
+    }
+
+    #[test]
+    fn fault_decode() {
+        let mr = |i: usize| 0x1000 + i;
+        let fault = FaultInfo::decode(&seL4_FaultTag::seL4_Fault_VMFault, mr);
+        assert_eq!(fault.ip, Some(0x1000));
+        assert_eq!(fault.addr, Some(0x1001));
+        assert_eq!(format!("{}", fault), "VMFault ip 0x1000 addr 0x1001");
+
+        let fault = FaultInfo::decode(&seL4_FaultTag::seL4_Fault_CapFault, mr);
+        assert_eq!((fault.ip, fault.addr), (Some(0x1000), Some(0x1001)));
+        let fault = FaultInfo::decode(&seL4_FaultTag::seL4_Fault_UserException, mr);
+        assert_eq!((fault.ip, fault.addr), (Some(0x1000), None));
+
+        // An exit carries nothing to decode.
+        let fault = FaultInfo::decode(&seL4_FaultTag::seL4_Fault_NullFault, mr);
+        assert_eq!((fault.ip, fault.addr), (None, None));
+        assert_eq!(format!("{}", fault), "NullFault");
+    }
+
+    #[test]
+    fn fault_policy_per_app() {
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let world = get_endpoint(&mut sdk, "world").unwrap();
+        let vm_fault = &seL4_FaultTag::seL4_Fault_VMFault;
+
+        assert_eq!(sdk.set_fault_policy(hello, FaultPolicy::LogAndKill), Ok(()));
+        assert_eq!(
+            sdk.handle_fault(hello, vm_fault),
+            Ok(FaultAction::Kill(String::from("hello")))
+        );
+        assert_eq!(sdk.handle_fault(world, vm_fault), Ok(FaultAction::Suspend));
+        assert_eq!(sdk.get_app(world).unwrap().faults, 1);
+
+        // Faults from an unknown app are counted but otherwise rejected.
+        assert_eq!(
+            sdk.set_fault_policy(0, FaultPolicy::LogAndKill),
+            Err(SDKError::InvalidBadge)
+        );
+        assert_eq!(sdk.handle_fault(0, vm_fault), Err(SDKError::InvalidBadge));
+        assert_eq!(sdk.faults, 3);
+    }
+
+    #[test]
+    fn snapshot() {
+        use sdk_interface::AudioConfig;
+
+        let mut sdk = SDKRuntime::new(&(0, 0, 0));
+        let hello = get_endpoint(&mut sdk, "hello").unwrap();
+        let world = get_endpoint(&mut sdk, "world").unwrap();
+        let idle = get_endpoint(&mut sdk, "idle").unwrap();
+
+        // Setup state as the timer, model, & audio requests would (the
+        // services they call need seL4).
+        let config = AudioConfig {
+            rate: 1_000_000,
+            buffer_size: 160,
+        };
+        let app = sdk.get_mut_app(hello).unwrap();
+        app.timer_state[0] = TimerState::Oneshot(3);
+        app.timer_state[5] = TimerState::Periodic(1);
+        app.models[1] = (6, ModelState::Periodic(String::from("mobilenet")));
+        app.models[2] = (7, ModelState::Oneshot(String::from("vad")));
+        app.audio_play_state = AudioPlayState::Playing(config);
+        app.next_log_seq();
+        let app = sdk.get_mut_app(world).unwrap();
+        app.timer_state[2] = TimerState::Oneshot(4);
+        app.models[0] = (8, ModelState::Idle(String::from("keyword")));
+        app.audio_record_state = AudioRecordState::Recording(config);
+        let ids = (1 << 1) | (1 << 3) | (1 << 4) | (1 << 6) | (1 << 7) | (1 << 8);
+        sdk.ids = BitArray::new([ids]);
+        sdk.pending_mask = 1 << 3;
+        assert_eq!(sdk.set_fault_policy(world, FaultPolicy::LogAndKill), Ok(()));
+        assert!(sdk
+            .handle_fault(world, &seL4_FaultTag::seL4_Fault_VMFault)
+            .is_ok());
+
+        let snapshot = sdk.snapshot();
+        assert_eq!(snapshot.ids, ids);
+        assert_eq!(snapshot.pending_mask, 1 << 3);
+        assert_eq!(snapshot.released_mask, 0);
+        assert_eq!(snapshot.faults, 1);
+        assert_eq!(snapshot.apps.len(), 3);
+        assert!(snapshot.apps.windows(2).all(|w| w[0].badge < w[1].badge));
+        let find = |badge| snapshot.apps.iter().find(|app| app.badge == badge).unwrap();
+
+        let app = find(hello);
+        assert_eq!(app.app_id, "hello");
+        assert_eq!(
+            app.timers,
+            [
+                TimerSnapshot {
+                    app_timer_id: 0,
+                    sdk_timer_id: 3,
+                    periodic: false,
+                },
+                TimerSnapshot {
+                    app_timer_id: 5,
+                    sdk_timer_id: 1,
+                    periodic: true,
+                },
+            ]
+        );
+        assert_eq!(
+            app.models,
+            [
+                (6, ModelSnapshot::Periodic(String::from("mobilenet"))),
+                (7, ModelSnapshot::Oneshot(String::from("vad"))),
+            ]
+        );
+        assert_eq!(app.audio.record, AudioRecordState::Idle);
+        assert_eq!(app.audio.play, AudioPlayState::Playing(config));
+        assert_eq!(app.log_seq, 1);
+        assert_eq!(app.faults, 0);
+
+        let app = find(world);
+        assert_eq!(app.app_id, "world");
+        assert_eq!(
+            app.timers,
+            [TimerSnapshot {
+                app_timer_id: 2,
+                sdk_timer_id: 4,
+                periodic: false,
+            }]
+        );
+        assert_eq!(app.models, [(8, ModelSnapshot::Idle(String::from("keyword")))]);
+        assert_eq!(app.audio.record, AudioRecordState::Recording(config));
+        assert_eq!(app.audio.play, AudioPlayState::Idle);
+        assert_eq!(app.fault_policy, FaultPolicy::LogAndKill);
+        assert_eq!(app.faults, 1);
+
+        let app = find(idle);
+        assert_eq!(app.app_id, "idle");
+        assert!(app.timers.is_empty());
+        assert!(app.models.is_empty());
+        assert!(app.audio.record.is_idle() && app.audio.play.is_idle());
+        assert_eq!(app.fault_policy, FaultPolicy::default());
     }
 }
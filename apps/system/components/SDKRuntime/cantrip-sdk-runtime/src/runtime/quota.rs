@@ -0,0 +1,244 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-application resource accounting against the ResourceLimits
+//! supplied when the application's endpoint was created.
+
+use cantrip_sdk_manager::ResourceLimits;
+use core::time::Duration;
+use sdk_interface::error::SDKError;
+
+/// Wall-clock time by which a request must be done with the runtime
+/// thread.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Deadline(Option<Duration>);
+#[allow(dead_code)]
+impl Deadline {
+    // Returns true once the time read through |now| is past the
+    // deadline. Nothing expires when there is no deadline (the clock is
+    // not read) or no clock.
+    pub fn expired(&self, now: impl FnOnce() -> Option<Duration>) -> bool {
+        match self.0 {
+            Some(deadline) => now().map_or(false, |now| now >= deadline),
+            None => false,
+        }
+    }
+}
+
+// NB: not all checks are used in every platform configuration
+#[allow(dead_code)]
+pub struct AppQuota {
+    limits: ResourceLimits,
+    record_bytes: usize,
+    play_bytes: usize,
+    // Log token bucket; holds at most one second's worth of bytes.
+    log_tokens: usize,
+    log_refill: Option<Duration>, // Time of last refill
+}
+#[allow(dead_code)]
+impl AppQuota {
+    pub fn new(limits: &ResourceLimits) -> Self {
+        Self {
+            limits: *limits,
+            record_bytes: 0,
+            play_bytes: 0,
+            log_tokens: limits.log_bytes_per_sec,
+            log_refill: None,
+        }
+    }
+
+    // Checks another timer may be started when |active| are running.
+    pub fn check_timers(&self, active: usize) -> Result<(), SDKError> {
+        if active >= self.limits.max_timers {
+            return Err(SDKError::QuotaExceeded);
+        }
+        Ok(())
+    }
+
+    // Returns the most timers that may be active at once.
+    pub fn max_timers(&self) -> usize { self.limits.max_timers }
+
+    // Returns the most models that may be loaded at once.
+    pub fn max_models(&self) -> usize { self.limits.max_models }
+
+    // Checks another model may be loaded when |loaded| are present.
+    pub fn check_models(&self, loaded: usize) -> Result<(), SDKError> {
+        if loaded >= self.limits.max_models {
+            return Err(SDKError::QuotaExceeded);
+        }
+        Ok(())
+    }
+
+    // Checks a record buffer of |bytes| fits with the current play buffer.
+    pub fn check_record_bytes(&self, bytes: usize) -> Result<(), SDKError> {
+        self.check_audio_bytes(bytes, self.play_bytes)
+    }
+    // Records the size of the record buffer; 0 when not recording.
+    pub fn set_record_bytes(&mut self, bytes: usize) { self.record_bytes = bytes; }
+
+    // Checks a play buffer of |bytes| fits with the current record buffer.
+    pub fn check_play_bytes(&self, bytes: usize) -> Result<(), SDKError> {
+        self.check_audio_bytes(self.record_bytes, bytes)
+    }
+    // Records the size of the play buffer; 0 when not playing.
+    pub fn set_play_bytes(&mut self, bytes: usize) { self.play_bytes = bytes; }
+
+    fn check_audio_bytes(&self, record_bytes: usize, play_bytes: usize) -> Result<(), SDKError> {
+        match record_bytes.checked_add(play_bytes) {
+            Some(total) if total <= self.limits.max_audio_bytes => Ok(()),
+            _ => Err(SDKError::QuotaExceeded),
+        }
+    }
+
+    // Returns true if log output is rate limited; otherwise log need
+    // not be called (and the clock need not be read).
+    pub fn log_limited(&self) -> bool { self.limits.log_bytes_per_sec != usize::MAX }
+
+    // Accounts for |len| bytes of log output at time |now|.
+    pub fn log(&mut self, now: Duration, len: usize) -> Result<(), SDKError> {
+        let rate = self.limits.log_bytes_per_sec;
+        if rate == usize::MAX {
+            return Ok(());
+        }
+        let last = *self.log_refill.get_or_insert(now);
+        let elapsed_us = now.saturating_sub(last).as_micros();
+        let refill = (elapsed_us * rate as u128 / 1_000_000) as usize;
+        // NB: only advance the refill time when tokens are added so
+        //   frequent calls do not lose fractional credit
+        if refill > 0 {
+            self.log_tokens = self.log_tokens.saturating_add(refill).min(rate);
+            self.log_refill = Some(now);
+        }
+        if len > self.log_tokens {
+            return Err(SDKError::QuotaExceeded);
+        }
+        self.log_tokens -= len;
+        Ok(())
+    }
+
+    // Returns true if requests have a deadline; otherwise deadline
+    // need not be called (and the clock need not be read).
+    pub fn request_limited(&self) -> bool { self.limits.max_request_ms != usize::MAX }
+
+    // Returns the deadline for a request that starts at |now|.
+    pub fn deadline(&self, now: impl FnOnce() -> Option<Duration>) -> Deadline {
+        if !self.request_limited() {
+            return Deadline(None);
+        }
+        let limit = Duration::from_millis(self.limits.max_request_ms as u64);
+        Deadline(now().map(|now| now.saturating_add(limit)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> ResourceLimits {
+        ResourceLimits {
+            max_timers: 2,
+            max_models: 1,
+            max_audio_bytes: 1024,
+            log_bytes_per_sec: 100,
+            max_request_ms: 10,
+        }
+    }
+
+    #[test]
+    fn timers() {
+        let quota = AppQuota::new(&limits());
+        assert_eq!(quota.check_timers(0), Ok(()));
+        assert_eq!(quota.check_timers(1), Ok(()));
+        assert_eq!(quota.check_timers(2), Err(SDKError::QuotaExceeded));
+    }
+
+    #[test]
+    fn models() {
+        let quota = AppQuota::new(&limits());
+        assert_eq!(quota.check_models(0), Ok(()));
+        assert_eq!(quota.check_models(1), Err(SDKError::QuotaExceeded));
+    }
+
+    #[test]
+    fn audio_bytes() {
+        let mut quota = AppQuota::new(&limits());
+        assert_eq!(quota.check_record_bytes(1024), Ok(()));
+        assert_eq!(quota.check_record_bytes(1025), Err(SDKError::QuotaExceeded));
+        quota.set_record_bytes(512);
+        assert_eq!(quota.check_play_bytes(512), Ok(()));
+        assert_eq!(quota.check_play_bytes(513), Err(SDKError::QuotaExceeded));
+        quota.set_play_bytes(512);
+        assert_eq!(quota.check_record_bytes(513), Err(SDKError::QuotaExceeded));
+        // Stopping playback returns its space.
+        quota.set_play_bytes(0);
+        assert_eq!(quota.check_record_bytes(1024), Ok(()));
+        assert_eq!(quota.check_play_bytes(usize::MAX), Err(SDKError::QuotaExceeded));
+    }
+
+    #[test]
+    fn log_rate() {
+        let mut quota = AppQuota::new(&limits());
+        assert!(quota.log_limited());
+        let t0 = Duration::from_secs(5);
+        // Starts with a full second's worth of tokens.
+        assert_eq!(quota.log(t0, 60), Ok(()));
+        assert_eq!(quota.log(t0, 40), Ok(()));
+        assert_eq!(quota.log(t0, 1), Err(SDKError::QuotaExceeded));
+        // Refills at the configured rate, including across short intervals.
+        for i in 1..=5 {
+            assert_eq!(quota.log(t0 + Duration::from_millis(i), 0), Ok(()));
+        }
+        assert_eq!(quota.log(t0 + Duration::from_millis(10), 1), Ok(()));
+        assert_eq!(
+            quota.log(t0 + Duration::from_millis(10), 1),
+            Err(SDKError::QuotaExceeded)
+        );
+        // Never holds more than one second's worth.
+        assert_eq!(
+            quota.log(t0 + Duration::from_secs(10), 101),
+            Err(SDKError::QuotaExceeded)
+        );
+        assert_eq!(quota.log(t0 + Duration::from_secs(10), 100), Ok(()));
+    }
+
+    #[test]
+    fn deadline() {
+        let quota = AppQuota::new(&limits());
+        let t0 = Duration::from_secs(5);
+        let deadline = quota.deadline(|| Some(t0));
+        assert!(!deadline.expired(|| Some(t0)));
+        assert!(!deadline.expired(|| Some(t0 + Duration::from_millis(9))));
+        assert!(deadline.expired(|| Some(t0 + Duration::from_millis(10))));
+        // Without a clock there is nothing to check against.
+        assert!(!deadline.expired(|| None));
+        let deadline = quota.deadline(|| None);
+        assert!(!deadline.expired(|| unreachable!()));
+    }
+
+    #[test]
+    fn unlimited() {
+        let mut quota = AppQuota::new(&ResourceLimits::default());
+        assert!(!quota.log_limited());
+        assert_eq!(quota.check_timers(1024), Ok(()));
+        assert_eq!(quota.check_models(1024), Ok(()));
+        for _ in 0..64 {
+            assert_eq!(quota.log(Duration::ZERO, 4096), Ok(()));
+        }
+        quota.set_record_bytes(usize::MAX / 2);
+        assert_eq!(quota.check_play_bytes(usize::MAX / 2), Ok(()));
+        assert!(!quota.request_limited());
+        let deadline = quota.deadline(|| unreachable!());
+        assert!(!deadline.expired(|| unreachable!()));
+    }
+}
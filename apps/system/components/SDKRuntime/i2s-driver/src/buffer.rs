@@ -15,8 +15,16 @@
 //! A u32 buffer with a beginning and ending that wrap around a fixed size array.
 //!
 //! This is a FIFO queue that overwrites when the buffer is full.
+//!
+//! Buffers hold up to BUFFER_CAPACITY items in a fixed array. A larger
+//! capacity may be set at runtime in which case the items are held in
+//! a heap allocation instead.
+
+use alloc::boxed::Box;
+use alloc::collections::TryReserveError;
+use alloc::vec::Vec;
 
-const BUFFER_CAPACITY: usize = 2048; // NB: intentionally 2x AUDIO_RECORD_CAPACITY
+pub const BUFFER_CAPACITY: usize = 2048; // NB: intentionally 2x AUDIO_RECORD_CAPACITY
 
 type ItemType = u32;
 
@@ -25,7 +33,10 @@ pub struct Buffer {
     begin: usize,
     end: usize,
     size: usize,
+    capacity: usize,
+    overwritten: usize, // Items overwritten by push since the last take
     data: [ItemType; BUFFER_CAPACITY],
+    heap: Option<Box<[ItemType]>>, // Used when capacity > BUFFER_CAPACITY
 }
 
 impl Buffer {
@@ -34,34 +45,106 @@ impl Buffer {
             begin: 0,
             end: 0,
             size: 0,
+            capacity: BUFFER_CAPACITY,
+            overwritten: 0,
             data: [0; BUFFER_CAPACITY],
+            heap: None,
         }
     }
 
+    /// Returns the number of items the buffer holds when full.
+    pub fn capacity(&self) -> usize { self.capacity }
+
+    /// Changes the buffer to hold |capacity| items, discarding any data.
+    ///
+    /// Capacities up to BUFFER_CAPACITY use the fixed array; anything
+    /// larger is allocated from the heap. On allocation failure the
+    /// buffer is left unchanged.
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<(), TryReserveError> {
+        let heap = self.reserve(capacity)?;
+        self.resize(capacity, heap);
+        Ok(())
+    }
+
+    /// Returns the heap storage needed to hold |capacity| items; None
+    /// if the fixed array or the current heap storage holds them. The
+    /// buffer is not changed until the result is passed to resize.
+    pub fn reserve(&self, capacity: usize) -> Result<Option<Box<[ItemType]>>, TryReserveError> {
+        assert!(capacity > 0);
+        let reuse = self.heap.as_ref().map(|heap| heap.len()) == Some(capacity);
+        if capacity <= BUFFER_CAPACITY || reuse {
+            return Ok(None);
+        }
+        let mut heap = Vec::new();
+        heap.try_reserve_exact(capacity)?;
+        heap.resize(capacity, 0);
+        Ok(Some(heap.into_boxed_slice()))
+    }
+
+    /// Changes the buffer to hold |capacity| items, discarding any data,
+    /// using |heap| as returned by reserve for the same |capacity|.
+    pub fn resize(&mut self, capacity: usize, heap: Option<Box<[ItemType]>>) {
+        if capacity <= BUFFER_CAPACITY {
+            self.heap = None;
+        } else if heap.is_some() {
+            self.heap = heap;
+        }
+        self.capacity = capacity;
+        self.begin = 0;
+        self.end = 0;
+        self.size = 0;
+        self.overwritten = 0;
+    }
+
     /// Resets buffer.
     ///
     /// This does not modify the data.
     pub fn clear(&mut self) {
         self.begin = 0;
         self.end = 0;
+        self.size = 0;
     }
 
     /// Returns true if buffer is empty, false otherwise.
     pub fn is_empty(&self) -> bool { self.size == 0 }
 
     /// Returns available data slot to be written.
-    pub fn available_space(&self) -> usize { BUFFER_CAPACITY - self.size }
+    pub fn available_space(&self) -> usize { self.capacity - self.size }
+
+    /// Returns true if at least |threshold| data slots can be written.
+    pub fn has_space(&self, threshold: usize) -> bool { self.available_space() >= threshold }
 
     /// Returns available data to be read.
     pub fn available_data(&self) -> usize { self.size }
 
     /// Adds an item to the buffer.
+    ///
+    /// If the buffer is full the oldest item is overwritten.
     pub fn push(&mut self, item: ItemType) {
-        self.data[self.end] = item;
-        self.end = Buffer::advance(self.end);
-        if self.size < BUFFER_CAPACITY {
+        let end = self.end;
+        self.items_mut()[end] = item;
+        self.end = self.advance(end);
+        if self.size < self.capacity {
             self.size += 1;
+        } else {
+            // |end| was the oldest item; the next oldest is now first.
+            self.begin = self.end;
+            self.overwritten += 1;
+        }
+    }
+
+    /// Returns the number of items overwritten since the last call.
+    pub fn take_overwritten(&mut self) -> usize { core::mem::take(&mut self.overwritten) }
+
+    /// Adds as many items from |items| as fit without overwriting.
+    ///
+    /// Returns the number of items added.
+    pub fn push_available(&mut self, items: &[ItemType]) -> usize {
+        let count = items.len().min(self.available_space());
+        for &item in &items[..count] {
+            self.push(item);
         }
+        count
     }
 
     /// Remove an item at the front of the buffer.
@@ -72,12 +155,287 @@ impl Buffer {
         if self.is_empty() {
             return None;
         }
-        let result = self.data[self.begin];
-        self.begin = Buffer::advance(self.begin);
+        let result = self.items()[self.begin];
+        self.begin = self.advance(self.begin);
         self.size -= 1;
         Some(result)
     }
 
+    /// Copies up to |n| items from the front of the buffer into |out|
+    /// without removing them.
+    ///
+    /// Returns the number of items copied.
+    pub fn peek(&self, n: usize, out: &mut [ItemType]) -> usize {
+        let count = n.min(out.len()).min(self.size);
+        let items = self.items();
+        let first = count.min(self.capacity - self.begin);
+        out[..first].copy_from_slice(&items[self.begin..self.begin + first]);
+        out[first..count].copy_from_slice(&items[..count - first]);
+        count
+    }
+
+    /// Returns the item |index| places from the front of the buffer
+    /// without removing it, or None if there are not that many items.
+    pub fn peek_at(&self, index: usize) -> Option<ItemType> {
+        if index >= self.size {
+            return None;
+        }
+        Some(self.items()[(self.begin + index) % self.capacity])
+    }
+
+    /// Returns the items at the front of the buffer that are stored
+    /// contiguously, i.e. up to the end of the array.
+    pub fn front_slice(&self) -> &[ItemType] {
+        let count = self.size.min(self.capacity - self.begin);
+        &self.items()[self.begin..self.begin + count]
+    }
+
+    /// Removes up to |count| items from the front of the buffer.
+    pub fn discard(&mut self, count: usize) {
+        let count = count.min(self.size);
+        self.begin = (self.begin + count) % self.capacity;
+        self.size -= count;
+    }
+
+    /// Puts back up to |count| items removed by discard. The items are
+    /// still in place provided nothing was pushed since.
+    pub fn restore(&mut self, count: usize) {
+        let count = count.min(self.capacity - self.size);
+        self.begin = (self.begin + self.capacity - count) % self.capacity;
+        self.size += count;
+    }
+
     /// Increments the begin or end marker and wrap around if necessary.
-    fn advance(position: usize) -> usize { (position + 1) % BUFFER_CAPACITY }
+    fn advance(&self, position: usize) -> usize { (position + 1) % self.capacity }
+
+    fn items(&self) -> &[ItemType] {
+        match &self.heap {
+            Some(heap) => heap,
+            None => &self.data[..self.capacity],
+        }
+    }
+    fn items_mut(&mut self) -> &mut [ItemType] {
+        match &mut self.heap {
+            Some(heap) => heap,
+            None => &mut self.data[..self.capacity],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(buf: &mut Buffer, count: usize) {
+        for i in 0..count {
+            buf.push(i as ItemType);
+        }
+    }
+
+    #[test]
+    fn has_space_threshold() {
+        let threshold = 100;
+        let mut buf = Buffer::new();
+        assert!(buf.has_space(threshold));
+
+        // One slot short of |threshold| free; no wakeup.
+        fill(&mut buf, BUFFER_CAPACITY - threshold + 1);
+        assert_eq!(buf.available_space(), threshold - 1);
+        assert!(!buf.has_space(threshold));
+
+        // Draining one item reaches |threshold| exactly; wakeup.
+        assert!(buf.pop().is_some());
+        assert_eq!(buf.available_space(), threshold);
+        assert!(buf.has_space(threshold));
+    }
+
+    #[test]
+    fn has_space_full() {
+        let mut buf = Buffer::new();
+        fill(&mut buf, BUFFER_CAPACITY);
+        assert!(!buf.has_space(1));
+        assert!(buf.has_space(0));
+    }
+
+    #[test]
+    fn push_available_partial() {
+        let mut buf = Buffer::new();
+        fill(&mut buf, BUFFER_CAPACITY - 3);
+        // Only the items that fit are taken; nothing is overwritten.
+        assert_eq!(buf.push_available(&[7, 8, 9, 10, 11]), 3);
+        assert_eq!(buf.available_space(), 0);
+        assert_eq!(buf.pop(), Some(0));
+        assert_eq!(buf.push_available(&[12, 13]), 1);
+        assert_eq!(buf.push_available(&[14]), 0);
+    }
+
+    #[test]
+    fn overwritten_count() {
+        let mut buf = Buffer::new();
+        fill(&mut buf, BUFFER_CAPACITY);
+        assert_eq!(buf.take_overwritten(), 0);
+        fill(&mut buf, 5);
+        assert_eq!(buf.available_data(), BUFFER_CAPACITY);
+        assert_eq!(buf.take_overwritten(), 5);
+        // The count restarts after each take.
+        assert_eq!(buf.take_overwritten(), 0);
+    }
+
+    #[test]
+    fn overwrite_keeps_order() {
+        let mut buf = Buffer::new();
+        fill(&mut buf, BUFFER_CAPACITY + 5);
+        assert_eq!(buf.take_overwritten(), 5);
+        // The oldest 5 items are gone; the rest pop in order.
+        for i in 5..BUFFER_CAPACITY + 5 {
+            assert_eq!(buf.pop(), Some(i as ItemType));
+        }
+        assert_eq!(buf.pop(), None);
+    }
+
+    #[test]
+    fn clear_empties() {
+        let mut buf = Buffer::new();
+        fill(&mut buf, 10);
+        buf.clear();
+        assert!(buf.is_empty());
+        assert_eq!(buf.available_space(), BUFFER_CAPACITY);
+        assert_eq!(buf.pop(), None);
+    }
+
+    // Returns a buffer holding |count| items with the first 4 stored at
+    // the end of the array so the rest wrap to the front.
+    fn wrapped(count: usize) -> Buffer {
+        let mut buf = Buffer::new();
+        fill(&mut buf, BUFFER_CAPACITY - 4);
+        for _ in 0..BUFFER_CAPACITY - 4 {
+            assert!(buf.pop().is_some());
+        }
+        fill(&mut buf, count);
+        assert_eq!(buf.begin, BUFFER_CAPACITY - 4);
+        buf
+    }
+
+    #[test]
+    fn peek_wraps() {
+        let buf = wrapped(10);
+        let mut out = [0; 16];
+        assert_eq!(buf.peek(8, &mut out), 8);
+        assert_eq!(out[..8], [0, 1, 2, 3, 4, 5, 6, 7]);
+        // Limited by the items held and by |out|.
+        assert_eq!(buf.peek(16, &mut out), 10);
+        assert_eq!(out[..10], [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(buf.peek(16, &mut out[..3]), 3);
+        assert_eq!(buf.peek(0, &mut out), 0);
+    }
+
+    #[test]
+    fn peek_does_not_consume() {
+        let mut buf = wrapped(10);
+        let mut out = [0; 10];
+        assert_eq!(buf.peek(10, &mut out), 10);
+        assert_eq!(buf.available_data(), 10);
+        for i in 0..10 {
+            assert_eq!(buf.pop(), Some(i));
+        }
+        assert_eq!(buf.peek(10, &mut out), 0);
+    }
+
+    #[test]
+    fn peek_at_wraps() {
+        let buf = wrapped(6);
+        assert_eq!(buf.peek_at(0), Some(0));
+        assert_eq!(buf.peek_at(3), Some(3));
+        assert_eq!(buf.peek_at(4), Some(4));
+        assert_eq!(buf.peek_at(5), Some(5));
+        assert_eq!(buf.peek_at(6), None);
+        assert_eq!(Buffer::new().peek_at(0), None);
+    }
+
+    #[test]
+    fn front_slice_wraps() {
+        let mut buf = wrapped(10);
+        // Only the items before the end of the array are contiguous.
+        assert_eq!(buf.front_slice(), [0, 1, 2, 3]);
+        buf.discard(3);
+        assert_eq!(buf.front_slice(), [3]);
+        buf.discard(1);
+        assert_eq!(buf.begin, 0);
+        assert_eq!(buf.front_slice(), [4, 5, 6, 7, 8, 9]);
+        // Discarding is capped at the items held.
+        buf.discard(100);
+        assert!(buf.is_empty());
+        assert_eq!(buf.front_slice(), []);
+    }
+
+    #[test]
+    fn discard_restore() {
+        let mut buf = wrapped(10);
+        buf.discard(3);
+        buf.restore(3);
+        assert_eq!(buf.available_data(), 10);
+        assert_eq!(buf.front_slice(), [0, 1, 2, 3]);
+        // Restoring across the end of the array.
+        buf.discard(5);
+        assert_eq!(buf.front_slice(), [5, 6, 7, 8, 9]);
+        buf.restore(2);
+        assert_eq!(buf.front_slice(), [3]);
+        // Restoring is capped at the free space.
+        buf.restore(2 * BUFFER_CAPACITY);
+        assert_eq!(buf.available_data(), buf.capacity());
+    }
+
+    #[test]
+    fn set_capacity_fixed() {
+        let mut buf = Buffer::new();
+        fill(&mut buf, 10);
+        assert!(buf.set_capacity(160).is_ok());
+        assert!(buf.heap.is_none());
+        assert!(buf.is_empty());
+        fill(&mut buf, 160);
+        assert!(!buf.has_space(1));
+        // Overwrites at the new capacity.
+        fill(&mut buf, 2);
+        assert_eq!(buf.take_overwritten(), 2);
+        assert_eq!(buf.available_data(), 160);
+    }
+
+    #[test]
+    fn set_capacity_heap() {
+        let mut buf = Buffer::new();
+        assert!(buf.set_capacity(2 * BUFFER_CAPACITY).is_ok());
+        assert_eq!(buf.heap.as_ref().map(|heap| heap.len()), Some(2 * BUFFER_CAPACITY));
+        fill(&mut buf, 2 * BUFFER_CAPACITY);
+        assert_eq!(buf.available_data(), 2 * BUFFER_CAPACITY);
+        assert_eq!(buf.take_overwritten(), 0);
+        for i in 0..2 * BUFFER_CAPACITY {
+            assert_eq!(buf.pop(), Some(i as ItemType));
+        }
+        assert_eq!(buf.pop(), None);
+
+        // Returning to the default capacity releases the heap storage.
+        assert!(buf.set_capacity(BUFFER_CAPACITY).is_ok());
+        assert!(buf.heap.is_none());
+        assert_eq!(buf.available_space(), BUFFER_CAPACITY);
+    }
+
+    #[test]
+    fn reserve_leaves_buffer() {
+        let mut buf = Buffer::new();
+        fill(&mut buf, 10);
+        let heap = buf.reserve(2 * BUFFER_CAPACITY).unwrap();
+        assert_eq!(heap.as_ref().map(|heap| heap.len()), Some(2 * BUFFER_CAPACITY));
+        assert_eq!(buf.capacity(), BUFFER_CAPACITY);
+        assert_eq!(buf.available_data(), 10);
+        // A failed reservation does not change the buffer either.
+        assert!(buf.reserve(usize::MAX).is_err());
+        assert_eq!(buf.available_data(), 10);
+
+        buf.resize(2 * BUFFER_CAPACITY, heap);
+        assert_eq!(buf.capacity(), 2 * BUFFER_CAPACITY);
+        assert!(buf.is_empty());
+        // The current heap storage is reused.
+        assert_eq!(buf.reserve(2 * BUFFER_CAPACITY), Ok(None));
+        assert_eq!(buf.reserve(BUFFER_CAPACITY), Ok(None));
+    }
 }
@@ -0,0 +1,287 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Data movement between the I2S FIFOs and the driver's buffers.
+//!
+//! This is the part of the watermark interrupt handlers that touches
+//! the hardware. It has no seL4 dependencies so tests/ can drive it
+//! with recorded register traces.
+
+use crate::buffer::Buffer;
+use crate::{get_fifo_status, get_rdata, set_wdata};
+use alloc::collections::TryReserveError;
+use core::ops::DerefMut;
+use log::trace;
+
+const I2S_TX_FIFO_CAPACITY: u32 = 32;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum WhichBuffer {
+    A,
+    B,
+}
+
+pub struct DoubleBuffer {
+    pub buffer_a: Buffer,
+    pub buffer_b: Buffer,
+    // Front is the current buffer to read received data
+    front: WhichBuffer,
+    // Back is the current buffer to write received data (from the RX FIFO)
+    back: WhichBuffer,
+    // Running count of received samples overwritten before they were
+    // read; the drop log and the collecting client each keep a mark
+    // of what they have seen
+    overwritten: usize,
+    logged: usize,    // |overwritten| at the last take_dropped
+    collected: usize, // |overwritten| at the last take_overwritten
+    // Set with stop_on_full once both buffers are full; recording is
+    // over and the remaining data is left to be collected
+    full: bool,
+}
+impl DoubleBuffer {
+    pub const fn new() -> Self {
+        Self {
+            buffer_a: Buffer::new(),
+            buffer_b: Buffer::new(),
+            front: WhichBuffer::B,
+            back: WhichBuffer::A,
+            overwritten: 0,
+            logged: 0,
+            collected: 0,
+            full: false,
+        }
+    }
+    pub fn front(&mut self) -> &mut Buffer {
+        if self.front == WhichBuffer::A {
+            &mut self.buffer_a
+        } else {
+            &mut self.buffer_b
+        }
+    }
+    pub fn back(&mut self) -> &mut Buffer {
+        if self.back == WhichBuffer::A {
+            &mut self.buffer_a
+        } else {
+            &mut self.buffer_b
+        }
+    }
+    pub fn flip(&mut self) -> bool {
+        assert!(self.back().available_space() == 0);
+        if self.front().is_empty() {
+            let next = self.front;
+            self.front = self.back;
+            self.back = next;
+            true
+        } else {
+            false // NB: will lose data, see drain_rx_fifo
+        }
+    }
+    /// Sets the number of samples each buffer holds; any received
+    /// data is discarded. On allocation failure neither buffer is
+    /// changed.
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<(), TryReserveError> {
+        if self.buffer_a.capacity() != capacity {
+            // NB: allocate for both before changing either so the two
+            //   buffers always have the same capacity
+            let heap_a = self.buffer_a.reserve(capacity)?;
+            let heap_b = self.buffer_b.reserve(capacity)?;
+            self.buffer_a.resize(capacity, heap_a);
+            self.buffer_b.resize(capacity, heap_b);
+        }
+        Ok(())
+    }
+    // Folds the samples overwritten in either buffer into the count.
+    fn count_overwritten(&mut self) {
+        let count = self.buffer_a.take_overwritten() + self.buffer_b.take_overwritten();
+        self.overwritten = self.overwritten.wrapping_add(count);
+    }
+    /// Returns the number of samples dropped since the last call.
+    pub fn take_dropped(&mut self) -> usize {
+        self.count_overwritten();
+        let count = self.overwritten.wrapping_sub(self.logged);
+        self.logged = self.overwritten;
+        count
+    }
+    /// Returns the number of received samples overwritten since the
+    /// last take_overwritten. This counts the same samples as
+    /// take_dropped but for the client collecting the data rather than
+    /// the drop log.
+    pub fn overwritten(&mut self) -> usize {
+        self.count_overwritten();
+        self.overwritten.wrapping_sub(self.collected)
+    }
+    /// Like overwritten but also resets the count.
+    pub fn take_overwritten(&mut self) -> usize {
+        let count = self.overwritten();
+        self.collected = self.overwritten;
+        count
+    }
+    /// Returns true if recording stopped because both buffers filled.
+    pub fn is_full(&self) -> bool { self.full }
+    /// Resumes receiving after recording stopped on full.
+    pub fn restart(&mut self) { self.full = false; }
+    /// Returns true once recording stopped on full and everything
+    /// received has been collected.
+    pub fn session_ended(&mut self) -> bool {
+        self.full && self.front().is_empty() && self.back().is_empty()
+    }
+    /// Returns the next received sample. After recording stops on full
+    /// nothing else flips the buffers so the back buffer is read once
+    /// the front buffer is empty.
+    pub fn pop(&mut self) -> Option<u32> {
+        self.flip_if_stopped();
+        self.front().pop()
+    }
+    /// Returns the samples a collect of up to |max| samples would pop,
+    /// in place, if they are stored contiguously. When fewer than |max|
+    /// are available the collect must not wait (|wait_if_empty|) or go
+    /// on to the back buffer. Nothing is removed; use discard once the
+    /// samples are read.
+    pub fn collect_slice(&mut self, max: usize, wait_if_empty: bool) -> Option<&[u32]> {
+        self.flip_if_stopped();
+        let full = self.full;
+        let front = self.front();
+        let span = front.front_slice();
+        if span.len() >= max {
+            Some(&span[..max])
+        } else if !wait_if_empty && !full && span.len() == front.available_data() {
+            Some(span)
+        } else {
+            None
+        }
+    }
+    /// Removes |count| samples returned by collect_slice.
+    pub fn discard(&mut self, count: usize) { self.front().discard(count); }
+    /// Puts back |count| samples just removed by discard.
+    pub fn restore(&mut self, count: usize) { self.front().restore(count); }
+    fn flip_if_stopped(&mut self) {
+        if self.full && self.front().is_empty() && !self.back().is_empty() {
+            self.flip();
+        }
+    }
+    pub fn clear(&mut self) {
+        self.front().clear();
+        self.back().clear();
+        self.count_overwritten();
+        self.overwritten = 0;
+        self.logged = 0;
+        self.collected = 0;
+        self.full = false;
+    }
+}
+
+pub fn tx_fifo_level() -> u32 { get_fifo_status().txlvl().into() }
+pub fn rx_fifo_level() -> u32 { get_fifo_status().rxlvl().into() }
+
+/// Copies from the receive FIFO into the back buffer and flips the
+/// buffers once the back buffer is full.
+///
+/// With |stop_on_full| data is left in the receive FIFO when the back
+/// buffer is full; once both buffers are full |buf| is marked full so
+/// the caller can stop recording. Otherwise the back buffer is
+/// overwritten and the overwritten samples are counted as dropped
+/// (see take_dropped & take_overwritten).
+/// Returns true if the buffers were flipped.
+pub fn drain_rx_fifo(buf: &mut DoubleBuffer, stop_on_full: bool) -> bool {
+    if buf.full {
+        return false;
+    }
+    let back = buf.back();
+    if stop_on_full {
+        while rx_fifo_level() > 0 && back.available_space() > 0 {
+            back.push(get_rdata());
+        }
+    } else {
+        while rx_fifo_level() > 0 {
+            back.push(get_rdata());
+        }
+    }
+    let flipped = back.available_space() == 0 && buf.flip();
+    // NB: a back buffer still full could not flip so both are full
+    buf.full = stop_on_full && buf.back().available_space() == 0;
+    flipped
+}
+
+/// Copies from TX_BUFFER into the transmit FIFO.
+///
+/// This stops when the transmit FIFO is full or when TX_BUFFER is empty,
+/// whichever comes first.
+pub fn fill_tx_fifo(buf: &mut Buffer) {
+    trace!("fill_tx_fifo {} buf {}", tx_fifo_level(), buf.available_data());
+    while tx_fifo_level() < I2S_TX_FIFO_CAPACITY {
+        if let Some(b) = buf.pop() {
+            set_wdata(b);
+        } else {
+            break;
+        }
+    }
+}
+
+/// Plays out |buf| through the transmit FIFO until both are empty.
+///
+/// |wait| blocks until the FIFO drains; it is handed the buffer so a
+/// caller holding a lock can release it while blocked. |expired| is
+/// checked each time around and, once true, the unplayed samples in
+/// |buf| are discarded. Returns the buffer and whether it was played out.
+pub fn drain_tx_buffer<B: DerefMut<Target = Buffer>>(
+    mut buf: B,
+    mut wait: impl FnMut(B) -> B,
+    expired: impl Fn() -> bool,
+) -> (B, bool) {
+    while !buf.is_empty() || tx_fifo_level() > 0 {
+        if expired() {
+            trace!("drain_tx_buffer: discard {}", buf.available_data());
+            while buf.pop().is_some() {}
+            return (buf, false);
+        }
+        fill_tx_fifo(&mut buf);
+        buf = wait(buf);
+    }
+    (buf, true)
+}
+
+/// Writes |pattern| to the transmit FIFO and checks it comes back
+/// through the receive FIFO, as it does with loopback enabled.
+///
+/// Up to |latency| samples received ahead of the pattern (e.g. stale
+/// data still in the FIFO path) are skipped; once the first sample of
+/// the pattern is seen every following sample must match. |poll| is
+/// called while waiting for data; the check fails once it returns false.
+pub fn loopback_check(pattern: &[u32], latency: usize, mut poll: impl FnMut() -> bool) -> bool {
+    assert!(pattern.len() <= I2S_TX_FIFO_CAPACITY as usize);
+    for sample in pattern {
+        set_wdata(*sample);
+    }
+    let mut skipped = 0;
+    let mut matched = 0;
+    while matched < pattern.len() {
+        if rx_fifo_level() == 0 {
+            if !poll() {
+                trace!("loopback_check: timeout, matched {matched}");
+                return false;
+            }
+            continue;
+        }
+        let sample = get_rdata();
+        if sample == pattern[matched] {
+            matched += 1;
+        } else if matched == 0 && skipped < latency {
+            skipped += 1;
+        } else {
+            trace!("loopback_check: got {sample:#x} expected {:#x}", pattern[matched]);
+            return false;
+        }
+    }
+    true
+}
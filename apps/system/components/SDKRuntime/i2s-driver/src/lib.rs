@@ -11,21 +11,42 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
+extern crate alloc;
 use cantrip_os_common::camkes::semaphore::seL4_Semaphore;
+use core::time::Duration;
 #[allow(unused_imports)]
-use log::{error, info, trace};
+use log::{error, info, trace, warn};
+use sdk_interface::AudioDirection;
+use sdk_interface::AudioFormat;
 use sdk_interface::SDKError;
+use sdk_interface::AUDIO_RECORD_BUFFER_MAX;
 use spin::Mutex;
+use spin::MutexGuard;
 
 mod buffer;
 use buffer::Buffer; // NB: buffer holds 32-bit values
+use buffer::BUFFER_CAPACITY;
 
 #[allow(dead_code)]
 mod i2s;
 use i2s::*;
 
+mod fifo;
+use fifo::{
+    drain_rx_fifo, drain_tx_buffer, fill_tx_fifo, loopback_check, rx_fifo_level, tx_fifo_level,
+    DoubleBuffer,
+};
+
+mod overrun;
+use overrun::DropLog;
+
+#[allow(dead_code)]
+mod watermark;
+#[cfg(feature = "adaptive_rxilvl")]
+use watermark::RxWatermark;
+
 extern "Rust" {
     static RX_NONEMPTY: seL4_Semaphore;
     static TX_EMPTY: seL4_Semaphore;
@@ -33,78 +54,50 @@ extern "Rust" {
 
 use reg_constants::platform::TOP_MATCHA_SMC_I2S_CLOCK_FREQ_PERIPHERAL_HZ as CLK_FIXED_FREQ_HZ;
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum WhichBuffer {
-    A,
-    B,
-}
-
-struct DoubleBuffer {
-    pub buffer_a: Buffer,
-    pub buffer_b: Buffer,
-    // Front is the current buffer to read received data
-    pub front: WhichBuffer,
-    // Back is the current buffer to write received data (from the RX FIFO)
-    pub back: WhichBuffer,
-}
-impl DoubleBuffer {
-    pub const fn new() -> Self {
-        Self {
-            buffer_a: Buffer::new(),
-            buffer_b: Buffer::new(),
-            front: WhichBuffer::B,
-            back: WhichBuffer::A,
-        }
-    }
-    pub fn front(&mut self) -> &mut Buffer {
-        if self.front == WhichBuffer::A {
-            &mut self.buffer_a
-        } else {
-            &mut self.buffer_b
-        }
-    }
-    pub fn back(&mut self) -> &mut Buffer {
-        if self.back == WhichBuffer::A {
-            &mut self.buffer_a
-        } else {
-            &mut self.buffer_b
-        }
-    }
-    pub fn flip(&mut self) -> bool {
-        assert!(self.back().available_space() == 0);
-        if self.front().is_empty() {
-            let next = self.front;
-            self.front = self.back;
-            self.back = next;
-            true
-        } else {
-            false // NB: will lose data, maybe count
-        }
-    }
-    pub fn clear(&mut self) {
-        self.front().clear();
-        self.back().clear();
-    }
-}
+// NB: holds BUFFER_CAPACITY samples per buffer until audio_record_start
+//   sets the capacity requested by the client
 static RX_BUFFER: Mutex<DoubleBuffer> = Mutex::new(DoubleBuffer::new());
 static mut RX_STOP_ON_FULL: bool = false; // NB: protected by RX_BUFFER
+
+// Dropped samples are logged at most once per interval using the time
+// from RX_CLOCK; without a clock drops are logged only when recording
+// stops. RX_CLOCK may be an RPC so it is never called with RX_BUFFER held.
+pub const RX_DROP_LOG_INTERVAL_DEFAULT: Duration = Duration::from_secs(1);
+fn no_clock() -> Option<Duration> { None }
+// NB: RX_DROP_LOG & RX_CLOCK are protected by RX_BUFFER
+static mut RX_DROP_LOG: DropLog = DropLog::new(RX_DROP_LOG_INTERVAL_DEFAULT);
+static mut RX_CLOCK: fn() -> Option<Duration> = no_clock;
+// Set while a collect with a deadline is blocked so the RX handler wakes
+// it on each interrupt to check the time.
+static mut RX_TIMED_WAIT: bool = false; // NB: protected by RX_BUFFER
+#[cfg(feature = "adaptive_rxilvl")]
+static mut RX_WATERMARK: Option<RxWatermark> = None; // NB: protected by RX_BUFFER
 static TX_BUFFER: Mutex<Buffer> = Mutex::new(Buffer::new());
+// Set while a write or stop with a deadline is blocked so the TX handler
+// wakes it on each interrupt to check the time.
+// NB: protected by TX_BUFFER
+static mut TX_TIMED_WAIT: bool = false;
+// Free space in TX_BUFFER required to wakeup a blocked writer.
+const TX_WAKEUP_THRESHOLD_DEFAULT: usize = 16;
+static mut TX_WAKEUP_THRESHOLD: usize = TX_WAKEUP_THRESHOLD_DEFAULT; // NB: protected by TX_BUFFER
+
+// XXX worth making errors distinct?
+fn cvt_rxilvl(rxilvl: u8) -> Result<RxILvl, SDKError> {
+    match rxilvl {
+        1 => Ok(RxILvl::RxLvl1),
+        4 => Ok(RxILvl::RxLvl4),
+        8 => Ok(RxILvl::RxLvl8),
+        16 => Ok(RxILvl::RxLvl16),
+        30 => Ok(RxILvl::RxLvl30),
+        _ => Err(SDKError::InvalidAudioParameter),
+    }
+}
 
 /// Resets the audio hardware according to |rxrst| and |txrst| and
 /// sets the tx/rx FIFO watermark levels. Any recording or playing
-/// is terminated.
+/// is terminated. With the "adaptive_rxilvl" feature |rxilvl| is the
+/// starting point for tuning the RX watermark level while recording.
 pub fn audio_reset(rxrst: bool, txrst: bool, rxilvl: u8, txilvl: u8) -> Result<(), SDKError> {
-    // XXX worth making errors distinct?
-    fn cvt_rxilvl(rxilvl: u8) -> Result<RxILvl, SDKError> {
-        match rxilvl {
-            1 => Ok(RxILvl::RxLvl1),
-            4 => Ok(RxILvl::RxLvl4),
-            8 => Ok(RxILvl::RxLvl8),
-            16 => Ok(RxILvl::RxLvl16),
-            30 => Ok(RxILvl::RxLvl30),
-            _ => Err(SDKError::InvalidAudioParameter),
-        }
-    }
     fn cvt_txilvl(txilvl: u8) -> Result<TxILvl, SDKError> {
         match txilvl {
             1 => Ok(TxILvl::TxLvl1),
@@ -130,9 +123,35 @@ pub fn audio_reset(rxrst: bool, txrst: bool, rxilvl: u8, txilvl: u8) -> Result<(
             .with_rxilvl(cvt_rxilvl(rxilvl)?)
             .with_txilvl(cvt_txilvl(txilvl)?),
     );
+    #[cfg(feature = "adaptive_rxilvl")]
+    {
+        let _buf = RX_BUFFER.lock();
+        unsafe {
+            RX_WATERMARK = Some(RxWatermark::new(rxilvl));
+        }
+    }
     Ok(())
 }
 
+// Adjusts the RX watermark level according to how much received data
+// is waiting for the consumer.
+#[cfg(feature = "adaptive_rxilvl")]
+fn adapt_rxilvl(buf: &mut DoubleBuffer) {
+    // NB: must be called with RX_BUFFER lock held
+    let pending = buf.front().available_data();
+    let capacity = buf.front().capacity();
+    if let Some(rxilvl) = unsafe { RX_WATERMARK.as_mut() }.and_then(|w| w.sample(pending, capacity))
+    {
+        trace!("rxilvl -> {rxilvl} pending {pending}");
+        set_fifo_ctrl(
+            get_fifo_ctrl()
+                .with_rxrst(false)
+                .with_txrst(false)
+                .with_rxilvl(cvt_rxilvl(rxilvl).unwrap()),
+        );
+    }
+}
+
 fn audio_drain_rx_fifo() {
     // NB: must be called with RX_BUFFER lock held
     trace!("audio_drain_rx_fifo begin");
@@ -141,20 +160,55 @@ fn audio_drain_rx_fifo() {
     }
     trace!("audio_drain_rx_fifo end");
 }
-fn audio_stop_recording(buf: &mut DoubleBuffer) {
-    trace!("audio_stop_recording");
+fn audio_disable_rx() {
     // NB: must be called with RX_BUFFER lock held
     set_ctrl(get_ctrl().with_rx(false));
     set_fifo_ctrl(get_fifo_ctrl().with_rxrst(true)); // Flush RX FIFO
     set_intr_enable(get_intr_enable().with_rx_watermark(false));
     set_intr_state(get_intr_state().with_rx_watermark(false));
     audio_drain_rx_fifo();
+}
+fn audio_stop_recording(buf: &mut DoubleBuffer) {
+    trace!("audio_stop_recording");
+    audio_disable_rx();
     buf.clear();
+    if let Some(count) = unsafe { RX_DROP_LOG.flush() } {
+        log_drops(count);
+    }
+}
+
+fn log_drops(count: usize) {
+    // NB: must be called with RX_BUFFER lock held
+    warn!(
+        "rx: dropped {count} samples in the last {}ms",
+        unsafe { RX_DROP_LOG.interval() }.as_millis()
+    );
+}
+
+// Reports drops left pending at the end of an overrun burst once the
+// interval has elapsed. Called when the client collects since that is
+// when the consumer has caught up. The lock is released to read the
+// clock and re-acquired to update the log.
+fn expire_drops(guard: MutexGuard<DoubleBuffer>) {
+    if !unsafe { RX_DROP_LOG.is_pending() } {
+        return;
+    }
+    let clock = unsafe { RX_CLOCK };
+    drop(guard);
+    if let Some(now) = clock() {
+        let _buf = RX_BUFFER.lock();
+        if let Some(count) = unsafe { RX_DROP_LOG.expire(now) } {
+            log_drops(count);
+        }
+    }
 }
 
+/// Starts recording at |rate|. Received samples are double-buffered
+/// with each buffer holding |buffer_size| samples; sizes up to the
+/// default BUFFER_CAPACITY need no heap allocation.
 pub fn audio_record_start(
     rate: usize,
-    _buffer_size: usize,
+    buffer_size: usize,
     stop_on_full: bool,
 ) -> Result<(), SDKError> {
     fn nz(x: usize) -> usize {
@@ -164,24 +218,136 @@ pub fn audio_record_start(
             x
         }
     }
-    trace!("audio_record_start rate {rate} stop_on_full {stop_on_full}");
-    let mut _buf = RX_BUFFER.lock();
+    trace!("audio_record_start rate {rate} buffer_size {buffer_size} stop_on_full {stop_on_full}");
+    let mut buf = RX_BUFFER.lock();
     let nco_rx = CLK_FIXED_FREQ_HZ / (nz(2 * rate) as u64);
     if nco_rx > reg_constants::i2s::I2S_CTRL_NCO_RX_MASK as u64 {
         error!("bad nco_rx {nco_rx} for rate {rate}");
         return Err(SDKError::InvalidAudioParameter);
     }
+    if buffer_size == 0 || buffer_size > AUDIO_RECORD_BUFFER_MAX {
+        error!("bad buffer_size {buffer_size}");
+        return Err(SDKError::InvalidAudioParameter);
+    }
+    if buf.set_capacity(buffer_size).is_err() {
+        error!("no memory for {buffer_size} sample record buffers");
+        return Err(SDKError::OutOfResources);
+    }
     // XXX or force client to stop?
     //    audio_stop_recording(buf);
     unsafe {
         RX_STOP_ON_FULL = stop_on_full;
     }
+    buf.restart();
     set_intr_state(get_intr_state().with_rx_watermark(true));
     set_intr_enable(get_intr_enable().with_rx_watermark(true));
     set_ctrl(get_ctrl().with_rx(true).with_nco_rx(nco_rx as u8));
     Ok(())
 }
 
+// Samples are always 16-bit stereo packed into a u32 (see
+// sdk_interface::audio); only the rate is configurable.
+const AUDIO_CHANNELS: u8 = 2;
+const AUDIO_BITS_PER_SAMPLE: u8 = 16;
+
+/// Returns the sample format for |direction|. The rate is derived from
+/// the NCO divider so it reflects what the hardware actually runs at
+/// rather than the rate requested; 0 if the divider was never set.
+pub fn audio_get_format(direction: AudioDirection) -> AudioFormat {
+    let ctrl = get_ctrl();
+    let nco = match direction {
+        AudioDirection::Record => ctrl.nco_rx(),
+        AudioDirection::Play => ctrl.nco_tx(),
+    };
+    let sample_rate = if nco == 0 {
+        0
+    } else {
+        (CLK_FIXED_FREQ_HZ / (2 * nco as u64)) as usize
+    };
+    AudioFormat {
+        sample_rate,
+        channels: AUDIO_CHANNELS,
+        bits_per_sample: AUDIO_BITS_PER_SAMPLE,
+    }
+}
+
+// Loopback self-test parameters. The ramp fits in the TX FIFO and
+// starts at 1 so stale (zero) samples are not mistaken for it.
+const SELFTEST_SAMPLES: usize = 16;
+// Samples that may be received ahead of the ramp: one FIFO's worth.
+const SELFTEST_LATENCY: usize = 32;
+// RX FIFO polls before giving up on the echo.
+const SELFTEST_POLLS: usize = 100_000;
+
+/// Checks the audio data path by enabling loopback, writing a ramp to
+/// the transmit FIFO, and reading it back from the receive FIFO.
+/// Recording and playing must be stopped (InvalidAudioState otherwise).
+/// Returns SelfTestFailed if the ramp does not come back intact. On
+/// return loopback is disabled and both FIFOs are flushed whatever
+/// the outcome.
+pub fn audio_selftest() -> Result<(), SDKError> {
+    trace!("audio_selftest");
+    let _rx_buf = RX_BUFFER.lock();
+    let _tx_buf = TX_BUFFER.lock();
+    let ctrl = get_ctrl();
+    if ctrl.rx() || ctrl.tx() {
+        error!("audio_selftest: stop recording & playing first");
+        return Err(SDKError::InvalidAudioState);
+    }
+    // Data only move with the NCOs running; use the slowest rate if
+    // a stream was never started.
+    fn nz(nco: u8, max: u8) -> u8 {
+        if nco == 0 {
+            max
+        } else {
+            nco
+        }
+    }
+    let ramp: [u32; SELFTEST_SAMPLES] = core::array::from_fn(|i| {
+        let x = i as u32 + 1;
+        (x << 16) | x // Same value on both channels
+    });
+    set_ctrl(
+        get_ctrl()
+            .with_nco_rx(nz(ctrl.nco_rx(), reg_constants::i2s::I2S_CTRL_NCO_RX_MASK as u8))
+            .with_nco_tx(nz(ctrl.nco_tx(), reg_constants::i2s::I2S_CTRL_NCO_TX_MASK as u8))
+            .with_slpbk(true)
+            .with_rx(true)
+            .with_tx(true),
+    );
+    let mut polls = 0;
+    let passed = loopback_check(&ramp, SELFTEST_LATENCY, || {
+        polls += 1;
+        polls < SELFTEST_POLLS
+    });
+    // NB: the saved ctrl has rx & tx disabled
+    set_ctrl(ctrl.with_slpbk(false));
+    set_fifo_ctrl(
+        get_fifo_ctrl()
+            .with_rxrst(true) // Flush RX FIFO
+            .with_txrst(true), // Flush TX FIFO
+    );
+    audio_drain_rx_fifo();
+    if passed {
+        Ok(())
+    } else {
+        error!("audio_selftest: loopback data mismatch");
+        Err(SDKError::SelfTestFailed)
+    }
+}
+
+/// Sets how often samples dropped while recording are logged; |now|
+/// supplies the time (e.g. from the TimerService). A zero |interval|
+/// disables logging.
+pub fn audio_set_drop_log(interval: Duration, now: fn() -> Option<Duration>) {
+    trace!("audio_set_drop_log {interval:?}");
+    let _buf = RX_BUFFER.lock();
+    unsafe {
+        RX_DROP_LOG.set_interval(interval);
+        RX_CLOCK = now;
+    }
+}
+
 pub fn audio_record_stop() -> Result<(), SDKError> {
     trace!("audio_record_stop");
     let mut buf = RX_BUFFER.lock();
@@ -189,38 +355,104 @@ pub fn audio_record_stop() -> Result<(), SDKError> {
     Ok(())
 }
 
-pub fn audio_record_collect(data: &mut [u32], wait_if_empty: bool) -> Result<usize, SDKError> {
+/// Collects up to |data.len()| recorded samples. With |wait_if_empty|
+/// this blocks for more data until |data| is full or |expired| returns
+/// true; if that happens before anything is collected Timeout is returned.
+/// |expired| is None when there is no deadline; otherwise it is checked
+/// each time the RX handler runs so a blocked collect returns within
+/// one watermark interrupt of the deadline.
+/// Returns the number of samples collected, whether the recording
+/// session ended (it stopped on full and everything has been collected),
+/// and the number of samples overwritten since the last collect.
+pub fn audio_record_collect(
+    data: &mut [u32],
+    wait_if_empty: bool,
+    expired: Option<impl Fn() -> bool>,
+) -> Result<(usize, bool, usize), SDKError> {
     let mut guard = RX_BUFFER.lock();
-    let mut buf = guard.front();
     let mut count = 0;
     while count < data.len() {
-        if let Some(b) = buf.pop() {
+        if let Some(b) = guard.pop() {
             data[count] = b;
             count += 1;
         } else {
             // Optionally block until data is present. Note this may
             // block the caller which may block the runtime interface
             // thread which in turn may block other apps/clients.
-            if wait_if_empty {
+            // Once recording has stopped on full no more data arrives.
+            if wait_if_empty && !guard.is_full() {
                 // XXX maybe check count < data.len / 2 or similar?
                 trace!("wait for flip");
-                while buf.is_empty() {
+                while guard.front().is_empty() && !guard.is_full() {
+                    if expired.as_ref().map_or(false, |expired| expired()) {
+                        unsafe {
+                            RX_TIMED_WAIT = false;
+                        }
+                        return if count > 0 {
+                            Ok((count, false, guard.take_overwritten()))
+                        } else {
+                            Err(SDKError::Timeout)
+                        };
+                    }
+                    unsafe {
+                        RX_TIMED_WAIT = expired.is_some();
+                    }
                     drop(guard);
                     unsafe {
                         RX_NONEMPTY.wait();
                     }
                     guard = RX_BUFFER.lock();
-                    buf = guard.front();
                 }
             } else {
                 break;
             }
         }
     }
-    Ok(count)
+    unsafe {
+        RX_TIMED_WAIT = false;
+    }
+    let result = (count, guard.session_ended(), guard.take_overwritten());
+    expire_drops(guard);
+    Ok(result)
 }
 
-pub fn audio_play_start(rate: usize, _buffer_size: usize) -> Result<(), SDKError> {
+/// Like audio_record_collect but passes the samples to |f| in place
+/// rather than copying them out of the record buffer, along with
+/// whether the session ended and the number of samples overwritten.
+/// This is possible only when the samples are stored contiguously and
+/// no wait is needed; otherwise nothing is collected and None is
+/// returned so the caller can fall back to audio_record_collect.
+/// The samples are collected only if |f| succeeds; on error they are
+/// left for the next collect.
+pub fn audio_record_collect_in_place<R, E>(
+    max_samples: usize,
+    wait_if_empty: bool,
+    f: impl FnOnce(&[u32], bool, usize) -> Result<R, E>,
+) -> Option<Result<R, E>> {
+    let mut guard = RX_BUFFER.lock();
+    let count = guard.collect_slice(max_samples, wait_if_empty)?.len();
+    // NB: discard the samples to check whether this collect ends the
+    //   session, then put them back for |f|; they are untouched since
+    //   the RX handler only writes the back buffer and is held off by
+    //   the lock
+    guard.discard(count);
+    let session_ended = guard.session_ended();
+    guard.restore(count);
+    let overwritten = guard.overwritten();
+    let result = f(&guard.front().front_slice()[..count], session_ended, overwritten);
+    if result.is_ok() {
+        guard.discard(count);
+        guard.take_overwritten();
+    }
+    expire_drops(guard);
+    Some(result)
+}
+
+pub fn audio_play_start(
+    rate: usize,
+    _buffer_size: usize,
+    wakeup_threshold: Option<usize>,
+) -> Result<(), SDKError> {
     fn nz(x: usize) -> usize {
         if x == 0 {
             1
@@ -228,13 +460,21 @@ pub fn audio_play_start(rate: usize, _buffer_size: usize) -> Result<(), SDKError
             x
         }
     }
-    trace!("audio_play_start {rate}");
+    trace!("audio_play_start {rate} {wakeup_threshold:?}");
     let mut buf = TX_BUFFER.lock();
     let nco_tx = CLK_FIXED_FREQ_HZ / (nz(2 * rate) as u64);
     if nco_tx > reg_constants::i2s::I2S_CTRL_NCO_TX_MASK as u64 {
         error!("bad nco_tx {nco_tx} for rate {rate}");
         return Err(SDKError::InvalidAudioParameter);
     }
+    let wakeup_threshold = wakeup_threshold.unwrap_or(TX_WAKEUP_THRESHOLD_DEFAULT);
+    if wakeup_threshold == 0 || wakeup_threshold > BUFFER_CAPACITY {
+        error!("bad wakeup_threshold {wakeup_threshold}");
+        return Err(SDKError::InvalidAudioParameter);
+    }
+    unsafe {
+        TX_WAKEUP_THRESHOLD = wakeup_threshold;
+    }
     // XXX or force client to stop?
     buf.clear();
     //    audio_stop_playing(&mut buf);
@@ -244,31 +484,68 @@ pub fn audio_play_start(rate: usize, _buffer_size: usize) -> Result<(), SDKError
     Ok(())
 }
 
-pub fn audio_play_stop() -> Result<(), SDKError> {
+/// Stops playing once queued samples are played out. If |expired|
+/// returns true first the remaining samples are discarded, playback
+/// is stopped, and Timeout is returned. |expired| is None when there
+/// is no deadline; otherwise it is checked each time the TX handler runs.
+pub fn audio_play_stop(expired: Option<impl Fn() -> bool>) -> Result<(), SDKError> {
     trace!("audio_play_stop");
-    let mut buf = TX_BUFFER.lock();
-    // XXX client may want to flush instead of waiting
-    while !buf.is_empty() || tx_fifo_level() > 0 {
-        fill_tx_fifo(&mut buf);
-        drop(buf);
-        unsafe {
-            // XXX TxWatermark posts when buf is empty
-            TX_EMPTY.wait();
-        }
-        buf = TX_BUFFER.lock();
+    // NB: use audio_play_flush to discard queued samples instead
+    let (mut buf, drained) = drain_tx_buffer(
+        TX_BUFFER.lock(),
+        |buf| {
+            unsafe {
+                TX_TIMED_WAIT = expired.is_some();
+            }
+            drop(buf);
+            unsafe {
+                // XXX TxWatermark posts when buf is empty
+                TX_EMPTY.wait();
+            }
+            TX_BUFFER.lock()
+        },
+        || expired.as_ref().map_or(false, |expired| expired()),
+    );
+    unsafe {
+        TX_TIMED_WAIT = false;
     }
     audio_stop_playing(&mut buf);
-    Ok(())
+    if drained {
+        Ok(())
+    } else {
+        Err(SDKError::Timeout)
+    }
 }
 
-fn tx_fifo_level() -> u32 { get_fifo_status().txlvl().into() }
-fn rx_fifo_level() -> u32 { get_fifo_status().rxlvl().into() }
+/// Discards queued samples, including those in the TX FIFO, without
+/// waiting for them to be played. Playback remains enabled so new
+/// samples may be written; use audio_play_stop to end playback.
+pub fn audio_play_flush() {
+    trace!("audio_play_flush");
+    let mut buf = TX_BUFFER.lock();
+    buf.clear();
+    set_fifo_ctrl(get_fifo_ctrl().with_txrst(true)); // Flush TX FIFO
+}
 
-pub fn audio_play_write(data: &[u32]) -> Result<(), SDKError> {
+/// Queues |data| to play, blocking while the buffer is full. If
+/// |expired| returns true while blocked the rest of |data| is dropped
+/// and Timeout is returned; samples already queued are still played.
+/// |expired| is None when there is no deadline; otherwise it is checked
+/// each time the TX handler runs.
+pub fn audio_play_write(data: &[u32], expired: Option<impl Fn() -> bool>) -> Result<(), SDKError> {
     trace!("play write {}", data.len());
     let mut buf = TX_BUFFER.lock();
     for ix in 0..data.len() {
         while buf.available_space() == 0 {
+            if expired.as_ref().map_or(false, |expired| expired()) {
+                unsafe {
+                    TX_TIMED_WAIT = false;
+                }
+                return Err(SDKError::Timeout);
+            }
+            unsafe {
+                TX_TIMED_WAIT = expired.is_some();
+            }
             trace!(
                 "wait for tx_watermark {ix} avail {} fifo {}",
                 buf.available_space(),
@@ -287,27 +564,25 @@ pub fn audio_play_write(data: &[u32]) -> Result<(), SDKError> {
         }
         buf.push(data[ix]);
     }
+    unsafe {
+        TX_TIMED_WAIT = false;
+    }
     if !buf.is_empty() {
         fill_tx_fifo(&mut buf);
     }
     Ok(())
 }
 
-/// Copies from TX_BUFFER into the transmit FIFO.
-///
-/// This stops when the transmit FIFO is full or when TX_BUFFER is empty,
-/// whichever comes first.
-fn fill_tx_fifo(buf: &mut Buffer) {
-    const I2S_TX_FIFO_CAPACITY: u32 = 32;
-
-    trace!("fill_tx_fifo {} buf {}", tx_fifo_level(), buf.available_data());
-    while tx_fifo_level() < I2S_TX_FIFO_CAPACITY {
-        if let Some(b) = buf.pop() {
-            set_wdata(b);
-        } else {
-            break;
-        }
+/// Queues as many samples of |data| as fit in the play buffer without
+/// blocking. Returns the number queued; 0 if the buffer is full.
+pub fn audio_play_write_nb(data: &[u32]) -> usize {
+    trace!("play write nb {}", data.len());
+    let mut buf = TX_BUFFER.lock();
+    let count = buf.push_available(data);
+    if !buf.is_empty() {
+        fill_tx_fifo(&mut buf);
     }
+    count
 }
 
 fn audio_stop_playing(buf: &mut Buffer) {
@@ -328,32 +603,47 @@ impl RxWatermarkInterfaceThread {
         trace!("rx_watermark begin");
         // Drain the RX fifo; data goes to the RX_BUFFER.
         let mut guard = RX_BUFFER.lock();
-        if unsafe { RX_STOP_ON_FULL } {
-            let buf = guard.back();
-            while rx_fifo_level() > 0 && buf.available_space() > 0 {
-                buf.push(get_rdata());
+        let flipped = drain_rx_fifo(&mut guard, unsafe { RX_STOP_ON_FULL });
+        if flipped {
+            trace!("buffer flip");
+            // Notify any waiters of the buffer flip.
+            unsafe {
+                RX_NONEMPTY.post();
             }
-        } else {
-            let buf = guard.back();
-            while rx_fifo_level() > 0 {
-                buf.push(get_rdata());
+        } else if guard.is_full() {
+            // Both buffers are full; stop recording but keep the data
+            // for the client to collect. Waiters are woken so they see
+            // the session has ended.
+            trace!("buffer full, stop recording");
+            audio_disable_rx();
+            unsafe {
+                RX_NONEMPTY.post();
             }
-        }
-        if guard.back().available_space() == 0 {
-            if guard.flip() {
-                trace!("buffer flip");
-                // Notify any waiters of the buffer flip.
-                unsafe {
-                    RX_NONEMPTY.post();
-                }
+        } else if unsafe { RX_TIMED_WAIT } {
+            // Wake a blocked collect to check its deadline.
+            unsafe {
+                RX_NONEMPTY.post();
             }
         }
+        #[cfg(feature = "adaptive_rxilvl")]
+        adapt_rxilvl(&mut guard);
+        let dropped = guard.take_dropped();
+        let clock = unsafe { RX_CLOCK };
         set_intr_state(get_intr_state().with_rx_watermark(true));
         trace!(
             "rx_watermark end, fifo {} buf {}",
             rx_fifo_level(),
             guard.back().available_data()
         );
+        drop(guard);
+        // NB: the clock is read only on drops and without RX_BUFFER held
+        if dropped != 0 {
+            let now = clock();
+            let _buf = RX_BUFFER.lock();
+            if let Some(count) = unsafe { RX_DROP_LOG.record(dropped, now) } {
+                log_drops(count);
+            }
+        }
     }
 }
 
@@ -363,7 +653,8 @@ impl TxWatermarkInterfaceThread {
         trace!("handle tx_watermark");
         let mut buf = TX_BUFFER.lock();
         fill_tx_fifo(&mut buf);
-        if buf.available_space() >= 16 {
+        // NB: a blocked request with a deadline is woken to check it
+        if unsafe { TX_TIMED_WAIT } || buf.has_space(unsafe { TX_WAKEUP_THRESHOLD }) {
             unsafe {
                 TX_EMPTY.post();
             }
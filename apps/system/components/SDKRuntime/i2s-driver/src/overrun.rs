@@ -0,0 +1,186 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Throttled reporting of dropped RX samples.
+//!
+//! When recording without stop_on_full a consumer that falls behind
+//! causes samples to be overwritten on every interrupt. Rather than log
+//! each drop the count is accumulated and reported at most once per
+//! interval. The caller supplies the time, and should read the clock
+//! only when samples are dropped (or drops are pending) so the interrupt
+//! path does not pay for it otherwise. Drops left pending when a burst
+//! ends are reported by |expire| or |flush|.
+
+use core::time::Duration;
+
+pub struct DropLog {
+    interval: Duration,      // Zero disables reporting
+    start: Option<Duration>, // Start of the current interval
+    dropped: usize,          // Samples dropped since |start|
+}
+impl DropLog {
+    pub const fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            start: None,
+            dropped: 0,
+        }
+    }
+
+    /// Sets the reporting interval and starts a new one.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+        self.start = None;
+        self.dropped = 0;
+    }
+
+    pub fn interval(&self) -> Duration { self.interval }
+
+    /// Returns true if there are drops not yet reported.
+    pub fn is_pending(&self) -> bool { self.dropped != 0 }
+
+    /// Records |dropped| samples at time |now|. Returns the number of
+    /// samples dropped over the interval at the first drop after it has
+    /// elapsed. Without a clock drops are counted but only reported
+    /// by |flush|.
+    pub fn record(&mut self, dropped: usize, now: Option<Duration>) -> Option<usize> {
+        if dropped == 0 || self.interval.is_zero() {
+            return None;
+        }
+        self.dropped = self.dropped.saturating_add(dropped);
+        let now = now?;
+        let start = *self.start.get_or_insert(now);
+        if now.saturating_sub(start) < self.interval {
+            return None;
+        }
+        self.start = Some(now);
+        Some(core::mem::take(&mut self.dropped))
+    }
+
+    /// Reports pending drops if the interval has elapsed at |now|
+    /// without a further drop (i.e. the burst has ended). The next
+    /// drop starts a new interval.
+    pub fn expire(&mut self, now: Duration) -> Option<usize> {
+        let start = self.start?;
+        if self.dropped == 0 || now.saturating_sub(start) < self.interval {
+            return None;
+        }
+        self.start = None;
+        Some(core::mem::take(&mut self.dropped))
+    }
+
+    /// Reports any pending drops regardless of the interval (e.g. when
+    /// recording stops).
+    pub fn flush(&mut self) -> Option<usize> {
+        self.start = None;
+        match core::mem::take(&mut self.dropped) {
+            0 => None,
+            dropped => Some(dropped),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    // Runs |ticks| interrupts |period| apart each dropping |dropped|
+    // samples; returns the (time in ms, count) of each report.
+    fn overrun(
+        log: &mut DropLog,
+        ticks: u64,
+        period: Duration,
+        dropped: usize,
+    ) -> Vec<(u64, usize)> {
+        (0..ticks)
+            .filter_map(|tick| {
+                let now = period * tick as u32;
+                log.record(dropped, Some(now))
+                    .map(|count| (now.as_millis() as u64, count))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn continuous_overrun() {
+        // 16 samples dropped every 10ms are reported every 100ms.
+        let mut log = DropLog::new(INTERVAL);
+        let reports = overrun(&mut log, 35, Duration::from_millis(10), 16);
+        assert_eq!(reports, [(100, 11 * 16), (200, 10 * 16), (300, 10 * 16)]);
+    }
+
+    #[test]
+    fn configured_interval() {
+        let mut log = DropLog::new(INTERVAL);
+        log.set_interval(Duration::from_millis(250));
+        let reports = overrun(&mut log, 60, Duration::from_millis(10), 1);
+        assert_eq!(reports, [(250, 26), (500, 25)]);
+    }
+
+    #[test]
+    fn quiet_interval() {
+        let mut log = DropLog::new(INTERVAL);
+        // No drops, no report.
+        assert_eq!(log.record(0, Some(INTERVAL)), None);
+        assert!(!log.is_pending());
+        // The first drop starts the interval; drops are reported at the
+        // first drop after it has elapsed.
+        assert_eq!(log.record(3, Some(INTERVAL)), None);
+        assert!(log.is_pending());
+        assert_eq!(log.record(0, None), None);
+        assert_eq!(log.record(2, Some(3 * INTERVAL)), Some(5));
+        assert!(!log.is_pending());
+    }
+
+    #[test]
+    fn burst_end() {
+        // A burst that ends mid-interval is reported once the interval
+        // expires without further drops.
+        let mut log = DropLog::new(INTERVAL);
+        let reports = overrun(&mut log, 15, Duration::from_millis(10), 4);
+        assert_eq!(reports, [(100, 11 * 4)]);
+        assert!(log.is_pending());
+        assert_eq!(log.expire(Duration::from_millis(150)), None);
+        assert_eq!(log.expire(Duration::from_millis(200)), Some(4 * 4));
+        assert_eq!(log.expire(Duration::from_millis(400)), None);
+        // The next drop starts a new interval.
+        assert_eq!(log.record(1, Some(Duration::from_millis(450))), None);
+        assert_eq!(log.record(1, Some(Duration::from_millis(550))), Some(2));
+    }
+
+    #[test]
+    fn flush() {
+        let mut log = DropLog::new(INTERVAL);
+        assert_eq!(log.flush(), None);
+        assert_eq!(log.record(7, Some(Duration::ZERO)), None);
+        assert_eq!(log.flush(), Some(7));
+        assert_eq!(log.flush(), None);
+        // Drops counted without a clock are only reported by flush.
+        assert_eq!(log.record(3, None), None);
+        assert_eq!(log.expire(10 * INTERVAL), None);
+        assert_eq!(log.flush(), Some(3));
+    }
+
+    #[test]
+    fn disabled() {
+        // A zero interval never reports.
+        let mut log = DropLog::new(Duration::ZERO);
+        for _ in 0..1000 {
+            assert_eq!(log.record(10, Some(INTERVAL)), None);
+        }
+        assert_eq!(log.flush(), None);
+    }
+}
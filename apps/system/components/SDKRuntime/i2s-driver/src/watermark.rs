@@ -0,0 +1,137 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Self-tuning RX FIFO watermark level.
+//!
+//! The rx_watermark interrupt fires once the RX FIFO holds rxilvl
+//! entries. Each time the interrupt is handled the amount of received
+//! data still waiting for the consumer is sampled. If the consumer keeps
+//! the buffer near-empty rxilvl is raised so more data is moved per
+//! interrupt; if data backs up rxilvl is lowered. A level change needs
+//! several consecutive samples on the same side to avoid oscillating.
+
+/// Watermark levels supported by the hardware, in increasing order.
+pub const RX_LEVELS: [u8; 5] = [1, 4, 8, 16, 30];
+
+// Consecutive samples required before changing level.
+const RX_SAMPLES: u8 = 8;
+
+pub struct RxWatermark {
+    index: usize, // Current level in RX_LEVELS
+    near_empty: u8,
+    near_full: u8,
+}
+impl RxWatermark {
+    /// Starts tuning from |level|; levels not in RX_LEVELS start at
+    /// the lowest level.
+    pub fn new(level: u8) -> Self {
+        Self {
+            index: RX_LEVELS.iter().position(|&x| x == level).unwrap_or(0),
+            near_empty: 0,
+            near_full: 0,
+        }
+    }
+
+    /// Returns the current watermark level.
+    pub fn level(&self) -> u8 { RX_LEVELS[self.index] }
+
+    /// Records that |pending| of |capacity| buffered entries are waiting
+    /// for the consumer. Returns the new level when it should change.
+    pub fn sample(&mut self, pending: usize, capacity: usize) -> Option<u8> {
+        if pending <= capacity / 8 {
+            self.near_full = 0;
+            self.near_empty = self.near_empty.saturating_add(1);
+            if self.near_empty >= RX_SAMPLES && self.index < RX_LEVELS.len() - 1 {
+                self.near_empty = 0;
+                self.index += 1;
+                return Some(self.level());
+            }
+        } else if pending >= capacity - capacity / 4 {
+            self.near_empty = 0;
+            self.near_full = self.near_full.saturating_add(1);
+            if self.near_full >= RX_SAMPLES && self.index > 0 {
+                self.near_full = 0;
+                self.index -= 1;
+                return Some(self.level());
+            }
+        } else {
+            self.near_empty = 0;
+            self.near_full = 0;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPACITY: usize = 2048;
+
+    // Simulates a consumer that drains |drain| entries for every
+    // |produce| entries received; returns the levels chosen.
+    fn simulate(start: u8, produce: usize, drain: usize, rounds: usize) -> Vec<u8> {
+        let mut watermark = RxWatermark::new(start);
+        let mut pending = 0;
+        let mut levels = vec![watermark.level()];
+        for _ in 0..rounds {
+            pending = (pending + produce).min(CAPACITY);
+            pending -= drain.min(pending);
+            if let Some(level) = watermark.sample(pending, CAPACITY) {
+                levels.push(level);
+            }
+        }
+        levels
+    }
+
+    #[test]
+    fn fast_consumer_raises_level() {
+        let levels = simulate(1, 16, 32, 100);
+        assert_eq!(levels, RX_LEVELS);
+    }
+
+    #[test]
+    fn slow_consumer_lowers_level() {
+        let levels = simulate(30, 64, 8, 200);
+        assert_eq!(levels, [30, 16, 8, 4, 1]);
+    }
+
+    #[test]
+    fn steady_consumer_holds_level() {
+        // Occupancy stays mid-buffer.
+        let mut watermark = RxWatermark::new(8);
+        for _ in 0..100 {
+            assert_eq!(watermark.sample(CAPACITY / 2, CAPACITY), None);
+        }
+        assert_eq!(watermark.level(), 8);
+    }
+
+    #[test]
+    fn needs_consecutive_samples() {
+        let mut watermark = RxWatermark::new(8);
+        for _ in 0..100 {
+            for _ in 0..RX_SAMPLES - 1 {
+                assert_eq!(watermark.sample(0, CAPACITY), None);
+            }
+            // An interleaved full sample restarts the count.
+            assert_eq!(watermark.sample(CAPACITY, CAPACITY), None);
+        }
+        assert_eq!(watermark.level(), 8);
+    }
+
+    #[test]
+    fn invalid_start_level() {
+        assert_eq!(RxWatermark::new(5).level(), RX_LEVELS[0]);
+    }
+}
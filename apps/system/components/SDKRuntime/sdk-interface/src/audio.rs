@@ -0,0 +1,160 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Audio sample helpers.
+//!
+//! Samples are exchanged with the SDK runtime as packed u32's with the
+//! left channel in the upper 16 bits and the right channel in the lower
+//! 16 bits; each channel is a signed 16-bit value.
+
+fn left(sample: u32) -> i32 { ((sample >> 16) as i16) as i32 }
+fn right(sample: u32) -> i32 { (sample as i16) as i32 }
+fn pack(left: i32, right: i32) -> u32 { ((left as u16 as u32) << 16) | (right as u16 as u32) }
+
+// Linear interpolation between |a| and |b| at |num|/|den|.
+fn lerp(a: i32, b: i32, num: u64, den: u64) -> i32 {
+    a + (((b - a) as i64 * num as i64) / den as i64) as i32
+}
+
+/// Resamples |input| recorded at |in_rate| to |out_rate| by linear
+/// interpolation of each channel, writing the result to |out|. Returns
+/// the number of samples written; this is limited by |out.len()| and
+/// by the duration of |input| (the last input sample is held through
+/// the end of its period). A zero rate produces no output.
+pub fn resample(input: &[u32], in_rate: usize, out_rate: usize, out: &mut [u32]) -> usize {
+    if input.is_empty() || in_rate == 0 || out_rate == 0 {
+        return 0;
+    }
+    let (in_rate, out_rate) = (in_rate as u64, out_rate as u64);
+    let last = input.len() - 1;
+    let mut count = 0;
+    for (i, sample) in out.iter_mut().enumerate() {
+        // Position of output sample |i| in the input stream, in units
+        // of 1/out_rate input samples.
+        let pos = i as u64 * in_rate;
+        let ix = (pos / out_rate) as usize;
+        if ix > last {
+            break;
+        }
+        let frac = pos % out_rate;
+        let (a, b) = (input[ix], input[core::cmp::min(ix + 1, last)]);
+        *sample = pack(
+            lerp(left(a), left(b), frac, out_rate),
+            lerp(right(a), right(b), frac, out_rate),
+        );
+        count += 1;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ramp with the right channel the negation of the left.
+    fn ramp(len: usize, step: i32) -> alloc::vec::Vec<u32> {
+        (0..len as i32).map(|i| pack(i * step, -i * step)).collect()
+    }
+
+    #[test]
+    fn resample_same_rate() {
+        let input = ramp(8, 100);
+        let mut out = [0u32; 8];
+        assert_eq!(resample(&input, 16000, 16000, &mut out), 8);
+        assert_eq!(&out[..], &input[..]);
+    }
+
+    #[test]
+    fn resample_zero_rate() {
+        let input = ramp(8, 100);
+        let mut out = [0u32; 8];
+        assert_eq!(resample(&input, 0, 16000, &mut out), 0);
+        assert_eq!(resample(&input, 16000, 0, &mut out), 0);
+        assert_eq!(resample(&[], 16000, 16000, &mut out), 0);
+    }
+
+    #[test]
+    fn resample_upsample_integer() {
+        let input = ramp(4, 100);
+        let mut out = [0u32; 16];
+        // 2x: every other output sample is interpolated midway.
+        assert_eq!(resample(&input, 8000, 16000, &mut out), 8);
+        for (i, s) in out[..7].iter().enumerate() {
+            assert_eq!(left(*s), i as i32 * 50);
+            assert_eq!(right(*s), -(i as i32) * 50);
+        }
+        assert_eq!(out[7], input[3]);
+    }
+
+    #[test]
+    fn resample_downsample_integer() {
+        let input = ramp(8, 100);
+        let mut out = [0u32; 8];
+        // 1/2: every other input sample is kept.
+        assert_eq!(resample(&input, 1_000_000, 500_000, &mut out), 4);
+        for (i, s) in out[..4].iter().enumerate() {
+            assert_eq!(*s, input[2 * i]);
+        }
+    }
+
+    #[test]
+    fn resample_upsample_non_integer() {
+        let input = ramp(3, 300);
+        let mut out = [0u32; 8];
+        // 3/2: output positions step by 2/3 of an input sample.
+        assert_eq!(resample(&input, 2, 3, &mut out), 5);
+        let expected = [0, 200, 400, 600, 600];
+        for (s, e) in out[..5].iter().zip(expected) {
+            assert_eq!(left(*s), e);
+            assert_eq!(right(*s), -e);
+        }
+    }
+
+    #[test]
+    fn resample_downsample_non_integer() {
+        let input = ramp(7, 200);
+        let mut out = [0u32; 8];
+        // 2/3: output positions step by 3/2 of an input sample.
+        assert_eq!(resample(&input, 3, 2, &mut out), 5);
+        let expected = [0, 300, 600, 900, 1200];
+        for (s, e) in out[..5].iter().zip(expected) {
+            assert_eq!(left(*s), e);
+            assert_eq!(right(*s), -e);
+        }
+    }
+
+    #[test]
+    fn resample_out_too_short() {
+        let input = ramp(8, 100);
+        let mut out = [0u32; 3];
+        assert_eq!(resample(&input, 8000, 16000, &mut out), 3);
+        assert_eq!(left(out[2]), 100);
+    }
+
+    #[test]
+    fn resample_extremes() {
+        // Full-scale swings must not overflow the interpolation.
+        let input = [
+            pack(i16::MIN as i32, i16::MAX as i32),
+            pack(i16::MAX as i32, i16::MIN as i32),
+        ];
+        let mut out = [0u32; 4];
+        assert_eq!(resample(&input, 1, 2, &mut out), 4);
+        assert_eq!(out[0], input[0]);
+        assert_eq!(left(out[1]), -1);
+        assert_eq!(right(out[1]), 0);
+        assert_eq!(out[2], input[1]);
+        assert_eq!(out[3], input[1]);
+    }
+}
@@ -13,10 +13,11 @@
 // limitations under the License.
 
 use num_enum::TryFromPrimitive;
+use serde::{Deserialize, Serialize};
 
 /// Rust Error enum used for representing an SDK error with postcard. This is
 /// what most rust components will actually use as their error handling enum.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum SDKError {
     DeserializeFailed,
     SerializeFailed,
@@ -39,6 +40,19 @@ pub enum SDKError {
     InvalidInputRange,
     InvalidAudioParameter,
     InvalidAudioState,
+    QuotaExceeded,
+    InvalidFrame,
+    InvalidModelImage,
+    Timeout,
+    ModelAlreadyExists,
+    InvalidUploadOffset,
+    UploadIncomplete,
+    ServerFaulted,
+    ReplyTooLarge,
+    SelfTestFailed,
+    InputChecksumMismatch,
+    Faulted,
+    InvalidModelState,
 }
 
 impl From<postcard::Error> for SDKError {
@@ -72,6 +86,19 @@ pub enum SDKRuntimeError {
     SDKInvalidInputRange,
     SDKInvalidAudioParameter,
     SDKInvalidAudioState,
+    SDKQuotaExceeded,
+    SDKInvalidFrame,
+    SDKInvalidModelImage,
+    SDKTimeout,
+    SDKModelAlreadyExists,
+    SDKInvalidUploadOffset,
+    SDKUploadIncomplete,
+    SDKServerFaulted, // Reply was a fault message; the runtime crashed
+    SDKReplyTooLarge, // Reply does not fit the params page; ask for less
+    SDKSelfTestFailed,
+    SDKInputChecksumMismatch,
+    SDKFaulted, // App faulted and is suspended
+    SDKInvalidModelState,
 }
 
 /// Mapping function from Rust -> C.
@@ -99,6 +126,19 @@ impl From<SDKError> for SDKRuntimeError {
             SDKError::InvalidInputRange => SDKRuntimeError::SDKInvalidInputRange,
             SDKError::InvalidAudioParameter => SDKRuntimeError::SDKInvalidAudioParameter,
             SDKError::InvalidAudioState => SDKRuntimeError::SDKInvalidAudioState,
+            SDKError::QuotaExceeded => SDKRuntimeError::SDKQuotaExceeded,
+            SDKError::InvalidFrame => SDKRuntimeError::SDKInvalidFrame,
+            SDKError::InvalidModelImage => SDKRuntimeError::SDKInvalidModelImage,
+            SDKError::Timeout => SDKRuntimeError::SDKTimeout,
+            SDKError::ModelAlreadyExists => SDKRuntimeError::SDKModelAlreadyExists,
+            SDKError::InvalidUploadOffset => SDKRuntimeError::SDKInvalidUploadOffset,
+            SDKError::UploadIncomplete => SDKRuntimeError::SDKUploadIncomplete,
+            SDKError::ServerFaulted => SDKRuntimeError::SDKServerFaulted,
+            SDKError::ReplyTooLarge => SDKRuntimeError::SDKReplyTooLarge,
+            SDKError::SelfTestFailed => SDKRuntimeError::SDKSelfTestFailed,
+            SDKError::InputChecksumMismatch => SDKRuntimeError::SDKInputChecksumMismatch,
+            SDKError::Faulted => SDKRuntimeError::SDKFaulted,
+            SDKError::InvalidModelState => SDKRuntimeError::SDKInvalidModelState,
         }
     }
 }
@@ -136,6 +176,19 @@ impl From<SDKRuntimeError> for Result<(), SDKError> {
             SDKRuntimeError::SDKInvalidInputRange => Err(SDKError::InvalidInputRange),
             SDKRuntimeError::SDKInvalidAudioParameter => Err(SDKError::InvalidAudioParameter),
             SDKRuntimeError::SDKInvalidAudioState => Err(SDKError::InvalidAudioState),
+            SDKRuntimeError::SDKQuotaExceeded => Err(SDKError::QuotaExceeded),
+            SDKRuntimeError::SDKInvalidFrame => Err(SDKError::InvalidFrame),
+            SDKRuntimeError::SDKInvalidModelImage => Err(SDKError::InvalidModelImage),
+            SDKRuntimeError::SDKTimeout => Err(SDKError::Timeout),
+            SDKRuntimeError::SDKModelAlreadyExists => Err(SDKError::ModelAlreadyExists),
+            SDKRuntimeError::SDKInvalidUploadOffset => Err(SDKError::InvalidUploadOffset),
+            SDKRuntimeError::SDKUploadIncomplete => Err(SDKError::UploadIncomplete),
+            SDKRuntimeError::SDKServerFaulted => Err(SDKError::ServerFaulted),
+            SDKRuntimeError::SDKReplyTooLarge => Err(SDKError::ReplyTooLarge),
+            SDKRuntimeError::SDKSelfTestFailed => Err(SDKError::SelfTestFailed),
+            SDKRuntimeError::SDKInputChecksumMismatch => Err(SDKError::InputChecksumMismatch),
+            SDKRuntimeError::SDKFaulted => Err(SDKError::Faulted),
+            SDKRuntimeError::SDKInvalidModelState => Err(SDKError::InvalidModelState),
         }
     }
 }
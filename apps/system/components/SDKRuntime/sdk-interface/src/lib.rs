@@ -16,12 +16,18 @@
 
 #![cfg_attr(not(test), no_std)]
 
+pub mod audio;
 pub mod error;
 
+pub use audio::resample;
 pub use error::SDKError;
 pub use error::SDKRuntimeError;
 
 extern crate alloc;
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use serde::{Deserialize, Serialize};
 use zerovec::ZeroVec;
@@ -31,6 +37,7 @@ use sel4_sys::seL4_Call;
 use sel4_sys::seL4_MessageInfo;
 use sel4_sys::seL4_PageBits;
 use sel4_sys::seL4_SetCap;
+use sel4_sys::seL4_Word;
 
 const PAGE_SIZE: usize = 1 << seL4_PageBits;
 
@@ -52,6 +59,56 @@ extern "C" {
 // pub for server-side logic
 pub const SDKRUNTIME_REQUEST_DATA_SIZE: usize = PAGE_SIZE / 2;
 
+// pub for server-side logic
+pub const SDKRUNTIME_REPLY_DATA_SIZE: usize = PAGE_SIZE - SDKRUNTIME_REQUEST_DATA_SIZE;
+
+/// Response types bound their encoded size so it is known statically
+/// that a reply fits in the reply half of the params page (the bounds
+/// are checked by the reply_bounds test). A type whose size depends on
+/// state outside the request (e.g. the names of an app's keys) has no
+/// bound; serialize_reply reports ReplyTooLarge if one does not fit.
+pub trait SDKReply: Serialize {
+    /// The most bytes a reply of this type takes when serialized.
+    const MAX_SERIALIZED_LEN: Option<usize>;
+}
+
+// NB: postcard encodes integers at their full width (usize as a u64);
+//   only lengths and enum tags are varints.
+const USIZE_LEN: usize = size_of::<u64>();
+
+// Returns the bytes postcard uses to encode |value| as a varint.
+const fn varint_len(mut value: u64) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+/// Serializes |reply| to |reply_slice|. A reply that does not fit is
+/// reported as ReplyTooLarge rather than as a serialization failure so
+/// the client can ask for less (e.g. fewer samples).
+// pub for server-side logic
+pub fn serialize_reply<T: SDKReply>(reply: &T, reply_slice: &mut [u8]) -> Result<(), SDKError> {
+    match postcard::to_slice(reply, reply_slice) {
+        Ok(_) => Ok(()),
+        Err(postcard::Error::SerializeBufferFull) => Err(SDKError::ReplyTooLarge),
+        Err(_) => Err(SDKError::SerializeFailed),
+    }
+}
+
+/// Checks a received request carries the RPC parameters frame. The
+/// client attaches the frame as the only cap; if that cap is invalid
+/// (e.g. revoked) nothing is transferred and there is nothing to decode.
+// pub for server-side logic
+pub fn check_request_frame(info: &seL4_MessageInfo) -> Result<(), SDKError> {
+    if info.get_extraCaps() != 1 || info.get_capsUnwrapped() != 0 {
+        return Err(SDKError::InvalidFrame);
+    }
+    Ok(())
+}
+
 /// Application identity derived from seL4 Endpoint badge setup when
 /// the application is started by ProcessManager.
 ///
@@ -78,6 +135,29 @@ pub struct PingRequest {}
 pub struct LogRequest<'a> {
     pub msg: &'a [u8],
 }
+impl<'a> LogRequest<'a> {
+    /// Returns |msg| as a string suitable for logging and whether it was
+    /// truncated. Invalid UTF-8 sequences are replaced with U+FFFD; a
+    /// result longer than MAX_LOG_MSG_LEN bytes is cut on a char boundary
+    /// to fit with LOG_ELLIPSIS marking the cut.
+    pub fn msg_lossy(&self) -> (Cow<'a, str>, bool) {
+        let msg = String::from_utf8_lossy(self.msg);
+        if msg.len() <= MAX_LOG_MSG_LEN {
+            return (msg, false);
+        }
+        let mut end = MAX_LOG_MSG_LEN - LOG_ELLIPSIS.len();
+        while !msg.is_char_boundary(end) {
+            end -= 1;
+        }
+        (Cow::Owned(String::from(&msg[..end]) + LOG_ELLIPSIS), true)
+    }
+}
+
+/// Max bytes of a LogRequest msg that are logged, including any
+/// LOG_ELLIPSIS.
+pub const MAX_LOG_MSG_LEN: usize = 256;
+/// Marks where a too-long LogRequest msg was cut.
+pub const LOG_ELLIPSIS: &str = "...";
 
 /// SecurityCoordinator key-value api's
 
@@ -90,6 +170,36 @@ pub struct ReadKeyRequest<'a> {
 pub struct ReadKeyResponse<'a> {
     pub value: &'a [u8],
 }
+impl SDKReply for ReadKeyResponse<'_> {
+    const MAX_SERIALIZED_LEN: Option<usize> =
+        Some(varint_len(KEY_VALUE_DATA_SIZE as u64) + KEY_VALUE_DATA_SIZE);
+}
+
+/// SDKRuntimeRequest::ReadKeys
+#[derive(Serialize, Deserialize)]
+pub struct ReadKeysRequest<'a> {
+    #[serde(borrow)]
+    pub keys: Cow<'a, [&'a str]>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ReadKeysResponse<'a> {
+    // Value or error for each key in the request, in order
+    #[serde(borrow)]
+    pub values: Vec<Result<&'a [u8], SDKError>>,
+}
+impl SDKReply for ReadKeysResponse<'_> {
+    // NB: an error takes 2 bytes (tag & variant), less than a value
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(
+        varint_len(MAX_READ_KEYS as u64)
+            + MAX_READ_KEYS * (1 + varint_len(KEY_VALUE_DATA_SIZE as u64) + KEY_VALUE_DATA_SIZE),
+    );
+}
+
+/// Max keys read by one ReadKeys request. This is bounded by what fits
+/// in the reply half of the params page when every key has a value:
+/// each value is a varint length and the data preceded by the Result
+/// tag, all after the varint count of values.
+pub const MAX_READ_KEYS: usize = (SDKRUNTIME_REPLY_DATA_SIZE - 1) / (KEY_VALUE_DATA_SIZE + 2);
 
 /// SDKRuntimeRequest::WriteKey
 #[derive(Serialize, Deserialize)]
@@ -104,6 +214,22 @@ pub struct DeleteKeyRequest<'a> {
     pub key: &'a str,
 }
 
+/// SDKRuntimeRequest::SyncKeys
+#[derive(Serialize, Deserialize)]
+pub struct SyncKeysRequest {}
+
+/// SDKRuntimeRequest::ListKeys
+#[derive(Serialize, Deserialize)]
+pub struct ListKeysRequest {}
+#[derive(Serialize, Deserialize)]
+pub struct ListKeysResponse<'a> {
+    #[serde(borrow)]
+    pub keys: Vec<&'a str>,
+}
+impl SDKReply for ListKeysResponse<'_> {
+    const MAX_SERIALIZED_LEN: Option<usize> = None;
+}
+
 /// TimerService api's
 
 pub type TimerId = u32;
@@ -130,6 +256,63 @@ pub struct TimerWaitRequest {}
 pub struct TimerWaitResponse {
     pub mask: TimerMask,
 }
+impl SDKReply for TimerWaitResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(size_of::<u32>());
+}
+
+/// SDKRuntimeRequest::WaitForTimersTimeout
+#[derive(Serialize, Deserialize)]
+pub struct TimerWaitTimeoutRequest {
+    pub timeout_ms: TimerDuration,
+}
+
+/// SDKRuntimeRequest::TimerRemaining
+#[derive(Serialize, Deserialize)]
+pub struct TimerRemainingRequest {
+    pub id: TimerId,
+}
+#[derive(Serialize, Deserialize)]
+pub struct TimerRemainingResponse {
+    pub remaining_ms: TimerDuration,
+}
+impl SDKReply for TimerRemainingResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(size_of::<u32>());
+}
+
+/// SDKRuntimeRequest::TimerGetResolution
+#[derive(Serialize, Deserialize)]
+pub struct TimerResolutionRequest {}
+#[derive(Serialize, Deserialize)]
+pub struct TimerResolutionResponse {
+    pub resolution_hz: u32,
+}
+impl SDKReply for TimerResolutionResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(size_of::<u32>());
+}
+
+/// Timer & model id's an app may still use; see
+/// SDKRuntimeRequest::GetResourceInfo. The counts reflect the app's
+/// quota and the id's left in the runtime's id space, which is shared
+/// by all apps, so they may go down without the app doing anything.
+/// |log_dropped| counts sdk_log calls rejected by the app's log rate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ResourceInfo {
+    pub timers_available: u8, // Timers that may be started now
+    pub timers_max: u8,       // Most timers the app may have at once
+    pub model_available: u8,  // Models that may be loaded now
+    pub log_dropped: u32,     // Log messages dropped by the rate limit
+}
+
+/// SDKRuntimeRequest::GetResourceInfo
+#[derive(Serialize, Deserialize)]
+pub struct ResourceInfoRequest {}
+#[derive(Serialize, Deserialize)]
+pub struct ResourceInfoResponse {
+    pub info: ResourceInfo,
+}
+impl SDKReply for ResourceInfoResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(3 + size_of::<u32>());
+}
 
 /// MlCoordinator api's
 
@@ -145,6 +328,9 @@ big_array! { BigArray; }
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModelOutput {
     pub jobnum: usize,
+    pub generation: u64,     // Advances with each completed run
+    pub produced_at_us: u64, // Timer clock when the run completed
+    pub elapsed_us: u32,     // Run time; 0 if it could not be measured
     pub return_code: u32,
     pub epc: Option<u32>,
     #[serde(with = "BigArray")]
@@ -166,6 +352,9 @@ pub struct ModelOneshotRequest<'a> {
 pub struct ModelStartResponse {
     pub id: ModelId,
 }
+impl SDKReply for ModelStartResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(size_of::<u32>());
+}
 
 /// SDKRuntimeRequest::PeriodicModel
 #[derive(Serialize, Deserialize)]
@@ -181,6 +370,13 @@ pub struct ModelCancelRequest {
     pub id: ModelId,
 }
 
+/// SDKRuntimeRequest::TransferModel
+#[derive(Serialize, Deserialize)]
+pub struct ModelTransferRequest<'a> {
+    pub id: ModelId,
+    pub to_app: &'a str,
+}
+
 /// SDKRuntimeRequest::WaitForModel and SDKRuntimeRequest::PollForModels
 #[derive(Serialize, Deserialize)]
 pub struct ModelWaitRequest {}
@@ -188,6 +384,9 @@ pub struct ModelWaitRequest {}
 pub struct ModelWaitResponse {
     pub mask: ModelMask,
 }
+impl SDKReply for ModelWaitResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(size_of::<u32>());
+}
 
 /// SDKRuntimeRequest::GetModelOutput
 #[derive(Serialize, Deserialize)]
@@ -198,6 +397,15 @@ pub struct ModelOutputRequest {
 pub struct ModelOutputResponse {
     pub output: ModelOutput,
 }
+impl SDKReply for ModelOutputResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(
+        USIZE_LEN
+            + 2 * size_of::<u64>()
+            + 2 * size_of::<u32>()
+            + (1 + size_of::<u32>()) // epc
+            + MAX_OUTPUT_DATA,
+    );
+}
 
 /// SDKRuntimeRequest::GetModelInputParams
 #[derive(Serialize, Deserialize)]
@@ -209,6 +417,9 @@ pub struct ModelGetInputParamsResponse {
     pub id: ModelId,
     pub input_params: ModelInput,
 }
+impl SDKReply for ModelGetInputParamsResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(3 * size_of::<u32>());
+}
 
 /// SDKRuntimeRequest::SetModelInput
 #[derive(Serialize, Deserialize)]
@@ -218,6 +429,74 @@ pub struct ModelSetInputRequest<'a> {
     pub input_data: &'a [u8],
 }
 
+/// SDKRuntimeRequest::GetModelInput
+#[derive(Serialize, Deserialize)]
+pub struct ModelGetInputRequest {
+    pub id: ModelId,
+    pub input_data_offset: u32,
+    pub len: u32,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ModelGetInputResponse<'a> {
+    pub input_data: &'a [u8],
+}
+impl SDKReply for ModelGetInputResponse<'_> {
+    const MAX_SERIALIZED_LEN: Option<usize> =
+        Some(varint_len(MODEL_GET_INPUT_MAX as u64) + MODEL_GET_INPUT_MAX);
+}
+
+/// SDKRuntimeRequest::VerifyModelInput
+#[derive(Serialize, Deserialize)]
+pub struct ModelVerifyInputRequest {
+    pub id: ModelId,
+    pub crc: u32,
+}
+
+/// Max input bytes returned by one GetModelInput request.
+pub const MODEL_GET_INPUT_MAX: usize = 1024;
+
+/// SDKRuntimeRequest::GetModelOutputRange
+#[derive(Serialize, Deserialize)]
+pub struct ModelOutputRangeRequest {
+    pub id: ModelId,
+    pub offset: u32,
+    pub len: u32,
+    pub clear: bool,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ModelOutputRangeResponse<'a> {
+    pub data: &'a [u8],
+}
+impl SDKReply for ModelOutputRangeResponse<'_> {
+    const MAX_SERIALIZED_LEN: Option<usize> =
+        Some(varint_len(MODEL_OUTPUT_RANGE_MAX as u64) + MODEL_OUTPUT_RANGE_MAX);
+}
+
+/// Max output bytes returned by one GetModelOutputRange request.
+pub const MODEL_OUTPUT_RANGE_MAX: usize = 1024;
+
+/// What a model is doing; the model name is not included.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ModelState {
+    None,     // Not loaded
+    Idle,     // Loaded but not running
+    Oneshot,  // Running once
+    Periodic, // Running periodically
+}
+
+/// SDKRuntimeRequest::ModelGetState
+#[derive(Serialize, Deserialize)]
+pub struct ModelGetStateRequest {
+    pub id: ModelId,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ModelGetStateResponse {
+    pub state: ModelState,
+}
+impl SDKReply for ModelGetStateResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(1);
+}
+
 /// Audio api's
 
 /// SDKRuntimeRequest::AudioReset
@@ -236,8 +515,14 @@ pub struct AudioRecordStartRequest {
     pub buffer_size: usize,
     // If true, stop on buffer full, otherwise treat as a circular buffer
     pub stop_on_full: bool,
+    // How often dropped samples are logged; 0 disables logging and
+    // None uses the runtime default
+    pub drop_log_ms: Option<u32>,
 }
 
+/// Max buffer_size (in samples) accepted by AudioRecordStart.
+pub const AUDIO_RECORD_BUFFER_MAX: usize = 4096;
+
 /// SDKRuntimeRequest::AudioRecordCollect
 #[derive(Serialize, Deserialize)]
 pub struct AudioRecordCollectRequest {
@@ -248,8 +533,43 @@ pub struct AudioRecordCollectRequest {
 pub struct AudioRecordCollectResponse<'a> {
     #[serde(borrow)]
     pub data: ZeroVec<'a, u32>,
+    // Recording stopped on full and everything has been collected
+    pub session_ended: bool,
+    // Samples overwritten since the previous collect
+    pub dropped: u32,
+}
+impl SDKReply for AudioRecordCollectResponse<'_> {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(
+        varint_len((AUDIO_RECORD_COLLECT_MAX_SAMPLES * size_of::<u32>()) as u64)
+            + AUDIO_RECORD_COLLECT_MAX_SAMPLES * size_of::<u32>()
+            + 1
+            + size_of::<u32>(),
+    );
+}
+impl<'a> AudioRecordCollectResponse<'a> {
+    /// Copies the collected samples to |data| without allocating.
+    /// Returns the number of samples copied.
+    pub fn copy_to(&self, data: &mut [u32]) -> usize {
+        let count = core::cmp::min(data.len(), self.data.len());
+        for (dst, src) in data[..count].iter_mut().zip(self.data.iter()) {
+            *dst = src;
+        }
+        count
+    }
 }
 
+/// Max samples returned by one AudioRecordCollect request. This is
+/// bounded by what fits in the reply half of the params page: the
+/// samples are serialized as a byte array preceded by a varint length
+/// and followed by the session_ended flag and the dropped count (a
+/// u32).
+pub const AUDIO_RECORD_COLLECT_MAX_SAMPLES: usize =
+    (PAGE_SIZE - SDKRUNTIME_REQUEST_DATA_SIZE - 2 * size_of::<u32>()) / size_of::<u32>();
+
+/// Receives samples read in place by audio_record_collect_in_place
+/// along with the session ended flag and the overwritten count.
+pub type AudioRecordCollectFn<'a> = dyn FnMut(&[u32], bool, usize) -> Result<(), SDKError> + 'a;
+
 /// SDKRuntimeRequest::AudioRecordStop
 #[derive(Serialize, Deserialize)]
 pub struct AudioRecordStopRequest {}
@@ -259,6 +579,9 @@ pub struct AudioRecordStopRequest {}
 pub struct AudioPlayStartRequest {
     pub rate: usize,
     pub buffer_size: usize, // XXX in samples?
+    // Free space (in samples) in the play buffer required to wakeup a
+    // blocked writer; None selects the driver default.
+    pub wakeup_threshold: Option<usize>,
 }
 
 /// SDKRuntimeRequest::AudioPlayWrite
@@ -268,16 +591,182 @@ pub struct AudioPlayWriteRequest<'a> {
     pub data: ZeroVec<'a, u32>,
 }
 
+/// SDKRuntimeRequest::AudioPlayWriteNonBlocking
+// NB: uses AudioPlayWriteRequest
+#[derive(Serialize, Deserialize)]
+pub struct AudioPlayWriteResponse {
+    pub accepted: usize, // Samples queued; 0 if the play buffer is full
+}
+impl SDKReply for AudioPlayWriteResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(USIZE_LEN);
+}
+
 /// SDKRuntimeRequest::AudioPlayStop
 #[derive(Serialize, Deserialize)]
 pub struct AudioPlayStopRequest {}
 
+/// SDKRuntimeRequest::AudioPlayFlush
+#[derive(Serialize, Deserialize)]
+pub struct AudioPlayFlushRequest {}
+
+/// Parameters an audio stream was started with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AudioConfig {
+    pub rate: usize,
+    pub buffer_size: usize,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AudioRecordState {
+    Idle,
+    Recording(AudioConfig),
+}
+impl AudioRecordState {
+    pub fn is_idle(&self) -> bool { matches!(self, AudioRecordState::Idle) }
+    pub fn is_recording(&self) -> bool { matches!(self, AudioRecordState::Recording(_)) }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AudioPlayState {
+    Idle,
+    Playing(AudioConfig),
+}
+impl AudioPlayState {
+    pub fn is_idle(&self) -> bool { matches!(self, AudioPlayState::Idle) }
+    pub fn is_playing(&self) -> bool { matches!(self, AudioPlayState::Playing(_)) }
+}
+
+/// An app's audio state as returned by SDKRuntimeRequest::AudioGetState.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AudioState {
+    pub record: AudioRecordState,
+    pub play: AudioPlayState,
+}
+
+/// SDKRuntimeRequest::AudioGetState
+#[derive(Serialize, Deserialize)]
+pub struct AudioGetStateRequest {}
+#[derive(Serialize, Deserialize)]
+pub struct AudioGetStateResponse {
+    pub state: AudioState,
+}
+impl SDKReply for AudioGetStateResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(2 * (1 + 2 * USIZE_LEN));
+}
+
+/// Which audio stream a request refers to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AudioDirection {
+    Record,
+    Play,
+}
+
+/// Layout of the u32 samples exchanged with the audio hardware. Each
+/// sample packs |channels| values of |bits_per_sample| bits; see the
+/// audio module for the stereo packing.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AudioFormat {
+    pub sample_rate: usize, // Hz; 0 if the stream was never started
+    pub channels: u8,
+    pub bits_per_sample: u8, // Per channel
+}
+
+/// SDKRuntimeRequest::AudioGetFormat
+#[derive(Serialize, Deserialize)]
+pub struct AudioGetFormatRequest {
+    pub direction: AudioDirection,
+}
+#[derive(Serialize, Deserialize)]
+pub struct AudioGetFormatResponse {
+    pub format: AudioFormat,
+}
+impl SDKReply for AudioGetFormatResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(USIZE_LEN + 2);
+}
+
+/// SDKRuntimeRequest::AudioSelfTest
+#[derive(Serialize, Deserialize)]
+pub struct AudioSelfTestRequest {}
+#[derive(Serialize, Deserialize)]
+pub struct AudioSelfTestResponse {
+    pub passed: bool,
+}
+impl SDKReply for AudioSelfTestResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(1);
+}
+
+/// What the SDKRuntime does when an app faults. The runtime is the
+/// fault handler for every app; by default (Suspend) the faulting thread
+/// is left suspended as it was before policies could be set.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum FaultPolicy {
+    /// Log the fault and stop the app.
+    LogAndKill,
+    /// Log the fault then stop and start the app again. The restarted
+    /// app begins with the default policy.
+    LogAndRestart,
+    /// Record the fault (see SDKRuntime diagnostics) and leave the app
+    /// suspended. Nothing is notified; a supervisor finds the fault by
+    /// polling the diagnostics. Any further requests from the app fail
+    /// with Faulted.
+    #[default]
+    Suspend,
+}
+
+/// SDKRuntimeRequest::SetFaultPolicy
+#[derive(Serialize, Deserialize)]
+pub struct SetFaultPolicyRequest {
+    pub policy: FaultPolicy,
+}
+
+/// SDKRuntimeRequest::Shutdown
+#[derive(Serialize, Deserialize)]
+pub struct ShutdownRequest {}
+
+/// Max model bytes sent by one ModelUploadChunk request; this leaves
+/// room in the request half of the params page for the model name.
+pub const MODEL_UPLOAD_CHUNK_SIZE: usize = 1024;
+
+/// SDKRuntimeRequest::ModelUploadBegin
+#[derive(Serialize, Deserialize)]
+pub struct ModelUploadBeginRequest<'a> {
+    pub model_id: &'a str,
+    pub size: usize, // Total model bytes
+}
+#[derive(Serialize, Deserialize)]
+pub struct ModelUploadResponse {
+    pub received: usize, // Bytes received; the next chunk starts here
+}
+impl SDKReply for ModelUploadResponse {
+    const MAX_SERIALIZED_LEN: Option<usize> = Some(USIZE_LEN);
+}
+
+/// SDKRuntimeRequest::ModelUploadChunk
+#[derive(Serialize, Deserialize)]
+pub struct ModelUploadChunkRequest<'a> {
+    pub model_id: &'a str,
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+// NB: returns ModelUploadResponse
+
+/// SDKRuntimeRequest::ModelUploadCommit
+#[derive(Serialize, Deserialize)]
+pub struct ModelUploadCommitRequest<'a> {
+    pub model_id: &'a str,
+    pub signature: Option<&'a [u8]>, // Detached signature of the model
+}
+
 /// SDKRequest token sent over the seL4 IPC interface. We need repr(seL4_Word)
 /// but cannot use that so use the implied usize type instead.
 ///
 /// Note that this enum starts off at 64. This is to avoid collisions with the
 /// seL4_Fault enumeration used by the kernel, as the SDK runtime is also used
-/// as the application's fault handler.
+/// as the application's fault handler. A message with a label below 64 is
+/// decoded as a fault: the runtime logs the fault type with the faulting
+/// app's badge, instruction & address and, rather than replying (which
+/// would resume the faulting thread), applies the app's FaultPolicy.
 #[repr(usize)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq, IntoPrimitive, TryFromPrimitive)]
 pub enum SDKRuntimeRequest {
@@ -304,12 +793,53 @@ pub enum SDKRuntimeRequest {
     SetModelInput, // Set input data for loaded model: [id: ModelId, input_data_offset: u32, input_data: &[u8]
 
     AudioReset, // Reset audio state: [rxrst: bool, txrst: bool, rxilvl: u8, txilvl: u8]
-    AudioRecordStart, // Start recording: [rate: usize, buffer_size: usize, stop_on_full: bool]
+    AudioRecordStart, // Start recording: [rate: usize, buffer_size: usize, stop_on_full: bool, drop_log_ms: Option<u32>]
     AudioRecordCollect, // Collect recorded data: [max_samples: usize, wait_if_empty: bool]
     AudioRecordStop, // Stop recording (any un-collected data are discarded): []
-    AudioPlayStart, // Start playing: [rate: usize, buffer_size: usize]
+    AudioPlayStart, // Start playing: [rate: usize, buffer_size: usize, wakeup_threshold: Option<usize>]
     AudioPlayWrite, // Write play samples: [data: &[u32]]
     AudioPlayStop, // Stop playing: []
+
+    SyncKeys, // Persist key-value store: []
+
+    AudioGetState, // Return recording & playing state: [] -> AudioState
+
+    SetFaultPolicy, // Set action taken on a fault: [policy: FaultPolicy]
+
+    ModelUploadBegin, // Start/resume model upload: [model_id: &str, size: usize] -> received: usize
+    ModelUploadChunk, // Upload model data: [model_id: &str, offset: usize, data: &[u8]] -> received: usize
+    ModelUploadCommit, // Install uploaded model: [model_id: &str, signature: Option<&[u8]>]
+
+    ReadKeys, // Read several keys: [keys: &[&str]] -> values: &[Result<&[u8], SDKError>]
+    ListKeys, // List key names: [] -> keys: &[&str]
+
+    GetModelInput, // Read back input data: [id: ModelId, input_data_offset: u32, len: u32] -> &[u8]
+
+    AudioPlayWriteNonBlocking, // Write play samples without blocking: [data: &[u32]] -> accepted: usize
+
+    AudioGetFormat, // Return the hardware sample format: [direction: AudioDirection] -> AudioFormat
+
+    ModelGetState, // Return what a model is doing: [id: ModelId] -> ModelState
+
+    WaitForTimersTimeout, // Wait for timers to expire or timeout: [timeout_ms: TimerDuration] -> TimerMask
+
+    TimerRemaining, // Return time until a timer expires: [id: TimerId] -> TimerDuration
+
+    TimerGetResolution, // Return the timer tick frequency: [] -> resolution_hz: u32
+
+    AudioSelfTest, // Check the audio path with a loopback test: [] -> passed: bool
+
+    VerifyModelInput, // Check staged input data: [id: ModelId, crc: u32]
+
+    GetModelOutputRange, // Read part of the model output: [id: ModelId, offset: u32, len: u32, clear: bool] -> &[u8]
+
+    GetResourceInfo, // Return timer & model id's available & log drops: [] -> ResourceInfo
+
+    Shutdown, // Release the app's timers, models & audio: []
+
+    AudioPlayFlush, // Discard samples queued to play: []
+
+    TransferModel, // Hand a loaded model to another app: [id: ModelId, to_app: &str]
 }
 
 /// Rust interface for the SDKRuntime.
@@ -327,19 +857,36 @@ pub trait SDKRuntimeInterface {
     /// Pings the SDK runtime, going from client to server and back via CAmkES IPC.
     fn ping(&self, app_id: SDKAppId) -> Result<(), SDKError>;
 
-    /// Logs |msg| through the system logger.
-    fn log(&self, app_id: SDKAppId, msg: &str) -> Result<(), SDKError>;
+    /// Logs |msg| through the system logger; |truncated| is true if |msg|
+    /// was cut to MAX_LOG_MSG_LEN (see LogRequest::msg_lossy). Messages
+    /// over the app's log rate are dropped.
+    fn log(&mut self, app_id: SDKAppId, msg: &str, truncated: bool) -> Result<(), SDKError>;
 
     /// Returns any value for the specified |key| in the app's  private key-value store.
     /// Data are written to |keyval| and returned as a slice.
     fn read_key(&self, app_id: SDKAppId, key: &str) -> Result<KeyValueData, SDKError>;
 
+    /// Returns the value or error for each of |keys| in the app's private
+    /// key-value store. At most MAX_READ_KEYS may be read at once.
+    fn read_keys(
+        &self,
+        app_id: SDKAppId,
+        keys: &[&str],
+    ) -> Result<Vec<Result<KeyValueData, SDKError>>, SDKError>;
+
     /// Writes |value| for the specified |key| in the app's private key-value store.
     fn write_key(&self, app_id: SDKAppId, key: &str, value: &KeyValueData) -> Result<(), SDKError>;
 
     /// Deletes the specified |key| in the app's private key-value store.
     fn delete_key(&self, app_id: SDKAppId, key: &str) -> Result<(), SDKError>;
 
+    /// Persists the app's private key-value store to backing storage.
+    /// Returns only after the data are durable.
+    fn sync_keys(&self, app_id: SDKAppId) -> Result<(), SDKError>;
+
+    /// Returns the names of the keys in the app's private key-value store.
+    fn list_keys(&self, app_id: SDKAppId) -> Result<Vec<String>, SDKError>;
+
     /// Create a one-shot timer named |id| of |duration_ms|.
     fn timer_oneshot(
         &mut self,
@@ -358,10 +905,25 @@ pub trait SDKRuntimeInterface {
     fn timer_cancel(&mut self, app_id: SDKAppId, id: TimerId) -> Result<(), SDKError>;
     /// Wait for any running timer to complete.
     fn timer_wait(&mut self, app_id: SDKAppId) -> Result<TimerMask, SDKError>;
+    /// Wait for any running timer to complete or |timeout_ms| to pass.
+    /// An empty mask is returned on timeout.
+    fn timer_wait_timeout(
+        &mut self,
+        app_id: SDKAppId,
+        timeout_ms: TimerDuration,
+    ) -> Result<TimerMask, SDKError>;
     /// Poll for any running timer that have completed.
     fn timer_poll(&mut self, app_id: SDKAppId) -> Result<TimerMask, SDKError>;
-
-    /// Create a one-shot run of |model_id|.
+    /// Returns the time until timer |id| next expires.
+    fn timer_remaining(&self, app_id: SDKAppId, id: TimerId) -> Result<TimerDuration, SDKError>;
+    /// Returns the tick frequency of the timer hardware in Hz.
+    fn timer_resolution(&self, app_id: SDKAppId) -> Result<u32, SDKError>;
+    /// Returns how many more timers & models the app may start.
+    fn resource_info(&self, app_id: SDKAppId) -> Result<ResourceInfo, SDKError>;
+
+    /// Create a one-shot run of |model_id|. Returns the id used to
+    /// refer to the model; several models may be loaded at once and
+    /// each keeps its id while it stays loaded.
     fn model_oneshot(&mut self, app_id: SDKAppId, model_id: &str) -> Result<ModelId, SDKError>;
     /// Create a periodic (repeating) timer named |id| of |duration_ms|.
     fn model_periodic(
@@ -372,6 +934,13 @@ pub trait SDKRuntimeInterface {
     ) -> Result<ModelId, SDKError>;
     /// Cancel a previously created timer.
     fn model_cancel(&mut self, app_id: SDKAppId, id: ModelId) -> Result<(), SDKError>;
+    /// Hand loaded model |id| to app |to_app|; it keeps the same id.
+    fn model_transfer(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        to_app: &str,
+    ) -> Result<(), SDKError>;
     /// Wait for any running timer to complete.
     fn model_wait(&mut self, app_id: SDKAppId) -> Result<ModelMask, SDKError>;
     /// Poll for any running timer that have completed.
@@ -392,6 +961,40 @@ pub trait SDKRuntimeInterface {
         input_data_offset: u32,
         input_data: &[u8],
     ) -> Result<(), SDKError>;
+    /// Returns |len| bytes of input data for model |id| starting at
+    /// |input_data_offset|; this includes input staged for the next run.
+    /// At most MODEL_GET_INPUT_MAX bytes may be read at once.
+    fn model_get_input(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        input_data_offset: u32,
+        len: u32,
+    ) -> Result<Vec<u8>, SDKError>;
+    /// Checks |crc|, the CRC-32 (IEEE) of the input data written for
+    /// model |id|, against what is staged for the next run. The data
+    /// covered run from offset 0 to the end of the furthest byte written.
+    fn model_verify_input(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        crc: u32,
+    ) -> Result<(), SDKError>;
+    /// Returns up to |len| bytes of output from model |id| starting at
+    /// |offset|; fewer bytes are returned at the end of the output. The
+    /// output read is kept for later reads until |clear| is passed.
+    /// At most MODEL_OUTPUT_RANGE_MAX bytes may be read at once.
+    fn model_output_range(
+        &mut self,
+        app_id: SDKAppId,
+        id: ModelId,
+        offset: u32,
+        len: u32,
+        clear: bool,
+    ) -> Result<Vec<u8>, SDKError>;
+    /// Returns the state of model |id|; ModelState::None if no model is
+    /// loaded with |id|.
+    fn model_get_state(&self, app_id: SDKAppId, id: ModelId) -> Result<ModelState, SDKError>;
 
     /// Resets the audio framework.
     fn audio_reset(
@@ -404,37 +1007,121 @@ pub trait SDKRuntimeInterface {
     ) -> Result<(), SDKError>;
     /// Start recording audio into a buffer of size |buffer_size| using
     /// |rate| sampling. If the buffer fills before a stop request is
-    /// received recording is automatically stopped.
+    /// received recording is automatically stopped. |buffer_size| is
+    /// in samples and must be at most AUDIO_RECORD_BUFFER_MAX. Samples
+    /// dropped while recording are logged at most once per
+    /// |drop_log_ms| (0 disables; None uses the default).
     fn audio_record_start(
         &mut self,
         app_id: SDKAppId,
         rate: usize,
         buffer_size: usize,
         stop_on_full: bool,
+        drop_log_ms: Option<u32>,
     ) -> Result<(), SDKError>;
-    /// Collects data from a recording started with |audio_record_start|.
-    /// The data are returned in native (hardware) format.
+    /// Collects data from a recording started with |audio_record_start|
+    /// into |data|. The data are returned in native (hardware) format.
+    /// Returns the number of samples written to |data|, whether the
+    /// session ended (with stop_on_full the buffer filled, recording
+    /// stopped, and all the recorded data has now been collected), and
+    /// the number of samples overwritten since the previous collect.
     fn audio_record_collect(
+        &mut self,
+        app_id: SDKAppId,
+        data: &mut [u32],
+        wait_if_empty: bool,
+    ) -> Result<(usize, bool, usize), SDKError>;
+    /// Like |audio_record_collect| but passes up to |max_samples|
+    /// samples to |f| in place, along with the session ended flag and
+    /// overwritten count, instead of copying them out. Returns false
+    /// having collected nothing if the samples cannot be read in place
+    /// (e.g. they wrap around the end of the record buffer); the caller
+    /// should then use |audio_record_collect|.
+    fn audio_record_collect_in_place(
         &mut self,
         app_id: SDKAppId,
         max_samples: usize,
         wait_if_empty: bool,
-    ) -> Result<&[u32], SDKError>;
+        f: &mut AudioRecordCollectFn<'_>,
+    ) -> Result<bool, SDKError>;
     /// Stop a recording session started with |audio_record_start|.
     fn audio_record_stop(&mut self, app_id: SDKAppId) -> Result<(), SDKError>;
 
-    /// Start playing audio data with |rate| sampling.
+    /// Start playing audio data with |rate| sampling. A writer blocked
+    /// on a full play buffer is woken when |wakeup_threshold| samples
+    /// of space are available (None selects a default).
     fn audio_play_start(
         &mut self,
         app_id: SDKAppId,
         rate: usize,
         buffer_size: usize,
+        wakeup_threshold: Option<usize>,
     ) -> Result<(), SDKError>;
     /// Writes data according to |audio_play_start|.
     /// The data are assumed in native (hardware) format.
     fn audio_play_write(&mut self, app_id: SDKAppId, data: &[u32]) -> Result<(), SDKError>;
+    /// Like |audio_play_write| but never blocks; queues as many samples
+    /// as fit and returns the number accepted (0 if the buffer is full).
+    fn audio_play_write_nb(&mut self, app_id: SDKAppId, data: &[u32]) -> Result<usize, SDKError>;
     /// Stop a play session started with |audio_play_start|.
     fn audio_play_stop(&mut self, app_id: SDKAppId) -> Result<(), SDKError>;
+    /// Discards samples written with |audio_play_write| that have not
+    /// been played; unlike |audio_play_stop| this does not wait and the
+    /// play session continues.
+    fn audio_play_flush(&mut self, app_id: SDKAppId) -> Result<(), SDKError>;
+    /// Returns whether the app is recording and/or playing and with
+    /// what parameters.
+    fn audio_get_state(&self, app_id: SDKAppId) -> Result<AudioState, SDKError>;
+    /// Returns the hardware sample format for |direction|. The sample
+    /// rate is the one the hardware was last started with.
+    fn audio_get_format(
+        &self,
+        app_id: SDKAppId,
+        direction: AudioDirection,
+    ) -> Result<AudioFormat, SDKError>;
+    /// Runs the audio loopback self-test: a known pattern is played
+    /// and must be received back intact. Recording and playing must be
+    /// stopped. Returns whether the test passed.
+    fn audio_selftest(&mut self, app_id: SDKAppId) -> Result<bool, SDKError>;
+
+    /// Sets the action taken when the app faults.
+    fn set_fault_policy(&mut self, app_id: SDKAppId, policy: FaultPolicy) -> Result<(), SDKError>;
+
+    /// Releases the app's timers, models & audio as is done when the
+    /// app is stopped. The app may still make requests; e.g. to log a
+    /// final message before exiting.
+    fn shutdown(&mut self, app_id: SDKAppId) -> Result<(), SDKError>;
+
+    /// Starts uploading a model of |size| bytes named |model_id| or
+    /// resumes an upload already in progress. Returns the bytes received
+    /// so far; the next chunk should start there.
+    fn model_upload_begin(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        size: usize,
+    ) -> Result<usize, SDKError>;
+
+    /// Writes |data| at |offset| of the model being uploaded. Chunks must
+    /// be sent in order; data already received are ignored. Returns the
+    /// bytes received so far.
+    fn model_upload_chunk(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<usize, SDKError>;
+
+    /// Installs the uploaded model so it may be run like a builtin
+    /// model. The upload is checked against |signature| when the
+    /// system requires signed models.
+    fn model_upload_commit(
+        &mut self,
+        app_id: SDKAppId,
+        model_id: &str,
+        signature: Option<&[u8]>,
+    ) -> Result<(), SDKError>;
 }
 
 /// Rust client-side request processing. Note there is no CAmkES stub to
@@ -455,6 +1142,21 @@ pub trait SDKRuntimeInterface {
 //   to lookup the mapped page early. Downside to a fixed mapping is it
 //   limits how to handle requests w/ different-sized params (e.g. sensor
 //   frame vs key-value params).
+// Decodes the status of an SDKRuntime reply with |label| and |length|
+// message registers. Replies never carry message registers but a fault
+// message does; since fault labels overlap the error codes a reply with
+// a fault-range label and a non-zero length is taken to mean the runtime
+// faulted while handling the request.
+fn decode_reply(label: seL4_Word, length: seL4_Word) -> Result<(), SDKRuntimeError> {
+    if length != 0 && label < SDKRuntimeRequest::Ping as seL4_Word {
+        return Err(SDKRuntimeError::SDKServerFaulted);
+    }
+    match SDKRuntimeError::try_from(label).or(Err(SDKRuntimeError::SDKUnknownResponse))? {
+        SDKRuntimeError::SDKSuccess => Ok(()),
+        status => Err(status),
+    }
+}
+
 fn sdk_request<'a, S: Serialize, D: Deserialize<'a>>(
     request: SDKRuntimeRequest,
     request_args: &S,
@@ -482,11 +1184,7 @@ fn sdk_request<'a, S: Serialize, D: Deserialize<'a>>(
         );
         seL4_SetCap(0, 0);
 
-        let status = SDKRuntimeError::try_from(info.get_label())
-            .or(Err(SDKRuntimeError::SDKUnknownResponse))?;
-        if status != SDKRuntimeError::SDKSuccess {
-            return Err(status);
-        }
+        decode_reply(info.get_label(), info.get_length())?;
     }
 
     // Decode response data.
@@ -499,6 +1197,19 @@ pub fn sdk_ping() -> Result<(), SDKRuntimeError> {
     sdk_request::<PingRequest, ()>(SDKRuntimeRequest::Ping, &PingRequest {})
 }
 
+/// Primes the RPC path so an app's first real request does not pay
+/// one-time costs; call once after sdk_init. This touches the params
+/// page and does a no-op round trip through the SDKRuntime. NB: the
+/// SDKRuntime maps the params frame per request so there is no server
+/// mapping to establish; what is warmed are the first-touch costs
+/// (caches, TLB) on both sides.
+#[inline]
+pub fn sdk_prewarm() -> Result<(), SDKRuntimeError> {
+    let params_slice = unsafe { core::slice::from_raw_parts_mut(CANTRIP_SDK_PARAMS, PAGE_SIZE) };
+    params_slice.fill(0);
+    sdk_ping()
+}
+
 /// Rust client-side wrapper for the log method.
 #[inline]
 pub fn sdk_log(msg: &str) -> Result<(), SDKRuntimeError> {
@@ -522,6 +1233,41 @@ pub fn sdk_read_key<'a>(key: &str, keyval: &'a mut [u8]) -> Result<&'a [u8], SDK
     Ok(keyval)
 }
 
+/// Rust client-side wrapper for the read keys method. The value of
+/// keys[i] is written to out[i] in one round trip; the status of each
+/// key is returned. Asking for more than MAX_READ_KEYS keys (or more
+/// keys than |out| holds) fails with SDKSerializeFailed.
+#[inline]
+pub fn sdk_read_keys(
+    keys: &[&str],
+    out: &mut [KeyValueData],
+) -> Result<Vec<Result<(), SDKRuntimeError>>, SDKRuntimeError> {
+    if keys.len() > MAX_READ_KEYS || keys.len() > out.len() {
+        return Err(SDKRuntimeError::SDKSerializeFailed);
+    }
+    let response = sdk_request::<ReadKeysRequest, ReadKeysResponse>(
+        SDKRuntimeRequest::ReadKeys,
+        &ReadKeysRequest {
+            keys: Cow::Borrowed(keys),
+        },
+    )?;
+    if response.values.len() != keys.len() {
+        return Err(SDKRuntimeError::SDKUnknownResponse);
+    }
+    Ok(response
+        .values
+        .into_iter()
+        .zip(out.iter_mut())
+        .map(|(value, keyval)| match value {
+            Ok(value) => {
+                keyval[..value.len()].copy_from_slice(value);
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        })
+        .collect())
+}
+
 /// Rust client-side wrapper for the write key method.
 #[inline]
 pub fn sdk_write_key(key: &str, value: &[u8]) -> Result<(), SDKRuntimeError> {
@@ -534,6 +1280,40 @@ pub fn sdk_delete_key(key: &str) -> Result<(), SDKRuntimeError> {
     sdk_request::<DeleteKeyRequest, ()>(SDKRuntimeRequest::DeleteKey, &DeleteKeyRequest { key })
 }
 
+/// Rust client-side wrapper for the sync keys method.
+#[inline]
+pub fn sdk_sync_keys() -> Result<(), SDKRuntimeError> {
+    sdk_request::<SyncKeysRequest, ()>(SDKRuntimeRequest::SyncKeys, &SyncKeysRequest {})
+}
+
+// Writes |keys| to |out| one per line; returns the number of bytes
+// written or SDKSerializeFailed if |out| is too small.
+fn write_key_names(keys: &[&str], out: &mut [u8]) -> Result<usize, SDKRuntimeError> {
+    let mut len = 0;
+    for key in keys {
+        let end = len + key.len() + 1;
+        if end > out.len() {
+            return Err(SDKRuntimeError::SDKSerializeFailed);
+        }
+        out[len..end - 1].copy_from_slice(key.as_bytes());
+        out[end - 1] = b'\n';
+        len = end;
+    }
+    Ok(len)
+}
+
+/// Rust client-side wrapper for the list keys method. The names of the
+/// app's keys are written to |out| newline-separated (each name is
+/// followed by a newline); the number of bytes written is returned.
+#[inline]
+pub fn sdk_list_keys(out: &mut [u8]) -> Result<usize, SDKRuntimeError> {
+    let response = sdk_request::<ListKeysRequest, ListKeysResponse>(
+        SDKRuntimeRequest::ListKeys,
+        &ListKeysRequest {},
+    )?;
+    write_key_names(&response.keys, out)
+}
+
 /// Rust client-side wrapper for the timer_oneshot method.
 #[inline]
 pub fn sdk_timer_oneshot(id: TimerId, duration_ms: TimerDuration) -> Result<(), SDKRuntimeError> {
@@ -571,6 +1351,19 @@ pub fn sdk_timer_wait() -> Result<TimerMask, SDKRuntimeError> {
     Ok(response.mask)
 }
 
+/// Rust client-side wrapper for the timer_wait_timeout method.
+/// Returns None if |timeout_ms| passes before any timer completes.
+#[inline]
+pub fn sdk_timer_wait_timeout(
+    timeout_ms: TimerDuration,
+) -> Result<Option<TimerMask>, SDKRuntimeError> {
+    let response = sdk_request::<TimerWaitTimeoutRequest, TimerWaitResponse>(
+        SDKRuntimeRequest::WaitForTimersTimeout,
+        &TimerWaitTimeoutRequest { timeout_ms },
+    )?;
+    Ok(Some(response.mask).filter(|mask| *mask != 0))
+}
+
 /// Rust client-side wrapper for the timer_poll method.
 #[inline]
 pub fn sdk_timer_poll() -> Result<TimerMask, SDKRuntimeError> {
@@ -581,6 +1374,41 @@ pub fn sdk_timer_poll() -> Result<TimerMask, SDKRuntimeError> {
     Ok(response.mask)
 }
 
+/// Rust client-side wrapper for the timer_remaining method.
+/// Returns the milliseconds until timer |id| next expires.
+#[inline]
+pub fn sdk_timer_remaining(id: TimerId) -> Result<TimerDuration, SDKRuntimeError> {
+    let response = sdk_request::<TimerRemainingRequest, TimerRemainingResponse>(
+        SDKRuntimeRequest::TimerRemaining,
+        &TimerRemainingRequest { id },
+    )?;
+    Ok(response.remaining_ms)
+}
+
+/// Rust client-side wrapper for the timer_resolution method.
+/// Returns the timer tick frequency in Hz; durations finer than a tick
+/// cannot be achieved.
+#[inline]
+pub fn sdk_timer_resolution() -> Result<u32, SDKRuntimeError> {
+    let response = sdk_request::<TimerResolutionRequest, TimerResolutionResponse>(
+        SDKRuntimeRequest::TimerGetResolution,
+        &TimerResolutionRequest {},
+    )?;
+    Ok(response.resolution_hz)
+}
+
+/// Rust client-side wrapper for the resource_info method. An app that
+/// starts timers or models on demand can use this to back off rather
+/// than fail with OutOfResources or QuotaExceeded.
+#[inline]
+pub fn sdk_resource_info() -> Result<ResourceInfo, SDKRuntimeError> {
+    let response = sdk_request::<ResourceInfoRequest, ResourceInfoResponse>(
+        SDKRuntimeRequest::GetResourceInfo,
+        &ResourceInfoRequest {},
+    )?;
+    Ok(response.info)
+}
+
 /// Rust client-side wrapper for the model_oneshot method.
 #[inline]
 pub fn sdk_model_oneshot(model_id: &str) -> Result<ModelId, SDKRuntimeError> {
@@ -616,6 +1444,15 @@ pub fn sdk_model_cancel(id: ModelId) -> Result<(), SDKRuntimeError> {
     )
 }
 
+/// Rust client-side wrapper for the model_transfer method.
+#[inline]
+pub fn sdk_model_transfer(id: ModelId, to_app: &str) -> Result<(), SDKRuntimeError> {
+    sdk_request::<ModelTransferRequest, ()>(
+        SDKRuntimeRequest::TransferModel,
+        &ModelTransferRequest { id, to_app },
+    )
+}
+
 /// Rust client-side wrapper for the model_wait method.
 #[inline]
 pub fn sdk_model_wait() -> Result<ModelMask, SDKRuntimeError> {
@@ -675,6 +1512,92 @@ pub fn sdk_model_set_input(
     )
 }
 
+/// Rust client-side wrapper for the model_verify_input method. |crc| is
+/// the CRC-32 (IEEE) of the input data written with sdk_model_set_input
+/// starting from offset 0; InputChecksumMismatch is returned if what is
+/// staged for the next run differs (e.g. a write was lost). The check is
+/// repeated when the next run starts and the run is skipped if the staged
+/// input changed in between.
+#[inline]
+pub fn sdk_model_verify_input(id: ModelId, crc: u32) -> Result<(), SDKRuntimeError> {
+    sdk_request::<ModelVerifyInputRequest, ()>(
+        SDKRuntimeRequest::VerifyModelInput,
+        &ModelVerifyInputRequest { id, crc },
+    )
+}
+
+/// Rust client-side wrapper for the model_get_input method. Input data
+/// for model |id| starting at |input_data_offset| are written to |out|,
+/// which may hold at most MODEL_GET_INPUT_MAX bytes. Use this to check
+/// data written with sdk_model_set_input; bytes never written read as 0.
+#[inline]
+pub fn sdk_model_get_input(
+    id: ModelId,
+    input_data_offset: u32,
+    out: &mut [u8],
+) -> Result<(), SDKRuntimeError> {
+    if out.len() > MODEL_GET_INPUT_MAX {
+        return Err(SDKRuntimeError::SDKInvalidInputRange);
+    }
+    let response = sdk_request::<ModelGetInputRequest, ModelGetInputResponse>(
+        SDKRuntimeRequest::GetModelInput,
+        &ModelGetInputRequest {
+            id,
+            input_data_offset,
+            len: out.len() as u32,
+        },
+    )?;
+    if response.input_data.len() != out.len() {
+        return Err(SDKRuntimeError::SDKUnknownResponse);
+    }
+    out.copy_from_slice(response.input_data);
+    Ok(())
+}
+
+/// Rust client-side wrapper for the model_output_range method. Output
+/// of model |id| starting at |offset| is written to |out|, which may
+/// hold at most MODEL_OUTPUT_RANGE_MAX bytes; the number of bytes
+/// written is returned (0 past the end of the output). Use this for
+/// output larger than ModelOutput::data. The first read keeps the
+/// output of the last completed run so a client paging through it
+/// sees one run's output; pass |clear| on the final read to release
+/// it so the next read returns the output of a later run.
+#[inline]
+pub fn sdk_model_output_range(
+    id: ModelId,
+    offset: u32,
+    out: &mut [u8],
+    clear: bool,
+) -> Result<usize, SDKRuntimeError> {
+    if out.len() > MODEL_OUTPUT_RANGE_MAX {
+        return Err(SDKRuntimeError::SDKInvalidInputRange);
+    }
+    let response = sdk_request::<ModelOutputRangeRequest, ModelOutputRangeResponse>(
+        SDKRuntimeRequest::GetModelOutputRange,
+        &ModelOutputRangeRequest {
+            id,
+            offset,
+            len: out.len() as u32,
+            clear,
+        },
+    )?;
+    let count = response.data.len();
+    if count > out.len() {
+        return Err(SDKRuntimeError::SDKUnknownResponse);
+    }
+    out[..count].copy_from_slice(response.data);
+    Ok(count)
+}
+
+#[inline]
+pub fn sdk_model_state(id: ModelId) -> Result<ModelState, SDKRuntimeError> {
+    let response = sdk_request::<ModelGetStateRequest, ModelGetStateResponse>(
+        SDKRuntimeRequest::ModelGetState,
+        &ModelGetStateRequest { id },
+    )?;
+    Ok(response.state)
+}
+
 #[inline]
 pub fn sdk_audio_reset(
     rxrst: bool,
@@ -705,6 +1628,28 @@ pub fn sdk_audio_record_start(
             rate,
             buffer_size,
             stop_on_full,
+            drop_log_ms: None,
+        },
+    )
+}
+
+/// Like sdk_audio_record_start but with control over how often samples
+/// dropped by a circular (!|stop_on_full|) recording are logged; a
+/// |drop_log_ms| of 0 disables logging.
+#[inline]
+pub fn sdk_audio_record_start_with_drop_log(
+    rate: usize,
+    buffer_size: usize,
+    stop_on_full: bool,
+    drop_log_ms: u32,
+) -> Result<(), SDKRuntimeError> {
+    sdk_request::<AudioRecordStartRequest, ()>(
+        SDKRuntimeRequest::AudioRecordStart,
+        &AudioRecordStartRequest {
+            rate,
+            buffer_size,
+            stop_on_full,
+            drop_log_ms: Some(drop_log_ms),
         },
     )
 }
@@ -718,8 +1663,7 @@ pub fn sdk_audio_record_collect_non_blocking(data: &mut [u32]) -> Result<usize,
             wait_if_empty: false,
         },
     )?;
-    data[..response.data.len()].copy_from_slice(response.data.to_vec().as_slice());
-    Ok(response.data.len())
+    Ok(response.copy_to(data))
 }
 
 #[inline]
@@ -731,8 +1675,26 @@ pub fn sdk_audio_record_collect(data: &mut [u32]) -> Result<usize, SDKRuntimeErr
             wait_if_empty: true,
         },
     )?;
-    data[..response.data.len()].copy_from_slice(response.data.to_vec().as_slice());
-    Ok(response.data.len())
+    Ok(response.copy_to(data))
+}
+
+/// Like sdk_audio_record_collect but also returns whether the session
+/// ended and how many samples were overwritten since the previous
+/// collect; a recording started with stop_on_full ends once the buffer
+/// has filled and everything recorded has been collected.
+#[inline]
+pub fn sdk_audio_record_collect_status(
+    data: &mut [u32],
+    wait_if_empty: bool,
+) -> Result<(usize, bool, u32), SDKRuntimeError> {
+    let response = sdk_request::<AudioRecordCollectRequest, AudioRecordCollectResponse>(
+        SDKRuntimeRequest::AudioRecordCollect,
+        &AudioRecordCollectRequest {
+            max_samples: data.len(),
+            wait_if_empty,
+        },
+    )?;
+    Ok((response.copy_to(data), response.session_ended, response.dropped))
 }
 
 #[inline]
@@ -747,7 +1709,30 @@ pub fn sdk_audio_record_stop() -> Result<(), SDKRuntimeError> {
 pub fn sdk_audio_play_start(rate: usize, buffer_size: usize) -> Result<(), SDKRuntimeError> {
     sdk_request::<AudioPlayStartRequest, ()>(
         SDKRuntimeRequest::AudioPlayStart,
-        &AudioPlayStartRequest { rate, buffer_size },
+        &AudioPlayStartRequest {
+            rate,
+            buffer_size,
+            wakeup_threshold: None,
+        },
+    )
+}
+
+/// Like sdk_audio_play_start but with control over when a blocked
+/// sdk_audio_play_write is woken up; a smaller |wakeup_threshold|
+/// lowers latency, a larger one reduces wakeups.
+#[inline]
+pub fn sdk_audio_play_start_with_wakeup(
+    rate: usize,
+    buffer_size: usize,
+    wakeup_threshold: usize,
+) -> Result<(), SDKRuntimeError> {
+    sdk_request::<AudioPlayStartRequest, ()>(
+        SDKRuntimeRequest::AudioPlayStart,
+        &AudioPlayStartRequest {
+            rate,
+            buffer_size,
+            wakeup_threshold: Some(wakeup_threshold),
+        },
     )
 }
 
@@ -761,6 +1746,20 @@ pub fn sdk_audio_play_write(data: &[u32]) -> Result<(), SDKRuntimeError> {
     )
 }
 
+/// Like sdk_audio_play_write but returns immediately with the number of
+/// samples accepted (0 if the play buffer is full); the caller resubmits
+/// the rest when it chooses, pacing playback itself.
+#[inline]
+pub fn sdk_audio_play_write_nb(data: &[u32]) -> Result<usize, SDKRuntimeError> {
+    let response = sdk_request::<AudioPlayWriteRequest, AudioPlayWriteResponse>(
+        SDKRuntimeRequest::AudioPlayWriteNonBlocking,
+        &AudioPlayWriteRequest {
+            data: ZeroVec::from_slice_or_alloc(data),
+        },
+    )?;
+    Ok(response.accepted)
+}
+
 #[inline]
 pub fn sdk_audio_play_stop() -> Result<(), SDKRuntimeError> {
     sdk_request::<AudioPlayStopRequest, ()>(
@@ -768,3 +1767,487 @@ pub fn sdk_audio_play_stop() -> Result<(), SDKRuntimeError> {
         &AudioPlayStopRequest {},
     )
 }
+
+/// Rust client-side wrapper for the audio_play_flush method; e.g. to
+/// cut off a prompt when the user starts talking.
+#[inline]
+pub fn sdk_audio_play_flush() -> Result<(), SDKRuntimeError> {
+    sdk_request::<AudioPlayFlushRequest, ()>(
+        SDKRuntimeRequest::AudioPlayFlush,
+        &AudioPlayFlushRequest {},
+    )
+}
+
+#[inline]
+pub fn sdk_audio_state() -> Result<AudioState, SDKRuntimeError> {
+    let response = sdk_request::<AudioGetStateRequest, AudioGetStateResponse>(
+        SDKRuntimeRequest::AudioGetState,
+        &AudioGetStateRequest {},
+    )?;
+    Ok(response.state)
+}
+
+#[inline]
+pub fn sdk_audio_get_format(direction: AudioDirection) -> Result<AudioFormat, SDKRuntimeError> {
+    let response = sdk_request::<AudioGetFormatRequest, AudioGetFormatResponse>(
+        SDKRuntimeRequest::AudioGetFormat,
+        &AudioGetFormatRequest { direction },
+    )?;
+    Ok(response.format)
+}
+
+#[inline]
+pub fn sdk_audio_selftest() -> Result<bool, SDKRuntimeError> {
+    let response = sdk_request::<AudioSelfTestRequest, AudioSelfTestResponse>(
+        SDKRuntimeRequest::AudioSelfTest,
+        &AudioSelfTestRequest {},
+    )?;
+    Ok(response.passed)
+}
+
+#[inline]
+pub fn sdk_set_fault_policy(policy: FaultPolicy) -> Result<(), SDKRuntimeError> {
+    sdk_request::<SetFaultPolicyRequest, ()>(
+        SDKRuntimeRequest::SetFaultPolicy,
+        &SetFaultPolicyRequest { policy },
+    )
+}
+
+/// Rust client-side wrapper for the shutdown method. An app that exits
+/// on its own should call this first so its timers & models stop
+/// without waiting for the ProcessManager to clean up.
+#[inline]
+pub fn sdk_shutdown() -> Result<(), SDKRuntimeError> {
+    sdk_request::<ShutdownRequest, ()>(SDKRuntimeRequest::Shutdown, &ShutdownRequest {})
+}
+
+#[inline]
+pub fn sdk_model_upload_begin(model_id: &str, size: usize) -> Result<usize, SDKRuntimeError> {
+    let response = sdk_request::<ModelUploadBeginRequest, ModelUploadResponse>(
+        SDKRuntimeRequest::ModelUploadBegin,
+        &ModelUploadBeginRequest { model_id, size },
+    )?;
+    Ok(response.received)
+}
+
+#[inline]
+pub fn sdk_model_upload_chunk(
+    model_id: &str,
+    offset: usize,
+    data: &[u8],
+) -> Result<usize, SDKRuntimeError> {
+    let response = sdk_request::<ModelUploadChunkRequest, ModelUploadResponse>(
+        SDKRuntimeRequest::ModelUploadChunk,
+        &ModelUploadChunkRequest {
+            model_id,
+            offset,
+            data,
+        },
+    )?;
+    Ok(response.received)
+}
+
+#[inline]
+pub fn sdk_model_upload_commit(
+    model_id: &str,
+    signature: Option<&[u8]>,
+) -> Result<(), SDKRuntimeError> {
+    sdk_request::<ModelUploadCommitRequest, ()>(
+        SDKRuntimeRequest::ModelUploadCommit,
+        &ModelUploadCommitRequest {
+            model_id,
+            signature,
+        },
+    )
+}
+
+/// Uploads |model| as |model_id| in MODEL_UPLOAD_CHUNK_SIZE chunks and
+/// installs it. If a previous upload of |model_id| was interrupted it
+/// is resumed where it left off.
+pub fn sdk_model_upload(
+    model_id: &str,
+    model: &[u8],
+    signature: Option<&[u8]>,
+) -> Result<(), SDKRuntimeError> {
+    let mut received = sdk_model_upload_begin(model_id, model.len())?;
+    while received < model.len() {
+        let end = core::cmp::min(received + MODEL_UPLOAD_CHUNK_SIZE, model.len());
+        received = sdk_model_upload_chunk(model_id, received, &model[received..end])?;
+    }
+    sdk_model_upload_commit(model_id, signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reply_status() {
+        assert_eq!(decode_reply(SDKRuntimeError::SDKSuccess as seL4_Word, 0), Ok(()));
+        assert_eq!(
+            decode_reply(SDKRuntimeError::SDKNoSuchModel as seL4_Word, 0),
+            Err(SDKRuntimeError::SDKNoSuchModel)
+        );
+        assert_eq!(decode_reply(1000, 0), Err(SDKRuntimeError::SDKUnknownResponse));
+    }
+
+    #[test]
+    fn reply_fault() {
+        // A VMFault message (label 5, 5 message registers) is not an
+        // error code even though the label is a valid one.
+        assert_eq!(decode_reply(5, 5), Err(SDKRuntimeError::SDKServerFaulted));
+        assert_eq!(decode_reply(1, 1), Err(SDKRuntimeError::SDKServerFaulted));
+        // Labels outside the fault range are not faults.
+        assert_eq!(
+            decode_reply(SDKRuntimeRequest::Ping as seL4_Word, 1),
+            Err(SDKRuntimeError::SDKUnknownResponse)
+        );
+    }
+
+    #[test]
+    fn log_msg_empty() {
+        let request = LogRequest { msg: b"" };
+        assert_eq!(request.msg_lossy(), (Cow::Borrowed(""), false));
+    }
+
+    #[test]
+    fn log_msg_valid() {
+        let request = LogRequest { msg: b"hello" };
+        assert!(matches!(request.msg_lossy(), (Cow::Borrowed("hello"), false)));
+    }
+
+    #[test]
+    fn log_msg_non_utf8() {
+        let request = LogRequest {
+            msg: &[b'a', 0xff, 0xfe, b'b'],
+        };
+        let (msg, truncated) = request.msg_lossy();
+        assert_eq!(msg, "a\u{fffd}\u{fffd}b");
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn log_msg_too_long() {
+        let data = [b'x'; MAX_LOG_MSG_LEN + 10];
+        let request = LogRequest { msg: &data };
+        let (msg, truncated) = request.msg_lossy();
+        assert!(truncated);
+        assert_eq!(msg.len(), MAX_LOG_MSG_LEN);
+        let head = msg.strip_suffix(LOG_ELLIPSIS).unwrap();
+        assert!(head.bytes().all(|b| b == b'x'));
+
+        // A message that just fits is not cut.
+        let request = LogRequest {
+            msg: &data[..MAX_LOG_MSG_LEN],
+        };
+        assert!(!request.msg_lossy().1);
+    }
+
+    #[test]
+    fn log_msg_too_long_split_char() {
+        // A 3-byte char straddles the cut; it must be dropped, not split.
+        let cut = MAX_LOG_MSG_LEN - LOG_ELLIPSIS.len();
+        let mut data = [b'x'; MAX_LOG_MSG_LEN + 2];
+        data[cut - 1..cut + 2].copy_from_slice("\u{20ac}".as_bytes());
+        let request = LogRequest { msg: &data };
+        let (msg, truncated) = request.msg_lossy();
+        assert!(truncated);
+        assert_eq!(msg.len(), MAX_LOG_MSG_LEN - 1);
+        assert!(!msg.contains('\u{fffd}'));
+    }
+
+    #[test]
+    fn log_msg_too_long_non_utf8() {
+        let data = [0xffu8; MAX_LOG_MSG_LEN + 10];
+        let request = LogRequest { msg: &data };
+        let (msg, truncated) = request.msg_lossy();
+        assert!(truncated);
+        assert!(msg.len() <= MAX_LOG_MSG_LEN);
+        let head = msg.strip_suffix(LOG_ELLIPSIS).unwrap();
+        assert!(head.chars().all(|c| c == '\u{fffd}'));
+    }
+
+    #[test]
+    fn request_without_frame() {
+        let request = |caps_unwrapped, extra_caps| {
+            let info = seL4_MessageInfo::new(
+                /*label=*/ SDKRuntimeRequest::Ping.into(),
+                caps_unwrapped,
+                extra_caps,
+                /*length=*/ 0,
+            );
+            SDKRuntimeError::from(check_request_frame(&info))
+        };
+        assert_eq!(request(0, 1), SDKRuntimeError::SDKSuccess);
+        assert_eq!(request(0, 0), SDKRuntimeError::SDKInvalidFrame);
+        // A cap unwrapped to its badge is not a frame.
+        assert_eq!(request(1, 1), SDKRuntimeError::SDKInvalidFrame);
+    }
+
+    #[test]
+    fn model_upload_chunk_fits_request() {
+        let data = [0xa5u8; MODEL_UPLOAD_CHUNK_SIZE];
+        let model_id = "m".repeat(128); // A long model name
+        let mut request = [0u8; SDKRUNTIME_REQUEST_DATA_SIZE];
+        let chunk = ModelUploadChunkRequest {
+            model_id: &model_id,
+            offset: usize::MAX,
+            data: &data,
+        };
+        assert!(postcard::to_slice(&chunk, &mut request).is_ok());
+    }
+
+    #[test]
+    fn audio_collect_max_fits_reply() {
+        let samples = [0xdeadbeefu32; AUDIO_RECORD_COLLECT_MAX_SAMPLES];
+        let mut reply = [0u8; PAGE_SIZE - SDKRUNTIME_REQUEST_DATA_SIZE];
+        let response = AudioRecordCollectResponse {
+            data: ZeroVec::from_slice_or_alloc(&samples),
+            session_ended: true,
+            dropped: u32::MAX,
+        };
+        assert!(postcard::to_slice(&response, &mut reply).is_ok());
+    }
+
+    #[test]
+    fn model_get_input_max_fits_reply() {
+        let input_data = [0xa5u8; MODEL_GET_INPUT_MAX];
+        let mut reply = [0u8; SDKRUNTIME_REPLY_DATA_SIZE];
+        let response = ModelGetInputResponse {
+            input_data: &input_data,
+        };
+        assert!(postcard::to_slice(&response, &mut reply).is_ok());
+    }
+
+    #[test]
+    fn model_output_range_max_fits_reply() {
+        let data = [0xa5u8; MODEL_OUTPUT_RANGE_MAX];
+        let mut reply = [0u8; SDKRUNTIME_REPLY_DATA_SIZE];
+        let response = ModelOutputRangeResponse { data: &data };
+        assert!(postcard::to_slice(&response, &mut reply).is_ok());
+    }
+
+    #[test]
+    fn list_keys_names() {
+        let mut reply = [0u8; SDKRUNTIME_REPLY_DATA_SIZE];
+        let response = ListKeysResponse {
+            keys: vec!["alpha", "b", "gamma"],
+        };
+        let reply = postcard::to_slice(&response, &mut reply).unwrap();
+        let decoded = postcard::from_bytes::<ListKeysResponse>(reply).unwrap();
+
+        let mut out = [0u8; 16];
+        let len = write_key_names(&decoded.keys, &mut out).unwrap();
+        assert_eq!(&out[..len], b"alpha\nb\ngamma\n");
+        assert_eq!(write_key_names(&[], &mut out), Ok(0));
+        // Names are never truncated.
+        assert_eq!(
+            write_key_names(&decoded.keys, &mut out[..len - 1]),
+            Err(SDKRuntimeError::SDKSerializeFailed)
+        );
+    }
+
+    #[test]
+    fn read_keys_request() {
+        let keys = ["a", "bb", "ccc"];
+        let mut request = [0u8; SDKRUNTIME_REQUEST_DATA_SIZE];
+        let _ = postcard::to_slice(
+            &ReadKeysRequest {
+                keys: Cow::Borrowed(&keys),
+            },
+            &mut request,
+        )
+        .unwrap();
+        let decoded = postcard::from_bytes::<ReadKeysRequest>(&request).unwrap();
+        assert_eq!(&*decoded.keys, &keys);
+    }
+
+    #[test]
+    fn read_keys_reply_fits() {
+        let value = [0xffu8; KEY_VALUE_DATA_SIZE];
+        let mut reply = [0u8; SDKRUNTIME_REPLY_DATA_SIZE];
+        let mut response = ReadKeysResponse {
+            values: (0..MAX_READ_KEYS).map(|_| Ok(&value[..])).collect(),
+        };
+        assert_eq!(serialize_reply(&response, &mut reply), Ok(()));
+
+        // Per-key errors come back in order.
+        response.values[1] = Err(SDKError::ReadKeyFailed);
+        assert_eq!(serialize_reply(&response, &mut reply), Ok(()));
+        let decoded = postcard::from_bytes::<ReadKeysResponse>(&reply).unwrap();
+        assert_eq!(decoded.values.len(), MAX_READ_KEYS);
+        assert_eq!(decoded.values[0], Ok(&value[..]));
+        assert_eq!(decoded.values[1], Err(SDKError::ReadKeyFailed));
+
+        // One more key does not fit.
+        response.values = (0..=MAX_READ_KEYS).map(|_| Ok(&value[..])).collect();
+        assert_eq!(serialize_reply(&response, &mut reply), Err(SDKError::ReplyTooLarge));
+    }
+
+    #[test]
+    fn reply_size_fits() {
+        let response = ModelOutputResponse {
+            output: ModelOutput {
+                jobnum: usize::MAX,
+                generation: u64::MAX,
+                produced_at_us: u64::MAX,
+                elapsed_us: u32::MAX,
+                return_code: u32::MAX,
+                epc: Some(u32::MAX),
+                data: [0xff; MAX_OUTPUT_DATA],
+            },
+        };
+        let mut reply = [0u8; SDKRUNTIME_REPLY_DATA_SIZE];
+        assert_eq!(serialize_reply(&response, &mut reply), Ok(()));
+        let len = postcard::to_slice(&response, &mut reply).unwrap().len();
+
+        // The check is exact: one byte short is too small.
+        assert_eq!(
+            serialize_reply(&response, &mut reply[..len - 1]),
+            Err(SDKError::ReplyTooLarge)
+        );
+        assert_eq!(serialize_reply(&response, &mut reply[..len]), Ok(()));
+    }
+
+    #[test]
+    fn reply_size_overflow() {
+        let samples = [0xdeadbeefu32; AUDIO_RECORD_COLLECT_MAX_SAMPLES + 1];
+        let response = AudioRecordCollectResponse {
+            data: ZeroVec::from_slice_or_alloc(&samples),
+            session_ended: true,
+            dropped: u32::MAX,
+        };
+        let mut reply = [0u8; SDKRUNTIME_REPLY_DATA_SIZE];
+        assert_eq!(serialize_reply(&response, &mut reply), Err(SDKError::ReplyTooLarge));
+        assert_eq!(
+            SDKRuntimeError::from(SDKError::ReplyTooLarge),
+            SDKRuntimeError::SDKReplyTooLarge
+        );
+    }
+
+    // Checks |reply|, the largest reply of its type, takes exactly the
+    // type's bound and that the bound fits in the reply half of the page.
+    fn check_reply_bound<T: SDKReply>(reply: &T) {
+        let bound = T::MAX_SERIALIZED_LEN.unwrap();
+        assert!(bound <= SDKRUNTIME_REPLY_DATA_SIZE);
+        let mut buf = [0u8; PAGE_SIZE];
+        assert_eq!(postcard::to_slice(reply, &mut buf).unwrap().len(), bound);
+    }
+
+    #[test]
+    fn reply_bounds() {
+        let value = [0xffu8; KEY_VALUE_DATA_SIZE];
+        check_reply_bound(&ReadKeyResponse { value: &value });
+        check_reply_bound(&ReadKeysResponse {
+            values: (0..MAX_READ_KEYS).map(|_| Ok(&value[..])).collect(),
+        });
+        assert_eq!(ListKeysResponse::MAX_SERIALIZED_LEN, None);
+        check_reply_bound(&TimerWaitResponse { mask: u32::MAX });
+        check_reply_bound(&TimerRemainingResponse {
+            remaining_ms: u32::MAX,
+        });
+        check_reply_bound(&TimerResolutionResponse {
+            resolution_hz: u32::MAX,
+        });
+        check_reply_bound(&ResourceInfoResponse {
+            info: ResourceInfo {
+                timers_available: u8::MAX,
+                timers_max: u8::MAX,
+                model_available: u8::MAX,
+                log_dropped: u32::MAX,
+            },
+        });
+        check_reply_bound(&ModelStartResponse { id: u32::MAX });
+        check_reply_bound(&ModelWaitResponse { mask: u32::MAX });
+        check_reply_bound(&ModelOutputResponse {
+            output: ModelOutput {
+                jobnum: usize::MAX,
+                generation: u64::MAX,
+                produced_at_us: u64::MAX,
+                elapsed_us: u32::MAX,
+                return_code: u32::MAX,
+                epc: Some(u32::MAX),
+                data: [0xff; MAX_OUTPUT_DATA],
+            },
+        });
+        check_reply_bound(&ModelGetInputParamsResponse {
+            id: u32::MAX,
+            input_params: ModelInput {
+                input_ptr: u32::MAX,
+                input_size_bytes: u32::MAX,
+            },
+        });
+        let data = [0xa5u8; MODEL_GET_INPUT_MAX];
+        check_reply_bound(&ModelGetInputResponse { input_data: &data });
+        let data = [0xa5u8; MODEL_OUTPUT_RANGE_MAX];
+        check_reply_bound(&ModelOutputRangeResponse { data: &data });
+        check_reply_bound(&ModelGetStateResponse {
+            state: ModelState::Periodic,
+        });
+        let samples = [0xdeadbeefu32; AUDIO_RECORD_COLLECT_MAX_SAMPLES];
+        check_reply_bound(&AudioRecordCollectResponse {
+            data: ZeroVec::from_slice_or_alloc(&samples),
+            session_ended: true,
+            dropped: u32::MAX,
+        });
+        check_reply_bound(&AudioPlayWriteResponse {
+            accepted: usize::MAX,
+        });
+        let config = AudioConfig {
+            rate: usize::MAX,
+            buffer_size: usize::MAX,
+        };
+        check_reply_bound(&AudioGetStateResponse {
+            state: AudioState {
+                record: AudioRecordState::Recording(config),
+                play: AudioPlayState::Playing(config),
+            },
+        });
+        check_reply_bound(&AudioGetFormatResponse {
+            format: AudioFormat {
+                sample_rate: usize::MAX,
+                channels: u8::MAX,
+                bits_per_sample: u8::MAX,
+            },
+        });
+        check_reply_bound(&AudioSelfTestResponse { passed: true });
+        check_reply_bound(&ModelUploadResponse {
+            received: usize::MAX,
+        });
+    }
+
+    #[test]
+    fn audio_collect_copy_to() {
+        let samples: [u32; 64] = core::array::from_fn(|i| (i as u32) * 0x01010101 + 0x80);
+        let mut reply = [0u8; PAGE_SIZE - SDKRUNTIME_REQUEST_DATA_SIZE];
+        let _ = postcard::to_slice(
+            &AudioRecordCollectResponse {
+                data: ZeroVec::from_slice_or_alloc(&samples),
+                session_ended: false,
+                dropped: 3,
+            },
+            &mut reply,
+        )
+        .unwrap();
+        let response = postcard::from_bytes::<AudioRecordCollectResponse>(&reply).unwrap();
+        assert!(!response.session_ended);
+        assert_eq!(response.dropped, 3);
+
+        // Must match byte-for-byte the original samples & the to_vec path.
+        let mut data = [0u32; 64];
+        assert_eq!(response.copy_to(&mut data), samples.len());
+        assert_eq!(data, samples);
+        assert_eq!(response.data.to_vec().as_slice(), &data[..]);
+
+        // Short destination buffer is filled without overrun.
+        let mut short = [0u32; 10];
+        assert_eq!(response.copy_to(&mut short), short.len());
+        assert_eq!(short, samples[..10]);
+
+        // Long destination buffer is only partially written.
+        let mut long = [0u32; 100];
+        assert_eq!(response.copy_to(&mut long), samples.len());
+        assert_eq!(long[..64], samples);
+        assert!(long[64..].iter().all(|x| *x == 0));
+    }
+}
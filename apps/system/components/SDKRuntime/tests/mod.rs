@@ -15,14 +15,29 @@
 #![allow(non_camel_case_types)]
 #![allow(dead_code)]
 
+extern crate alloc;
+
 const I2S_CSR_SIZE: usize = 4096;
+#[repr(align(4))] // NB: registers are read as u32
 struct I2S_CSR {
     pub data: [u8; I2S_CSR_SIZE],
 }
 static mut I2S_CSR: I2S_CSR = I2S_CSR {
     data: [0u8; I2S_CSR_SIZE],
 };
+#[no_mangle]
 pub fn get_i2s_csr() -> &'static [u8] { unsafe { &I2S_CSR.data } }
+#[no_mangle]
 pub fn get_i2s_csr_mut() -> &'static mut [u8] { unsafe { &mut I2S_CSR.data } }
 
 include!("../i2s-driver/src/i2s.rs");
+
+// NB: buffer.rs has inner doc comments so it cannot be include!'d
+#[path = "../i2s-driver/src/buffer.rs"]
+mod buffer;
+
+#[path = "../i2s-driver/src/fifo.rs"]
+mod fifo;
+
+#[cfg(test)]
+mod trace;
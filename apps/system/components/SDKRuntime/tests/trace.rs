@@ -0,0 +1,505 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Replays recorded I2S register traces through the driver's FIFO code.
+//!
+//! A trace is the sequence of FIFO levels seen at each watermark
+//! interrupt. The harness keeps a model of the RX and TX FIFOs behind
+//! the faked I2S_CSR region: FIFO_STATUS tracks the model and RDATA/WDATA
+//! move words in and out of it. Each step runs the same code as the
+//! interrupt handlers so buffer behaviour can be checked off-target.
+
+use crate::buffer::Buffer;
+use crate::fifo::{drain_rx_fifo, drain_tx_buffer, fill_tx_fifo, loopback_check, DoubleBuffer};
+use crate::{get_i2s_mut, FifoStatus};
+use reg_constants::i2s::I2S_FIFO_STATUS_REG_OFFSET;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+const I2S_FIFO_CAPACITY: usize = 32;
+
+/// Recorded register state for one event.
+#[derive(Clone, Copy, Debug)]
+pub enum Step {
+    /// rx_watermark fired with |0| samples in the RX FIFO.
+    RxWatermark(usize),
+    /// tx_watermark fired with |0| words left in the TX FIFO.
+    TxWatermark(usize),
+    /// The client collected up to |0| recorded samples.
+    Collect(usize),
+    /// Like Collect but reading the samples in place when possible.
+    CollectInPlace(usize),
+    /// The client wrote |0| samples to play.
+    Write(usize),
+}
+
+struct Fifos {
+    rx: VecDeque<u32>,
+    tx: VecDeque<u32>,
+    // With loopback each word written to WDATA is passed through this
+    // and lands in the RX FIFO instead of being played.
+    loopback: Option<fn(u32) -> u32>,
+}
+impl Fifos {
+    // Updates FIFO_STATUS to match the FIFO contents.
+    fn sync(&self) {
+        let status = FifoStatus::new()
+            .with_rxlvl(self.rx.len() as u8)
+            .with_txlvl(self.tx.len() as u8);
+        unsafe {
+            get_i2s_mut(I2S_FIFO_STATUS_REG_OFFSET)
+                .write_volatile(u32::from_ne_bytes(status.into_bytes()));
+        }
+    }
+}
+static FIFOS: Mutex<Fifos> = Mutex::new(Fifos {
+    rx: VecDeque::new(),
+    tx: VecDeque::new(),
+    loopback: None,
+});
+// Serializes Players; the faked I2S_CSR region is global.
+static PLAYER: Mutex<()> = Mutex::new(());
+
+fn fifos() -> MutexGuard<'static, Fifos> { FIFOS.lock().unwrap_or_else(|e| e.into_inner()) }
+
+#[no_mangle]
+fn i2s_fifo_pop_rdata() -> u32 {
+    let mut fifos = fifos();
+    let data = fifos.rx.pop_front().expect("RDATA read with RX FIFO empty");
+    fifos.sync();
+    data
+}
+
+#[no_mangle]
+fn i2s_fifo_push_wdata(data: u32) {
+    let mut fifos = fifos();
+    assert!(fifos.tx.len() < I2S_FIFO_CAPACITY, "WDATA write with TX FIFO full");
+    match fifos.loopback {
+        Some(echo) => fifos.rx.push_back(echo(data)),
+        None => fifos.tx.push_back(data),
+    }
+    fifos.sync();
+}
+
+/// Runs traces against a fresh set of driver buffers.
+///
+/// Recorded samples are numbered from 0 in the order they enter the RX
+/// FIFO and samples written for playback likewise, so tests can check
+/// for loss and reordering.
+pub struct Player {
+    _lock: MutexGuard<'static, ()>,
+    rx: Box<DoubleBuffer>,
+    tx: Box<Buffer>,
+    stop_on_full: bool,
+    recorded: u32,
+    written: u32,
+    pub collected: Vec<u32>,
+    pub played: Vec<u32>,
+    pub flips: usize,
+    pub session_ended: bool, // As reported by the last collect
+    pub in_place: usize,     // Collects that read samples in place
+}
+impl Player {
+    pub fn new(stop_on_full: bool) -> Self {
+        let lock = PLAYER.lock().unwrap_or_else(|e| e.into_inner());
+        let mut fifos = fifos();
+        fifos.rx.clear();
+        fifos.tx.clear();
+        fifos.loopback = None;
+        fifos.sync();
+        Self {
+            _lock: lock,
+            rx: Box::new(DoubleBuffer::new()),
+            tx: Box::new(Buffer::new()),
+            stop_on_full,
+            recorded: 0,
+            written: 0,
+            collected: Vec::new(),
+            played: Vec::new(),
+            flips: 0,
+            session_ended: false,
+            in_place: 0,
+        }
+    }
+
+    pub fn run(&mut self, trace: &[Step]) {
+        for step in trace {
+            self.step(*step);
+        }
+    }
+
+    pub fn step(&mut self, step: Step) {
+        match step {
+            Step::RxWatermark(level) => {
+                assert!(level <= I2S_FIFO_CAPACITY);
+                {
+                    // NB: samples that would overflow the FIFO are lost
+                    // by the hardware and never numbered.
+                    let mut fifos = fifos();
+                    while fifos.rx.len() < level {
+                        fifos.rx.push_back(self.recorded);
+                        self.recorded += 1;
+                    }
+                    fifos.sync();
+                }
+                if drain_rx_fifo(&mut self.rx, self.stop_on_full) {
+                    self.flips += 1;
+                } else if self.rx.is_full() {
+                    // The handler stops recording, flushing the RX FIFO.
+                    let mut fifos = fifos();
+                    fifos.rx.clear();
+                    fifos.sync();
+                }
+            }
+            Step::TxWatermark(level) => {
+                {
+                    let mut fifos = fifos();
+                    while fifos.tx.len() > level {
+                        let data = fifos.tx.pop_front().unwrap();
+                        self.played.push(data);
+                    }
+                    fifos.sync();
+                }
+                fill_tx_fifo(&mut self.tx);
+            }
+            Step::Collect(count) => {
+                for _ in 0..count {
+                    match self.rx.pop() {
+                        Some(data) => self.collected.push(data),
+                        None => break,
+                    }
+                }
+                self.session_ended = self.rx.session_ended();
+            }
+            Step::CollectInPlace(count) => match self.rx.collect_slice(count, false) {
+                Some(data) => {
+                    let count = data.len();
+                    self.collected.extend_from_slice(data);
+                    self.rx.discard(count);
+                    self.session_ended = self.rx.session_ended();
+                    self.in_place += 1;
+                }
+                None => self.step(Step::Collect(count)),
+            },
+            Step::Write(count) => {
+                for _ in 0..count {
+                    assert!(self.tx.available_space() > 0);
+                    self.tx.push(self.written);
+                    self.written += 1;
+                }
+                fill_tx_fifo(&mut self.tx);
+            }
+        }
+    }
+
+    /// Plays out the TX buffer as audio_play_stop does. Each wait plays
+    /// |rate| words from the TX FIFO and takes 1ms; the request deadline
+    /// passes after |budget_ms|. Returns whether the buffer was played out.
+    pub fn stop(&mut self, rate: usize, budget_ms: u64) -> bool {
+        let played = &mut self.played;
+        let clock = Cell::new(Duration::ZERO);
+        let deadline = Duration::from_millis(budget_ms);
+        let (_, drained) = drain_tx_buffer(
+            &mut *self.tx,
+            |buf| {
+                let mut fifos = fifos();
+                for _ in 0..rate.min(fifos.tx.len()) {
+                    played.push(fifos.tx.pop_front().unwrap());
+                }
+                fifos.sync();
+                clock.set(clock.get() + Duration::from_millis(1));
+                buf
+            },
+            || clock.get() >= deadline,
+        );
+        drained
+    }
+
+    /// Runs the loopback self-test check of |pattern| with |echo| applied
+    /// to the looped back data (None for no loopback). |stale| samples
+    /// are already waiting in the RX FIFO. The check gives up after
+    /// |budget| polls of an empty RX FIFO.
+    pub fn loopback(
+        &mut self,
+        pattern: &[u32],
+        echo: Option<fn(u32) -> u32>,
+        stale: usize,
+        latency: usize,
+        budget: usize,
+    ) -> bool {
+        {
+            let mut fifos = fifos();
+            for _ in 0..stale {
+                fifos.rx.push_back(0);
+            }
+            fifos.loopback = echo;
+            fifos.sync();
+        }
+        let mut polls = 0;
+        let passed = loopback_check(pattern, latency, || {
+            polls += 1;
+            polls < budget
+        });
+        fifos().loopback = None;
+        passed
+    }
+
+    /// Returns the number of samples waiting in the RX FIFO.
+    pub fn rx_fifo_level(&self) -> usize { crate::fifo::rx_fifo_level() as usize }
+
+    /// Returns the number of words waiting in the TX FIFO.
+    pub fn tx_fifo_level(&self) -> usize { crate::fifo::tx_fifo_level() as usize }
+
+    /// Returns the number of samples recorded but not yet collected.
+    pub fn pending(&mut self) -> usize {
+        self.rx.front().available_data() + self.rx.back().available_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BUFFER_CAPACITY;
+    use Step::*;
+
+    // Recording with rxilvl 16. Interrupt latency lets a few more
+    // samples land before the handler reads FIFO_STATUS. NB: the levels
+    // sum to 128 so the back buffer fills exactly at an interrupt;
+    // without stop_on_full samples drained past that point overwrite it.
+    const RECORD_LEVELS: [usize; 8] = [16, 17, 16, 15, 18, 16, 14, 16];
+
+    // Playback with txilvl 8 where the handler sometimes runs late.
+    const PLAY_LEVELS: [usize; 6] = [8, 7, 8, 8, 5, 8];
+
+    fn samples(count: usize) -> Vec<u32> { (0..count as u32).collect() }
+
+    // A client collecting half a buffer after every 8 interrupts.
+    fn normal_record(rounds: usize) -> Vec<Step> {
+        let mut trace = Vec::new();
+        for _ in 0..rounds {
+            trace.extend(RECORD_LEVELS.map(RxWatermark));
+            trace.push(Collect(BUFFER_CAPACITY / 2));
+        }
+        trace
+    }
+
+    // A client that stops collecting; the RX FIFO keeps filling.
+    fn stalled_record(interrupts: usize) -> Vec<Step> { vec![RxWatermark(16); interrupts] }
+
+    #[test]
+    fn record_normal() {
+        let mut player = Player::new(false);
+        player.run(&normal_record(40));
+        // 40 rounds record 5120 samples, two full buffers.
+        assert_eq!(player.flips, 2);
+        assert_eq!(player.collected, samples(2 * BUFFER_CAPACITY));
+        assert_eq!(player.pending(), 5120 - 2 * BUFFER_CAPACITY);
+        assert_eq!(player.rx_fifo_level(), 0);
+    }
+
+    #[test]
+    fn record_in_place() {
+        // Each buffer is emptied before it is refilled so the samples
+        // never wrap and every collect is done in place.
+        let mut trace = Vec::new();
+        for _ in 0..40 {
+            trace.extend(RECORD_LEVELS.map(RxWatermark));
+            trace.push(CollectInPlace(1000));
+        }
+        let mut player = Player::new(false);
+        player.run(&trace);
+        assert_eq!(player.in_place, 40);
+        assert_eq!(player.collected, samples(player.collected.len()));
+        assert_eq!(player.collected.len() + player.pending(), 5120);
+    }
+
+    #[test]
+    fn record_in_place_wrapped() {
+        let mut player = Player::new(false);
+        // Overwriting the back buffer leaves its samples wrapped.
+        player.run(&stalled_record(3 * BUFFER_CAPACITY / 16 + 1));
+        player.step(CollectInPlace(BUFFER_CAPACITY));
+        assert_eq!(player.collected, samples(BUFFER_CAPACITY));
+        player.step(RxWatermark(16));
+        assert_eq!(player.flips, 2);
+
+        // The wrapped samples are popped instead, oldest first.
+        player.step(CollectInPlace(BUFFER_CAPACITY));
+        assert_eq!(player.in_place, 1);
+        assert_eq!(player.collected.len(), 2 * BUFFER_CAPACITY);
+        assert!(player.collected.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn record_in_place_stop_on_full() {
+        let mut player = Player::new(true);
+        player.run(&stalled_record(2 * BUFFER_CAPACITY / 16));
+        assert!(player.rx.is_full());
+
+        // The back buffer is read in place once the front is collected.
+        player.step(CollectInPlace(BUFFER_CAPACITY));
+        assert!(!player.session_ended);
+        player.step(CollectInPlace(BUFFER_CAPACITY));
+        assert!(player.session_ended);
+        assert_eq!(player.in_place, 2);
+        assert_eq!(player.collected, samples(2 * BUFFER_CAPACITY));
+    }
+
+    #[test]
+    fn record_overrun() {
+        let mut player = Player::new(false);
+        // Fill both buffers and then some; the front buffer is never
+        // collected so the second flip fails.
+        player.run(&stalled_record(3 * BUFFER_CAPACITY / 16));
+        assert_eq!(player.flips, 1);
+        // The RX FIFO is still drained, overwriting the back buffer.
+        assert_eq!(player.rx_fifo_level(), 0);
+        assert_eq!(player.pending(), 2 * BUFFER_CAPACITY);
+        // Every sample past the two buffers overwrote one; the drop log
+        // and the client each see the same count.
+        assert_eq!(player.rx.take_dropped(), BUFFER_CAPACITY);
+        assert_eq!(player.rx.take_dropped(), 0);
+        assert_eq!(player.rx.take_overwritten(), BUFFER_CAPACITY);
+        assert_eq!(player.rx.take_overwritten(), 0);
+
+        // The front buffer is intact.
+        player.step(Collect(BUFFER_CAPACITY));
+        assert_eq!(player.collected, samples(BUFFER_CAPACITY));
+
+        // Once collected the full back buffer flips on the next interrupt.
+        player.step(RxWatermark(16));
+        assert_eq!(player.flips, 2);
+    }
+
+    #[test]
+    fn record_overrun_stop_on_full() {
+        let mut player = Player::new(true);
+        player.run(&stalled_record(BUFFER_CAPACITY / 16));
+        assert_eq!(player.flips, 1);
+        assert!(!player.rx.is_full());
+
+        // Recording stops once both buffers are full; the RX FIFO is
+        // flushed and later interrupts receive nothing.
+        player.run(&stalled_record(BUFFER_CAPACITY / 16));
+        assert!(player.rx.is_full());
+        player.run(&stalled_record(4));
+        assert_eq!(player.flips, 1);
+        assert_eq!(player.rx_fifo_level(), 0);
+        assert_eq!(player.pending(), 2 * BUFFER_CAPACITY);
+
+        // Nothing recorded before the stop is lost; the back buffer is
+        // collected after the front without another interrupt.
+        player.step(Collect(2 * BUFFER_CAPACITY));
+        assert_eq!(player.collected, samples(2 * BUFFER_CAPACITY));
+        assert_eq!(player.pending(), 0);
+        assert_eq!(player.rx.take_dropped(), 0);
+    }
+
+    #[test]
+    fn record_stop_on_full_session_ended() {
+        let mut player = Player::new(true);
+        player.run(&stalled_record(2 * BUFFER_CAPACITY / 16));
+        assert!(player.rx.is_full());
+
+        // Only the collect that drains the last sample reports the end.
+        player.step(Collect(BUFFER_CAPACITY));
+        assert!(!player.session_ended);
+        player.step(Collect(BUFFER_CAPACITY - 1));
+        assert!(!player.session_ended);
+        player.step(Collect(BUFFER_CAPACITY));
+        assert!(player.session_ended);
+        assert_eq!(player.collected, samples(2 * BUFFER_CAPACITY));
+
+        // Collecting after the end still reports it.
+        player.step(Collect(1));
+        assert!(player.session_ended);
+        assert_eq!(player.collected.len(), 2 * BUFFER_CAPACITY);
+
+        // Without stop_on_full the session never ends on its own.
+        drop(player);
+        let mut player = Player::new(false);
+        player.run(&stalled_record(3 * BUFFER_CAPACITY / 16));
+        player.step(Collect(2 * BUFFER_CAPACITY));
+        assert!(!player.rx.is_full());
+        assert!(!player.session_ended);
+    }
+
+    #[test]
+    fn play_normal() {
+        let mut player = Player::new(false);
+        player.step(Write(100));
+        assert_eq!(player.tx_fifo_level(), I2S_FIFO_CAPACITY);
+        // Refill from the TX buffer until it runs dry.
+        for level in PLAY_LEVELS.iter().cycle().take(10) {
+            player.step(TxWatermark(*level));
+            assert!(player.tx_fifo_level() <= I2S_FIFO_CAPACITY);
+        }
+        player.step(TxWatermark(0));
+        assert_eq!(player.tx_fifo_level(), 0);
+        assert_eq!(player.played, samples(100));
+    }
+
+    #[test]
+    fn play_stop() {
+        let mut player = Player::new(false);
+        player.step(Write(100));
+        assert!(player.stop(I2S_FIFO_CAPACITY, u64::MAX));
+        assert_eq!(player.tx_fifo_level(), 0);
+        assert_eq!(player.played, samples(100));
+    }
+
+    #[test]
+    fn play_stop_deadline() {
+        let mut player = Player::new(false);
+        player.step(Write(100));
+        // A slow TX path that plays one word per wakeup cannot drain
+        // 100 samples before the deadline.
+        assert!(!player.stop(1, 4));
+        assert_eq!(player.played, samples(4));
+        // The unplayed samples are discarded (the driver then resets
+        // the TX FIFO when it stops playing).
+        assert!(player.tx.is_empty());
+        assert_eq!(player.tx_fifo_level(), I2S_FIFO_CAPACITY - 1);
+    }
+    #[test]
+    fn loopback() {
+        let ramp: Vec<u32> = (1..=16).map(|x| (x << 16) | x).collect();
+        let mut player = Player::new(false);
+        assert!(player.loopback(&ramp, Some(|x| x), 0, 32, 100));
+        assert_eq!(player.rx_fifo_level(), 0);
+        assert_eq!(player.tx_fifo_level(), 0);
+
+        // Stale samples ahead of the ramp are skipped up to the latency.
+        assert!(player.loopback(&ramp, Some(|x| x), 32, 32, 100));
+        assert!(!player.loopback(&ramp, Some(|x| x), 33, 32, 100));
+    }
+
+    #[test]
+    fn loopback_fail() {
+        let ramp: Vec<u32> = (1..=16).map(|x| (x << 16) | x).collect();
+        let mut player = Player::new(false);
+        // One channel lost.
+        assert!(!player.loopback(&ramp, Some(|x| x & 0xffff), 0, 32, 100));
+        // A corrupted sample part way through the ramp.
+        let flip8 = |x| if x == 0x80008 { x ^ 1 } else { x };
+        assert!(!player.loopback(&ramp, Some(flip8), 0, 32, 100));
+        // Nothing comes back; the check gives up rather than hang.
+        drop(player);
+        let mut player = Player::new(false);
+        assert!(!player.loopback(&ramp, None, 0, 32, 100));
+        assert_eq!(player.tx_fifo_level(), ramp.len());
+    }
+}
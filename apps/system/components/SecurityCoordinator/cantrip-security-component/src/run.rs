@@ -69,8 +69,21 @@ impl CamkesThreadInterface for SecurityCoordinatorControlThread {
 
     cfg_if! {
       if #[cfg(feature = "mailbox-driver")] {
-        // XXX HACK: compensate for rtirq not setup
-        fn post_init() { mailbox_driver::RtirqInterfaceThread::post_init(); }
+        fn post_init() {
+            // XXX HACK: compensate for rtirq not setup
+            mailbox_driver::RtirqInterfaceThread::post_init();
+
+            // Agree on message limits with the SEC; if it does not
+            // support this the legacy limits are used.
+            match mailbox_driver::mbox_negotiate() {
+                Ok(limits) => log::info!("SEC message limits {:?}", limits),
+                Err(e) => log::info!(
+                    "SEC negotiate failed: {:?}, using {:?}",
+                    e,
+                    mailbox_driver::mbox_msg_limits()
+                ),
+            }
+        }
         fn run() {
             // NB: do not handle rtirq, it blocks waiting for the api thread
             shared_irq_loop!(
@@ -134,7 +147,8 @@ impl SecurityInterfaceThread {
             SecurityRequest::InstallApp {
                 app_id,
                 pkg_contents,
-            } => Self::install_app_request(app_id, pkg_contents.into_owned()),
+                signature,
+            } => Self::install_app_request(app_id, pkg_contents.into_owned(), signature),
             SecurityRequest::InstallModel {
                 app_id,
                 model_id,
@@ -148,13 +162,18 @@ impl SecurityInterfaceThread {
             SecurityRequest::GetManifest(bundle_id) => {
                 Self::get_manifest_request(bundle_id, reply_buffer)
             }
-            SecurityRequest::LoadApplication(bundle_id) => {
-                Self::load_application_request(bundle_id, reply_buffer)
-            }
+            SecurityRequest::LoadApplication {
+                bundle_id,
+                expected_hash,
+            } => Self::load_application_request(bundle_id, expected_hash.as_ref(), reply_buffer),
             SecurityRequest::LoadModel {
                 bundle_id,
                 model_id,
             } => Self::load_model_request(bundle_id, model_id, reply_buffer),
+            SecurityRequest::LoadSharedModel {
+                bundle_id,
+                model_id,
+            } => Self::load_shared_model_request(bundle_id, model_id, reply_buffer),
             SecurityRequest::ReadKey { bundle_id, key } => {
                 Self::read_key_request(bundle_id, key, reply_buffer)
             }
@@ -166,6 +185,29 @@ impl SecurityInterfaceThread {
             SecurityRequest::DeleteKey { bundle_id, key } => {
                 Self::delete_key_request(bundle_id, key)
             }
+            SecurityRequest::ListKeys { bundle_id } => {
+                Self::list_keys_request(bundle_id, reply_buffer)
+            }
+            SecurityRequest::SyncKeys { bundle_id } => Self::sync_keys_request(bundle_id),
+            SecurityRequest::ModelUploadBegin {
+                bundle_id,
+                model_id,
+                size,
+            } => Self::model_upload_begin_request(bundle_id, model_id, size, reply_buffer),
+            SecurityRequest::ModelUploadChunk {
+                bundle_id,
+                model_id,
+                offset,
+                data,
+            } => Self::model_upload_chunk_request(bundle_id, model_id, offset, data, reply_buffer),
+            SecurityRequest::ModelUploadCommit {
+                bundle_id,
+                model_id,
+                signature,
+            } => Self::model_upload_commit_request(bundle_id, model_id, signature),
+            SecurityRequest::ModelUploadAbort { bundle_id } => {
+                Self::model_upload_abort_request(bundle_id)
+            }
             SecurityRequest::CapScan => Self::capscan_request(),
             SecurityRequest::Test(count) => Self::test_request(count),
         }
@@ -199,7 +241,11 @@ impl SecurityInterfaceThread {
             .or(Err(SecurityRequestError::SerializeFailed))?;
         Ok(None)
     }
-    fn install_app_request(app_id: &str, mut pkg_contents: ObjDescBundle) -> SecurityResult {
+    fn install_app_request(
+        app_id: &str,
+        mut pkg_contents: ObjDescBundle,
+        signature: Option<&[u8]>,
+    ) -> SecurityResult {
         let _cleanup = Camkes::cleanup_request_cap();
         let recv_path = CAMKES.get_current_recv_path();
         Camkes::debug_assert_slot_cnode("install_application_request", &recv_path);
@@ -212,7 +258,7 @@ impl SecurityInterfaceThread {
         pkg_contents.cnode = container_slot.release();
 
         cantrip_security()
-            .install_app(app_id, &pkg_contents)
+            .install_app(app_id, &pkg_contents, signature)
             .map(|_| None)
     }
     fn install_model_request(
@@ -268,9 +314,13 @@ impl SecurityInterfaceThread {
             .or(Err(SecurityRequestError::SerializeFailed))?;
         Ok(None)
     }
-    fn load_application_request(bundle_id: &str, reply_buffer: &mut [u8]) -> SecurityResult {
+    fn load_application_request(
+        bundle_id: &str,
+        expected_hash: Option<&PackageHash>,
+        reply_buffer: &mut [u8],
+    ) -> SecurityResult {
         trace!("LOAD APPLICATION bundle_id {}", bundle_id);
-        let bundle_frames = cantrip_security().load_application(bundle_id)?;
+        let bundle_frames = cantrip_security().load_application(bundle_id, expected_hash)?;
         // TODO(sleffler): maybe rearrange to eliminate clone
         let _ = postcard::to_slice(
             &LoadApplicationResponse {
@@ -299,6 +349,22 @@ impl SecurityInterfaceThread {
         trace!("LOAD MODEL -> {}", model_frames);
         Ok(Some(model_frames.cnode))
     }
+    fn load_shared_model_request(
+        bundle_id: &str,
+        model_id: &str,
+        reply_buffer: &mut [u8],
+    ) -> SecurityResult {
+        let model_frames = cantrip_security().load_shared_model(bundle_id, model_id)?;
+        let _ = postcard::to_slice(
+            &LoadApplicationResponse {
+                bundle_frames: model_frames.clone(),
+            },
+            reply_buffer,
+        )
+        .or(Err(SecurityRequestError::SerializeFailed))?;
+        trace!("LOAD SHARED MODEL -> {}", model_frames);
+        Ok(Some(model_frames.cnode))
+    }
     fn read_key_request(bundle_id: &str, key: &str, reply_buffer: &mut [u8]) -> SecurityResult {
         let _cleanup = Camkes::cleanup_request_cap();
         trace!("READ KEY bundle_id {} key {}", bundle_id, key);
@@ -319,6 +385,75 @@ impl SecurityInterfaceThread {
         trace!("DELETE KEY bundle_id {} key {}", bundle_id, key);
         cantrip_security().delete_key(bundle_id, key).map(|_| None)
     }
+    fn list_keys_request(bundle_id: &str, reply_buffer: &mut [u8]) -> SecurityResult {
+        let _cleanup = Camkes::cleanup_request_cap();
+        trace!("LIST KEYS bundle_id {}", bundle_id);
+        let keys = cantrip_security().list_keys(bundle_id)?;
+        let _ = postcard::to_slice(&ListKeysResponse { keys }, reply_buffer)
+            .or(Err(SecurityRequestError::SerializeFailed))?;
+        Ok(None)
+    }
+    fn sync_keys_request(bundle_id: &str) -> SecurityResult {
+        let _cleanup = Camkes::cleanup_request_cap();
+        trace!("SYNC KEYS bundle_id {}", bundle_id);
+        cantrip_security().sync_keys(bundle_id).map(|_| None)
+    }
+    fn model_upload_begin_request(
+        bundle_id: &str,
+        model_id: &str,
+        size: usize,
+        reply_buffer: &mut [u8],
+    ) -> SecurityResult {
+        let _cleanup = Camkes::cleanup_request_cap();
+        trace!(
+            "MODEL UPLOAD BEGIN bundle_id {} model_id {} size {}",
+            bundle_id,
+            model_id,
+            size
+        );
+        let received = cantrip_security().model_upload_begin(bundle_id, model_id, size)?;
+        let _ = postcard::to_slice(&ModelUploadResponse { received }, reply_buffer)
+            .or(Err(SecurityRequestError::SerializeFailed))?;
+        Ok(None)
+    }
+    fn model_upload_chunk_request(
+        bundle_id: &str,
+        model_id: &str,
+        offset: usize,
+        data: &[u8],
+        reply_buffer: &mut [u8],
+    ) -> SecurityResult {
+        let _cleanup = Camkes::cleanup_request_cap();
+        trace!(
+            "MODEL UPLOAD CHUNK bundle_id {} model_id {} offset {} len {}",
+            bundle_id,
+            model_id,
+            offset,
+            data.len()
+        );
+        let received = cantrip_security().model_upload_chunk(bundle_id, model_id, offset, data)?;
+        let _ = postcard::to_slice(&ModelUploadResponse { received }, reply_buffer)
+            .or(Err(SecurityRequestError::SerializeFailed))?;
+        Ok(None)
+    }
+    fn model_upload_commit_request(
+        bundle_id: &str,
+        model_id: &str,
+        signature: Option<&[u8]>,
+    ) -> SecurityResult {
+        let _cleanup = Camkes::cleanup_request_cap();
+        trace!("MODEL UPLOAD COMMIT bundle_id {} model_id {}", bundle_id, model_id);
+        cantrip_security()
+            .model_upload_commit(bundle_id, model_id, signature)
+            .map(|_| None)
+    }
+    fn model_upload_abort_request(bundle_id: &str) -> SecurityResult {
+        let _cleanup = Camkes::cleanup_request_cap();
+        trace!("MODEL UPLOAD ABORT bundle_id {}", bundle_id);
+        cantrip_security()
+            .model_upload_abort(bundle_id)
+            .map(|_| None)
+    }
     fn capscan_request() -> SecurityResult {
         let _cleanup = Camkes::cleanup_request_cap();
         let _ = Camkes::capscan();
@@ -14,30 +14,34 @@
 
 //! Cantrip OS security coordinator fake manager
 
+use crate::keystore::KeyStore;
 use crate::BundleData;
 use crate::SecurityManagerInterface;
 use alloc::string::{String, ToString};
 use cantrip_security_interface::*;
-use cpio::CpioNewcReader;
+use cpio_scan::{find_entry, scan_archive, MalformedEntry};
 use hashbrown::HashMap;
-use log::error;
-
-const CAPACITY_KEYS: usize = 2; // Per-bundle HashMap of key-values
 
 extern "Rust" {
     fn get_cpio_archive() -> &'static [u8]; // CPIO archive of built-in files
 }
 
 struct FakeBundleData {
-    keys: HashMap<String, KeyValueData>,
+    keys: KeyStore,
 }
 impl FakeBundleData {
     fn new() -> Self {
         Self {
-            keys: HashMap::with_capacity(CAPACITY_KEYS),
+            keys: KeyStore::new(),
         }
     }
 }
+// A malformed builtin archive is reported as ArchiveInvalid; the scan
+// logs the offset of the bad entry.
+fn archive_invalid(_err: MalformedEntry) -> SecurityRequestError {
+    SecurityRequestError::ArchiveInvalid
+}
+
 pub struct FakeSecurityManager {
     bundles: HashMap<String, FakeBundleData>,
 }
@@ -82,34 +86,20 @@ impl SecurityManagerInterface for FakeSecurityManager {
     // Returns an array of bundle id's from the builtin archive.
     fn get_builtins(&self) -> Result<BundleIdArray, SecurityRequestError> {
         let mut builtins = BundleIdArray::new();
-        for e in CpioNewcReader::new(unsafe { get_cpio_archive() }) {
-            match e {
-                Err(err) => {
-                    error!("cpio read err {:?}", err);
-                    return Err(SecurityRequestError::GetPackagesFailed);
-                }
-                Ok(entry) => builtins.push(entry.name.to_string()),
-            }
-        }
+        scan_archive(unsafe { get_cpio_archive() }, |name, _| -> Option<()> {
+            builtins.push(name.to_string());
+            None
+        })
+        .map_err(archive_invalid)?;
         Ok(builtins)
     }
 
     // Returns a bundle backed by builtin data.
     fn lookup_builtin(&self, filename: &str) -> Result<BundleData, SecurityRequestError> {
-        for e in CpioNewcReader::new(unsafe { get_cpio_archive() }) {
-            match e {
-                Err(err) => {
-                    error!("cpio read err {:?}", err);
-                    return Err(SecurityRequestError::BundleNotFound);
-                }
-                Ok(entry) => {
-                    if entry.name == filename {
-                        return Ok(BundleData::new_from_flash(entry.data));
-                    }
-                }
-            }
-        }
-        Err(SecurityRequestError::BundleNotFound)
+        find_entry(unsafe { get_cpio_archive() }, filename)
+            .map_err(archive_invalid)?
+            .map(BundleData::new_from_flash)
+            .ok_or(SecurityRequestError::BundleNotFound)
     }
 
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
@@ -128,11 +118,7 @@ impl SecurityManagerInterface for FakeSecurityManager {
 
     // NB: key-value ops require a load'd application so only do get_bundle
     fn read_key(&self, bundle_id: &str, key: &str) -> Result<&KeyValueData, SecurityRequestError> {
-        let bundle = self.get_bundle(bundle_id)?;
-        bundle
-            .keys
-            .get(key)
-            .ok_or(SecurityRequestError::KeyNotFound)
+        self.get_bundle(bundle_id)?.keys.read(key)
     }
     fn write_key(
         &mut self,
@@ -140,19 +126,33 @@ impl SecurityManagerInterface for FakeSecurityManager {
         key: &str,
         value: &[u8],
     ) -> Result<(), SecurityRequestError> {
-        let bundle = self.get_bundle_mut(bundle_id)?;
-        let mut keyval = [0u8; KEY_VALUE_DATA_SIZE];
-        keyval[..value.len()].copy_from_slice(value);
-        let _ = bundle.keys.insert(key.to_string(), keyval);
-        Ok(())
+        self.get_bundle_mut(bundle_id)?.keys.write(key, value)
     }
     fn delete_key(&mut self, bundle_id: &str, key: &str) -> Result<(), SecurityRequestError> {
-        let bundle = self.get_bundle_mut(bundle_id)?;
-        // TODO(sleffler): error if no entry?
-        let _ = bundle.keys.remove(key);
-        Ok(())
+        self.get_bundle_mut(bundle_id)?.keys.delete(key)
+    }
+    fn list_keys(&self, bundle_id: &str) -> Result<KeyNameArray, SecurityRequestError> {
+        Ok(self.get_bundle(bundle_id)?.keys.list())
+    }
+    fn sync_keys(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
+        // NB: key-value stores are memory-only
+        let _ = self.get_bundle(bundle_id)?;
+        Err(SecurityRequestError::NoPlatformSupport)
     }
     fn test(&self, _count: usize) -> Result<(), SecurityRequestError> {
         Err(SecurityRequestError::TestFailed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_invalid_error() {
+        assert_eq!(
+            archive_invalid(MalformedEntry { offset: 128 }),
+            SecurityRequestError::ArchiveInvalid
+        );
+    }
+}
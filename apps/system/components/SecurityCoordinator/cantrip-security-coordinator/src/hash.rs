@@ -0,0 +1,123 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Package hash verification.
+//!
+//! A caller may supply the expected SHA-256 of a package when it is
+//! loaded. The hash is computed as the package is copied, a page at a
+//! time, so the whole image never needs to be resident.
+
+use cantrip_security_interface::PackageHash;
+use cantrip_security_interface::SecurityRequestError;
+use core::cmp;
+use sha2::Digest;
+use sha2::Sha256;
+
+pub struct PackageHasher {
+    hasher: Sha256,
+    expected: PackageHash,
+    remaining: usize, // Package bytes not yet hashed
+}
+impl PackageHasher {
+    // Starts hashing a package of |len| bytes that should hash to |expected|.
+    pub fn new(expected: &PackageHash, len: usize) -> Self {
+        Self {
+            hasher: Sha256::new(),
+            expected: *expected,
+            remaining: len,
+        }
+    }
+
+    // Adds the next chunk of package data; data past the end of the
+    // package (e.g. the tail of the last page) are ignored.
+    pub fn update(&mut self, chunk: &[u8]) {
+        let count = cmp::min(self.remaining, chunk.len());
+        self.hasher.update(&chunk[..count]);
+        self.remaining -= count;
+    }
+
+    // Checks the package data against the expected hash.
+    pub fn verify(self) -> Result<(), SecurityRequestError> {
+        if self.remaining == 0 && self.hasher.finalize()[..] == self.expected[..] {
+            Ok(())
+        } else {
+            Err(SecurityRequestError::HashMismatch)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    const PAGE_SIZE: usize = 4096;
+
+    fn package() -> Vec<u8> { (0..3 * PAGE_SIZE + 123).map(|x| x as u8).collect() }
+
+    fn sha256(data: &[u8]) -> PackageHash { Sha256::digest(data).into() }
+
+    // Verifies |len| bytes of |data| a page at a time like the upload path.
+    fn verify(expected: &PackageHash, len: usize, data: &[u8]) -> Result<(), SecurityRequestError> {
+        let mut hasher = PackageHasher::new(expected, len);
+        for chunk in data.chunks(PAGE_SIZE) {
+            hasher.update(chunk);
+        }
+        hasher.verify()
+    }
+
+    #[test]
+    fn known_hash() {
+        let expected = [
+            0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae,
+            0x22, 0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61,
+            0xf2, 0x00, 0x15, 0xad,
+        ];
+        assert_eq!(verify(&expected, 3, b"abc"), Ok(()));
+    }
+
+    #[test]
+    fn matching_package() {
+        let data = package();
+        assert_eq!(verify(&sha256(&data), data.len(), &data), Ok(()));
+    }
+
+    #[test]
+    fn page_padding_ignored() {
+        let data = package();
+        let mut padded = data.clone();
+        padded.resize(4 * PAGE_SIZE, 0xff);
+        assert_eq!(verify(&sha256(&data), data.len(), &padded), Ok(()));
+    }
+
+    #[test]
+    fn tampered_package() {
+        let mut data = package();
+        let expected = sha256(&data);
+        data[PAGE_SIZE + 1] ^= 1;
+        assert_eq!(
+            verify(&expected, data.len(), &data),
+            Err(SecurityRequestError::HashMismatch)
+        );
+    }
+
+    #[test]
+    fn truncated_package() {
+        let data = package();
+        assert_eq!(
+            verify(&sha256(&data), data.len(), &data[..2 * PAGE_SIZE]),
+            Err(SecurityRequestError::HashMismatch)
+        );
+    }
+}
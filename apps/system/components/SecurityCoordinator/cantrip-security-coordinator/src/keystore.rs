@@ -0,0 +1,117 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-bundle key-value store shared by the back-end implementations.
+
+use alloc::string::{String, ToString};
+use cantrip_security_interface::*;
+use hashbrown::HashMap;
+
+const CAPACITY_KEYS: usize = 2; // Per-bundle HashMap of key-values
+
+pub struct KeyStore {
+    keys: HashMap<String, KeyValueData>,
+}
+impl Default for KeyStore {
+    fn default() -> Self { Self::new() }
+}
+impl KeyStore {
+    pub fn new() -> Self {
+        Self {
+            keys: HashMap::with_capacity(CAPACITY_KEYS),
+        }
+    }
+
+    pub fn read(&self, key: &str) -> Result<&KeyValueData, SecurityRequestError> {
+        self.keys.get(key).ok_or(SecurityRequestError::KeyNotFound)
+    }
+
+    // Stores |value| zero-padded to KEY_VALUE_DATA_SIZE under |key|.
+    pub fn write(&mut self, key: &str, value: &[u8]) -> Result<(), SecurityRequestError> {
+        if value.len() > KEY_VALUE_DATA_SIZE {
+            return Err(SecurityRequestError::ValueInvalid);
+        }
+        let mut keyval = [0u8; KEY_VALUE_DATA_SIZE];
+        keyval[..value.len()].copy_from_slice(value);
+        let _ = self.keys.insert(key.to_string(), keyval);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, key: &str) -> Result<(), SecurityRequestError> {
+        // TODO(sleffler): error if no entry?
+        let _ = self.keys.remove(key);
+        Ok(())
+    }
+
+    // Returns the key names in sorted order.
+    pub fn list(&self) -> KeyNameArray {
+        let mut result: KeyNameArray = self.keys.keys().cloned().collect();
+        result.sort();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(s: &str) -> KeyValueData {
+        let mut keyval = [0u8; KEY_VALUE_DATA_SIZE];
+        keyval[..s.len()].copy_from_slice(s.as_bytes());
+        keyval
+    }
+
+    #[test]
+    fn set_get_delete() {
+        let mut store = KeyStore::new();
+        assert_eq!(store.read("foo"), Err(SecurityRequestError::KeyNotFound));
+
+        assert_eq!(store.write("foo", b"bar"), Ok(()));
+        assert_eq!(store.read("foo"), Ok(&value("bar")));
+
+        // Overwrite does not leave stale bytes behind.
+        assert_eq!(store.write("foo", b"b"), Ok(()));
+        assert_eq!(store.read("foo"), Ok(&value("b")));
+
+        assert_eq!(store.delete("foo"), Ok(()));
+        assert_eq!(store.read("foo"), Err(SecurityRequestError::KeyNotFound));
+        // Deleting a missing key is not an error.
+        assert_eq!(store.delete("foo"), Ok(()));
+    }
+
+    #[test]
+    fn value_size() {
+        let mut store = KeyStore::new();
+        let max = [0xa5u8; KEY_VALUE_DATA_SIZE];
+        assert_eq!(store.write("max", &max), Ok(()));
+        assert_eq!(store.read("max"), Ok(&max));
+        assert_eq!(
+            store.write("big", &[0u8; KEY_VALUE_DATA_SIZE + 1]),
+            Err(SecurityRequestError::ValueInvalid)
+        );
+        assert_eq!(store.read("big"), Err(SecurityRequestError::KeyNotFound));
+    }
+
+    #[test]
+    fn list() {
+        let mut store = KeyStore::new();
+        assert!(store.list().is_empty());
+        for key in ["zeta", "alpha", "mu"] {
+            assert_eq!(store.write(key, key.as_bytes()), Ok(()));
+        }
+        assert_eq!(store.list(), ["alpha", "mu", "zeta"]);
+        assert_eq!(store.delete("mu"), Ok(()));
+        assert_eq!(store.list(), ["alpha", "zeta"]);
+    }
+}
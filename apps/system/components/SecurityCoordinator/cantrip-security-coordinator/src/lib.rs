@@ -21,14 +21,26 @@
 
 extern crate alloc;
 use alloc::string::{String, ToString};
-use cantrip_memory_interface::cantrip_cnode_alloc;
+use alloc::vec::Vec;
+use cantrip_memory_interface::cantrip_cnode_alloc_for;
+use cantrip_memory_interface::cantrip_cnode_free;
+#[cfg(not(test))]
 use cantrip_memory_interface::cantrip_object_free_in_cnode;
+use cantrip_memory_interface::cantrip_object_free_toplevel;
+use cantrip_memory_interface::cnode_depth_for;
 use cantrip_memory_interface::ObjDescBundle;
 use cantrip_os_common::copyregion::CopyRegion;
+use cantrip_os_common::cspace_slot::read_only_rights;
 use cantrip_os_common::cspace_slot::CSpaceSlot;
+use cantrip_os_common::sel4_sys;
 use cantrip_security_interface::*;
+use core::cmp;
+use core::str;
 use hashbrown::HashMap;
 
+use sel4_sys::seL4_CNode_Copy;
+use sel4_sys::seL4_CPtr;
+
 #[cfg(all(feature = "fake", feature = "sec"))]
 compile_error!("features \"fake\" and \"sec\" are mutually exclusive");
 
@@ -37,10 +49,33 @@ compile_error!("features \"fake\" and \"sec\" are mutually exclusive");
 mod manager;
 pub use manager::CantripSecurityManager;
 
+mod hash;
+use hash::PackageHasher;
+
+mod keystore;
+
+mod model_upload;
+use model_upload::ModelUploads;
+
+mod shared;
+use shared::SharedModels;
+
 mod upload;
 use upload::*;
 
+#[cfg(feature = "verify_signatures")]
+mod signature;
+#[cfg(feature = "verify_signatures")]
+use signature::*;
+
+// Provisioned public key used to check package signatures.
+#[cfg(feature = "verify_signatures")]
+const SIGNING_PUBLIC_KEY: &[u8; PUBLIC_KEY_LEN] =
+    include_bytes!(env!("CANTRIP_SIGNING_PUBLIC_KEY"));
+
 pub const CAPACITY_BUNDLES: usize = 10; // HashMap of bundles
+pub const MAX_BUNDLES: usize = 32; // Dynamically installed bundles
+pub const MAX_UPLOAD_BYTES: usize = 4 * 1024 * 1024; // Model uploads per app
 
 const APP_SUFFIX: &str = ".app";
 const MODEL_SUFFIX: &str = ".model";
@@ -66,6 +101,10 @@ pub trait SecurityManagerInterface {
         value: &[u8],
     ) -> Result<(), SecurityRequestError>;
     fn delete_key(&mut self, bundle_id: &str, key: &str) -> Result<(), SecurityRequestError>;
+    fn list_keys(&self, bundle_id: &str) -> Result<KeyNameArray, SecurityRequestError>;
+    // Persists the key-value store; back-ends without durable
+    // storage return NoPlatformSupport.
+    fn sync_keys(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError>;
     fn test(&self, count: usize) -> Result<(), SecurityRequestError>;
 }
 
@@ -75,14 +114,18 @@ pub trait SecurityManagerInterface {
 /// the MemoryManager.
 #[allow(dead_code)]
 enum PkgContents {
-    Flash(&'static [u8]),   // Data resides in flash
-    Sec(u32),               // Data resides on Security Core (SEC)
+    Flash(&'static [u8]), // Data resides in flash
+    #[cfg(feature = "sec")]
+    Sec(mailbox_driver::FileId), // Data resides on Security Core (SEC)
     Dynamic(ObjDescBundle), // Data resides in dynamically allocated memory
 }
 
 pub struct BundleData {
     pkg_contents: PkgContents,
     pkg_size: usize,
+    manifest: Vec<u8>, // Leading manifest (if any), not part of the image
+    #[cfg(feature = "verify_signatures")]
+    signature: Option<[u8; SIGNATURE_LEN]>, // Detached package signature
 }
 #[allow(dead_code)]
 impl BundleData {
@@ -91,14 +134,21 @@ impl BundleData {
         Self {
             pkg_contents: PkgContents::Dynamic(pkg_contents.clone()),
             pkg_size: pkg_contents.size_bytes(),
+            manifest: Vec::new(),
+            #[cfg(feature = "verify_signatures")]
+            signature: None,
         }
     }
 
     // Returns a bundle for a builtin package that resided on the SEC.
-    fn new_from_sec(fid: u32, size_bytes: usize) -> Self {
+    #[cfg(feature = "sec")]
+    fn new_from_sec(fid: mailbox_driver::FileId, size_bytes: usize) -> Self {
         Self {
             pkg_contents: PkgContents::Sec(fid),
             pkg_size: size_bytes,
+            manifest: Vec::new(),
+            #[cfg(feature = "verify_signatures")]
+            signature: None,
         }
     }
 
@@ -107,44 +157,128 @@ impl BundleData {
         Self {
             pkg_contents: PkgContents::Flash(slice),
             pkg_size: slice.len(),
+            manifest: Vec::new(),
+            #[cfg(feature = "verify_signatures")]
+            signature: None,
         }
     }
 
     // Returns a copy of the package contents suitable for sending
     // to another thread. The data are copied to newly allocated frames
     // and the frames are aggregated in a CNode ready to attach to
-    // an IPC message.
-    fn deep_copy(&self) -> Result<ObjDescBundle, UploadError> {
-        let mut upload = match &self.pkg_contents {
-            PkgContents::Flash(data) => upload_slice(data),
-            PkgContents::Sec(fid) => upload_sec(*fid, self.pkg_size),
-            PkgContents::Dynamic(bundle) => upload_obj_bundle(bundle),
-        }?;
-
-        // XXX move to Upload
-        // Collect the frames in a top-level CNode.
-        let cnode_depth = upload.frames().count_log2();
-        let cnode = cantrip_cnode_alloc(cnode_depth).or(Err(UploadError::MallocFailed))?;
-        upload
-            .frames_mut()
-            .move_objects_from_toplevel(cnode.objs[0].cptr, cnode_depth as u8)
-            .or(Err(UploadError::MoveFailed))?;
+    // an IPC message. If |expected_hash| is given the copy must match.
+    fn deep_copy(&self, expected_hash: Option<&PackageHash>) -> Result<ObjDescBundle, UploadError> {
+        let mut upload = self.upload(expected_hash)?;
+        if let Err(e) = upload.check_hash() {
+            let _ = cantrip_object_free_toplevel(upload.frames());
+            return Err(e);
+        }
+
+        // NB: check the copy rather than the source so what is verified
+        //     is what gets instantiated.
+        #[cfg(feature = "verify_signatures")]
+        if let Err(e) = self.verify(&upload) {
+            let _ = cantrip_object_free_toplevel(upload.frames());
+            return Err(e);
+        }
+
+        move_to_cnode(upload.frames_mut())?;
         Ok(upload.frames().clone())
     }
+
+    // Returns a copy of the package contents in an Upload container.
+    // If |expected_hash| is given the contents are hashed as they are
+    // copied; use Upload::check_hash for the result.
+    fn upload(&self, expected_hash: Option<&PackageHash>) -> Result<Upload, UploadError> {
+        let hasher = expected_hash.map(|hash| PackageHasher::new(hash, self.pkg_size));
+        match &self.pkg_contents {
+            PkgContents::Flash(data) => upload_slice(data, hasher),
+            #[cfg(feature = "sec")]
+            PkgContents::Sec(fid) => upload_sec(*fid, self.pkg_size, hasher),
+            PkgContents::Dynamic(bundle) => upload_obj_bundle(bundle, &self.manifest, hasher),
+        }
+    }
+
+    // Checks |upload| holds package contents signed by the provisioned key.
+    #[cfg(feature = "verify_signatures")]
+    fn verify(&self, upload: &Upload) -> Result<(), UploadError> {
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or(UploadError::SignatureInvalid)?;
+        let len = self.pkg_size - self.manifest.len();
+        verify_frames(&self.manifest, upload.frames(), len, signature)
+    }
 }
 impl Drop for BundleData {
     fn drop(&mut self) {
         if let PkgContents::Dynamic(bundle) = &self.pkg_contents {
-            let _ = cantrip_object_free_in_cnode(bundle);
+            free_pkg_frames(bundle);
         }
     }
 }
 
+// Returns the frames of a dynamically loaded package (or a shared
+// model master) to the MemoryManager.
+#[cfg(not(test))]
+fn free_pkg_frames(frames: &ObjDescBundle) { let _ = cantrip_object_free_in_cnode(frames); }
+
+// Unit tests run without a MemoryManager; record the CNode instead so
+// tests can check the frames were released.
+#[cfg(test)]
+fn free_pkg_frames(frames: &ObjDescBundle) {
+    tests::FREED_CNODES.with(|freed| freed.borrow_mut().push(frames.cnode));
+}
+
+// Moves top-level |frames| to a newly allocated CNode ready to attach
+// to an IPC message.
+#[cfg(not(test))]
+fn move_to_cnode(frames: &mut ObjDescBundle) -> Result<(), UploadError> {
+    let frame_count = frames.count();
+    let cnode = cantrip_cnode_alloc_for(frame_count).or(Err(UploadError::MallocFailed))?;
+    frames
+        .move_objects_from_toplevel(cnode.objs[0].cptr, cnode_depth_for(frame_count) as u8)
+        .or(Err(UploadError::MoveFailed))
+}
+
+// A model upload collects frames in the top-level CNode as chunks arrive.
+#[cfg(not(test))]
+fn new_upload_frames() -> ObjDescBundle { toplevel_frames() }
+
+// Writes |data| at |offset| of the model upload held in |frames|.
+#[cfg(not(test))]
+fn write_upload_frames(
+    frames: &mut ObjDescBundle,
+    offset: usize,
+    data: &[u8],
+) -> Result<(), UploadError> {
+    let mut region = unsafe { CopyRegion::new(get_deep_copy_dest_mut()) };
+    write_frames(frames, &mut region, offset, data)
+}
+
+// Returns the frames of an abandoned model upload to the MemoryManager.
+#[cfg(not(test))]
+fn free_upload_frames(frames: ObjDescBundle) { let _ = cantrip_object_free_toplevel(&frames); }
+
+// Unit tests have no frames to fill; the contents of model uploads are
+// kept by the tests and frames are only counted.
+#[cfg(test)]
+use tests::{free_upload_frames, move_to_cnode, new_upload_frames, write_upload_frames};
+
 // Returns a copy (including seL4 objects) of |src| in an Upload container.
-fn upload_obj_bundle(src: &ObjDescBundle) -> Result<Upload, UploadError> {
+// The leading |manifest| bytes of |src| are hashed but not copied so the
+// loader sees only the image.
+fn upload_obj_bundle(
+    src: &ObjDescBundle,
+    manifest: &[u8],
+    hasher: Option<PackageHasher>,
+) -> Result<Upload, UploadError> {
     // Dest is an upload object that allocates a page at-a-time so
     // the MemoryManager doesn't have to handle a huge memory request.
     let mut dest = Upload::new(unsafe { get_deep_copy_dest_mut() });
+    dest.hash_with(hasher);
+    dest.hash(manifest);
+    let mut skip = manifest.len();
 
     // Src top-level slot & copy region
     let src_slot = CSpaceSlot::new();
@@ -153,13 +287,16 @@ fn upload_obj_bundle(src: &ObjDescBundle) -> Result<Upload, UploadError> {
     for src_cptr in src.cptr_iter() {
         // Map src frame and copy data (allocating memory as needed)..
         src_slot
-            .dup_to(src.cnode, src_cptr, src.depth)
+            .dup_ro_to(src.cnode, src_cptr, src.depth)
             .or(Err(UploadError::MoveFailed))?;
         src_region
             .map(src_slot.slot)
             .or(Err(UploadError::PageMap))?;
 
-        dest.write(src_region.as_ref())?;
+        let data = src_region.as_ref();
+        let skipped = cmp::min(skip, data.len());
+        dest.write(&data[skipped..])?;
+        skip -= skipped;
 
         // Unmap & clear top-level src slot required for mapping.
         src_region.unmap().or(Err(UploadError::PageUnmap))?;
@@ -171,39 +308,174 @@ fn upload_obj_bundle(src: &ObjDescBundle) -> Result<Upload, UploadError> {
 
 #[cfg(feature = "sec")]
 // Returns a copy (including seL4 objects) of |fid| in an Upload container.
-fn upload_sec(fid: u32, size_bytes: usize) -> Result<Upload<'static>, UploadError> {
+fn upload_sec(
+    fid: mailbox_driver::FileId,
+    size_bytes: usize,
+    hasher: Option<PackageHasher>,
+) -> Result<Upload<'static>, UploadError> {
     // Dest is an upload object that allocates a page at-a-time so
     // the MemoryManager doesn't have to handle a huge memory request.
     let mut dest = Upload::new(unsafe { get_deep_copy_dest_mut() });
+    dest.hash_with(hasher);
 
     let mut off: usize = 0;
     while off < size_bytes {
         // Fetch the next page of the file.
-        let frame = dest.expand_and_map()?; // XXX no need to map
-        mailbox_driver::mbox_get_file_page(fid, off as u32, frame.cptr)
+        let frame = dest.expand_and_map()?; // NB: mapped for hashing
+        mailbox_driver::mbox_get_file_page(fid, mailbox_driver::FileOffset(off as u32), frame.cptr)
             .or(Err(UploadError::ReadFailed))?;
         off += frame.size_bytes().unwrap();
+        dest.hash_mapped_frame();
         dest.unmap_current_frame()?;
     }
     dest.finish();
     Ok(dest)
 }
 
-#[cfg(not(feature = "sec"))]
-fn upload_sec(_fid: u32, _size_bytes: usize) -> Result<Upload<'static>, UploadError> {
-    Err(UploadError::ReadFailed)
-}
-
 // Returns a copy (including seL4 objects) of |src| in an Upload container.
-fn upload_slice(src: &[u8]) -> Result<Upload, UploadError> {
+fn upload_slice(src: &[u8], hasher: Option<PackageHasher>) -> Result<Upload, UploadError> {
     // Dest is an upload object that allocates a page at-a-time so
     // the MemoryManager doesn't have to handle a huge memory request.
     let mut dest = Upload::new(unsafe { get_deep_copy_dest_mut() });
+    dest.hash_with(hasher);
     dest.write(src)?;
     dest.finish();
     Ok(dest)
 }
 
+// Passes the first |len| bytes held in top-level |frames| to |f| a
+// page at a time.
+fn read_frames(
+    frames: &ObjDescBundle,
+    len: usize,
+    mut f: impl FnMut(&[u8]),
+) -> Result<(), UploadError> {
+    let mut region = unsafe { CopyRegion::new(get_deep_copy_src_mut()) };
+    let mut remaining = len;
+    for cptr in frames.cptr_iter() {
+        if remaining == 0 {
+            break;
+        }
+        region.map(cptr).or(Err(UploadError::PageMap))?;
+        let count = cmp::min(remaining, region.mapped_bytes());
+        f(&region.as_ref()[..count]);
+        remaining -= count;
+        region.unmap().or(Err(UploadError::PageUnmap))?;
+    }
+    if remaining != 0 {
+        return Err(UploadError::ReadFailed);
+    }
+    Ok(())
+}
+
+// Returns the manifest (header and text) at the front of the package
+// held in |frames|; a package without a manifest returns an empty Vec.
+#[cfg(not(test))]
+fn read_leading_manifest(frames: &ObjDescBundle) -> Result<Vec<u8>, UploadError> {
+    // Frames are mapped through a top-level slot like upload_obj_bundle.
+    let slot = CSpaceSlot::new();
+    let mut region = unsafe { CopyRegion::new(get_deep_copy_src_mut()) };
+    leading_manifest_with(frames, |cptr, f| {
+        slot.dup_ro_to(frames.cnode, cptr, frames.depth)
+            .or(Err(UploadError::MoveFailed))?;
+        region.map(slot.slot).or(Err(UploadError::PageMap))?;
+        f(region.as_ref());
+        region.unmap().or(Err(UploadError::PageUnmap))?;
+        slot.delete().or(Err(UploadError::MoveFailed))
+    })
+}
+#[cfg(test)]
+fn read_leading_manifest(frames: &ObjDescBundle) -> Result<Vec<u8>, UploadError> {
+    leading_manifest_with(frames, tests::read_page)
+}
+
+// Like read_leading_manifest but each frame's contents are passed to
+// the closure given to |read_page|.
+fn leading_manifest_with(
+    frames: &ObjDescBundle,
+    mut read_page: impl FnMut(seL4_CPtr, &mut dyn FnMut(&[u8])) -> Result<(), UploadError>,
+) -> Result<Vec<u8>, UploadError> {
+    let len = cmp::min(frames.size_bytes(), MANIFEST_HEADER_SIZE + MAX_MANIFEST_SIZE);
+    let mut data = Vec::with_capacity(len);
+    for cptr in frames.cptr_iter() {
+        if data.len() == len {
+            break;
+        }
+        read_page(cptr, &mut |page| {
+            let count = cmp::min(len - data.len(), page.len());
+            data.extend_from_slice(&page[..count]);
+        })?;
+    }
+    data.truncate(Manifest::leading_len(&data));
+    Ok(data)
+}
+
+// Checks |prefix| followed by the first |len| bytes held in top-level
+// |frames| were signed by the provisioned key.
+#[cfg(feature = "verify_signatures")]
+fn verify_frames(
+    prefix: &[u8],
+    frames: &ObjDescBundle,
+    len: usize,
+    signature: &[u8],
+) -> Result<(), UploadError> {
+    let mut verifier = PackageVerifier::new(SIGNING_PUBLIC_KEY, signature)
+        .or(Err(UploadError::SignatureInvalid))?;
+    verifier.update(prefix);
+    read_frames(frames, len, |chunk| verifier.update(chunk))?;
+    verifier.verify().or(Err(UploadError::SignatureInvalid))
+}
+
+// Returns read-only dups of the frames in |master| collected in a new
+// CNode; the frames are shared, only the CNode is allocated. The caller
+// releases the dups with cantrip_cnode_free.
+fn share_frames(master: &ObjDescBundle) -> Result<ObjDescBundle, SecurityRequestError> {
+    let frame_count = master.count();
+    let cnode =
+        cantrip_cnode_alloc_for(frame_count).or(Err(SecurityRequestError::LoadModelFailed))?;
+    let mut shared = master.clone();
+    shared.cnode = cnode.objs[0].cptr;
+    shared.depth = cnode_depth_for(frame_count) as u8;
+    for cptr in master.cptr_iter() {
+        let result = unsafe {
+            seL4_CNode_Copy(
+                /*dest_root=*/ shared.cnode,
+                /*dest_index=*/ cptr,
+                /*dest_depth=*/ shared.depth,
+                /*src_root=*/ master.cnode,
+                /*src_index=*/ cptr,
+                /*src_depth=*/ master.depth,
+                read_only_rights(),
+            )
+        };
+        if result.is_err() {
+            // NB: deleting the CNode deletes any dups already made.
+            let _ = cantrip_cnode_free(&shared);
+            return Err(SecurityRequestError::LoadModelFailed);
+        }
+    }
+    Ok(shared)
+}
+
+// Maps a deep_copy failure to |err|; signature and hash failures are
+// passed through so callers can tell an untrusted package from a failed copy.
+fn deep_copy_err(e: UploadError, err: SecurityRequestError) -> SecurityRequestError {
+    match e {
+        UploadError::SignatureInvalid => SecurityRequestError::SignatureInvalid,
+        UploadError::HashMismatch => SecurityRequestError::HashMismatch,
+        _ => err,
+    }
+}
+
+// Treats a missing bundle as success for requests whose goal is that
+// the bundle be absent.
+fn absent_ok(result: Result<(), SecurityRequestError>) -> Result<(), SecurityRequestError> {
+    match result {
+        Err(SecurityRequestError::BundleNotFound) => Ok(()),
+        result => result,
+    }
+}
+
 // Returns |key| or |key|+|suffix| if |key| does not end with |suffix|.
 fn promote_key(key: &str, suffixes: &[&str]) -> String {
     for suf in suffixes {
@@ -221,6 +493,8 @@ fn promote_key(key: &str, suffixes: &[&str]) -> String {
 pub struct CantripSecurityCoordinator {
     manager: CantripSecurityManager,
     bundles: HashMap<String, BundleData>,
+    shared_models: SharedModels<ObjDescBundle>,
+    model_uploads: ModelUploads<ObjDescBundle>, // Top-level frames
 }
 impl Default for CantripSecurityCoordinator {
     fn default() -> Self { Self::new() }
@@ -232,6 +506,8 @@ impl CantripSecurityCoordinator {
         Self {
             manager: CantripSecurityManager::new(),
             bundles: HashMap::with_capacity(CAPACITY_BUNDLES),
+            shared_models: SharedModels::new(),
+            model_uploads: ModelUploads::new(),
         }
     }
 
@@ -267,20 +543,184 @@ impl CantripSecurityCoordinator {
         self.manager.lookup_builtin(filename)
     }
 
+    // Returns a bundle backed by builtin data that is ready to load;
+    // this includes any detached signature when signatures are checked.
+    fn load_builtin(&self, filename: &str) -> Result<BundleData, SecurityRequestError> {
+        #[allow(unused_mut)]
+        let mut bundle = self.get_bundle_from_builtins(filename)?;
+        #[cfg(feature = "verify_signatures")]
+        {
+            bundle.signature = self.lookup_signature(filename);
+        }
+        Ok(bundle)
+    }
+
+    // Returns the detached signature for builtin |filename|, if any.
+    #[cfg(feature = "verify_signatures")]
+    fn lookup_signature(&self, filename: &str) -> Option<[u8; SIGNATURE_LEN]> {
+        let sig_data = self
+            .get_bundle_from_builtins(&(filename.to_string() + SIGNATURE_SUFFIX))
+            .ok()?;
+        let upload = sig_data.upload(None).ok()?;
+        let mut signature = [0u8; SIGNATURE_LEN];
+        let mut off = 0;
+        let result = read_frames(upload.frames(), SIGNATURE_LEN, |chunk| {
+            signature[off..off + chunk.len()].copy_from_slice(chunk);
+            off += chunk.len();
+        });
+        let _ = cantrip_object_free_toplevel(upload.frames());
+        result.ok().map(|_| signature)
+    }
+
+    // Returns the contents of the manifest file for builtin |filename|.
+    // A missing or oversized file is reported as UploadError::ReadFailed.
+    fn lookup_manifest(&self, filename: &str) -> Result<Vec<u8>, UploadError> {
+        let manifest_data = self
+            .get_bundle_from_builtins(&(filename.to_string() + MANIFEST_SUFFIX))
+            .or(Err(UploadError::ReadFailed))?;
+        if manifest_data.pkg_size > MAX_MANIFEST_SIZE {
+            return Err(UploadError::ReadFailed);
+        }
+        let upload = manifest_data.upload(None)?;
+        let mut text = Vec::with_capacity(manifest_data.pkg_size);
+        let result = read_frames(upload.frames(), manifest_data.pkg_size, |chunk| {
+            text.extend_from_slice(chunk)
+        });
+        let _ = cantrip_object_free_toplevel(upload.frames());
+        result.map(|_| text)
+    }
+
+    // Returns the number of dynamically installed bundles; builtins
+    // promoted to the hashmap are not counted as they are bounded by
+    // the contents of flash.
+    fn installed_count(&self) -> usize {
+        self.bundles
+            .values()
+            .filter(|bd| matches!(bd.pkg_contents, PkgContents::Dynamic(_)))
+            .count()
+    }
+
+    // Checks a bundle may be installed under |key|: it must not already
+    // be present and there must be room for another installed bundle.
+    fn check_install(&self, key: &str) -> Result<(), SecurityRequestError> {
+        if self.bundles.contains_key(key) {
+            return Err(SecurityRequestError::DeleteFirst);
+        }
+        if self.installed_count() >= MAX_BUNDLES {
+            return Err(SecurityRequestError::TooManyBundles);
+        }
+        Ok(())
+    }
+
+    // Returns the key under which |bundle_id|'s upload of |model_id| is
+    // installed. Uploads are private to the uploader so they cannot
+    // replace another app's model (or a builtin).
+    fn upload_key(bundle_id: &str, model_id: &str) -> String {
+        bundle_id.to_string() + "/" + model_id
+    }
+
+    // Checks |bundle_id| may upload |size| bytes of |model_id|: the name
+    // must not be that of a builtin and the upload, together with the
+    // bundle's other uploads (installed or in progress), must fit in
+    // MAX_UPLOAD_BYTES.
+    fn check_upload(
+        &self,
+        bundle_id: &str,
+        model_id: &str,
+        size: usize,
+    ) -> Result<(), SecurityRequestError> {
+        if self.get_bundle_from_builtins(model_id).is_ok() {
+            return Err(SecurityRequestError::DeleteFirst);
+        }
+        let key = Self::upload_key(bundle_id, model_id);
+        let prefix = Self::upload_key(bundle_id, "");
+        let installed: usize = self
+            .bundles
+            .iter()
+            .filter(|(k, _)| k.starts_with(&prefix) && **k != key)
+            .map(|(_, bd)| bd.pkg_size)
+            .sum();
+        let reserved = self.model_uploads.reserved(bundle_id, &key);
+        if installed.saturating_add(reserved).saturating_add(size) > MAX_UPLOAD_BYTES {
+            return Err(SecurityRequestError::UploadQuotaExceeded);
+        }
+        Ok(())
+    }
+
     // Remove any entry for |bundle_id|.
+    // Returns the key under which models shared by |bundle_id| are kept.
+    // Like find_key but the bundle need not be registered (e.g. a builtin
+    // that was never promoted) so uninstall finds the same key as load.
+    fn shared_key(&self, bundle_id: &str) -> String {
+        self.find_key(bundle_id)
+            .unwrap_or_else(|_| bundle_id.to_string())
+    }
+
     fn remove_bundle(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
         self.find_key(bundle_id)
             .map(|key| self.bundles.remove(&key))
             .map(|_| ())
     }
 
-    fn load_app_bundle(&mut self, bundle_id: &str) -> Result<ObjDescBundle, SecurityRequestError> {
+    // Registers |bundle_data| under |key|. An existing bundle is left
+    // untouched; |bundle_data| is dropped which frees any frames it holds.
+    fn insert_bundle(
+        &mut self,
+        key: &str,
+        bundle_data: BundleData,
+    ) -> Result<(), SecurityRequestError> {
+        if self.bundles.contains_key(key) {
+            return Err(SecurityRequestError::DeleteFirst);
+        }
+        self.bundles.insert(key.to_string(), bundle_data);
+        Ok(())
+    }
+
+    // Returns a bundle for the top-level |frames| holding |size| bytes
+    // of an uploaded model.
+    fn uploaded_model_bundle(
+        mut frames: ObjDescBundle,
+        size: usize,
+        signature: Option<&[u8]>,
+    ) -> Result<BundleData, UploadError> {
+        // NB: check the frames while they are top-level so they can be
+        //     freed if the check fails.
+        #[cfg(feature = "verify_signatures")]
+        let signature = match signature
+            .and_then(|sig| <[u8; SIGNATURE_LEN]>::try_from(sig).ok())
+            .ok_or(UploadError::SignatureInvalid)
+            .and_then(|sig| verify_frames(&[], &frames, size, &sig).map(|_| sig))
+        {
+            Ok(signature) => signature,
+            Err(e) => {
+                let _ = cantrip_object_free_toplevel(&frames);
+                return Err(e);
+            }
+        };
+        // NB: without signature checking an uploaded signature is ignored.
+        #[cfg(not(feature = "verify_signatures"))]
+        let _ = signature;
+        move_to_cnode(&mut frames)?;
+        let mut bundle_data = BundleData::new(&frames);
+        bundle_data.pkg_size = size;
+        #[cfg(feature = "verify_signatures")]
+        {
+            bundle_data.signature = Some(signature);
+        }
+        Ok(bundle_data)
+    }
+
+    fn load_app_bundle(
+        &mut self,
+        bundle_id: &str,
+        expected_hash: Option<&PackageHash>,
+    ) -> Result<ObjDescBundle, SecurityRequestError> {
         // Clone everything (struct + associated seL4 objects) so the
         // return is as though it was newly instantiated from flash.
         let bundle_data = self.bundles.get(bundle_id).unwrap();
         let app_bundle = bundle_data
-            .deep_copy()
-            .or(Err(SecurityRequestError::LoadApplicationFailed))?;
+            .deep_copy(expected_hash)
+            .map_err(|e| deep_copy_err(e, SecurityRequestError::LoadApplicationFailed))?;
         // XXX currently always returns success
         let _ = self.manager.load_application(bundle_id);
         Ok(app_bundle)
@@ -295,14 +735,43 @@ impl SecurityCoordinatorInterface for CantripSecurityCoordinator {
     fn install_app(
         &mut self,
         app_id: &str,
-        _pkg_contents: &ObjDescBundle,
+        pkg_contents: &ObjDescBundle,
+        signature: Option<&[u8]>,
     ) -> Result<(), SecurityRequestError> {
         let key = promote_key(app_id, &[APP_SUFFIX]);
-        if self.bundles.contains_key(&key) {
-            return Err(SecurityRequestError::DeleteFirst);
+        self.check_install(&key)?;
+        // NB: the signature is kept with the bundle and checked against
+        //     the package contents on each load, as for builtins; an
+        //     unsigned package is rejected up front.
+        #[cfg(feature = "verify_signatures")]
+        let signature = signature
+            .and_then(|sig| <[u8; SIGNATURE_LEN]>::try_from(sig).ok())
+            .ok_or(SecurityRequestError::SignatureInvalid)?;
+        // NB: without signature checking a signature is ignored.
+        #[cfg(not(feature = "verify_signatures"))]
+        let _ = signature;
+        // NB: the bundle takes ownership of the package frames; they are
+        //     freed when the bundle is dropped (including on failure below).
+        #[allow(unused_mut)]
+        let mut bundle_data = BundleData::new(pkg_contents);
+        #[cfg(feature = "verify_signatures")]
+        {
+            bundle_data.signature = Some(signature);
         }
-        // XXX defer to back-end impl.
-        Err(SecurityRequestError::InstallFailed)
+        self.insert_bundle(&key, bundle_data)?;
+        // Any leading manifest is kept aside so it is not loaded with
+        // the image; a package without one installs as before.
+        let result = read_leading_manifest(pkg_contents)
+            .or(Err(SecurityRequestError::InstallFailed))
+            .and_then(|manifest| {
+                self.bundles.get_mut(&key).unwrap().manifest = manifest;
+                self.manager.load_application(&key)
+            });
+        if let Err(e) = result {
+            self.bundles.remove(&key);
+            return Err(e);
+        }
+        Ok(())
     }
     fn install_model(
         &mut self,
@@ -312,16 +781,21 @@ impl SecurityCoordinatorInterface for CantripSecurityCoordinator {
     ) -> Result<(), SecurityRequestError> {
         // NB: no key promotion, model name must be fully specified
         let key = promote_key(model_id, &[""]);
-        if self.bundles.contains_key(&key) {
-            return Err(SecurityRequestError::DeleteFirst);
-        }
+        self.check_install(&key)?;
         // XXX defer to back-end impl.
         Err(SecurityRequestError::InstallFailed)
     }
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
-        // NB: does not remove flash/built-in contents
-        let _ = self.manager.uninstall(bundle_id);
-        self.remove_bundle(bundle_id)
+        // NB: does not remove flash/built-in contents; a bundle that is
+        //   not installed (e.g. already uninstalled or builtin-only) is
+        //   treated as uninstalled so a retry succeeds.
+        absent_ok(self.manager.uninstall(bundle_id))?;
+        // NB: shared frames must be released before the bundle is removed.
+        let key = self.shared_key(bundle_id);
+        for master in self.shared_models.remove_bundle(&key) {
+            free_pkg_frames(&master);
+        }
+        absent_ok(self.remove_bundle(bundle_id))
     }
 
     fn get_packages(&self) -> Result<BundleIdArray, SecurityRequestError> {
@@ -344,42 +818,66 @@ impl SecurityCoordinatorInterface for CantripSecurityCoordinator {
     // TODO(sleffler): use get_bundle so package must be loaded? instantiating
     //   hashmap entries may be undesirable
     fn get_manifest(&self, bundle_id: &str) -> Result<String, SecurityRequestError> {
-        let _bundle = self.get_bundle(bundle_id)?;
-        Err(SecurityRequestError::GetManifestFailed)
+        let key = self.find_key(bundle_id)?;
+        let bundle_data = self.bundles.get(&key).unwrap();
+        let text = match &bundle_data.pkg_contents {
+            PkgContents::Dynamic(_) => Manifest::find_leading(&bundle_data.manifest)
+                .map(|text| text.as_bytes().to_vec())
+                .ok_or(UploadError::ReadFailed),
+            _ => self.lookup_manifest(&key),
+        }
+        .map_err(|e| match e {
+            UploadError::ReadFailed => SecurityRequestError::GetManifestFailed,
+            _ => SecurityRequestError::ReadFailed,
+        })?;
+        let manifest = str::from_utf8(&text)
+            .ok()
+            .and_then(Manifest::parse)
+            .ok_or(SecurityRequestError::GetManifestFailed)?;
+        Ok(manifest.to_string())
     }
 
-    fn load_application(&mut self, bundle_id: &str) -> Result<ObjDescBundle, SecurityRequestError> {
+    fn load_application(
+        &mut self,
+        bundle_id: &str,
+        expected_hash: Option<&PackageHash>,
+    ) -> Result<ObjDescBundle, SecurityRequestError> {
         // NB: loading may promote a bundle from the built-ins archive to the hashmap
         if self.bundles.contains_key(bundle_id) {
-            return self.load_app_bundle(bundle_id);
+            return self.load_app_bundle(bundle_id, expected_hash);
         }
-        if let Ok(bd) = self.get_bundle_from_builtins(bundle_id) {
-            assert!(self.bundles.insert(bundle_id.to_string(), bd).is_none());
-            return self.load_app_bundle(bundle_id);
+        if let Ok(bd) = self.load_builtin(bundle_id) {
+            self.insert_bundle(bundle_id, bd)?;
+            return self.load_app_bundle(bundle_id, expected_hash);
         }
         let key = promote_key(bundle_id, &[APP_SUFFIX]);
         if !self.bundles.contains_key(&key) {
-            let bd = self.get_bundle_from_builtins(&key)?;
-            assert!(self.bundles.insert(key.clone(), bd).is_none());
+            let bd = self.load_builtin(&key)?;
+            self.insert_bundle(&key, bd)?;
         }
-        self.load_app_bundle(&key)
+        self.load_app_bundle(&key, expected_hash)
     }
     fn load_model(
         &mut self,
-        _bundle_id: &str, // TODO(sleffler): models are meant to be associated with bundle_id
+        bundle_id: &str,
         model_id: &str,
     ) -> Result<ObjDescBundle, SecurityRequestError> {
         fn load_model_data(model_data: &BundleData) -> Result<ObjDescBundle, SecurityRequestError> {
             // Clone everything (struct + associated seL4 objects) so the
             // return is as though it was newly instantiated from flash.
             model_data
-                .deep_copy()
-                .or(Err(SecurityRequestError::LoadModelFailed))
+                .deep_copy(None)
+                .map_err(|e| deep_copy_err(e, SecurityRequestError::LoadModelFailed))
+        }
+        // NB: a model uploaded by |bundle_id| takes precedence; uploads
+        //   never share a name with a builtin (see check_upload).
+        if let Some(bd) = self.bundles.get(&Self::upload_key(bundle_id, model_id)) {
+            return load_model_data(bd);
         }
         if let Some(bd) = self.bundles.get(model_id) {
             return load_model_data(bd);
         }
-        if let Ok(bd) = self.get_bundle_from_builtins(model_id) {
+        if let Ok(bd) = self.load_builtin(model_id) {
             // No need to add to bundles since no other calls make sense
             // (though perhaps size_buffer might be useful).
             return load_model_data(&bd);
@@ -387,6 +885,20 @@ impl SecurityCoordinatorInterface for CantripSecurityCoordinator {
         // NB: no key promotion, model name must be fully specified
         Err(SecurityRequestError::BundleNotFound)
     }
+    fn load_shared_model(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+    ) -> Result<ObjDescBundle, SecurityRequestError> {
+        let key = self.shared_key(bundle_id);
+        if let Some(frames) = self.shared_models.share(&key, model_id, share_frames) {
+            return frames;
+        }
+        // First load, make the master copy that later loads share.
+        let master = self.load_model(bundle_id, model_id)?;
+        self.shared_models
+            .insert_and_share(&key, model_id, master, share_frames)
+    }
 
     // NB: key-value ops require a load'd bundle so only do get_bundle
     fn read_key(&self, bundle_id: &str, key: &str) -> Result<&KeyValueData, SecurityRequestError> {
@@ -404,5 +916,523 @@ impl SecurityCoordinatorInterface for CantripSecurityCoordinator {
     fn delete_key(&mut self, bundle_id: &str, key: &str) -> Result<(), SecurityRequestError> {
         self.manager.delete_key(&self.find_key(bundle_id)?, key)
     }
+    fn list_keys(&self, bundle_id: &str) -> Result<KeyNameArray, SecurityRequestError> {
+        self.manager.list_keys(&self.find_key(bundle_id)?)
+    }
+    fn sync_keys(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
+        self.manager.sync_keys(&self.find_key(bundle_id)?)
+    }
+
+    fn model_upload_begin(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+        size: usize,
+    ) -> Result<usize, SecurityRequestError> {
+        // NB: no key promotion, model name must be fully specified
+        // NB: checked again on commit as other installs may intervene
+        let key = Self::upload_key(bundle_id, model_id);
+        self.check_install(&key)?;
+        self.check_upload(bundle_id, model_id, size)?;
+        self.model_uploads
+            .begin(bundle_id, &key, size, new_upload_frames, free_upload_frames)
+    }
+    fn model_upload_chunk(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<usize, SecurityRequestError> {
+        let key = Self::upload_key(bundle_id, model_id);
+        self.model_uploads
+            .write(bundle_id, &key, offset, data, |frames, offset, data| {
+                write_upload_frames(frames, offset, data)
+                    .or(Err(SecurityRequestError::InstallModelFailed))
+            })
+    }
+    fn model_upload_commit(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+        signature: Option<&[u8]>,
+    ) -> Result<(), SecurityRequestError> {
+        let key = Self::upload_key(bundle_id, model_id);
+        self.check_install(&key)?;
+        // NB: the upload is consumed; on failure it must be started over
+        let (frames, size) = self.model_uploads.commit(bundle_id, &key)?;
+        let bundle_data = Self::uploaded_model_bundle(frames, size, signature)
+            .map_err(|e| deep_copy_err(e, SecurityRequestError::InstallModelFailed))?;
+        self.insert_bundle(&key, bundle_data)
+    }
+    fn model_upload_abort(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
+        self.model_uploads.abort(bundle_id, free_upload_frames);
+        Ok(())
+    }
+
     fn test(&self, count: usize) -> Result<(), SecurityRequestError> { self.manager.test(count) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::vec::Vec;
+    use cantrip_memory_interface::ObjDesc;
+    use core::cell::RefCell;
+    use std::collections::BTreeMap;
+
+    std::thread_local! {
+        // CNodes of package frames released by BundleData::drop.
+        pub(super) static FREED_CNODES: RefCell<Vec<sel4_sys::seL4_CPtr>> =
+            RefCell::new(Vec::new());
+        // Contents of the model upload in progress.
+        static UPLOADED: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+        // Frame counts of model uploads released by free_upload_frames.
+        static FREED_UPLOADS: RefCell<Vec<usize>> = RefCell::new(Vec::new());
+        // Contents of package frames by cptr; others read as zero.
+        static PAGES: RefCell<BTreeMap<seL4_CPtr, Vec<u8>>> = RefCell::new(BTreeMap::new());
+    }
+
+    const FRAME_SIZE: usize = 4096;
+    const UPLOAD_CNODE: sel4_sys::seL4_CPtr = 99;
+
+    pub(super) fn new_upload_frames() -> ObjDescBundle {
+        UPLOADED.with(|uploaded| uploaded.borrow_mut().clear());
+        ObjDescBundle::new(/*cnode=*/ 0, /*depth=*/ 64, Vec::new())
+    }
+    pub(super) fn write_upload_frames(
+        frames: &mut ObjDescBundle,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), UploadError> {
+        UPLOADED.with(|uploaded| {
+            let mut uploaded = uploaded.borrow_mut();
+            assert_eq!(offset, uploaded.len());
+            uploaded.extend_from_slice(data);
+            while frames.count() * FRAME_SIZE < uploaded.len() {
+                let page = ObjDesc::new(sel4_sys::seL4_SmallPageObject, 1, frames.count());
+                frames.objs.push(page);
+                frames.maybe_combine_last();
+            }
+        });
+        Ok(())
+    }
+    pub(super) fn free_upload_frames(frames: ObjDescBundle) {
+        FREED_UPLOADS.with(|freed| freed.borrow_mut().push(frames.count()));
+    }
+    pub(super) fn move_to_cnode(frames: &mut ObjDescBundle) -> Result<(), UploadError> {
+        frames.cnode = UPLOAD_CNODE;
+        Ok(())
+    }
+    pub(super) fn read_page(cptr: seL4_CPtr, f: &mut dyn FnMut(&[u8])) -> Result<(), UploadError> {
+        PAGES.with(|pages| match pages.borrow().get(&cptr) {
+            Some(page) => f(page),
+            None => f(&[0u8; FRAME_SIZE]),
+        });
+        Ok(())
+    }
+
+    // Returns a package of |count| frames holding |data|.
+    fn package(count: usize, data: &[u8]) -> ObjDescBundle {
+        PAGES.with(|pages| {
+            let mut pages = pages.borrow_mut();
+            pages.clear();
+            for (cptr, chunk) in data.chunks(FRAME_SIZE).enumerate() {
+                let mut page = chunk.to_vec();
+                page.resize(FRAME_SIZE, 0);
+                pages.insert(cptr, page);
+            }
+        });
+        let frames = ObjDesc::new(sel4_sys::seL4_SmallPageObject, count, /*cptr=*/ 0);
+        ObjDescBundle::new(/*cnode=*/ 42, /*depth=*/ 1, Vec::from([frames]))
+    }
+
+    // Returns |text| with the manifest header.
+    fn manifest(text: &str) -> Vec<u8> {
+        let mut manifest = Vec::from(&MANIFEST_MAGIC[..]);
+        manifest.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        manifest.extend_from_slice(text.as_bytes());
+        manifest
+    }
+
+    // Builtins archive for the fake back-end: a newc cpio archive
+    // holding only "builtin.model".
+    #[cfg(feature = "fake")]
+    fn builtins_archive() -> Vec<u8> {
+        let mut archive = Vec::new();
+        for (name, data) in [("builtin.model", &b"model"[..]), ("TRAILER!!!", &[][..])] {
+            archive.extend_from_slice(b"070701");
+            // ino, mode, uid, gid, nlink, mtime, filesize, devmajor,
+            // devminor, rdevmajor, rdevminor, namesize, check
+            let (filesize, namesize) = (data.len(), name.len() + 1);
+            let fields = [0, 0o100644, 0, 0, 1, 0, filesize, 0, 0, 0, 0, namesize, 0];
+            for field in fields {
+                archive.extend_from_slice(format!("{:08x}", field).as_bytes());
+            }
+            archive.extend_from_slice(name.as_bytes());
+            archive.push(0);
+            archive.resize((archive.len() + 3) & !3, 0);
+            archive.extend_from_slice(data);
+            archive.resize((archive.len() + 3) & !3, 0);
+        }
+        archive
+    }
+    #[cfg(feature = "fake")]
+    #[no_mangle]
+    fn get_cpio_archive() -> &'static [u8] {
+        std::thread_local! {
+            static ARCHIVE: &'static [u8] = Vec::leak(builtins_archive());
+        }
+        ARCHIVE.with(|archive| *archive)
+    }
+
+    #[test]
+    fn uninstall_missing() {
+        let mut coordinator = CantripSecurityCoordinator::new();
+        assert_eq!(coordinator.uninstall("nonexistent"), Ok(()));
+        // Retrying is harmless.
+        assert_eq!(coordinator.uninstall("nonexistent"), Ok(()));
+    }
+
+    #[test]
+    fn too_many_bundles() {
+        let mut coordinator = CantripSecurityCoordinator::new();
+        let empty = ObjDescBundle::new(0, 0, Vec::new());
+        for i in 0..MAX_BUNDLES {
+            let key = format!("model{i}");
+            assert_eq!(coordinator.check_install(&key), Ok(()));
+            coordinator.bundles.insert(key, BundleData::new(&empty));
+        }
+        // Builtins promoted on load do not count against the limit.
+        coordinator
+            .bundles
+            .insert("hello.app".to_string(), BundleData::new_from_flash(&[]));
+        assert_eq!(coordinator.installed_count(), MAX_BUNDLES);
+
+        // One past the limit is rejected cleanly; a re-install is still
+        // reported as such.
+        assert_eq!(
+            coordinator.check_install("one_too_many"),
+            Err(SecurityRequestError::TooManyBundles)
+        );
+        assert_eq!(
+            coordinator.check_install("model0"),
+            Err(SecurityRequestError::DeleteFirst)
+        );
+
+        // Uninstalling makes room.
+        let bd = coordinator.bundles.remove("model0").unwrap();
+        assert_eq!(coordinator.check_install("one_too_many"), Ok(()));
+
+        // NB: installed bundles free their memory on drop which needs
+        //     the MemoryManager.
+        core::mem::forget(bd);
+        core::mem::forget(coordinator);
+    }
+
+    #[test]
+    fn install_app() {
+        let mut coordinator = CantripSecurityCoordinator::new();
+        let page = ObjDesc::new(sel4_sys::seL4_SmallPageObject, 2, /*cptr=*/ 0);
+        let frames = ObjDescBundle::new(/*cnode=*/ 42, /*depth=*/ 1, Vec::from([page]));
+        assert_eq!(coordinator.install_app("hello", &frames, None), Ok(()));
+        assert_eq!(coordinator.installed_count(), 1);
+        assert_eq!(
+            coordinator.get_bundle("hello").map(|bd| bd.pkg_size),
+            Ok(frames.size_bytes())
+        );
+        // The back-end has loaded the app so key-value ops work.
+        assert_eq!(coordinator.write_key("hello", "foo", b"bar"), Ok(()));
+
+        // A re-install is rejected without touching the original.
+        assert_eq!(
+            coordinator.install_app("hello.app", &frames, None),
+            Err(SecurityRequestError::DeleteFirst)
+        );
+        assert!(FREED_CNODES.with(|freed| freed.borrow().is_empty()));
+
+        // Uninstalling releases the package frames.
+        assert_eq!(coordinator.uninstall("hello.app"), Ok(()));
+        assert_eq!(coordinator.installed_count(), 0);
+        assert_eq!(FREED_CNODES.with(|freed| freed.take()), [42]);
+    }
+
+    #[cfg(feature = "verify_signatures")]
+    #[test]
+    fn install_app_signature() {
+        let mut coordinator = CantripSecurityCoordinator::new();
+        let page = ObjDesc::new(sel4_sys::seL4_SmallPageObject, 1, /*cptr=*/ 0);
+        let frames = ObjDescBundle::new(/*cnode=*/ 42, /*depth=*/ 1, Vec::from([page]));
+        // Unsigned and malformed signatures are rejected before the
+        // package is taken.
+        for signature in [None, Some(&[0u8; SIGNATURE_LEN - 1][..])] {
+            assert_eq!(
+                coordinator.install_app("hello", &frames, signature),
+                Err(SecurityRequestError::SignatureInvalid)
+            );
+            assert_eq!(coordinator.installed_count(), 0);
+        }
+
+        // A signature is kept with the bundle to check on load.
+        let signature = [7u8; SIGNATURE_LEN];
+        assert_eq!(coordinator.install_app("hello", &frames, Some(&signature)), Ok(()));
+        assert_eq!(
+            coordinator.bundles.get("hello.app").unwrap().signature,
+            Some(signature)
+        );
+
+        assert_eq!(coordinator.uninstall("hello"), Ok(()));
+        assert_eq!(FREED_CNODES.with(|freed| freed.take()), [42]);
+    }
+
+    #[test]
+    fn installed_manifest() {
+        let mut coordinator = CantripSecurityCoordinator::new();
+        let page = ObjDesc::new(sel4_sys::seL4_SmallPageObject, 1, /*cptr=*/ 0);
+        let frames = ObjDescBundle::new(/*cnode=*/ 42, /*depth=*/ 1, Vec::from([page]));
+        assert_eq!(coordinator.install_app("hello", &frames, None), Ok(()));
+        // A package without a manifest installs; only the query fails.
+        assert_eq!(
+            coordinator.get_manifest("hello"),
+            Err(SecurityRequestError::GetManifestFailed)
+        );
+
+        // The manifest kept aside at install is what is returned.
+        let text = "entrypoint = 0x1000\nmemory = 4096\n";
+        coordinator.bundles.get_mut("hello.app").unwrap().manifest = manifest(text);
+        assert_eq!(coordinator.get_manifest("hello"), Ok(text.to_string()));
+
+        assert_eq!(coordinator.uninstall("hello"), Ok(()));
+        assert_eq!(FREED_CNODES.with(|freed| freed.take()), [42]);
+    }
+
+    #[test]
+    fn install_reads_manifest() {
+        let mut coordinator = CantripSecurityCoordinator::new();
+        let text = "entrypoint = 0x1000\nmemory = 4096\n";
+        let mut data = manifest(text);
+        data.extend_from_slice(b"\x7fELF");
+        let frames = package(1, &data);
+        assert_eq!(coordinator.install_app("hello", &frames, None), Ok(()));
+        assert_eq!(coordinator.bundles.get("hello.app").unwrap().manifest, manifest(text));
+        assert_eq!(coordinator.get_manifest("hello"), Ok(text.to_string()));
+
+        assert_eq!(coordinator.uninstall("hello"), Ok(()));
+        assert_eq!(FREED_CNODES.with(|freed| freed.take()), [42]);
+    }
+
+    #[test]
+    fn leading_manifest() {
+        let text = "entrypoint = 0x1000\nmemory = 4096\n";
+        let mut data = manifest(text);
+        data.extend_from_slice(b"\x7fELF");
+
+        // Only the frames holding the manifest are read.
+        let frames = package(3, &data);
+        let mut reads = 0;
+        let counting = |cptr: seL4_CPtr, f: &mut dyn FnMut(&[u8])| {
+            reads += 1;
+            read_page(cptr, f)
+        };
+        assert_eq!(leading_manifest_with(&frames, counting).unwrap(), manifest(text));
+        assert_eq!(reads, 1);
+
+        // No manifest, or one that is not at the front.
+        let frames = package(2, b"\x7fELF");
+        assert!(read_leading_manifest(&frames).unwrap().is_empty());
+        let frames = package(1, &[&b"\x7fELF"[..], &data[..]].concat());
+        assert!(read_leading_manifest(&frames).unwrap().is_empty());
+
+        // An oversized manifest is ignored.
+        let frames = package(1, &manifest(&"x".repeat(MAX_MANIFEST_SIZE + 1)));
+        assert!(read_leading_manifest(&frames).unwrap().is_empty());
+
+        // Failures mapping frames are passed back.
+        let failing = |_cptr: seL4_CPtr, _f: &mut dyn FnMut(&[u8])| Err(UploadError::PageMap);
+        assert!(matches!(
+            leading_manifest_with(&frames, failing),
+            Err(UploadError::PageMap)
+        ));
+    }
+
+    #[test]
+    fn uninstall_releases_shared_models() {
+        fn master(cnode: sel4_sys::seL4_CPtr) -> ObjDescBundle {
+            let page = ObjDesc::new(sel4_sys::seL4_SmallPageObject, 1, /*cptr=*/ 0);
+            ObjDescBundle::new(cnode, /*depth=*/ 1, Vec::from([page]))
+        }
+        fn dup(master: &ObjDescBundle) -> Result<ObjDescBundle, ()> { Ok(master.clone()) }
+
+        let mut coordinator = CantripSecurityCoordinator::new();
+        assert_eq!(coordinator.install_app("hello", &master(42), None), Ok(()));
+        // Masters as load_shared_model records them; "world" loads a
+        // like-named model that must not be shared with "hello".
+        let hello_key = coordinator.shared_key("hello");
+        for (bundle_id, model_id, cnode) in [
+            (hello_key.as_str(), "model", 1),
+            (hello_key.as_str(), "other", 2),
+            ("world", "model", 3),
+        ] {
+            let models = &mut coordinator.shared_models;
+            let _ = models.insert_and_share(bundle_id, model_id, master(cnode), dup);
+        }
+        assert_eq!(
+            coordinator
+                .shared_models
+                .share(&coordinator.shared_key("world"), "model", dup)
+                .map(|frames| frames.unwrap().cnode),
+            Some(3)
+        );
+
+        // Uninstall releases every master of the bundle, and only those.
+        assert_eq!(coordinator.uninstall("hello"), Ok(()));
+        let mut freed = FREED_CNODES.with(|freed| freed.take());
+        freed.sort();
+        assert_eq!(freed, [1, 2, 42]);
+        assert!(coordinator
+            .shared_models
+            .share(&hello_key, "model", dup)
+            .is_none());
+        assert!(coordinator
+            .shared_models
+            .share("world", "model", dup)
+            .is_some());
+
+        // NB: masters free their memory on drop which needs the MemoryManager.
+        core::mem::forget(coordinator);
+    }
+
+    #[test]
+    fn model_upload() {
+        let mut coordinator = CantripSecurityCoordinator::new();
+        let model: Vec<u8> = (0..2 * FRAME_SIZE + 100).map(|x| x as u8).collect();
+
+        // Chunks that straddle frames are written as they arrive.
+        assert_eq!(coordinator.model_upload_begin("app", "up.model", model.len()), Ok(0));
+        let mut received = 0;
+        for chunk in model.chunks(1000) {
+            received = coordinator
+                .model_upload_chunk("app", "up.model", received, chunk)
+                .unwrap();
+        }
+        assert_eq!(received, model.len());
+        assert_eq!(coordinator.model_upload_commit("app", "up.model", None), Ok(()));
+        assert_eq!(UPLOADED.with(|uploaded| uploaded.take()), model);
+
+        // The model is installed from its own CNode, private to "app".
+        let bd = coordinator.get_bundle("app/up.model").unwrap();
+        assert_eq!(bd.pkg_size, model.len());
+        assert!(matches!(
+            &bd.pkg_contents,
+            PkgContents::Dynamic(frames) if frames.cnode == UPLOAD_CNODE && frames.count() == 3
+        ));
+        assert_eq!(coordinator.installed_count(), 1);
+
+        // Another upload of the model must wait for an uninstall.
+        assert_eq!(
+            coordinator.model_upload_begin("app", "up.model", model.len()),
+            Err(SecurityRequestError::DeleteFirst)
+        );
+
+        // An aborted upload releases the frames received so far.
+        assert_eq!(
+            coordinator.model_upload_begin("app", "next.model", model.len()),
+            Ok(0)
+        );
+        assert_eq!(
+            coordinator.model_upload_chunk("app", "next.model", 0, &model[..FRAME_SIZE + 1]),
+            Ok(FRAME_SIZE + 1)
+        );
+        assert_eq!(coordinator.model_upload_abort("app"), Ok(()));
+        assert_eq!(FREED_UPLOADS.with(|freed| freed.take()), [2]);
+        assert_eq!(
+            coordinator.model_upload_commit("app", "next.model", None),
+            Err(SecurityRequestError::UploadNotFound)
+        );
+
+        assert_eq!(coordinator.uninstall("app/up.model"), Ok(()));
+        assert_eq!(FREED_CNODES.with(|freed| freed.take()), [UPLOAD_CNODE]);
+    }
+
+    #[cfg(feature = "fake")]
+    #[test]
+    fn model_upload_limits() {
+        let mut coordinator = CantripSecurityCoordinator::new();
+
+        // A builtin cannot be replaced.
+        assert_eq!(
+            coordinator.model_upload_begin("app", "builtin.model", 1),
+            Err(SecurityRequestError::DeleteFirst)
+        );
+
+        // Uploads are charged to the uploader: "app" may not reserve
+        // more than MAX_UPLOAD_BYTES but "other" is unaffected.
+        let a_size = MAX_UPLOAD_BYTES - 8;
+        assert_eq!(
+            coordinator.model_upload_begin("app", "big.model", MAX_UPLOAD_BYTES + 1),
+            Err(SecurityRequestError::UploadQuotaExceeded)
+        );
+        assert_eq!(coordinator.model_upload_begin("app", "a.model", a_size), Ok(0));
+        assert_eq!(
+            coordinator.model_upload_begin("app", "b.model", 9),
+            Err(SecurityRequestError::UploadQuotaExceeded)
+        );
+        assert_eq!(coordinator.model_upload_begin("other", "b.model", 9), Ok(0));
+
+        // Resuming an upload does not count it twice.
+        assert_eq!(coordinator.model_upload_begin("app", "a.model", a_size), Ok(0));
+
+        // Installed uploads count against the uploader too.
+        assert_eq!(coordinator.model_upload_abort("app"), Ok(()));
+        assert_eq!(coordinator.model_upload_begin("app", "c.model", 8), Ok(0));
+        assert_eq!(coordinator.model_upload_chunk("app", "c.model", 0, &[7; 8]), Ok(8));
+        assert_eq!(coordinator.model_upload_commit("app", "c.model", None), Ok(()));
+        assert_eq!(
+            coordinator.model_upload_begin("app", "a.model", MAX_UPLOAD_BYTES - 7),
+            Err(SecurityRequestError::UploadQuotaExceeded)
+        );
+
+        // Models of the same name uploaded by different apps are kept
+        // apart; neither replaces the other.
+        assert_eq!(coordinator.model_upload_chunk("other", "b.model", 0, &[9; 9]), Ok(9));
+        assert_eq!(coordinator.model_upload_commit("other", "b.model", None), Ok(()));
+        assert_eq!(coordinator.model_upload_begin("other", "c.model", 8), Ok(0));
+        assert_eq!(coordinator.model_upload_chunk("other", "c.model", 0, &[8; 8]), Ok(8));
+        assert_eq!(coordinator.model_upload_commit("other", "c.model", None), Ok(()));
+        for key in ["app/c.model", "other/c.model"] {
+            assert_eq!(coordinator.get_bundle(key).map(|bd| bd.pkg_size), Ok(8));
+        }
+        assert_eq!(
+            coordinator.get_bundle("c.model").err(),
+            Some(SecurityRequestError::BundleNotFound)
+        );
+        FREED_UPLOADS.with(|freed| freed.take());
+
+        // NB: installed bundles free their memory on drop which needs
+        //     the MemoryManager.
+        core::mem::forget(coordinator);
+    }
+
+    #[test]
+    fn insert_bundle_keeps_existing() {
+        let mut coordinator = CantripSecurityCoordinator::new();
+        let data: &'static [u8] = b"first";
+        let first = BundleData::new_from_flash(data);
+        assert_eq!(coordinator.insert_bundle("hello.app", first), Ok(()));
+        assert_eq!(
+            coordinator.insert_bundle("hello.app", BundleData::new_from_flash(&[])),
+            Err(SecurityRequestError::DeleteFirst)
+        );
+        assert_eq!(coordinator.get_bundle("hello").map(|bd| bd.pkg_size), Ok(data.len()));
+    }
+
+    #[test]
+    fn absent_ok_passes_failures() {
+        assert_eq!(absent_ok(Err(SecurityRequestError::BundleNotFound)), Ok(()));
+        assert_eq!(
+            absent_ok(Err(SecurityRequestError::UninstallFailed)),
+            Err(SecurityRequestError::UninstallFailed)
+        );
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Model uploads in progress.
+//!
+//! A model too large for one request is streamed in chunks. Chunks
+//! must arrive in order but a chunk may be resent (e.g. when a reply
+//! was lost) and an interrupted upload is resumed by beginning it
+//! again; begin returns where the next chunk starts. Frames are
+//! allocated as data arrive so an aborted upload holds only what
+//! was received.
+
+use alloc::string::{String, ToString};
+use cantrip_security_interface::SecurityRequestError;
+use hashbrown::HashMap;
+
+struct PendingUpload<F> {
+    bundle_id: String, // Uploader
+    size: usize,       // Total bytes expected
+    received: usize,   // Bytes written to |frames|
+    frames: F,
+}
+
+pub struct ModelUploads<F> {
+    uploads: HashMap<String, PendingUpload<F>>, // Keyed by model_id
+}
+impl<F> Default for ModelUploads<F> {
+    fn default() -> Self { Self::new() }
+}
+impl<F> ModelUploads<F> {
+    pub fn new() -> Self {
+        Self {
+            uploads: HashMap::new(),
+        }
+    }
+
+    /// Starts an upload of |size| bytes of |model_id| by |bundle_id|
+    /// with frames from |new|. An upload already started by |bundle_id|
+    /// is resumed unless the size differs, in which case it is restarted
+    /// and the old frames are passed to |free|. Returns the bytes
+    /// received so far.
+    pub fn begin(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+        size: usize,
+        new: impl FnOnce() -> F,
+        free: impl FnOnce(F),
+    ) -> Result<usize, SecurityRequestError> {
+        if size == 0 {
+            return Err(SecurityRequestError::BundleDataInvalid);
+        }
+        if let Some(upload) = self.uploads.get(model_id) {
+            if upload.bundle_id != bundle_id {
+                return Err(SecurityRequestError::DeleteFirst);
+            }
+            if upload.size == size {
+                return Ok(upload.received);
+            }
+            free(self.uploads.remove(model_id).unwrap().frames);
+        }
+        self.uploads.insert(
+            model_id.to_string(),
+            PendingUpload {
+                bundle_id: bundle_id.to_string(),
+                size,
+                received: 0,
+                frames: new(),
+            },
+        );
+        Ok(0)
+    }
+
+    /// Writes |data| at |offset| of |model_id| using |write| which is
+    /// passed the frames, the offset to write at, and the bytes. Data
+    /// already received are skipped. Returns the bytes received so far.
+    pub fn write(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+        offset: usize,
+        data: &[u8],
+        write: impl FnOnce(&mut F, usize, &[u8]) -> Result<(), SecurityRequestError>,
+    ) -> Result<usize, SecurityRequestError> {
+        let upload = self.get_mut(bundle_id, model_id)?;
+        if offset > upload.received {
+            return Err(SecurityRequestError::UploadOffsetInvalid);
+        }
+        let end = offset
+            .checked_add(data.len())
+            .ok_or(SecurityRequestError::UploadOffsetInvalid)?;
+        if end > upload.size {
+            return Err(SecurityRequestError::UploadOffsetInvalid);
+        }
+        if end > upload.received {
+            let skip = upload.received - offset;
+            write(&mut upload.frames, upload.received, &data[skip..])?;
+            upload.received = end;
+        }
+        Ok(upload.received)
+    }
+
+    /// Completes the upload of |model_id|, returning the frames and the
+    /// size of the model. An incomplete upload is kept so it may be
+    /// resumed.
+    pub fn commit(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+    ) -> Result<(F, usize), SecurityRequestError> {
+        let upload = self.get_mut(bundle_id, model_id)?;
+        if upload.received != upload.size {
+            return Err(SecurityRequestError::UploadIncomplete);
+        }
+        let upload = self.uploads.remove(model_id).unwrap();
+        Ok((upload.frames, upload.size))
+    }
+
+    /// Discards any uploads by |bundle_id|, passing their frames to |free|.
+    pub fn abort(&mut self, bundle_id: &str, mut free: impl FnMut(F)) {
+        self.uploads
+            .extract_if(|_, upload| upload.bundle_id == bundle_id)
+            .for_each(|(_, upload)| free(upload.frames));
+    }
+
+    /// Returns the bytes reserved by uploads in progress by |bundle_id|
+    /// other than |model_id|.
+    pub fn reserved(&self, bundle_id: &str, model_id: &str) -> usize {
+        self.uploads
+            .iter()
+            .filter(|(id, upload)| upload.bundle_id == bundle_id && id.as_str() != model_id)
+            .map(|(_, upload)| upload.size)
+            .sum()
+    }
+
+    /// Returns whether an upload of |model_id| is in progress.
+    pub fn contains(&self, model_id: &str) -> bool { self.uploads.contains_key(model_id) }
+
+    fn get_mut(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+    ) -> Result<&mut PendingUpload<F>, SecurityRequestError> {
+        match self.uploads.get_mut(model_id) {
+            Some(upload) if upload.bundle_id == bundle_id => Ok(upload),
+            _ => Err(SecurityRequestError::UploadNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    const PAGE: usize = 8;
+
+    // Frames are modeled as a byte vector that grows a page at a time.
+    #[derive(Default)]
+    struct Frames {
+        data: Vec<u8>,
+    }
+    impl Frames {
+        fn pages(&self) -> usize { self.data.len() / PAGE }
+    }
+
+    fn write(frames: &mut Frames, offset: usize, data: &[u8]) -> Result<(), SecurityRequestError> {
+        assert_eq!(offset, frames.data.len());
+        frames.data.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn model(size: usize) -> Vec<u8> { (0..size).map(|x| x as u8).collect() }
+
+    #[test]
+    fn multi_chunk() {
+        let mut uploads = ModelUploads::new();
+        let data = model(3 * PAGE + 3);
+        assert_eq!(uploads.begin("app", "m", data.len(), Frames::default, drop), Ok(0));
+
+        let mut offset = 0;
+        for chunk in data.chunks(5) {
+            offset = uploads.write("app", "m", offset, chunk, write).unwrap();
+        }
+        assert_eq!(offset, data.len());
+
+        let (frames, size) = uploads.commit("app", "m").unwrap();
+        assert_eq!(size, data.len());
+        assert_eq!(frames.data, data);
+        assert_eq!(frames.pages(), 3);
+        assert!(!uploads.contains("m"));
+    }
+
+    #[test]
+    fn resume() {
+        let mut uploads = ModelUploads::new();
+        let data = model(20);
+        assert_eq!(uploads.begin("app", "m", 20, Frames::default, drop), Ok(0));
+        assert_eq!(uploads.write("app", "m", 0, &data[..8], write), Ok(8));
+
+        // A commit before all the data arrive keeps the upload.
+        assert_eq!(
+            uploads.commit("app", "m").err(),
+            Some(SecurityRequestError::UploadIncomplete)
+        );
+
+        // Beginning again says where to pick up.
+        assert_eq!(uploads.begin("app", "m", 20, Frames::default, drop), Ok(8));
+        // A resent chunk is skipped, an overlapping one is trimmed.
+        assert_eq!(uploads.write("app", "m", 0, &data[..8], write), Ok(8));
+        assert_eq!(uploads.write("app", "m", 4, &data[4..12], write), Ok(12));
+        // A gap is rejected.
+        assert_eq!(
+            uploads.write("app", "m", 16, &data[16..], write),
+            Err(SecurityRequestError::UploadOffsetInvalid)
+        );
+        assert_eq!(uploads.write("app", "m", 12, &data[12..], write), Ok(20));
+        // Writing past the end is rejected.
+        assert_eq!(
+            uploads.write("app", "m", 20, &[0], write),
+            Err(SecurityRequestError::UploadOffsetInvalid)
+        );
+
+        let (frames, _) = uploads.commit("app", "m").unwrap();
+        assert_eq!(frames.data, data);
+    }
+
+    #[test]
+    fn owner() {
+        let mut uploads = ModelUploads::<Frames>::new();
+        assert_eq!(uploads.begin("app", "m", 8, Frames::default, drop), Ok(0));
+
+        // Another app cannot use or take over the upload.
+        assert_eq!(
+            uploads.begin("other", "m", 8, Frames::default, drop),
+            Err(SecurityRequestError::DeleteFirst)
+        );
+        assert_eq!(
+            uploads.write("other", "m", 0, &[0], write),
+            Err(SecurityRequestError::UploadNotFound)
+        );
+        assert_eq!(
+            uploads.commit("other", "m").err(),
+            Some(SecurityRequestError::UploadNotFound)
+        );
+        assert_eq!(
+            uploads.write("app", "x", 0, &[0], write),
+            Err(SecurityRequestError::UploadNotFound)
+        );
+    }
+
+    #[test]
+    fn abort_frees_partial_frames() {
+        let mut uploads = ModelUploads::new();
+        let data = model(2 * PAGE);
+        assert_eq!(uploads.begin("app", "a", 4 * PAGE, Frames::default, drop), Ok(0));
+        assert_eq!(uploads.begin("app", "b", PAGE, Frames::default, drop), Ok(0));
+        assert_eq!(uploads.begin("other", "c", PAGE, Frames::default, drop), Ok(0));
+        assert_eq!(uploads.write("app", "a", 0, &data, write), Ok(2 * PAGE));
+
+        let mut freed = Vec::new();
+        uploads.abort("app", |frames| freed.push(frames.pages()));
+        freed.sort();
+        assert_eq!(freed, [0, 2]);
+        assert!(!uploads.contains("a") && !uploads.contains("b"));
+        assert!(uploads.contains("c"));
+        assert_eq!(uploads.reserved("app", "x"), 0);
+        assert_eq!(uploads.reserved("other", "x"), PAGE);
+        assert_eq!(uploads.reserved("other", "c"), 0);
+
+        // Restarting with a new size frees what was received.
+        assert_eq!(uploads.write("other", "c", 0, &data[..PAGE], write), Ok(PAGE));
+        let mut freed = 0;
+        assert_eq!(
+            uploads.begin("other", "c", 2 * PAGE, Frames::default, |f| freed = f.pages()),
+            Ok(0)
+        );
+        assert_eq!(freed, 1);
+
+        // An empty model is rejected.
+        assert_eq!(
+            uploads.begin("app", "d", 0, Frames::default, drop),
+            Err(SecurityRequestError::BundleDataInvalid)
+        );
+    }
+}
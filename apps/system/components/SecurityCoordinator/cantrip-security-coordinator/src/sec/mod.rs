@@ -14,28 +14,24 @@
 
 //! Cantrip OS security coordinator Security Core (SEC) manager
 
+use crate::keystore::KeyStore;
 use crate::BundleData;
 use crate::SecurityManagerInterface;
 use alloc::string::{String, ToString};
-use cantrip_memory_interface::cantrip_frame_alloc;
-use cantrip_memory_interface::cantrip_object_free_toplevel;
-use cantrip_memory_interface::ObjDescBundle;
-use cantrip_os_common::copyregion::CopyRegion;
+use cantrip_memory_interface::cantrip_frame_alloc_mapped;
 use cantrip_security_interface::*;
 use core::mem::size_of;
 use hashbrown::HashMap;
 use log::info;
 use mailbox_driver::*;
 
-const CAPACITY_KEYS: usize = 2; // Per-bundle HashMap of key-values
-
 struct SecBundleData {
-    keys: HashMap<String, KeyValueData>, // NB: emulate until SEC has support
+    keys: KeyStore, // NB: emulate until SEC has support
 }
 impl SecBundleData {
     fn new() -> Self {
         Self {
-            keys: HashMap::with_capacity(CAPACITY_KEYS),
+            keys: KeyStore::new(),
         }
     }
 }
@@ -108,11 +104,7 @@ impl SecurityManagerInterface for SecSecurityManager {
 
     // NB: key-value ops require a load'd bundle so only do get_bundle
     fn read_key(&self, bundle_id: &str, key: &str) -> Result<&KeyValueData, SecurityRequestError> {
-        let bundle = self.get_bundle(bundle_id)?;
-        bundle
-            .keys
-            .get(key)
-            .ok_or(SecurityRequestError::KeyNotFound)
+        self.get_bundle(bundle_id)?.keys.read(key)
     }
     fn write_key(
         &mut self,
@@ -120,17 +112,18 @@ impl SecurityManagerInterface for SecSecurityManager {
         key: &str,
         value: &[u8],
     ) -> Result<(), SecurityRequestError> {
-        let bundle = self.get_bundle_mut(bundle_id)?;
-        let mut keyval = [0u8; KEY_VALUE_DATA_SIZE];
-        keyval[..value.len()].copy_from_slice(value);
-        let _ = bundle.keys.insert(key.to_string(), keyval);
-        Ok(())
+        self.get_bundle_mut(bundle_id)?.keys.write(key, value)
     }
     fn delete_key(&mut self, bundle_id: &str, key: &str) -> Result<(), SecurityRequestError> {
-        let bundle = self.get_bundle_mut(bundle_id)?;
-        // TODO(sleffler): error if no entry?
-        let _ = bundle.keys.remove(key);
-        Ok(())
+        self.get_bundle_mut(bundle_id)?.keys.delete(key)
+    }
+    fn list_keys(&self, bundle_id: &str) -> Result<KeyNameArray, SecurityRequestError> {
+        Ok(self.get_bundle(bundle_id)?.keys.list())
+    }
+    fn sync_keys(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
+        // NB: keys are emulated in memory until the SEC has support
+        let _ = self.get_bundle(bundle_id)?;
+        Err(SecurityRequestError::NoPlatformSupport)
     }
 
     fn test(&self, count: usize) -> Result<(), SecurityRequestError> {
@@ -140,51 +133,39 @@ impl SecurityManagerInterface for SecSecurityManager {
             return Err(SecurityRequestError::TestFailed);
         }
 
-        fn test_mailbox(
-            count: usize,
-            frame_bundle: &ObjDescBundle,
-        ) -> Result<(), SecurityRequestError> {
-            // Map the message buffer using an existing copyregion.
-            extern "Rust" {
-                fn get_deep_copy_src_mut() -> &'static mut [u8];
-            }
-            let mut msg_region = unsafe { CopyRegion::new(get_deep_copy_src_mut()) };
-            msg_region.map(frame_bundle.objs[0].cptr).expect("map");
-
-            let msg = msg_region.as_word_mut();
-
-            // Write initial values; we expect the SEC to overwrite.
-            let first = 0;
-            let last = count - 1;
-            msg[first] = 0xDEADBEEF;
-            msg[last] = 0xF00DCAFE;
-
-            let sent_bytes = (count * size_of::<u32>()) as u32;
-            let recv_bytes =
-                mbox_test(frame_bundle.objs[0].cptr, sent_bytes).expect("mailbox_test");
-            if recv_bytes != sent_bytes {
-                info!("sent bytes {} != recv bytes {}", sent_bytes, recv_bytes);
-            }
-
-            // The security core should have replaced the first and last dwords
-            // in msg with 0x12345678 and 0x87654321.
-            if msg[first] != 0x12345678 || msg[last] != 0x87654321 {
-                info!("initial data:  0xdeadbeef 0xf00dcafe");
-                info!("expected data: 0x12345678 0x87654321");
-                info!("received data: {:#08x} {:#08x}", msg[first], msg[last]);
-                Err(SecurityRequestError::TestFailed)
-            } else {
-                Ok(())
-            }
-            // NB: msg_region unmapped on drop
+        // Allocate a 4k page to serve as our message buffer and map it
+        // using an existing copyregion; it is unmapped & freed on drop.
+        extern "Rust" {
+            fn get_deep_copy_src_mut() -> &'static mut [u8];
+        }
+        let mut msg_frames = cantrip_frame_alloc_mapped(4096, unsafe { get_deep_copy_src_mut() })
+            .or(Err(SecurityRequestError::CapAllocFailed))?;
+        let msg_cptr = msg_frames.frames().objs[0].cptr;
+        let msg = unsafe {
+            core::slice::from_raw_parts_mut(msg_frames.as_mut_ptr() as *mut u32, MAX_WORDS)
+        };
+
+        // Write initial values; we expect the SEC to overwrite.
+        let first = 0;
+        let last = count - 1;
+        msg[first] = 0xDEADBEEF;
+        msg[last] = 0xF00DCAFE;
+
+        let sent_bytes = (count * size_of::<u32>()) as u32;
+        let recv_bytes = mbox_test(msg_cptr, sent_bytes).expect("mailbox_test");
+        if recv_bytes != sent_bytes {
+            info!("sent bytes {} != recv bytes {}", sent_bytes, recv_bytes);
         }
 
-        // Allocate a 4k page to serve as our message buffer.
-        let frame_bundle =
-            cantrip_frame_alloc(4096).or(Err(SecurityRequestError::CapAllocFailed))?;
-        let result = test_mailbox(count, &frame_bundle);
-        let _ = cantrip_object_free_toplevel(&frame_bundle);
-
-        result
+        // The security core should have replaced the first and last dwords
+        // in msg with 0x12345678 and 0x87654321.
+        if msg[first] != 0x12345678 || msg[last] != 0x87654321 {
+            info!("initial data:  0xdeadbeef 0xf00dcafe");
+            info!("expected data: 0x12345678 0x87654321");
+            info!("received data: {:#08x} {:#08x}", msg[first], msg[last]);
+            Err(SecurityRequestError::TestFailed)
+        } else {
+            Ok(())
+        }
     }
 }
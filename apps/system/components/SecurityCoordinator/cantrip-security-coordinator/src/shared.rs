@@ -0,0 +1,203 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Model frames shared between loads.
+//!
+//! seL4 has no copy-on-write so sharing is by request: a client that
+//! only reads a model (e.g. the MlCoordinator copying it to the TCM)
+//! asks for a shared load and gets read-only dups of a master copy that
+//! is made on first use. A client that writes uses load_model and gets
+//! a private copy. Masters are kept per bundle so one bundle never
+//! sees another's copy of a like-named model; they are released when
+//! the bundle is uninstalled.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+pub struct SharedModels<F> {
+    masters: HashMap<String, HashMap<String, F>>, // bundle_id -> model_id -> master
+}
+impl<F> Default for SharedModels<F> {
+    fn default() -> Self { Self::new() }
+}
+impl<F> SharedModels<F> {
+    pub fn new() -> Self {
+        Self {
+            masters: HashMap::new(),
+        }
+    }
+
+    /// Returns a dup made with |share| of the master for |model_id|
+    /// loaded by |bundle_id| or None if there is no master.
+    pub fn share<E>(
+        &self,
+        bundle_id: &str,
+        model_id: &str,
+        share: impl FnOnce(&F) -> Result<F, E>,
+    ) -> Option<Result<F, E>> {
+        self.masters
+            .get(bundle_id)
+            .and_then(|models| models.get(model_id))
+            .map(share)
+    }
+
+    /// Records |master| as the copy of |model_id| loaded by |bundle_id|
+    /// to share and returns a dup made with |share|.
+    pub fn insert_and_share<E>(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+        master: F,
+        share: impl FnOnce(&F) -> Result<F, E>,
+    ) -> Result<F, E> {
+        share(
+            self.masters
+                .entry(bundle_id.to_string())
+                .or_default()
+                .entry(model_id.to_string())
+                .or_insert(master),
+        )
+    }
+
+    /// Removes and returns all masters loaded by |bundle_id|.
+    pub fn remove_bundle(&mut self, bundle_id: &str) -> Vec<F> {
+        self.masters
+            .remove(bundle_id)
+            .map_or_else(Vec::new, |models| models.into_values().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Frames {
+        ids: Vec<usize>,
+        read_only: bool,
+    }
+
+    // Hands out frame ids; |allocated| counts frames allocated.
+    #[derive(Default)]
+    struct FakeMemory {
+        allocated: usize,
+    }
+    impl FakeMemory {
+        // A deep copy allocates new frames.
+        fn copy(&mut self, count: usize) -> Frames {
+            let ids = (self.allocated..self.allocated + count).collect();
+            self.allocated += count;
+            Frames {
+                ids,
+                read_only: false,
+            }
+        }
+    }
+
+    // A dup references the same frames.
+    fn dup(master: &Frames) -> Result<Frames, ()> {
+        Ok(Frames {
+            ids: master.ids.clone(),
+            read_only: true,
+        })
+    }
+
+    // Mimics load_shared_model.
+    fn load_shared_by(
+        models: &mut SharedModels<Frames>,
+        memory: &mut FakeMemory,
+        bundle_id: &str,
+    ) -> Frames {
+        if let Some(frames) = models.share(bundle_id, "model", dup) {
+            return frames.unwrap();
+        }
+        let master = memory.copy(3);
+        models
+            .insert_and_share(bundle_id, "model", master, dup)
+            .unwrap()
+    }
+    fn load_shared(models: &mut SharedModels<Frames>, memory: &mut FakeMemory) -> Frames {
+        load_shared_by(models, memory, "hello")
+    }
+
+    #[test]
+    fn shared_load() {
+        let mut models = SharedModels::new();
+        let mut memory = FakeMemory::default();
+
+        // The first app's load makes the master copy.
+        let first = load_shared(&mut models, &mut memory);
+        assert_eq!(memory.allocated, 3);
+        assert!(first.read_only);
+
+        // The second app's load shares the same frames.
+        let second = load_shared(&mut models, &mut memory);
+        assert_eq!(memory.allocated, 3);
+        assert!(second.read_only);
+        assert_eq!(first.ids, second.ids);
+
+        // A writer gets a private copy (see load_model).
+        let writer = memory.copy(3);
+        assert_eq!(memory.allocated, 6);
+        assert!(!writer.read_only);
+        assert!(writer.ids.iter().all(|id| !first.ids.contains(id)));
+
+        // Readers continue to share the master.
+        assert_eq!(load_shared(&mut models, &mut memory).ids, first.ids);
+        assert_eq!(memory.allocated, 6);
+    }
+
+    #[test]
+    fn remove_master() {
+        let mut models = SharedModels::new();
+        let mut memory = FakeMemory::default();
+        let first = load_shared(&mut models, &mut memory);
+
+        // Once removed (e.g. uninstalled) the next load makes a new master.
+        let removed = models.remove_bundle("hello");
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].ids, first.ids);
+        assert!(models.remove_bundle("hello").is_empty());
+        let next = load_shared(&mut models, &mut memory);
+        assert_eq!(memory.allocated, 6);
+        assert!(next.ids.iter().all(|id| !first.ids.contains(id)));
+    }
+
+    #[test]
+    fn masters_per_bundle() {
+        let mut models = SharedModels::new();
+        let mut memory = FakeMemory::default();
+
+        // A like-named model loaded by another bundle is not shared.
+        let hello = load_shared_by(&mut models, &mut memory, "hello");
+        let world = load_shared_by(&mut models, &mut memory, "world");
+        assert_eq!(memory.allocated, 6);
+        assert!(world.ids.iter().all(|id| !hello.ids.contains(id)));
+
+        // Each bundle's masters are removed together and only its own.
+        let master = memory.copy(3);
+        let other = models
+            .insert_and_share("hello", "other", master, dup)
+            .unwrap();
+        let mut removed: Vec<_> = models
+            .remove_bundle("hello")
+            .into_iter()
+            .map(|frames| frames.ids)
+            .collect();
+        removed.sort();
+        assert_eq!(removed, [hello.ids, other.ids]);
+        assert_eq!(load_shared_by(&mut models, &mut memory, "world").ids, world.ids);
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Package signature verification.
+//!
+//! Packages are signed with a detached ed25519 signature. Builtin
+//! packages have it stored next to the package as <bundle_id>.sig;
+//! dynamically installed apps and uploaded models pass it with the
+//! install request and it is kept with the bundle. Package contents
+//! are fed to the verifier as they are read so nothing beyond a page
+//! needs to be resident at once.
+
+use cantrip_security_interface::SecurityRequestError;
+use ed25519_dalek::Signature;
+use ed25519_dalek::StreamVerifier;
+use ed25519_dalek::VerifyingKey;
+
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+pub const SIGNATURE_LEN: usize = ed25519_dalek::SIGNATURE_LENGTH;
+pub const PUBLIC_KEY_LEN: usize = ed25519_dalek::PUBLIC_KEY_LENGTH;
+
+pub struct PackageVerifier(StreamVerifier);
+impl PackageVerifier {
+    // Starts verifying a package against |signature| made with the
+    // private half of |public_key|.
+    pub fn new(
+        public_key: &[u8; PUBLIC_KEY_LEN],
+        signature: &[u8],
+    ) -> Result<Self, SecurityRequestError> {
+        let key =
+            VerifyingKey::from_bytes(public_key).or(Err(SecurityRequestError::SignatureInvalid))?;
+        let signature =
+            Signature::from_slice(signature).or(Err(SecurityRequestError::SignatureInvalid))?;
+        key.verify_stream(&signature)
+            .map(Self)
+            .or(Err(SecurityRequestError::SignatureInvalid))
+    }
+
+    // Adds the next chunk of package data.
+    pub fn update(&mut self, chunk: &[u8]) { self.0.update(chunk); }
+
+    // Checks the package data against the signature.
+    pub fn verify(self) -> Result<(), SecurityRequestError> {
+        self.0
+            .finalize_and_verify()
+            .or(Err(SecurityRequestError::SignatureInvalid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Signer;
+    use ed25519_dalek::SigningKey;
+
+    const PAGE_SIZE: usize = 4096;
+
+    fn signing_key() -> SigningKey { SigningKey::from_bytes(&[7u8; 32]) }
+
+    fn package() -> Vec<u8> { (0..3 * PAGE_SIZE + 123).map(|x| x as u8).collect() }
+
+    // Verifies |data| a page at a time like the upload path.
+    fn verify(
+        public_key: &[u8; PUBLIC_KEY_LEN],
+        signature: &[u8],
+        data: &[u8],
+    ) -> Result<(), SecurityRequestError> {
+        let mut verifier = PackageVerifier::new(public_key, signature)?;
+        for chunk in data.chunks(PAGE_SIZE) {
+            verifier.update(chunk);
+        }
+        verifier.verify()
+    }
+
+    #[test]
+    fn signed_package() {
+        let key = signing_key();
+        let data = package();
+        let signature = key.sign(&data).to_bytes();
+        assert_eq!(verify(key.verifying_key().as_bytes(), &signature, &data), Ok(()));
+    }
+
+    #[test]
+    fn unsigned_package() {
+        let key = signing_key();
+        let data = package();
+        assert_eq!(
+            verify(key.verifying_key().as_bytes(), &[], &data),
+            Err(SecurityRequestError::SignatureInvalid)
+        );
+        assert_eq!(
+            verify(key.verifying_key().as_bytes(), &[0u8; SIGNATURE_LEN], &data),
+            Err(SecurityRequestError::SignatureInvalid)
+        );
+    }
+
+    #[test]
+    fn tampered_package() {
+        let key = signing_key();
+        let mut data = package();
+        let signature = key.sign(&data).to_bytes();
+        data[PAGE_SIZE + 1] ^= 1;
+        assert_eq!(
+            verify(key.verifying_key().as_bytes(), &signature, &data),
+            Err(SecurityRequestError::SignatureInvalid)
+        );
+    }
+
+    #[test]
+    fn truncated_package() {
+        let key = signing_key();
+        let data = package();
+        let signature = key.sign(&data).to_bytes();
+        assert_eq!(
+            verify(key.verifying_key().as_bytes(), &signature, &data[..2 * PAGE_SIZE]),
+            Err(SecurityRequestError::SignatureInvalid)
+        );
+    }
+
+    #[test]
+    fn wrong_key() {
+        let key = signing_key();
+        let data = package();
+        let signature = key.sign(&data).to_bytes();
+        let other = SigningKey::from_bytes(&[8u8; 32]);
+        assert_eq!(
+            verify(other.verifying_key().as_bytes(), &signature, &data),
+            Err(SecurityRequestError::SignatureInvalid)
+        );
+    }
+}
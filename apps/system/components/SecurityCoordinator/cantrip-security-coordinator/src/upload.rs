@@ -13,6 +13,7 @@
 // limitations under the License.
 
 extern crate alloc;
+use crate::hash::PackageHasher;
 use alloc::vec;
 use cantrip_memory_interface::cantrip_frame_alloc;
 use cantrip_memory_interface::ObjDesc;
@@ -32,32 +33,72 @@ pub enum UploadError {
     MallocFailed,
     MoveFailed,
     ReadFailed,
+    SignatureInvalid,
+    HashMismatch,
 }
 
 extern "C" {
     static SELF_CNODE: seL4_CPtr;
 }
 
+// Returns an empty bundle for frames collected in the top-level CNode.
+pub fn toplevel_frames() -> ObjDescBundle {
+    ObjDescBundle::new(unsafe { SELF_CNODE }, seL4_WordBits as u8, vec![])
+}
+
+// Writes |data| at |offset| in top-level |frames|, allocating frames as
+// the data grow; |offset| must be within or just past the last frame.
+// Each frame is mapped through |region| only while it is written so
+// the region may be used for other work between writes.
+// NB: unit tests substitute the caller and have no frames to write.
+#[cfg_attr(test, allow(dead_code))]
+pub fn write_frames(
+    frames: &mut ObjDescBundle,
+    region: &mut CopyRegion,
+    mut offset: usize,
+    data: &[u8],
+) -> Result<(), UploadError> {
+    let frame_size = region.size();
+    let mut cursor = data;
+    while !cursor.is_empty() {
+        let index = offset / frame_size;
+        if index == frames.count() {
+            let new_page = cantrip_frame_alloc(frame_size).or(Err(UploadError::MallocFailed))?;
+            assert_eq!(new_page.cnode, frames.cnode);
+            frames.objs.push(new_page.objs[0]);
+            frames.maybe_combine_last();
+        }
+        let frame = frames.cptr_iter().nth(index).unwrap();
+        region.map(frame).or(Err(UploadError::PageMap))?;
+        let start = offset % frame_size;
+        let bytes_to_write = cmp::min(frame_size - start, cursor.len());
+        region.as_mut()[start..start + bytes_to_write].copy_from_slice(&cursor[..bytes_to_write]);
+        region.unmap().or(Err(UploadError::PageUnmap))?;
+        offset += bytes_to_write;
+        cursor = &cursor[bytes_to_write..];
+    }
+    Ok(())
+}
+
 pub struct Upload<'a> {
     frames: ObjDescBundle, // Page frames
     copyregion: CopyRegion<'a>,
-    next_free: usize, // Next available byte in mapped frame
+    next_free: usize,              // Next available byte in mapped frame
+    hasher: Option<PackageHasher>, // Hash of the data as they are copied
 }
 
 // XXX reclaim frames on drop; verify unmap happens
 impl<'a> Upload<'a> {
     pub fn new(region: &'a mut [u8]) -> Self {
         Self {
-            frames: ObjDescBundle::new(
-                // Collect frames in the top-level CNode for now
-                unsafe { SELF_CNODE },
-                seL4_WordBits as u8,
-                vec![],
-            ),
+            frames: toplevel_frames(),
             copyregion: unsafe { CopyRegion::new(region) },
             next_free: 0,
+            hasher: None,
         }
     }
+    // Hashes data written from now on with |hasher|; see check_hash.
+    pub fn hash_with(&mut self, hasher: Option<PackageHasher>) { self.hasher = hasher; }
     #[allow(dead_code)]
     pub fn len(&self) -> usize {
         (self.frames.count() * self.copyregion.size())
@@ -67,6 +108,29 @@ impl<'a> Upload<'a> {
     pub fn frames(&self) -> &ObjDescBundle { &self.frames }
     pub fn frames_mut(&mut self) -> &mut ObjDescBundle { &mut self.frames }
 
+    // Checks the data copied match any expected hash.
+    pub fn check_hash(&mut self) -> Result<(), UploadError> {
+        match self.hasher.take() {
+            Some(hasher) => hasher.verify().or(Err(UploadError::HashMismatch)),
+            None => Ok(()),
+        }
+    }
+
+    // Adds the contents of the mapped frame to any hash; this is for
+    // frames filled directly rather than by write.
+    pub fn hash_mapped_frame(&mut self) {
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(self.copyregion.as_ref());
+        }
+    }
+
+    // Adds |data| to any hash without copying it.
+    pub fn hash(&mut self, data: &[u8]) {
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(data);
+        }
+    }
+
     // Unmap the current page and reset state.
     pub fn unmap_current_frame(&mut self) -> Result<(), UploadError> {
         if self.frames.objs.last().is_some() {
@@ -112,6 +176,9 @@ impl<'a> Upload<'a> {
                         bytes_to_write,
                     )
                 };
+                if let Some(hasher) = self.hasher.as_mut() {
+                    hasher.update(&region[self.next_free..self.next_free + bytes_to_write]);
+                }
                 self.next_free += bytes_to_write;
                 cursor = &cursor[bytes_to_write..];
 
@@ -39,6 +39,9 @@ use sel4_sys::seL4_CPtr;
 use serde_big_array::big_array;
 big_array! { BigArray; }
 
+mod manifest;
+pub use manifest::*;
+
 // Size of the buffers used to pass serialized data between Rust <> C.
 // The data structure size is bounded by the camkes ipc buffer (2K bytes!)
 // and also by it being allocated on the stack of the rpc glue code.
@@ -54,6 +57,11 @@ pub const KEY_VALUE_DATA_SIZE: usize = 100;
 pub type KeyValueData = [u8; KEY_VALUE_DATA_SIZE];
 
 pub type BundleIdArray = Vec<String>;
+pub type KeyNameArray = Vec<String>;
+
+// SHA-256 digest of a package's contents.
+pub const PACKAGE_HASH_LEN: usize = 32;
+pub type PackageHash = [u8; PACKAGE_HASH_LEN];
 
 #[repr(usize)]
 #[derive(Debug, Default, Eq, PartialEq, FromPrimitive, IntoPrimitive)]
@@ -88,6 +96,15 @@ pub enum SecurityRequestError {
     WriteFailed,
     DeleteFailed,
     TestFailed,
+    SignatureInvalid,
+    NoPlatformSupport,
+    ArchiveInvalid,      // Built-in archive is malformed (e.g. truncated)
+    UploadNotFound,      // No model upload in progress
+    UploadOffsetInvalid, // Chunk does not follow the data received
+    UploadIncomplete,    // Commit before all data were received
+    TooManyBundles,      // Installed bundle limit reached; uninstall first
+    HashMismatch,        // Package contents do not match the expected hash
+    UploadQuotaExceeded, // Model uploads exceed the uploader's quota
 }
 impl From<SecurityRequestError> for Result<(), SecurityRequestError> {
     fn from(err: SecurityRequestError) -> Result<(), SecurityRequestError> {
@@ -107,6 +124,7 @@ pub enum SecurityRequest<'a> {
         // Install application
         app_id: &'a str,
         pkg_contents: Cow<'a, ObjDescBundle>,
+        signature: Option<&'a [u8]>,
     },
     InstallModel {
         // Install model
@@ -117,14 +135,23 @@ pub enum SecurityRequest<'a> {
     Uninstall(&'a str), // Uninstall package
     GetPackages,        // Get package names -> BundleIdArray
 
-    SizeBuffer(&'a str),      // Size application image -> u32
-    GetManifest(&'a str),     // Application manifest -> String
-    LoadApplication(&'a str), // Load application -> ObjDescBundle
+    SizeBuffer(&'a str),  // Size application image -> u32
+    GetManifest(&'a str), // Application manifest -> String
+    LoadApplication {
+        // Load application -> ObjDescBundle
+        bundle_id: &'a str,
+        expected_hash: Option<PackageHash>, // Checked against the contents
+    },
     LoadModel {
         // Load ML model -> ObjDescBundle
         bundle_id: &'a str,
         model_id: &'a str,
     },
+    LoadSharedModel {
+        // Load ML model read-only, sharing frames -> ObjDescBundle
+        bundle_id: &'a str,
+        model_id: &'a str,
+    },
 
     ReadKey {
         // Read key value -> value
@@ -142,6 +169,38 @@ pub enum SecurityRequest<'a> {
         bundle_id: &'a str,
         key: &'a str,
     },
+    ListKeys {
+        // List key names -> KeyNameArray
+        bundle_id: &'a str,
+    },
+    SyncKeys {
+        // Persist key-value store to backing storage
+        bundle_id: &'a str,
+    },
+
+    ModelUploadBegin {
+        // Start or resume a model upload -> ModelUploadResponse
+        bundle_id: &'a str,
+        model_id: &'a str,
+        size: usize,
+    },
+    ModelUploadChunk {
+        // Write model data -> ModelUploadResponse
+        bundle_id: &'a str,
+        model_id: &'a str,
+        offset: usize,
+        data: &'a [u8],
+    },
+    ModelUploadCommit {
+        // Install the uploaded model
+        bundle_id: &'a str,
+        model_id: &'a str,
+        signature: Option<&'a [u8]>,
+    },
+    ModelUploadAbort {
+        // Discard any uploads in progress
+        bundle_id: &'a str,
+    },
 
     CapScan, // Dump CNode contents to console
     Test(usize),
@@ -153,6 +212,7 @@ impl<'a> SecurityRequest<'a> {
             | SecurityRequest::InstallApp {
                 app_id: _,
                 pkg_contents,
+                signature: _,
             }
             | SecurityRequest::InstallModel {
                 app_id: _,
@@ -165,11 +225,18 @@ impl<'a> SecurityRequest<'a> {
             | SecurityRequest::GetPackages
             | SecurityRequest::SizeBuffer(_)
             | SecurityRequest::GetManifest(_)
-            | SecurityRequest::LoadApplication(_)
+            | SecurityRequest::LoadApplication {
+                bundle_id: _,
+                expected_hash: _,
+            }
             | SecurityRequest::LoadModel {
                 bundle_id: _,
                 model_id: _,
             }
+            | SecurityRequest::LoadSharedModel {
+                bundle_id: _,
+                model_id: _,
+            }
             | SecurityRequest::ReadKey {
                 bundle_id: _,
                 key: _,
@@ -183,6 +250,25 @@ impl<'a> SecurityRequest<'a> {
                 bundle_id: _,
                 key: _,
             }
+            | SecurityRequest::ListKeys { bundle_id: _ }
+            | SecurityRequest::SyncKeys { bundle_id: _ }
+            | SecurityRequest::ModelUploadBegin {
+                bundle_id: _,
+                model_id: _,
+                size: _,
+            }
+            | SecurityRequest::ModelUploadChunk {
+                bundle_id: _,
+                model_id: _,
+                offset: _,
+                data: _,
+            }
+            | SecurityRequest::ModelUploadCommit {
+                bundle_id: _,
+                model_id: _,
+                signature: _,
+            }
+            | SecurityRequest::ModelUploadAbort { bundle_id: _ }
             | SecurityRequest::CapScan
             | SecurityRequest::Test(_) => None,
         }
@@ -234,6 +320,17 @@ pub struct ReadKeyResponse {
     pub value: KeyValueData,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListKeysResponse {
+    pub keys: KeyNameArray,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModelUploadResponse {
+    // Bytes received so far; the next chunk starts here.
+    pub received: usize,
+}
+
 // Public api's.
 pub trait SecurityCoordinatorInterface {
     // NB: deprecated
@@ -242,6 +339,7 @@ pub trait SecurityCoordinatorInterface {
         &mut self,
         app_id: &str,
         pkg_contents: &ObjDescBundle,
+        signature: Option<&[u8]>,
     ) -> Result<(), SecurityRequestError>;
     fn install_model(
         &mut self,
@@ -254,12 +352,26 @@ pub trait SecurityCoordinatorInterface {
     fn size_buffer(&self, bundle_id: &str) -> Result<usize, SecurityRequestError>;
     // NB: unimplemented
     fn get_manifest(&self, bundle_id: &str) -> Result<String, SecurityRequestError>;
-    fn load_application(&mut self, bundle_id: &str) -> Result<ObjDescBundle, SecurityRequestError>;
+    // Returns a copy of |bundle_id|'s contents. If |expected_hash| is
+    // given the SHA-256 of the contents must match or HashMismatch is
+    // returned.
+    fn load_application(
+        &mut self,
+        bundle_id: &str,
+        expected_hash: Option<&PackageHash>,
+    ) -> Result<ObjDescBundle, SecurityRequestError>;
     fn load_model(
         &mut self,
         bundle_id: &str,
         model_id: &str,
     ) -> Result<ObjDescBundle, SecurityRequestError>;
+    // Like load_model but the frames are read-only and shared with
+    // other loads of the same model.
+    fn load_shared_model(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+    ) -> Result<ObjDescBundle, SecurityRequestError>;
     fn read_key(&self, bundle_id: &str, key: &str) -> Result<&KeyValueData, SecurityRequestError>;
     fn write_key(
         &mut self,
@@ -268,6 +380,32 @@ pub trait SecurityCoordinatorInterface {
         value: &[u8],
     ) -> Result<(), SecurityRequestError>;
     fn delete_key(&mut self, bundle_id: &str, key: &str) -> Result<(), SecurityRequestError>;
+    fn list_keys(&self, bundle_id: &str) -> Result<KeyNameArray, SecurityRequestError>;
+    fn sync_keys(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError>;
+    // Model upload: data are streamed in chunks that must arrive in
+    // order; begin returns where to (re)start. On commit the model is
+    // installed for use by |bundle_id| only; builtins may not be
+    // replaced and uploads are limited per bundle.
+    fn model_upload_begin(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+        size: usize,
+    ) -> Result<usize, SecurityRequestError>;
+    fn model_upload_chunk(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<usize, SecurityRequestError>;
+    fn model_upload_commit(
+        &mut self,
+        bundle_id: &str,
+        model_id: &str,
+        signature: Option<&[u8]>,
+    ) -> Result<(), SecurityRequestError>;
+    fn model_upload_abort(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError>;
     fn test(&self, count: usize) -> Result<(), SecurityRequestError>;
 }
 
@@ -307,10 +445,14 @@ pub fn cantrip_security_install(
         .map(|reply: InstallResponse| reply.bundle_id)
 }
 
+/// Installs application |app_id| from |pkg_contents|. |signature| is the
+/// package's detached signature; it is required when the SecurityCoordinator
+/// checks signatures and otherwise ignored.
 #[inline]
 pub fn cantrip_security_install_application(
     app_id: &str,
     pkg_contents: &ObjDescBundle,
+    signature: Option<&[u8]>,
 ) -> Result<(), SecurityRequestError> {
     Camkes::debug_assert_slot_cnode(
         "cantrip_security_install_application",
@@ -319,6 +461,7 @@ pub fn cantrip_security_install_application(
     cantrip_security_request(&SecurityRequest::InstallApp {
         app_id,
         pkg_contents: Cow::Borrowed(pkg_contents),
+        signature,
     })
 }
 
@@ -365,6 +508,7 @@ pub fn cantrip_security_get_manifest(bundle_id: &str) -> Result<String, Security
 #[inline]
 pub fn cantrip_security_load_application(
     bundle_id: &str,
+    expected_hash: Option<&PackageHash>,
     container_slot: &CSpaceSlot,
 ) -> Result<ObjDescBundle, SecurityRequestError> {
     let _cleanup = container_slot.push_recv_path();
@@ -378,9 +522,11 @@ pub fn cantrip_security_load_application(
         sel4_sys::cap_identify(container_slot.slot)
     );
 
-    let mut reply = cantrip_security_request::<LoadApplicationResponse>(
-        &SecurityRequest::LoadApplication(bundle_id),
-    )?;
+    let mut reply =
+        cantrip_security_request::<LoadApplicationResponse>(&SecurityRequest::LoadApplication {
+            bundle_id,
+            expected_hash: expected_hash.copied(),
+        })?;
     sel4_sys::debug_assert_slot_cnode!(container_slot.slot);
     reply.bundle_frames.cnode = container_slot.slot;
     Ok(reply.bundle_frames)
@@ -411,6 +557,34 @@ pub fn cantrip_security_load_model(
     Ok(reply.model_frames)
 }
 
+// Like cantrip_security_load_model but the frames are read-only dups of
+// a copy cached by the SecurityCoordinator for all loads of |model_id|.
+// The frames must not be freed; release them with cantrip_cnode_free
+// which frees only the container.
+#[inline]
+pub fn cantrip_security_load_shared_model(
+    bundle_id: &str,
+    model_id: &str,
+    container_slot: &CSpaceSlot,
+) -> Result<ObjDescBundle, SecurityRequestError> {
+    let _cleanup = container_slot.push_recv_path();
+    sel4_sys::debug_assert_slot_empty!(
+        container_slot.slot,
+        "Expected slot {:?} empty but has {:?}",
+        &container_slot.get_path(),
+        sel4_sys::cap_identify(container_slot.slot)
+    );
+
+    let mut reply =
+        cantrip_security_request::<LoadModelResponse>(&SecurityRequest::LoadSharedModel {
+            bundle_id,
+            model_id,
+        })?;
+    sel4_sys::debug_assert_slot_cnode!(container_slot.slot);
+    reply.model_frames.cnode = container_slot.slot;
+    Ok(reply.model_frames)
+}
+
 #[inline]
 pub fn cantrip_security_read_key(
     bundle_id: &str,
@@ -438,6 +612,67 @@ pub fn cantrip_security_delete_key(bundle_id: &str, key: &str) -> Result<(), Sec
     cantrip_security_request(&SecurityRequest::DeleteKey { bundle_id, key })
 }
 
+#[inline]
+pub fn cantrip_security_list_keys(bundle_id: &str) -> Result<KeyNameArray, SecurityRequestError> {
+    cantrip_security_request(&SecurityRequest::ListKeys { bundle_id })
+        .map(|reply: ListKeysResponse| reply.keys)
+}
+
+#[inline]
+pub fn cantrip_security_sync_keys(bundle_id: &str) -> Result<(), SecurityRequestError> {
+    cantrip_security_request(&SecurityRequest::SyncKeys { bundle_id })
+}
+
+// Starts uploading |size| bytes of |model_id| for |bundle_id| or resumes
+// an upload already in progress. Returns the offset of the next chunk.
+#[inline]
+pub fn cantrip_security_model_upload_begin(
+    bundle_id: &str,
+    model_id: &str,
+    size: usize,
+) -> Result<usize, SecurityRequestError> {
+    cantrip_security_request(&SecurityRequest::ModelUploadBegin {
+        bundle_id,
+        model_id,
+        size,
+    })
+    .map(|reply: ModelUploadResponse| reply.received)
+}
+
+#[inline]
+pub fn cantrip_security_model_upload_chunk(
+    bundle_id: &str,
+    model_id: &str,
+    offset: usize,
+    data: &[u8],
+) -> Result<usize, SecurityRequestError> {
+    cantrip_security_request(&SecurityRequest::ModelUploadChunk {
+        bundle_id,
+        model_id,
+        offset,
+        data,
+    })
+    .map(|reply: ModelUploadResponse| reply.received)
+}
+
+#[inline]
+pub fn cantrip_security_model_upload_commit(
+    bundle_id: &str,
+    model_id: &str,
+    signature: Option<&[u8]>,
+) -> Result<(), SecurityRequestError> {
+    cantrip_security_request(&SecurityRequest::ModelUploadCommit {
+        bundle_id,
+        model_id,
+        signature,
+    })
+}
+
+#[inline]
+pub fn cantrip_security_model_upload_abort(bundle_id: &str) -> Result<(), SecurityRequestError> {
+    cantrip_security_request(&SecurityRequest::ModelUploadAbort { bundle_id })
+}
+
 #[inline]
 pub fn cantrip_security_capscan() -> Result<(), SecurityRequestError> {
     cantrip_security_request(&SecurityRequest::CapScan)
@@ -0,0 +1,235 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Application manifests.
+//!
+//! A manifest is text with one "key = value" setting per line; blank
+//! lines and lines starting with '#' are ignored. Values are decimal or
+//! 0x-prefixed hex numbers. The settings are:
+//!
+//!   entrypoint  address where the application starts running
+//!   memory      bytes of memory the application may use
+//!
+//! Both are required. The application's SDKRuntime limits may also be
+//! set (see ResourceLimits in cantrip-sdk-manager); any left unset are
+//! unlimited:
+//!
+//!   max_timers         concurrently active timers
+//!   max_models         concurrently loaded models
+//!   max_audio_bytes    total record+play buffer space
+//!   log_bytes_per_sec  sustained log output
+//!   max_request_ms     server-side time spent on one request
+//!
+//! Unknown keys are skipped so a manifest may carry settings for newer
+//! consumers. GetManifest returns the manifest in
+//! canonical form (the Display output) which Manifest::parse accepts.
+//!
+//! A builtin bundle's manifest is a separate file in the builtin archive
+//! named by appending MANIFEST_SUFFIX to the bundle id. A dynamically
+//! installed package carries its manifest at the front: MANIFEST_MAGIC,
+//! the length of the text as a little-endian u32, then the text. The
+//! manifest is stripped before the package reaches the loader; a package
+//! without one loads as-is.
+
+use core::fmt;
+use core::str;
+
+pub const MANIFEST_SUFFIX: &str = "/manifest";
+pub const MANIFEST_MAGIC: &[u8; 4] = b"MNFT";
+pub const MANIFEST_HEADER_SIZE: usize = MANIFEST_MAGIC.len() + 4;
+pub const MAX_MANIFEST_SIZE: usize = 1024; // Fits in a GetManifest reply
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Manifest {
+    pub entrypoint: usize,
+    pub memory_bytes: usize,
+    pub limits: ManifestLimits,
+}
+
+/// SDKRuntime limits set by a manifest; None is unlimited.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct ManifestLimits {
+    pub max_timers: Option<usize>,
+    pub max_models: Option<usize>,
+    pub max_audio_bytes: Option<usize>,
+    pub log_bytes_per_sec: Option<usize>,
+    pub max_request_ms: Option<usize>,
+}
+impl Manifest {
+    /// Returns the manifest described by |text| or None if a required
+    /// setting is missing or any setting is malformed or repeated.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut entrypoint = None;
+        let mut memory_bytes = None;
+        let mut limits = ManifestLimits::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once('=')?;
+            let setting = match key.trim() {
+                "entrypoint" => &mut entrypoint,
+                "memory" => &mut memory_bytes,
+                "max_timers" => &mut limits.max_timers,
+                "max_models" => &mut limits.max_models,
+                "max_audio_bytes" => &mut limits.max_audio_bytes,
+                "log_bytes_per_sec" => &mut limits.log_bytes_per_sec,
+                "max_request_ms" => &mut limits.max_request_ms,
+                _ => continue,
+            };
+            if setting.replace(parse_number(value.trim())?).is_some() {
+                return None;
+            }
+        }
+        Some(Self {
+            entrypoint: entrypoint?,
+            memory_bytes: memory_bytes?,
+            limits,
+        })
+    }
+
+    /// Returns the manifest text at the front of a package that starts
+    /// with |data|. None is returned if there is no manifest or |data|
+    /// does not hold all of it.
+    pub fn find_leading(data: &[u8]) -> Option<&str> {
+        let header = data.get(..MANIFEST_HEADER_SIZE)?;
+        if &header[..MANIFEST_MAGIC.len()] != MANIFEST_MAGIC {
+            return None;
+        }
+        let len = u32::from_le_bytes(header[MANIFEST_MAGIC.len()..].try_into().ok()?) as usize;
+        if len > MAX_MANIFEST_SIZE {
+            return None;
+        }
+        let text = data.get(MANIFEST_HEADER_SIZE..MANIFEST_HEADER_SIZE + len)?;
+        str::from_utf8(text).ok()
+    }
+
+    /// Returns the number of bytes taken by a manifest at the front of a
+    /// package that starts with |data|; 0 if there is no manifest. These
+    /// bytes are not part of the loadable image.
+    pub fn leading_len(data: &[u8]) -> usize {
+        Self::find_leading(data).map_or(0, |text| MANIFEST_HEADER_SIZE + text.len())
+    }
+}
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "entrypoint = {:#x}", self.entrypoint)?;
+        writeln!(f, "memory = {}", self.memory_bytes)?;
+        let limits = &self.limits;
+        for (key, value) in [
+            ("max_timers", limits.max_timers),
+            ("max_models", limits.max_models),
+            ("max_audio_bytes", limits.max_audio_bytes),
+            ("log_bytes_per_sec", limits.log_bytes_per_sec),
+            ("max_request_ms", limits.max_request_ms),
+        ] {
+            if let Some(value) = value {
+                writeln!(f, "{} = {}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_number(value: &str) -> Option<usize> {
+    match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn parse() {
+        let manifest = Manifest::parse(
+            "# hello.app\n\
+             entrypoint = 0x10074\n\
+             \n\
+             memory=65536\n\
+             stack = 4096\n",
+        );
+        assert_eq!(
+            manifest,
+            Some(Manifest {
+                entrypoint: 0x10074,
+                memory_bytes: 65536,
+                limits: ManifestLimits::default(),
+            })
+        );
+
+        // The canonical form parses back to the same manifest.
+        let manifest = manifest.unwrap();
+        assert_eq!(Manifest::parse(&manifest.to_string()), Some(manifest));
+    }
+
+    #[test]
+    fn parse_limits() {
+        let manifest = Manifest::parse(
+            "entrypoint = 0x10074\n\
+             memory = 65536\n\
+             max_timers = 2\n\
+             log_bytes_per_sec = 0x400\n",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.limits,
+            ManifestLimits {
+                max_timers: Some(2),
+                log_bytes_per_sec: Some(1024),
+                ..ManifestLimits::default()
+            }
+        );
+        assert_eq!(Manifest::parse(&manifest.to_string()), Some(manifest));
+
+        // Limits are held to the same rules as other settings.
+        assert_eq!(Manifest::parse("entrypoint = 1\nmemory = 1\nmax_models = x\n"), None);
+        assert_eq!(
+            Manifest::parse("entrypoint = 1\nmemory = 1\nmax_models = 1\nmax_models = 2\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_malformed() {
+        assert_eq!(Manifest::parse(""), None);
+        assert_eq!(Manifest::parse("entrypoint = 0x1000\n"), None);
+        assert_eq!(Manifest::parse("entrypoint = 0x1000\nmemory\n"), None);
+        assert_eq!(Manifest::parse("entrypoint = start\nmemory = 1\n"), None);
+        assert_eq!(Manifest::parse("entrypoint = 1\nmemory = 1\nmemory = 2\n"), None);
+    }
+
+    #[test]
+    fn find_leading() {
+        let text = "entrypoint = 0x1000\nmemory = 4096\n";
+        let mut package = Vec::from(&MANIFEST_MAGIC[..]);
+        package.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        package.extend_from_slice(text.as_bytes());
+        package.extend_from_slice(b"\x7fELF");
+        assert_eq!(Manifest::find_leading(&package), Some(text));
+        assert_eq!(Manifest::leading_len(&package), MANIFEST_HEADER_SIZE + text.len());
+        assert_eq!(&package[Manifest::leading_len(&package)..], b"\x7fELF");
+
+        // Truncated or missing manifests are not found.
+        assert_eq!(Manifest::find_leading(&package[..MANIFEST_HEADER_SIZE + 4]), None);
+        assert_eq!(Manifest::find_leading(b"\x7fELF"), None);
+        assert_eq!(Manifest::leading_len(b"\x7fELF"), 0);
+        package[4..8].copy_from_slice(&(MAX_MANIFEST_SIZE as u32 + 1).to_le_bytes());
+        assert_eq!(Manifest::find_leading(&package), None);
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use crate::mailbox::*;
+use crate::proto::ReplyWait;
 use cantrip_os_common::camkes::semaphore::seL4_Semaphore;
 use log::{error, trace};
 
@@ -20,6 +21,12 @@ extern "Rust" {
     static RX_SEMAPHORE: seL4_Semaphore;
 }
 
+/// Waits for the rtirq handler to signal a reply has arrived.
+pub struct SemaphoreWait;
+impl ReplyWait for SemaphoreWait {
+    fn wait() { unsafe { RX_SEMAPHORE.wait() } }
+}
+
 // IRQ Support.
 
 // WTIRQ: interrupt for outbox.count > write_threshold.
@@ -16,6 +16,8 @@
 
 #[allow(dead_code)]
 mod mailbox;
+mod msg;
+pub use msg::MsgLimits;
 mod proto;
 pub use proto::*;
 
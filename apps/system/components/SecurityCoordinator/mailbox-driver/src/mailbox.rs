@@ -14,6 +14,7 @@
 
 // Helpers to read/write Mailbox MMIO registers.
 
+use cantrip_os_common::sel4_sys::seL4_Yield;
 use modular_bitfield::prelude::*;
 use reg_constants::mailbox::*;
 
@@ -53,20 +54,44 @@ fn delay(count: usize) -> usize {
     j
 }
 
-pub fn enqueue(x: u32) {
-    while get_status().full() {
-        #[cfg(feature = "rootserver")]
-        delay(1000);
+// Status polls before a busy-wait starts yielding the CPU. Most waits
+// are satisfied within a few polls; past this the SEC is slow (e.g. at
+// boot) and other threads should be allowed to run.
+const SPIN_LIMIT: usize = 64;
+
+// Busy-waits while |busy| returns true, calling |yield_cpu| between
+// polls once SPIN_LIMIT is reached. Returns the number of polls that
+// found |busy| true.
+fn wait_while(mut busy: impl FnMut() -> bool, mut yield_cpu: impl FnMut()) -> usize {
+    let mut polls = 0;
+    while busy() {
+        polls += 1;
+        if polls < SPIN_LIMIT {
+            #[cfg(feature = "rootserver")]
+            delay(1000);
+            core::hint::spin_loop();
+        } else {
+            yield_cpu();
+        }
     }
+    polls
+}
+fn yield_cpu() { unsafe { seL4_Yield() } }
+
+pub fn enqueue(x: u32) {
+    wait_while(|| get_status().full(), yield_cpu);
     set_mboxw(x);
 }
 pub fn dequeue() -> u32 {
-    while get_status().empty() {
-        #[cfg(feature = "rootserver")]
-        delay(1000);
-    }
+    wait_while(|| get_status().empty(), yield_cpu);
     get_mboxr()
 }
+// Discards whatever is in the inbound FIFO; used to resync with the SEC.
+pub fn drain() {
+    while !get_status().empty() {
+        let _ = get_mboxr();
+    }
+}
 
 // Interrupt State register.
 #[bitfield]
@@ -284,6 +309,46 @@ mod tests {
     fn bit(x: u32) -> u32 { 1 << x }
     fn field(v: u32, mask: u32, shift: usize) -> u32 { (v & mask) << shift }
 
+    // A FIFO that stays empty for |ready_after| polls.
+    struct MockFifo {
+        polls: usize,
+        ready_after: usize,
+    }
+    impl MockFifo {
+        fn empty(&mut self) -> bool {
+            self.polls += 1;
+            self.polls <= self.ready_after
+        }
+    }
+
+    #[test]
+    fn wait_spins() {
+        let mut fifo = MockFifo {
+            polls: 0,
+            ready_after: SPIN_LIMIT / 2,
+        };
+        let mut yields = 0;
+        assert_eq!(wait_while(|| fifo.empty(), || yields += 1), SPIN_LIMIT / 2);
+        assert_eq!(yields, 0);
+    }
+    #[test]
+    fn wait_yields() {
+        let mut fifo = MockFifo {
+            polls: 0,
+            ready_after: SPIN_LIMIT + 10,
+        };
+        let mut yields = 0;
+        assert_eq!(wait_while(|| fifo.empty(), || yields += 1), SPIN_LIMIT + 10);
+        // Every poll past the limit gives up the CPU.
+        assert_eq!(yields, 11);
+    }
+    #[test]
+    fn wait_ready() {
+        let mut yields = 0;
+        assert_eq!(wait_while(|| false, || yields += 1), 0);
+        assert_eq!(yields, 0);
+    }
+
     #[test]
     fn intr_state() {
         assert_eq!(
@@ -0,0 +1,307 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Mailbox message framing and size limits.
+//!
+//! Messages pass through the mailbox FIFOs as a header word holding the
+//! message length in bytes followed by the message padded to a whole
+//! number of words. The largest message either side will handle is
+//! agreed with the Security Core at startup; see mbox_negotiate.
+//!
+//! If the Security Core agrees (see MsgLimits::seq_numbers) the header
+//! also carries an 8-bit sequence number; a reply echoes the sequence
+//! number of its request so the driver can tell when it has lost
+//! lockstep with the Security Core (e.g. after a dropped irq). Otherwise
+//! the header is the legacy one: just the length.
+
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::vec;
+use core::convert::TryInto;
+use core::mem::size_of;
+use serde::{Deserialize, Serialize};
+
+/// Size of the driver's message buffer; the most the driver offers
+/// when negotiating limits.
+pub const MAX_MSG_BYTES: usize = 1024;
+
+/// Largest reply accepted by reading it into a heap buffer when it does
+/// not fit in the message buffer; bounds what a bad header can allocate.
+#[cfg(feature = "alloc")]
+pub const MAX_SPILL_BYTES: usize = 16 * 1024;
+
+/// Message limits agreed with the Security Core.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MsgLimits {
+    pub max_msg_bytes: u32, // Largest message passed through the FIFOs
+    pub seq_numbers: bool,  // Headers carry sequence numbers
+}
+impl MsgLimits {
+    /// Limits that apply before (or without) negotiation.
+    pub const LEGACY: MsgLimits = MsgLimits {
+        max_msg_bytes: 256,
+        seq_numbers: false,
+    };
+
+    /// Limits the driver offers.
+    pub const DRIVER: MsgLimits = MsgLimits {
+        max_msg_bytes: MAX_MSG_BYTES as u32,
+        seq_numbers: true,
+    };
+
+    /// Returns the limits supported by both |self| and |other|.
+    pub fn agree(&self, other: &MsgLimits) -> MsgLimits {
+        MsgLimits {
+            max_msg_bytes: self.max_msg_bytes.min(other.max_msg_bytes),
+            seq_numbers: self.seq_numbers && other.seq_numbers,
+        }
+    }
+}
+
+// Header layout: bit 31 is used by the protocol (HEADER_FLAG_LONG_MESSAGE)
+// and the low bits hold the length. With sequence numbers bits 30:23 hold
+// the sequence number.
+const HEADER_SEQ_SHIFT: u32 = 23;
+const HEADER_LEN_MASK: u32 = (1 << HEADER_SEQ_SHIFT) - 1;
+const LEGACY_LEN_MASK: u32 = (1 << 31) - 1;
+
+/// Returns a header for a message of |bytes|; |seq| is the sequence
+/// number, if sequence numbers were agreed.
+pub fn make_header(bytes: usize, seq: Option<u8>) -> u32 {
+    match seq {
+        Some(seq) => {
+            assert!(bytes <= HEADER_LEN_MASK as usize);
+            ((seq as u32) << HEADER_SEQ_SHIFT) | bytes as u32
+        }
+        None => {
+            assert!(bytes <= LEGACY_LEN_MASK as usize);
+            bytes as u32
+        }
+    }
+}
+
+/// Returns the message length in bytes held in |header|; |seq_numbers|
+/// says whether the header carries a sequence number.
+pub fn header_len(header: u32, seq_numbers: bool) -> usize {
+    let mask = if seq_numbers {
+        HEADER_LEN_MASK
+    } else {
+        LEGACY_LEN_MASK
+    };
+    (header & mask) as usize
+}
+
+/// Returns the sequence number held in |header|.
+pub fn header_seq(header: u32) -> u8 { (header >> HEADER_SEQ_SHIFT) as u8 }
+
+fn howmany(a: usize, b: usize) -> usize { (a + b - 1) / b }
+
+/// Returns the number of bytes |msg| occupies in the FIFO (without the
+/// header).
+pub fn padded_len(msg: &[u8]) -> usize { howmany(msg.len(), size_of::<u32>()) * size_of::<u32>() }
+
+/// Writes |msg| to the FIFO with |enqueue|, zero-padding the last word.
+pub fn send_words(msg: &[u8], mut enqueue: impl FnMut(u32)) {
+    let mut chunks = msg.chunks_exact(size_of::<u32>());
+    for chunk in &mut chunks {
+        enqueue(u32::from_ne_bytes(chunk.try_into().unwrap()));
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let mut word = [0u8; size_of::<u32>()];
+        word[..tail.len()].copy_from_slice(tail);
+        enqueue(u32::from_ne_bytes(word));
+    }
+}
+
+/// Reads a message of |bytes| from the FIFO with |dequeue| into |buf|.
+///
+/// Returns the message or None if it does not fit in |buf|; in that
+/// case the message is drained from the FIFO so the next one starts
+/// with its header.
+pub fn recv_words(bytes: usize, buf: &mut [u8], mut dequeue: impl FnMut() -> u32) -> Option<&[u8]> {
+    let words = howmany(bytes, size_of::<u32>());
+    if bytes > buf.len() {
+        for _ in 0..words {
+            let _ = dequeue();
+        }
+        return None;
+    }
+    for word in 0..words {
+        let data = dequeue().to_ne_bytes();
+        let start = word * size_of::<u32>();
+        let end = bytes.min(start + size_of::<u32>());
+        buf[start..end].copy_from_slice(&data[..end - start]);
+    }
+    Some(&buf[..bytes])
+}
+
+/// Reads a message of |bytes| from the FIFO with |dequeue| and passes it
+/// to |f|. A message that does not fit in |buf| is read into a heap
+/// buffer of up to MAX_SPILL_BYTES when built with "alloc".
+///
+/// Returns None if the message is too large; like recv_words the
+/// message is drained from the FIFO.
+pub fn recv_message<R>(
+    bytes: usize,
+    buf: &mut [u8],
+    dequeue: impl FnMut() -> u32,
+    f: impl FnOnce(&[u8]) -> R,
+) -> Option<R> {
+    #[cfg(feature = "alloc")]
+    if bytes > buf.len() && bytes <= MAX_SPILL_BYTES {
+        let mut spill = vec![0u8; bytes];
+        return recv_words(bytes, &mut spill, dequeue).map(f);
+    }
+    recv_words(bytes, buf, dequeue).map(f)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+    use super::*;
+    use alloc::collections::VecDeque;
+
+    fn send_msg(msg: &[u8], fifo: &mut VecDeque<u32>) {
+        fifo.push_back(msg.len() as u32);
+        send_words(msg, |word| fifo.push_back(word));
+    }
+    fn recv_msg<'a>(fifo: &mut VecDeque<u32>, buf: &'a mut [u8]) -> Option<&'a [u8]> {
+        let header = fifo.pop_front().unwrap();
+        recv_words(header as usize, buf, || fifo.pop_front().unwrap())
+    }
+
+    #[test]
+    fn negotiate() {
+        let mut to_sec = VecDeque::new();
+        let mut from_sec = VecDeque::new();
+        let mut buf = [0u8; MAX_MSG_BYTES];
+
+        // The driver offers its limits using the legacy limits.
+        let limits = MsgLimits::LEGACY;
+        let msg = postcard::to_slice(&MsgLimits::DRIVER, &mut buf).unwrap();
+        send_msg(msg, &mut to_sec);
+
+        // The SEC replies with its own limits.
+        let sec_limits = MsgLimits {
+            max_msg_bytes: 768,
+            seq_numbers: false,
+        };
+        let mut sec_buf = [0u8; 768];
+        let offer: MsgLimits =
+            postcard::from_bytes(recv_msg(&mut to_sec, &mut sec_buf).unwrap()).unwrap();
+        assert_eq!(offer, MsgLimits::DRIVER);
+        send_msg(postcard::to_slice(&sec_limits, &mut sec_buf).unwrap(), &mut from_sec);
+
+        let reply = recv_msg(&mut from_sec, &mut buf[..limits.max_msg_bytes as usize]).unwrap();
+        let limits = MsgLimits::DRIVER.agree(&postcard::from_bytes(reply).unwrap());
+        assert_eq!(limits, sec_limits);
+
+        // A reply too large for the legacy limits now fits.
+        let listing = [b'x'; 700];
+        send_msg(
+            postcard::to_slice(&&listing[..], &mut sec_buf).unwrap(),
+            &mut from_sec,
+        );
+        let reply = recv_msg(&mut from_sec, &mut buf[..limits.max_msg_bytes as usize]).unwrap();
+        assert_eq!(postcard::from_bytes::<&[u8]>(reply).unwrap(), &listing[..]);
+        assert!(from_sec.is_empty());
+    }
+
+    #[test]
+    fn agree() {
+        let sec_limits = MsgLimits {
+            max_msg_bytes: 4096,
+            seq_numbers: true,
+        };
+        assert_eq!(MsgLimits::DRIVER.agree(&sec_limits), MsgLimits::DRIVER);
+        assert_eq!(MsgLimits::DRIVER.agree(&MsgLimits::LEGACY), MsgLimits::LEGACY);
+    }
+
+    #[test]
+    fn reply_too_large() {
+        let mut fifo = VecDeque::new();
+        send_msg(&[b'x'; 700], &mut fifo);
+        send_msg(b"hello", &mut fifo);
+
+        // The oversized reply is dropped without losing FIFO framing.
+        let mut buf = [0u8; 256];
+        assert_eq!(recv_msg(&mut fifo, &mut buf), None);
+        assert_eq!(recv_msg(&mut fifo, &mut buf), Some(&b"hello"[..]));
+        assert!(fifo.is_empty());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn reply_spilled() {
+        let mut fifo = VecDeque::new();
+        let listing = [b'x'; 700];
+        send_msg(&listing, &mut fifo);
+        send_msg(&[b'y'; MAX_SPILL_BYTES + 1], &mut fifo);
+        send_msg(b"hello", &mut fifo);
+
+        let mut buf = [0u8; 256];
+        let recv = |fifo: &mut VecDeque<u32>, buf: &mut [u8]| {
+            let header = fifo.pop_front().unwrap();
+            recv_message(header as usize, buf, || fifo.pop_front().unwrap(), |msg| msg.to_vec())
+        };
+        // A reply too large for |buf| is read into the heap, one too
+        // large to spill is dropped without losing FIFO framing.
+        assert_eq!(recv(&mut fifo, &mut buf).as_deref(), Some(&listing[..]));
+        assert_eq!(recv(&mut fifo, &mut buf), None);
+        assert_eq!(recv(&mut fifo, &mut buf).as_deref(), Some(&b"hello"[..]));
+        assert!(fifo.is_empty());
+    }
+
+    #[test]
+    fn header() {
+        let header = make_header(MAX_SPILL_BYTES, Some(0xa5));
+        assert_eq!(header_len(header, true), MAX_SPILL_BYTES);
+        assert_eq!(header_seq(header), 0xa5);
+
+        // The sequence number does not touch the top bit.
+        assert_eq!(make_header(0, Some(u8::MAX)) & 0x80000000, 0);
+        assert_eq!(header_seq(make_header(4, Some(u8::MAX)) | 0x80000000), u8::MAX);
+        assert_eq!(header_len(make_header(4, Some(u8::MAX)) | 0x80000000, true), 4);
+    }
+
+    #[test]
+    fn legacy_header() {
+        // Without sequence numbers the header is just the length.
+        assert_eq!(make_header(MAX_SPILL_BYTES, None), MAX_SPILL_BYTES as u32);
+        let long = 1 << HEADER_SEQ_SHIFT;
+        assert_eq!(header_len(make_header(long, None), false), long);
+        assert_eq!(header_len(make_header(4, None) | 0x80000000, false), 4);
+    }
+
+    #[test]
+    fn seq_numbers_negotiated() {
+        // Sequence numbers are used only if the SEC says it supports them;
+        // a SEC that predates them gets legacy headers.
+        assert!(MsgLimits::DRIVER.agree(&MsgLimits::DRIVER).seq_numbers);
+        let sec_limits = MsgLimits {
+            seq_numbers: false,
+            ..MsgLimits::DRIVER
+        };
+        assert!(!MsgLimits::DRIVER.agree(&sec_limits).seq_numbers);
+        assert!(!MsgLimits::DRIVER.agree(&MsgLimits::LEGACY).seq_numbers);
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(padded_len(b""), 0);
+        assert_eq!(padded_len(b"hello"), 8);
+        assert_eq!(padded_len(b"word"), 4);
+    }
+}
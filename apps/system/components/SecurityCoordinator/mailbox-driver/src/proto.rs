@@ -14,12 +14,13 @@
 
 extern crate alloc;
 use crate::mailbox::*;
+use crate::msg::*;
 #[cfg(feature = "alloc")]
 use alloc::string::String;
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 use cantrip_os_common::sel4_sys;
-use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
 use log::trace;
 use num_enum::{FromPrimitive, IntoPrimitive};
 use serde::de::DeserializeOwned;
@@ -33,18 +34,38 @@ use sel4_sys::seL4_Page_GetAddress;
 /// through the FIFO immediately following the header.
 pub const HEADER_FLAG_LONG_MESSAGE: u32 = 0x80000000;
 
+/// Identifies a file on the Security Core; returned by FindFile.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FileId(pub u32);
+
+/// Byte offset into a Security Core file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FileOffset(pub u32);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum SECRequest<'a> {
-    FindFile(&'a str),     // Find file by name -> (/*fid*/ u32, /*size*/ u32)
-    GetFilePage(u32, u32), // Get page of file data -> <attached page>
+    FindFile(&'a str), // Find file by name -> (FileId, /*size*/ u32)
+    // Get page of file data -> <attached page>; same as GetFilePageRange
+    // of the whole page holding the offset.
+    GetFilePage(FileId, FileOffset),
 
     InputSelect(/*peripheral=*/ u32, /*pad=*/ u32), // Connect the input of |pad| to |peripheral|
     OutputSelect(/*pad=*/ u32, /*peripheral=*/ u32), // Connect the output of |peripheral| to |pad|
 
     Test(/*count*/ u32), // Scribble on count words of supplied page
 
-    #[cfg(feature = "alloc")]
+    // NB: not conditional on "alloc" so the encoding of later requests
+    //   is the same for all builds.
     GetBuiltins, // Get package names -> Vec(String)
+
+    Negotiate(MsgLimits), // Agree on message limits -> MsgLimits
+
+    // Get |len| bytes of file data at |offset| -> <attached page>; the
+    // data are placed at the offset's position in the page and the rest
+    // of the page is left untouched.
+    GetFilePageRange(FileId, FileOffset, /*len=*/ u32),
 }
 
 #[cfg(feature = "alloc")]
@@ -55,7 +76,7 @@ pub struct GetBuiltinsResponse {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FindFileResponse {
-    pub fid: u32,        // Unique file identifier
+    pub fid: FileId,     // Unique file identifier
     pub size_bytes: u32, // File size
 }
 
@@ -73,6 +94,8 @@ pub enum SECRequestError {
     // Generic errors.
     SendFailed,
     RecvFailed,
+    MessageTooLarge,
+    ReplyTooLarge, // Reply exceeds the space available to receive it
 }
 impl From<SECRequestError> for Result<(), SECRequestError> {
     fn from(err: SECRequestError) -> Result<(), SECRequestError> {
@@ -84,66 +107,104 @@ impl From<SECRequestError> for Result<(), SECRequestError> {
     }
 }
 
+/// How the driver waits for the SEC to reply to a request. The reply is
+/// read from the FIFO once wait returns; dequeue spins for any words not
+/// yet present.
+pub trait ReplyWait {
+    fn wait();
+}
+
+/// Waits by spinning in dequeue; for the rootserver which runs w/o irqs.
+pub struct BusyWait;
+impl ReplyWait for BusyWait {
+    fn wait() {}
+}
+
+#[cfg(feature = "rootserver")]
+type Wait = BusyWait;
+#[cfg(not(feature = "rootserver"))]
+type Wait = crate::camkes::SemaphoreWait;
+
+// Sequence number for the next request; see make_header.
+static NEXT_SEQ: AtomicU8 = AtomicU8::new(0);
+
+// Message limits agreed with the SEC by mbox_negotiate.
+static MAX_MSG_BYTES_AGREED: AtomicU32 = AtomicU32::new(MsgLimits::LEGACY.max_msg_bytes);
+static SEQ_NUMBERS_AGREED: AtomicBool = AtomicBool::new(MsgLimits::LEGACY.seq_numbers);
+
+/// Returns the message limits in use; these are the legacy limits
+/// until mbox_negotiate succeeds.
+pub fn mbox_msg_limits() -> MsgLimits {
+    MsgLimits {
+        max_msg_bytes: MAX_MSG_BYTES_AGREED.load(Ordering::Relaxed),
+        seq_numbers: SEQ_NUMBERS_AGREED.load(Ordering::Relaxed),
+    }
+}
+
 fn sec_request<T: DeserializeOwned>(
     request: &SECRequest,
     opt_cap: Option<seL4_CPtr>,
 ) -> Result<T, SECRequestError> {
-    fn howmany(a: usize, b: usize) -> usize { (a + b - 1) / b }
-    fn roundup(a: usize, b: usize) -> usize { howmany(a, b) * b }
-
     trace!("sec_request {:?} opt_cap {:?}", &request, opt_cap);
 
-    // XXX alignment
-    // XXX bigger for returning builtins
-    let mut request_slice: [u8; 256] = [0; 256];
-    let encoded_bytes = postcard::to_slice(request, &mut request_slice[..])
-        .or(Err(SECRequestError::SerializeFailed))?
-        .len();
+    let limits = mbox_msg_limits();
+    let max_msg_bytes = limits.max_msg_bytes as usize;
+    let mut msg_buffer = [0u8; MAX_MSG_BYTES];
+    let msg_slice = &mut msg_buffer[..max_msg_bytes];
+    let request_bytes =
+        postcard::to_slice(request, msg_slice).or(Err(SECRequestError::SerializeFailed))?;
 
-    let bytes = roundup(encoded_bytes, size_of::<u32>()) as u32;
+    // NB: firmware that predates sequence numbers gets the legacy header
+    let seq = limits
+        .seq_numbers
+        .then(|| NEXT_SEQ.fetch_add(1, Ordering::Relaxed));
+    let header = make_header(padded_len(request_bytes), seq);
     if let Some(cptr) = opt_cap {
         let paddr = unsafe { seL4_Page_GetAddress(cptr) }.or(Err(SECRequestError::PageInvalid))?;
-        enqueue(bytes | HEADER_FLAG_LONG_MESSAGE);
+        enqueue(header | HEADER_FLAG_LONG_MESSAGE);
         enqueue(paddr as u32);
     } else {
-        enqueue(bytes); // NB: no associated page
+        enqueue(header); // NB: no associated page
     }
     // Send serialized request through the queue.
-    for word in 0..(bytes as usize / size_of::<u32>()) {
-        enqueue(unsafe { request_slice.as_ptr().cast::<u32>().add(word).read() });
-    }
+    send_words(request_bytes, enqueue);
 
-    #[cfg(not(feature = "rootserver"))]
-    {
-        // Wait for notification from the rtirq handler.
-        use cantrip_os_common::camkes::semaphore::seL4_Semaphore;
-        extern "Rust" {
-            static RX_SEMAPHORE: seL4_Semaphore;
-        }
-        unsafe { RX_SEMAPHORE.wait() };
-    }
+    Wait::wait();
 
     let header = dequeue();
+    if let Some(seq) = seq {
+        if header_seq(header) != seq {
+            // Out of step with the SEC (e.g. a stale reply); discard
+            // everything queued so the caller can retry from a clean FIFO.
+            trace!("sec_request: reply seq {} expected {}", header_seq(header), seq);
+            drain();
+            return Err(SECRequestError::RecvFailed);
+        }
+    }
     if (header & HEADER_FLAG_LONG_MESSAGE) != 0 {
         // NB: vestige of old protocol, should never occur
         let _paddr = dequeue();
     }
 
     // Receive reply from the queue and deserialize.
-    // NB: safe to re-use request_slice for deserialize
-    let recv_bytes = header & !HEADER_FLAG_LONG_MESSAGE;
-    let recv_words = howmany(recv_bytes as usize, size_of::<u32>());
-    for word in 0..recv_words {
-        unsafe {
-            request_slice
-                .as_mut_ptr()
-                .cast::<u32>()
-                .add(word)
-                .write(dequeue())
-        }
-    }
-    postcard::from_bytes(&request_slice[..(recv_bytes as usize)])
-        .or(Err(SECRequestError::DeserializeFailed))
+    // NB: safe to re-use msg_buffer for deserialize
+    let recv_bytes = header_len(header, limits.seq_numbers);
+    recv_message(recv_bytes, &mut msg_buffer[..max_msg_bytes], dequeue, |reply| {
+        postcard::from_bytes(reply).or(Err(SECRequestError::DeserializeFailed))
+    })
+    .ok_or(SECRequestError::ReplyTooLarge)?
+}
+
+/// Agrees on message limits with the SEC; done once at startup. On
+/// success the agreed limits apply to all subsequent requests (including
+/// whether headers carry sequence numbers). If the SEC does not support
+/// negotiation the legacy limits remain in use.
+pub fn mbox_negotiate() -> Result<MsgLimits, SECRequestError> {
+    let reply: MsgLimits = sec_request(&SECRequest::Negotiate(MsgLimits::DRIVER), None)?;
+    let limits = MsgLimits::DRIVER.agree(&reply);
+    MAX_MSG_BYTES_AGREED.store(limits.max_msg_bytes, Ordering::Relaxed);
+    SEQ_NUMBERS_AGREED.store(limits.seq_numbers, Ordering::Relaxed);
+    Ok(limits)
 }
 
 #[cfg(feature = "alloc")]
@@ -151,16 +212,63 @@ pub fn mbox_get_builtins() -> Result<cantrip_security_interface::BundleIdArray,
     sec_request(&SECRequest::GetBuiltins, None).map(|reply: GetBuiltinsResponse| reply.names)
 }
 
-pub fn mbox_find_file(name: &str) -> Result<(u32, u32), SECRequestError> {
+/// Looks up file |name|, returning its id and size in bytes.
+pub fn mbox_find_file(name: &str) -> Result<(FileId, u32), SECRequestError> {
     sec_request(&SECRequest::FindFile(name), None)
         .map(|reply: FindFileResponse| (reply.fid, reply.size_bytes))
 }
 
-pub fn mbox_get_file_page(fid: u32, offset: u32, frame: seL4_CPtr) -> Result<(), SECRequestError> {
+/// Fetches the page of file |fid| that holds byte |offset| into |frame|.
+///
+/// The file id and offset have distinct types so they cannot be
+/// transposed or confused with the file size:
+/// ```no_run
+/// # use cantrip_os_common::sel4_sys::seL4_CPtr;
+/// # use mailbox_driver::*;
+/// # fn f(frame: seL4_CPtr) -> Result<(), SECRequestError> {
+/// let (fid, _size_bytes) = mbox_find_file("hello.app")?;
+/// mbox_get_file_page(fid, FileOffset(0), frame)
+/// # }
+/// ```
+/// ```compile_fail
+/// # use cantrip_os_common::sel4_sys::seL4_CPtr;
+/// # use mailbox_driver::*;
+/// # fn f(frame: seL4_CPtr) -> Result<(), SECRequestError> {
+/// let (fid, _size_bytes) = mbox_find_file("hello.app")?;
+/// mbox_get_file_page(FileOffset(0), fid, frame)
+/// # }
+/// ```
+/// ```compile_fail
+/// # use cantrip_os_common::sel4_sys::seL4_CPtr;
+/// # use mailbox_driver::*;
+/// # fn f(frame: seL4_CPtr) -> Result<(), SECRequestError> {
+/// let (fid, size_bytes) = mbox_find_file("hello.app")?;
+/// mbox_get_file_page(fid, size_bytes, frame)
+/// # }
+/// ```
+pub fn mbox_get_file_page(
+    fid: FileId,
+    offset: FileOffset,
+    frame: seL4_CPtr,
+) -> Result<(), SECRequestError> {
     sec_request(&SECRequest::GetFilePage(fid, offset), Some(frame))?;
     Ok(())
 }
 
+/// Fetches |len| bytes of file |fid| starting at byte |offset| into
+/// |frame|. The data land at the offset's position within the page (so
+/// the range must not cross a page boundary); the rest of |frame| is
+/// not touched, unlike mbox_get_file_page.
+pub fn mbox_get_file_range(
+    fid: FileId,
+    offset: FileOffset,
+    len: u32,
+    frame: seL4_CPtr,
+) -> Result<(), SECRequestError> {
+    sec_request(&SECRequest::GetFilePageRange(fid, offset, len), Some(frame))?;
+    Ok(())
+}
+
 pub fn mbox_input_select(peripheral: u32, pad: u32) -> Result<(), SECRequestError> {
     sec_request(&SECRequest::InputSelect(peripheral, pad), None)?;
     Ok(())
@@ -177,3 +285,58 @@ pub fn mbox_test(frame: seL4_CPtr, count: u32) -> Result<u32, SECRequestError> {
     // XXX just send back count for now
     Ok(count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::VecDeque;
+    use alloc::format;
+
+    // The SEC decodes requests by variant index so these must not change;
+    // add new requests at the end.
+    #[test]
+    fn request_discriminants() {
+        let fid = FileId(1);
+        let offset = FileOffset(0);
+        let requests = [
+            SECRequest::FindFile("hello.app"),
+            SECRequest::GetFilePage(fid, offset),
+            SECRequest::InputSelect(0, 0),
+            SECRequest::OutputSelect(0, 0),
+            SECRequest::Test(0),
+            SECRequest::GetBuiltins,
+            SECRequest::Negotiate(MsgLimits::DRIVER),
+            SECRequest::GetFilePageRange(fid, offset, 0),
+        ];
+        let mut buf = [0u8; MAX_MSG_BYTES];
+        for (index, request) in requests.iter().enumerate() {
+            let bytes = postcard::to_slice(request, &mut buf).unwrap();
+            assert_eq!(bytes[0] as usize, index, "{:?}", request);
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn get_builtins_spilled() {
+        let names: Vec<String> = (0..40)
+            .map(|i| format!("builtin_application_{:02}.app", i))
+            .collect();
+        let msg = postcard::to_allocvec(&GetBuiltinsResponse {
+            names: names.clone(),
+        })
+        .unwrap();
+        assert!(msg.len() > MAX_MSG_BYTES);
+
+        let mut fifo = VecDeque::new();
+        send_words(&msg, |word| fifo.push_back(word));
+        let mut buf = [0u8; MAX_MSG_BYTES];
+        let reply = recv_message(
+            msg.len(),
+            &mut buf,
+            || fifo.pop_front().unwrap(),
+            |reply| postcard::from_bytes::<GetBuiltinsResponse>(reply).unwrap(),
+        );
+        assert_eq!(reply.map(|reply| reply.names), Some(names));
+        assert!(fifo.is_empty());
+    }
+}
@@ -24,3 +24,6 @@ static mut MAILBOX_MMIO: MAILBOX_MMIO = MAILBOX_MMIO {
 };
 
 include!("../mailbox-driver/src/mailbox.rs");
+
+#[path = "../mailbox-driver/src/msg.rs"]
+mod msg;
@@ -19,8 +19,15 @@
 #![feature(const_mut_refs)]
 
 use cantrip_os_common::camkes;
+use cantrip_os_common::diagnostics::Diagnostics;
+use cantrip_os_common::diagnostics::Heartbeat;
 use cantrip_os_common::logger;
 use cantrip_timer_interface::CompletedTimersResponse;
+use cantrip_timer_interface::DiagnosticsResponse;
+use cantrip_timer_interface::HeartbeatResponse;
+use cantrip_timer_interface::NowResponse;
+use cantrip_timer_interface::RemainingResponse;
+use cantrip_timer_interface::ResolutionResponse;
 use cantrip_timer_interface::TimerId;
 use cantrip_timer_interface::TimerInterface;
 use cantrip_timer_interface::TimerServiceError;
@@ -38,7 +45,7 @@ mod generated {
 }
 use generated::*;
 
-fn cantrip_timer() -> impl TimerInterface {
+fn cantrip_timer() -> impl TimerInterface + Diagnostics {
     static CANTRIP_TIMER: CantripTimerService<opentitan_timer::OtTimer> =
         CantripTimerService::empty();
     let mut manager = CANTRIP_TIMER.get();
@@ -100,6 +107,17 @@ impl TimerInterfaceThread {
             } => Self::periodic_request(client_id, timer_id, duration_in_ms),
             TimerServiceRequest::Cancel(timer_id) => Self::cancel_request(client_id, timer_id),
             TimerServiceRequest::Capscan => Self::capscan_request(),
+            TimerServiceRequest::Diagnostics => Self::diagnostics_request(reply_buffer),
+            TimerServiceRequest::Heartbeat => Self::heartbeat_request(reply_buffer),
+            TimerServiceRequest::Now => Self::now_request(reply_buffer),
+            TimerServiceRequest::Reschedule {
+                timer_id,
+                new_duration_in_ms,
+            } => Self::reschedule_request(client_id, timer_id, new_duration_in_ms),
+            TimerServiceRequest::Remaining(timer_id) => {
+                Self::remaining_request(client_id, timer_id, reply_buffer)
+            }
+            TimerServiceRequest::GetResolution => Self::resolution_request(reply_buffer),
         }
     }
 
@@ -139,8 +157,62 @@ impl TimerInterfaceThread {
         cantrip_timer().cancel(client_id, timer_id).map(|_| 0)
     }
 
+    fn reschedule_request(
+        client_id: usize,
+        timer_id: TimerId,
+        duration_ms: u32,
+    ) -> Result<usize, TimerServiceError> {
+        let duration = Duration::from_millis(duration_ms as u64);
+        cantrip_timer()
+            .reschedule(client_id, timer_id, duration)
+            .map(|_| 0)
+    }
+
+    fn remaining_request(
+        client_id: usize,
+        timer_id: TimerId,
+        reply_buffer: &mut [u8],
+    ) -> Result<usize, TimerServiceError> {
+        let remaining = cantrip_timer()
+            .remaining(client_id, timer_id)
+            .ok_or(TimerServiceError::NoSuchTimer)?;
+        let remaining_us = remaining.as_micros() as u64;
+        let reply_slice = postcard::to_slice(&RemainingResponse { remaining_us }, reply_buffer)
+            .or(Err(TimerServiceError::SerializeFailed))?;
+        Ok(reply_slice.len())
+    }
+
     fn capscan_request() -> Result<usize, TimerServiceError> {
         let _ = Camkes::capscan();
         Ok(0)
     }
+
+    fn diagnostics_request(reply_buffer: &mut [u8]) -> Result<usize, TimerServiceError> {
+        let report = cantrip_timer().diagnostics();
+        let reply_slice = postcard::to_slice(&DiagnosticsResponse { report }, reply_buffer)
+            .or(Err(TimerServiceError::SerializeFailed))?;
+        Ok(reply_slice.len())
+    }
+
+    fn heartbeat_request(reply_buffer: &mut [u8]) -> Result<usize, TimerServiceError> {
+        static HEARTBEAT: Heartbeat = Heartbeat::new();
+        let count = HEARTBEAT.beat();
+        let reply_slice = postcard::to_slice(&HeartbeatResponse { count }, reply_buffer)
+            .or(Err(TimerServiceError::SerializeFailed))?;
+        Ok(reply_slice.len())
+    }
+
+    fn now_request(reply_buffer: &mut [u8]) -> Result<usize, TimerServiceError> {
+        let now_us = cantrip_timer().now().as_micros() as u64;
+        let reply_slice = postcard::to_slice(&NowResponse { now_us }, reply_buffer)
+            .or(Err(TimerServiceError::SerializeFailed))?;
+        Ok(reply_slice.len())
+    }
+
+    fn resolution_request(reply_buffer: &mut [u8]) -> Result<usize, TimerServiceError> {
+        let resolution_hz = cantrip_timer().resolution_hz();
+        let reply_slice = postcard::to_slice(&ResolutionResponse { resolution_hz }, reply_buffer)
+            .or(Err(TimerServiceError::SerializeFailed))?;
+        Ok(reply_slice.len())
+    }
 }
@@ -15,6 +15,7 @@
 #![no_std]
 
 use cantrip_os_common::camkes;
+use cantrip_os_common::diagnostics::DiagnosticsReport;
 use cantrip_os_common::sel4_sys;
 use core::time::Duration;
 use log::trace;
@@ -44,6 +45,10 @@ pub trait HardwareTimer {
     fn now(&self) -> Ticks;
     // Return the deadline `duration` in the future, in Ticks.
     fn deadline(&self, duration: Duration) -> Ticks;
+    // Returns the time represented by `ticks`.
+    fn to_duration(&self, ticks: Ticks) -> Duration;
+    // Returns the tick frequency in Hz.
+    fn resolution_hz(&self) -> u32;
     fn set_alarm(&self, deadline: Ticks);
 }
 
@@ -61,8 +66,23 @@ pub trait TimerInterface {
         duration: Duration,
     ) -> Result<(), TimerServiceError>;
     fn cancel(&mut self, client_id: usize, timer_id: TimerId) -> Result<(), TimerServiceError>;
+    // Changes the duration of a running timer: a periodic timer's period
+    // from its next expiry, a oneshot's remaining time.
+    fn reschedule(
+        &mut self,
+        client_id: usize,
+        timer_id: TimerId,
+        duration: Duration,
+    ) -> Result<(), TimerServiceError>;
     fn completed_timers(&mut self, client_id: usize) -> Result<TimerMask, TimerServiceError>;
+    // Returns the time until a running timer next expires; None if the
+    // timer is not running.
+    fn remaining(&self, client_id: usize, timer_id: TimerId) -> Option<Duration>;
     fn service_interrupt(&mut self);
+    // Returns the current time of the hardware timer.
+    fn now(&self) -> Duration;
+    // Returns the tick frequency of the hardware timer in Hz.
+    fn resolution_hz(&self) -> u32;
 }
 
 /// Return codes from TimerService api's.
@@ -104,6 +124,19 @@ pub enum TimerServiceRequest {
     Cancel(TimerId),
 
     Capscan,
+    Diagnostics, // -> DiagnosticsResponse
+    Heartbeat,   // -> HeartbeatResponse
+
+    Now, // -> NowResponse
+
+    Reschedule {
+        timer_id: TimerId,
+        new_duration_in_ms: TimerDuration,
+    },
+
+    Remaining(TimerId), // -> RemainingResponse
+
+    GetResolution, // -> ResolutionResponse
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -111,6 +144,31 @@ pub struct CompletedTimersResponse {
     pub timer_mask: TimerMask,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiagnosticsResponse {
+    pub report: DiagnosticsReport,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeartbeatResponse {
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NowResponse {
+    pub now_us: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemainingResponse {
+    pub remaining_us: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResolutionResponse {
+    pub resolution_hz: u32,
+}
+
 // Size of the data buffer used to pass a serialized TimerServiceRequest.
 // The size is bounded by the camkes ipc buffer (120 seL4_Word's).
 pub const TIMER_REQUEST_DATA_SIZE: usize = 128; // sufficient for encoded TimerServiceRequest
@@ -180,6 +238,38 @@ pub fn cantrip_timer_cancel(timer_id: TimerId) -> Result<(), TimerServiceError>
     cantrip_timer_request(&TimerServiceRequest::Cancel(timer_id))
 }
 
+/// Changes the duration of a running |timer_id| without cancelling it.
+/// A periodic timer keeps its phase: the pending expiry is unchanged and
+/// |new_duration_in_ms| is the period from then on. A one-shot timer that
+/// has not yet fired instead expires |new_duration_in_ms| from now.
+#[inline]
+pub fn cantrip_timer_reschedule(
+    timer_id: TimerId,
+    new_duration_in_ms: TimerDuration,
+) -> Result<(), TimerServiceError> {
+    cantrip_timer_request(&TimerServiceRequest::Reschedule {
+        timer_id,
+        new_duration_in_ms,
+    })
+}
+
+/// Returns the time until a running |timer_id| next expires. A timer
+/// that is not running (e.g. a one-shot that has fired) returns
+/// TimerServiceError::NoSuchTimer.
+#[inline]
+pub fn cantrip_timer_remaining(timer_id: TimerId) -> Result<Duration, TimerServiceError> {
+    cantrip_timer_request(&TimerServiceRequest::Remaining(timer_id))
+        .map(|reply: RemainingResponse| Duration::from_micros(reply.remaining_us))
+}
+
+/// Returns the tick frequency of the TimerService clock in Hz. Timer
+/// durations are rounded to a whole number of ticks.
+#[inline]
+pub fn cantrip_timer_resolution() -> Result<u32, TimerServiceError> {
+    cantrip_timer_request(&TimerServiceRequest::GetResolution)
+        .map(|reply: ResolutionResponse| reply.resolution_hz)
+}
+
 /// Waits for the next pending timer for the client. If a timer completes
 /// the associated timer id is returned.
 #[inline]
@@ -211,3 +301,27 @@ pub fn cantrip_timer_poll() -> Result<TimerMask, TimerServiceError> {
 pub fn cantrip_timer_capscan() -> Result<(), TimerServiceError> {
     cantrip_timer_request(&TimerServiceRequest::Capscan)
 }
+
+/// Returns a DiagnosticsReport for the TimerService.
+#[inline]
+pub fn cantrip_timer_diagnostics() -> Result<DiagnosticsReport, TimerServiceError> {
+    cantrip_timer_request(&TimerServiceRequest::Diagnostics)
+        .map(|reply: DiagnosticsResponse| reply.report)
+}
+
+/// Returns the TimerService heartbeat count; the count advances on each
+/// call while the service is responsive.
+#[inline]
+pub fn cantrip_timer_heartbeat() -> Result<u64, TimerServiceError> {
+    cantrip_timer_request(&TimerServiceRequest::Heartbeat)
+        .map(|reply: HeartbeatResponse| reply.count)
+}
+
+/// Returns the current time of the TimerService clock. The clock is
+/// monotonic but its origin is unspecified so only differences between
+/// returned values are meaningful.
+#[inline]
+pub fn cantrip_timer_now() -> Result<Duration, TimerServiceError> {
+    cantrip_timer_request(&TimerServiceRequest::Now)
+        .map(|reply: NowResponse| Duration::from_micros(reply.now_us))
+}
@@ -12,11 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![allow(stable_features)]
 #![feature(map_first_last)]
 #![feature(const_btree_new)]
 
+use cantrip_os_common::diagnostics::{Diagnostics, DiagnosticsReport};
 use cantrip_timer_interface::*;
 use core::time::Duration;
 use spin::Mutex;
@@ -78,8 +79,30 @@ impl<'a, HT: HardwareTimer> TimerInterface for Guard<'a, HT> {
     fn cancel(&mut self, client_id: usize, timer_id: TimerId) -> Result<(), TimerServiceError> {
         self.manager.as_mut().unwrap().cancel(client_id, timer_id)
     }
+    fn reschedule(
+        &mut self,
+        client_id: usize,
+        timer_id: TimerId,
+        duration: Duration,
+    ) -> Result<(), TimerServiceError> {
+        self.manager
+            .as_mut()
+            .unwrap()
+            .reschedule(client_id, timer_id, duration)
+    }
     fn completed_timers(&mut self, client_id: usize) -> Result<TimerMask, TimerServiceError> {
         self.manager.as_mut().unwrap().completed_timers(client_id)
     }
+    fn remaining(&self, client_id: usize, timer_id: TimerId) -> Option<Duration> {
+        self.manager
+            .as_ref()
+            .unwrap()
+            .remaining(client_id, timer_id)
+    }
     fn service_interrupt(&mut self) { self.manager.as_mut().unwrap().service_interrupt() }
+    fn now(&self) -> Duration { self.manager.as_ref().unwrap().now() }
+    fn resolution_hz(&self) -> u32 { self.manager.as_ref().unwrap().resolution_hz() }
+}
+impl<'a, HT: HardwareTimer> Diagnostics for Guard<'a, HT> {
+    fn diagnostics(&self) -> DiagnosticsReport { self.manager.as_ref().unwrap().diagnostics() }
 }
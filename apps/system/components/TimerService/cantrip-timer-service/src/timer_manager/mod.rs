@@ -15,6 +15,7 @@
 extern crate alloc;
 
 use alloc::collections::BTreeMap;
+use cantrip_os_common::diagnostics::{Diagnostics, DiagnosticsReport};
 use cantrip_os_common::sel4_sys::seL4_Word;
 use cantrip_timer_interface::*;
 use core::time::Duration;
@@ -52,6 +53,22 @@ impl<HT: HardwareTimer> TimerManager<HT> {
         }
     }
 
+    // Returns the deadline of |timer_id| for |client_id| if it is running.
+    fn find(&self, client_id: usize, timer_id: TimerId) -> Option<Ticks> {
+        self.events
+            .iter()
+            .find(|(_, ev)| ev.client_id == client_id && ev.timer_id == timer_id)
+            .map(|(&key, _)| key)
+    }
+
+    // Arms the hardware timer for the next deadline.
+    fn arm(&self) {
+        // Next deadline is always on top of the tree.
+        if let Some((deadline, _)) = self.events.first_key_value() {
+            self.timer.set_alarm(*deadline)
+        }
+    }
+
     // Checks |client_id| and |timer_id| are valid and that no timer exists.
     fn check_timer_params(
         &self,
@@ -66,11 +83,7 @@ impl<HT: HardwareTimer> TimerManager<HT> {
             return Err(TimerServiceError::NoSuchTimer);
         }
 
-        if self
-            .events
-            .iter()
-            .any(|(_, ev)| ev.client_id == client_id && ev.timer_id == timer_id)
-        {
+        if self.find(client_id, timer_id).is_some() {
             return Err(TimerServiceError::TimerAlreadyExists);
         }
         Ok(())
@@ -95,11 +108,7 @@ impl<HT: HardwareTimer> TimerManager<HT> {
                 recurring,
             },
         );
-
-        // Next deadline is always on top of the tree.
-        if let Some(event) = self.events.first_entry() {
-            self.timer.set_alarm(*event.key())
-        }
+        self.arm();
 
         Ok(())
     }
@@ -139,16 +148,43 @@ impl<HT: HardwareTimer> TimerInterface for TimerManager<HT> {
     fn cancel(&mut self, client_id: usize, timer_id: TimerId) -> Result<(), TimerServiceError> {
         // NB: no need for an explicit client_id check
         let key = self
-            .events
-            .iter()
-            .find(|(_, ev)| ev.client_id == client_id && ev.timer_id == timer_id)
-            .map(|(&key, _)| key)
+            .find(client_id, timer_id)
             .ok_or(TimerServiceError::NoSuchTimer)?;
         self.events.remove(&key);
 
         Ok(())
     }
 
+    fn reschedule(
+        &mut self,
+        client_id: usize,
+        timer_id: TimerId,
+        duration: Duration,
+    ) -> Result<(), TimerServiceError> {
+        // NB: no need for an explicit client_id check
+        let key = self
+            .find(client_id, timer_id)
+            .ok_or(TimerServiceError::NoSuchTimer)?;
+        let event = self.events.get_mut(&key).unwrap();
+        if let Some(period) = event.recurring.as_mut() {
+            // Keep the pending expiry so the timer does not lose phase.
+            *period = duration;
+        } else {
+            let event = self.events.remove(&key).unwrap();
+            self.events.insert(self.timer.deadline(duration), event);
+            self.arm();
+        }
+
+        Ok(())
+    }
+
+    // The deadline of each running timer is its key in |events|.
+    fn remaining(&self, client_id: usize, timer_id: TimerId) -> Option<Duration> {
+        let deadline = self.find(client_id, timer_id)?;
+        let ticks = deadline.saturating_sub(self.timer.now());
+        Some(self.timer.to_duration(ticks))
+    }
+
     // Service a hardware timer interrupt. For all expired timer requests
     // signal the client and, if periodic, re-queue the timer. If there
     // are still pending timer requests, re-arm the hardware timer.
@@ -183,4 +219,160 @@ impl<HT: HardwareTimer> TimerInterface for TimerManager<HT> {
             self.timer.set_alarm(*event.key())
         }
     }
+
+    fn now(&self) -> Duration { self.timer.to_duration(self.timer.now()) }
+
+    fn resolution_hz(&self) -> u32 { self.timer.resolution_hz() }
+}
+impl<HT: HardwareTimer> Diagnostics for TimerManager<HT> {
+    fn diagnostics(&self) -> DiagnosticsReport {
+        let mut report = DiagnosticsReport::new("timer");
+        report
+            .add("now", self.timer.now())
+            .add("pending", self.events.len() as u64)
+            .add(
+                "periodic",
+                self.events
+                    .values()
+                    .filter(|ev| ev.recurring.is_some())
+                    .count() as u64,
+            )
+            .add("next_deadline", self.events.keys().next().copied().unwrap_or(0));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    // Signalled by service_interrupt; timer_state records what expired.
+    #[no_mangle]
+    fn timer_emit(_badge: seL4_Word) {}
+
+    // A timer that ticks once per millisecond and only moves when told.
+    #[derive(Default)]
+    struct FakeTimer {
+        now: Cell<Ticks>,
+        alarm: Cell<Option<Ticks>>,
+    }
+    impl HardwareTimer for &FakeTimer {
+        fn setup(&self) {}
+        fn ack_interrupt(&self) {}
+        fn now(&self) -> Ticks { self.now.get() }
+        fn deadline(&self, duration: Duration) -> Ticks { self.now() + duration.as_millis() as Ticks }
+        fn to_duration(&self, ticks: Ticks) -> Duration { Duration::from_millis(ticks) }
+        fn resolution_hz(&self) -> u32 { 1000 }
+        fn set_alarm(&self, deadline: Ticks) { self.alarm.set(Some(deadline)); }
+    }
+
+    const CLIENT: usize = 1;
+
+    // Advances the clock to |ms| servicing each alarm on the way; returns
+    // the times at which |timer_id| expired.
+    fn run_until(
+        manager: &mut TimerManager<&FakeTimer>,
+        timer: &FakeTimer,
+        ms: Ticks,
+        timer_id: TimerId,
+    ) -> Vec<Ticks> {
+        let mut expired = Vec::new();
+        while let Some(alarm) = timer.alarm.get().filter(|&alarm| alarm <= ms) {
+            timer.now.set(alarm);
+            timer.alarm.set(None);
+            manager.service_interrupt();
+            if manager.completed_timers(CLIENT).unwrap() & (1 << timer_id) != 0 {
+                expired.push(alarm);
+            }
+        }
+        timer.now.set(ms);
+        expired
+    }
+
+    #[test]
+    fn reschedule_periodic() {
+        let timer = FakeTimer::default();
+        let mut manager = TimerManager::new(&timer);
+        manager.add_periodic(CLIENT, 0, Duration::from_millis(100)).unwrap();
+        assert_eq!(run_until(&mut manager, &timer, 150, 0), [100]);
+
+        // Mid-flight the pending expiry stands; the new period applies
+        // from there on.
+        manager.reschedule(CLIENT, 0, Duration::from_millis(30)).unwrap();
+        assert_eq!(run_until(&mut manager, &timer, 300, 0), [200, 230, 260, 290]);
+
+        manager.reschedule(CLIENT, 0, Duration::from_millis(250)).unwrap();
+        assert_eq!(run_until(&mut manager, &timer, 1000, 0), [320, 570, 820]);
+    }
+
+    #[test]
+    fn reschedule_oneshot() {
+        let timer = FakeTimer::default();
+        let mut manager = TimerManager::new(&timer);
+        manager.add_oneshot(CLIENT, 1, Duration::from_millis(100)).unwrap();
+        assert_eq!(timer.alarm.get(), Some(100));
+
+        // Shortening re-arms the hardware for the earlier deadline.
+        assert_eq!(run_until(&mut manager, &timer, 40, 1), []);
+        manager.reschedule(CLIENT, 1, Duration::from_millis(20)).unwrap();
+        assert_eq!(timer.alarm.get(), Some(60));
+        assert_eq!(run_until(&mut manager, &timer, 1000, 1), [60]);
+
+        // Extending pushes the expiry out.
+        manager.add_oneshot(CLIENT, 1, Duration::from_millis(100)).unwrap();
+        assert_eq!(run_until(&mut manager, &timer, 1050, 1), []);
+        manager.reschedule(CLIENT, 1, Duration::from_millis(200)).unwrap();
+        assert_eq!(run_until(&mut manager, &timer, 1200, 1), []);
+        assert_eq!(run_until(&mut manager, &timer, 2000, 1), [1250]);
+
+        // Once fired there is nothing to reschedule.
+        assert_eq!(
+            manager.reschedule(CLIENT, 1, Duration::from_millis(10)),
+            Err(TimerServiceError::NoSuchTimer)
+        );
+    }
+
+    #[test]
+    fn remaining() {
+        let timer = FakeTimer::default();
+        let mut manager = TimerManager::new(&timer);
+        manager.add_periodic(CLIENT, 0, Duration::from_millis(100)).unwrap();
+        manager.add_oneshot(CLIENT, 1, Duration::from_millis(50)).unwrap();
+        assert_eq!(manager.remaining(CLIENT, 0), Some(Duration::from_millis(100)));
+        assert_eq!(manager.remaining(CLIENT, 2), None);
+        assert_eq!(manager.remaining(CLIENT + 1, 0), None);
+
+        run_until(&mut manager, &timer, 30, 0);
+        assert_eq!(manager.remaining(CLIENT, 1), Some(Duration::from_millis(20)));
+
+        // A periodic timer counts down to its next expiry; a fired
+        // one-shot is no longer running.
+        run_until(&mut manager, &timer, 130, 0);
+        assert_eq!(manager.remaining(CLIENT, 0), Some(Duration::from_millis(70)));
+        assert_eq!(manager.remaining(CLIENT, 1), None);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let timer = FakeTimer::default();
+        let mut manager = TimerManager::new(&timer);
+        let report = manager.diagnostics();
+        assert_eq!(report.component, "timer");
+        assert_eq!(report.get("pending"), Some(0));
+        assert_eq!(report.get("next_deadline"), Some(0));
+
+        manager.add_periodic(CLIENT, 0, Duration::from_millis(100)).unwrap();
+        manager.add_oneshot(CLIENT, 1, Duration::from_millis(50)).unwrap();
+        run_until(&mut manager, &timer, 20, 0);
+        let report = manager.diagnostics();
+        assert_eq!(report.get("now"), Some(20));
+        assert_eq!(report.get("pending"), Some(2));
+        assert_eq!(report.get("periodic"), Some(1));
+        assert_eq!(report.get("next_deadline"), Some(50));
+        assert_eq!(
+            report.to_string(),
+            "timer:\n  now: 20\n  pending: 2\n  periodic: 1\n  next_deadline: 50"
+        );
+    }
 }
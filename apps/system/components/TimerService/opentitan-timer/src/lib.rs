@@ -58,6 +58,17 @@ impl HardwareTimer for OtTimer {
         self.now() + tick_duration
     }
 
+    fn to_duration(&self, ticks: Ticks) -> Duration {
+        Duration::from_micros((ticks * 1_000_000) / TIMER_FREQ)
+    }
+
+    fn resolution_hz(&self) -> u32 {
+        // Report the divider programmed in setup rather than TIMER_FREQ.
+        let config = get_config();
+        let hz = (TIMER_BASE_FREQ * config.step() as u64) / (config.prescale() as u64 + 1);
+        hz.try_into().unwrap_or(u32::MAX)
+    }
+
     fn set_alarm(&self, deadline: Ticks) {
         let high = (deadline >> 32) as u32;
         let low = (deadline & 0xffffffff) as u32;
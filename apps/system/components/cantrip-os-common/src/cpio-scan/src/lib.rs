@@ -0,0 +1,140 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scanning of the cpio (newc) archives that hold built-in files.
+//!
+//! A malformed archive (e.g. a truncated build artifact) stops the scan
+//! at the bad entry; its byte offset is logged and returned so the
+//! failure point is diagnosable.
+
+#![cfg_attr(not(test), no_std)]
+
+use cpio::CpioNewcReader;
+use log::error;
+
+/// A malformed entry in a cpio archive at byte |offset|.
+#[derive(Debug, PartialEq)]
+pub struct MalformedEntry {
+    pub offset: usize,
+}
+
+/// Calls |f| with the name & data of each entry in the cpio |archive|
+/// until it returns Some. Scanning stops at the first malformed entry
+/// which is logged and returned with its offset.
+pub fn scan_archive<'a, T>(
+    archive: &'a [u8],
+    mut f: impl FnMut(&'a str, &'a [u8]) -> Option<T>,
+) -> Result<Option<T>, MalformedEntry> {
+    let mut offset = 0;
+    for e in CpioNewcReader::new(archive) {
+        match e {
+            Err(err) => {
+                error!("cpio archive malformed at offset 0x{:x}: {:?}", offset, err);
+                return Err(MalformedEntry { offset });
+            }
+            Ok(entry) => {
+                if let Some(v) = f(entry.name, entry.data) {
+                    return Ok(Some(v));
+                }
+                // The next entry starts 4-byte aligned after the data.
+                let end =
+                    entry.data.as_ptr() as usize + entry.data.len() - archive.as_ptr() as usize;
+                offset = (end + 3) & !3;
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the data of the entry named |filename| in |archive|; a
+/// missing entry is returned as None.
+pub fn find_entry<'a>(
+    archive: &'a [u8],
+    filename: &str,
+) -> Result<Option<&'a [u8]>, MalformedEntry> {
+    scan_archive(archive, |name, data| (name == filename).then_some(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Appends a newc entry for |name| holding |data| to |archive|.
+    fn push_entry(archive: &mut Vec<u8>, name: &str, data: &[u8]) {
+        archive.extend_from_slice(b"070701");
+        // ino, mode, uid, gid, nlink, mtime, filesize, devmajor,
+        // devminor, rdevmajor, rdevminor, namesize, check
+        let (filesize, namesize) = (data.len(), name.len() + 1);
+        let fields = [0, 0o100644, 0, 0, 1, 0, filesize, 0, 0, 0, 0, namesize, 0];
+        for field in fields {
+            archive.extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize((archive.len() + 3) & !3, 0);
+        archive.extend_from_slice(data);
+        archive.resize((archive.len() + 3) & !3, 0);
+    }
+
+    fn archive() -> Vec<u8> {
+        let mut archive = Vec::new();
+        push_entry(&mut archive, "hello.app", b"hello");
+        push_entry(&mut archive, "mobilenet.model", &[7u8; 300]);
+        push_entry(&mut archive, "TRAILER!!!", &[]);
+        archive
+    }
+
+    fn names(archive: &[u8]) -> Result<Vec<&str>, MalformedEntry> {
+        let mut names = Vec::new();
+        scan_archive(archive, |name, _| -> Option<()> {
+            names.push(name);
+            None
+        })?;
+        Ok(names)
+    }
+
+    #[test]
+    fn scan_archive_ok() {
+        let archive = archive();
+        assert_eq!(names(&archive), Ok(vec!["hello.app", "mobilenet.model"]));
+        assert_eq!(find_entry(&archive, "hello.app"), Ok(Some(&b"hello"[..])));
+        assert_eq!(find_entry(&archive, "missing"), Ok(None));
+    }
+
+    #[test]
+    fn scan_archive_truncated() {
+        let mut archive = archive();
+        // NB: the second entry starts after the first's 110-byte header,
+        //     10-byte name (NUL included), and 5-byte data padded to 8.
+        let second = 110 + 10 + 8;
+        assert_eq!(&archive[second..second + 6], b"070701");
+
+        // Truncate in the middle of the second entry's data.
+        archive.truncate(second + 200);
+        assert_eq!(names(&archive), Err(MalformedEntry { offset: second }));
+        // A lookup of an entry before the failure point still works.
+        assert_eq!(find_entry(&archive, "hello.app"), Ok(Some(&b"hello"[..])));
+        assert_eq!(
+            find_entry(&archive, "mobilenet.model"),
+            Err(MalformedEntry { offset: second })
+        );
+    }
+
+    #[test]
+    fn scan_archive_corrupt() {
+        let mut archive = archive();
+        archive[0] = b'X'; // Bad magic
+        assert_eq!(names(&archive), Err(MalformedEntry { offset: 0 }));
+    }
+}
@@ -12,11 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-//! RAII wrapper for a dynamically allocated CSpace slot.
+//! RAII wrapper for a dynamically allocated CSpace slot or a contiguous
+//! run of slots.
 
 #![cfg_attr(not(test), no_std)]
 #![allow(non_snake_case)]
 
+use core::ops::Range;
 use slot_allocator::CANTRIP_CSPACE_SLOTS;
 
 use sel4_sys::seL4_CNode_Copy;
@@ -37,6 +39,21 @@ extern "C" {
     static SELF_CNODE: seL4_CPtr;
 }
 
+/// Returns the rights for a copy with full authority.
+pub fn all_rights() -> seL4_CapRights {
+    seL4_CapRights::new(
+        /*grant_reply=*/ 1, /*grant=*/ 1, /*read=*/ 1, /*write=*/ 1,
+    )
+}
+
+/// Returns the rights for a copy that may only be read, e.g. a frame
+/// that is mapped to be read.
+pub fn read_only_rights() -> seL4_CapRights {
+    seL4_CapRights::new(
+        /*grant_reply=*/ 0, /*grant=*/ 0, /*read=*/ 1, /*write=*/ 0,
+    )
+}
+
 // RAII wrapper for push_recv_path.
 pub struct RecvPathCleanup {
     slot: seL4_CPtr,
@@ -53,26 +70,50 @@ impl Drop for RecvPathCleanup {
 }
 
 pub struct CSpaceSlot {
-    pub slot: seL4_CPtr,
+    pub slot: seL4_CPtr, // First slot of the run
+    count: usize,
 }
 impl CSpaceSlot {
-    pub fn new() -> Self {
+    pub fn new() -> Self { Self::new_range(1) }
+
+    /// Allocates a run of |count| contiguous slots. The methods that
+    /// operate on "our slot" use the first slot; the _nth variants take
+    /// an index into the run.
+    pub fn new_range(count: usize) -> Self {
+        assert!(count > 0);
         CSpaceSlot {
-            slot: unsafe { CANTRIP_CSPACE_SLOTS.alloc(1) }.expect("CSpaceSlot"),
+            slot: unsafe { CANTRIP_CSPACE_SLOTS.alloc(count) }.expect("CSpaceSlot"),
+            count,
         }
     }
 
-    /// Releases ownership of the slot; this inhibits the normal cleanup
-    /// done by drop. The slot that was being managed is returned.
+    /// Returns the number of slots managed.
+    pub fn count(&self) -> usize { self.count }
+
+    /// Returns the slots managed, in order.
+    pub fn slots(&self) -> Range<seL4_CPtr> { self.slot..self.slot + self.count }
+
+    /// Releases ownership of the slot(s); this inhibits the normal cleanup
+    /// done by drop. The (first) slot that was being managed is returned.
     pub fn release(&mut self) -> seL4_CPtr {
         let slot = self.slot;
         self.slot = seL4_CPtr::MAX;
+        self.count = 0;
         slot
     }
 
+    // Returns the |n|th slot of the run.
+    fn nth(&self, n: usize) -> seL4_CPtr {
+        assert!(n < self.count, "slot {} out of range {}", n, self.count);
+        self.slot + n
+    }
+
     /// Returns the (root, index, depth) seL4 path for the slot.
-    pub fn get_path(&self) -> (seL4_CPtr, seL4_CPtr, u8) {
-        (unsafe { SELF_CNODE }, self.slot, seL4_WordBits as u8)
+    pub fn get_path(&self) -> (seL4_CPtr, seL4_CPtr, u8) { self.get_path_nth(0) }
+
+    /// Returns the (root, index, depth) seL4 path for the |n|th slot.
+    pub fn get_path_nth(&self, n: usize) -> (seL4_CPtr, seL4_CPtr, u8) {
+        (unsafe { SELF_CNODE }, self.nth(n), seL4_WordBits as u8)
     }
 
     /// Temporarily sets the receive path used for receiving a capability
@@ -85,6 +126,21 @@ impl CSpaceSlot {
         cleanup
     }
 
+    /// Moves any cap received in our slot (see push_recv_path) to a newly
+    /// allocated slot and returns that slot; the caller takes ownership of
+    /// it (and the cap). None is returned if nothing was received: seL4
+    /// rejects a move from an empty slot, in which case the new slot is
+    /// freed. Our slot is left empty so it may receive again. This may be
+    /// called while the RecvPathCleanup from push_recv_path is live;
+    /// dropping the cleanup restores the receive path but never touches
+    /// the cap that was moved.
+    pub fn take_received(&self) -> Option<seL4_CPtr> {
+        let mut dest = CSpaceSlot::new();
+        dest.move_to(unsafe { SELF_CNODE }, self.slot, seL4_WordBits as u8)
+            .ok()
+            .map(|_| dest.release())
+    }
+
     /// Copies the specified path to our slot.
     pub fn copy_to(
         &self,
@@ -92,11 +148,23 @@ impl CSpaceSlot {
         src_index: seL4_CPtr,
         src_depth: u8,
         rights: seL4_CapRights,
+    ) -> seL4_Result {
+        self.copy_to_nth(0, src_root, src_index, src_depth, rights)
+    }
+
+    /// Copies the specified path to our |n|th slot.
+    pub fn copy_to_nth(
+        &self,
+        n: usize,
+        src_root: seL4_CPtr,
+        src_index: seL4_CPtr,
+        src_depth: u8,
+        rights: seL4_CapRights,
     ) -> seL4_Result {
         unsafe {
             seL4_CNode_Copy(
                 /*dest_root=*/ SELF_CNODE,
-                /*dest_index= */ self.slot,
+                /*dest_index= */ self.nth(n),
                 /*dest_depth=*/ seL4_WordBits as u8,
                 src_root,
                 src_index,
@@ -108,10 +176,17 @@ impl CSpaceSlot {
 
     /// Copies the specified path to our slot.
     pub fn dup_to(&self, src_root: seL4_CPtr, src_index: seL4_CPtr, src_depth: u8) -> seL4_Result {
-        let seL4_AllRights = seL4_CapRights::new(
-            /*grant_reply=*/ 1, /*grant=*/ 1, /*read=*/ 1, /*write=*/ 1,
-        );
-        self.copy_to(src_root, src_index, src_depth, seL4_AllRights)
+        self.copy_to(src_root, src_index, src_depth, all_rights())
+    }
+
+    /// Copies the specified path to our slot with read-only rights.
+    pub fn dup_ro_to(
+        &self,
+        src_root: seL4_CPtr,
+        src_index: seL4_CPtr,
+        src_depth: u8,
+    ) -> seL4_Result {
+        self.copy_to(src_root, src_index, src_depth, read_only_rights())
     }
 
     /// Mints the specified path to our slot.
@@ -139,10 +214,21 @@ impl CSpaceSlot {
 
     /// Moves the specified path to our slot.
     pub fn move_to(&self, src_root: seL4_CPtr, src_slot: seL4_CPtr, src_depth: u8) -> seL4_Result {
+        self.move_to_nth(0, src_root, src_slot, src_depth)
+    }
+
+    /// Moves the specified path to our |n|th slot.
+    pub fn move_to_nth(
+        &self,
+        n: usize,
+        src_root: seL4_CPtr,
+        src_slot: seL4_CPtr,
+        src_depth: u8,
+    ) -> seL4_Result {
         unsafe {
             seL4_CNode_Move(
                 /*dest_root=*/ SELF_CNODE,
-                /*dest_index= */ self.slot,
+                /*dest_index= */ self.nth(n),
                 /*dest_depth=*/ seL4_WordBits as u8,
                 src_root,
                 src_slot,
@@ -191,10 +277,18 @@ impl CSpaceSlot {
         }
     }
 
-    /// Delete any cap in our slot.
+    /// Delete any cap in our slot(s).
     // NB: deleting an empty slot is a noop to seL4
     pub fn delete(&self) -> seL4_Result {
-        let result_error: seL4_Error = unsafe { seL4_CNode_Delete(SELF_CNODE, self.slot, seL4_WordBits as u8).error as usize }.into();
+        for n in 0..self.count {
+            self.delete_nth(n)?;
+        }
+        Ok(())
+    }
+
+    /// Delete any cap in our |n|th slot.
+    pub fn delete_nth(&self, n: usize) -> seL4_Result {
+        let result_error: seL4_Error = unsafe { seL4_CNode_Delete(SELF_CNODE, self.nth(n), seL4_WordBits as u8).error as usize }.into();
         result_error.into()
     }
 }
@@ -202,7 +296,32 @@ impl Drop for CSpaceSlot {
     fn drop(&mut self) {
         if self.slot != seL4_CPtr::MAX {
             self.delete().expect("CSpaceSlot");
-            unsafe { CANTRIP_CSPACE_SLOTS.free(self.slot, 1) }
+            unsafe { CANTRIP_CSPACE_SLOTS.free(self.slot, self.count) }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns (grant_reply, grant, read, write) for |rights|.
+    fn bits(rights: seL4_CapRights) -> (usize, usize, usize, usize) {
+        (
+            rights.get_capAllowGrantReply(),
+            rights.get_capAllowGrant(),
+            rights.get_capAllowRead(),
+            rights.get_capAllowWrite(),
+        )
+    }
+
+    #[test]
+    fn all_rights_granted() {
+        assert_eq!(bits(all_rights()), (1, 1, 1, 1));
+    }
+
+    #[test]
+    fn read_only_rights_granted() {
+        assert_eq!(bits(read_only_rights()), (0, 0, 1, 0));
+    }
+}
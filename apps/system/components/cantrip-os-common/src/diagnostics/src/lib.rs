@@ -0,0 +1,164 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Uniform diagnostics support for CantripOS services. Each service
+//! implements the Diagnostics trait and returns a DiagnosticsReport
+//! through its RPC interface so tooling (e.g. the shell) can dump the
+//! state of the whole system with one command.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use serde::{Deserialize, Serialize};
+
+/// A named value reported by a service.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticsEntry {
+    pub name: String,
+    pub value: u64,
+}
+
+/// Diagnostics returned by a service. Reports are passed back in RPC
+/// replies, some of which are small (e.g. rpc_basic services), so keep
+/// names short and the number of entries modest.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    pub component: String,
+    pub entries: Vec<DiagnosticsEntry>,
+}
+impl DiagnosticsReport {
+    pub fn new(component: &str) -> Self {
+        Self {
+            component: component.to_string(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Appends an entry |name| with |value| to the report.
+    pub fn add(&mut self, name: &str, value: u64) -> &mut Self {
+        self.entries.push(DiagnosticsEntry {
+            name: name.to_string(),
+            value,
+        });
+        self
+    }
+
+    /// Returns the value of the first entry named |name|.
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.value)
+    }
+
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+    pub fn len(&self) -> usize { self.entries.len() }
+}
+impl fmt::Display for DiagnosticsReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.component)?;
+        for entry in &self.entries {
+            write!(f, "\n  {}: {}", entry.name, entry.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Services implement this to report their internal state.
+pub trait Diagnostics {
+    fn diagnostics(&self) -> DiagnosticsReport;
+}
+
+/// Liveness counter returned by each service's heartbeat RPC. The count
+/// advances on every beat so a supervisor detects a wedged component by
+/// a count that stops changing. The count is held in a usize (riscv32
+/// has no 64-bit atomics) and wraps; callers should compare counts for
+/// change rather than order.
+pub struct Heartbeat(AtomicUsize);
+impl Default for Heartbeat {
+    fn default() -> Self { Self::new() }
+}
+impl Heartbeat {
+    pub const fn new() -> Self { Self(AtomicUsize::new(0)) }
+
+    /// Advances the counter and returns the new count.
+    pub fn beat(&self) -> u64 { self.0.fetch_add(1, Ordering::Relaxed).wrapping_add(1) as u64 }
+
+    /// Returns the current count without advancing it.
+    pub fn count(&self) -> u64 { self.0.load(Ordering::Relaxed) as u64 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_report() {
+        let report = DiagnosticsReport::new("test");
+        assert!(report.is_empty());
+        assert_eq!(report.get("foo"), None);
+        assert_eq!(report.to_string(), "test:");
+    }
+
+    #[test]
+    fn add_get() {
+        let mut report = DiagnosticsReport::new("test");
+        report.add("foo", 1).add("bar", 2);
+        assert_eq!(report.len(), 2);
+        assert_eq!(report.get("foo"), Some(1));
+        assert_eq!(report.get("bar"), Some(2));
+        assert_eq!(report.get("baz"), None);
+        assert_eq!(report.to_string(), "test:\n  foo: 1\n  bar: 2");
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let mut report = DiagnosticsReport::new("test");
+        report.add("foo", u64::MAX).add("bar", 0);
+        let mut buf = [0u8; 64];
+        let bytes = postcard::to_slice(&report, &mut buf).unwrap();
+        let decoded = postcard::from_bytes::<DiagnosticsReport>(bytes).unwrap();
+        assert_eq!(decoded, report);
+    }
+
+    struct Fake(u64);
+    impl Diagnostics for Fake {
+        fn diagnostics(&self) -> DiagnosticsReport {
+            let mut report = DiagnosticsReport::new("fake");
+            report.add("value", self.0);
+            report
+        }
+    }
+
+    #[test]
+    fn trait_object() {
+        let fake: &dyn Diagnostics = &Fake(42);
+        assert_eq!(fake.diagnostics().get("value"), Some(42));
+    }
+
+    #[test]
+    fn heartbeat_advances() {
+        static HEARTBEAT: Heartbeat = Heartbeat::new();
+        assert_eq!(HEARTBEAT.count(), 0);
+        let first = HEARTBEAT.beat();
+        let second = HEARTBEAT.beat();
+        assert_ne!(first, second);
+        assert_eq!(HEARTBEAT.count(), second);
+    }
+}
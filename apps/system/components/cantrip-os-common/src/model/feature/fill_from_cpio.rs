@@ -14,14 +14,19 @@
 
 // Fill file data from the cpio archive baked into capdl-loader.
 
+use super::check_filedata_fill;
 use crate::CantripOsModel;
 use capdl::*;
 use core::ptr;
-use cpio::CpioNewcReader;
+use cpio_scan::find_entry;
 use cstr_core::CStr;
-use log::trace;
+use log::{error, trace};
 
 use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_Error;
+use sel4_sys::seL4_FailedLookup;
+use sel4_sys::seL4_InvalidArgument;
+use sel4_sys::seL4_RangeError;
 use sel4_sys::seL4_Result;
 
 use static_assertions::assert_cfg;
@@ -38,14 +43,16 @@ impl<'a> CantripOsModel<'a> {
         sel4_frame: seL4_CPtr,
         frame_fill: &CDL_FrameFill_Element_t,
     ) -> seL4_Result {
-        let cpio_lookup = |filename: &str| -> &[u8] {
-            for e in CpioNewcReader::new(self.capdl_archive) {
-                let entry = e.unwrap();
-                if entry.name == filename {
-                    return entry.data;
-                }
-            }
-            panic!("{} not found in cpio archive", filename);
+        // NB: a malformed archive (e.g. a truncated build artifact) is
+        //     logged by find_entry with the byte offset of the bad entry.
+        let archive = self.capdl_archive;
+        let cpio_lookup = |filename: &str| -> Result<&[u8], seL4_Error> {
+            find_entry(archive, filename)
+                .or(Err(seL4_InvalidArgument))?
+                .ok_or_else(|| {
+                    error!("{} not found in cpio archive", filename);
+                    seL4_FailedLookup
+                })
         };
         let file_data = frame_fill.get_file_data();
         let filename = unsafe { CStr::from_ptr(file_data.filename) }
@@ -54,9 +61,29 @@ impl<'a> CantripOsModel<'a> {
         // Check the last lookup before scanning the cpio archive.
         if filename != self.last_filename {
             trace!("switch filedata fill to {}", filename);
-            self.last_data = cpio_lookup(filename);
+            self.last_data = cpio_lookup(filename)?;
             self.last_filename = filename;
         }
+        check_filedata_fill(
+            filename,
+            file_data.file_offset,
+            frame_fill.dest_offset,
+            frame_fill.dest_len,
+        )?;
+        if file_data
+            .file_offset
+            .checked_add(frame_fill.dest_len)
+            .map_or(true, |end| end > self.last_data.len())
+        {
+            error!(
+                "{}: fill [0x{:x}, +0x{:x}) is past the end of the file (0x{:x})",
+                filename,
+                file_data.file_offset,
+                frame_fill.dest_len,
+                self.last_data.len()
+            );
+            return Err(seL4_RangeError);
+        }
         let base = Self::map_copy_region(sel4_frame)?;
         unsafe {
             ptr::copy_nonoverlapping(
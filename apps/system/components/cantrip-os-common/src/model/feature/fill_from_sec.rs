@@ -15,11 +15,13 @@
 // Fill file data from the Security Core.
 // Uses a stripped-down synchronous mailbox driver.
 
+use super::check_filedata_fill;
 use super::PAGE_SIZE;
 use crate::CantripOsModel;
 use capdl::*;
+use core::sync::atomic::{AtomicBool, Ordering};
 use cstr_core::CStr;
-use log::trace;
+use log::{trace, warn};
 
 use sel4_sys::seL4_Result;
 use sel4_sys::*;
@@ -29,6 +31,10 @@ use mailbox_driver::*;
 use static_assertions::assert_cfg;
 assert_cfg!(feature = "CONFIG_CAPDL_LOADER_FILL_FROM_SEC");
 
+// Whether the SEC takes GetFilePageRange requests; cleared on the first
+// failure so older firmware is sent GetFilePage instead.
+static FILL_RANGE: AtomicBool = AtomicBool::new(true);
+
 // Glue for mailbox hw access (mimics camkes-generated bits).
 
 #[repr(C, align(4096))]
@@ -83,21 +89,48 @@ impl<'a> CantripOsModel<'a> {
             (self.last_fid, _) = mbox_find_file(filename).or(Err(seL4_FailedLookup))?;
             self.last_filename = filename;
         }
-        // XXX Could use a bounce page or copy if frame_fill.dest_offset != 0
-        assert!(frame_fill.dest_offset == 0);
-        assert!(frame_fill.dest_len <= PAGE_SIZE);
-        assert!((file_data.file_offset % PAGE_SIZE) == 0);
-        // TODO(sleffler): add offset + length to rpc (SEC fills entire page) & remove local hacks
+        check_filedata_fill(
+            filename,
+            file_data.file_offset,
+            frame_fill.dest_offset,
+            frame_fill.dest_len,
+        )?;
+        if FILL_RANGE.load(Ordering::Relaxed) {
+            // NB: the SEC writes only the requested bytes, the rest of the
+            //     frame is left as-is.
+            if mbox_get_file_range(
+                self.last_fid,
+                FileOffset(file_data.file_offset as u32),
+                frame_fill.dest_len as u32,
+                sel4_frame,
+            )
+            .is_ok()
+            {
+                return Ok(());
+            }
+            // Firmware that predates GetFilePageRange rejects it; use
+            // whole page requests from here on.
+            warn!("{}: GetFilePageRange failed, falling back to GetFilePage", filename);
+            FILL_RANGE.store(false, Ordering::Relaxed);
+        }
+        self.fill_frame_with_page(sel4_frame, file_data.file_offset, frame_fill)
+    }
+
+    // Fills |sel4_frame| with a whole page of file data and clears
+    // whatever the SEC returned outside the fill.
+    fn fill_frame_with_page(
+        &mut self,
+        sel4_frame: seL4_CPtr,
+        file_offset: usize,
+        frame_fill: &CDL_FrameFill_Element_t,
+    ) -> seL4_Result {
+        let page_offset = file_offset - frame_fill.dest_offset;
         let base = Self::map_copy_region(sel4_frame)?;
         let slice = unsafe { core::slice::from_raw_parts_mut(base as *mut u8, PAGE_SIZE) };
-        if frame_fill.dest_offset != 0 {
-            slice[0..frame_fill.dest_offset].fill(0);
-        }
-        let status = mbox_get_file_page(self.last_fid, file_data.file_offset as u32, sel4_frame)
+        let status = mbox_get_file_page(self.last_fid, FileOffset(page_offset as u32), sel4_frame)
             .or(Err(seL4_InvalidArgument));
-        if frame_fill.dest_len < PAGE_SIZE {
-            slice[frame_fill.dest_len..].fill(0);
-        }
+        slice[..frame_fill.dest_offset].fill(0);
+        slice[frame_fill.dest_offset + frame_fill.dest_len..].fill(0);
         let _ = Self::unmap_copy_region(sel4_frame);
         status
     }
@@ -29,6 +29,7 @@ use spin::Mutex;
 struct Slots {
     bits: Option<BitBox<u8, Lsb0>>,
     used: usize,
+    reserved: usize, // Free slots held for SlotReservation's
     name: &'static str, // Component name
                         // TODO(sleffler): maybe track last alloc for O(1) sequential allocations
 }
@@ -37,6 +38,7 @@ impl Slots {
         Slots {
             bits: Some(bitvec![u8, Lsb0; 0; size].into_boxed_bitslice()),
             used: 0,
+            reserved: 0,
             name,
         }
     }
@@ -44,6 +46,7 @@ impl Slots {
         Slots {
             bits: None,
             used: 0,
+            reserved: 0,
             name: "",
         }
     }
@@ -53,9 +56,39 @@ impl Slots {
         self.name = name;
     }
     fn used_slots(&self) -> usize { self.used }
+    fn reserved_slots(&self) -> usize { self.reserved }
+    // NB: reserved slots are not considered free
     fn free_slots(&self) -> usize {
         let bits = self.bits.as_ref().unwrap();
-        bits.len() - self.used
+        bits.len() - self.used - self.reserved
+    }
+
+    fn reserve(&mut self, count: usize) -> bool {
+        if count > self.free_slots() {
+            error!("cannot reserve slots: {count} > {}", self.free_slots());
+            return false;
+        }
+        self.reserved += count;
+        true
+    }
+    fn unreserve(&mut self, count: usize) {
+        assert!(
+            count <= self.reserved,
+            "{}: count {} > reserved {}",
+            self.name,
+            count,
+            self.reserved
+        );
+        self.reserved -= count;
+    }
+    // Allocates |count| slots from those reserved.
+    fn alloc_reserved(&mut self, count: usize) -> Option<usize> {
+        self.unreserve(count);
+        let result = self.alloc_first_fit(count);
+        if result.is_none() {
+            self.reserved += count;
+        }
+        result
     }
 
     fn not_any_in_range(&self, range: Range<usize>) -> bool {
@@ -171,6 +204,21 @@ impl CantripSlotAllocator {
     /// Returns the number of slots available.
     pub fn free_slots(&self) -> usize { (*self.slots.lock()).free_slots() }
 
+    /// Returns the number of slots held by outstanding reservations.
+    pub fn reserved_slots(&self) -> usize { (*self.slots.lock()).reserved_slots() }
+
+    /// Reserves |count| slots for later allocation through the returned
+    /// guard. Reserved slots are withheld from alloc until allocated
+    /// through the guard or the guard is dropped. Returns None if fewer
+    /// than |count| slots are available.
+    pub fn reserve(&self, count: usize) -> Option<SlotReservation<'_>> {
+        if (*self.slots.lock()).reserve(count) {
+            Some(SlotReservation { slots: self, count })
+        } else {
+            None
+        }
+    }
+
     pub fn alloc(&self, count: usize) -> Option<usize> {
         (*self.slots.lock())
             .alloc_first_fit(count)
@@ -183,6 +231,33 @@ impl CantripSlotAllocator {
     }
 }
 
+/// RAII guard for slots reserved with CantripSlotAllocator::reserve.
+/// Any slots not allocated through the guard are returned on drop.
+pub struct SlotReservation<'a> {
+    slots: &'a CantripSlotAllocator,
+    count: usize,
+}
+impl SlotReservation<'_> {
+    /// Returns the number of reserved slots not yet allocated.
+    pub fn remaining(&self) -> usize { self.count }
+
+    /// Allocates |count| consecutive slots from the reservation. This
+    /// fails if more than the remaining reservation is requested or the
+    /// free slots are too fragmented to satisfy the request.
+    pub fn alloc(&mut self, count: usize) -> Option<usize> {
+        if count > self.count {
+            error!("reservation exceeded: {count} > {}", self.count);
+            return None;
+        }
+        let first_slot = (*self.slots.slots.lock()).alloc_reserved(count)?;
+        self.count = self.count - count;
+        Some(first_slot + self.slots.base_slot)
+    }
+}
+impl Drop for SlotReservation<'_> {
+    fn drop(&mut self) { (*self.slots.slots.lock()).unreserve(self.count) }
+}
+
 #[cfg(test)]
 mod slot_tests {
     use super::*;
@@ -348,6 +423,53 @@ mod slot_tests {
         assert!(slots.alloc_first_fit(3).is_none());
     }
 
+    #[test]
+    fn test_slots_reserve() {
+        let mut slots = Slots::new("reserve", NSLOTS);
+        // Reserving more than is free fails cleanly.
+        assert!(!slots.reserve(NSLOTS + 1));
+        assert_eq!(slots.reserved_slots(), 0);
+        assert_eq!(slots.free_slots(), NSLOTS);
+
+        assert!(slots.reserve(NSLOTS - 4));
+        assert_eq!(slots.reserved_slots(), NSLOTS - 4);
+        assert_eq!(slots.free_slots(), 4);
+        // Reserved slots are withheld from unreserved allocations.
+        assert!(slots.alloc_first_fit(5).is_none());
+        let first = slots.alloc_first_fit(4).unwrap();
+        assert_eq!(slots.free_slots(), 0);
+        assert!(slots.alloc_first_fit(1).is_none());
+        assert!(!slots.reserve(1));
+
+        // Allocations from the reservation do not run out.
+        for _ in 0..(NSLOTS - 4) / 2 {
+            assert!(slots.alloc_reserved(2).is_some());
+        }
+        assert_eq!(slots.reserved_slots(), 0);
+        assert_eq!(slots.used_slots(), NSLOTS);
+
+        slots.free(first, 4);
+        assert!(slots.reserve(4));
+        slots.unreserve(4);
+        assert_eq!(slots.free_slots(), 4);
+    }
+
+    #[test]
+    fn test_slots_reserve_fragmented() {
+        let mut slots = Slots::new("reserve_fragmented", 4);
+        let first = slots.alloc_first_fit(4).unwrap();
+        slots.free(first + 1, 1);
+        slots.free(first + 3, 1);
+        assert!(slots.reserve(2));
+        // 2 slots are reserved but not consecutive.
+        assert!(slots.alloc_reserved(2).is_none());
+        assert_eq!(slots.reserved_slots(), 2);
+        assert!(slots.alloc_reserved(1).is_some());
+        assert!(slots.alloc_reserved(1).is_some());
+        assert_eq!(slots.reserved_slots(), 0);
+        assert_eq!(slots.free_slots(), 0);
+    }
+
     #[test]
     #[should_panic]
     fn test_slots_free_single_invalid() {
@@ -471,4 +593,26 @@ mod cantrip_slot_tests {
             assert!(SLOTS.alloc(SLOT_RANGE.len() + 1).is_none());
         }
     }
+
+    #[test]
+    fn test_slots_reservation() {
+        let slots = CantripSlotAllocator::new("reservation", SLOT_RANGE.start, 8);
+        assert!(slots.reserve(9).is_none());
+        assert_eq!(slots.reserved_slots(), 0);
+        {
+            let mut reservation = slots.reserve(6).unwrap();
+            assert_eq!(slots.free_slots(), 2);
+            assert!(slots.alloc(3).is_none());
+            let first = reservation.alloc(4).unwrap();
+            assert!(SLOT_RANGE.contains(&first));
+            assert_eq!(reservation.remaining(), 2);
+            // Cannot allocate beyond the reservation.
+            assert!(reservation.alloc(3).is_none());
+            assert_eq!(slots.used_slots(), 4);
+            slots.free(first, 4);
+        }
+        // Unused reservation is released on drop.
+        assert_eq!(slots.reserved_slots(), 0);
+        assert_eq!(slots.free_slots(), 8);
+    }
 }